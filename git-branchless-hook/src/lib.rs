@@ -19,22 +19,35 @@
 use std::fmt::Write;
 use std::fs::File;
 use std::io::{stdin, BufRead};
-use std::time::SystemTime;
+use std::time::{Duration, SystemTime};
 
 use eyre::Context;
 use git_branchless_invoke::CommandContext;
-use git_branchless_opts::{HookArgs, HookSubcommand};
+use git_branchless_opts::{
+    HookArgs, HookSubcommand, HOOK_PROTOCOL_VERSION, HOOK_PROTOCOL_VERSION_ENV_VAR,
+};
 use itertools::Itertools;
+use lazy_static::lazy_static;
+use lib::core::check_out::create_snapshot;
+use lib::core::config::{
+    get_gc_pin_recent_days, get_ignored_ref_patterns, get_protected_remotes,
+    get_undo_snapshot_before_rebase,
+};
 use lib::core::dag::Dag;
+use lib::core::phase::{Phase, PhaseDb};
 use lib::core::repo_ext::RepoExt;
 use lib::core::rewrite::rewrite_hooks::get_deferred_commits_path;
+use lib::core::rewrite::{clear_edit_state_if_head_left_stack, is_non_fast_forward};
 use lib::util::EyreExitOr;
 use tracing::{error, instrument, warn};
 
-use lib::core::eventlog::{should_ignore_ref_updates, Event, EventLogDb, EventReplayer};
+use lib::core::eventlog::{should_ignore_ref_updates, CopyType, Event, EventLogDb, EventReplayer};
 use lib::core::formatting::{Glyphs, Pluralize};
-use lib::core::gc::{gc, mark_commit_reachable};
-use lib::git::{CategorizedReferenceName, MaybeZeroOid, NonZeroOid, ReferenceName, Repo};
+use lib::core::gc::{gc, mark_commit_reachable, pin_recent_commits};
+use lib::core::hook_output::HookOutputDb;
+use lib::git::{
+    CategorizedReferenceName, Commit, GitRunInfo, MaybeZeroOid, NonZeroOid, ReferenceName, Repo,
+};
 
 use lib::core::effects::Effects;
 pub use lib::core::rewrite::rewrite_hooks::{
@@ -58,29 +71,136 @@ fn hook_post_checkout(
 
     let now = SystemTime::now();
     let timestamp = now.duration_since(SystemTime::UNIX_EPOCH)?;
-    writeln!(
-        effects.get_output_stream(),
-        "branchless: processing checkout"
-    )?;
-
     let repo = Repo::from_current_dir()?;
     let conn = repo.get_db_conn()?;
     let event_log_db = EventLogDb::new(&conn)?;
-    let event_tx_id = event_log_db.make_transaction_id(now, "hook-post-checkout")?;
+    let event_tx_id = event_log_db.make_hook_transaction_id(&repo, now, "hook-post-checkout")?;
+    HookOutputDb::new(&conn)?.print_hook_message(effects, event_tx_id, "processing checkout")?;
+    let current_head_oid: MaybeZeroOid = current_head_oid.parse()?;
     event_log_db.add_events(vec![Event::RefUpdateEvent {
         timestamp: timestamp.as_secs_f64(),
         event_tx_id,
         old_oid: previous_head_oid.parse()?,
-        new_oid: {
-            let oid: MaybeZeroOid = current_head_oid.parse()?;
-            oid
-        },
+        new_oid: current_head_oid,
         ref_name: ReferenceName::from("HEAD"),
         message: None,
     }])?;
+
+    // Rebases check out intermediate commits, and update the edit state once
+    // they finish.
+    if !repo.is_rebase_underway()? {
+        clear_edit_state_if_head_left_stack(&repo, current_head_oid)?;
+    }
     Ok(())
 }
 
+/// Handle Git's `pre-rebase` hook by taking a snapshot of the working copy,
+/// so that a rebase started outside of git-branchless can be undone.
+///
+/// Git provides no corresponding hook for `git checkout -f`, so that operation
+/// can't be snapshotted.
+///
+/// See the man-page for `githooks(5)`.
+#[instrument]
+fn hook_pre_rebase(effects: &Effects, git_run_info: &GitRunInfo) -> eyre::Result<()> {
+    let now = SystemTime::now();
+    let repo = Repo::from_current_dir()?;
+    if !get_undo_snapshot_before_rebase(&repo)? {
+        return Ok(());
+    }
+
+    let conn = repo.get_db_conn()?;
+    let event_log_db = EventLogDb::new(&conn)?;
+    let event_tx_id = event_log_db.make_hook_transaction_id(&repo, now, "hook-pre-rebase")?;
+    create_snapshot(effects, git_run_info, &repo, &event_log_db, event_tx_id)?;
+    Ok(())
+}
+
+/// Determine whether the given commit was just created by `git cherry-pick`
+/// or `git revert`, and if so, which commit it was copied from.
+///
+/// `git cherry-pick` leaves `CHERRY_PICK_HEAD` in place until the
+/// `post-commit` hook has run, but `git revert` only writes `REVERT_HEAD` if it
+/// stops at a conflict. When several commits are being copied, the one being
+/// committed is also the first entry of the sequencer's todo list. Otherwise,
+/// fall back to the annotations which `git cherry-pick -x` and `git revert` add
+/// to the commit message.
+fn find_copy_source(repo: &Repo, commit: &Commit) -> eyre::Result<Option<(CopyType, NonZeroOid)>> {
+    for (ref_name, copy_type) in [
+        ("CHERRY_PICK_HEAD", CopyType::CherryPick),
+        ("REVERT_HEAD", CopyType::Revert),
+    ] {
+        let reference = match repo.find_reference(&ReferenceName::from(ref_name))? {
+            Some(reference) => reference,
+            None => continue,
+        };
+        if let Some(source_commit) = reference.peel_to_commit()? {
+            if source_commit.get_oid() != commit.get_oid() {
+                return Ok(Some((copy_type, source_commit.get_oid())));
+            }
+        }
+    }
+
+    if let Some((copy_type, source_commit_oid)) = read_sequencer_todo_head(repo)? {
+        if source_commit_oid != commit.get_oid() {
+            return Ok(Some((copy_type, source_commit_oid)));
+        }
+    }
+
+    lazy_static! {
+        static ref CHERRY_PICK_RE: regex::Regex =
+            regex::Regex::new(r"\(cherry picked from commit ([0-9a-f]{40})\)").unwrap();
+        static ref REVERT_RE: regex::Regex =
+            regex::Regex::new(r"This reverts commit ([0-9a-f]{40})").unwrap();
+    }
+    let message = commit.get_message_raw();
+    let message = String::from_utf8_lossy(&message);
+    for (re, copy_type) in [
+        (&*CHERRY_PICK_RE, CopyType::CherryPick),
+        (&*REVERT_RE, CopyType::Revert),
+    ] {
+        let source_commit_oid = match re.captures_iter(&message).last() {
+            Some(captures) => captures[1].parse::<NonZeroOid>()?,
+            None => continue,
+        };
+        if source_commit_oid != commit.get_oid() && repo.find_commit(source_commit_oid)?.is_some() {
+            return Ok(Some((copy_type, source_commit_oid)));
+        }
+    }
+
+    Ok(None)
+}
+
+/// Get the commit which the sequencer of `git cherry-pick` or `git revert` is
+/// currently copying, if any.
+fn read_sequencer_todo_head(repo: &Repo) -> eyre::Result<Option<(CopyType, NonZeroOid)>> {
+    let todo_path = repo.get_path().join("sequencer").join("todo");
+    let todo = match std::fs::read_to_string(&todo_path) {
+        Ok(todo) => todo,
+        Err(err) if err.kind() == std::io::ErrorKind::NotFound => return Ok(None),
+        Err(err) => {
+            return Err(err).wrap_err_with(|| format!("Reading sequencer todo at {todo_path:?}"))
+        }
+    };
+    let line = match todo
+        .lines()
+        .map(str::trim)
+        .find(|line| !line.is_empty() && !line.starts_with('#'))
+    {
+        Some(line) => line,
+        None => return Ok(None),
+    };
+    let (copy_type, commit_spec) = match line.split_whitespace().collect_vec().as_slice() {
+        ["pick" | "p", commit_spec, ..] => (CopyType::CherryPick, *commit_spec),
+        ["revert", commit_spec, ..] => (CopyType::Revert, *commit_spec),
+        _ => return Ok(None),
+    };
+    match repo.revparse_single_commit(commit_spec)? {
+        Some(source_commit) => Ok(Some((copy_type, source_commit.get_oid()))),
+        None => Ok(None),
+    }
+}
+
 fn hook_post_commit_common(effects: &Effects, hook_name: &str) -> eyre::Result<()> {
     let now = SystemTime::now();
     let glyphs = Glyphs::detect();
@@ -143,16 +263,29 @@ fn hook_post_commit_common(effects: &Effects, hook_name: &str) -> eyre::Result<(
         .duration_since(SystemTime::UNIX_EPOCH)?
         .as_secs_f64();
 
-    let event_tx_id = event_log_db.make_transaction_id(now, hook_name)?;
-    event_log_db.add_events(vec![Event::CommitEvent {
+    let event_tx_id = event_log_db.make_hook_transaction_id(&repo, now, hook_name)?;
+    let mut events = vec![Event::CommitEvent {
         timestamp,
         event_tx_id,
         commit_oid: commit.get_oid(),
-    }])?;
-    writeln!(
-        effects.get_output_stream(),
-        "branchless: processed commit: {}",
-        glyphs.render(commit.friendly_describe(&glyphs)?)?,
+    }];
+    if let Some((copy_type, source_commit_oid)) = find_copy_source(&repo, &commit)? {
+        events.push(Event::CopyEvent {
+            timestamp,
+            event_tx_id,
+            copy_type,
+            source_commit_oid,
+            new_commit_oid: commit.get_oid(),
+        });
+    }
+    event_log_db.add_events(events)?;
+    HookOutputDb::new(&conn)?.print_hook_message(
+        effects,
+        event_tx_id,
+        format!(
+            "processed commit: {}",
+            glyphs.render(commit.friendly_describe(&glyphs)?)?
+        ),
     )?;
 
     Ok(())
@@ -170,12 +303,65 @@ fn hook_post_commit(effects: &Effects) -> eyre::Result<()> {
 /// `post-commit` hook after a merge commit, so we need to handle this case
 /// explicitly with another hook.
 ///
+/// Only merges which created a merge commit are recorded. A fast-forward merge
+/// just moves `HEAD` to an existing commit, which is recorded by the
+/// `reference-transaction` hook, and a squash merge doesn't create a commit
+/// until `git commit` is run, which invokes the `post-commit` hook.
+///
 /// See the man-page for `githooks(5)`.
 #[instrument]
-fn hook_post_merge(effects: &Effects, _is_squash_merge: isize) -> eyre::Result<()> {
+fn hook_post_merge(effects: &Effects, is_squash_merge: isize) -> eyre::Result<()> {
+    if is_squash_merge != 0 {
+        return Ok(());
+    }
+
+    let repo = Repo::from_current_dir()?;
+    let is_merge_commit = match repo.get_head_info()?.oid {
+        Some(head_oid) => repo.find_commit_or_fail(head_oid)?.get_parent_count() > 1,
+        None => false,
+    };
+    if !is_merge_commit {
+        return Ok(());
+    }
+
     hook_post_commit_common(effects, "post-merge")
 }
 
+/// Handle Git's `pre-auto-gc` hook by freeing the references to commits which
+/// are no longer visible, and then making sure that the commits referred to by
+/// recent events are kept alive, so that `git undo` can still return to them.
+///
+/// See the man-page for `githooks(5)`.
+#[instrument]
+fn hook_pre_auto_gc(effects: &Effects) -> eyre::Result<()> {
+    gc(effects)?;
+
+    let repo = Repo::from_current_dir()?;
+    let conn = repo.get_db_conn()?;
+    let event_log_db = EventLogDb::new(&conn)?;
+    let event_replayer = EventReplayer::from_event_log_db(effects, &repo, &event_log_db)?;
+    let pin_recent_days = get_gc_pin_recent_days(&repo)?;
+    let cutoff = SystemTime::now()
+        .checked_sub(Duration::from_secs(pin_recent_days * 24 * 60 * 60))
+        .unwrap_or(SystemTime::UNIX_EPOCH);
+    let num_pinned = pin_recent_commits(&repo, &event_replayer, cutoff)?;
+    writeln!(
+        effects.get_output_stream(),
+        "branchless: {} from the last {} kept alive",
+        Pluralize {
+            determiner: None,
+            amount: num_pinned,
+            unit: ("recent commit", "recent commits"),
+        },
+        Pluralize {
+            determiner: None,
+            amount: usize::try_from(pin_recent_days)?,
+            unit: ("day", "days"),
+        },
+    )?;
+    Ok(())
+}
+
 /// Handle Git's `post-applypatch` hook.
 ///
 /// See the man-page for `githooks(5)`.
@@ -425,13 +611,6 @@ fn hook_reference_transaction(effects: &Effects, transaction_state: &str) -> eyr
     }
     let now = SystemTime::now();
 
-    let repo = Repo::from_current_dir()?;
-    let conn = repo.get_db_conn()?;
-    let event_log_db = EventLogDb::new(&conn)?;
-    let event_tx_id = event_log_db.make_transaction_id(now, "reference-transaction")?;
-
-    let packed_references = read_packed_refs_file(&repo)?;
-
     let parsed_lines: Vec<ParsedReferenceTransactionLine> = stdin()
         .lock()
         .split(b'\n')
@@ -462,64 +641,227 @@ fn hook_reference_transaction(effects: &Effects, transaction_state: &str) -> eyr
                  new_oid: _,
              }| !should_ignore_ref_updates(ref_name),
         )
-        .map(|parsed_line| fix_packed_reference_oid(&repo, &packed_references, parsed_line))
         .collect();
     if parsed_lines.is_empty() {
         return Ok(());
     }
 
-    let num_reference_updates = Pluralize {
-        determiner: None,
-        amount: parsed_lines.len(),
-        unit: ("update", "updates"),
+    let repo = Repo::from_current_dir()?;
+    let ignored_ref_patterns = get_ignored_ref_patterns(&repo)?;
+    let parsed_lines: Vec<ParsedReferenceTransactionLine> = parsed_lines
+        .into_iter()
+        .filter(
+            |ParsedReferenceTransactionLine {
+                 ref_name,
+                 old_oid: _,
+                 new_oid: _,
+             }| {
+                !ignored_ref_patterns
+                    .iter()
+                    .any(|pattern| pattern.matches(ref_name.as_str()))
+            },
+        )
+        .collect();
+    if parsed_lines.is_empty() {
+        return Ok(());
+    }
+
+    // Reading the `packed-refs` file can be slow in repositories with many
+    // references, and it's only needed to fix up updates which create or
+    // delete a reference. Skip it in the common case, such as a `git fetch`
+    // which only moves existing remote-tracking branches.
+    let needs_packed_references = parsed_lines.iter().any(
+        |ParsedReferenceTransactionLine {
+             ref_name: _,
+             old_oid,
+             new_oid,
+         }| {
+            matches!(old_oid, MaybeZeroOid::Zero) || matches!(new_oid, MaybeZeroOid::Zero)
+        },
+    );
+    let parsed_lines = if needs_packed_references {
+        let packed_references = read_packed_refs_file(&repo)?;
+        parsed_lines
+            .into_iter()
+            .map(|parsed_line| fix_packed_reference_oid(&repo, &packed_references, parsed_line))
+            .collect()
+    } else {
+        parsed_lines
     };
-    writeln!(
-        effects.get_output_stream(),
-        "branchless: processing {}: {}",
-        num_reference_updates,
+
+    let conn = repo.get_db_conn()?;
+    let event_log_db = EventLogDb::new(&conn)?;
+    let event_tx_id = event_log_db.make_hook_transaction_id(&repo, now, "reference-transaction")?;
+    let timestamp = now
+        .duration_since(SystemTime::UNIX_EPOCH)
+        .wrap_err("Calculating timestamp")?
+        .as_secs_f64();
+    let make_events = |parsed_lines: Vec<ParsedReferenceTransactionLine>| -> Vec<Event> {
         parsed_lines
-            .iter()
+            .into_iter()
             .map(
                 |ParsedReferenceTransactionLine {
                      ref_name,
-                     old_oid: _,
-                     new_oid: _,
-                 }| { CategorizedReferenceName::new(ref_name).friendly_describe() }
+                     old_oid,
+                     new_oid,
+                 }| {
+                    Event::RefUpdateEvent {
+                        timestamp,
+                        event_tx_id,
+                        ref_name,
+                        old_oid,
+                        new_oid,
+                        message: None,
+                    }
+                },
             )
-            .map(|description| format!("{}", console::style(description).green()))
-            .sorted()
-            .collect::<Vec<_>>()
-            .join(", ")
+            .collect()
+    };
+
+    // Most transactions during a `git fetch` only move remote-tracking
+    // branches. Unless one of them belongs to a protected remote, tracks the
+    // main branch, or was deleted, there's nothing to do beyond recording the
+    // updates, so skip the hook message and the phase and commit graph
+    // bookkeeping below.
+    let protected_remotes = get_protected_remotes(&repo)?;
+    let main_branch_reference_names = repo.get_main_branch_tracking_reference_names()?;
+    let is_protected_remote_reference = |ref_name: &ReferenceName| {
+        protected_remotes.iter().any(|remote_name| {
+            ref_name
+                .as_str()
+                .starts_with(&format!("refs/remotes/{remote_name}/"))
+        })
+    };
+    let is_remote_only = parsed_lines.iter().all(
+        |ParsedReferenceTransactionLine {
+             ref_name,
+             old_oid: _,
+             new_oid,
+         }| {
+            matches!(
+                CategorizedReferenceName::new(ref_name),
+                CategorizedReferenceName::RemoteBranch { .. }
+            ) && !matches!(new_oid, MaybeZeroOid::Zero)
+                && !main_branch_reference_names.contains(ref_name)
+                && !is_protected_remote_reference(ref_name)
+        },
+    );
+    if is_remote_only {
+        event_log_db.add_events(make_events(parsed_lines))?;
+        return Ok(());
+    }
+
+    let num_reference_updates = Pluralize {
+        determiner: None,
+        amount: parsed_lines.len(),
+        unit: ("update", "updates"),
+    };
+    HookOutputDb::new(&conn)?.print_hook_message(
+        effects,
+        event_tx_id,
+        format!(
+            "processing {}: {}",
+            num_reference_updates,
+            parsed_lines
+                .iter()
+                .map(
+                    |ParsedReferenceTransactionLine {
+                         ref_name,
+                         old_oid: _,
+                         new_oid: _,
+                     }| {
+                        CategorizedReferenceName::new(ref_name).friendly_describe()
+                    }
+                )
+                .map(|description| format!("{}", console::style(description).green()))
+                .sorted()
+                .collect::<Vec<_>>()
+                .join(", ")
+        ),
     )?;
 
-    let timestamp = now
-        .duration_since(SystemTime::UNIX_EPOCH)
-        .wrap_err("Calculating timestamp")?
-        .as_secs_f64();
-    let events = parsed_lines
-        .into_iter()
-        .map(
+    // Commits which have been pushed to (or fetched from) a protected remote
+    // are shared with others, so record them as public.
+    if !protected_remotes.is_empty() {
+        let public_commit_oids = parsed_lines.iter().filter_map(
             |ParsedReferenceTransactionLine {
                  ref_name,
-                 old_oid,
+                 old_oid: _,
                  new_oid,
-             }| {
-                Event::RefUpdateEvent {
-                    timestamp,
-                    event_tx_id,
-                    ref_name,
-                    old_oid,
-                    new_oid,
-                    message: None,
+             }| match new_oid {
+                MaybeZeroOid::NonZero(new_oid) if is_protected_remote_reference(ref_name) => {
+                    Some(*new_oid)
                 }
+                MaybeZeroOid::NonZero(_) | MaybeZeroOid::Zero => None,
             },
-        )
-        .collect::<Vec<Event>>();
-    event_log_db.add_events(events)?;
+        );
+        PhaseDb::new(&conn)?.set_phase(public_commit_oids, Phase::Public)?;
+    }
+
+    // If the upstream main branch was rewritten (such as by a force-push),
+    // keep its old commits alive, so that `git sync --onto-new-main` can find
+    // the stacks which were based on them.
+    let mut is_main_branch_rewritten = false;
+    for ParsedReferenceTransactionLine {
+        ref_name,
+        old_oid,
+        new_oid,
+    } in parsed_lines.iter()
+    {
+        if let (MaybeZeroOid::NonZero(old_oid), MaybeZeroOid::NonZero(new_oid)) = (old_oid, new_oid)
+        {
+            if main_branch_reference_names.contains(ref_name)
+                && matches!(
+                    CategorizedReferenceName::new(ref_name),
+                    CategorizedReferenceName::RemoteBranch { .. }
+                )
+                && is_non_fast_forward(&repo, *old_oid, *new_oid)?
+            {
+                mark_commit_reachable(&repo, *old_oid)?;
+                is_main_branch_rewritten = true;
+            }
+        }
+    }
+    if is_main_branch_rewritten {
+        writeln!(
+            effects.get_output_stream(),
+            "branchless: the upstream main branch was rewritten; to move your commits onto it, run: git sync --onto-new-main"
+        )?;
+    }
+
+    // Keep the commits of deleted remote-tracking branches alive (such as
+    // after a `git fetch --prune`), so that `git undo` can restore them.
+    for ParsedReferenceTransactionLine {
+        ref_name,
+        old_oid,
+        new_oid,
+    } in parsed_lines.iter()
+    {
+        if let (MaybeZeroOid::NonZero(old_oid), MaybeZeroOid::Zero) = (old_oid, new_oid) {
+            if matches!(
+                CategorizedReferenceName::new(ref_name),
+                CategorizedReferenceName::RemoteBranch { .. }
+            ) {
+                mark_commit_reachable(&repo, *old_oid)?;
+            }
+        }
+    }
+
+    event_log_db.add_events(make_events(parsed_lines))?;
 
     Ok(())
 }
 
+/// Get the protocol version which the running hook script was installed with.
+/// Hook scripts which predate the protocol version don't set it, so they're
+/// treated as version 0.
+fn get_hook_protocol_version() -> u32 {
+    std::env::var(HOOK_PROTOCOL_VERSION_ENV_VAR)
+        .ok()
+        .and_then(|version| version.trim().parse().ok())
+        .unwrap_or(0)
+}
+
 /// `hook` subcommand.
 #[instrument]
 pub fn command_main(ctx: CommandContext, args: HookArgs) -> EyreExitOr<()> {
@@ -529,6 +871,20 @@ pub fn command_main(ctx: CommandContext, args: HookArgs) -> EyreExitOr<()> {
     } = ctx;
     let HookArgs { subcommand } = args;
 
+    // The hook subcommands are kept stable across protocol versions, so run
+    // the hook anyway on a best-effort basis.
+    let protocol_version = get_hook_protocol_version();
+    if protocol_version > HOOK_PROTOCOL_VERSION {
+        writeln!(
+            effects.get_error_stream(),
+            "branchless: this hook was installed by a newer version of git-branchless (hook protocol version {protocol_version}, but only up to {HOOK_PROTOCOL_VERSION} is supported)"
+        )?;
+        writeln!(
+            effects.get_error_stream(),
+            "branchless: to reinstall the hooks for this version, run: git branchless init"
+        )?;
+    }
+
     match subcommand {
         HookSubcommand::DetectEmptyCommit { old_commit_oid } => {
             let old_commit_oid: NonZeroOid = old_commit_oid.parse()?;
@@ -536,7 +892,11 @@ pub fn command_main(ctx: CommandContext, args: HookArgs) -> EyreExitOr<()> {
         }
 
         HookSubcommand::PreAutoGc => {
-            gc(&effects)?;
+            hook_pre_auto_gc(&effects)?;
+        }
+
+        HookSubcommand::PreRebase { args: _ } => {
+            hook_pre_rebase(&effects, &git_run_info)?;
         }
 
         HookSubcommand::PostApplypatch => {
@@ -580,6 +940,20 @@ pub fn command_main(ctx: CommandContext, args: HookArgs) -> EyreExitOr<()> {
             let commit_oid: NonZeroOid = commit_oid.parse()?;
             hook_skip_upstream_applied_commit(&effects, commit_oid)?;
         }
+
+        HookSubcommand::Unknown(args) => {
+            // Don't fail the Git operation which invoked the hook, since
+            // there's nothing the user can do about it at this point.
+            let hook_name = args.first().map(String::as_str).unwrap_or_default();
+            writeln!(
+                effects.get_error_stream(),
+                "branchless: ignoring unknown hook {hook_name}, which may have been installed by a newer version of git-branchless"
+            )?;
+            writeln!(
+                effects.get_error_stream(),
+                "branchless: to reinstall the hooks for this version, run: git branchless init"
+            )?;
+        }
     }
 
     Ok(Ok(()))
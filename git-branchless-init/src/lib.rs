@@ -18,13 +18,18 @@ use eyre::Context;
 use git_branchless_invoke::CommandContext;
 use itertools::Itertools;
 use lib::core::config::env_vars::should_use_separate_command_binary;
-use lib::util::EyreExitOr;
+use lib::util::{ExitCode, EyreExitOr};
 use path_slash::PathExt;
 use tracing::{instrument, warn};
 
-use git_branchless_opts::{write_man_pages, InitArgs, InstallManPagesArgs};
+use git_branchless_opts::{
+    write_man_pages, InitArgs, InstallManPagesArgs, HOOK_PROTOCOL_VERSION,
+    HOOK_PROTOCOL_VERSION_ENV_VAR,
+};
 use lib::core::config::{
-    get_default_branch_name, get_default_hooks_dir, get_main_worktree_hooks_dir,
+    get_custom_aliases, get_default_branch_name, get_default_hooks_dir, get_main_branch_name,
+    get_main_worktree_hooks_dir, get_undo_snapshot_before_rebase, CUSTOM_ALIAS_CONFIG_SECTION,
+    MAIN_BRANCH_CONFIG_KEY,
 };
 use lib::core::dag::Dag;
 use lib::core::effects::Effects;
@@ -32,42 +37,50 @@ use lib::core::eventlog::{EventLogDb, EventReplayer};
 use lib::core::repo_ext::RepoExt;
 use lib::git::{BranchType, Config, ConfigRead, ConfigWrite, GitRunInfo, GitVersion, Repo};
 
-/// The contents of all Git hooks to install.
+/// The contents of all Git hooks to install. Each hook script sets
+/// [`HOOK_PROTOCOL_VERSION_ENV_VAR`] to the [`HOOK_PROTOCOL_VERSION`] it was
+/// written for.
 pub const ALL_HOOKS: &[(&str, &str)] = &[
     (
         "post-applypatch",
         r#"
-git branchless hook post-applypatch "$@"
+BRANCHLESS_HOOK_PROTOCOL_VERSION=1 git branchless hook post-applypatch "$@"
 "#,
     ),
     (
         "post-checkout",
         r#"
-git branchless hook post-checkout "$@"
+BRANCHLESS_HOOK_PROTOCOL_VERSION=1 git branchless hook post-checkout "$@"
 "#,
     ),
     (
         "post-commit",
         r#"
-git branchless hook post-commit "$@"
+BRANCHLESS_HOOK_PROTOCOL_VERSION=1 git branchless hook post-commit "$@"
 "#,
     ),
     (
         "post-merge",
         r#"
-git branchless hook post-merge "$@"
+BRANCHLESS_HOOK_PROTOCOL_VERSION=1 git branchless hook post-merge "$@"
 "#,
     ),
     (
         "post-rewrite",
         r#"
-git branchless hook post-rewrite "$@"
+BRANCHLESS_HOOK_PROTOCOL_VERSION=1 git branchless hook post-rewrite "$@"
 "#,
     ),
     (
         "pre-auto-gc",
         r#"
-git branchless hook pre-auto-gc "$@"
+BRANCHLESS_HOOK_PROTOCOL_VERSION=1 git branchless hook pre-auto-gc "$@"
+"#,
+    ),
+    (
+        "pre-rebase",
+        r#"
+BRANCHLESS_HOOK_PROTOCOL_VERSION=1 git branchless hook pre-rebase "$@"
 "#,
     ),
     (
@@ -75,7 +88,7 @@ git branchless hook pre-auto-gc "$@"
         r#"
 # Avoid canceling the reference transaction in the case that `branchless` fails
 # for whatever reason.
-git branchless hook reference-transaction "$@" || (
+BRANCHLESS_HOOK_PROTOCOL_VERSION=1 git branchless hook reference-transaction "$@" || (
 echo 'branchless: Failed to process reference transaction!'
 echo 'branchless: Some events (e.g. branch updates) may have been lost.'
 echo 'branchless: This is a bug. Please report it.'
@@ -84,11 +97,31 @@ echo 'branchless: This is a bug. Please report it.'
     ),
 ];
 
+/// Get the hooks from [`ALL_HOOKS`] which should be installed in this
+/// repository. The `pre-rebase` hook is only needed to take snapshots when
+/// `branchless.undo.snapshotBeforeRebase` is set, so it's skipped otherwise.
+pub fn get_hooks_to_install(repo: &Repo) -> eyre::Result<Vec<(&'static str, &'static str)>> {
+    let snapshot_before_rebase = get_undo_snapshot_before_rebase(repo)?;
+    Ok(ALL_HOOKS
+        .iter()
+        .copied()
+        .filter(|(hook_type, _hook_script)| *hook_type != "pre-rebase" || snapshot_before_rebase)
+        .collect())
+}
+
+/// The config key recording which version of git-branchless last installed the
+/// hooks and aliases, so that they can be reinstalled after an upgrade.
+const INSTALLED_VERSION_CONFIG_KEY: &str = "branchless.core.installedVersion";
+
+/// The version of git-branchless to record under [`INSTALLED_VERSION_CONFIG_KEY`].
+const INSTALLED_VERSION: &str = env!("CARGO_PKG_VERSION");
+
 const ALL_ALIASES: &[(&str, &str)] = &[
     ("amend", "amend"),
     ("hide", "hide"),
     ("move", "move"),
     ("next", "next"),
+    ("obslog", "obslog"),
     ("prev", "prev"),
     ("query", "query"),
     ("record", "record"),
@@ -172,6 +205,32 @@ fn update_between_lines(lines: &str, updated_lines: &str) -> String {
     new_lines
 }
 
+/// Get the lines between the update markers in the given hook script, if any.
+fn get_between_lines(lines: &str) -> Option<String> {
+    let mut result = None;
+    for line in lines.lines() {
+        if line == UPDATE_MARKER_START {
+            result = Some(String::new());
+        } else if line == UPDATE_MARKER_END {
+            return result;
+        } else if let Some(result) = result.as_mut() {
+            result.push_str(line);
+            result.push('\n');
+        }
+    }
+    None
+}
+
+/// Rewrite the hook script contents to invoke the separate `git-branchless-hook`
+/// binary, if it should be used.
+fn adjust_hook_command(contents: &str) -> String {
+    if should_use_separate_command_binary("hook") {
+        contents.replace("branchless hook", "branchless-hook")
+    } else {
+        contents.to_string()
+    }
+}
+
 #[instrument]
 fn write_script(path: &Path, contents: &str) -> eyre::Result<()> {
     let script_dir = path
@@ -179,11 +238,7 @@ fn write_script(path: &Path, contents: &str) -> eyre::Result<()> {
         .ok_or_else(|| eyre::eyre!("No parent for dir {:?}", path))?;
     std::fs::create_dir_all(script_dir).wrap_err("Creating script dir")?;
 
-    let contents = if should_use_separate_command_binary("hook") {
-        contents.replace("branchless hook", "branchless-hook")
-    } else {
-        contents.to_string()
-    };
+    let contents = adjust_hook_command(contents);
     std::fs::write(path, contents).wrap_err("Writing script contents")?;
 
     // Setting hook file as executable only supported on Unix systems.
@@ -241,18 +296,135 @@ fn install_hook(
     Ok(())
 }
 
+/// Determine whether the installed hook script matches the one which this
+/// version of git-branchless would install. Scripts with no git-branchless
+/// section at all are considered stale.
+#[instrument]
+fn is_hook_up_to_date(hook: &Hook, hook_script: &str) -> eyre::Result<bool> {
+    let path = match hook {
+        Hook::RegularHook { path } | Hook::MultiHook { path } => path,
+    };
+    let contents = match std::fs::read_to_string(path) {
+        Ok(contents) => contents,
+        Err(err) if err.kind() == std::io::ErrorKind::NotFound => return Ok(false),
+        Err(err) => return Err(eyre::eyre!(err)),
+    };
+    let installed_script = match hook {
+        Hook::RegularHook { path: _ } => get_between_lines(&contents),
+        Hook::MultiHook { path: _ } => contents
+            .strip_prefix(SHEBANG)
+            .map(|contents| contents.to_string()),
+    };
+    Ok(match installed_script {
+        Some(installed_script) => {
+            installed_script.trim() == adjust_hook_command(hook_script).trim()
+        }
+        None => false,
+    })
+}
+
+/// Reinstall any hooks and aliases which were installed by a different version
+/// of git-branchless and no longer match what this version would install, such
+/// as when a hook's arguments have changed. Otherwise, stale hooks would fail
+/// with confusing argument errors the next time Git runs them.
+///
+/// This is cheap to call at startup: the hooks are only audited when the
+/// version recorded in the repository's configuration differs from this one.
+/// Does nothing if git-branchless isn't initialized in the current repository.
+#[instrument]
+pub fn reinstall_stale_hooks(effects: &Effects, git_run_info: &GitRunInfo) -> eyre::Result<()> {
+    let repo = match Repo::from_current_dir() {
+        Ok(repo) => repo,
+        Err(_) => return Ok(()),
+    };
+    let repo = repo.open_worktree_parent_repo()?.unwrap_or(repo);
+    let config_path = repo.get_config_path()?;
+    if !config_path.exists() {
+        return Ok(());
+    }
+    let installed_version: Option<String> = repo
+        .get_readonly_config()?
+        .get(INSTALLED_VERSION_CONFIG_KEY)?;
+    if installed_version.as_deref() == Some(INSTALLED_VERSION) {
+        return Ok(());
+    }
+
+    let hooks_dir = get_main_worktree_hooks_dir(git_run_info, &repo, None)?;
+    let mut stale_hooks = Vec::new();
+    for (hook_type, hook_script) in get_hooks_to_install(&repo)? {
+        let hook = determine_hook_path(&repo, &hooks_dir, hook_type)?;
+        if !is_hook_up_to_date(&hook, hook_script)? {
+            stale_hooks.push((hook_type, hook, hook_script));
+        }
+    }
+
+    let mut config = Config::open(&config_path)?;
+    let default_config = Config::open_default()?;
+    let mut stale_aliases = Vec::new();
+    for (from, to) in ALL_ALIASES {
+        let alias_key = format!("alias.{from}");
+        let default_alias: Option<String> = default_config.get(&alias_key)?;
+        if default_alias.is_some() {
+            continue;
+        }
+
+        // Only update aliases which were installed by git-branchless in the
+        // other form for the same subcommand. Aliases which the user removed
+        // or customized are left alone.
+        let existing_alias: Option<String> = config.get(&alias_key)?;
+        let alias = make_alias(to);
+        let is_stale = match existing_alias {
+            Some(existing_alias) => {
+                existing_alias != alias
+                    && (existing_alias == format!("branchless {to}")
+                        || existing_alias == format!("branchless-{to}"))
+            }
+            None => false,
+        };
+        if is_stale {
+            stale_aliases.push((alias_key, alias));
+        }
+    }
+
+    if !stale_hooks.is_empty() || !stale_aliases.is_empty() {
+        writeln!(
+            effects.get_error_stream(),
+            "branchless: git-branchless was upgraded from {}, reinstalling: {}",
+            installed_version.as_deref().unwrap_or("an unknown version"),
+            stale_hooks
+                .iter()
+                .map(|(hook_type, _hook, _hook_script)| format!("{hook_type} hook"))
+                .chain(
+                    stale_aliases
+                        .iter()
+                        .map(|(alias_key, _alias)| alias_key.clone())
+                )
+                .join(", ")
+        )?;
+    }
+    for (_hook_type, hook, hook_script) in stale_hooks {
+        update_hook_contents(&hook, hook_script)?;
+    }
+    for (alias_key, alias) in stale_aliases {
+        config.set(&alias_key, alias)?;
+    }
+    config.set(INSTALLED_VERSION_CONFIG_KEY, INSTALLED_VERSION)?;
+    Ok(())
+}
+
 #[instrument]
 fn install_hooks(effects: &Effects, git_run_info: &GitRunInfo, repo: &Repo) -> eyre::Result<()> {
+    let hooks = get_hooks_to_install(repo)?;
     writeln!(
         effects.get_output_stream(),
         "Installing hooks: {}",
-        ALL_HOOKS
+        hooks
             .iter()
             .map(|(hook_type, _hook_script)| hook_type)
             .join(", ")
     )?;
     let hooks_dir = get_main_worktree_hooks_dir(git_run_info, repo, None)?;
-    for (hook_type, hook_script) in ALL_HOOKS {
+    for (hook_type, hook_script) in hooks {
         install_hook(repo, &hooks_dir, hook_type, hook_script)?;
     }
 
@@ -314,14 +486,22 @@ fn should_use_wrapped_command_alias() -> bool {
     cfg!(feature = "man-pages")
 }
 
+/// The expansion of the alias for the given subcommand.
+fn make_alias(subcommand: &str) -> String {
+    if should_use_wrapped_command_alias() {
+        format!("branchless-{subcommand}")
+    } else {
+        format!("branchless {subcommand}")
+    }
+}
+
 #[instrument]
 fn install_alias(
     effects: &Effects,
-    repo: &Repo,
     config: &mut Config,
     default_config: &Config,
     from: &str,
-    to: &str,
+    alias: String,
 ) -> eyre::Result<()> {
     let alias_key = format!("alias.{from}");
 
@@ -339,17 +519,61 @@ fn install_alias(
         return Ok(());
     }
 
-    let alias = if should_use_wrapped_command_alias() {
-        format!("branchless-{to}")
-    } else {
-        format!("branchless {to}")
-    };
     config.set(&alias_key, alias)?;
     Ok(())
 }
 
+/// Parse the custom aliases passed on the command-line as `NAME=EXPANSION`,
+/// where `EXPANSION` is the arguments to `git branchless`, such as `up=next
+/// --all`.
+fn parse_custom_aliases(
+    effects: &Effects,
+    aliases: Vec<String>,
+) -> EyreExitOr<Vec<(String, String)>> {
+    let mut result = Vec::new();
+    for alias in aliases {
+        let (name, expansion) = match alias.split_once('=') {
+            Some((name, expansion)) => (name.trim(), expansion.trim()),
+            None => {
+                writeln!(
+                    effects.get_error_stream(),
+                    "Invalid alias (expected NAME=EXPANSION): {alias}"
+                )?;
+                return Ok(Err(ExitCode(1)));
+            }
+        };
+
+        // Git only accepts alphanumeric characters and dashes in the names
+        // of config variables, and the name must start with a letter.
+        let is_valid_name = name.starts_with(|c: char| c.is_ascii_alphabetic())
+            && name.chars().all(|c| c.is_ascii_alphanumeric() || c == '-');
+        if !is_valid_name {
+            writeln!(
+                effects.get_error_stream(),
+                "Invalid alias name (expected letters, digits, and dashes): {name}"
+            )?;
+            return Ok(Err(ExitCode(1)));
+        }
+        if expansion.is_empty() {
+            writeln!(
+                effects.get_error_stream(),
+                "Alias {name} must expand to a git-branchless subcommand"
+            )?;
+            return Ok(Err(ExitCode(1)));
+        }
+        result.push((name.to_owned(), expansion.to_owned()));
+    }
+    Ok(Ok(result))
+}
+
 #[instrument]
 fn detect_main_branch_name(repo: &Repo) -> eyre::Result<Option<String>> {
+    // Prefer the branch which a remote considers to be its default branch, as
+    // recorded by `git clone` in `refs/remotes/<remote>/HEAD`.
+    if let Some(branch_name) = repo.detect_main_branch_from_remote_head()? {
+        return Ok(Some(branch_name));
+    }
+
     if let Some(default_branch_name) = get_default_branch_name(repo)? {
         if repo
             .find_branch(&default_branch_name, BranchType::Local)?
@@ -382,9 +606,23 @@ fn install_aliases(
     config: &mut Config,
     default_config: &Config,
     git_run_info: &GitRunInfo,
+    custom_aliases: &[(String, String)],
 ) -> eyre::Result<()> {
     for (from, to) in ALL_ALIASES {
-        install_alias(effects, repo, config, default_config, from, to)?;
+        install_alias(effects, config, default_config, from, make_alias(to))?;
+    }
+
+    // Custom aliases may include arguments, so they can't use the wrapped
+    // command. Git appends any arguments passed to the alias itself, so they
+    // are passed through to the subcommand.
+    for (from, to) in custom_aliases {
+        install_alias(
+            effects,
+            config,
+            default_config,
+            from,
+            format!("branchless {to}"),
+        )?;
     }
 
     let version_str = git_run_info
@@ -517,7 +755,8 @@ fn set_configs(
         },
     };
 
-    config.set("branchless.core.mainBranch", main_branch_name)?;
+    config.set(MAIN_BRANCH_CONFIG_KEY, main_branch_name)?;
+    config.set(INSTALLED_VERSION_CONFIG_KEY, INSTALLED_VERSION)?;
     config.set("advice.detachedHead", false)?;
     config.set("log.excludeDecoration", "refs/branchless/*")?;
 
@@ -604,7 +843,13 @@ fn command_init(
     effects: &Effects,
     git_run_info: &GitRunInfo,
     main_branch_name: Option<&str>,
+    aliases: Vec<String>,
 ) -> EyreExitOr<()> {
+    let new_custom_aliases = match parse_custom_aliases(effects, aliases)? {
+        Ok(custom_aliases) => custom_aliases,
+        Err(exit_code) => return Ok(Err(exit_code)),
+    };
+
     let mut in_ = BufReader::new(stdin());
     let repo = Repo::from_current_dir()?;
     let mut repo = repo.open_worktree_parent_repo()?.unwrap_or(repo);
@@ -615,12 +860,26 @@ fn command_init(
 
     set_configs(&mut in_, effects, &repo, &mut config, main_branch_name)?;
     install_hooks(effects, git_run_info, &repo)?;
+
+    // Aliases passed on the command-line are stored so that they're
+    // reinstalled by future invocations, and take precedence over any
+    // previously-configured aliases with the same name.
+    let mut custom_aliases = get_custom_aliases(&repo)?;
+    for (name, expansion) in new_custom_aliases {
+        config.set(
+            format!("{CUSTOM_ALIAS_CONFIG_SECTION}.{name}"),
+            expansion.as_str(),
+        )?;
+        custom_aliases.retain(|(existing_name, _)| !existing_name.eq_ignore_ascii_case(&name));
+        custom_aliases.push((name, expansion));
+    }
     install_aliases(
         effects,
         &mut repo,
         &mut config,
         &default_config,
         git_run_info,
+        &custom_aliases,
     )?;
     install_man_pages(effects, &repo, &mut config)?;
 
@@ -641,6 +900,20 @@ fn command_init(
         )?;
     }
 
+    // Git only supports paths longer than `MAX_PATH` (260 characters) on
+    // Windows if `core.longpaths` is set, but it's left to the user to enable
+    // it, since it isn't supported by all tools.
+    if cfg!(windows) {
+        let long_paths: Option<bool> = repo.get_readonly_config()?.get("core.longpaths")?;
+        if long_paths.is_none() {
+            writeln!(
+                effects.get_output_stream(),
+                "To work with paths longer than 260 characters, run: {}",
+                console::style("git config core.longpaths true").bold()
+            )?;
+        }
+    }
+
     writeln!(
         effects.get_output_stream(),
         "{}",
@@ -657,6 +930,65 @@ fn command_init(
     Ok(Ok(()))
 }
 
+/// Re-detect the main branch and update the main branch setting, such as after
+/// the upstream repository renamed its main branch.
+#[instrument]
+fn command_detect_main_branch(effects: &Effects) -> EyreExitOr<()> {
+    let repo = Repo::from_current_dir()?;
+    let repo = repo.open_worktree_parent_repo()?.unwrap_or(repo);
+
+    let old_main_branch_name = get_main_branch_name(&repo)?;
+    let new_main_branch_name = match detect_main_branch_name(&repo)? {
+        Some(main_branch_name) => main_branch_name,
+        None => {
+            writeln!(
+                effects.get_error_stream(),
+                "Your main branch name could not be auto-detected."
+            )?;
+            writeln!(
+                effects.get_error_stream(),
+                "Set it explicitly by running: git branchless init --main-branch <branch>"
+            )?;
+            return Ok(Err(ExitCode(1)));
+        }
+    };
+
+    if new_main_branch_name == old_main_branch_name {
+        writeln!(
+            effects.get_output_stream(),
+            "Your main branch is already set to: {}",
+            style(&new_main_branch_name).bold()
+        )?;
+        return Ok(Ok(()));
+    }
+
+    let mut config = Config::open(&repo.get_config_path()?)?;
+    config.set(MAIN_BRANCH_CONFIG_KEY, new_main_branch_name.as_str())?;
+    writeln!(
+        effects.get_output_stream(),
+        "Updated your main branch from {} to: {}",
+        style(&old_main_branch_name).bold(),
+        style(&new_main_branch_name).bold()
+    )?;
+
+    // Sync the DAG so that commits on the new main branch are considered
+    // public from now on.
+    let conn = repo.get_db_conn()?;
+    let event_log_db = EventLogDb::new(&conn)?;
+    let event_replayer = EventReplayer::from_event_log_db(effects, &repo, &event_log_db)?;
+    let event_cursor = event_replayer.make_default_cursor();
+    let references_snapshot = repo.get_references_snapshot()?;
+    Dag::open_and_sync(
+        effects,
+        &repo,
+        &event_replayer,
+        event_cursor,
+        &references_snapshot,
+    )?;
+
+    Ok(Ok(()))
+}
+
 /// Uninstall `git-branchless` in the current repo.
 #[instrument]
 fn command_uninstall(effects: &Effects, git_run_info: &GitRunInfo) -> EyreExitOr<()> {
@@ -675,14 +1007,27 @@ pub fn command_main(ctx: CommandContext, args: InitArgs) -> EyreExitOr<()> {
         git_run_info,
     } = ctx;
     match args {
+        InitArgs {
+            uninstall: false,
+            main_branch_name: Some(None),
+            aliases: _,
+        } => command_detect_main_branch(&effects),
+
         InitArgs {
             uninstall: false,
             main_branch_name,
-        } => command_init(&effects, &git_run_info, main_branch_name.as_deref()),
+            aliases,
+        } => command_init(
+            &effects,
+            &git_run_info,
+            main_branch_name.flatten().as_deref(),
+            aliases,
+        ),
 
         InitArgs {
             uninstall: true,
             main_branch_name: _,
+            aliases: _,
         } => command_uninstall(&effects, &git_run_info),
     }
 }
@@ -697,7 +1042,12 @@ pub fn command_install_man_pages(ctx: CommandContext, args: InstallManPagesArgs)
 
 #[cfg(test)]
 mod tests {
-    use super::{update_between_lines, ALL_ALIASES, UPDATE_MARKER_END, UPDATE_MARKER_START};
+    use git_branchless_opts::{HOOK_PROTOCOL_VERSION, HOOK_PROTOCOL_VERSION_ENV_VAR};
+
+    use super::{
+        get_between_lines, update_between_lines, ALL_ALIASES, ALL_HOOKS, UPDATE_MARKER_END,
+        UPDATE_MARKER_START,
+    };
 
     #[test]
     fn test_update_between_lines() {
@@ -733,6 +1083,36 @@ contents 3
         )
     }
 
+    #[test]
+    fn test_get_between_lines() {
+        let input = format!(
+            "\
+hello, world
+{UPDATE_MARKER_START}
+contents 1
+{UPDATE_MARKER_END}
+goodbye, world
+"
+        );
+        assert_eq!(get_between_lines(&input), Some("contents 1\n".to_string()));
+        assert_eq!(get_between_lines("hello, world\n"), None);
+
+        let unterminated = format!("{UPDATE_MARKER_START}\ncontents 1\n");
+        assert_eq!(get_between_lines(&unterminated), None);
+    }
+
+    #[test]
+    fn test_all_hooks_set_protocol_version() {
+        let expected =
+            format!("{HOOK_PROTOCOL_VERSION_ENV_VAR}={HOOK_PROTOCOL_VERSION} git branchless hook ");
+        for (hook_type, hook_script) in ALL_HOOKS {
+            assert!(
+                hook_script.contains(&format!("{expected}{hook_type} ")),
+                "Hook script for {hook_type} should start with: {expected}"
+            );
+        }
+    }
+
     #[test]
     fn test_all_alias_binaries_exist() {
         let all_alias_binaries_installed = cfg!(feature = "man-pages");
@@ -18,6 +18,7 @@ use std::collections::HashMap;
 use std::convert::TryInto;
 use std::ffi::OsString;
 use std::fmt::Write;
+use std::fs::File;
 use std::path::PathBuf;
 use std::time::SystemTime;
 
@@ -25,10 +26,11 @@ use clap::{CommandFactory, FromArgMatches, Parser};
 use cursive_core::theme::BaseColor;
 use cursive_core::utils::markup::StyledString;
 use eyre::Context;
-use git_branchless_opts::{ColorSetting, GlobalArgs};
+use git_branchless_opts::{ColorSetting, GlobalArgs, OutputFormat};
 use lib::core::config::env_vars::{get_git_exec_path, get_path_to_git};
+use lib::core::config::{get_style_glyph_set, get_style_theme};
 use lib::core::effects::Effects;
-use lib::core::formatting::Glyphs;
+use lib::core::formatting::{is_color_disabled_by_env, Glyphs};
 use lib::git::GitRunInfo;
 use lib::git::{Repo, RepoError};
 use lib::util::{ExitCode, EyreExitOr};
@@ -176,6 +178,8 @@ pub fn do_main_and_drop_locals<T: Parser>(
     let GlobalArgs {
         working_directory,
         color,
+        output,
+        output_file,
     } = GlobalArgs::from_arg_matches(&matches)
         .map_err(|err| eyre::eyre!("Could not parse global arguments: {err}"))?;
 
@@ -203,12 +207,64 @@ pub fn do_main_and_drop_locals<T: Parser>(
         },
     };
 
-    let color = match color {
+    let glyphs = match color {
         Some(ColorSetting::Always) => Glyphs::pretty(),
         Some(ColorSetting::Never) => Glyphs::text(),
         Some(ColorSetting::Auto) | None => Glyphs::detect(),
     };
-    let effects = Effects::new(color);
+    // Some output is styled with `console` directly rather than rendered with
+    // `Glyphs`, so make sure that it respects the same setting. When
+    // auto-detecting, `console` does its own detection for each stream.
+    let colors_enabled = match color {
+        Some(ColorSetting::Always) => Some(true),
+        Some(ColorSetting::Never) => Some(false),
+        Some(ColorSetting::Auto) | None if is_color_disabled_by_env() => Some(false),
+        Some(ColorSetting::Auto) | None => None,
+    };
+    if let Some(colors_enabled) = colors_enabled {
+        console::set_colors_enabled(colors_enabled);
+        console::set_colors_enabled_stderr(colors_enabled);
+    }
+    // An invalid style setting shouldn't make every command fail, so fall back
+    // to the default style instead.
+    let mut style_errors = Vec::new();
+    let glyphs = match Repo::from_current_dir() {
+        Ok(repo) => {
+            let glyphs = match get_style_glyph_set(&repo) {
+                Ok(glyph_set) => glyphs.with_glyph_set(glyph_set),
+                Err(err) => {
+                    style_errors.push(err);
+                    glyphs
+                }
+            };
+            match get_style_theme(&repo) {
+                Ok(theme) => glyphs.with_theme(theme),
+                Err(err) => {
+                    style_errors.push(err);
+                    glyphs
+                }
+            }
+        }
+        Err(_) => glyphs,
+    };
+    let effects = Effects::new(glyphs);
+    for err in style_errors {
+        writeln!(
+            effects.get_error_stream(),
+            "Warning: {err:#}; using the default style instead."
+        )?;
+    }
+    let effects = match output {
+        Some(OutputFormat::Human) | None => effects,
+        Some(OutputFormat::JsonLines) => match output_file {
+            Some(output_file) => {
+                let file = File::create(&output_file)
+                    .wrap_err_with(|| format!("Could not open output file: {:?}", &output_file))?;
+                effects.with_step_stream(Box::new(file))
+            }
+            None => eyre::bail!("--output json-lines requires --output-file"),
+        },
+    };
 
     let _tracing_guard = install_tracing(effects.clone());
     install_libgit2_tracing();
@@ -1,7 +1,9 @@
 //! Handle checking out commits on disk.
 
+use std::collections::HashSet;
 use std::ffi::{OsStr, OsString};
 use std::fmt::Write;
+use std::path::PathBuf;
 use std::time::{SystemTime, UNIX_EPOCH};
 
 use cursive::theme::BaseColor;
@@ -12,8 +14,9 @@ use tracing::instrument;
 
 use crate::core::config::get_auto_switch_branches;
 use crate::git::{
-    update_index, CategorizedReferenceName, GitRunInfo, MaybeZeroOid, NonZeroOid, ReferenceName,
-    Repo, Stage, UpdateIndexCommand, WorkingCopySnapshot,
+    update_index, CategorizedReferenceName, CherryPickFastOptions, Commit, CreateCommitFastError,
+    GitRunInfo, MaybeZeroOid, NonZeroOid, ReferenceName, Repo, Stage, Tree, UpdateIndexCommand,
+    WorkingCopySnapshot,
 };
 use crate::try_exit_code;
 use crate::util::EyreExitOr;
@@ -353,3 +356,51 @@ pub fn restore_snapshot(
 
     Ok(Ok(()))
 }
+
+/// Apply the uncommitted changes saved in `snapshot` on top of `commit`, such
+/// as to carry them over to another commit. Returns the resulting tree, or the
+/// paths which conflicted.
+///
+/// The snapshot's unstaged commit contains all of the changes to tracked files
+/// on top of its `HEAD` commit, so both the staged and unstaged changes are
+/// carried over.
+pub fn carry_snapshot_changes<'a>(
+    repo: &'a Repo,
+    snapshot: &'a WorkingCopySnapshot,
+    commit: &'a Commit,
+) -> eyre::Result<Result<Tree<'a>, HashSet<PathBuf>>> {
+    match repo.cherry_pick_fast(
+        &snapshot.commit_unstaged,
+        commit,
+        &CherryPickFastOptions {
+            reuse_parent_tree_if_possible: true,
+        },
+    ) {
+        Ok(tree) => Ok(Ok(tree)),
+        Err(CreateCommitFastError::MergeConflict { conflicting_paths }) => {
+            Ok(Err(conflicting_paths))
+        }
+        Err(other) => eyre::bail!(other),
+    }
+}
+
+/// Write `tree`, as returned by [`carry_snapshot_changes`] for the current
+/// `HEAD` commit, to the working copy. The changes are left unstaged, but any
+/// newly-added files are kept tracked.
+pub fn write_carried_changes(
+    effects: &Effects,
+    git_run_info: &GitRunInfo,
+    event_tx_id: EventTransactionId,
+    tree: &Tree,
+) -> EyreExitOr<()> {
+    try_exit_code!(git_run_info.run(
+        effects,
+        Some(event_tx_id),
+        &["read-tree", "-m", "-u", "HEAD", &tree.get_oid().to_string()],
+    )?);
+    git_run_info.run(
+        effects,
+        Some(event_tx_id),
+        &["reset", "--mixed", "--quiet", "--intent-to-add"],
+    )
+}
@@ -0,0 +1,263 @@
+//! Pluggable providers of additional metadata to display for commits.
+//!
+//! Providers are compiled in behind feature flags and enabled per-repository
+//! by listing their names in the `branchless.commitMetadata.providers` config
+//! variable. Each enabled provider may annotate a commit's line in the
+//! smartlog, e.g. with the issue tracker ticket mentioned in its message.
+
+use cursive::utils::markup::StyledString;
+use tracing::{instrument, warn};
+
+use crate::core::config::get_commit_metadata_providers;
+use crate::git::{Commit, Repo};
+
+use super::formatting::{Glyphs, StyledStringBuilder};
+use super::node_descriptors::{NodeDescriptor, NodeObject, Redactor};
+
+/// A source of additional information about a commit.
+pub trait CommitMetadataProvider {
+    /// The name of the provider, as used in the
+    /// `branchless.commitMetadata.providers` config variable.
+    fn get_name(&self) -> &'static str;
+
+    /// Provide a description of the given commit.
+    ///
+    /// A return value of `None` indicates that the provider has nothing to say
+    /// about the provided commit.
+    fn describe_commit(
+        &mut self,
+        glyphs: &Glyphs,
+        commit: &Commit,
+    ) -> eyre::Result<Option<StyledString>>;
+}
+
+/// The names of all providers compiled into this build.
+pub const AVAILABLE_COMMIT_METADATA_PROVIDERS: &[&str] = &[
+    #[cfg(feature = "commit-metadata-jira")]
+    jira::JiraTicketProvider::NAME,
+    #[cfg(feature = "commit-metadata-review-status")]
+    review_status::ReviewStatusProvider::NAME,
+];
+
+/// Construct the provider with the given name, or `None` if there is no such
+/// provider in this build.
+pub fn make_commit_metadata_provider(name: &str) -> Option<Box<dyn CommitMetadataProvider>> {
+    match name {
+        #[cfg(feature = "commit-metadata-jira")]
+        jira::JiraTicketProvider::NAME => Some(Box::new(jira::JiraTicketProvider)),
+        #[cfg(feature = "commit-metadata-review-status")]
+        review_status::ReviewStatusProvider::NAME => {
+            Some(Box::new(review_status::ReviewStatusProvider))
+        }
+        _ => None,
+    }
+}
+
+/// Display the metadata from all providers enabled for the repository.
+pub struct CommitMetadataDescriptor<'a> {
+    providers: Vec<Box<dyn CommitMetadataProvider>>,
+    redactor: &'a Redactor,
+}
+
+impl std::fmt::Debug for CommitMetadataDescriptor<'_> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let provider_names: Vec<&str> = self
+            .providers
+            .iter()
+            .map(|provider| provider.get_name())
+            .collect();
+        write!(f, "<CommitMetadataDescriptor providers={provider_names:?}>")
+    }
+}
+
+impl<'a> CommitMetadataDescriptor<'a> {
+    /// Constructor.
+    ///
+    /// Provider names which aren't available in this build are skipped with a
+    /// warning, rather than preventing the smartlog from being rendered.
+    pub fn new(repo: &Repo, redactor: &'a Redactor) -> eyre::Result<Self> {
+        let mut providers = Vec::new();
+        for name in get_commit_metadata_providers(repo)? {
+            match make_commit_metadata_provider(&name) {
+                Some(provider) => providers.push(provider),
+                None => {
+                    warn!(
+                        ?name,
+                        available = ?AVAILABLE_COMMIT_METADATA_PROVIDERS,
+                        "Unknown commit metadata provider"
+                    );
+                }
+            }
+        }
+        Ok(CommitMetadataDescriptor {
+            providers,
+            redactor,
+        })
+    }
+}
+
+impl<'a> NodeDescriptor for CommitMetadataDescriptor<'a> {
+    #[instrument]
+    fn describe_node(
+        &mut self,
+        glyphs: &Glyphs,
+        object: &NodeObject,
+    ) -> eyre::Result<Option<StyledString>> {
+        match self.redactor {
+            Redactor::Enabled { .. } => return Ok(None),
+            Redactor::Disabled => {}
+        }
+        let commit = match object {
+            NodeObject::Commit { commit } => commit,
+            NodeObject::GarbageCollected { oid: _ } => return Ok(None),
+        };
+
+        let descriptions = self
+            .providers
+            .iter_mut()
+            .filter_map(|provider| provider.describe_commit(glyphs, commit).transpose())
+            .collect::<eyre::Result<Vec<_>>>()?;
+        if descriptions.is_empty() {
+            Ok(None)
+        } else {
+            Ok(Some(StyledStringBuilder::join(" ", descriptions)))
+        }
+    }
+}
+
+/// Read the full commit message, lossily decoded.
+#[cfg(any(
+    feature = "commit-metadata-jira",
+    feature = "commit-metadata-review-status"
+))]
+fn get_message(commit: &Commit) -> String {
+    use bstr::ByteSlice;
+    commit.get_message_raw().to_str_lossy().into_owned()
+}
+
+#[cfg(feature = "commit-metadata-jira")]
+mod jira {
+    use cursive::theme::BaseColor;
+    use lazy_static::lazy_static;
+    use regex::Regex;
+
+    use super::*;
+
+    /// Display the Jira ticket referenced in the commit message, such as
+    /// `PROJ-123`.
+    #[derive(Debug)]
+    pub struct JiraTicketProvider;
+
+    impl JiraTicketProvider {
+        pub const NAME: &'static str = "jira";
+    }
+
+    pub(super) fn extract_ticket(message: &str) -> Option<String> {
+        lazy_static! {
+            static ref RE: Regex = Regex::new(r"\b(?P<ticket>[A-Z][A-Z0-9_]+-[1-9][0-9]*)\b")
+                .expect("Failed to compile `extract_ticket` regex");
+        }
+        let captures = RE.captures(message)?;
+        Some(captures["ticket"].to_owned())
+    }
+
+    impl CommitMetadataProvider for JiraTicketProvider {
+        fn get_name(&self) -> &'static str {
+            Self::NAME
+        }
+
+        fn describe_commit(
+            &mut self,
+            _glyphs: &Glyphs,
+            commit: &Commit,
+        ) -> eyre::Result<Option<StyledString>> {
+            let result = extract_ticket(&get_message(commit))
+                .map(|ticket| StyledString::styled(ticket, BaseColor::Cyan.dark()));
+            Ok(result)
+        }
+    }
+}
+
+#[cfg(feature = "commit-metadata-review-status")]
+mod review_status {
+    use cursive::theme::BaseColor;
+
+    use super::*;
+
+    /// Display whether the commit has been reviewed, according to the
+    /// `Reviewed-by` and `Acked-by` trailers in its message.
+    #[derive(Debug)]
+    pub struct ReviewStatusProvider;
+
+    impl ReviewStatusProvider {
+        pub const NAME: &'static str = "review-status";
+    }
+
+    pub(super) fn count_reviewers(message: &str) -> usize {
+        message
+            .lines()
+            .filter(|line| line.starts_with("Reviewed-by:") || line.starts_with("Acked-by:"))
+            .count()
+    }
+
+    impl CommitMetadataProvider for ReviewStatusProvider {
+        fn get_name(&self) -> &'static str {
+            Self::NAME
+        }
+
+        fn describe_commit(
+            &mut self,
+            _glyphs: &Glyphs,
+            commit: &Commit,
+        ) -> eyre::Result<Option<StyledString>> {
+            let result = match count_reviewers(&get_message(commit)) {
+                0 => None,
+                1 => Some(StyledString::styled("(reviewed)", BaseColor::Green.light())),
+                num_reviewers => Some(StyledString::styled(
+                    format!("(reviewed x{num_reviewers})"),
+                    BaseColor::Green.light(),
+                )),
+            };
+            Ok(result)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    #[cfg(feature = "commit-metadata-jira")]
+    #[test]
+    fn test_extract_ticket() {
+        use super::jira::extract_ticket;
+
+        assert_eq!(
+            extract_ticket("PROJ-123: fix the thing"),
+            Some(String::from("PROJ-123"))
+        );
+        assert_eq!(
+            extract_ticket("Fix the thing\n\nSee AB2-7 for details."),
+            Some(String::from("AB2-7"))
+        );
+        assert_eq!(extract_ticket("Fix x86-64 build"), None);
+        assert_eq!(extract_ticket("proj-123 is lowercase"), None);
+        assert_eq!(extract_ticket("PROJ-0123 has a leading zero"), None);
+    }
+
+    #[cfg(feature = "commit-metadata-review-status")]
+    #[test]
+    fn test_count_reviewers() {
+        use super::review_status::count_reviewers;
+
+        assert_eq!(count_reviewers("Fix the thing"), 0);
+        assert_eq!(
+            count_reviewers("Fix the thing\n\nReviewed-by: Jane <jane@example.com>\n"),
+            1
+        );
+        assert_eq!(
+            count_reviewers(
+                "Fix the thing\n\nReviewed-by: Jane <jane@example.com>\nAcked-by: Joe <joe@example.com>\n"
+            ),
+            2
+        );
+    }
+}
@@ -9,7 +9,7 @@ use cursive::utils::markup::StyledString;
 use eyre::Context;
 use tracing::{instrument, warn};
 
-use crate::core::formatting::StyledStringBuilder;
+use crate::core::formatting::{parse_color, GlyphSet, StyledStringBuilder, Theme};
 use crate::git::{ConfigRead, GitRunInfo, GitRunOpts, Repo};
 
 use super::effects::Effects;
@@ -61,6 +61,9 @@ pub fn get_main_worktree_hooks_dir(
     Ok(hooks_path)
 }
 
+/// Config key for `get_main_branch_name`.
+pub const MAIN_BRANCH_CONFIG_KEY: &str = "branchless.core.mainBranch";
+
 /// Get the configured name of the main branch.
 ///
 /// The following config values are resolved, in order. The first valid value is returned.
@@ -72,7 +75,7 @@ pub fn get_main_worktree_hooks_dir(
 pub fn get_main_branch_name(repo: &Repo) -> eyre::Result<String> {
     let config = repo.get_readonly_config()?;
 
-    if let Some(branch_name) = config.get("branchless.core.mainBranch")? {
+    if let Some(branch_name) = config.get(MAIN_BRANCH_CONFIG_KEY)? {
         return Ok(branch_name);
     }
 
@@ -87,6 +90,162 @@ pub fn get_main_branch_name(repo: &Repo) -> eyre::Result<String> {
     Ok("master".to_string())
 }
 
+/// Config key for `get_main_commit_spec`.
+pub const MAIN_COMMIT_CONFIG_KEY: &str = "branchless.core.mainCommit";
+
+/// Get the revision which the main branch is pinned to, if any.
+///
+/// This is read from `branchless.core.mainCommit`, and can be any revision
+/// which `git rev-parse` accepts, such as a tag, a commit hash, or
+/// `FETCH_HEAD`. If set, it takes precedence over the main branch, so that the
+/// public commits can be determined in repositories which track their upstream
+/// without a local main branch.
+#[instrument]
+pub fn get_main_commit_spec(repo: &Repo) -> eyre::Result<Option<String>> {
+    repo.get_readonly_config()?.get(MAIN_COMMIT_CONFIG_KEY)
+}
+
+/// Config key for `get_main_branch_patterns`.
+pub const MAIN_BRANCHES_CONFIG_KEY: &str = "branchless.core.mainBranches";
+
+/// Get the patterns for any additional long-lived branches, such as release
+/// branches. Commits reachable from a branch matching any of these patterns are
+/// considered public, just like commits reachable from the main branch.
+///
+/// The patterns are read from `branchless.core.mainBranches`, which can be set
+/// multiple times. Each value is a comma- or whitespace-separated list of
+/// branch names or glob patterns, such as `release/*`.
+#[instrument]
+pub fn get_main_branch_patterns(repo: &Repo) -> eyre::Result<Vec<glob::Pattern>> {
+    get_glob_patterns(repo, MAIN_BRANCHES_CONFIG_KEY)
+}
+
+/// Config section for `get_custom_aliases`. Each alias is set as
+/// `branchless.alias.<name>`.
+pub const CUSTOM_ALIAS_CONFIG_SECTION: &str = "branchless.alias";
+
+/// Get the user-defined aliases, as pairs of the alias name and the `git
+/// branchless` arguments which it expands to, such as `("up", "next --all")`.
+/// They're installed as Git aliases by `git branchless init`, so any extra
+/// arguments passed to the alias are appended to the expansion.
+#[instrument]
+pub fn get_custom_aliases(repo: &Repo) -> eyre::Result<Vec<(String, String)>> {
+    let config = repo.get_readonly_config()?;
+    let prefix = format!("{CUSTOM_ALIAS_CONFIG_SECTION}.");
+    let result = config
+        .list(format!("^{}", regex::escape(&prefix)))?
+        .into_iter()
+        .filter_map(|(key, value)| {
+            key.strip_prefix(&prefix)
+                .map(|name| (name.to_owned(), value))
+        })
+        .collect();
+    Ok(result)
+}
+
+/// Config key for `get_protected_branch_patterns`.
+pub const PROTECTED_BRANCHES_CONFIG_KEY: &str = "branchless.protectedBranches";
+
+/// Get the patterns for branches which are protected from rewriting. Commits
+/// reachable from a branch matching any of these patterns can't be rewritten
+/// without `--force-rewrite`.
+///
+/// The patterns are read from `branchless.protectedBranches`, in the same
+/// format as `branchless.core.mainBranches`.
+#[instrument]
+pub fn get_protected_branch_patterns(repo: &Repo) -> eyre::Result<Vec<glob::Pattern>> {
+    get_glob_patterns(repo, PROTECTED_BRANCHES_CONFIG_KEY)
+}
+
+/// If `true`, commits pointed to by tags can't be rewritten without
+/// `--force-rewrite`, and can't be hidden. Tags usually mark released states of
+/// the repository, which shouldn't change.
+#[instrument]
+pub fn get_protect_tags(repo: &Repo) -> eyre::Result<bool> {
+    repo.get_readonly_config()?
+        .get_or("branchless.protectTags", true)
+}
+
+/// If `true`, record rewrites in the notes under `refs/notes/branchless`, so
+/// that they're visible to other clones of the repository and to other tools.
+/// See [`crate::core::notes`].
+#[instrument]
+pub fn get_mirror_rewrites_to_notes(repo: &Repo) -> eyre::Result<bool> {
+    repo.get_readonly_config()?
+        .get_or("branchless.notes.mirrorRewrites", false)
+}
+
+/// Config key for `get_ignored_ref_patterns`.
+pub const IGNORED_REFS_CONFIG_KEY: &str = "branchless.eventlog.ignoreRefs";
+
+/// Get the patterns for references whose updates shouldn't be recorded in the
+/// event log, such as `refs/notes/*` or `refs/pull/*`. These are matched
+/// against the full reference name.
+///
+/// The patterns are read from `branchless.eventlog.ignoreRefs`, in the same
+/// format as `branchless.core.mainBranches`.
+#[instrument]
+pub fn get_ignored_ref_patterns(repo: &Repo) -> eyre::Result<Vec<glob::Pattern>> {
+    get_glob_patterns(repo, IGNORED_REFS_CONFIG_KEY)
+}
+
+/// If `true`, group the events observed by Git hooks into one transaction per
+/// external Git command (such as `git pull`), so that they can be undone
+/// together. This only has an effect on Linux. See
+/// [`EventLogDb::make_hook_transaction_id`].
+///
+/// [`EventLogDb::make_hook_transaction_id`]: crate::core::eventlog::EventLogDb::make_hook_transaction_id
+#[instrument]
+pub fn get_eventlog_group_external_commands(repo: &Repo) -> eyre::Result<bool> {
+    repo.get_readonly_config()?
+        .get_or("branchless.eventlog.groupExternalCommands", true)
+}
+
+#[instrument]
+fn get_glob_patterns(repo: &Repo, config_key: &str) -> eyre::Result<Vec<glob::Pattern>> {
+    let config = repo.get_readonly_config()?;
+    let mut result = Vec::new();
+    for (_key, value) in config.list(format!("^{}$", regex::escape(&config_key.to_lowercase())))? {
+        for pattern in value
+            .split(|c: char| c == ',' || c.is_whitespace())
+            .filter(|pattern| !pattern.is_empty())
+        {
+            let pattern = glob::Pattern::new(pattern)
+                .wrap_err_with(|| format!("Parsing pattern {pattern:?} in {config_key}"))?;
+            result.push(pattern);
+        }
+    }
+    Ok(result)
+}
+
+/// Config key for `get_protected_remotes`.
+pub const PROTECTED_REMOTES_CONFIG_KEY: &str = "branchless.core.protectedRemotes";
+
+/// Get the names of the remotes which are considered protected. Commits pushed
+/// to (or fetched from) a protected remote are recorded as public, even if they
+/// aren't reachable from the main branch.
+///
+/// The names are read from `branchless.core.protectedRemotes`, which can be set
+/// multiple times. Each value is a comma- or whitespace-separated list of
+/// remote names.
+#[instrument]
+pub fn get_protected_remotes(repo: &Repo) -> eyre::Result<Vec<String>> {
+    let config = repo.get_readonly_config()?;
+    let mut result = Vec::new();
+    for (_key, value) in config.list(format!(
+        "^{}$",
+        regex::escape(&PROTECTED_REMOTES_CONFIG_KEY.to_lowercase())
+    ))? {
+        result.extend(
+            value
+                .split(|c: char| c == ',' || c.is_whitespace())
+                .filter(|remote_name| !remote_name.is_empty())
+                .map(|remote_name| remote_name.to_string()),
+        );
+    }
+    Ok(result)
+}
+
 /// If `true`, switch to the branch associated with a target commit instead of
 /// the commit directly.
 ///
@@ -108,6 +267,73 @@ pub fn get_smartlog_default_revset(repo: &Repo) -> eyre::Result<String> {
         })
 }
 
+/// The maximum number of draft commits to render in the smartlog, if any. See
+/// `git smartlog --limit`.
+#[instrument]
+pub fn get_smartlog_limit(repo: &Repo) -> eyre::Result<Option<usize>> {
+    let limit: Option<i32> = repo
+        .get_readonly_config()?
+        .get("branchless.smartlog.limit")?;
+    match limit {
+        Some(limit) if limit > 0 => Ok(Some(usize::try_from(limit)?)),
+        Some(_) | None => Ok(None),
+    }
+}
+
+/// The paths to which the smartlog is scoped by default, if any. Only the
+/// stacks of draft commits which touch one of these paths are rendered. See
+/// `git smartlog --all-paths`.
+///
+/// The `branchless.smartlog.paths` config variable may be set multiple times.
+/// Each value is a path relative to the root of the repository, or a glob
+/// pattern such as `services/foo/**`.
+#[instrument]
+pub fn get_smartlog_paths(repo: &Repo) -> eyre::Result<Vec<String>> {
+    let entries = repo
+        .get_readonly_config()?
+        .list("branchless.smartlog.paths")?;
+    let result = entries
+        .into_iter()
+        .map(|(_key, value)| value)
+        .filter(|path| !path.is_empty())
+        .collect();
+    Ok(result)
+}
+
+/// The set of characters to use for glyphs, such as in the smartlog.
+#[instrument]
+pub fn get_style_glyph_set(repo: &Repo) -> eyre::Result<GlyphSet> {
+    let glyph_set: Option<String> = repo.get_readonly_config()?.get("branchless.style.glyphs")?;
+    match glyph_set {
+        Some(glyph_set) => glyph_set
+            .parse()
+            .wrap_err("Parsing `branchless.style.glyphs`"),
+        None => Ok(GlyphSet::Unicode),
+    }
+}
+
+/// The theme to use when rendering colored output. The base theme is set with
+/// `branchless.style.theme` (`default` or `high-contrast`), and individual
+/// colors can be replaced with `branchless.style.color.<color> = <color>`,
+/// such as `branchless.style.color.yellow = magenta`.
+#[instrument]
+pub fn get_style_theme(repo: &Repo) -> eyre::Result<Theme> {
+    let config = repo.get_readonly_config()?;
+    let theme_name: Option<String> = config.get("branchless.style.theme")?;
+    let mut theme = match theme_name.as_deref() {
+        None | Some("default") => Theme::default(),
+        Some("high-contrast") => Theme::high_contrast(),
+        Some(other) => eyre::bail!("Unknown theme for `branchless.style.theme`: {other:?}"),
+    };
+    for (key, value) in config.list(r"^branchless\.style\.color\.")? {
+        let from = key.trim_start_matches("branchless.style.color.");
+        let from = parse_color(from).wrap_err_with(|| format!("Parsing config key `{key}`"))?;
+        let to = parse_color(&value).wrap_err_with(|| format!("Parsing value of `{key}`"))?;
+        theme = theme.override_color(from, to);
+    }
+    Ok(theme)
+}
+
 /// Get the default comment character.
 #[instrument]
 pub fn get_comment_char(repo: &Repo) -> eyre::Result<char> {
@@ -196,6 +422,42 @@ pub fn get_editor(git_run_info: &GitRunInfo, repo: &Repo) -> eyre::Result<Option
     }
 }
 
+/// Get the configured pager, if any. Returns `None` if paging is disabled,
+/// such as by setting the pager to `cat`.
+///
+/// As with [`get_editor`], `git var` is consulted first so that the pager is
+/// resolved the same way that Git resolves it: `$GIT_PAGER`, then
+/// `core.pager`, then `$PAGER`.
+#[instrument]
+pub fn get_pager(git_run_info: &GitRunInfo, repo: &Repo) -> eyre::Result<Option<OsString>> {
+    let pager =
+        match git_run_info.run_silent(repo, None, &["var", "GIT_PAGER"], GitRunOpts::default()) {
+            Ok(result) if result.exit_code.is_success() => {
+                let pager = std::str::from_utf8(&result.stdout)
+                    .context("Decoding git var output as UTF-8")?;
+                pager.trim_end().to_string()
+            }
+            result => {
+                warn!(?result, "`git var` invocation failed");
+                match std::env::var("GIT_PAGER") {
+                    Ok(pager) => pager,
+                    Err(_) => {
+                        let pager: Option<String> =
+                            repo.get_readonly_config()?.get("core.pager")?;
+                        match pager {
+                            Some(pager) => pager,
+                            None => std::env::var("PAGER").unwrap_or_else(|_| "less".to_string()),
+                        }
+                    }
+                }
+            }
+        };
+    match pager.trim() {
+        "" | "cat" => Ok(None),
+        pager => Ok(Some(pager.into())),
+    }
+}
+
 /// If `true`, create working copy snapshots automatically after certain
 /// operations.
 #[instrument]
@@ -204,6 +466,49 @@ pub fn get_undo_create_snapshots(repo: &Repo) -> eyre::Result<bool> {
         .get_or("branchless.undo.createSnapshots", true)
 }
 
+/// If `true`, create a working copy snapshot from the `pre-rebase` hook, so
+/// that rebases started outside of git-branchless can be undone with their
+/// working copy contents. The hook is only installed by `git branchless init`
+/// when this is set.
+#[instrument]
+pub fn get_undo_snapshot_before_rebase(repo: &Repo) -> eyre::Result<bool> {
+    repo.get_readonly_config()?
+        .get_or("branchless.undo.snapshotBeforeRebase", false)
+}
+
+/// The number of days for which the commits referred to by the event log are
+/// kept alive when Git's automatic garbage collection runs, even if they've
+/// since been hidden, so that `git undo` can still return to them.
+#[instrument]
+pub fn get_gc_pin_recent_days(repo: &Repo) -> eyre::Result<u64> {
+    let days: i32 = repo
+        .get_readonly_config()?
+        .get_or("branchless.gc.pinRecentDays", 30)?;
+    Ok(u64::try_from(days).unwrap_or_default())
+}
+
+/// If `true`, when hiding commits, move the branches pointing to them under
+/// `refs/branchless/archived/` rather than deleting them, so that they can be
+/// restored by `git unhide`.
+#[instrument]
+pub fn get_hide_archive_branches(repo: &Repo) -> eyre::Result<bool> {
+    repo.get_readonly_config()?
+        .get_or("branchless.hide.archiveBranches", false)
+}
+
+/// If `true`, when rebasing with uncommitted changes in the working copy, save
+/// them in a working copy snapshot first and reapply them on top of the new
+/// `HEAD` afterwards. If an on-disk rebase stops at a merge conflict, they're
+/// reapplied once it's continued to completion. Changes which can't be
+/// reapplied are left in the snapshot. Defaults to the value of
+/// `rebase.autoStash`.
+#[instrument]
+pub fn get_auto_stash(repo: &Repo) -> eyre::Result<bool> {
+    let config = repo.get_readonly_config()?;
+    let rebase_auto_stash = config.get_or("rebase.autoStash", false)?;
+    config.get_or("branchless.autoStash", rebase_auto_stash)
+}
+
 /// If `true`, when restacking a commit, do not update its timestamp to the
 /// current time.
 #[instrument]
@@ -212,6 +517,43 @@ pub fn get_restack_preserve_timestamps(repo: &Repo) -> eyre::Result<bool> {
         .get_or("branchless.restack.preserveTimestamps", false)
 }
 
+/// If `true`, when rebasing, keep commits which become empty rather than
+/// dropping them, unless they were found to be already applied upstream by
+/// their patch IDs. Commits with a `branchless: keep-empty` trailer are always
+/// kept.
+#[instrument]
+pub fn get_restack_keep_empty_commits(repo: &Repo) -> eyre::Result<bool> {
+    repo.get_readonly_config()?
+        .get_or("branchless.restack.keepEmptyCommits", false)
+}
+
+/// If `true`, after amending a commit with `git commit --amend`, automatically
+/// restack its descendants and move its branches onto the amended commit,
+/// rather than only warning about them.
+#[instrument]
+pub fn get_restack_auto_restack_on_amend(repo: &Repo) -> eyre::Result<bool> {
+    repo.get_readonly_config()?
+        .get_or("branchless.restack.autoRestackOnAmend", false)
+}
+
+/// If `true`, whenever commits are rewritten in a way which abandons their
+/// descendants, such as by `git commit --amend` or `git rebase`, restack the
+/// descendants in-memory right away, rather than only warning about them. If
+/// restacking would cause merge conflicts, the warning is printed instead.
+#[instrument]
+pub fn get_restack_auto(repo: &Repo) -> eyre::Result<bool> {
+    repo.get_readonly_config()?
+        .get_or("branchless.restack.auto", false)
+}
+
+/// If `true`, when `git branchless wrap` is run at a terminal and the wrapped
+/// command abandoned any commits, offer to restack them right away.
+#[instrument]
+pub fn get_restack_prompt_abandoned(repo: &Repo) -> eyre::Result<bool> {
+    repo.get_readonly_config()?
+        .get_or("branchless.restack.promptAbandoned", true)
+}
+
 /// If `true`, when advancing to a "next" commit, prompt interactively to
 /// if there is ambiguity in which commit to advance to.
 #[instrument]
@@ -227,6 +569,13 @@ pub fn get_commit_descriptors_branches(repo: &Repo) -> eyre::Result<bool> {
         .get_or("branchless.commitDescriptors.branches", true)
 }
 
+/// If `true`, show tags pointing to each commit in the smartlog.
+#[instrument]
+pub fn get_commit_descriptors_tags(repo: &Repo) -> eyre::Result<bool> {
+    repo.get_readonly_config()?
+        .get_or("branchless.commitDescriptors.tags", true)
+}
+
 /// If `true`, show associated Phabricator commits in the smartlog.
 #[instrument]
 pub fn get_commit_descriptors_differential_revision(repo: &Repo) -> eyre::Result<bool> {
@@ -234,6 +583,22 @@ pub fn get_commit_descriptors_differential_revision(repo: &Repo) -> eyre::Result
         .get_or("branchless.commitDescriptors.differentialRevision", true)
 }
 
+/// If `true`, show which commit each commit was cherry-picked from or reverts
+/// in the smartlog, if recorded.
+#[instrument]
+pub fn get_commit_descriptors_copy_sources(repo: &Repo) -> eyre::Result<bool> {
+    repo.get_readonly_config()?
+        .get_or("branchless.commitDescriptors.copySources", false)
+}
+
+/// If `true`, show the nickname of each draft commit in the smartlog. See
+/// [`crate::core::nicknames`].
+#[instrument]
+pub fn get_commit_descriptors_nicknames(repo: &Repo) -> eyre::Result<bool> {
+    repo.get_readonly_config()?
+        .get_or("branchless.commitDescriptors.nicknames", false)
+}
+
 /// If `true`, show the age of each commit in the smartlog.
 #[instrument]
 pub fn get_commit_descriptors_relative_time(repo: &Repo) -> eyre::Result<bool> {
@@ -241,6 +606,95 @@ pub fn get_commit_descriptors_relative_time(repo: &Repo) -> eyre::Result<bool> {
         .get_or("branchless.commitDescriptors.relativeTime", true)
 }
 
+/// Get the names of the commit metadata providers to use in the smartlog.
+///
+/// The `branchless.commitMetadata.providers` config variable may be set
+/// multiple times, and each value may list several provider names separated by
+/// commas or whitespace.
+#[instrument]
+pub fn get_commit_metadata_providers(repo: &Repo) -> eyre::Result<Vec<String>> {
+    let entries = repo
+        .get_readonly_config()?
+        .list("branchless.commitmetadata.providers")?;
+    let result = entries
+        .iter()
+        .flat_map(|(_key, value)| value.split(|c: char| c == ',' || c.is_whitespace()))
+        .filter(|name| !name.is_empty())
+        .map(|name| name.to_owned())
+        .collect();
+    Ok(result)
+}
+
+/// Get the command to run after commits have been rewritten, if any.
+///
+/// The command is run with a shell and receives a JSON description of the
+/// rewritten commits on stdin.
+#[instrument]
+pub fn get_hooks_post_rewrite(repo: &Repo) -> eyre::Result<Option<String>> {
+    repo.get_readonly_config()?
+        .get("branchless.hooks.postRewrite")
+}
+
+/// Get the command to check the message of each commit created by rewriting
+/// commits, if any.
+///
+/// The command is run with a shell and receives the commit message on stdin.
+/// If it exits unsuccessfully, the operation is aborted.
+#[instrument]
+pub fn get_hooks_message_lint(repo: &Repo) -> eyre::Result<Option<String>> {
+    repo.get_readonly_config()?
+        .get("branchless.hooks.messageLint")
+}
+
+/// Get the template for naming the branches which `git submit` creates for
+/// commits which don't have a branch, if any.
+///
+/// The placeholders `{user}` (the part of `user.email` before the `@`),
+/// `{oid}`, and `{short_oid}` are replaced for each commit. If unset, commits
+/// without a branch aren't submitted.
+#[instrument]
+pub fn get_submit_branch_name_template(repo: &Repo) -> eyre::Result<Option<String>> {
+    repo.get_readonly_config()?
+        .get("branchless.submit.branchNameTemplate")
+}
+
+/// Whether `git submit` should push branches whose commits changed only in
+/// their base, rather than in their contents. These are skipped by default, so
+/// that pushing them doesn't reset the review and CI state of their code
+/// reviews.
+#[instrument]
+pub fn get_submit_push_unchanged_commits(repo: &Repo) -> eyre::Result<bool> {
+    repo.get_readonly_config()?
+        .get_or("branchless.submit.pushUnchangedCommits", false)
+}
+
+/// Get the trailers which `git reword --trailers <forge>` adds for the given
+/// forge, if configured. Each line of the value is one trailer template in the
+/// form `Key: value`.
+#[instrument]
+pub fn get_trailer_template(repo: &Repo, forge: &str) -> eyre::Result<Option<String>> {
+    repo.get_readonly_config()?
+        .get(format!("branchless.trailers.{forge}"))
+}
+
+/// Get the remote to which `git branchless sync-state` pushes and from which
+/// it pulls, if no remote is provided on the command line.
+#[instrument]
+pub fn get_sync_state_remote(repo: &Repo) -> eyre::Result<Option<String>> {
+    repo.get_readonly_config()?
+        .get("branchless.syncState.remote")
+}
+
+/// The maximum number of events which `git branchless sync-state push` stores
+/// on the remote. The oldest events are dropped first.
+#[instrument]
+pub fn get_sync_state_max_events(repo: &Repo) -> eyre::Result<usize> {
+    let max_events: i32 = repo
+        .get_readonly_config()?
+        .get_or("branchless.syncState.maxEvents", 10_000)?;
+    Ok(usize::try_from(max_events).unwrap_or_default())
+}
+
 /// Config key for `get_restack_warn_abandoned`.
 pub const RESTACK_WARN_ABANDONED_CONFIG_KEY: &str = "branchless.restack.warnAbandoned";
 
@@ -259,6 +713,10 @@ pub enum Hint {
     /// Suggest running `git restack` when the smartlog prints an abandoned commit.
     SmartlogFixAbandoned,
 
+    /// Suggest running `git fetch --unshallow` when the smartlog is rendered
+    /// for a shallow clone.
+    SmartlogShallowClone,
+
     /// Suggest showing more output with `git test show` using `--verbose`.
     TestShowVerbose,
 }
@@ -270,6 +728,7 @@ impl Hint {
             Hint::MoveImplicitHeadArgument => "branchless.hint.moveImplicitHeadArgument",
             Hint::RestackWarnAbandoned => "branchless.hint.restackWarnAbandoned",
             Hint::SmartlogFixAbandoned => "branchless.hint.smartlogFixAbandoned",
+            Hint::SmartlogShallowClone => "branchless.hint.smartlogShallowClone",
             Hint::TestShowVerbose => "branchless.hint.testShowVerbose",
         }
     }
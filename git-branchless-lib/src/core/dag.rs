@@ -2,11 +2,12 @@
 //! allows for efficient graph queries.
 
 use std::cmp::Ordering;
-use std::collections::HashMap;
+use std::collections::{HashMap, HashSet};
 use std::convert::TryFrom;
-use std::fmt::Debug;
+use std::fmt::{Debug, Write};
 use std::future::Future;
 use std::iter::FromIterator;
+use std::path::PathBuf;
 use std::sync::{Arc, Mutex};
 
 use async_trait::async_trait;
@@ -20,11 +21,18 @@ use itertools::Itertools;
 use once_cell::sync::OnceCell;
 use tracing::{instrument, trace, warn};
 
+use crate::core::config::{
+    get_main_branch_name, get_main_branch_patterns, get_protect_tags, get_protected_branch_patterns,
+};
 use crate::core::effects::{Effects, OperationType};
 use crate::core::eventlog::{CommitActivityStatus, EventCursor, EventReplayer};
-use crate::git::{Commit, MaybeZeroOid, NonZeroOid, Repo, Time};
+use crate::core::phase::{Phase, PhaseDb};
+use crate::git::{
+    BranchType, CategorizedReferenceName, Commit, CommitGraph, MaybeZeroOid, NonZeroOid,
+    ReferenceName, Repo, Time,
+};
 
-use super::repo_ext::RepoReferencesSnapshot;
+use super::repo_ext::{RepoExt, RepoReferencesSnapshot};
 
 impl From<NonZeroOid> for eden_dag::VertexName {
     fn from(oid: NonZeroOid) -> Self {
@@ -85,6 +93,14 @@ pub fn union_all(commits: &[CommitSet]) -> CommitSet {
 
 struct GitParentsBlocking {
     repo: Arc<Mutex<Repo>>,
+
+    /// The commits at the boundary of a shallow clone. Their parents aren't
+    /// available, so they're treated as roots of the DAG.
+    shallow_commit_oids: HashSet<NonZeroOid>,
+
+    /// The commit-graph written by Git, if any. Looking up parents here avoids
+    /// loading each commit object through libgit2.
+    commit_graph: Option<CommitGraph>,
 }
 
 #[async_trait]
@@ -100,6 +116,16 @@ impl Parents for GitParentsBlocking {
             MaybeZeroOid::NonZero(oid) => oid,
             MaybeZeroOid::Zero => return Ok(Vec::new()),
         };
+        if self.shallow_commit_oids.contains(&oid) {
+            return Ok(Vec::new());
+        }
+        if let Some(parent_oids) = self
+            .commit_graph
+            .as_ref()
+            .and_then(|commit_graph| commit_graph.get_parent_oids(oid))
+        {
+            return Ok(parent_oids.into_iter().map(CommitVertex::from).collect());
+        }
 
         let repo = self.repo.lock().unwrap();
         let commit = repo
@@ -130,6 +156,53 @@ impl Parents for GitParentsBlocking {
     }
 }
 
+/// The path to the file which records the OID that the main branch pointed to
+/// when the DAG was last synced.
+fn get_synced_main_branch_oid_path(repo: &Repo) -> eyre::Result<PathBuf> {
+    let dag_dir = repo.get_dag_dir()?;
+    let mut file_name = dag_dir
+        .file_name()
+        .map(|file_name| file_name.to_os_string())
+        .unwrap_or_default();
+    file_name.push("-main-branch-oid");
+    Ok(dag_dir.with_file_name(file_name))
+}
+
+#[instrument]
+fn write_synced_main_branch_oid(repo: &Repo, main_branch_oid: NonZeroOid) -> eyre::Result<()> {
+    if Dag::get_synced_main_branch_oid(repo)? == Some(main_branch_oid) {
+        return Ok(());
+    }
+    let path = get_synced_main_branch_oid_path(repo)?;
+    std::fs::write(&path, main_branch_oid.to_string())
+        .wrap_err_with(|| format!("Writing file at: {:?}", &path))
+}
+
+/// Get the commits pointed to by local branches whose names match any of the
+/// given patterns.
+fn get_branch_commits_matching(
+    branch_oid_to_names: &HashMap<NonZeroOid, HashSet<ReferenceName>>,
+    patterns: &[glob::Pattern],
+) -> CommitSet {
+    branch_oid_to_names
+        .iter()
+        .filter(|(_oid, reference_names)| {
+            reference_names.iter().any(|reference_name| {
+                match CategorizedReferenceName::new(reference_name) {
+                    name @ CategorizedReferenceName::LocalBranch { .. } => {
+                        let branch_name = name.render_suffix();
+                        patterns.iter().any(|pattern| pattern.matches(&branch_name))
+                    }
+                    CategorizedReferenceName::RemoteBranch { .. }
+                    | CategorizedReferenceName::Tag { .. }
+                    | CategorizedReferenceName::OtherRef { .. } => false,
+                }
+            })
+        })
+        .map(|(oid, _reference_names)| *oid)
+        .collect()
+}
+
 /// Interface to access the directed acyclic graph (DAG) representing Git's
 /// commit graph. Based on the Eden SCM DAG.
 pub struct Dag {
@@ -142,6 +215,30 @@ pub struct Dag {
     /// A set containing the commit that the main branch currently points to.
     pub main_branch_commit: CommitSet,
 
+    /// A set containing the commits currently pointed to by any additional
+    /// long-lived branches, as configured with `branchless.core.mainBranches`.
+    /// Like the main branch, their ancestors are considered public.
+    pub main_branches_commits: CommitSet,
+
+    /// A set containing the commits which were explicitly recorded as public
+    /// (see [`crate::core::phase`]). Their ancestors are also considered
+    /// public.
+    pub public_phase_commits: CommitSet,
+
+    /// A set containing the commits which were explicitly recorded as secret.
+    /// Their non-public descendants are also considered secret.
+    pub secret_phase_commits: CommitSet,
+
+    /// A set containing the commits currently pointed to by protected
+    /// branches, as configured with `branchless.protectedBranches`. They and
+    /// their ancestors may not be rewritten without `--force-rewrite`.
+    pub protected_branches_commits: CommitSet,
+
+    /// A set containing the commits currently pointed to by tags, if
+    /// `branchless.protectTags` is set. They may not be rewritten without
+    /// `--force-rewrite`.
+    pub protected_tags_commits: CommitSet,
+
     /// A set containing all commits currently pointed to by local branches.
     pub branch_commits: CommitSet,
 
@@ -167,6 +264,11 @@ impl Dag {
             inner,
             head_commit: self.head_commit.clone(),
             main_branch_commit: self.main_branch_commit.clone(),
+            main_branches_commits: self.main_branches_commits.clone(),
+            public_phase_commits: self.public_phase_commits.clone(),
+            secret_phase_commits: self.secret_phase_commits.clone(),
+            protected_branches_commits: self.protected_branches_commits.clone(),
+            protected_tags_commits: self.protected_tags_commits.clone(),
             branch_commits: self.branch_commits.clone(),
             observed_commits: self.observed_commits.clone(),
             obsolete_commits: self.obsolete_commits.clone(),
@@ -195,9 +297,117 @@ impl Dag {
             references_snapshot,
         )?;
         dag.sync(effects, repo)?;
+
+        let main_branch_oid = references_snapshot.main_branch_oid;
+        if let Some(abandoned_commits) =
+            dag.get_main_branch_rewritten_commits(repo, main_branch_oid)?
+        {
+            // The main branch was rewritten upstream (for example, by a
+            // force-push) since the DAG was last synced, so the abandoned
+            // commits are still recorded as part of the main branch's
+            // segments. Those segments are append-only, so the only way to
+            // invalidate them is to rebuild the graph. The new main branch
+            // OID is recorded below, so this only happens once per rewrite.
+            warn!(
+                ?main_branch_oid,
+                num_abandoned_commits = dag.set_count(&abandoned_commits)?,
+                "Main branch was rewritten upstream; rebuilding the commit graph cache"
+            );
+            drop(dag);
+            Self::clear_cache(repo)?;
+            dag = Self::open_without_syncing(
+                effects,
+                repo,
+                event_replayer,
+                event_cursor,
+                references_snapshot,
+            )?;
+            dag.sync(effects, repo)?;
+        }
+        write_synced_main_branch_oid(repo, main_branch_oid)?;
+
         Ok(dag)
     }
 
+    /// Delete the on-disk cache of the commit graph. It will be rebuilt the
+    /// next time that the DAG is opened and synced.
+    #[instrument]
+    pub fn clear_cache(repo: &Repo) -> eyre::Result<()> {
+        let dag_dir = repo.get_dag_dir()?;
+        match std::fs::remove_dir_all(&dag_dir) {
+            Ok(()) => {}
+            Err(err) if err.kind() == std::io::ErrorKind::NotFound => {}
+            Err(err) => {
+                return Err(err)
+                    .wrap_err_with(|| format!("Deleting DAG directory at: {:?}", &dag_dir))
+            }
+        }
+        let synced_main_branch_oid_path = get_synced_main_branch_oid_path(repo)?;
+        match std::fs::remove_file(&synced_main_branch_oid_path) {
+            Ok(()) => {}
+            Err(err) if err.kind() == std::io::ErrorKind::NotFound => {}
+            Err(err) => {
+                return Err(err).wrap_err_with(|| {
+                    format!("Deleting file at: {:?}", &synced_main_branch_oid_path)
+                })
+            }
+        }
+        Ok(())
+    }
+
+    /// Get the OID that the main branch pointed to when the DAG was last
+    /// synced, if known.
+    #[instrument]
+    pub fn get_synced_main_branch_oid(repo: &Repo) -> eyre::Result<Option<NonZeroOid>> {
+        let path = get_synced_main_branch_oid_path(repo)?;
+        match std::fs::read_to_string(&path) {
+            Ok(contents) => Ok(contents.trim().parse().ok()),
+            Err(err) if err.kind() == std::io::ErrorKind::NotFound => Ok(None),
+            Err(err) => Err(err).wrap_err_with(|| format!("Reading file at: {:?}", &path)),
+        }
+    }
+
+    /// If the main branch was rewritten upstream since the DAG was last
+    /// synced, return the commits which used to be part of the main branch
+    /// but no longer are.
+    ///
+    /// Local rewrites of the main branch (such as amending it or resetting it)
+    /// aren't considered, since they don't change any of the segments which
+    /// the DAG has already indexed as part of the main branch.
+    #[instrument]
+    fn get_main_branch_rewritten_commits(
+        &self,
+        repo: &Repo,
+        main_branch_oid: NonZeroOid,
+    ) -> eyre::Result<Option<CommitSet>> {
+        let synced_main_branch_oid = match Self::get_synced_main_branch_oid(repo)? {
+            Some(synced_main_branch_oid) if synced_main_branch_oid != main_branch_oid => {
+                synced_main_branch_oid
+            }
+            Some(_) | None => return Ok(None),
+        };
+        if !self.set_contains(&self.query_all()?, synced_main_branch_oid)? {
+            return Ok(None);
+        }
+        if self.query_is_ancestor(synced_main_branch_oid, main_branch_oid)? {
+            return Ok(None);
+        }
+
+        let main_branch = repo.find_branch(&get_main_branch_name(repo)?, BranchType::Local)?;
+        let upstream_main_branch_oid = match main_branch {
+            Some(main_branch) => main_branch.get_upstream_branch_target()?,
+            None => None,
+        };
+        if upstream_main_branch_oid != Some(main_branch_oid) {
+            return Ok(None);
+        }
+
+        let abandoned_commits = self
+            .query_ancestors(CommitSet::from(synced_main_branch_oid))?
+            .difference(&self.query_ancestors(CommitSet::from(main_branch_oid))?);
+        Ok(Some(abandoned_commits))
+    }
+
     /// Initialize a DAG for the given repository, without updating it with new
     /// commits that may have appeared.
     ///
@@ -217,6 +427,7 @@ impl Dag {
             head_oid,
             main_branch_oid,
             branch_oid_to_names,
+            tag_oid_to_names,
         } = references_snapshot;
 
         let obsolete_commits: CommitSet = observed_commits
@@ -239,11 +450,44 @@ impl Dag {
         };
         let main_branch_commit = CommitSet::from(*main_branch_oid);
         let branch_commits: CommitSet = branch_oid_to_names.keys().copied().collect();
+        let main_branches_commits =
+            get_branch_commits_matching(branch_oid_to_names, &get_main_branch_patterns(repo)?);
+        let protected_branches_commits =
+            get_branch_commits_matching(branch_oid_to_names, &get_protected_branch_patterns(repo)?);
+        let protected_tags_commits: CommitSet = if get_protect_tags(repo)? {
+            tag_oid_to_names.keys().copied().collect()
+        } else {
+            CommitSet::empty()
+        };
+        let (public_phase_commits, secret_phase_commits) = {
+            let conn = repo.get_db_conn()?;
+            let phase_db = PhaseDb::new(&conn)?;
+            let get_phase_commits = |phase: Phase| -> eyre::Result<CommitSet> {
+                let mut result = Vec::new();
+                for commit_oid in phase_db.get_commits_with_phase(phase)? {
+                    // The commit may have been garbage-collected since its
+                    // phase was recorded.
+                    if repo.find_commit(commit_oid)?.is_some() {
+                        result.push(commit_oid);
+                    }
+                }
+                Ok(result.into_iter().collect())
+            };
+            (
+                get_phase_commits(Phase::Public)?,
+                get_phase_commits(Phase::Secret)?,
+            )
+        };
 
         Ok(Self {
             inner: dag,
             head_commit,
             main_branch_commit,
+            main_branches_commits,
+            public_phase_commits,
+            secret_phase_commits,
+            protected_branches_commits,
+            protected_tags_commits,
             branch_commits,
             observed_commits,
             obsolete_commits,
@@ -270,11 +514,12 @@ impl Dag {
     /// Update the DAG with all commits reachable from branches.
     #[instrument]
     fn sync(&mut self, effects: &Effects, repo: &Repo) -> eyre::Result<()> {
-        let master_heads = self.main_branch_commit.clone();
+        let master_heads = self.query_public_heads();
         let non_master_heads = self
             .observed_commits
             .union(&self.head_commit)
-            .union(&self.branch_commits);
+            .union(&self.branch_commits)
+            .union(&self.secret_phase_commits);
         self.sync_from_oids(effects, repo, master_heads, non_master_heads)
     }
 
@@ -288,7 +533,6 @@ impl Dag {
         non_master_heads: CommitSet,
     ) -> eyre::Result<()> {
         let (effects, _progress) = effects.start_operation(OperationType::UpdateCommitGraph);
-        let _effects = effects;
 
         let master_group_options = {
             let mut options = VertexOptions::default();
@@ -307,10 +551,25 @@ impl Dag {
             .collect_vec();
         let heads = [master_heads, non_master_heads].concat();
 
+        let shallow_commit_oids = repo.get_shallow_commit_oids()?;
+        let commit_graph = match repo.get_commit_graph() {
+            Ok(commit_graph) => commit_graph,
+            Err(err) => {
+                // The commit-graph is only an optimization, so walk the
+                // commit objects instead.
+                writeln!(
+                    effects.get_error_stream(),
+                    "Warning: ignoring the commit-graph: {err}"
+                )?;
+                None
+            }
+        };
         let repo = repo.try_clone()?;
         futures::executor::block_on(self.inner.add_heads_and_flush(
             &GitParentsBlocking {
                 repo: Arc::new(Mutex::new(repo)),
+                shallow_commit_oids,
+                commit_graph,
             },
             &VertexListWithOptions::from(heads),
         ))?;
@@ -346,6 +605,11 @@ impl Dag {
             head_commit: self.head_commit.clone(),
             branch_commits: self.branch_commits.clone(),
             main_branch_commit: self.main_branch_commit.clone(),
+            main_branches_commits: self.main_branches_commits.clone(),
+            public_phase_commits: self.public_phase_commits.clone(),
+            secret_phase_commits: self.secret_phase_commits.clone(),
+            protected_branches_commits: self.protected_branches_commits.clone(),
+            protected_tags_commits: self.protected_tags_commits.clone(),
             observed_commits: self.observed_commits.clone(),
             obsolete_commits: CommitSet::empty(),
             draft_commits: Default::default(),
@@ -401,11 +665,24 @@ impl Dag {
         &self.inner
     }
 
+    /// Get the commits pointed to by the main branch and by any additional
+    /// long-lived branches. Their ancestors are considered public.
+    pub fn query_main_heads(&self) -> CommitSet {
+        self.main_branch_commit.union(&self.main_branches_commits)
+    }
+
+    /// Get the commits whose ancestors are considered public: the main heads
+    /// (see `query_main_heads`) and any commits explicitly recorded as public.
+    pub fn query_public_heads(&self) -> CommitSet {
+        self.query_main_heads().union(&self.public_phase_commits)
+    }
+
     /// Determine whether or not the given commit is a public commit (i.e. is an
-    /// ancestor of the main branch).
+    /// ancestor of the main branch, of an additional long-lived branch, or of a
+    /// commit explicitly recorded as public).
     #[instrument]
     pub fn is_public_commit(&self, commit_oid: NonZeroOid) -> eyre::Result<bool> {
-        let main_branch_commits = self.commit_set_to_vec(&self.main_branch_commit)?;
+        let main_branch_commits = self.commit_set_to_vec(&self.query_public_heads())?;
         for main_branch_commit in main_branch_commits {
             if self.run_blocking(
                 self.inner
@@ -463,7 +740,7 @@ impl Dag {
     pub fn query_public_commits_slow(&self) -> eyre::Result<&CommitSet> {
         self.public_commits.get_or_try_init(|| {
             let public_commits =
-                self.run_blocking(self.inner.ancestors(self.main_branch_commit.clone()))?;
+                self.run_blocking(self.inner.ancestors(self.query_public_heads()))?;
             Ok(public_commits)
         })
     }
@@ -478,7 +755,8 @@ impl Dag {
                 .union(&self.observed_commits.difference(&self.obsolete_commits))
                 .union(&self.head_commit)
                 .union(&self.main_branch_commit)
-                .union(&self.branch_commits);
+                .union(&self.branch_commits)
+                .union(&self.public_phase_commits);
             let visible_heads = self.run_blocking(self.inner.heads(visible_heads))?;
             Ok(visible_heads)
         })
@@ -521,12 +799,21 @@ impl Dag {
             let visible_heads = self.query_visible_heads()?;
             let draft_commits = self.run_blocking(
                 self.inner
-                    .only(visible_heads.clone(), self.main_branch_commit.clone()),
+                    .only(visible_heads.clone(), self.query_public_heads()),
             )?;
             Ok(draft_commits)
         })
     }
 
+    /// Determine the set of "secret" commits. These are the draft commits
+    /// which were explicitly recorded as secret, or which are descendants of
+    /// such commits.
+    #[instrument]
+    pub fn query_secret_commits(&self) -> eyre::Result<CommitSet> {
+        let secret_commits = self.query_descendants(self.secret_phase_commits.clone())?;
+        Ok(secret_commits.intersection(self.query_draft_commits()?))
+    }
+
     /// Wrapper around DAG method.
     #[instrument]
     pub fn query_all(&self) -> eyre::Result<CommitSet> {
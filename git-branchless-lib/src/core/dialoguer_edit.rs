@@ -40,7 +40,7 @@ use std::process;
 /// ## Example
 ///
 /// ```rust,no_run
-/// use git_branchless_reword::dialoguer_edit::Editor;
+/// use branchless::core::dialoguer_edit::Editor;
 ///
 /// if let Some(rv) = Editor::new().edit("Enter a commit message").unwrap() {
 ///     println!("Your message:");
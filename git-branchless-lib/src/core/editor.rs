@@ -0,0 +1,33 @@
+//! Invoke the user's configured editor to edit text, such as commit messages.
+
+use eyre::Context;
+use tracing::instrument;
+
+use crate::core::config::get_editor;
+use crate::core::dialoguer_edit::Editor;
+use crate::git::{GitRunInfo, Repo};
+
+/// Open the user's configured commit editor seeded with the provided message.
+///
+/// The editor is resolved in the same way as Git resolves it: `$GIT_EDITOR`,
+/// then `core.editor`, then `$VISUAL` and `$EDITOR`. Commands which call this
+/// should also accept the message as an argument (usually `-m`/`--message`),
+/// so that they can be used from scripts without an editor.
+#[instrument]
+pub fn edit_message(git_run_info: &GitRunInfo, repo: &Repo, message: &str) -> eyre::Result<String> {
+    let mut editor = Editor::new();
+    let (editor, editor_program) = match get_editor(git_run_info, repo)? {
+        Some(editor_program) => (editor.executable(&editor_program), editor_program),
+        None => (&mut editor, "<default>".into()),
+    };
+    if editor_program == ":" {
+        // Special case in Git: treat `:` as a no-op editor.
+        return Ok(message.to_string());
+    }
+    let result = editor
+        .require_save(false)
+        .edit(message)
+        .with_context(|| format!("Invoking editor: '{}'", editor_program.to_string_lossy()))?
+        .expect("`Editor::edit` should not return `None` when `require_save` is `false`");
+    Ok(result)
+}
@@ -12,6 +12,7 @@ use std::{io, thread};
 use indicatif::{MultiProgress, ProgressBar, ProgressDrawTarget, ProgressStyle};
 use itertools::Itertools;
 use lazy_static::lazy_static;
+use serde::Serialize;
 use tracing::warn;
 
 use crate::core::formatting::Glyphs;
@@ -41,6 +42,7 @@ pub enum OperationType {
     RebaseCommits,
     RepairBranches,
     RepairCommits,
+    RepairEvents,
     RunGitCommand(Arc<String>),
     RunTestOnCommit(Arc<String>),
     RunTests(Arc<String>),
@@ -78,6 +80,7 @@ impl ToString for OperationType {
             OperationType::RebaseCommits => "Rebasing commits",
             OperationType::RepairBranches => "Checking for broken branches",
             OperationType::RepairCommits => "Checking for broken commits",
+            OperationType::RepairEvents => "Checking events for missing commits",
             OperationType::RunGitCommand(command) => {
                 return format!("Running Git command: {}", &command)
             }
@@ -103,6 +106,55 @@ enum OutputDest {
     },
 }
 
+/// A machine-readable notification about a step of a long-running operation,
+/// such as a commit being rebased, for use by editor integrations. Reported
+/// with [`Effects::report_step`] and written as a line of JSON.
+#[derive(Clone, Debug, Serialize)]
+#[serde(tag = "event", rename_all = "kebab-case")]
+pub enum StepEvent {
+    /// The steps which the operation is about to carry out.
+    Planned {
+        /// The name of the operation, such as `rebase`.
+        operation: &'static str,
+
+        /// The identifiers of the steps, such as commit hashes.
+        steps: Vec<String>,
+    },
+
+    /// A step has started.
+    Started {
+        /// The name of the operation.
+        operation: &'static str,
+
+        /// The identifier of the step.
+        step: String,
+    },
+
+    /// A step has finished.
+    Finished {
+        /// The name of the operation.
+        operation: &'static str,
+
+        /// The identifier of the step.
+        step: String,
+
+        /// The outcome of the step, such as `committed` or `passed`.
+        status: String,
+    },
+
+    /// A step couldn't be completed because of merge conflicts.
+    Conflicted {
+        /// The name of the operation.
+        operation: &'static str,
+
+        /// The identifier of the step.
+        step: String,
+
+        /// The paths which were in conflict.
+        paths: Vec<String>,
+    },
+}
+
 /// An index into the recursive hierarchy of progress bars. For example, the key
 /// `[OperationType::GetMergeBase, OperationType::WalkCommits]` refers to the
 /// "walk commits" operation which is nested under the "get merge-base"
@@ -122,6 +174,7 @@ impl RootOperation {
     }
 
     pub fn show_multi_progress(&mut self) {
+        // This draw target is hidden if stderr is not a terminal.
         self.multi_progress
             .set_draw_target(ProgressDrawTarget::stderr());
     }
@@ -371,7 +424,7 @@ impl OperationState {
                 // be visible in the terminal, so we add a space at the end of
                 // the line so that the length number isn't overlapped by the
                 // cursor.
-                Arc::new(Mutex::new(ProgressStyle::default_bar().template("{prefix}{spinner} {wide_msg} {bar} {pos}/{len} ").unwrap()));
+                Arc::new(Mutex::new(ProgressStyle::default_bar().template("{prefix}{spinner} {wide_msg} {bar} {pos}/{len} (ETA {eta}) ").unwrap()));
             static ref WAITING_PROGRESS_STYLE: Arc<Mutex<ProgressStyle>> = Arc::new(Mutex::new(IN_PROGRESS_SPINNER_STYLE
                 .clone().lock().unwrap().clone()
                 // Requires at least two tick values, so just pass the same one twice.
@@ -443,6 +496,7 @@ pub struct Effects {
     updater_thread_handle: Arc<RwLock<UpdaterThreadHandle>>,
     operation_key: Vec<OperationType>,
     root_operation: Arc<Mutex<RootOperation>>,
+    step_stream: Option<Arc<Mutex<Box<dyn WriteIo + Send>>>>,
 }
 
 impl std::fmt::Debug for Effects {
@@ -518,6 +572,7 @@ impl Effects {
             updater_thread_handle,
             operation_key: Default::default(),
             root_operation,
+            step_stream: None,
         }
     }
 
@@ -529,6 +584,7 @@ impl Effects {
             updater_thread_handle: Default::default(),
             operation_key: Default::default(),
             root_operation: Default::default(),
+            step_stream: None,
         }
     }
 
@@ -547,6 +603,7 @@ impl Effects {
             updater_thread_handle: Default::default(),
             operation_key: Default::default(),
             root_operation: Default::default(),
+            step_stream: None,
         }
     }
 
@@ -578,6 +635,46 @@ impl Effects {
         }
     }
 
+    /// Write each [`StepEvent`] reported to the returned `Effects` to the
+    /// provided stream, as one line of JSON per event.
+    pub fn with_step_stream(self, stream: Box<dyn WriteIo + Send>) -> Self {
+        Self {
+            step_stream: Some(Arc::new(Mutex::new(stream))),
+            ..self
+        }
+    }
+
+    /// Report a step of a long-running operation to the step stream, if one
+    /// was provided with [`Effects::with_step_stream`]. The step stream is
+    /// independent of the regular output, so it's written even if output is
+    /// suppressed.
+    pub fn report_step(&self, event: StepEvent) {
+        let step_stream = match &self.step_stream {
+            Some(step_stream) => step_stream,
+            None => return,
+        };
+        let mut line = match serde_json::to_string(&event) {
+            Ok(line) => line,
+            Err(err) => {
+                warn!(?err, ?event, "Failed to serialize step event");
+                return;
+            }
+        };
+        line.push('\n');
+
+        // A panic on another thread while writing shouldn't stop the rest of
+        // the steps from being reported.
+        let mut step_stream = step_stream
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner());
+        if let Err(err) = step_stream
+            .write_all(line.as_bytes())
+            .and_then(|()| step_stream.flush())
+        {
+            warn!(?err, "Failed to write step event");
+        }
+    }
+
     /// Start reporting progress for the specified operation type.
     ///
     /// A progress spinner is shown until the returned `ProgressHandle` is
@@ -1091,6 +1188,17 @@ mod tests {
         Ok(())
     }
 
+    #[test]
+    fn test_effects_progress_hidden_when_not_attended() {
+        let effects = Effects::new(Glyphs::text());
+        let mut root_operation = effects.root_operation.lock().unwrap();
+        root_operation.show_multi_progress();
+        assert_eq!(
+            root_operation.multi_progress.is_hidden(),
+            !console::user_attended_stderr()
+        );
+    }
+
     /// Test for the issue fixed by <https://github.com/console-rs/indicatif/pull/403>.
     #[test]
     fn test_effects_progress_rewind_panic() -> eyre::Result<()> {
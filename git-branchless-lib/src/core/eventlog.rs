@@ -8,15 +8,20 @@
 use std::cmp::Ordering;
 use std::collections::{HashMap, HashSet};
 use std::convert::{TryFrom, TryInto};
+use std::fmt::Display;
 
+use std::path::Path;
 use std::str::FromStr;
 use std::time::{Duration, SystemTime};
 
 use eyre::Context;
 use tracing::{error, instrument};
 
+use crate::core::config::get_eventlog_group_external_commands;
 use crate::core::effects::{Effects, OperationType};
+use crate::core::nicknames::{self, transfer_nicknames};
 use crate::core::repo_ext::RepoExt;
+use crate::core::stacks::{self, transfer_stack_tips};
 use crate::git::{CategorizedReferenceName, MaybeZeroOid, NonZeroOid, ReferenceName, Repo};
 
 use super::repo_ext::RepoReferencesSnapshot;
@@ -53,7 +58,7 @@ struct Row {
 ///
 /// Unlike in a database, there is no specific guarantee that an event
 /// transaction is an atomic unit of work.
-#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
 pub enum EventTransactionId {
     /// A normal transaction ID.
     Id(isize),
@@ -62,11 +67,11 @@ pub enum EventTransactionId {
     Suppressed,
 }
 
-impl ToString for EventTransactionId {
-    fn to_string(&self) -> String {
+impl Display for EventTransactionId {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         match self {
-            EventTransactionId::Id(event_id) => event_id.to_string(),
-            EventTransactionId::Suppressed => "SUPPRESSED".to_string(),
+            EventTransactionId::Id(event_id) => write!(f, "{event_id}"),
+            EventTransactionId::Suppressed => write!(f, "SUPPRESSED"),
         }
     }
 }
@@ -84,6 +89,56 @@ impl FromStr for EventTransactionId {
     }
 }
 
+/// An event transaction as recorded in the database.
+#[derive(Clone, Debug, PartialEq)]
+pub struct EventTransaction {
+    /// The ID of the transaction.
+    pub event_tx_id: EventTransactionId,
+
+    /// The timestamp at which the transaction was started.
+    pub timestamp: f64,
+
+    /// The name of the operation which started the transaction, such as
+    /// `move` or `hook-post-checkout`.
+    pub message: String,
+
+    /// A human-readable description of what the operation did, such as `move 3
+    /// commits onto abc123`, if one was recorded.
+    pub label: Option<String>,
+}
+
+impl EventTransaction {
+    /// Get the label of the transaction if one was recorded, or its message
+    /// otherwise.
+    pub fn get_description(&self) -> &str {
+        match &self.label {
+            Some(label) => label,
+            None => &self.message,
+        }
+    }
+}
+
+/// The way in which one commit was copied from another. See
+/// [`Event::CopyEvent`].
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum CopyType {
+    /// The commit was created by `git cherry-pick`.
+    CherryPick,
+
+    /// The commit was created by `git revert`.
+    Revert,
+}
+
+impl CopyType {
+    /// The name of the copy type, as stored in the event log.
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            CopyType::CherryPick => "cherry-pick",
+            CopyType::Revert => "revert",
+        }
+    }
+}
+
 /// An event that occurred to one of the commits in the repository.
 #[derive(Clone, Debug, PartialEq)]
 pub enum Event {
@@ -198,6 +253,28 @@ pub enum Event {
         /// reference name like `refs/heads/foo`.
         ref_name: Option<ReferenceName>,
     },
+
+    /// Indicates that a commit was created from another commit, such as by
+    /// `git cherry-pick` or `git revert`.
+    ///
+    /// Unlike with a rewrite, the source commit is not obsoleted. This event
+    /// only records the relationship between the two commits.
+    CopyEvent {
+        /// The timestamp of the event.
+        timestamp: f64,
+
+        /// The transaction ID of the event.
+        event_tx_id: EventTransactionId,
+
+        /// How the commit was copied.
+        copy_type: CopyType,
+
+        /// The OID of the commit which was copied.
+        source_commit_oid: NonZeroOid,
+
+        /// The OID of the newly-created commit.
+        new_commit_oid: NonZeroOid,
+    },
 }
 
 impl Event {
@@ -210,6 +287,7 @@ impl Event {
             Event::ObsoleteEvent { timestamp, .. } => timestamp,
             Event::UnobsoleteEvent { timestamp, .. } => timestamp,
             Event::WorkingCopySnapshot { timestamp, .. } => timestamp,
+            Event::CopyEvent { timestamp, .. } => timestamp,
         };
         SystemTime::UNIX_EPOCH + Duration::from_secs_f64(*timestamp)
     }
@@ -223,6 +301,7 @@ impl Event {
             Event::ObsoleteEvent { event_tx_id, .. } => *event_tx_id,
             Event::UnobsoleteEvent { event_tx_id, .. } => *event_tx_id,
             Event::WorkingCopySnapshot { event_tx_id, .. } => *event_tx_id,
+            Event::CopyEvent { event_tx_id, .. } => *event_tx_id,
         }
     }
 }
@@ -255,6 +334,10 @@ impl TryFrom<Event> for Row {
             | Event::WorkingCopySnapshot {
                 event_tx_id: EventTransactionId::Suppressed,
                 ..
+            }
+            | Event::CopyEvent {
+                event_tx_id: EventTransactionId::Suppressed,
+                ..
             } => return Err(()),
 
             Event::RewriteEvent {
@@ -346,6 +429,22 @@ impl TryFrom<Event> for Row {
                 ref_name,
                 message: None,
             },
+
+            Event::CopyEvent {
+                timestamp,
+                event_tx_id: EventTransactionId::Id(event_tx_id),
+                copy_type,
+                source_commit_oid,
+                new_commit_oid,
+            } => Row {
+                timestamp,
+                event_tx_id,
+                type_: String::from(copy_type.as_str()),
+                ref1: Some(source_commit_oid.into()),
+                ref2: Some(new_commit_oid.into()),
+                ref_name: None,
+                message: None,
+            },
         };
         Ok(row)
     }
@@ -445,6 +544,23 @@ fn try_from_row_helper(row: &Row) -> Result<Event, eyre::Error> {
             }
         }
 
+        copy_type @ ("cherry-pick" | "revert") => {
+            let copy_type = if copy_type == CopyType::Revert.as_str() {
+                CopyType::Revert
+            } else {
+                CopyType::CherryPick
+            };
+            let source_commit_oid: NonZeroOid = get_oid(&ref1, "source commit OID")?.try_into()?;
+            let new_commit_oid: NonZeroOid = get_oid(&ref2, "new commit OID")?.try_into()?;
+            Event::CopyEvent {
+                timestamp,
+                event_tx_id,
+                copy_type,
+                source_commit_oid,
+                new_commit_oid,
+            }
+        }
+
         other => eyre::bail!("Unknown event type {}", other),
     };
     Ok(event)
@@ -464,6 +580,29 @@ impl TryFrom<Row> for Event {
     }
 }
 
+/// A backend which stores `Event`s and the transactions they belong to.
+///
+/// [`EventLogDb`] stores them in the repository's SQLite database. Other
+/// backends (such as a service shared by a team) can implement this trait to
+/// have the event log replayed from them instead.
+pub trait EventLogStorage: std::fmt::Debug {
+    /// Add events in the given order to the storage, atomically.
+    fn add_events(&self, events: Vec<Event>) -> eyre::Result<()>;
+
+    /// Get all the stored events, ordered from oldest to newest.
+    fn get_events(&self) -> eyre::Result<Vec<Event>>;
+
+    /// Create a new event transaction ID to be used to add subsequent events.
+    fn make_transaction_id(
+        &self,
+        now: SystemTime,
+        message: &str,
+    ) -> eyre::Result<EventTransactionId>;
+
+    /// Get all transactions which have been recorded, from oldest to newest.
+    fn get_transactions(&self) -> eyre::Result<Vec<EventTransaction>>;
+}
+
 /// Stores `Event`s on disk.
 pub struct EventLogDb<'conn> {
     conn: &'conn rusqlite::Connection,
@@ -511,9 +650,96 @@ CREATE TABLE IF NOT EXISTS event_transactions (
     )
     .wrap_err("Creating `event_transactions` table")?;
 
+    // Stored separately from `event_transactions` so that existing databases
+    // don't need to be migrated.
+    conn.execute(
+        "
+CREATE TABLE IF NOT EXISTS event_transaction_labels (
+    event_tx_id INTEGER NOT NULL PRIMARY KEY,
+    label TEXT NOT NULL
+)
+",
+        rusqlite::params![],
+    )
+    .wrap_err("Creating `event_transaction_labels` table")?;
+
+    conn.execute(
+        "
+CREATE TABLE IF NOT EXISTS event_transaction_commands (
+    event_tx_id INTEGER NOT NULL PRIMARY KEY,
+    command_key TEXT NOT NULL
+)
+",
+        rusqlite::params![],
+    )
+    .wrap_err("Creating `event_transaction_commands` table")?;
+    conn.execute(
+        "
+CREATE INDEX IF NOT EXISTS event_transaction_commands_command_key
+ON event_transaction_commands (command_key)
+",
+        rusqlite::params![],
+    )
+    .wrap_err("Creating `event_transaction_commands` index")?;
+
+    // Adding rewrite events updates the nicknames of commits and the tips of
+    // named stacks.
+    nicknames::init_tables(conn)?;
+    stacks::init_tables(conn)?;
+
     Ok(())
 }
 
+/// Identify the outermost Git command which (possibly indirectly) started the
+/// current process, by its PID and start time. For example, when a hook is
+/// invoked by the `git merge` run by `git pull`, this identifies the `git
+/// pull` process.
+///
+/// Returns `None` if the command can't be determined, such as when not running
+/// under Git, or on platforms other than Linux.
+#[cfg(target_os = "linux")]
+pub(crate) fn get_external_git_command_key() -> Option<String> {
+    /// Get the command name, parent PID, and start time of the given process.
+    fn read_stat(pid: u32) -> Option<(String, u32, u64)> {
+        let stat = std::fs::read_to_string(format!("/proc/{pid}/stat")).ok()?;
+        // The command name is wrapped in parentheses, and may itself contain
+        // spaces or parentheses.
+        let (_, rest) = stat.split_once('(')?;
+        let (comm, rest) = rest.rsplit_once(')')?;
+        // The remaining fields start at the third field, the process state.
+        let fields: Vec<&str> = rest.split_whitespace().collect();
+        let ppid = fields.get(1)?.parse().ok()?;
+        let start_time = fields.get(19)?.parse().ok()?;
+        Some((comm.to_owned(), ppid, start_time))
+    }
+
+    let mut pid = std::process::id();
+    let mut outermost_git_process = None;
+    while let Some((comm, ppid, start_time)) = read_stat(pid) {
+        if comm == "git" {
+            outermost_git_process = Some((pid, start_time));
+        } else {
+            // Other processes (such as the shell running a hook script) are
+            // only considered to be part of the Git command if they were
+            // started by Git.
+            match read_stat(ppid) {
+                Some((parent_comm, _, _)) if parent_comm == "git" => {}
+                _ => break,
+            }
+        }
+        if ppid <= 1 {
+            break;
+        }
+        pid = ppid;
+    }
+    outermost_git_process.map(|(pid, start_time)| format!("{pid}:{start_time}"))
+}
+
+#[cfg(not(target_os = "linux"))]
+pub(crate) fn get_external_git_command_key() -> Option<String> {
+    None
+}
+
 impl<'conn> EventLogDb<'conn> {
     /// Constructor.
     #[instrument]
@@ -529,27 +755,11 @@ impl<'conn> EventLogDb<'conn> {
     #[instrument]
     pub fn add_events(&self, events: Vec<Event>) -> eyre::Result<()> {
         let tx = self.conn.unchecked_transaction()?;
-        for event in events {
-            let row = match Row::try_from(event) {
-                Ok(row) => row,
-                Err(()) => continue,
-            };
-            let Row {
-                timestamp,
-                type_,
-                event_tx_id,
-                ref1,
-                ref2,
-                ref_name,
-                message,
-            } = row;
-
-            let ref1 = ref1.as_ref().map(|x| x.as_str());
-            let ref2 = ref2.as_ref().map(|x| x.as_str());
-            let ref_name = ref_name.as_ref().map(|x| x.as_str());
-            let message = message.as_ref().map(|x| x.as_str());
-
-            tx.execute(
+        let mut rewrites = Vec::new();
+        {
+            // Prepare the statement once for the whole batch, since some
+            // operations (such as `git fetch`) can produce many events at once.
+            let mut stmt = tx.prepare_cached(
                 "
 INSERT INTO event_log VALUES (
     :timestamp,
@@ -561,7 +771,36 @@ INSERT INTO event_log VALUES (
     :message
 )
             ",
-                rusqlite::named_params! {
+            )?;
+            for event in events {
+                if let Event::RewriteEvent {
+                    old_commit_oid: MaybeZeroOid::NonZero(old_commit_oid),
+                    new_commit_oid: MaybeZeroOid::NonZero(new_commit_oid),
+                    ..
+                } = event
+                {
+                    rewrites.push((old_commit_oid, new_commit_oid));
+                }
+                let row = match Row::try_from(event) {
+                    Ok(row) => row,
+                    Err(()) => continue,
+                };
+                let Row {
+                    timestamp,
+                    type_,
+                    event_tx_id,
+                    ref1,
+                    ref2,
+                    ref_name,
+                    message,
+                } = row;
+
+                let ref1 = ref1.as_ref().map(|x| x.as_str());
+                let ref2 = ref2.as_ref().map(|x| x.as_str());
+                let ref_name = ref_name.as_ref().map(|x| x.as_str());
+                let message = message.as_ref().map(|x| x.as_str());
+
+                stmt.execute(rusqlite::named_params! {
                     ":timestamp": timestamp,
                     ":type": &type_,
                     ":event_tx_id": event_tx_id,
@@ -569,6 +808,58 @@ INSERT INTO event_log VALUES (
                     ":new_ref": &ref2,
                     ":ref_name": &ref_name,
                     ":message": &message,
+                })?;
+            }
+        }
+        transfer_nicknames(&tx, rewrites.iter().copied())?;
+        transfer_stack_tips(&tx, rewrites)?;
+        tx.commit()?;
+        Ok(())
+    }
+
+    /// Stop tracking the given commits, as if they had never been observed.
+    ///
+    /// The commits' own commit, hide, and unhide events are deleted. Rewrites
+    /// of the commits into other commits are turned into creations of those
+    /// other commits, so that their successors stay visible, and rewrites of
+    /// other commits into them are turned into deletions, so that their
+    /// predecessors stay hidden. Reference updates are kept as-is, since they
+    /// record the history of the references rather than of the commits.
+    #[instrument]
+    pub fn forget_commits(&self, commit_oids: &[NonZeroOid]) -> eyre::Result<()> {
+        let tx = self.conn.unchecked_transaction()?;
+        let zero_oid = MaybeZeroOid::Zero.to_string();
+        for commit_oid in commit_oids {
+            let commit_oid = commit_oid.to_string();
+            tx.execute(
+                "
+UPDATE event_log
+SET old_ref = :zero_oid
+WHERE type = 'rewrite' AND old_ref = :commit_oid
+",
+                rusqlite::named_params! {
+                    ":zero_oid": &zero_oid,
+                    ":commit_oid": &commit_oid,
+                },
+            )?;
+            tx.execute(
+                "
+UPDATE event_log
+SET new_ref = :zero_oid
+WHERE type = 'rewrite' AND new_ref = :commit_oid
+",
+                rusqlite::named_params! {
+                    ":zero_oid": &zero_oid,
+                    ":commit_oid": &commit_oid,
+                },
+            )?;
+            tx.execute(
+                "
+DELETE FROM event_log
+WHERE type IN ('commit', 'hide', 'unhide') AND old_ref = :commit_oid
+",
+                rusqlite::named_params! {
+                    ":commit_oid": &commit_oid,
                 },
             )?;
         }
@@ -580,38 +871,44 @@ INSERT INTO event_log VALUES (
     ///
     /// Returns: All the events in the database, ordered from oldest to newest.
     #[instrument]
-
     pub fn get_events(&self) -> eyre::Result<Vec<Event>> {
-        let mut stmt = self.conn.prepare(
-            "
-SELECT timestamp, type, event_tx_id, old_ref, new_ref, ref_name, message
-FROM event_log
-ORDER BY rowid ASC
-",
+        query_events(self.conn)
+    }
+
+    /// Get the events which were added after the event with the given row ID,
+    /// along with their row IDs. Unlike the IDs used by
+    /// [`EventReplayer::make_cursor`], row IDs don't change when earlier
+    /// events are removed, so they can be used to resume reading the event
+    /// log later.
+    ///
+    /// Returns: The events, ordered from oldest to newest.
+    #[instrument]
+    pub fn get_events_after(&self, row_id: i64) -> eyre::Result<Vec<(i64, Event)>> {
+        query_events_after(self.conn, row_id)
+    }
+
+    /// Get the row ID of the most recent event, or 0 if there are no events.
+    /// See [`EventLogDb::get_events_after`].
+    #[instrument]
+    pub fn get_last_event_row_id(&self) -> eyre::Result<i64> {
+        let row_id: i64 = self.conn.query_row(
+            "SELECT IFNULL(MAX(rowid), 0) FROM event_log",
+            rusqlite::params![],
+            |row| row.get(0),
         )?;
-        let rows: rusqlite::Result<Vec<Row>> = stmt
-            .query_map(rusqlite::params![], |row| {
-                let timestamp: f64 = row.get("timestamp")?;
-                let event_tx_id: isize = row.get("event_tx_id")?;
-                let type_: String = row.get("type")?;
-                let ref_name: Option<String> = row.get("ref_name")?;
-                let old_ref: Option<String> = row.get("old_ref")?;
-                let new_ref: Option<String> = row.get("new_ref")?;
-                let message: Option<String> = row.get("message")?;
-
-                Ok(Row {
-                    timestamp,
-                    event_tx_id,
-                    type_,
-                    ref_name: ref_name.map(ReferenceName::from),
-                    ref1: old_ref.map(ReferenceName::from),
-                    ref2: new_ref.map(ReferenceName::from),
-                    message: message.map(ReferenceName::from),
-                })
-            })?
-            .collect();
-        let rows = rows?;
-        rows.into_iter().map(Event::try_from).collect()
+        Ok(row_id)
+    }
+
+    /// Get the number of events in the database. This is the ID of the most
+    /// recent event, as used by [`EventReplayer::make_cursor`].
+    #[instrument]
+    pub fn count_events(&self) -> eyre::Result<usize> {
+        let count: i64 = self.conn.query_row(
+            "SELECT COUNT(*) FROM event_log",
+            rusqlite::params![],
+            |row| row.get(0),
+        )?;
+        Ok(usize::try_from(count)?)
     }
 
     #[instrument]
@@ -665,6 +962,76 @@ ORDER BY rowid ASC
         self.make_transaction_id_inner(now, message.as_ref())
     }
 
+    /// Create an event transaction ID for events observed by a Git hook. If
+    /// a previous hook invocation was caused by the same external Git
+    /// command, then its event transaction ID is reused. For example, the
+    /// reference updates, checkout, and rewrites caused by a single `git pull`
+    /// are grouped into one transaction, so that they can be undone together.
+    ///
+    /// This can be disabled with `branchless.eventlog.groupExternalCommands`.
+    ///
+    /// The external Git command is found by walking up the process tree
+    /// through `/proc`, so this is only supported on Linux. On other
+    /// platforms, each hook invocation gets its own transaction.
+    #[instrument]
+    pub fn make_hook_transaction_id(
+        &self,
+        repo: &Repo,
+        now: SystemTime,
+        message: impl AsRef<str> + std::fmt::Debug,
+    ) -> eyre::Result<EventTransactionId> {
+        let command_key = if std::env::var_os(BRANCHLESS_TRANSACTION_ID_ENV_VAR).is_some()
+            || !get_eventlog_group_external_commands(repo)?
+        {
+            None
+        } else {
+            get_external_git_command_key()
+        };
+        let command_key = match command_key {
+            Some(command_key) => command_key,
+            None => return self.make_transaction_id_inner(now, message.as_ref()),
+        };
+
+        let existing_event_tx_id: Option<isize> = {
+            let mut stmt = self.conn.prepare(
+                "
+SELECT event_tx_id
+FROM event_transaction_commands
+WHERE command_key = :command_key
+ORDER BY event_tx_id DESC
+LIMIT 1
+",
+            )?;
+            let mut rows = stmt.query(rusqlite::named_params! {
+                ":command_key": &command_key,
+            })?;
+            match rows.next()? {
+                Some(row) => Some(row.get("event_tx_id")?),
+                None => None,
+            }
+        };
+        if let Some(event_tx_id) = existing_event_tx_id {
+            return Ok(EventTransactionId::Id(event_tx_id));
+        }
+
+        let event_tx_id = self.make_transaction_id_inner(now, message.as_ref())?;
+        if let EventTransactionId::Id(event_tx_id) = event_tx_id {
+            self.conn.execute(
+                "
+INSERT OR REPLACE INTO event_transaction_commands
+(event_tx_id, command_key)
+VALUES
+(:event_tx_id, :command_key)
+",
+                rusqlite::named_params! {
+                    ":event_tx_id": event_tx_id,
+                    ":command_key": &command_key,
+                },
+            )?;
+        }
+        Ok(event_tx_id)
+    }
+
     /// Get the message associated with the given transaction.
     pub fn get_transaction_message(&self, event_tx_id: EventTransactionId) -> eyre::Result<String> {
         let event_tx_id = match event_tx_id {
@@ -689,6 +1056,185 @@ WHERE event_tx_id = :event_tx_id
         )?;
         Ok(result)
     }
+
+    /// Record a human-readable description of what the given transaction did,
+    /// replacing any previously-recorded description.
+    pub fn set_transaction_label(
+        &self,
+        event_tx_id: EventTransactionId,
+        label: impl AsRef<str>,
+    ) -> eyre::Result<()> {
+        let event_tx_id = match event_tx_id {
+            EventTransactionId::Id(event_tx_id) => event_tx_id,
+            EventTransactionId::Suppressed => return Ok(()),
+        };
+        self.conn
+            .execute(
+                "
+            INSERT OR REPLACE INTO event_transaction_labels
+            (event_tx_id, label)
+            VALUES
+            (:event_tx_id, :label)
+        ",
+                rusqlite::named_params! {
+                    ":event_tx_id": event_tx_id,
+                    ":label": label.as_ref(),
+                },
+            )
+            .wrap_err("Setting event transaction label")?;
+        Ok(())
+    }
+
+    /// Get all transactions which have been recorded, from oldest to newest.
+    #[instrument]
+    pub fn get_transactions(&self) -> eyre::Result<Vec<EventTransaction>> {
+        query_transactions(self.conn)
+    }
+}
+
+impl EventLogStorage for EventLogDb<'_> {
+    fn add_events(&self, events: Vec<Event>) -> eyre::Result<()> {
+        EventLogDb::add_events(self, events)
+    }
+
+    fn get_events(&self) -> eyre::Result<Vec<Event>> {
+        EventLogDb::get_events(self)
+    }
+
+    fn make_transaction_id(
+        &self,
+        now: SystemTime,
+        message: &str,
+    ) -> eyre::Result<EventTransactionId> {
+        EventLogDb::make_transaction_id(self, now, message)
+    }
+
+    fn get_transactions(&self) -> eyre::Result<Vec<EventTransaction>> {
+        EventLogDb::get_transactions(self)
+    }
+}
+
+#[instrument]
+fn query_events(conn: &rusqlite::Connection) -> eyre::Result<Vec<Event>> {
+    let events = query_events_after(conn, 0)?;
+    Ok(events.into_iter().map(|(_row_id, event)| event).collect())
+}
+
+#[instrument]
+fn query_events_after(conn: &rusqlite::Connection, row_id: i64) -> eyre::Result<Vec<(i64, Event)>> {
+    let mut stmt = conn.prepare(
+        "
+SELECT rowid, timestamp, type, event_tx_id, old_ref, new_ref, ref_name, message
+FROM event_log
+WHERE rowid > :row_id
+ORDER BY rowid ASC
+",
+    )?;
+    let rows: rusqlite::Result<Vec<(i64, Row)>> = stmt
+        .query_map(rusqlite::named_params! { ":row_id": row_id }, |row| {
+            let row_id: i64 = row.get("rowid")?;
+            let timestamp: f64 = row.get("timestamp")?;
+            let event_tx_id: isize = row.get("event_tx_id")?;
+            let type_: String = row.get("type")?;
+            let ref_name: Option<String> = row.get("ref_name")?;
+            let old_ref: Option<String> = row.get("old_ref")?;
+            let new_ref: Option<String> = row.get("new_ref")?;
+            let message: Option<String> = row.get("message")?;
+
+            Ok((
+                row_id,
+                Row {
+                    timestamp,
+                    event_tx_id,
+                    type_,
+                    ref_name: ref_name.map(ReferenceName::from),
+                    ref1: old_ref.map(ReferenceName::from),
+                    ref2: new_ref.map(ReferenceName::from),
+                    message: message.map(ReferenceName::from),
+                },
+            ))
+        })?
+        .collect();
+    let rows = rows?;
+    rows.into_iter()
+        .map(|(row_id, row)| Ok((row_id, Event::try_from(row)?)))
+        .collect()
+}
+
+#[instrument]
+fn query_transactions(conn: &rusqlite::Connection) -> eyre::Result<Vec<EventTransaction>> {
+    let mut stmt = conn.prepare(
+        "
+SELECT event_transactions.event_tx_id, timestamp, message, label
+FROM event_transactions
+LEFT JOIN event_transaction_labels
+    ON event_transactions.event_tx_id = event_transaction_labels.event_tx_id
+ORDER BY event_transactions.event_tx_id ASC
+",
+    )?;
+    let transactions: rusqlite::Result<Vec<EventTransaction>> = stmt
+        .query_map(rusqlite::params![], |row| {
+            let event_tx_id: isize = row.get("event_tx_id")?;
+            let timestamp: f64 = row.get("timestamp")?;
+            let message: Option<String> = row.get("message")?;
+            let label: Option<String> = row.get("label")?;
+            Ok(EventTransaction {
+                event_tx_id: EventTransactionId::Id(event_tx_id),
+                timestamp,
+                message: message.unwrap_or_default(),
+                label,
+            })
+        })?
+        .collect();
+    let transactions = transactions?;
+    Ok(transactions)
+}
+
+/// An event log read from another repository's database, such as a backup or
+/// a copy from another machine, which can be replayed without modifying it.
+/// Attempting to add events or transactions fails.
+pub struct ReadOnlyEventLogDb {
+    conn: rusqlite::Connection,
+}
+
+impl std::fmt::Debug for ReadOnlyEventLogDb {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "<ReadOnlyEventLogDb path={:?}>", self.conn.path())
+    }
+}
+
+impl ReadOnlyEventLogDb {
+    /// Open the database at the given path, which is usually
+    /// `.git/branchless/db.sqlite3` in the other repository.
+    #[instrument]
+    pub fn open(path: &Path) -> eyre::Result<Self> {
+        let conn =
+            rusqlite::Connection::open_with_flags(path, rusqlite::OpenFlags::SQLITE_OPEN_READ_ONLY)
+                .wrap_err_with(|| format!("Opening event log database: {path:?}"))?;
+        Ok(ReadOnlyEventLogDb { conn })
+    }
+}
+
+impl EventLogStorage for ReadOnlyEventLogDb {
+    fn add_events(&self, _events: Vec<Event>) -> eyre::Result<()> {
+        eyre::bail!("Cannot add events to a read-only event log: {self:?}")
+    }
+
+    fn get_events(&self) -> eyre::Result<Vec<Event>> {
+        query_events(&self.conn)
+    }
+
+    fn make_transaction_id(
+        &self,
+        _now: SystemTime,
+        _message: &str,
+    ) -> eyre::Result<EventTransactionId> {
+        eyre::bail!("Cannot add transactions to a read-only event log: {self:?}")
+    }
+
+    fn get_transactions(&self) -> eyre::Result<Vec<EventTransaction>> {
+        query_transactions(&self.conn)
+    }
 }
 
 /// Determine whether a given reference is used to keep a commit alive.
@@ -782,6 +1328,10 @@ pub struct EventReplayer {
     /// If an entry is not present, it was either never observed, or it most
     /// recently changed to point to the zero hash (i.e. it was deleted).
     ref_locations: HashMap<ReferenceName, NonZeroOid>,
+
+    /// Map from commit OID to the commits it was copied from, along with the
+    /// ID of the event which recorded the copy.
+    copy_sources: HashMap<NonZeroOid, Vec<(isize, CopyType, NonZeroOid)>>,
 }
 
 impl std::fmt::Debug for EventReplayer {
@@ -803,24 +1353,25 @@ impl EventReplayer {
             main_branch_reference_name,
             commit_history: HashMap::new(),
             ref_locations: HashMap::new(),
+            copy_sources: HashMap::new(),
         }
     }
 
     /// Construct the replayer from all the events in the database.
     ///
     /// Args:
-    /// * `event_log_db`: The database to query events from.
+    /// * `event_log_db`: The storage to query events from.
     ///
     /// Returns: The constructed replayer.
     #[instrument]
     pub fn from_event_log_db(
         effects: &Effects,
         repo: &Repo,
-        event_log_db: &EventLogDb,
+        event_log_db: &dyn EventLogStorage,
     ) -> eyre::Result<Self> {
         let (_effects, _progress) = effects.start_operation(OperationType::ProcessEvents);
 
-        let main_branch_reference_name = repo.get_main_branch()?.get_reference_name()?;
+        let main_branch_reference_name = repo.get_main_branch_reference_name()?;
         let mut result = EventReplayer::new(main_branch_reference_name);
         for event in event_log_db.get_events()? {
             result.process_event(&event);
@@ -946,6 +1497,20 @@ impl EventReplayer {
                 // Do nothing. A working copy snapshot doesn't imply that the
                 // commit has become active or inactive.
             }
+
+            // A copy doesn't change whether either commit is active; the new
+            // commit is made active by its own `CommitEvent`.
+            Event::CopyEvent {
+                timestamp: _,
+                event_tx_id: _,
+                copy_type,
+                source_commit_oid,
+                new_commit_oid,
+            } => self.copy_sources.entry(*new_commit_oid).or_default().push((
+                id,
+                *copy_type,
+                *source_commit_oid,
+            )),
         };
     }
 
@@ -1060,6 +1625,22 @@ impl EventReplayer {
         Some(&event_info.event)
     }
 
+    /// Get the commit which the given commit was most recently recorded as
+    /// being copied from (such as by `git cherry-pick`), as of the cursor's
+    /// point in time.
+    pub fn get_cursor_copy_source(
+        &self,
+        cursor: EventCursor,
+        oid: NonZeroOid,
+    ) -> Option<(CopyType, NonZeroOid)> {
+        self.copy_sources
+            .get(&oid)?
+            .iter()
+            .rev()
+            .find(|(id, _copy_type, _source_commit_oid)| *id < cursor.event_id)
+            .map(|(_id, copy_type, source_commit_oid)| (*copy_type, *source_commit_oid))
+    }
+
     /// Get all OIDs which have been observed so far. This should be the set of
     /// non-inactive commits.
     pub fn get_cursor_oids(&self, cursor: EventCursor) -> HashSet<NonZeroOid> {
@@ -1095,6 +1676,63 @@ impl EventReplayer {
         EventCursor { event_id }
     }
 
+    /// Create an event cursor pointing to immediately before the event at the
+    /// given index into the event log.
+    fn make_cursor_at_index(&self, index: usize) -> EventCursor {
+        // An index which doesn't fit into an `isize` is past the end of the
+        // event log, so it would be clamped by `make_cursor` anyways.
+        self.make_cursor(isize::try_from(index).unwrap_or(isize::MAX))
+    }
+
+    /// Create an event cursor pointing to immediately before the first event
+    /// which happened after the given time.
+    ///
+    /// Timestamps come from the clocks of the processes which recorded the
+    /// events, so they aren't necessarily in order. The event log is scanned
+    /// from the beginning, so that an event with a skewed timestamp doesn't
+    /// affect where the cursor points in the rest of the log.
+    pub fn make_cursor_at_time(&self, time: SystemTime) -> EventCursor {
+        let event_id = self
+            .events
+            .iter()
+            .position(|event| event.get_timestamp() > time)
+            .unwrap_or(self.events.len());
+        self.make_cursor_at_index(event_id)
+    }
+
+    /// Create an event cursor pointing to immediately before the first
+    /// transaction which wasn't complete at the given time. Unlike
+    /// `make_cursor_at_time`, the cursor never points into the middle of a
+    /// transaction, so it's suitable for undoing to.
+    pub fn make_transaction_cursor_at_time(&self, time: SystemTime) -> EventCursor {
+        let cursor = self.make_cursor_at_time(time);
+        let event = usize::try_from(cursor.event_id)
+            .ok()
+            .and_then(|event_id| self.events.get(event_id));
+        match event {
+            Some(event) => self
+                .make_cursor_before_transaction(event.get_event_tx_id())
+                .unwrap_or(cursor),
+            None => cursor,
+        }
+    }
+
+    /// Create an event cursor pointing to immediately before the first event in
+    /// the given transaction, so that the transaction and all subsequent
+    /// transactions are considered to not have happened yet.
+    ///
+    /// Returns `None` if there are no events for the given transaction.
+    pub fn make_cursor_before_transaction(
+        &self,
+        event_tx_id: EventTransactionId,
+    ) -> Option<EventCursor> {
+        let event_id = self
+            .events
+            .iter()
+            .position(|event| event.get_event_tx_id() == event_tx_id)?;
+        Some(self.make_cursor_at_index(event_id))
+    }
+
     /// Advance the event cursor by the specified number of events.
     ///
     /// Args:
@@ -1217,7 +1855,8 @@ impl EventReplayer {
 
                     Event::RewriteEvent { .. }
                     | Event::ObsoleteEvent { .. }
-                    | Event::UnobsoleteEvent { .. } => None,
+                    | Event::UnobsoleteEvent { .. }
+                    | Event::CopyEvent { .. } => None,
                 }
             })
     }
@@ -1260,7 +1899,7 @@ impl EventReplayer {
         cursor: EventCursor,
         repo: &Repo,
     ) -> eyre::Result<NonZeroOid> {
-        let main_branch_reference_name = repo.get_main_branch()?.get_reference_name()?;
+        let main_branch_reference_name = repo.get_main_branch_reference_name()?;
         let main_branch_oid = self.get_cursor_branch_oid(cursor, &main_branch_reference_name)?;
         match main_branch_oid {
             Some(main_branch_oid) => Ok(main_branch_oid),
@@ -1338,10 +1977,14 @@ impl EventReplayer {
         let head_oid = self.get_cursor_head_oid(cursor);
         let main_branch_oid = self.get_cursor_main_branch_oid(cursor, repo)?;
         let branch_oid_to_names = self.get_cursor_branch_oid_to_names(cursor, repo)?;
+        // Tags aren't replayed from the event log, so use their current
+        // positions.
+        let tag_oid_to_names = repo.get_tag_oid_to_names()?;
         Ok(RepoReferencesSnapshot {
             head_oid,
             main_branch_oid,
             branch_oid_to_names,
+            tag_oid_to_names,
         })
     }
 
@@ -1429,6 +2072,9 @@ pub mod testing {
             }
             | Event::WorkingCopySnapshot {
                 ref mut timestamp, ..
+            }
+            | Event::CopyEvent {
+                ref mut timestamp, ..
             } => *timestamp = 0.0,
         }
         event
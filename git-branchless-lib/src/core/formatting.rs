@@ -5,8 +5,9 @@
 //! characters and with colors, using shell-specific escape codes.
 
 use std::fmt::Display;
+use std::str::FromStr;
 
-use cursive::theme::{Effect, Style};
+use cursive::theme::{BaseColor, Color, ColorType, Effect, Style};
 use cursive::utils::markup::StyledString;
 use cursive::utils::span::Span;
 
@@ -69,9 +70,134 @@ impl Display for Pluralize<'_> {
     }
 }
 
+/// Adjustments to the colors and effects used when rendering styled strings.
+/// These are applied to all output rendered with [`Glyphs::render`], and are
+/// configured with the `branchless.style.*` config keys.
+#[derive(Clone, Debug, Default)]
+pub struct Theme {
+    /// Colors to render in place of the colors that would otherwise be used.
+    color_overrides: Vec<(Color, Color)>,
+
+    /// Render brighter colors and don't render dimmed text, for readability.
+    high_contrast: bool,
+}
+
+impl Theme {
+    /// Make a high-contrast theme.
+    pub fn high_contrast() -> Self {
+        Self {
+            color_overrides: Vec::new(),
+            high_contrast: true,
+        }
+    }
+
+    /// Render `to` wherever `from` would otherwise have been rendered.
+    pub fn override_color(mut self, from: Color, to: Color) -> Self {
+        self.color_overrides.retain(|(color, _)| *color != from);
+        self.color_overrides.push((from, to));
+        self
+    }
+
+    fn apply(&self, style: Style) -> Style {
+        let Style {
+            mut effects,
+            mut color,
+        } = style;
+        if let ColorType::Color(front) = color.front {
+            let front = match self
+                .color_overrides
+                .iter()
+                .find(|(from, _to)| *from == front)
+            {
+                Some((_from, to)) => *to,
+                None if self.high_contrast => match front {
+                    Color::Dark(base_color) => Color::Light(base_color),
+                    // Bright black is typically rendered as gray, which is
+                    // hard to read on most backgrounds.
+                    Color::Light(BaseColor::Black) => Color::TerminalDefault,
+                    front => front,
+                },
+                None => front,
+            };
+            color.front = ColorType::Color(front);
+        }
+        if self.high_contrast {
+            effects.remove(Effect::Dim);
+        }
+        Style { effects, color }
+    }
+}
+
+/// Parse a color name as used in the `branchless.style.color.*` config keys:
+/// one of the eight basic terminal colors (such as `red`), optionally prefixed
+/// with `bright` (such as `brightRed`), or `default` for the terminal's default
+/// color.
+pub fn parse_color(name: &str) -> eyre::Result<Color> {
+    let name = name.trim().to_lowercase();
+    if name == "default" {
+        return Ok(Color::TerminalDefault);
+    }
+    let (name, is_bright) = match name.strip_prefix("bright") {
+        Some(name) => (name.trim_start_matches(|c| c == '-' || c == '_'), true),
+        None => (name.as_str(), false),
+    };
+    let base_color = match name {
+        "black" => BaseColor::Black,
+        "red" => BaseColor::Red,
+        "green" => BaseColor::Green,
+        "yellow" => BaseColor::Yellow,
+        "blue" => BaseColor::Blue,
+        "magenta" => BaseColor::Magenta,
+        "cyan" => BaseColor::Cyan,
+        "white" => BaseColor::White,
+        _ => eyre::bail!("Unknown color: {name:?}"),
+    };
+    if is_bright {
+        Ok(Color::Light(base_color))
+    } else {
+        Ok(Color::Dark(base_color))
+    }
+}
+
+/// The set of characters to use for glyphs, as configured with
+/// `branchless.style.glyphs`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum GlyphSet {
+    /// Use Unicode box-drawing characters and symbols when writing to a TTY.
+    Unicode,
+
+    /// Only use ASCII characters, even when writing to a TTY.
+    Ascii,
+}
+
+impl FromStr for GlyphSet {
+    type Err = eyre::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "unicode" => Ok(GlyphSet::Unicode),
+            "ascii" => Ok(GlyphSet::Ascii),
+            other => eyre::bail!("Unknown glyph set: {other:?}"),
+        }
+    }
+}
+
+/// Whether the user has asked for output without colors by setting the
+/// `NO_COLOR` environment variable to a non-empty value. See
+/// <https://no-color.org>.
+pub fn is_color_disabled_by_env() -> bool {
+    match std::env::var_os("NO_COLOR") {
+        Some(value) => !value.is_empty(),
+        None => false,
+    }
+}
+
 /// Glyphs to use for rendering the smartlog.
 #[derive(Clone)]
 pub struct Glyphs {
+    /// The theme to apply when rendering styled strings.
+    pub theme: Theme,
+
     /// Whether or not ANSI escape codes should be emitted (e.g. to render
     /// color).
     pub should_write_ansi_escape_codes: bool,
@@ -153,7 +279,7 @@ impl Glyphs {
     /// Make the `Glyphs` object appropriate for `stdout`.
     pub fn detect() -> Self {
         let color_support = concolor::get(concolor::Stream::Stdout);
-        if color_support.color() {
+        if color_support.color() && !is_color_disabled_by_env() {
             Glyphs::pretty()
         } else {
             Glyphs::text()
@@ -163,6 +289,7 @@ impl Glyphs {
     /// Glyphs used for output to a text file or non-TTY.
     pub fn text() -> Self {
         Glyphs {
+            theme: Theme::default(),
             should_write_ansi_escape_codes: false,
             line: "|",
             line_with_offshoot: "|",
@@ -192,6 +319,7 @@ impl Glyphs {
     /// Glyphs used for output to a TTY.
     pub fn pretty() -> Self {
         Glyphs {
+            theme: Theme::default(),
             should_write_ansi_escape_codes: true,
             line: "┃",
             line_with_offshoot: "┣",
@@ -218,6 +346,25 @@ impl Glyphs {
         }
     }
 
+    /// Use the glyphs from the given set, without changing whether or not
+    /// colors are rendered.
+    pub fn with_glyph_set(self, glyph_set: GlyphSet) -> Self {
+        match glyph_set {
+            GlyphSet::Unicode => self,
+            GlyphSet::Ascii => Glyphs {
+                theme: self.theme,
+                should_write_ansi_escape_codes: self.should_write_ansi_escape_codes,
+                ..Glyphs::text()
+            },
+        }
+    }
+
+    /// Use the provided theme when rendering styled strings.
+    pub fn with_theme(mut self, theme: Theme) -> Self {
+        self.theme = theme;
+        self
+    }
+
     /// Return a `Glyphs` object suitable for rendering graphs in the reverse of
     /// their usual order.
     pub fn reverse_order(mut self, reverse: bool) -> Self {
@@ -241,7 +388,7 @@ impl Glyphs {
                     width: _,
                 } = span;
                 if self.should_write_ansi_escape_codes {
-                    Ok(render_style_as_ansi(content, *attr)?)
+                    Ok(render_style_as_ansi(content, self.theme.apply(*attr))?)
                 } else {
                     Ok(content.to_string())
                 }
@@ -369,7 +516,6 @@ fn render_style_as_ansi(content: &str, style: Style) -> eyre::Result<String> {
     let Style { effects, color } = style;
     let output = {
         use console::style;
-        use cursive::theme::{BaseColor, Color, ColorType};
         let output = content.to_string();
         match color.front {
             ColorType::Palette(_) => {
@@ -428,3 +574,53 @@ fn render_style_as_ansi(content: &str, style: Style) -> eyre::Result<String> {
 
     Ok(output.to_string())
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_color() -> eyre::Result<()> {
+        assert_eq!(parse_color("red")?, Color::Dark(BaseColor::Red));
+        assert_eq!(parse_color("brightred")?, Color::Light(BaseColor::Red));
+        assert_eq!(parse_color("brightRed")?, Color::Light(BaseColor::Red));
+        assert_eq!(parse_color("default")?, Color::TerminalDefault);
+        assert!(parse_color("purple").is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn test_theme() -> eyre::Result<()> {
+        let string = StyledStringBuilder::new()
+            .append_styled("foo", BaseColor::Yellow.dark())
+            .append_styled("bar", Effect::Dim)
+            .build();
+
+        let rendered = Glyphs::pretty().render(string.clone())?;
+        assert!(rendered.contains("\u{1b}[33mfoo"));
+        assert!(rendered.contains("\u{1b}[2mbar"));
+
+        let theme = Theme::default().override_color(
+            Color::Dark(BaseColor::Yellow),
+            Color::Dark(BaseColor::Magenta),
+        );
+        let rendered = Glyphs::pretty().with_theme(theme).render(string.clone())?;
+        assert!(rendered.contains("\u{1b}[35mfoo"));
+        assert!(rendered.contains("\u{1b}[2mbar"));
+
+        let rendered = Glyphs::pretty()
+            .with_theme(Theme::high_contrast())
+            .render(string)?;
+        assert!(!rendered.contains("\u{1b}[33m"));
+        assert!(!rendered.contains("\u{1b}[2m"));
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_glyph_set() {
+        let glyphs = Glyphs::pretty().with_glyph_set(GlyphSet::Ascii);
+        assert!(glyphs.should_write_ansi_escape_codes);
+        assert_eq!(glyphs.commit_visible, "o");
+    }
+}
@@ -8,17 +8,27 @@
 //! garbage collection doesn't collect commits which branchless thinks are still
 //! active.
 
+use std::collections::HashSet;
 use std::fmt::Write;
+use std::time::SystemTime;
 
 use eyre::Context;
 use tracing::instrument;
 
 use crate::core::effects::Effects;
 use crate::core::eventlog::{
-    is_gc_ref, CommitActivityStatus, EventCursor, EventLogDb, EventReplayer,
+    is_gc_ref, CommitActivityStatus, Event, EventCursor, EventLogDb, EventReplayer,
 };
 use crate::core::formatting::Pluralize;
-use crate::git::{NonZeroOid, Reference, Repo};
+use crate::core::repo_ext::RepoExt;
+use crate::core::rewrite::find_rewrite_target;
+use crate::git::{Commit, MaybeZeroOid, NonZeroOid, Reference, ReferenceName, Repo};
+
+/// The namespace under which `git hide` archives the branches pointing to the
+/// commits it hides, if `branchless.hide.archiveBranches` is set. Unlike other
+/// references under `refs/branchless/`, these are kept even though their
+/// commits are hidden, so that `git unhide` can restore them.
+pub const ARCHIVED_BRANCH_PREFIX: &str = "refs/branchless/archived/";
 
 /// Find references under `refs/branchless/` which point to commits which are no
 /// longer active. These are safe to remove.
@@ -30,7 +40,9 @@ pub fn find_dangling_references<'repo>(
     let mut result = Vec::new();
     for reference in repo.get_all_references()? {
         let reference_name = reference.get_name()?;
-        if !is_gc_ref(&reference_name) {
+        if !is_gc_ref(&reference_name)
+            || reference_name.as_str().starts_with(ARCHIVED_BRANCH_PREFIX)
+        {
             continue;
         }
 
@@ -59,6 +71,55 @@ pub fn find_dangling_references<'repo>(
     Ok(result)
 }
 
+/// Find references under `refs/branchless/` which only keep alive commits that
+/// were hidden before `cutoff`, weren't rewritten into another commit, and
+/// aren't pointed to by any branch. Unlike [`find_dangling_references`], this
+/// leaves recently-hidden commits alone, so that they can still be restored.
+///
+/// Returns each such reference along with the commit it points to.
+pub fn find_prunable_references<'repo>(
+    repo: &'repo Repo,
+    event_replayer: &EventReplayer,
+    event_cursor: EventCursor,
+    cutoff: SystemTime,
+) -> eyre::Result<Vec<(Reference<'repo>, Commit<'repo>)>> {
+    let branch_oid_to_names = repo.get_branch_oid_to_names()?;
+    let mut result = Vec::new();
+    for reference in repo.get_all_references()? {
+        let reference_name = reference.get_name()?;
+        if !is_gc_ref(&reference_name)
+            || reference_name.as_str().starts_with(ARCHIVED_BRANCH_PREFIX)
+        {
+            continue;
+        }
+        let commit = match reference.peel_to_commit()? {
+            Some(commit) => commit,
+            None => continue,
+        };
+        let commit_oid = commit.get_oid();
+
+        match event_replayer.get_cursor_commit_activity_status(event_cursor, commit_oid) {
+            CommitActivityStatus::Active | CommitActivityStatus::Inactive => continue,
+            CommitActivityStatus::Obsolete => {}
+        }
+        if branch_oid_to_names.contains_key(&commit_oid) {
+            continue;
+        }
+        if let Some(MaybeZeroOid::NonZero(_)) =
+            find_rewrite_target(event_replayer, event_cursor, commit_oid)
+        {
+            continue;
+        }
+        match event_replayer.get_cursor_commit_latest_event(event_cursor, commit_oid) {
+            Some(event) if event.get_timestamp() <= cutoff => {}
+            Some(_) | None => continue,
+        }
+
+        result.push((reference, commit));
+    }
+    Ok(result)
+}
+
 /// Mark a commit as reachable.
 ///
 /// Once marked as reachable, the commit won't be collected by Git's garbage
@@ -94,6 +155,87 @@ pub fn mark_commit_reachable(repo: &Repo, commit_oid: NonZeroOid) -> eyre::Resul
     Ok(())
 }
 
+/// Undo [`mark_commit_reachable`] for the given commit, so that Git's garbage
+/// collection can collect it if nothing else refers to it.
+#[instrument]
+pub fn unmark_commit_reachable(repo: &Repo, commit_oid: NonZeroOid) -> eyre::Result<()> {
+    let ref_name = format!("refs/branchless/{commit_oid}");
+    if let Some(mut reference) = repo.find_reference(&ref_name.into())? {
+        reference.delete()?;
+    }
+    Ok(())
+}
+
+/// Get the commits which the given event refers to. Some of these may not
+/// exist anymore, or may not be commits at all, such as the target of a
+/// reference to a tag object.
+pub fn get_event_commit_oids(event: &Event) -> Vec<NonZeroOid> {
+    let oids = match event {
+        Event::RewriteEvent {
+            old_commit_oid,
+            new_commit_oid,
+            ..
+        } => vec![*old_commit_oid, *new_commit_oid],
+        Event::RefUpdateEvent {
+            old_oid, new_oid, ..
+        } => vec![*old_oid, *new_oid],
+        Event::CommitEvent { commit_oid, .. }
+        | Event::ObsoleteEvent { commit_oid, .. }
+        | Event::UnobsoleteEvent { commit_oid, .. } => {
+            vec![MaybeZeroOid::NonZero(*commit_oid)]
+        }
+        Event::WorkingCopySnapshot {
+            head_oid,
+            commit_oid,
+            ..
+        } => vec![*head_oid, MaybeZeroOid::NonZero(*commit_oid)],
+        Event::CopyEvent {
+            source_commit_oid,
+            new_commit_oid,
+            ..
+        } => vec![
+            MaybeZeroOid::NonZero(*source_commit_oid),
+            MaybeZeroOid::NonZero(*new_commit_oid),
+        ],
+    };
+    oids.into_iter()
+        .filter_map(|oid| match oid {
+            MaybeZeroOid::NonZero(oid) => Some(oid),
+            MaybeZeroOid::Zero => None,
+        })
+        .collect()
+}
+
+/// Mark every commit referred to by an event recorded after `cutoff` as
+/// reachable, including ones which have since been hidden, so that `git undo`
+/// can still return to them after Git's garbage collection runs.
+///
+/// Returns the number of commits which weren't already marked as reachable.
+#[instrument]
+pub fn pin_recent_commits(
+    repo: &Repo,
+    event_replayer: &EventReplayer,
+    cutoff: SystemTime,
+) -> eyre::Result<usize> {
+    let event_cursor = event_replayer.make_cursor_at_time(cutoff);
+    let commit_oids: HashSet<NonZeroOid> = event_replayer
+        .get_events_since_cursor(event_cursor)
+        .iter()
+        .flat_map(get_event_commit_oids)
+        .collect();
+
+    let mut num_pinned = 0;
+    for commit_oid in commit_oids {
+        let ref_name = ReferenceName::from(format!("refs/branchless/{commit_oid}"));
+        if repo.find_reference(&ref_name)?.is_some() || repo.find_commit(commit_oid)?.is_none() {
+            continue;
+        }
+        mark_commit_reachable(repo, commit_oid)?;
+        num_pinned += 1;
+    }
+    Ok(num_pinned)
+}
+
 /// Run branchless's garbage collection.
 ///
 /// Frees any references to commits which are no longer visible in the smartlog.
@@ -0,0 +1,267 @@
+//! Read history-tracking data from a Mercurial repository, so that it can be
+//! imported after migrating the repository to Git.
+//!
+//! Mercurial records rewrites as obsolescence markers in `.hg/store/obsstore`
+//! and commit phases as phase roots in `.hg/store/phaseroots`. Mercurial
+//! changesets are matched to Git commits using the mapping which hg-git keeps
+//! in `.hg/git-mapfile`.
+
+use std::collections::HashMap;
+use std::fmt::Write;
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+
+use eyre::Context;
+use tracing::instrument;
+
+use crate::git::NonZeroOid;
+
+/// The only supported version of the obsstore format. This is the default
+/// since Mercurial 3.4.
+const OBSSTORE_VERSION_1: u8 = 1;
+
+/// The size of the fixed-length header of each marker in the version 1 format:
+/// the marker size (`u32`), date (`f64`), timezone (`i16`), flags (`u16`), and
+/// the number of successors, parents, and metadata entries (`u8` each).
+const OBSSTORE_V1_HEADER_SIZE: usize = 19;
+
+/// Marker flag indicating that nodes are 32-byte SHA-256 hashes rather than
+/// 20-byte SHA-1 hashes.
+const OBSSTORE_FLAG_USING_SHA256: u16 = 2;
+
+/// Value for the number of parents which indicates that no parent data was
+/// recorded.
+const OBSSTORE_NO_PARENT_DATA: u8 = 255;
+
+/// An obsolescence marker, recording that a changeset was rewritten into its
+/// successors, or pruned if it has none. Nodes are hex-encoded.
+#[derive(Clone, Debug, PartialEq)]
+pub struct ObsMarker {
+    /// The changeset which was made obsolete.
+    pub predecessor: String,
+
+    /// The changesets which replaced it.
+    pub successors: Vec<String>,
+
+    /// When the marker was created, in seconds since the Unix epoch.
+    pub timestamp: f64,
+}
+
+/// The phase of the descendants of a Mercurial phase root.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum HgPhaseRootKind {
+    /// The root and its descendants are draft, and its parents are public.
+    Draft,
+
+    /// The root and its descendants are secret.
+    Secret,
+}
+
+/// The history-tracking data read from a Mercurial repository.
+#[derive(Debug)]
+pub struct HgHistory {
+    /// The obsolescence markers, in the order they were recorded.
+    pub markers: Vec<ObsMarker>,
+
+    /// The phase roots, as hex-encoded nodes.
+    pub phase_roots: Vec<(HgPhaseRootKind, String)>,
+
+    /// The Git commit corresponding to each hex-encoded Mercurial node.
+    pub git_oids: HashMap<String, NonZeroOid>,
+}
+
+fn encode_hex(bytes: &[u8]) -> String {
+    let mut result = String::with_capacity(bytes.len() * 2);
+    for byte in bytes {
+        write!(result, "{byte:02x}").expect("Writing to a String should not fail");
+    }
+    result
+}
+
+/// Parse the contents of a Mercurial `obsstore` file.
+#[instrument(skip(contents))]
+pub fn parse_obsstore(contents: &[u8]) -> eyre::Result<Vec<ObsMarker>> {
+    let (version, mut rest) = match contents.split_first() {
+        Some((version, rest)) => (*version, rest),
+        None => return Ok(Vec::new()),
+    };
+    if version != OBSSTORE_VERSION_1 {
+        eyre::bail!(
+            "Unsupported obsstore format version {version} (only version {OBSSTORE_VERSION_1} is supported)"
+        );
+    }
+
+    let mut result = Vec::new();
+    while !rest.is_empty() {
+        if rest.len() < OBSSTORE_V1_HEADER_SIZE {
+            eyre::bail!("Truncated obsolescence marker header");
+        }
+        let marker_size = usize::try_from(u32::from_be_bytes(rest[0..4].try_into()?))?;
+        if marker_size < OBSSTORE_V1_HEADER_SIZE || marker_size > rest.len() {
+            eyre::bail!("Invalid obsolescence marker size: {marker_size}");
+        }
+        let (marker, next) = rest.split_at(marker_size);
+        rest = next;
+
+        let timestamp = f64::from_be_bytes(marker[4..12].try_into()?);
+        let flags = u16::from_be_bytes(marker[14..16].try_into()?);
+        let num_successors = usize::from(marker[16]);
+        let num_parents = match marker[17] {
+            OBSSTORE_NO_PARENT_DATA => 0,
+            num_parents => usize::from(num_parents),
+        };
+        let node_size = if flags & OBSSTORE_FLAG_USING_SHA256 != 0 {
+            32
+        } else {
+            20
+        };
+
+        let nodes_size = node_size * (1 + num_successors + num_parents);
+        let nodes = match marker.get(OBSSTORE_V1_HEADER_SIZE..OBSSTORE_V1_HEADER_SIZE + nodes_size)
+        {
+            Some(nodes) => nodes,
+            None => eyre::bail!("Truncated obsolescence marker nodes"),
+        };
+        let mut nodes = nodes.chunks_exact(node_size).map(encode_hex);
+        let predecessor = nodes.next().expect("Marker should have a predecessor");
+        let successors = nodes.take(num_successors).collect();
+        // The parents and metadata aren't needed.
+        result.push(ObsMarker {
+            predecessor,
+            successors,
+            timestamp,
+        });
+    }
+    Ok(result)
+}
+
+/// Parse the contents of a Mercurial `phaseroots` file. Roots of phases other
+/// than draft and secret (such as archived) are ignored.
+#[instrument(skip(contents))]
+pub fn parse_phaseroots(contents: &str) -> eyre::Result<Vec<(HgPhaseRootKind, String)>> {
+    let mut result = Vec::new();
+    for line in contents.lines() {
+        let (phase, node) = match line.split_once(' ') {
+            Some(entry) => entry,
+            None if line.trim().is_empty() => continue,
+            None => eyre::bail!("Invalid phase root line: {line:?}"),
+        };
+        let kind = match phase {
+            "1" => HgPhaseRootKind::Draft,
+            "2" => HgPhaseRootKind::Secret,
+            _ => continue,
+        };
+        result.push((kind, node.trim().to_owned()));
+    }
+    Ok(result)
+}
+
+/// Parse the contents of an hg-git `git-mapfile`, which contains lines of the
+/// form `<git-oid> <hg-node>`.
+#[instrument(skip(contents))]
+pub fn parse_git_mapfile(contents: &str) -> eyre::Result<HashMap<String, NonZeroOid>> {
+    let mut result = HashMap::new();
+    for line in contents.lines() {
+        let (git_oid, hg_node) = match line.split_once(' ') {
+            Some(entry) => entry,
+            None if line.trim().is_empty() => continue,
+            None => eyre::bail!("Invalid git-mapfile line: {line:?}"),
+        };
+        let git_oid = NonZeroOid::from_str(git_oid)
+            .wrap_err_with(|| format!("Parsing commit OID: {git_oid:?}"))?;
+        result.insert(hg_node.trim().to_owned(), git_oid);
+    }
+    Ok(result)
+}
+
+/// Get the path to the hg-git mapping for the Mercurial repository at the
+/// given path.
+pub fn get_git_mapfile_path(hg_repo_path: &Path) -> PathBuf {
+    hg_repo_path.join(".hg").join("git-mapfile")
+}
+
+/// Read the history-tracking data from the Mercurial repository at the given
+/// path (the directory containing `.hg`). A missing `obsstore` or `phaseroots`
+/// file is treated as empty, but the hg-git mapping must be present.
+#[instrument]
+pub fn read_hg_history(hg_repo_path: &Path) -> eyre::Result<HgHistory> {
+    let store_dir = hg_repo_path.join(".hg").join("store");
+
+    let mapfile_path = get_git_mapfile_path(hg_repo_path);
+    let git_oids = match std::fs::read_to_string(&mapfile_path) {
+        Ok(contents) => parse_git_mapfile(&contents)?,
+        Err(err) if err.kind() == std::io::ErrorKind::NotFound => {
+            eyre::bail!(
+                "No hg-git mapping found at {}; the repository must have been converted with hg-git",
+                mapfile_path.display()
+            )
+        }
+        Err(err) => return Err(err).wrap_err("Reading git-mapfile"),
+    };
+
+    let markers = match std::fs::read(store_dir.join("obsstore")) {
+        Ok(contents) => parse_obsstore(&contents)?,
+        Err(err) if err.kind() == std::io::ErrorKind::NotFound => Vec::new(),
+        Err(err) => return Err(err).wrap_err("Reading obsstore"),
+    };
+
+    let phase_roots = match std::fs::read_to_string(store_dir.join("phaseroots")) {
+        Ok(contents) => parse_phaseroots(&contents)?,
+        Err(err) if err.kind() == std::io::ErrorKind::NotFound => Vec::new(),
+        Err(err) => return Err(err).wrap_err("Reading phaseroots"),
+    };
+
+    Ok(HgHistory {
+        markers,
+        phase_roots,
+        git_oids,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_obsstore() -> eyre::Result<()> {
+        let mut contents = vec![OBSSTORE_VERSION_1];
+        // A rewrite with no parent data and one metadata entry.
+        contents.extend(u32::to_be_bytes(19 + 20 + 20 + 2 + 4));
+        contents.extend(f64::to_be_bytes(1234.5));
+        contents.extend(i16::to_be_bytes(0));
+        contents.extend(u16::to_be_bytes(0));
+        contents.extend([1, OBSSTORE_NO_PARENT_DATA, 1]);
+        contents.extend([0xaa; 20]);
+        contents.extend([0xbb; 20]);
+        contents.extend([2, 2]);
+        contents.extend(b"opab");
+        // A prune with one parent.
+        contents.extend(u32::to_be_bytes(19 + 20 + 20));
+        contents.extend(f64::to_be_bytes(0.0));
+        contents.extend(i16::to_be_bytes(0));
+        contents.extend(u16::to_be_bytes(0));
+        contents.extend([0, 1, 0]);
+        contents.extend([0xcc; 20]);
+        contents.extend([0xdd; 20]);
+
+        assert_eq!(
+            parse_obsstore(&contents)?,
+            vec![
+                ObsMarker {
+                    predecessor: "aa".repeat(20),
+                    successors: vec!["bb".repeat(20)],
+                    timestamp: 1234.5,
+                },
+                ObsMarker {
+                    predecessor: "cc".repeat(20),
+                    successors: vec![],
+                    timestamp: 0.0,
+                },
+            ]
+        );
+
+        assert!(parse_obsstore(&[0]).is_err());
+        assert!(parse_obsstore(&contents[..contents.len() - 1]).is_err());
+        Ok(())
+    }
+}
@@ -0,0 +1,291 @@
+//! Control the progress messages printed by Git hooks.
+//!
+//! Each hook invocation prints a line such as `branchless: processed commit`.
+//! When a script makes hundreds of commits or checkouts, this produces hundreds
+//! of lines. The `BRANCHLESS_HOOK_OUTPUT` environment variable, which is
+//! inherited by the Git processes started by the script, controls this output:
+//!
+//! - `full` (the default): print every message.
+//! - `summary`: print the first message of each operation, and then at most
+//!   one message per second, along with the number of messages skipped in the
+//!   meantime. Repeated messages are skipped.
+//! - `quiet`: don't print any messages.
+//!
+//! An operation is either a git-branchless command, or an external Git
+//! command, such as a `git pull` which runs several hooks. The latter can only
+//! be identified on Linux; on other platforms, each hook invocation is its own
+//! operation.
+
+use std::fmt::{Display, Write};
+use std::time::{Duration, SystemTime};
+
+use eyre::Context;
+use tracing::instrument;
+
+use crate::core::effects::Effects;
+use crate::core::eventlog::{
+    get_external_git_command_key, EventTransactionId, BRANCHLESS_TRANSACTION_ID_ENV_VAR,
+};
+use crate::core::formatting::Pluralize;
+
+/// The environment variable which controls how much hooks print.
+pub const BRANCHLESS_HOOK_OUTPUT_ENV_VAR: &str = "BRANCHLESS_HOOK_OUTPUT";
+
+/// The minimum time between messages printed for the same operation in
+/// [`HookOutputMode::Summary`].
+const SUMMARY_INTERVAL: Duration = Duration::from_secs(1);
+
+/// How much hooks should print.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum HookOutputMode {
+    /// Print every message.
+    Full,
+
+    /// Coalesce the messages of each operation.
+    Summary,
+
+    /// Don't print any messages.
+    Quiet,
+}
+
+/// Get the hook output mode from the environment. Unrecognized values are
+/// treated as [`HookOutputMode::Full`].
+pub fn get_hook_output_mode() -> HookOutputMode {
+    match std::env::var(BRANCHLESS_HOOK_OUTPUT_ENV_VAR).as_deref() {
+        Ok("summary") => HookOutputMode::Summary,
+        Ok("quiet") => HookOutputMode::Quiet,
+        Ok(_) | Err(_) => HookOutputMode::Full,
+    }
+}
+
+/// Get the key identifying the operation which the current hook is part of.
+///
+/// Hooks run by a git-branchless command share its event transaction. Hooks
+/// run by the same external Git command share that command, regardless of
+/// whether `branchless.eventlog.groupExternalCommands` is set.
+fn get_operation_key(event_tx_id: isize) -> String {
+    let command_key = if std::env::var_os(BRANCHLESS_TRANSACTION_ID_ENV_VAR).is_some() {
+        None
+    } else {
+        get_external_git_command_key()
+    };
+    match command_key {
+        Some(command_key) => format!("command:{command_key}"),
+        None => format!("transaction:{event_tx_id}"),
+    }
+}
+
+/// Stores the state of the messages printed by hooks in
+/// [`HookOutputMode::Summary`].
+pub struct HookOutputDb<'conn> {
+    conn: &'conn rusqlite::Connection,
+}
+
+impl std::fmt::Debug for HookOutputDb<'_> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "<HookOutputDb path={:?}>", self.conn.path())
+    }
+}
+
+#[instrument]
+fn init_tables(conn: &rusqlite::Connection) -> eyre::Result<()> {
+    conn.execute(
+        "
+CREATE TABLE IF NOT EXISTS hook_output (
+    operation_key TEXT NOT NULL PRIMARY KEY,
+    last_message TEXT NOT NULL,
+    last_printed_timestamp REAL NOT NULL,
+    num_skipped_messages INTEGER NOT NULL
+)
+",
+        rusqlite::params![],
+    )
+    .wrap_err("Creating `hook_output` table")?;
+    Ok(())
+}
+
+/// Print the number of messages which were skipped, if any.
+fn write_skipped_messages(
+    effects: &Effects,
+    num_skipped_messages: i64,
+    operation_description: &str,
+) -> eyre::Result<()> {
+    if num_skipped_messages > 0 {
+        writeln!(
+            effects.get_output_stream(),
+            "branchless: (skipped {} from {operation_description})",
+            Pluralize {
+                determiner: None,
+                amount: usize::try_from(num_skipped_messages)?,
+                unit: ("message", "messages"),
+            }
+        )?;
+    }
+    Ok(())
+}
+
+impl<'conn> HookOutputDb<'conn> {
+    /// Constructor.
+    #[instrument]
+    pub fn new(conn: &'conn rusqlite::Connection) -> eyre::Result<Self> {
+        init_tables(conn)?;
+        Ok(HookOutputDb { conn })
+    }
+
+    /// Print a progress message from a hook, subject to the hook output mode.
+    /// The message should not include the `branchless: ` prefix.
+    #[instrument(skip(message))]
+    pub fn print_hook_message(
+        &self,
+        effects: &Effects,
+        event_tx_id: EventTransactionId,
+        message: impl Display,
+    ) -> eyre::Result<()> {
+        let event_tx_id = match (get_hook_output_mode(), event_tx_id) {
+            (HookOutputMode::Quiet, _) => return Ok(()),
+            (HookOutputMode::Summary, EventTransactionId::Id(event_tx_id)) => event_tx_id,
+            (HookOutputMode::Full, _)
+            | (HookOutputMode::Summary, EventTransactionId::Suppressed) => {
+                writeln!(effects.get_output_stream(), "branchless: {message}")?;
+                return Ok(());
+            }
+        };
+
+        let operation_key = get_operation_key(event_tx_id);
+        let message = message.to_string();
+        let now = SystemTime::now()
+            .duration_since(SystemTime::UNIX_EPOCH)?
+            .as_secs_f64();
+        let previous: Option<(String, f64, i64)> = {
+            let mut stmt = self.conn.prepare(
+                "
+SELECT last_message, last_printed_timestamp, num_skipped_messages
+FROM hook_output
+WHERE operation_key = :operation_key
+",
+            )?;
+            let mut rows = stmt.query(rusqlite::named_params! {
+                ":operation_key": &operation_key,
+            })?;
+            match rows.next()? {
+                Some(row) => Some((
+                    row.get("last_message")?,
+                    row.get("last_printed_timestamp")?,
+                    row.get("num_skipped_messages")?,
+                )),
+                None => None,
+            }
+        };
+
+        let num_skipped_messages = match previous {
+            None => {
+                // This is the first message of a new operation, so any other
+                // operations have most likely finished. Report what they
+                // skipped since their last message, and then forget them, so
+                // that the table doesn't keep growing.
+                self.flush_other_operations(effects, &operation_key)?;
+                0
+            }
+            Some((last_message, last_printed_timestamp, num_skipped_messages)) => {
+                if last_message == message
+                    || now - last_printed_timestamp < SUMMARY_INTERVAL.as_secs_f64()
+                {
+                    self.conn.execute(
+                        "
+UPDATE hook_output
+SET num_skipped_messages = num_skipped_messages + 1
+WHERE operation_key = :operation_key
+",
+                        rusqlite::named_params! {
+                            ":operation_key": &operation_key,
+                        },
+                    )?;
+                    return Ok(());
+                }
+                num_skipped_messages
+            }
+        };
+
+        write_skipped_messages(effects, num_skipped_messages, "this operation")?;
+        writeln!(effects.get_output_stream(), "branchless: {message}")?;
+        self.conn.execute(
+            "
+INSERT OR REPLACE INTO hook_output
+    (operation_key, last_message, last_printed_timestamp, num_skipped_messages)
+VALUES
+    (:operation_key, :last_message, :last_printed_timestamp, 0)
+",
+            rusqlite::named_params! {
+                ":operation_key": &operation_key,
+                ":last_message": message,
+                ":last_printed_timestamp": now,
+            },
+        )?;
+        Ok(())
+    }
+
+    /// Report the messages skipped for the git-branchless command with the
+    /// given event transaction since its last message, and forget about it.
+    /// This should be called once the Git subprocesses which it started have
+    /// finished.
+    #[instrument]
+    pub fn flush_skipped_messages(
+        &self,
+        effects: &Effects,
+        event_tx_id: EventTransactionId,
+    ) -> eyre::Result<()> {
+        let event_tx_id = match event_tx_id {
+            EventTransactionId::Id(event_tx_id) => event_tx_id,
+            EventTransactionId::Suppressed => return Ok(()),
+        };
+
+        let operation_key = format!("transaction:{event_tx_id}");
+        let num_skipped_messages: i64 = self.conn.query_row(
+            "
+SELECT COALESCE(SUM(num_skipped_messages), 0)
+FROM hook_output
+WHERE operation_key = :operation_key
+",
+            rusqlite::named_params! {
+                ":operation_key": &operation_key,
+            },
+            |row| row.get(0),
+        )?;
+        write_skipped_messages(effects, num_skipped_messages, "this operation")?;
+        self.conn.execute(
+            "
+DELETE FROM hook_output
+WHERE operation_key = :operation_key
+",
+            rusqlite::named_params! {
+                ":operation_key": &operation_key,
+            },
+        )?;
+        Ok(())
+    }
+
+    fn flush_other_operations(&self, effects: &Effects, operation_key: &str) -> eyre::Result<()> {
+        let num_skipped_messages: i64 = self.conn.query_row(
+            "
+SELECT COALESCE(SUM(num_skipped_messages), 0)
+FROM hook_output
+WHERE operation_key != :operation_key
+",
+            rusqlite::named_params! {
+                ":operation_key": operation_key,
+            },
+            |row| row.get(0),
+        )?;
+        write_skipped_messages(effects, num_skipped_messages, "a previous operation")?;
+        self.conn.execute(
+            "
+DELETE FROM hook_output
+WHERE operation_key != :operation_key
+",
+            rusqlite::named_params! {
+                ":operation_key": operation_key,
+            },
+        )?;
+        Ok(())
+    }
+}
@@ -1,13 +1,23 @@
 //! Core algorithms and data structures.
 
 pub mod check_out;
+pub mod commit_metadata;
 pub mod config;
 pub mod dag;
+pub mod dialoguer_edit;
+pub mod editor;
 pub mod effects;
 pub mod eventlog;
 pub mod formatting;
 pub mod gc;
+pub mod hg_import;
+pub mod hook_output;
+pub mod nicknames;
 pub mod node_descriptors;
+pub mod notes;
+pub mod phase;
 pub mod repo_ext;
 pub mod rewrite;
+pub mod stacks;
+pub mod sync_state;
 pub mod task;
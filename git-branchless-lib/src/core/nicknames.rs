@@ -0,0 +1,230 @@
+//! Short, human-friendly nicknames for commits.
+//!
+//! Each visible draft commit can be given a nickname such as `otter` or
+//! `maple2`, which is shown in the smartlog (see
+//! `branchless.commitDescriptors.nicknames`) and can be used anywhere a commit
+//! is expected. Nicknames are stored on disk and follow a commit when it's
+//! rewritten, so that a commit keeps the same nickname as it's amended,
+//! rebased, etc.
+
+use std::collections::HashMap;
+use std::str::FromStr;
+
+use eyre::Context;
+use tracing::instrument;
+
+use crate::git::NonZeroOid;
+
+/// The words from which nicknames are generated. These are short, easy to
+/// type, and don't overlap with any revset function names.
+const WORDS: &[&str] = &[
+    "acorn", "adder", "alder", "amber", "anvil", "apple", "aspen", "aster", "badger", "basil",
+    "beech", "birch", "bison", "brook", "cedar", "cliff", "clover", "cobra", "comet", "coral",
+    "crane", "daisy", "delta", "dingo", "dune", "eagle", "egret", "elm", "ember", "falcon", "fern",
+    "finch", "fjord", "flint", "gecko", "ginger", "glade", "grove", "gull", "hazel", "heron",
+    "holly", "ibis", "iris", "ivy", "jackal", "jade", "jasper", "juniper", "kelp", "kestrel",
+    "kiwi", "koala", "lark", "laurel", "lemur", "lilac", "lotus", "lynx", "magpie", "mango",
+    "maple", "marten", "meadow", "mink", "moss", "newt", "nutmeg", "oak", "ocelot", "olive",
+    "onyx", "orca", "osprey", "otter", "owl", "panda", "pearl", "pebble", "pine", "plover",
+    "poppy", "puffin", "quail", "quartz", "raven", "reed", "ridge", "robin", "rowan", "sage",
+    "salmon", "sparrow", "spruce", "stoat", "swift", "tapir", "teal", "thistle", "thrush", "tiger",
+    "topaz", "tulip", "tundra", "umber", "valley", "violet", "viper", "walnut", "walrus", "willow",
+    "wren", "yak", "yarrow", "zebra", "zinnia",
+];
+
+/// Stores the nicknames assigned to commits on disk.
+pub struct NicknameDb<'conn> {
+    conn: &'conn rusqlite::Connection,
+}
+
+impl std::fmt::Debug for NicknameDb<'_> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "<NicknameDb path={:?}>", self.conn.path())
+    }
+}
+
+/// Create the `commit_nicknames` table. This is called when the event log is
+/// opened, since rewrite events update the table.
+#[instrument]
+pub(crate) fn init_tables(conn: &rusqlite::Connection) -> eyre::Result<()> {
+    conn.execute(
+        "
+CREATE TABLE IF NOT EXISTS commit_nicknames (
+    nickname TEXT NOT NULL PRIMARY KEY,
+    commit_oid TEXT NOT NULL UNIQUE
+)
+",
+        rusqlite::params![],
+    )
+    .wrap_err("Creating `commit_nicknames` table")?;
+    Ok(())
+}
+
+/// Move the nicknames of the rewritten commits to their new versions. If the
+/// new version of a commit already has a nickname, then it's kept.
+///
+/// This is called whenever rewrite events are added to the event log, so that
+/// nicknames are stable across rewrites.
+#[instrument(skip(rewrites))]
+pub(crate) fn transfer_nicknames(
+    conn: &rusqlite::Connection,
+    rewrites: impl IntoIterator<Item = (NonZeroOid, NonZeroOid)>,
+) -> eyre::Result<()> {
+    let mut stmt = conn.prepare_cached(
+        "
+UPDATE OR IGNORE commit_nicknames
+SET commit_oid = :new_commit_oid
+WHERE commit_oid = :old_commit_oid
+",
+    )?;
+    for (old_commit_oid, new_commit_oid) in rewrites {
+        stmt.execute(rusqlite::named_params! {
+            ":old_commit_oid": old_commit_oid.to_string(),
+            ":new_commit_oid": new_commit_oid.to_string(),
+        })?;
+    }
+    Ok(())
+}
+
+/// Deterministically pick the preferred word for the given commit, so that the
+/// same commit gets the same nickname in different clones of a repository when
+/// there are no collisions.
+fn preferred_word_index(commit_oid: NonZeroOid) -> usize {
+    let bytes = commit_oid.as_bytes();
+    let value = (usize::from(bytes[0]) << 8) | usize::from(bytes[1]);
+    value % WORDS.len()
+}
+
+impl<'conn> NicknameDb<'conn> {
+    /// Constructor.
+    #[instrument]
+    pub fn new(conn: &'conn rusqlite::Connection) -> eyre::Result<Self> {
+        init_tables(conn)?;
+        Ok(NicknameDb { conn })
+    }
+
+    /// Get the nickname of the given commit, if it has one.
+    #[instrument]
+    pub fn get_nickname(&self, commit_oid: NonZeroOid) -> eyre::Result<Option<String>> {
+        let mut stmt = self.conn.prepare_cached(
+            "
+SELECT nickname
+FROM commit_nicknames
+WHERE commit_oid = :commit_oid
+",
+        )?;
+        let mut rows = stmt.query(rusqlite::named_params! {
+            ":commit_oid": commit_oid.to_string(),
+        })?;
+        match rows.next()? {
+            Some(row) => Ok(Some(row.get("nickname")?)),
+            None => Ok(None),
+        }
+    }
+
+    /// Get the commit with the given nickname, if any.
+    #[instrument]
+    pub fn get_commit_oid(&self, nickname: &str) -> eyre::Result<Option<NonZeroOid>> {
+        let mut stmt = self.conn.prepare_cached(
+            "
+SELECT commit_oid
+FROM commit_nicknames
+WHERE nickname = :nickname
+",
+        )?;
+        let mut rows = stmt.query(rusqlite::named_params! {
+            ":nickname": nickname,
+        })?;
+        let commit_oid: String = match rows.next()? {
+            Some(row) => row.get("commit_oid")?,
+            None => return Ok(None),
+        };
+        let commit_oid = NonZeroOid::from_str(&commit_oid)
+            .wrap_err_with(|| format!("Parsing commit OID: {commit_oid:?}"))?;
+        Ok(Some(commit_oid))
+    }
+
+    /// Get the nicknames of the given commits, assigning new nicknames to any
+    /// commits which don't already have one.
+    ///
+    /// A nickname held by a commit for which `is_reclaimable` returns `true`
+    /// (such as a commit which is no longer visible) may be reassigned to
+    /// another commit.
+    #[instrument(skip(commit_oids, is_reclaimable))]
+    pub fn assign_nicknames(
+        &self,
+        commit_oids: impl IntoIterator<Item = NonZeroOid>,
+        mut is_reclaimable: impl FnMut(NonZeroOid) -> eyre::Result<bool>,
+    ) -> eyre::Result<HashMap<NonZeroOid, String>> {
+        let tx = self.conn.unchecked_transaction()?;
+        let mut result = HashMap::new();
+        for commit_oid in commit_oids {
+            if let Some(nickname) = self.get_nickname(commit_oid)? {
+                result.insert(commit_oid, nickname);
+                continue;
+            }
+
+            let start_index = preferred_word_index(commit_oid);
+            let candidates = (1..).flat_map(|suffix: usize| {
+                (0..WORDS.len()).map(move |offset| {
+                    let word = WORDS[(start_index + offset) % WORDS.len()];
+                    if suffix == 1 {
+                        word.to_string()
+                    } else {
+                        format!("{word}{suffix}")
+                    }
+                })
+            });
+            for nickname in candidates {
+                match self.get_commit_oid(&nickname)? {
+                    None => {}
+                    Some(other_commit_oid) if is_reclaimable(other_commit_oid)? => {
+                        tx.execute(
+                            "
+DELETE FROM commit_nicknames
+WHERE nickname = :nickname
+",
+                            rusqlite::named_params! {
+                                ":nickname": &nickname,
+                            },
+                        )?;
+                    }
+                    Some(_) => continue,
+                }
+
+                tx.execute(
+                    "
+INSERT INTO commit_nicknames
+(nickname, commit_oid)
+VALUES
+(:nickname, :commit_oid)
+",
+                    rusqlite::named_params! {
+                        ":nickname": &nickname,
+                        ":commit_oid": commit_oid.to_string(),
+                    },
+                )?;
+                result.insert(commit_oid, nickname);
+                break;
+            }
+        }
+        tx.commit()?;
+        Ok(result)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_words_are_valid_names() {
+        let mut words = WORDS.to_vec();
+        words.sort_unstable();
+        words.dedup();
+        assert_eq!(words.len(), WORDS.len());
+        for word in WORDS {
+            assert!(word.chars().all(|c| c.is_ascii_lowercase()), "{word}");
+        }
+    }
+}
@@ -16,16 +16,19 @@ use regex::Regex;
 use tracing::instrument;
 
 use crate::core::config::{
-    get_commit_descriptors_branches, get_commit_descriptors_differential_revision,
-    get_commit_descriptors_relative_time,
+    get_commit_descriptors_branches, get_commit_descriptors_copy_sources,
+    get_commit_descriptors_differential_revision, get_commit_descriptors_nicknames,
+    get_commit_descriptors_relative_time, get_commit_descriptors_tags,
 };
 use crate::git::{
     CategorizedReferenceName, Commit, NonZeroOid, ReferenceName, Repo, ResolvedReferenceInfo,
 };
 
-use super::eventlog::{Event, EventCursor, EventReplayer};
+use super::dag::{CommitSet, Dag};
+use super::eventlog::{CopyType, Event, EventCursor, EventReplayer};
 use super::formatting::{Glyphs, StyledStringBuilder};
-use super::repo_ext::RepoReferencesSnapshot;
+use super::nicknames::NicknameDb;
+use super::repo_ext::{RepoExt, RepoReferencesSnapshot};
 use super::rewrite::find_rewrite_target;
 
 /// An object which can be rendered in the smartlog.
@@ -112,6 +115,7 @@ impl Redactor {
                         let prefix = match categorized_ref_name {
                             CategorizedReferenceName::LocalBranch { name: _, prefix } => prefix,
                             CategorizedReferenceName::RemoteBranch { name: _, prefix } => prefix,
+                            CategorizedReferenceName::Tag { name: _, prefix } => prefix,
                             CategorizedReferenceName::OtherRef { name: _ } => "",
                         };
                         format!("{prefix}redacted-ref-{len}").into()
@@ -142,6 +146,36 @@ impl Redactor {
     }
 }
 
+/// The maximum number of entries kept in the commit summary cache. The cache
+/// is cleared entirely when this is exceeded, which is simpler than evicting
+/// individual entries and rarely happens in practice.
+const COMMIT_SUMMARY_CACHE_CAPACITY: usize = 10_000;
+
+lazy_static! {
+    /// Commit summaries as rendered by [`CommitMessageDescriptor`], before
+    /// redaction, keyed by commit OID. A summary depends only on the contents
+    /// of the commit, so it's never out of date, regardless of the repository,
+    /// glyphs, or theme being used.
+    static ref COMMIT_SUMMARY_CACHE: Mutex<HashMap<NonZeroOid, String>> = Default::default();
+}
+
+fn get_or_insert_cached<K: Eq + std::hash::Hash, V: Clone>(
+    cache: &Mutex<HashMap<K, V>>,
+    key: K,
+    f: impl FnOnce() -> eyre::Result<V>,
+) -> eyre::Result<V> {
+    if let Some(value) = cache.lock().expect("Poisoned mutex").get(&key) {
+        return Ok(value.clone());
+    }
+    let value = f()?;
+    let mut cache = cache.lock().expect("Poisoned mutex");
+    if cache.len() >= COMMIT_SUMMARY_CACHE_CAPACITY {
+        cache.clear();
+    }
+    cache.insert(key, value.clone());
+    Ok(value)
+}
+
 /// Interface to display information about a node in the smartlog.
 pub trait NodeDescriptor {
     /// Provide a description of the given commit.
@@ -224,8 +258,10 @@ impl<'a> NodeDescriptor for CommitMessageDescriptor<'a> {
     ) -> eyre::Result<Option<StyledString>> {
         let summary = match object {
             NodeObject::Commit { commit } => {
-                let summary = commit.get_summary()?.to_vec();
-                summary.into_string_lossy()
+                get_or_insert_cached(&COMMIT_SUMMARY_CACHE, commit.get_oid(), || {
+                    let summary = commit.get_summary()?.to_vec();
+                    Ok(summary.into_string_lossy())
+                })?
             }
             NodeObject::GarbageCollected { oid: _ } => "<garbage collected>".to_string(),
         };
@@ -285,12 +321,65 @@ impl<'a> NodeDescriptor for ObsolescenceExplanationDescriptor<'a> {
             Event::RefUpdateEvent { .. }
             | Event::CommitEvent { .. }
             | Event::UnobsoleteEvent { .. }
-            | Event::WorkingCopySnapshot { .. } => None,
+            | Event::WorkingCopySnapshot { .. }
+            | Event::CopyEvent { .. } => None,
         };
         Ok(result)
     }
 }
 
+/// For commits created by `git cherry-pick` or `git revert`, show the commit
+/// which they were copied from.
+pub struct CopySourceDescriptor<'a> {
+    is_enabled: bool,
+    event_replayer: &'a EventReplayer,
+    event_cursor: EventCursor,
+}
+
+impl<'a> CopySourceDescriptor<'a> {
+    /// Constructor.
+    pub fn new(
+        repo: &Repo,
+        event_replayer: &'a EventReplayer,
+        event_cursor: EventCursor,
+    ) -> eyre::Result<Self> {
+        let is_enabled = get_commit_descriptors_copy_sources(repo)?;
+        Ok(CopySourceDescriptor {
+            is_enabled,
+            event_replayer,
+            event_cursor,
+        })
+    }
+}
+
+impl<'a> NodeDescriptor for CopySourceDescriptor<'a> {
+    fn describe_node(
+        &mut self,
+        _glyphs: &Glyphs,
+        object: &NodeObject,
+    ) -> eyre::Result<Option<StyledString>> {
+        if !self.is_enabled {
+            return Ok(None);
+        }
+
+        let (copy_type, source_commit_oid) = match self
+            .event_replayer
+            .get_cursor_copy_source(self.event_cursor, object.get_oid())
+        {
+            Some(copy_source) => copy_source,
+            None => return Ok(None),
+        };
+        let description = match copy_type {
+            CopyType::CherryPick => "copied from",
+            CopyType::Revert => "reverts",
+        };
+        Ok(Some(StyledString::styled(
+            format!("({description} {})", &source_commit_oid.to_string()[..8]),
+            BaseColor::Black.light(),
+        )))
+    }
+}
+
 /// Display branches that point to a given commit.
 #[derive(Debug)]
 pub struct BranchesDescriptor<'a> {
@@ -362,6 +451,9 @@ impl<'a> NodeDescriptor for BranchesDescriptor<'a> {
                         reference_name @ CategorizedReferenceName::RemoteBranch { .. } => {
                             format!("{}remote {}", icon, reference_name.render_suffix())
                         }
+                        reference_name @ CategorizedReferenceName::Tag { .. } => {
+                            format!("{}tag {}", icon, reference_name.render_suffix())
+                        }
                         reference_name @ CategorizedReferenceName::OtherRef { .. } => {
                             format!("{}ref {}", icon, reference_name.render_suffix())
                         }
@@ -378,6 +470,64 @@ impl<'a> NodeDescriptor for BranchesDescriptor<'a> {
     }
 }
 
+/// Display tags that point to a given commit.
+#[derive(Debug)]
+pub struct TagsDescriptor<'a> {
+    is_enabled: bool,
+    tag_oid_to_names: HashMap<NonZeroOid, HashSet<ReferenceName>>,
+    redactor: &'a Redactor,
+}
+
+impl<'a> TagsDescriptor<'a> {
+    /// Constructor.
+    pub fn new(
+        repo: &Repo,
+        references_snapshot: &RepoReferencesSnapshot,
+        redactor: &'a Redactor,
+    ) -> eyre::Result<Self> {
+        let is_enabled = get_commit_descriptors_tags(repo)?;
+        let tag_oid_to_names = if is_enabled {
+            references_snapshot.tag_oid_to_names.clone()
+        } else {
+            Default::default()
+        };
+        Ok(TagsDescriptor {
+            is_enabled,
+            tag_oid_to_names,
+            redactor,
+        })
+    }
+}
+
+impl<'a> NodeDescriptor for TagsDescriptor<'a> {
+    #[instrument]
+    fn describe_node(
+        &mut self,
+        _glyphs: &Glyphs,
+        object: &NodeObject,
+    ) -> eyre::Result<Option<StyledString>> {
+        if !self.is_enabled {
+            return Ok(None);
+        }
+
+        let mut tag_names: Vec<String> = match self.tag_oid_to_names.get(&object.get_oid()) {
+            Some(tag_names) => tag_names
+                .iter()
+                .map(|tag_name| {
+                    let tag_name = self.redactor.redact_ref_name(tag_name.to_owned());
+                    CategorizedReferenceName::new(&tag_name).friendly_describe()
+                })
+                .collect(),
+            None => return Ok(None),
+        };
+        tag_names.sort_unstable();
+        Ok(Some(StyledString::styled(
+            format!("({})", tag_names.join(", ")),
+            BaseColor::Yellow.light(),
+        )))
+    }
+}
+
 /// Display the associated Phabricator revision for a given commit.
 #[derive(Debug)]
 pub struct DifferentialRevisionDescriptor<'a> {
@@ -441,6 +591,52 @@ impl<'a> NodeDescriptor for DifferentialRevisionDescriptor<'a> {
     }
 }
 
+/// Display the nickname of a given draft commit. Nicknames are assigned to
+/// the visible draft commits being rendered as necessary.
+#[derive(Debug)]
+pub struct NicknameDescriptor {
+    nicknames: HashMap<NonZeroOid, String>,
+}
+
+impl NicknameDescriptor {
+    /// Constructor.
+    pub fn new(repo: &Repo, dag: &Dag, commits: &CommitSet) -> eyre::Result<Self> {
+        if !get_commit_descriptors_nicknames(repo)? {
+            return Ok(NicknameDescriptor {
+                nicknames: Default::default(),
+            });
+        }
+
+        let visible_commits = dag.query_visible_commits_slow()?;
+        let draft_commits = commits
+            .intersection(visible_commits)
+            .intersection(dag.query_draft_commits()?);
+        let conn = repo.get_db_conn()?;
+        let nickname_db = NicknameDb::new(&conn)?;
+        let nicknames = nickname_db
+            .assign_nicknames(dag.commit_set_to_vec(&draft_commits)?, |commit_oid| {
+                Ok(!dag.set_contains(visible_commits, commit_oid)?)
+            })?;
+        Ok(NicknameDescriptor { nicknames })
+    }
+}
+
+impl NodeDescriptor for NicknameDescriptor {
+    #[instrument]
+    fn describe_node(
+        &mut self,
+        _glyphs: &Glyphs,
+        object: &NodeObject,
+    ) -> eyre::Result<Option<StyledString>> {
+        let nickname = match self.nicknames.get(&object.get_oid()) {
+            Some(nickname) => nickname,
+            None => return Ok(None),
+        };
+        let result = StyledString::styled(nickname.clone(), BaseColor::Magenta.light());
+        Ok(Some(result))
+    }
+}
+
 /// Display how long ago the given commit was committed.
 #[derive(Debug)]
 pub struct RelativeTimeDescriptor {
@@ -544,6 +740,26 @@ Differential Revision: phabricator.com/D123";
         Ok(())
     }
 
+    #[test]
+    fn test_get_or_insert_cached() -> eyre::Result<()> {
+        let cache: Mutex<HashMap<usize, String>> = Default::default();
+        let mut num_renders = 0;
+        for _ in 0..3 {
+            let value = get_or_insert_cached(&cache, 1, || {
+                num_renders += 1;
+                Ok("foo".to_string())
+            })?;
+            assert_eq!(value, "foo");
+        }
+        assert_eq!(num_renders, 1);
+
+        let result = get_or_insert_cached(&cache, 2, || eyre::bail!("failed to render"));
+        assert!(result.is_err());
+        assert_eq!(cache.lock().unwrap().len(), 1);
+
+        Ok(())
+    }
+
     #[test]
     fn test_describe_time_delta() -> eyre::Result<()> {
         let test_cases: Vec<(isize, &str)> = vec![
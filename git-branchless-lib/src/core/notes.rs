@@ -0,0 +1,101 @@
+//! Mirror the rewrite history of commits into Git notes.
+//!
+//! The event log is stored in a local database, so other clones of the
+//! repository and other tools can't see which commits were rewritten into
+//! which. If `branchless.notes.mirrorRewrites` is set, each rewrite is also
+//! recorded as a line in the note attached to the old commit under
+//! [`REWRITE_NOTES_REF_NAME`]. Notes can be shared with `git push <remote>
+//! refs/notes/branchless`, shown with `git log --notes=branchless`, and read
+//! back into the event log with `git branchless import --from-notes`.
+
+use std::collections::HashMap;
+
+use tracing::instrument;
+
+use crate::core::config::get_mirror_rewrites_to_notes;
+use crate::git::{
+    list_notes, read_note, write_notes, MaybeZeroOid, NonZeroOid, ReferenceName, Repo,
+};
+
+/// The notes reference under which rewrites are recorded.
+pub const REWRITE_NOTES_REF_NAME: &str = "refs/notes/branchless";
+
+const REWRITTEN_INTO_PREFIX: &str = "Rewritten into ";
+const DELETED_LINE: &str = "Deleted";
+
+fn render_rewrite_line(new_commit_oid: MaybeZeroOid) -> String {
+    match new_commit_oid {
+        MaybeZeroOid::NonZero(new_commit_oid) => format!("{REWRITTEN_INTO_PREFIX}{new_commit_oid}"),
+        MaybeZeroOid::Zero => DELETED_LINE.to_string(),
+    }
+}
+
+/// Parse the rewrites recorded in a note. Lines which weren't written by
+/// [`mirror_rewrites_to_notes`] are ignored, so that the note can also be
+/// edited by hand.
+fn parse_rewrite_lines(message: &str) -> Vec<MaybeZeroOid> {
+    message
+        .lines()
+        .filter_map(|line| {
+            let line = line.trim();
+            if line == DELETED_LINE {
+                Some(MaybeZeroOid::Zero)
+            } else {
+                let oid = line.strip_prefix(REWRITTEN_INTO_PREFIX)?;
+                oid.parse::<NonZeroOid>().ok().map(MaybeZeroOid::NonZero)
+            }
+        })
+        .collect()
+}
+
+/// Record the given rewrites in the notes for the old commits, if enabled with
+/// `branchless.notes.mirrorRewrites`. Rewrites which are already recorded
+/// aren't duplicated. All of the rewrites are recorded in a single commit to
+/// the notes reference.
+#[instrument(skip(rewrites))]
+pub fn mirror_rewrites_to_notes(
+    repo: &Repo,
+    rewrites: impl IntoIterator<Item = (NonZeroOid, MaybeZeroOid)>,
+) -> eyre::Result<()> {
+    if !get_mirror_rewrites_to_notes(repo)? {
+        return Ok(());
+    }
+
+    let notes_ref = ReferenceName::from(REWRITE_NOTES_REF_NAME);
+    let mut messages: HashMap<NonZeroOid, String> = HashMap::new();
+    for (old_commit_oid, new_commit_oid) in rewrites {
+        let message = match messages.get(&old_commit_oid) {
+            Some(message) => message.clone(),
+            None => read_note(repo, &notes_ref, old_commit_oid)?.unwrap_or_default(),
+        };
+        if parse_rewrite_lines(&message).contains(&new_commit_oid) {
+            continue;
+        }
+
+        let mut message = message.trim_end().to_string();
+        if !message.is_empty() {
+            message.push('\n');
+        }
+        message.push_str(&render_rewrite_line(new_commit_oid));
+        message.push('\n');
+        messages.insert(old_commit_oid, message);
+    }
+    write_notes(repo, &notes_ref, &messages)?;
+    Ok(())
+}
+
+/// Get all of the rewrites recorded in the notes under
+/// [`REWRITE_NOTES_REF_NAME`], as pairs of the old and new commit OIDs.
+#[instrument]
+pub fn read_rewrite_notes(repo: &Repo) -> eyre::Result<Vec<(NonZeroOid, MaybeZeroOid)>> {
+    let notes_ref = ReferenceName::from(REWRITE_NOTES_REF_NAME);
+    let mut result = Vec::new();
+    for (old_commit_oid, message) in list_notes(repo, &notes_ref)? {
+        result.extend(
+            parse_rewrite_lines(&message)
+                .into_iter()
+                .map(|new_commit_oid| (old_commit_oid, new_commit_oid)),
+        );
+    }
+    Ok(result)
+}
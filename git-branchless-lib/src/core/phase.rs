@@ -0,0 +1,171 @@
+//! Explicit phase tracking for commits.
+//!
+//! By default, a commit is public if it's reachable from the main branch, and
+//! draft otherwise. That doesn't capture workflows where draft commits are
+//! shared with others, such as by pushing them to a shared feature branch. The
+//! phase of a commit can therefore be recorded explicitly:
+//!
+//! - A commit recorded as public is treated as public, as are all of its
+//!   ancestors. Public commits are never rewritten or hidden without
+//!   `--force-rewrite`. Commits pushed to a protected remote (see
+//!   `branchless.core.protectedRemotes`) are recorded as public automatically.
+//! - A commit recorded as secret is treated as secret, as are all of its
+//!   descendants which aren't public. Secret commits are never pushed by
+//!   `git submit`.
+
+use std::fmt::Display;
+use std::str::FromStr;
+
+use eyre::Context;
+use tracing::instrument;
+
+use crate::git::NonZeroOid;
+
+/// The phase of a commit.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum Phase {
+    /// The commit has been published and should not be rewritten.
+    Public,
+
+    /// The commit is a work in progress and may be rewritten. This is the
+    /// default phase for commits which aren't reachable from the main branch.
+    Draft,
+
+    /// The commit is local-only and should never be pushed.
+    Secret,
+}
+
+impl Phase {
+    fn as_str(&self) -> &'static str {
+        match self {
+            Phase::Public => "public",
+            Phase::Draft => "draft",
+            Phase::Secret => "secret",
+        }
+    }
+}
+
+impl Display for Phase {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.as_str())
+    }
+}
+
+impl FromStr for Phase {
+    type Err = eyre::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "public" => Ok(Phase::Public),
+            "draft" => Ok(Phase::Draft),
+            "secret" => Ok(Phase::Secret),
+            other => eyre::bail!("Unknown phase: {other:?}"),
+        }
+    }
+}
+
+/// Stores explicitly-recorded commit phases on disk.
+pub struct PhaseDb<'conn> {
+    conn: &'conn rusqlite::Connection,
+}
+
+impl std::fmt::Debug for PhaseDb<'_> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "<PhaseDb path={:?}>", self.conn.path())
+    }
+}
+
+#[instrument]
+fn init_tables(conn: &rusqlite::Connection) -> eyre::Result<()> {
+    conn.execute(
+        "
+CREATE TABLE IF NOT EXISTS commit_phases (
+    commit_oid TEXT NOT NULL PRIMARY KEY,
+    phase TEXT NOT NULL
+)
+",
+        rusqlite::params![],
+    )
+    .wrap_err("Creating `commit_phases` table")?;
+    Ok(())
+}
+
+impl<'conn> PhaseDb<'conn> {
+    /// Constructor.
+    #[instrument]
+    pub fn new(conn: &'conn rusqlite::Connection) -> eyre::Result<Self> {
+        init_tables(conn)?;
+        Ok(PhaseDb { conn })
+    }
+
+    /// Record the phase of the given commits. Recording a commit as draft
+    /// removes any phase previously recorded for it.
+    #[instrument(skip(commit_oids))]
+    pub fn set_phase(
+        &self,
+        commit_oids: impl IntoIterator<Item = NonZeroOid>,
+        phase: Phase,
+    ) -> eyre::Result<()> {
+        let tx = self.conn.unchecked_transaction()?;
+        for commit_oid in commit_oids {
+            match phase {
+                Phase::Draft => {
+                    tx.execute(
+                        "
+DELETE FROM commit_phases
+WHERE commit_oid = :commit_oid
+",
+                        rusqlite::named_params! {
+                            ":commit_oid": commit_oid.to_string(),
+                        },
+                    )?;
+                }
+                Phase::Public | Phase::Secret => {
+                    tx.execute(
+                        "
+INSERT OR REPLACE INTO commit_phases
+(commit_oid, phase)
+VALUES
+(:commit_oid, :phase)
+",
+                        rusqlite::named_params! {
+                            ":commit_oid": commit_oid.to_string(),
+                            ":phase": phase.as_str(),
+                        },
+                    )?;
+                }
+            }
+        }
+        tx.commit()?;
+        Ok(())
+    }
+
+    /// Get the commits which were explicitly recorded with the given phase.
+    /// This doesn't include the commits whose phase is implied by one of these
+    /// commits, such as the ancestors of a public commit.
+    #[instrument]
+    pub fn get_commits_with_phase(&self, phase: Phase) -> eyre::Result<Vec<NonZeroOid>> {
+        let mut stmt = self.conn.prepare(
+            "
+SELECT commit_oid
+FROM commit_phases
+WHERE phase = :phase
+",
+        )?;
+        let commit_oids: rusqlite::Result<Vec<String>> = stmt
+            .query_map(
+                rusqlite::named_params! {
+                    ":phase": phase.as_str(),
+                },
+                |row| row.get("commit_oid"),
+            )?
+            .collect();
+        commit_oids?
+            .into_iter()
+            .map(|commit_oid| {
+                NonZeroOid::from_str(&commit_oid)
+                    .wrap_err_with(|| format!("Parsing commit OID: {commit_oid:?}"))
+            })
+            .collect()
+    }
+}
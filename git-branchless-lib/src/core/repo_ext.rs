@@ -4,13 +4,13 @@ use std::collections::{HashMap, HashSet};
 
 use color_eyre::Help;
 use eyre::Context;
-use tracing::instrument;
+use tracing::{instrument, warn};
 
 use crate::git::{
     Branch, BranchType, CategorizedReferenceName, ConfigRead, NonZeroOid, ReferenceName, Repo,
 };
 
-use super::config::get_main_branch_name;
+use super::config::{get_main_branch_name, get_main_commit_spec, MAIN_COMMIT_CONFIG_KEY};
 
 /// A snapshot of all the positions of references we care about in the repository.
 #[derive(Debug)]
@@ -23,14 +23,43 @@ pub struct RepoReferencesSnapshot {
 
     /// A mapping from commit OID to the branches which point to that commit.
     pub branch_oid_to_names: HashMap<NonZeroOid, HashSet<ReferenceName>>,
+
+    /// A mapping from commit OID to the tags which point to that commit.
+    pub tag_oid_to_names: HashMap<NonZeroOid, HashSet<ReferenceName>>,
 }
 
 /// Helper functions on [`Repo`].
 pub trait RepoExt {
     /// Get the `Branch` for the main branch for the repository.
+    ///
+    /// If the configured main branch no longer exists, but a remote's default
+    /// branch exists locally (for example, because the upstream repository
+    /// renamed `master` to `main`), then that branch is returned instead. The
+    /// main branch setting is left as-is; run `git branchless init
+    /// --main-branch` to update it.
     fn get_main_branch(&self) -> eyre::Result<Branch>;
 
-    /// Get the OID corresponding to the main branch.
+    /// Detect the main branch from the default branch of a remote, as recorded
+    /// in `refs/remotes/<remote>/HEAD`. The `origin` remote is checked first.
+    /// Only branches which also exist locally are returned.
+    fn detect_main_branch_from_remote_head(&self) -> eyre::Result<Option<String>>;
+
+    /// Get the name of the reference for the main branch.
+    ///
+    /// If the main branch is pinned with `branchless.core.mainCommit`, then the
+    /// fully-qualified name of the configured revision is returned instead, or
+    /// the revision itself if it's not a reference, and the main branch
+    /// doesn't need to exist.
+    fn get_main_branch_reference_name(&self) -> eyre::Result<ReferenceName>;
+
+    /// Get the names of the references which track the main branch: the main
+    /// branch itself, and its upstream branch, if any. Returns nothing if the
+    /// main branch is pinned with `branchless.core.mainCommit` or doesn't
+    /// exist.
+    fn get_main_branch_tracking_reference_names(&self) -> eyre::Result<Vec<ReferenceName>>;
+
+    /// Get the OID corresponding to the main branch, or the commit that the
+    /// main branch is pinned to with `branchless.core.mainCommit`.
     fn get_main_branch_oid(&self) -> eyre::Result<NonZeroOid>;
 
     /// Get a mapping from OID to the names of branches which point to that OID.
@@ -39,6 +68,11 @@ pub trait RepoExt {
     /// be stripped if desired.
     fn get_branch_oid_to_names(&self) -> eyre::Result<HashMap<NonZeroOid, HashSet<ReferenceName>>>;
 
+    /// Get a mapping from OID to the names of tags which point to that OID.
+    /// Annotated tags are peeled to the commits they point to, and tags which
+    /// don't point to commits are omitted.
+    fn get_tag_oid_to_names(&self) -> eyre::Result<HashMap<NonZeroOid, HashSet<ReferenceName>>>;
+
     /// Get the positions of references in the repository.
     fn get_references_snapshot(&self) -> eyre::Result<RepoReferencesSnapshot>;
 
@@ -52,6 +86,10 @@ impl RepoExt for Repo {
         match self.find_branch(&main_branch_name, BranchType::Local)? {
             Some(branch) => Ok(branch),
             None => {
+                if let Some(branch) = find_renamed_main_branch(self, &main_branch_name)? {
+                    return Ok(branch);
+                }
+
                 let suggestion = format!(
                     r"
 The main branch {:?} could not be found in your repository
@@ -61,6 +99,10 @@ Either create it, or update the main branch setting by running:
 
     git branchless init --main-branch <branch>
 
+Or pin the main branch to a tag or other revision by running:
+
+    git config {MAIN_COMMIT_CONFIG_KEY} <revision>
+
 Note that remote main branches are no longer supported as of v0.6.0. See
 https://github.com/arxanas/git-branchless/discussions/595 for more details.",
                     get_main_branch_name(self)?,
@@ -82,8 +124,99 @@ https://github.com/arxanas/git-branchless/discussions/595 for more details.",
         }
     }
 
+    #[instrument]
+    fn detect_main_branch_from_remote_head(&self) -> eyre::Result<Option<String>> {
+        let mut remote_names = self.get_all_remote_names()?;
+        remote_names.sort_by_key(|remote_name| remote_name != "origin");
+        for remote_name in remote_names {
+            let remote_head_name = ReferenceName::from(format!("refs/remotes/{remote_name}/HEAD"));
+            let remote_head = match self.find_reference(&remote_head_name)? {
+                Some(remote_head) => remote_head,
+                None => continue,
+            };
+            let target_name = match self.resolve_reference(&remote_head)?.reference_name {
+                Some(target_name) => target_name,
+                None => continue,
+            };
+            if let Some(branch_name) = target_name
+                .as_str()
+                .strip_prefix(&format!("refs/remotes/{remote_name}/"))
+            {
+                if self.find_branch(branch_name, BranchType::Local)?.is_some() {
+                    return Ok(Some(branch_name.to_string()));
+                }
+            }
+        }
+        Ok(None)
+    }
+
+    fn get_main_branch_reference_name(&self) -> eyre::Result<ReferenceName> {
+        let main_commit_spec = match get_main_commit_spec(self)? {
+            Some(main_commit_spec) => main_commit_spec,
+            None => return Ok(self.get_main_branch()?.get_reference_name()?),
+        };
+
+        // Expand a short name, such as a tag name, into the fully-qualified
+        // name which reference updates are recorded under, using the same
+        // rules as `git rev-parse`. Anything else, such as a commit hash or
+        // `FETCH_HEAD`, is returned as-is.
+        let candidates = if main_commit_spec.starts_with("refs/") {
+            vec![main_commit_spec.clone()]
+        } else {
+            vec![
+                format!("refs/{main_commit_spec}"),
+                format!("refs/tags/{main_commit_spec}"),
+                format!("refs/heads/{main_commit_spec}"),
+                format!("refs/remotes/{main_commit_spec}"),
+                format!("refs/remotes/{main_commit_spec}/HEAD"),
+            ]
+        };
+        for candidate in candidates {
+            // Revisions such as `v1~2` don't form valid reference names.
+            if !git2::Reference::is_valid_name(&candidate) {
+                continue;
+            }
+            let reference_name = ReferenceName::from(candidate);
+            if self.find_reference(&reference_name)?.is_some() {
+                return Ok(reference_name);
+            }
+        }
+        Ok(ReferenceName::from(main_commit_spec))
+    }
+
+    fn get_main_branch_tracking_reference_names(&self) -> eyre::Result<Vec<ReferenceName>> {
+        if get_main_commit_spec(self)?.is_some() {
+            return Ok(Vec::new());
+        }
+        let main_branch = match self.find_branch(&get_main_branch_name(self)?, BranchType::Local)? {
+            Some(main_branch) => main_branch,
+            None => return Ok(Vec::new()),
+        };
+        let mut result = vec![main_branch.get_reference_name()?];
+        if let Some(upstream_branch) = main_branch.get_upstream_branch()? {
+            result.push(upstream_branch.get_reference_name()?);
+        }
+        Ok(result)
+    }
+
     #[instrument]
     fn get_main_branch_oid(&self) -> eyre::Result<NonZeroOid> {
+        if let Some(main_commit_spec) = get_main_commit_spec(self)? {
+            // Peel annotated tags to the commit they point to.
+            return match self.revparse_single_commit(&format!("{main_commit_spec}^{{commit}}"))? {
+                Some(commit) => Ok(commit.get_oid()),
+                None => Err(
+                    eyre::eyre!("Could not find main commit: {main_commit_spec:?}").with_suggestion(
+                        || {
+                            format!(
+                                "Update the {MAIN_COMMIT_CONFIG_KEY} setting, or unset it to use the main branch instead."
+                            )
+                        },
+                    ),
+                ),
+            };
+        }
+
         let main_branch = self.get_main_branch()?;
         let main_branch_oid = main_branch.get_oid()?;
         match main_branch_oid {
@@ -113,20 +246,38 @@ https://github.com/arxanas/git-branchless/discussions/595 for more details.",
         Ok(result)
     }
 
+    #[instrument]
+    fn get_tag_oid_to_names(&self) -> eyre::Result<HashMap<NonZeroOid, HashSet<ReferenceName>>> {
+        let mut result: HashMap<NonZeroOid, HashSet<ReferenceName>> = HashMap::new();
+        for reference in self.get_references_glob("refs/tags/*")? {
+            let reference_name = reference.get_name()?;
+            if let Some(commit) = reference.peel_to_commit()? {
+                result
+                    .entry(commit.get_oid())
+                    .or_default()
+                    .insert(reference_name);
+            }
+        }
+
+        Ok(result)
+    }
+
     fn get_references_snapshot(&self) -> eyre::Result<RepoReferencesSnapshot> {
         let head_oid = self.get_head_info()?.oid;
         let main_branch_oid = self.get_main_branch_oid()?;
         let branch_oid_to_names = self.get_branch_oid_to_names()?;
+        let tag_oid_to_names = self.get_tag_oid_to_names()?;
 
         Ok(RepoReferencesSnapshot {
             head_oid,
             main_branch_oid,
             branch_oid_to_names,
+            tag_oid_to_names,
         })
     }
 
     fn get_default_push_remote(&self) -> eyre::Result<Option<String>> {
-        let main_branch_name = self.get_main_branch()?.get_reference_name()?;
+        let main_branch_name = self.get_main_branch_reference_name()?;
         match CategorizedReferenceName::new(&main_branch_name) {
             name @ CategorizedReferenceName::LocalBranch { .. } => {
                 if let Some(main_branch) =
@@ -145,7 +296,7 @@ https://github.com/arxanas/git-branchless/discussions/595 for more details.",
                 }
             }
 
-            CategorizedReferenceName::OtherRef { .. } => {
+            CategorizedReferenceName::Tag { .. } | CategorizedReferenceName::OtherRef { .. } => {
                 // Do nothing.
             }
         }
@@ -154,3 +305,32 @@ https://github.com/arxanas/git-branchless/discussions/595 for more details.",
         Ok(push_default_remote_opt)
     }
 }
+
+/// Called when the configured main branch could not be found. If a remote's
+/// default branch exists locally, then assume that the main branch was renamed
+/// upstream and use it instead.
+///
+/// The main branch setting isn't changed here, since this is called by
+/// read-only operations. It's updated by `git branchless init --main-branch`.
+#[instrument]
+fn find_renamed_main_branch(
+    repo: &Repo,
+    old_main_branch_name: &str,
+) -> eyre::Result<Option<Branch>> {
+    let new_main_branch_name = match repo.detect_main_branch_from_remote_head()? {
+        Some(name) if name != old_main_branch_name => name,
+        Some(_) | None => return Ok(None),
+    };
+    let branch = match repo.find_branch(&new_main_branch_name, BranchType::Local)? {
+        Some(branch) => branch,
+        None => return Ok(None),
+    };
+
+    warn!(
+        ?old_main_branch_name,
+        ?new_main_branch_name,
+        "Main branch not found; using the remote's default branch instead. \
+Run `git branchless init --main-branch` to update the main branch setting."
+    );
+    Ok(Some(branch))
+}
@@ -0,0 +1,133 @@
+//! Keep track of the commit being edited with `git branchless edit`.
+//!
+//! While a commit is being edited, amending it restacks its descendants
+//! automatically, and `git next` restacks any descendants which were left
+//! behind before moving onto them.
+
+use std::collections::HashMap;
+use std::fs;
+use std::io;
+use std::path::PathBuf;
+
+use eyre::Context;
+use tracing::instrument;
+
+use crate::git::{MaybeZeroOid, NonZeroOid, Repo};
+
+/// The commit being edited with `git branchless edit`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct EditState {
+    /// The commit as it was when the edit started. Its descendants are the
+    /// ones to restack.
+    pub original_commit_oid: NonZeroOid,
+
+    /// The newest version of the commit, after any amends.
+    pub current_commit_oid: NonZeroOid,
+}
+
+fn get_edit_state_path(repo: &Repo) -> eyre::Result<PathBuf> {
+    Ok(repo.get_branchless_dir()?.join("edit-state"))
+}
+
+/// Get the commit currently being edited, if any.
+#[instrument]
+pub fn read_edit_state(repo: &Repo) -> eyre::Result<Option<EditState>> {
+    let edit_state_path = get_edit_state_path(repo)?;
+    let contents = match fs::read_to_string(&edit_state_path) {
+        Ok(contents) => contents,
+        Err(err) if err.kind() == io::ErrorKind::NotFound => return Ok(None),
+        Err(err) => {
+            return Err(err).with_context(|| format!("Reading edit state at {edit_state_path:?}"))
+        }
+    };
+    match contents.lines().collect::<Vec<_>>().as_slice() {
+        [original_commit_oid, current_commit_oid] => Ok(Some(EditState {
+            original_commit_oid: original_commit_oid.parse()?,
+            current_commit_oid: current_commit_oid.parse()?,
+        })),
+        _ => eyre::bail!("Invalid edit state at {edit_state_path:?}: {contents:?}"),
+    }
+}
+
+/// Record that the given commit is being edited.
+#[instrument]
+pub fn write_edit_state(repo: &Repo, edit_state: &EditState) -> eyre::Result<()> {
+    let edit_state_path = get_edit_state_path(repo)?;
+    let EditState {
+        original_commit_oid,
+        current_commit_oid,
+    } = edit_state;
+    fs::write(
+        &edit_state_path,
+        format!("{original_commit_oid}\n{current_commit_oid}\n"),
+    )
+    .with_context(|| format!("Writing edit state to {edit_state_path:?}"))?;
+    Ok(())
+}
+
+/// Stop editing the current commit, if any.
+#[instrument]
+pub fn clear_edit_state(repo: &Repo) -> eyre::Result<()> {
+    let edit_state_path = get_edit_state_path(repo)?;
+    match fs::remove_file(&edit_state_path) {
+        Ok(()) => Ok(()),
+        Err(err) if err.kind() == io::ErrorKind::NotFound => Ok(()),
+        Err(err) => Err(err).with_context(|| format!("Removing edit state at {edit_state_path:?}")),
+    }
+}
+
+/// Stop editing the current commit if `HEAD` was moved away from its stack,
+/// i.e. to a commit which isn't the commit being edited or one of the
+/// descendants of it or of its original version.
+#[instrument]
+pub fn clear_edit_state_if_head_left_stack(
+    repo: &Repo,
+    head_oid: MaybeZeroOid,
+) -> eyre::Result<()> {
+    let EditState {
+        original_commit_oid,
+        current_commit_oid,
+    } = match read_edit_state(repo)? {
+        Some(edit_state) => edit_state,
+        None => return Ok(()),
+    };
+    if let MaybeZeroOid::NonZero(head_oid) = head_oid {
+        for commit_oid in [current_commit_oid, original_commit_oid] {
+            if repo.find_merge_base(commit_oid, head_oid)? == Some(commit_oid) {
+                return Ok(());
+            }
+        }
+    }
+    clear_edit_state(repo)
+}
+
+/// If the commit being edited was rewritten, then keep track of its new
+/// version. Returns whether it was rewritten, in which case its descendants
+/// should be restacked.
+#[instrument(skip(rewritten_oids))]
+pub fn update_edit_state(
+    repo: &Repo,
+    rewritten_oids: &HashMap<NonZeroOid, MaybeZeroOid>,
+) -> eyre::Result<bool> {
+    let edit_state = match read_edit_state(repo)? {
+        Some(edit_state) => edit_state,
+        None => return Ok(false),
+    };
+    match rewritten_oids.get(&edit_state.current_commit_oid) {
+        Some(MaybeZeroOid::NonZero(new_commit_oid)) => {
+            write_edit_state(
+                repo,
+                &EditState {
+                    current_commit_oid: *new_commit_oid,
+                    ..edit_state
+                },
+            )?;
+            Ok(true)
+        }
+        Some(MaybeZeroOid::Zero) => {
+            clear_edit_state(repo)?;
+            Ok(false)
+        }
+        None => Ok(false),
+    }
+}
@@ -1,8 +1,12 @@
+use std::collections::{HashMap, HashSet};
+
 use tracing::instrument;
 
 use crate::core::dag::{CommitSet, Dag};
+use crate::core::effects::{Effects, OperationType};
 use crate::core::eventlog::{Event, EventCursor, EventReplayer};
-use crate::git::{MaybeZeroOid, NonZeroOid};
+use crate::core::repo_ext::RepoExt;
+use crate::git::{MaybeZeroOid, NonZeroOid, PatchId, Repo};
 
 /// For a rewritten commit, find the newest version of the commit.
 ///
@@ -57,7 +61,8 @@ pub fn find_rewrite_target(
         | Event::CommitEvent { .. }
         | Event::ObsoleteEvent { .. }
         | Event::UnobsoleteEvent { .. }
-        | Event::WorkingCopySnapshot { .. } => None,
+        | Event::WorkingCopySnapshot { .. }
+        | Event::CopyEvent { .. } => None,
     }
 }
 
@@ -84,3 +89,152 @@ pub fn find_abandoned_children(
 
     Ok(Some((rewritten_oid, non_obsolete_children_oids)))
 }
+
+/// Find the draft commits among `commits` which have already been applied to
+/// the main branch, as determined by their patch IDs. Returns each such commit
+/// along with the main branch commit which has the same patch ID.
+///
+/// Only the main branch commits which aren't ancestors of `commits` are
+/// considered, such as the commits which landed since the last fetch.
+#[instrument(skip(commits))]
+pub fn find_upstream_applied_commits(
+    effects: &Effects,
+    repo: &Repo,
+    dag: &Dag,
+    commits: CommitSet,
+) -> eyre::Result<Vec<(NonZeroOid, NonZeroOid)>> {
+    let (effects, _progress) = effects.start_operation(OperationType::DetectDuplicateCommits);
+    let commits = commits.difference(dag.query_public_commits_slow()?);
+    let upstream_commits = dag.query_only(dag.main_branch_commit.clone(), commits.clone())?;
+    if dag.set_is_empty(&commits)? || dag.set_is_empty(&upstream_commits)? {
+        return Ok(Vec::new());
+    }
+
+    // Save time by only calculating the patch IDs of commits which touch some
+    // of the same paths, since there may be many commits on either side.
+    let mut upstream_touched_paths = Vec::new();
+    for upstream_oid in dag.commit_set_to_vec(&upstream_commits)? {
+        let upstream_commit = repo.find_commit_or_fail(upstream_oid)?;
+        let touched_paths = repo.get_paths_touched_by_commit(&upstream_commit)?;
+        upstream_touched_paths.push((upstream_commit, touched_paths));
+    }
+    let all_upstream_touched_paths: HashSet<_> = upstream_touched_paths
+        .iter()
+        .flat_map(|(_commit, touched_paths)| touched_paths.iter().cloned())
+        .collect();
+
+    let mut local_patch_ids: HashMap<PatchId, Vec<NonZeroOid>> = HashMap::new();
+    let mut all_local_touched_paths = HashSet::new();
+    for commit_oid in dag.commit_set_to_vec(&commits)? {
+        let commit = repo.find_commit_or_fail(commit_oid)?;
+        let touched_paths = repo.get_paths_touched_by_commit(&commit)?;
+        if touched_paths.is_disjoint(&all_upstream_touched_paths) {
+            continue;
+        }
+        if let Some(patch_id) = repo.get_patch_id(&effects, &commit)? {
+            local_patch_ids
+                .entry(patch_id)
+                .or_default()
+                .push(commit_oid);
+            all_local_touched_paths.extend(touched_paths);
+        }
+    }
+
+    let mut result = Vec::new();
+    for (upstream_commit, touched_paths) in upstream_touched_paths {
+        if local_patch_ids.is_empty() {
+            break;
+        }
+        if touched_paths.is_disjoint(&all_local_touched_paths) {
+            continue;
+        }
+        if let Some(patch_id) = repo.get_patch_id(&effects, &upstream_commit)? {
+            if let Some(commit_oids) = local_patch_ids.remove(&patch_id) {
+                let upstream_oid = upstream_commit.get_oid();
+                result.extend(
+                    commit_oids
+                        .into_iter()
+                        .map(|commit_oid| (commit_oid, upstream_oid)),
+                );
+            }
+        }
+    }
+    result.sort_unstable();
+    Ok(result)
+}
+
+/// Whether moving a reference from `old_oid` to `new_oid` discards commits,
+/// such as when a branch is force-pushed after its history was rewritten.
+#[instrument]
+pub fn is_non_fast_forward(
+    repo: &Repo,
+    old_oid: NonZeroOid,
+    new_oid: NonZeroOid,
+) -> eyre::Result<bool> {
+    Ok(old_oid != new_oid && repo.find_merge_base(old_oid, new_oid)? != Some(old_oid))
+}
+
+/// Find the most recent non-fast-forward update of the main branch or its
+/// upstream branch in `events`, such as one caused by fetching after the
+/// upstream main branch was force-pushed. Returns the OIDs of the main branch
+/// before and after the update. Updates whose old commit no longer exists are
+/// skipped.
+#[instrument(skip(events))]
+pub fn find_main_branch_rewrite(
+    repo: &Repo,
+    events: &[Event],
+) -> eyre::Result<Option<(NonZeroOid, NonZeroOid)>> {
+    let main_branch_reference_names = repo.get_main_branch_tracking_reference_names()?;
+    for event in events.iter().rev() {
+        if let Event::RefUpdateEvent {
+            timestamp: _,
+            event_tx_id: _,
+            ref_name,
+            old_oid: MaybeZeroOid::NonZero(old_oid),
+            new_oid: MaybeZeroOid::NonZero(new_oid),
+            message: _,
+        } = event
+        {
+            if main_branch_reference_names.contains(ref_name)
+                && repo.find_commit(*old_oid)?.is_some()
+                && repo.find_commit(*new_oid)?.is_some()
+                && is_non_fast_forward(repo, *old_oid, *new_oid)?
+            {
+                return Ok(Some((*old_oid, *new_oid)));
+            }
+        }
+    }
+    Ok(None)
+}
+
+/// Find the commits in `new_commits` which are equivalent to the commits in
+/// `old_commits`, as determined by their patch IDs. Returns a mapping from the
+/// old commit to its equivalent new commit.
+#[instrument]
+pub fn find_equivalent_commits(
+    effects: &Effects,
+    repo: &Repo,
+    dag: &Dag,
+    old_commits: &CommitSet,
+    new_commits: &CommitSet,
+) -> eyre::Result<HashMap<NonZeroOid, NonZeroOid>> {
+    let (effects, _progress) = effects.start_operation(OperationType::DetectDuplicateCommits);
+    let mut new_patch_ids: HashMap<PatchId, NonZeroOid> = HashMap::new();
+    for new_oid in dag.commit_set_to_vec(new_commits)? {
+        let new_commit = repo.find_commit_or_fail(new_oid)?;
+        if let Some(patch_id) = repo.get_patch_id(&effects, &new_commit)? {
+            new_patch_ids.entry(patch_id).or_insert(new_oid);
+        }
+    }
+
+    let mut result = HashMap::new();
+    for old_oid in dag.commit_set_to_vec(old_commits)? {
+        let old_commit = repo.find_commit_or_fail(old_oid)?;
+        if let Some(patch_id) = repo.get_patch_id(&effects, &old_commit)? {
+            if let Some(new_oid) = new_patch_ids.get(&patch_id) {
+                result.insert(old_oid, *new_oid);
+            }
+        }
+    }
+    Ok(result)
+}
@@ -1,6 +1,7 @@
 use std::collections::{HashMap, HashSet};
 
 use std::fmt::Write;
+use std::io::BufRead;
 use std::path::PathBuf;
 use std::time::SystemTime;
 
@@ -8,18 +9,47 @@ use bstr::BString;
 use eyre::Context;
 use tracing::warn;
 
-use crate::core::check_out::{check_out_commit, CheckOutCommitOptions, CheckoutTarget};
+use crate::core::check_out::{
+    carry_snapshot_changes, check_out_commit, create_snapshot, restore_snapshot,
+    write_carried_changes, CheckOutCommitOptions, CheckoutTarget,
+};
+use crate::core::config::get_auto_stash;
 use crate::core::effects::Effects;
 use crate::core::eventlog::{EventLogDb, EventTransactionId};
 use crate::core::formatting::Pluralize;
+use crate::core::gc::mark_commit_reachable;
 use crate::core::repo_ext::RepoExt;
 use crate::git::{
-    BranchType, CategorizedReferenceName, GitRunInfo, MaybeZeroOid, NonZeroOid, ReferenceName,
-    Repo, ResolvedReferenceInfo,
+    fetch_missing_objects, BranchType, CategorizedReferenceName, Commit, GitRunInfo, MaybeZeroOid,
+    NonZeroOid, ReferenceName, Repo, ResolvedReferenceInfo, WorkingCopySnapshot,
 };
 use crate::util::{ExitCode, EyreExitOr};
 
-use super::plan::RebasePlan;
+use super::interrupted::{InterruptedRebase, InterruptedRebaseDb, InterruptedRebaseStage};
+use super::plan::{OidOrLabel, RebaseCommand, RebasePlan};
+use super::rewrite_hooks::lint_commit_messages;
+
+/// The commit message trailer which marks a commit as intentionally empty, so
+/// that it's not dropped when it's rebased.
+const KEEP_EMPTY_TRAILER: (&str, &str) = ("branchless", "keep-empty");
+
+/// Determine whether the given commit should be kept even though rebasing it
+/// produced an empty commit. Commits which were already applied upstream are
+/// skipped by the rebase plan before getting here.
+pub(crate) fn should_keep_empty_commit(
+    commit: &Commit,
+    keep_empty_commits: bool,
+) -> eyre::Result<bool> {
+    if keep_empty_commits {
+        return Ok(true);
+    }
+    let (trailer_key, trailer_value) = KEEP_EMPTY_TRAILER;
+    let is_marked = commit
+        .get_trailers()?
+        .iter()
+        .any(|(key, value)| key.eq_ignore_ascii_case(trailer_key) && value.trim() == trailer_value);
+    Ok(is_marked)
+}
 
 /// Given a list of rewritten OIDs, move the branches attached to those OIDs
 /// from their old commits to their new commits. Invoke the
@@ -31,8 +61,7 @@ pub fn move_branches<'a>(
     event_tx_id: EventTransactionId,
     rewritten_oids_map: &'a HashMap<NonZeroOid, MaybeZeroOid>,
 ) -> eyre::Result<()> {
-    let main_branch = repo.get_main_branch()?;
-    let main_branch_name = main_branch.get_reference_name()?;
+    let main_branch_name = repo.get_main_branch_reference_name()?;
     let branch_oid_to_names = repo.get_branch_oid_to_names()?;
 
     // We may experience an error in the case of a branch move. Ideally, we
@@ -86,6 +115,7 @@ pub fn move_branches<'a>(
                         // skipped. For a regular branch, we would delete the branch, but for the
                         // main branch, we should update it to point directly to the upstream
                         // version.
+                        let main_branch = repo.get_main_branch()?;
                         let target_oid = match main_branch.get_upstream_branch_target()? {
                             Some(target_oid) => {
                                 if let Err(err) = repo.create_reference(
@@ -100,8 +130,7 @@ pub fn move_branches<'a>(
                                 MaybeZeroOid::NonZero(target_oid)
                             }
                             None => {
-                                let mut main_branch_reference =
-                                    repo.get_main_branch()?.into_reference();
+                                let mut main_branch_reference = main_branch.into_reference();
                                 if let Err(err) = main_branch_reference.delete() {
                                     branch_move_err = Some(eyre::eyre!(err));
                                     break 'outer;
@@ -114,6 +143,7 @@ pub fn move_branches<'a>(
                         let branch_name = CategorizedReferenceName::new(reference_name);
                         match branch_name {
                             CategorizedReferenceName::RemoteBranch { .. }
+                            | CategorizedReferenceName::Tag { .. }
                             | CategorizedReferenceName::OtherRef { .. } => {
                                 warn!(?reference_name, "Not deleting non-local-branch reference");
                             }
@@ -327,6 +357,10 @@ pub enum MergeConflictRemediation {
 
     /// Indicate that the user should run `git move -m -s 'siblings(.)'`.
     Insert,
+
+    /// Indicate that the user should run `git sync --merge` before retrying
+    /// the operation.
+    Sync,
 }
 
 /// Information about a failure to merge that occurred while moving commits.
@@ -415,25 +449,52 @@ impl FailedMergeInfo {
                     "To resolve merge conflicts, run: git move -m -s 'siblings(.)'"
                 )?;
             }
+            MergeConflictRemediation::Sync => {
+                writeln!(
+                    effects.get_output_stream(),
+                    "To resolve merge conflicts, run: git sync --merge"
+                )?;
+                writeln!(effects.get_output_stream(), "Then retry this operation.")?;
+            }
         }
 
         Ok(())
     }
 }
 
+/// The identifiers of the steps of the given rebase plan, as reported with
+/// [`StepEvent::Planned`](crate::core::effects::StepEvent::Planned).
+fn get_planned_steps(rebase_plan: &RebasePlan) -> Vec<String> {
+    rebase_plan
+        .commands
+        .iter()
+        .filter_map(|command| match command {
+            RebaseCommand::Pick {
+                original_commit_oid,
+                commits_to_apply_oids: _,
+            } => Some(original_commit_oid.to_string()),
+            _ => None,
+        })
+        .collect()
+}
+
 mod in_memory {
     use std::collections::HashMap;
     use std::fmt::Write;
 
-    use bstr::{BString, ByteSlice};
+    use bstr::ByteSlice;
     use eyre::Context;
+    use itertools::Itertools;
     use tracing::{instrument, warn};
 
-    use crate::core::effects::{Effects, OperationIcon, OperationType};
+    use crate::core::config::get_restack_keep_empty_commits;
+    use crate::core::effects::{Effects, OperationIcon, OperationType, StepEvent};
     use crate::core::eventlog::EventLogDb;
     use crate::core::gc::mark_commit_reachable;
-    use crate::core::rewrite::execute::check_out_updated_head;
-    use crate::core::rewrite::move_branches;
+    use crate::core::repo_ext::RepoExt;
+    use crate::core::rewrite::interrupted::{
+        continue_interrupted_rebase, InterruptedRebase, InterruptedRebaseDb, InterruptedRebaseStage,
+    };
     use crate::core::rewrite::plan::{OidOrLabel, RebaseCommand, RebasePlan};
     use crate::git::{
         AmendFastOptions, CherryPickFastOptions, CreateCommitFastError, GitRunInfo, MaybeZeroOid,
@@ -441,7 +502,9 @@ mod in_memory {
     };
     use crate::util::EyreExitOr;
 
-    use super::{ExecuteRebasePlanOptions, FailedMergeInfo};
+    use super::{
+        get_planned_steps, should_keep_empty_commit, ExecuteRebasePlanOptions, FailedMergeInfo,
+    };
 
     pub enum RebaseInMemoryResult {
         Succeeded {
@@ -454,7 +517,30 @@ mod in_memory {
             /// caller will need to figure that out.
             new_head_oid: Option<NonZeroOid>,
         },
-        MergeFailed(FailedMergeInfo),
+        /// The rebase failed. If it failed due to a merge conflict, then the
+        /// progress made so far is also returned, so that the rest of the
+        /// rebase can be carried out on-disk.
+        MergeFailed(FailedMergeInfo, Option<PartialRebase>),
+
+        /// Some objects needed for the rebase are missing from the local
+        /// object database, such as in a partial clone. They need to be
+        /// fetched before retrying the rebase.
+        MissingObjects { oids: Vec<NonZeroOid> },
+    }
+
+    /// The state of an in-memory rebase which stopped partway through.
+    pub struct PartialRebase {
+        /// The index of the command in the rebase plan which failed.
+        pub command_index: usize,
+
+        /// The rebase head at the time of the failure.
+        pub current_oid: NonZeroOid,
+
+        /// The labels created so far.
+        pub labels: HashMap<String, NonZeroOid>,
+
+        /// The commits rewritten so far.
+        pub rewritten_oids: HashMap<NonZeroOid, MaybeZeroOid>,
     }
 
     #[instrument]
@@ -487,6 +573,7 @@ mod in_memory {
                 FailedMergeInfo::CannotRebaseMergeInMemory {
                     commit_oid: *merge_commit_oid,
                 },
+                None,
             ));
         }
 
@@ -519,6 +606,7 @@ mod in_memory {
             }
         };
 
+        let keep_empty_commits = get_restack_keep_empty_commits(repo)?;
         let mut i = 0;
         let num_picks = rebase_plan
             .commands
@@ -536,8 +624,12 @@ mod in_memory {
             })
             .count();
         let (effects, progress) = effects.start_operation(OperationType::RebaseCommits);
+        effects.report_step(StepEvent::Planned {
+            operation: "rebase",
+            steps: get_planned_steps(rebase_plan),
+        });
 
-        for command in rebase_plan.commands.iter() {
+        for (command_index, command) in rebase_plan.commands.iter().enumerate() {
             match command {
                 RebaseCommand::CreateLabel { label_name } => {
                     labels.insert(label_name.clone(), current_oid);
@@ -584,6 +676,10 @@ mod in_memory {
 
                     let commit_num = format!("[{i}/{num_picks}]");
                     progress.notify_progress(i, num_picks);
+                    effects.report_step(StepEvent::Started {
+                        operation: "rebase",
+                        step: original_commit_oid.to_string(),
+                    });
 
                     let commit_message = original_commit.get_message_raw();
                     let commit_message = commit_message.to_str().with_context(|| {
@@ -619,6 +715,7 @@ mod in_memory {
                                 FailedMergeInfo::CannotRebaseMergeInMemory {
                                     commit_oid: *commit_oid,
                                 },
+                                None,
                             ));
                         };
 
@@ -651,12 +748,30 @@ mod in_memory {
                         let commit_tree = match maybe_tree {
                             Ok(tree) => tree,
                             Err(CreateCommitFastError::MergeConflict { conflicting_paths }) => {
+                                effects.report_step(StepEvent::Conflicted {
+                                    operation: "rebase",
+                                    step: original_commit_oid.to_string(),
+                                    paths: conflicting_paths
+                                        .iter()
+                                        .map(|path| path.to_string_lossy().into_owned())
+                                        .sorted()
+                                        .collect(),
+                                });
                                 return Ok(RebaseInMemoryResult::MergeFailed(
                                     FailedMergeInfo::Conflict {
                                         commit_oid: *commit_oid,
                                         conflicting_paths,
                                     },
-                                ))
+                                    Some(PartialRebase {
+                                        command_index,
+                                        current_oid,
+                                        labels,
+                                        rewritten_oids,
+                                    }),
+                                ));
+                            }
+                            Err(CreateCommitFastError::MissingObjects { oids }) => {
+                                return Ok(RebaseInMemoryResult::MissingObjects { oids })
                             }
                             Err(other) => eyre::bail!(other),
                         };
@@ -696,6 +811,7 @@ mod in_memory {
                     if rebased_commit
                         .expect("rebased commit should not be None")
                         .is_empty()
+                        && !should_keep_empty_commit(&original_commit, keep_empty_commits)?
                     {
                         rewritten_oids.insert(*original_commit_oid, MaybeZeroOid::Zero);
                         maybe_set_skipped_head_new_oid(*original_commit_oid, current_oid);
@@ -704,6 +820,11 @@ mod in_memory {
                             effects.get_output_stream(),
                             "{commit_num} Skipped now-empty commit: {commit_description}",
                         )?;
+                        effects.report_step(StepEvent::Finished {
+                            operation: "rebase",
+                            step: original_commit_oid.to_string(),
+                            status: "skipped".to_string(),
+                        });
                     } else {
                         rewritten_oids.insert(
                             *original_commit_oid,
@@ -720,6 +841,11 @@ mod in_memory {
                             effects.get_output_stream(),
                             "{commit_num} Committed as: {commit_description}"
                         )?;
+                        effects.report_step(StepEvent::Finished {
+                            operation: "rebase",
+                            step: original_commit_oid.to_string(),
+                            status: "committed".to_string(),
+                        });
                     }
                 }
 
@@ -735,6 +861,7 @@ mod in_memory {
                         FailedMergeInfo::CannotRebaseMergeInMemory {
                             commit_oid: *commit_oid,
                         },
+                        None,
                     ));
                 }
 
@@ -906,7 +1033,7 @@ mod in_memory {
         let ExecuteRebasePlanOptions {
             now: _,
             event_tx_id,
-            preserve_timestamps: _,
+            preserve_timestamps,
             force_in_memory: _,
             force_on_disk: _,
             resolve_merge_conflicts: _,
@@ -919,62 +1046,58 @@ mod in_memory {
             }
         }
 
-        let head_info = repo.get_head_info()?;
-        if head_info.oid.is_some() {
-            // Avoid moving the branch which HEAD points to, or else the index will show
-            // a lot of changes in the working copy.
-            repo.detach_head(&head_info)?;
-        }
-
-        move_branches(effects, git_run_info, repo, *event_tx_id, rewritten_oids)?;
-
-        // Call the `post-rewrite` hook only after moving branches so that we don't
-        // produce a spurious abandoned-branch warning.
-        #[allow(clippy::format_collect)]
-        let post_rewrite_stdin: String = rewritten_oids
-            .iter()
-            .map(|(old_oid, new_oid)| format!("{old_oid} {new_oid}\n"))
+        let original_branches = repo
+            .get_branch_oid_to_names()?
+            .into_iter()
+            .filter(|(oid, _names)| rewritten_oids.contains_key(oid))
+            .flat_map(|(oid, names)| names.into_iter().map(move |name| (name, oid)))
             .collect();
-        let post_rewrite_stdin = BString::from(post_rewrite_stdin);
-        git_run_info.run_hook(
-            effects,
-            repo,
-            "post-rewrite",
-            *event_tx_id,
-            &["rebase"],
-            Some(post_rewrite_stdin),
-        )?;
+        let interrupted_rebase = InterruptedRebase {
+            event_tx_id: *event_tx_id,
+            rebase_plan: None,
+            preserve_timestamps: *preserve_timestamps,
+            head_info: repo.get_head_info()?,
+            skipped_head_updated_oid,
+            rewritten_oids: rewritten_oids.clone(),
+            original_branches,
+            stage: InterruptedRebaseStage::MoveBranches,
+        };
 
-        let exit_code = check_out_updated_head(
+        // Record the rewritten commits before updating the repository, so that
+        // the rebase can be continued or aborted if it's interrupted.
+        let conn = repo.get_db_conn()?;
+        let interrupted_rebase_db = InterruptedRebaseDb::new(&conn)?;
+        interrupted_rebase_db.save(&interrupted_rebase)?;
+        continue_interrupted_rebase(
             effects,
             git_run_info,
             repo,
             event_log_db,
-            *event_tx_id,
-            rewritten_oids,
-            &head_info,
-            skipped_head_updated_oid,
+            &interrupted_rebase_db,
+            &interrupted_rebase,
             check_out_commit_options,
-        )?;
-        Ok(exit_code)
+        )
     }
 }
 
 mod on_disk {
+    use std::collections::HashMap;
     use std::fmt::Write;
 
     use eyre::Context;
     use tracing::instrument;
 
-    use crate::core::effects::{Effects, OperationType};
+    use itertools::Itertools;
+
+    use crate::core::effects::{Effects, OperationType, StepEvent};
     use crate::core::rewrite::plan::RebaseCommand;
     use crate::core::rewrite::plan::RebasePlan;
     use crate::core::rewrite::rewrite_hooks::save_original_head_info;
-    use crate::git::{GitRunInfo, Repo};
+    use crate::git::{GitRunInfo, MaybeZeroOid, NonZeroOid, ReferenceName, Repo};
 
     use crate::util::ExitCode;
 
-    use super::ExecuteRebasePlanOptions;
+    use super::{get_planned_steps, ExecuteRebasePlanOptions};
 
     pub enum Error {
         ChangedFilesInRepository,
@@ -1150,9 +1273,44 @@ mod on_disk {
         Ok(Ok(()))
     }
 
+    /// Record commits which were already rewritten before the on-disk rebase
+    /// started, such as by an in-memory rebase which stopped partway through.
+    /// Git appends to this file as it rewrites commits, and passes its
+    /// contents to the `post-rewrite` hook at the end of the rebase.
+    fn write_rewritten_list(
+        repo: &Repo,
+        rewritten_oids: &HashMap<NonZeroOid, MaybeZeroOid>,
+    ) -> eyre::Result<()> {
+        if rewritten_oids.is_empty() {
+            return Ok(());
+        }
+        let mut rewritten_oids: Vec<_> = rewritten_oids.iter().collect();
+        rewritten_oids.sort();
+        let rewritten_list_file_path = repo.get_rebase_state_dir_path().join("rewritten-list");
+        #[allow(clippy::format_collect)]
+        std::fs::write(
+            &rewritten_list_file_path,
+            rewritten_oids
+                .into_iter()
+                .map(|(old_oid, new_oid)| format!("{old_oid} {new_oid}\n"))
+                .collect::<String>(),
+        )
+        .wrap_err_with(|| {
+            format!(
+                "Writing `rewritten-list` to: {:?}",
+                &rewritten_list_file_path
+            )
+        })?;
+        Ok(())
+    }
+
     /// Rebase on-disk. We don't use `git2`'s `Rebase` machinery because it ends up
     /// being too slow.
     ///
+    /// `rewritten_oids` contains any commits which were already rewritten
+    /// before the rebase, which are reported along with the commits rewritten
+    /// by the rebase itself.
+    ///
     /// Note that this calls `git rebase`, which may fail (e.g. if there are
     /// merge conflicts). The exit code is then propagated to the caller.
     #[instrument]
@@ -1161,6 +1319,7 @@ mod on_disk {
         git_run_info: &GitRunInfo,
         repo: &Repo,
         rebase_plan: &RebasePlan,
+        rewritten_oids: &HashMap<NonZeroOid, MaybeZeroOid>,
         options: &ExecuteRebasePlanOptions,
     ) -> eyre::Result<Result<ExitCode, Error>> {
         let ExecuteRebasePlanOptions {
@@ -1178,15 +1337,81 @@ mod on_disk {
             Ok(()) => {}
             Err(err) => return Ok(Err(err)),
         };
+        write_rewritten_list(repo, rewritten_oids)?;
 
+        let steps = get_planned_steps(rebase_plan);
+        effects.report_step(StepEvent::Planned {
+            operation: "rebase",
+            steps: steps.clone(),
+        });
         writeln!(
             effects.get_output_stream(),
             "Calling Git for on-disk rebase..."
         )?;
         match git_run_info.run(effects, Some(*event_tx_id), &["rebase", "--continue"])? {
-            Ok(()) => Ok(Ok(ExitCode::success())),
-            Err(err) => Ok(Ok(err)),
+            Ok(()) => {
+                // Git carries out the steps itself, so they can only be
+                // reported once it's finished all of them.
+                for step in steps {
+                    effects.report_step(StepEvent::Finished {
+                        operation: "rebase",
+                        step,
+                        status: "committed".to_string(),
+                    });
+                }
+                Ok(Ok(ExitCode::success()))
+            }
+            Err(err) => {
+                report_on_disk_conflict(effects, repo, rebase_plan)?;
+                Ok(Ok(err))
+            }
+        }
+    }
+
+    /// If the on-disk rebase stopped because of merge conflicts, report the
+    /// step which it stopped at.
+    fn report_on_disk_conflict(
+        effects: &Effects,
+        repo: &Repo,
+        rebase_plan: &RebasePlan,
+    ) -> eyre::Result<()> {
+        let index = repo.get_index()?;
+        if !index.has_conflicts() {
+            return Ok(());
         }
+        let rebase_head_oid = match repo.find_reference(&ReferenceName::from("REBASE_HEAD"))? {
+            Some(reference) => match reference.peel_to_commit()? {
+                Some(commit) => commit.get_oid(),
+                None => return Ok(()),
+            },
+            None => return Ok(()),
+        };
+        let step = rebase_plan
+            .commands
+            .iter()
+            .find_map(|command| match command {
+                RebaseCommand::Pick {
+                    original_commit_oid,
+                    commits_to_apply_oids,
+                } if *original_commit_oid == rebase_head_oid
+                    || commits_to_apply_oids.contains(&rebase_head_oid) =>
+                {
+                    Some(*original_commit_oid)
+                }
+                _ => None,
+            })
+            .unwrap_or(rebase_head_oid);
+        effects.report_step(StepEvent::Conflicted {
+            operation: "rebase",
+            step: step.to_string(),
+            paths: index
+                .get_conflicting_paths()?
+                .iter()
+                .map(|path| path.to_string_lossy().into_owned())
+                .sorted()
+                .collect(),
+        });
+        Ok(())
     }
 }
 
@@ -1243,8 +1468,177 @@ pub enum ExecuteRebasePlanResult {
     },
 }
 
+/// Offer to resolve the merge conflict which stopped an in-memory rebase in
+/// the working copy instead, reading the user's answer from `input`.
+fn confirm_resolve_in_working_copy(
+    effects: &Effects,
+    repo: &Repo,
+    failed_merge_info: &FailedMergeInfo,
+    input: &mut dyn BufRead,
+) -> eyre::Result<bool> {
+    let commit_oid = match failed_merge_info {
+        FailedMergeInfo::Conflict {
+            commit_oid,
+            conflicting_paths: _,
+        } => *commit_oid,
+        FailedMergeInfo::CannotRebaseMergeInMemory { commit_oid: _ } => return Ok(false),
+    };
+
+    writeln!(
+        effects.get_output_stream(),
+        "Merge conflict while applying: {}",
+        effects
+            .get_glyphs()
+            .render(repo.friendly_describe_commit_from_oid(effects.get_glyphs(), commit_oid)?)?
+    )?;
+    write!(
+        effects.get_output_stream(),
+        "Resolve it in the working copy and continue? [y/N] "
+    )?;
+    let mut user_input = String::new();
+    match input.read_line(&mut user_input) {
+        Ok(_size) => {
+            let user_input = user_input.trim();
+            Ok(user_input == "y" || user_input == "Y")
+        }
+        Err(_) => Ok(false),
+    }
+}
+
+/// Make a rebase plan which carries out the rest of an in-memory rebase which
+/// stopped at a merge conflict on-disk, starting with the command which
+/// failed. The labels and rebase head are restored from the in-memory rebase,
+/// so that the commits which it already rewrote aren't rewritten again.
+fn make_resumed_rebase_plan(
+    rebase_plan: &RebasePlan,
+    partial_rebase: &in_memory::PartialRebase,
+) -> RebasePlan {
+    let in_memory::PartialRebase {
+        command_index,
+        current_oid,
+        labels,
+        rewritten_oids: _,
+    } = partial_rebase;
+
+    let mut labels: Vec<_> = labels.iter().collect();
+    labels.sort();
+    let mut commands = Vec::new();
+    for (label_name, label_oid) in labels {
+        commands.push(RebaseCommand::Reset {
+            target: OidOrLabel::Oid(*label_oid),
+        });
+        commands.push(RebaseCommand::CreateLabel {
+            label_name: label_name.clone(),
+        });
+    }
+    commands.push(RebaseCommand::Reset {
+        target: OidOrLabel::Oid(*current_oid),
+    });
+    commands.extend(rebase_plan.commands[*command_index..].iter().cloned());
+    RebasePlan {
+        first_dest_oid: *current_oid,
+        commands,
+    }
+}
+
+/// The name of the file in the rebase state directory which holds the
+/// snapshot of the uncommitted changes stashed before an on-disk rebase which
+/// stopped at a merge conflict, so that they can be reapplied once it's
+/// continued to completion.
+const AUTO_STASH_FILE_NAME: &str = "branchless_auto_stash";
+
+/// Reapply the uncommitted changes saved in `snapshot` on top of the current
+/// `HEAD`, which may have been moved since the snapshot was taken.
+fn reapply_auto_stash(
+    effects: &Effects,
+    git_run_info: &GitRunInfo,
+    repo: &Repo,
+    event_tx_id: EventTransactionId,
+    snapshot: &WorkingCopySnapshot,
+) -> EyreExitOr<()> {
+    let head_oid = repo.get_head_info()?.oid;
+    if head_oid == snapshot.head_commit.as_ref().map(|commit| commit.get_oid()) {
+        return restore_snapshot(effects, git_run_info, repo, event_tx_id, snapshot);
+    }
+    let head_commit = match head_oid {
+        Some(head_oid) => repo.find_commit_or_fail(head_oid)?,
+        None => return Ok(Err(ExitCode(1))),
+    };
+
+    let tree = match carry_snapshot_changes(repo, snapshot, &head_commit)? {
+        Ok(tree) => tree,
+        Err(_conflicting_paths) => {
+            writeln!(
+                effects.get_output_stream(),
+                "The stashed changes conflict with the new HEAD, so they were not reapplied."
+            )?;
+            return Ok(Err(ExitCode(1)));
+        }
+    };
+    write_carried_changes(effects, git_run_info, event_tx_id, &tree)
+}
+
+/// Reapply the stashed changes in `snapshot`, and report the outcome.
+fn finish_auto_stash(
+    effects: &Effects,
+    git_run_info: &GitRunInfo,
+    repo: &Repo,
+    event_tx_id: EventTransactionId,
+    snapshot: &WorkingCopySnapshot,
+) -> eyre::Result<()> {
+    if reapply_auto_stash(effects, git_run_info, repo, event_tx_id, snapshot)?.is_ok() {
+        writeln!(
+            effects.get_output_stream(),
+            "Reapplied stashed uncommitted changes."
+        )?;
+    } else {
+        write_auto_stash_hint(effects, snapshot.base_commit.get_oid())?;
+    }
+    Ok(())
+}
+
+fn write_auto_stash_hint(effects: &Effects, snapshot_oid: NonZeroOid) -> eyre::Result<()> {
+    writeln!(
+        effects.get_output_stream(),
+        "The uncommitted changes were left in snapshot {snapshot_oid}. To restore them, run: git branchless snapshot restore {snapshot_oid}"
+    )?;
+    Ok(())
+}
+
+/// If the on-disk rebase which just finished was started with uncommitted
+/// changes stashed by `branchless.autoStash`, reapply them. This should be
+/// called from the `post-rewrite` hook, while the rebase state still exists.
+pub fn reapply_on_disk_auto_stash(
+    effects: &Effects,
+    git_run_info: &GitRunInfo,
+    repo: &Repo,
+    event_tx_id: EventTransactionId,
+) -> eyre::Result<()> {
+    let path = repo.get_rebase_state_dir_path().join(AUTO_STASH_FILE_NAME);
+    let snapshot_oid: NonZeroOid = match std::fs::read_to_string(&path) {
+        Ok(contents) => contents
+            .trim()
+            .parse()
+            .wrap_err_with(|| format!("Parsing snapshot OID in {path:?}"))?,
+        Err(err) if err.kind() == std::io::ErrorKind::NotFound => return Ok(()),
+        Err(err) => return Err(err).wrap_err_with(|| format!("Reading {path:?}")),
+    };
+    std::fs::remove_file(&path).wrap_err_with(|| format!("Removing {path:?}"))?;
+
+    let base_commit = repo.find_commit_or_fail(snapshot_oid)?;
+    match WorkingCopySnapshot::try_from_base_commit(repo, &base_commit)? {
+        Some(snapshot) => finish_auto_stash(effects, git_run_info, repo, event_tx_id, &snapshot),
+        None => write_auto_stash_hint(effects, snapshot_oid),
+    }
+}
+
 /// Execute the provided rebase plan. Returns the exit status (zero indicates
 /// success).
+///
+/// If `branchless.autoStash` is set and there are uncommitted changes, they're
+/// saved in a working copy snapshot before rebasing and reapplied afterwards.
+/// If they can't be reapplied, such as because the rebase stopped at a merge
+/// conflict, they're left in the snapshot.
 pub fn execute_rebase_plan(
     effects: &Effects,
     git_run_info: &GitRunInfo,
@@ -1252,17 +1646,116 @@ pub fn execute_rebase_plan(
     event_log_db: &EventLogDb,
     rebase_plan: &RebasePlan,
     options: &ExecuteRebasePlanOptions,
+) -> eyre::Result<ExecuteRebasePlanResult> {
+    execute_rebase_plan_with_input(
+        effects,
+        git_run_info,
+        repo,
+        event_log_db,
+        rebase_plan,
+        options,
+        None,
+    )
+}
+
+/// Execute the provided rebase plan, as with `execute_rebase_plan`.
+///
+/// If an in-memory rebase stops at a merge conflict and `input` is provided,
+/// the user is asked whether to resolve the conflict in the working copy, and
+/// their answer is read from `input`. If they agree, then the rest of the
+/// rebase is carried out on-disk, starting with the conflicting commit, and
+/// can be resumed with `git branchless continue` once the conflict is
+/// resolved.
+pub fn execute_rebase_plan_with_input(
+    effects: &Effects,
+    git_run_info: &GitRunInfo,
+    repo: &Repo,
+    event_log_db: &EventLogDb,
+    rebase_plan: &RebasePlan,
+    options: &ExecuteRebasePlanOptions,
+    input: Option<&mut dyn BufRead>,
+) -> eyre::Result<ExecuteRebasePlanResult> {
+    if !get_auto_stash(repo)? || !repo.has_changed_files(effects, git_run_info)? {
+        return execute_rebase_plan_without_auto_stash(
+            effects,
+            git_run_info,
+            repo,
+            event_log_db,
+            rebase_plan,
+            options,
+            input,
+        );
+    }
+
+    let event_tx_id = options.event_tx_id;
+    let snapshot = create_snapshot(effects, git_run_info, repo, event_log_db, event_tx_id)?;
+    let snapshot_oid = snapshot.base_commit.get_oid();
+    if let Err(exit_code) =
+        git_run_info.run(effects, Some(event_tx_id), &["reset", "--hard", "HEAD"])?
+    {
+        return Ok(ExecuteRebasePlanResult::Failed { exit_code });
+    }
+    writeln!(
+        effects.get_output_stream(),
+        "Stashed uncommitted changes in snapshot {snapshot_oid}."
+    )?;
+
+    let result = execute_rebase_plan_without_auto_stash(
+        effects,
+        git_run_info,
+        repo,
+        event_log_db,
+        rebase_plan,
+        options,
+        input,
+    )?;
+
+    // If an on-disk rebase stopped at a merge conflict, then the working copy
+    // is in use, so the changes can't be reapplied until the rebase is
+    // continued to completion.
+    let rebase_state_dir_path = repo.get_rebase_state_dir_path();
+    if repo.get_current_operation_type().is_none() {
+        finish_auto_stash(effects, git_run_info, repo, event_tx_id, &snapshot)?;
+    } else if rebase_state_dir_path.exists() {
+        std::fs::write(
+            rebase_state_dir_path.join(AUTO_STASH_FILE_NAME),
+            snapshot_oid.to_string(),
+        )
+        .wrap_err("Writing auto-stash snapshot OID")?;
+        writeln!(
+            effects.get_output_stream(),
+            "The uncommitted changes will be reapplied once the rebase is continued. Until then, they're saved in snapshot {snapshot_oid}."
+        )?;
+    } else {
+        write_auto_stash_hint(effects, snapshot_oid)?;
+    }
+    Ok(result)
+}
+
+fn execute_rebase_plan_without_auto_stash(
+    effects: &Effects,
+    git_run_info: &GitRunInfo,
+    repo: &Repo,
+    event_log_db: &EventLogDb,
+    rebase_plan: &RebasePlan,
+    options: &ExecuteRebasePlanOptions,
+    input: Option<&mut dyn BufRead>,
 ) -> eyre::Result<ExecuteRebasePlanResult> {
     let ExecuteRebasePlanOptions {
         now: _,
-        event_tx_id: _,
-        preserve_timestamps: _,
+        event_tx_id,
+        preserve_timestamps,
         force_in_memory,
         force_on_disk,
         resolve_merge_conflicts,
         check_out_commit_options: _,
     } = options;
 
+    // If an in-memory rebase stopped at a merge conflict and the user chose to
+    // resolve it in the working copy, this holds the plan for the rest of the
+    // rebase and the commits which were already rewritten in-memory.
+    let mut resumed_rebase: Option<(RebasePlan, HashMap<NonZeroOid, MaybeZeroOid>)> = None;
+
     if !force_on_disk {
         use in_memory::*;
         writeln!(
@@ -1270,69 +1763,205 @@ pub fn execute_rebase_plan(
             "Attempting rebase in-memory..."
         )?;
 
-        let failed_merge_info = match rebase_in_memory(effects, repo, rebase_plan, options)? {
-            RebaseInMemoryResult::MergeFailed(failed_merge_info) => failed_merge_info,
+        // Record the plan before starting, so that the rebase can be carried
+        // out again if it's interrupted before the repository is updated.
+        let conn = repo.get_db_conn()?;
+        let interrupted_rebase_db = InterruptedRebaseDb::new(&conn)?;
+        interrupted_rebase_db.save(&InterruptedRebase {
+            event_tx_id: *event_tx_id,
+            rebase_plan: Some(rebase_plan.clone()),
+            preserve_timestamps: *preserve_timestamps,
+            head_info: repo.get_head_info()?,
+            skipped_head_updated_oid: None,
+            rewritten_oids: HashMap::new(),
+            original_branches: HashMap::new(),
+            stage: InterruptedRebaseStage::RebaseCommits,
+        })?;
 
-            RebaseInMemoryResult::Succeeded {
-                rewritten_oids,
-                new_head_oid,
-            } => {
-                // Ignore the return code, as it probably indicates that the
-                // checkout failed (which might happen if the user has changes
-                // which don't merge cleanly). The user can resolve that
-                // themselves.
-                match post_rebase_in_memory(
-                    effects,
-                    git_run_info,
-                    repo,
-                    event_log_db,
-                    &rewritten_oids,
-                    new_head_oid,
-                    options,
-                )? {
-                    Ok(()) => {}
-                    Err(_exit_code) => {
-                        // FIXME: we may still want to propagate the exit code to the
-                        // caller.
+        let mut fetched_oids = HashSet::new();
+        let failed_merge_info = loop {
+            match rebase_in_memory(effects, repo, rebase_plan, options)? {
+                RebaseInMemoryResult::MergeFailed(failed_merge_info, partial_rebase) => {
+                    break Some((failed_merge_info, partial_rebase))
+                }
+
+                RebaseInMemoryResult::MissingObjects { oids } => {
+                    // Only try to fetch each object once, in case the promisor
+                    // remote claims to have sent an object but didn't.
+                    let is_fetched = if oids.iter().all(|oid| fetched_oids.contains(oid)) {
+                        false
+                    } else {
+                        writeln!(
+                            effects.get_output_stream(),
+                            "Fetching {} from promisor remote...",
+                            Pluralize {
+                                determiner: None,
+                                amount: oids.len(),
+                                unit: ("missing object", "missing objects"),
+                            }
+                        )?;
+                        fetched_oids.extend(oids.iter().copied());
+                        fetch_missing_objects(git_run_info, repo, Some(*event_tx_id), &oids)?
+                    };
+                    if is_fetched {
+                        continue;
+                    }
+
+                    if *force_in_memory {
+                        writeln!(
+                            effects.get_output_stream(),
+                            "Could not fetch objects needed for in-memory rebase."
+                        )?;
+                        writeln!(
+                            effects.get_output_stream(),
+                            "Aborting since an in-memory rebase was requested."
+                        )?;
+                        interrupted_rebase_db.clear()?;
+                        return Ok(ExecuteRebasePlanResult::Failed {
+                            exit_code: ExitCode(1),
+                        });
+                    } else {
+                        writeln!(
+                            effects.get_output_stream(),
+                            "Could not fetch objects needed for in-memory rebase, trying again on-disk..."
+                        )?;
+                        break None;
                     }
                 }
 
-                writeln!(effects.get_output_stream(), "In-memory rebase succeeded.")?;
-                return Ok(ExecuteRebasePlanResult::Succeeded {
-                    rewritten_oids: Some(rewritten_oids),
-                });
+                RebaseInMemoryResult::Succeeded {
+                    rewritten_oids,
+                    new_head_oid,
+                } => {
+                    let new_commit_oids =
+                        rewritten_oids
+                            .iter()
+                            .filter_map(|(old_oid, new_oid)| match new_oid {
+                                MaybeZeroOid::NonZero(new_oid) if new_oid != old_oid => {
+                                    Some(*new_oid)
+                                }
+                                _ => None,
+                            });
+                    if !lint_commit_messages(
+                        effects,
+                        git_run_info,
+                        repo,
+                        *event_tx_id,
+                        new_commit_oids,
+                    )? {
+                        writeln!(
+                            effects.get_output_stream(),
+                            "Aborting since a commit message was rejected."
+                        )?;
+                        interrupted_rebase_db.clear()?;
+                        return Ok(ExecuteRebasePlanResult::Failed {
+                            exit_code: ExitCode(1),
+                        });
+                    }
+
+                    // Ignore the return code, as it probably indicates that the
+                    // checkout failed (which might happen if the user has changes
+                    // which don't merge cleanly). The user can resolve that
+                    // themselves.
+                    match post_rebase_in_memory(
+                        effects,
+                        git_run_info,
+                        repo,
+                        event_log_db,
+                        &rewritten_oids,
+                        new_head_oid,
+                        options,
+                    )? {
+                        Ok(()) => {}
+                        Err(_exit_code) => {
+                            // FIXME: we may still want to propagate the exit code to the
+                            // caller.
+                        }
+                    }
+
+                    writeln!(effects.get_output_stream(), "In-memory rebase succeeded.")?;
+                    return Ok(ExecuteRebasePlanResult::Succeeded {
+                        rewritten_oids: Some(rewritten_oids),
+                    });
+                }
             }
         };
+        interrupted_rebase_db.clear()?;
 
-        if !resolve_merge_conflicts {
-            return Ok(ExecuteRebasePlanResult::DeclinedToMerge { failed_merge_info });
-        }
-
-        // The rebase has failed at this point, decide whether or not to try
-        // again with an on-disk rebase.
-        if *force_in_memory {
-            writeln!(
-                effects.get_output_stream(),
-                "Aborting since an in-memory rebase was requested."
-            )?;
-            return Ok(ExecuteRebasePlanResult::DeclinedToMerge { failed_merge_info });
-        } else {
-            writeln!(
-                effects.get_output_stream(),
-                "Failed to merge in-memory, trying again on-disk..."
-            )?;
+        if let Some((failed_merge_info, partial_rebase)) = failed_merge_info {
+            if *resolve_merge_conflicts {
+                // The rebase has failed at this point, decide whether or not to
+                // try again with an on-disk rebase.
+                if *force_in_memory {
+                    writeln!(
+                        effects.get_output_stream(),
+                        "Aborting since an in-memory rebase was requested."
+                    )?;
+                    return Ok(ExecuteRebasePlanResult::DeclinedToMerge { failed_merge_info });
+                } else {
+                    writeln!(
+                        effects.get_output_stream(),
+                        "Failed to merge in-memory, trying again on-disk..."
+                    )?;
+                }
+            } else {
+                let should_resolve_in_working_copy = match (&partial_rebase, input) {
+                    (Some(_), Some(input)) if !force_in_memory => {
+                        confirm_resolve_in_working_copy(effects, repo, &failed_merge_info, input)?
+                    }
+                    _ => false,
+                };
+                match partial_rebase {
+                    Some(partial_rebase) if should_resolve_in_working_copy => {
+                        for new_oid in partial_rebase.rewritten_oids.values() {
+                            if let MaybeZeroOid::NonZero(new_oid) = new_oid {
+                                mark_commit_reachable(repo, *new_oid)?;
+                            }
+                        }
+                        resumed_rebase = Some((
+                            make_resumed_rebase_plan(rebase_plan, &partial_rebase),
+                            partial_rebase.rewritten_oids,
+                        ));
+                    }
+                    _ => return Ok(ExecuteRebasePlanResult::DeclinedToMerge { failed_merge_info }),
+                }
+            }
         }
     }
 
     if !force_in_memory {
         use on_disk::*;
-        match rebase_on_disk(effects, git_run_info, repo, rebase_plan, options)? {
+        let no_rewritten_oids = HashMap::new();
+        let (rebase_plan, rewritten_oids) = match &resumed_rebase {
+            Some((rebase_plan, rewritten_oids)) => (rebase_plan, rewritten_oids),
+            None => (rebase_plan, &no_rewritten_oids),
+        };
+        match rebase_on_disk(
+            effects,
+            git_run_info,
+            repo,
+            rebase_plan,
+            rewritten_oids,
+            options,
+        )? {
             Ok(exit_code) if exit_code.is_success() => {
                 return Ok(ExecuteRebasePlanResult::Succeeded {
                     rewritten_oids: None,
                 });
             }
-            Ok(exit_code) => return Ok(ExecuteRebasePlanResult::Failed { exit_code }),
+            Ok(exit_code) => {
+                if resumed_rebase.is_some() {
+                    writeln!(
+                        effects.get_output_stream(),
+                        "After resolving the merge conflicts, run: git branchless continue"
+                    )?;
+                    writeln!(
+                        effects.get_output_stream(),
+                        "To cancel the operation instead, run: git branchless abort"
+                    )?;
+                }
+                return Ok(ExecuteRebasePlanResult::Failed { exit_code });
+            }
             Err(Error::ChangedFilesInRepository) => {
                 write!(
                     effects.get_output_stream(),
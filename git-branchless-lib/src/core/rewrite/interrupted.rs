@@ -0,0 +1,665 @@
+//! Persist the progress of in-memory rebases, so that they can be continued
+//! or aborted if they're interrupted.
+//!
+//! An in-memory rebase creates all of its new commits before touching the
+//! repository. The repository is only updated afterwards, when the branches
+//! are moved, the old commits are hidden, and the new `HEAD` is checked out. If
+//! the process is interrupted (such as by Ctrl-C or a crash) partway through
+//! these steps, then the repository would be left half-updated. To avoid this,
+//! the rebase plan is recorded in the database before the rebase starts, and
+//! the state needed to finish or roll back each later step is recorded before
+//! starting it. The record is cleared once the rebase has completed.
+
+use std::collections::HashMap;
+use std::fmt::{Display, Write};
+use std::str::FromStr;
+use std::time::SystemTime;
+
+use bstr::BString;
+use eyre::Context;
+use tracing::instrument;
+
+use crate::core::check_out::{check_out_commit, CheckOutCommitOptions, CheckoutTarget};
+use crate::core::effects::Effects;
+use crate::core::eventlog::{Event, EventLogDb, EventTransactionId};
+use crate::git::{
+    GitRunInfo, GitRunOpts, GitRunResult, MaybeZeroOid, NonZeroOid, ReferenceName, Repo,
+    ResolvedReferenceInfo,
+};
+use crate::util::{ExitCode, EyreExitOr};
+
+use super::execute::check_out_updated_head;
+use super::plan::RebasePlan;
+use super::{
+    execute_rebase_plan, move_branches, ExecuteRebasePlanOptions, ExecuteRebasePlanResult,
+    MergeConflictRemediation,
+};
+
+/// The step of updating the repository which an in-memory rebase had reached.
+/// The steps are carried out in the order that they're declared.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord)]
+pub enum InterruptedRebaseStage {
+    /// Creating the rewritten commits. The repository isn't modified during
+    /// this step.
+    RebaseCommits,
+
+    /// Moving the branches from the old commits to the new commits.
+    MoveBranches,
+
+    /// Running the `post-rewrite` hook, which records the rewrites and hides
+    /// the old commits.
+    PostRewrite,
+
+    /// Checking out the new `HEAD`.
+    CheckOut,
+}
+
+impl InterruptedRebaseStage {
+    fn as_str(&self) -> &'static str {
+        match self {
+            InterruptedRebaseStage::RebaseCommits => "rebase-commits",
+            InterruptedRebaseStage::MoveBranches => "move-branches",
+            InterruptedRebaseStage::PostRewrite => "post-rewrite",
+            InterruptedRebaseStage::CheckOut => "check-out",
+        }
+    }
+}
+
+impl Display for InterruptedRebaseStage {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            InterruptedRebaseStage::RebaseCommits => write!(f, "rewriting commits"),
+            InterruptedRebaseStage::MoveBranches => write!(f, "moving branches"),
+            InterruptedRebaseStage::PostRewrite => write!(f, "hiding the rewritten commits"),
+            InterruptedRebaseStage::CheckOut => write!(f, "checking out the new HEAD"),
+        }
+    }
+}
+
+impl FromStr for InterruptedRebaseStage {
+    type Err = eyre::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "rebase-commits" => Ok(InterruptedRebaseStage::RebaseCommits),
+            "move-branches" => Ok(InterruptedRebaseStage::MoveBranches),
+            "post-rewrite" => Ok(InterruptedRebaseStage::PostRewrite),
+            "check-out" => Ok(InterruptedRebaseStage::CheckOut),
+            other => eyre::bail!("Unknown interrupted rebase stage: {other:?}"),
+        }
+    }
+}
+
+/// The state of an in-memory rebase which was updating the repository.
+#[derive(Debug)]
+pub struct InterruptedRebase {
+    /// The transaction which the rebase's events belong to.
+    pub event_tx_id: EventTransactionId,
+
+    /// The plan which the rebase is carrying out. This is only needed until
+    /// the rewritten commits have been created, since the rebase is carried
+    /// out again from the start if it was interrupted before then.
+    pub rebase_plan: Option<RebasePlan>,
+
+    /// See [`ExecuteRebasePlanOptions::preserve_timestamps`].
+    pub preserve_timestamps: bool,
+
+    /// The `HEAD` before the rebase started.
+    pub head_info: ResolvedReferenceInfo,
+
+    /// Where to put `HEAD` if its commit was skipped. See
+    /// `check_out_updated_head`.
+    pub skipped_head_updated_oid: Option<NonZeroOid>,
+
+    /// The commits which the rebase rewrote, mapped to their new versions.
+    pub rewritten_oids: HashMap<NonZeroOid, MaybeZeroOid>,
+
+    /// The positions of the branches which the rebase moves, before it moved
+    /// them.
+    pub original_branches: HashMap<ReferenceName, NonZeroOid>,
+
+    /// The step which the rebase had reached.
+    pub stage: InterruptedRebaseStage,
+}
+
+/// Stores the state of the in-memory rebase being carried out, if any.
+pub struct InterruptedRebaseDb<'conn> {
+    conn: &'conn rusqlite::Connection,
+}
+
+impl std::fmt::Debug for InterruptedRebaseDb<'_> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "<InterruptedRebaseDb path={:?}>", self.conn.path())
+    }
+}
+
+#[instrument]
+fn init_tables(conn: &rusqlite::Connection) -> eyre::Result<()> {
+    conn.execute(
+        "
+CREATE TABLE IF NOT EXISTS interrupted_rebase (
+    id INTEGER NOT NULL PRIMARY KEY CHECK (id = 0),
+    event_tx_id TEXT NOT NULL,
+    stage TEXT NOT NULL,
+    rebase_plan TEXT,
+    preserve_timestamps INTEGER NOT NULL,
+    head_oid TEXT,
+    head_reference_name TEXT,
+    skipped_head_updated_oid TEXT
+)
+",
+        rusqlite::params![],
+    )
+    .wrap_err("Creating `interrupted_rebase` table")?;
+
+    conn.execute(
+        "
+CREATE TABLE IF NOT EXISTS interrupted_rebase_rewrites (
+    old_commit_oid TEXT NOT NULL PRIMARY KEY,
+    new_commit_oid TEXT NOT NULL
+)
+",
+        rusqlite::params![],
+    )
+    .wrap_err("Creating `interrupted_rebase_rewrites` table")?;
+
+    conn.execute(
+        "
+CREATE TABLE IF NOT EXISTS interrupted_rebase_branches (
+    reference_name TEXT NOT NULL PRIMARY KEY,
+    commit_oid TEXT NOT NULL
+)
+",
+        rusqlite::params![],
+    )
+    .wrap_err("Creating `interrupted_rebase_branches` table")?;
+
+    Ok(())
+}
+
+fn parse_oid(oid: &str) -> eyre::Result<NonZeroOid> {
+    NonZeroOid::from_str(oid).wrap_err_with(|| format!("Parsing commit OID: {oid:?}"))
+}
+
+impl<'conn> InterruptedRebaseDb<'conn> {
+    /// Constructor.
+    #[instrument]
+    pub fn new(conn: &'conn rusqlite::Connection) -> eyre::Result<Self> {
+        init_tables(conn)?;
+        Ok(InterruptedRebaseDb { conn })
+    }
+
+    /// Record the state of the rebase about to update the repository,
+    /// replacing any previously-recorded state.
+    #[instrument]
+    pub fn save(&self, interrupted_rebase: &InterruptedRebase) -> eyre::Result<()> {
+        let InterruptedRebase {
+            event_tx_id,
+            rebase_plan,
+            preserve_timestamps,
+            head_info,
+            skipped_head_updated_oid,
+            rewritten_oids,
+            original_branches,
+            stage,
+        } = interrupted_rebase;
+        let rebase_plan = rebase_plan
+            .as_ref()
+            .map(serde_json::to_string)
+            .transpose()
+            .wrap_err("Serializing rebase plan")?;
+
+        let tx = self.conn.unchecked_transaction()?;
+        tx.execute("DELETE FROM interrupted_rebase", rusqlite::params![])?;
+        tx.execute(
+            "DELETE FROM interrupted_rebase_rewrites",
+            rusqlite::params![],
+        )?;
+        tx.execute(
+            "DELETE FROM interrupted_rebase_branches",
+            rusqlite::params![],
+        )?;
+        tx.execute(
+            "
+INSERT INTO interrupted_rebase
+(id, event_tx_id, stage, rebase_plan, preserve_timestamps, head_oid, head_reference_name, skipped_head_updated_oid)
+VALUES
+(0, :event_tx_id, :stage, :rebase_plan, :preserve_timestamps, :head_oid, :head_reference_name, :skipped_head_updated_oid)
+",
+            rusqlite::named_params! {
+                ":event_tx_id": event_tx_id.to_string(),
+                ":stage": stage.as_str(),
+                ":rebase_plan": rebase_plan,
+                ":preserve_timestamps": preserve_timestamps,
+                ":head_oid": head_info.oid.map(|oid| oid.to_string()),
+                ":head_reference_name": head_info
+                    .reference_name
+                    .as_ref()
+                    .map(|reference_name| reference_name.as_str()),
+                ":skipped_head_updated_oid": skipped_head_updated_oid.map(|oid| oid.to_string()),
+            },
+        )?;
+        for (old_commit_oid, new_commit_oid) in rewritten_oids {
+            tx.execute(
+                "
+INSERT INTO interrupted_rebase_rewrites
+(old_commit_oid, new_commit_oid)
+VALUES
+(:old_commit_oid, :new_commit_oid)
+",
+                rusqlite::named_params! {
+                    ":old_commit_oid": old_commit_oid.to_string(),
+                    ":new_commit_oid": new_commit_oid.to_string(),
+                },
+            )?;
+        }
+        for (reference_name, commit_oid) in original_branches {
+            tx.execute(
+                "
+INSERT INTO interrupted_rebase_branches
+(reference_name, commit_oid)
+VALUES
+(:reference_name, :commit_oid)
+",
+                rusqlite::named_params! {
+                    ":reference_name": reference_name.as_str(),
+                    ":commit_oid": commit_oid.to_string(),
+                },
+            )?;
+        }
+        tx.commit()?;
+        Ok(())
+    }
+
+    /// Record that the rebase has reached the given step.
+    #[instrument]
+    pub fn set_stage(&self, stage: InterruptedRebaseStage) -> eyre::Result<()> {
+        self.conn.execute(
+            "
+UPDATE interrupted_rebase
+SET stage = :stage
+",
+            rusqlite::named_params! {
+                ":stage": stage.as_str(),
+            },
+        )?;
+        Ok(())
+    }
+
+    /// Get the state of the rebase which was interrupted, if any.
+    #[instrument]
+    pub fn load(&self) -> eyre::Result<Option<InterruptedRebase>> {
+        let row = {
+            let mut stmt = self.conn.prepare(
+                "
+SELECT event_tx_id, stage, rebase_plan, preserve_timestamps, head_oid, head_reference_name, skipped_head_updated_oid
+FROM interrupted_rebase
+",
+            )?;
+            let mut rows = stmt.query(rusqlite::params![])?;
+            match rows.next()? {
+                Some(row) => {
+                    let event_tx_id: String = row.get("event_tx_id")?;
+                    let stage: String = row.get("stage")?;
+                    let rebase_plan: Option<String> = row.get("rebase_plan")?;
+                    let preserve_timestamps: bool = row.get("preserve_timestamps")?;
+                    let head_oid: Option<String> = row.get("head_oid")?;
+                    let head_reference_name: Option<String> = row.get("head_reference_name")?;
+                    let skipped_head_updated_oid: Option<String> =
+                        row.get("skipped_head_updated_oid")?;
+                    (
+                        event_tx_id,
+                        stage,
+                        rebase_plan,
+                        preserve_timestamps,
+                        head_oid,
+                        head_reference_name,
+                        skipped_head_updated_oid,
+                    )
+                }
+                None => return Ok(None),
+            }
+        };
+        let (
+            event_tx_id,
+            stage,
+            rebase_plan,
+            preserve_timestamps,
+            head_oid,
+            head_reference_name,
+            skipped_head_updated_oid,
+        ) = row;
+
+        let mut rewritten_oids = HashMap::new();
+        {
+            let mut stmt = self.conn.prepare(
+                "
+SELECT old_commit_oid, new_commit_oid
+FROM interrupted_rebase_rewrites
+",
+            )?;
+            let rows: rusqlite::Result<Vec<(String, String)>> = stmt
+                .query_map(rusqlite::params![], |row| {
+                    Ok((row.get("old_commit_oid")?, row.get("new_commit_oid")?))
+                })?
+                .collect();
+            for (old_commit_oid, new_commit_oid) in rows? {
+                let new_commit_oid = MaybeZeroOid::from_str(&new_commit_oid)
+                    .wrap_err_with(|| format!("Parsing commit OID: {new_commit_oid:?}"))?;
+                rewritten_oids.insert(parse_oid(&old_commit_oid)?, new_commit_oid);
+            }
+        }
+
+        let mut original_branches = HashMap::new();
+        {
+            let mut stmt = self.conn.prepare(
+                "
+SELECT reference_name, commit_oid
+FROM interrupted_rebase_branches
+",
+            )?;
+            let rows: rusqlite::Result<Vec<(String, String)>> = stmt
+                .query_map(rusqlite::params![], |row| {
+                    Ok((row.get("reference_name")?, row.get("commit_oid")?))
+                })?
+                .collect();
+            for (reference_name, commit_oid) in rows? {
+                original_branches
+                    .insert(ReferenceName::from(reference_name), parse_oid(&commit_oid)?);
+            }
+        }
+
+        Ok(Some(InterruptedRebase {
+            event_tx_id: EventTransactionId::from_str(&event_tx_id)
+                .wrap_err_with(|| format!("Parsing event transaction ID: {event_tx_id:?}"))?,
+            rebase_plan: rebase_plan
+                .as_deref()
+                .map(serde_json::from_str)
+                .transpose()
+                .wrap_err("Deserializing rebase plan")?,
+            preserve_timestamps,
+            head_info: ResolvedReferenceInfo {
+                oid: head_oid.as_deref().map(parse_oid).transpose()?,
+                reference_name: head_reference_name.map(ReferenceName::from),
+            },
+            skipped_head_updated_oid: skipped_head_updated_oid
+                .as_deref()
+                .map(parse_oid)
+                .transpose()?,
+            rewritten_oids,
+            original_branches,
+            stage: stage.parse()?,
+        }))
+    }
+
+    /// Forget the state of the rebase, once it's finished or been aborted.
+    #[instrument]
+    pub fn clear(&self) -> eyre::Result<()> {
+        let tx = self.conn.unchecked_transaction()?;
+        tx.execute("DELETE FROM interrupted_rebase", rusqlite::params![])?;
+        tx.execute(
+            "DELETE FROM interrupted_rebase_rewrites",
+            rusqlite::params![],
+        )?;
+        tx.execute(
+            "DELETE FROM interrupted_rebase_branches",
+            rusqlite::params![],
+        )?;
+        tx.commit()?;
+        Ok(())
+    }
+}
+
+/// Carry out the remaining steps of updating the repository for an in-memory
+/// rebase, starting from the step which it had reached. This is used both for
+/// a rebase which just finished in-memory and for one which was interrupted.
+#[instrument]
+pub fn continue_interrupted_rebase(
+    effects: &Effects,
+    git_run_info: &GitRunInfo,
+    repo: &Repo,
+    event_log_db: &EventLogDb,
+    interrupted_rebase_db: &InterruptedRebaseDb,
+    interrupted_rebase: &InterruptedRebase,
+    check_out_commit_options: &CheckOutCommitOptions,
+) -> EyreExitOr<()> {
+    let InterruptedRebase {
+        event_tx_id,
+        rebase_plan,
+        preserve_timestamps,
+        head_info: _,
+        skipped_head_updated_oid: _,
+        rewritten_oids: _,
+        original_branches: _,
+        stage,
+    } = interrupted_rebase;
+
+    if *stage == InterruptedRebaseStage::RebaseCommits {
+        // Creating the rewritten commits doesn't modify the repository, so
+        // just carry out the rebase again.
+        let rebase_plan = match rebase_plan {
+            Some(rebase_plan) => rebase_plan,
+            None => eyre::bail!("No rebase plan was recorded for the interrupted rebase"),
+        };
+        interrupted_rebase_db.clear()?;
+        let options = ExecuteRebasePlanOptions {
+            now: SystemTime::now(),
+            event_tx_id: *event_tx_id,
+            preserve_timestamps: *preserve_timestamps,
+            force_in_memory: true,
+            force_on_disk: false,
+            resolve_merge_conflicts: false,
+            check_out_commit_options: check_out_commit_options.clone(),
+        };
+        return match execute_rebase_plan(
+            effects,
+            git_run_info,
+            repo,
+            event_log_db,
+            rebase_plan,
+            &options,
+        )? {
+            ExecuteRebasePlanResult::Succeeded { rewritten_oids: _ } => Ok(Ok(())),
+            ExecuteRebasePlanResult::DeclinedToMerge { failed_merge_info } => {
+                failed_merge_info.describe(effects, repo, MergeConflictRemediation::Retry)?;
+                Ok(Err(ExitCode(1)))
+            }
+            ExecuteRebasePlanResult::Failed { exit_code } => Ok(Err(exit_code)),
+        };
+    }
+
+    update_repository(
+        effects,
+        git_run_info,
+        repo,
+        event_log_db,
+        interrupted_rebase_db,
+        interrupted_rebase,
+        check_out_commit_options,
+    )
+}
+
+/// Carry out the steps of an in-memory rebase which update the repository,
+/// once its rewritten commits have been created.
+fn update_repository(
+    effects: &Effects,
+    git_run_info: &GitRunInfo,
+    repo: &Repo,
+    event_log_db: &EventLogDb,
+    interrupted_rebase_db: &InterruptedRebaseDb,
+    interrupted_rebase: &InterruptedRebase,
+    check_out_commit_options: &CheckOutCommitOptions,
+) -> EyreExitOr<()> {
+    let InterruptedRebase {
+        event_tx_id,
+        rebase_plan: _,
+        preserve_timestamps: _,
+        head_info,
+        skipped_head_updated_oid,
+        rewritten_oids,
+        original_branches: _,
+        stage,
+    } = interrupted_rebase;
+
+    if *stage <= InterruptedRebaseStage::MoveBranches {
+        let current_head_info = repo.get_head_info()?;
+        if current_head_info.oid.is_some() {
+            // Avoid moving the branch which HEAD points to, or else the index will show
+            // a lot of changes in the working copy.
+            repo.detach_head(&current_head_info)?;
+        }
+
+        move_branches(effects, git_run_info, repo, *event_tx_id, rewritten_oids)?;
+        interrupted_rebase_db.set_stage(InterruptedRebaseStage::PostRewrite)?;
+    }
+
+    if *stage <= InterruptedRebaseStage::PostRewrite {
+        // Call the `post-rewrite` hook only after moving branches so that we don't
+        // produce a spurious abandoned-branch warning.
+        let mut post_rewrite_stdin = String::new();
+        for (old_oid, new_oid) in rewritten_oids {
+            writeln!(post_rewrite_stdin, "{old_oid} {new_oid}")?;
+        }
+        let post_rewrite_stdin = BString::from(post_rewrite_stdin);
+        git_run_info.run_hook(
+            effects,
+            repo,
+            "post-rewrite",
+            *event_tx_id,
+            &["rebase"],
+            Some(post_rewrite_stdin),
+        )?;
+        interrupted_rebase_db.set_stage(InterruptedRebaseStage::CheckOut)?;
+    }
+
+    let exit_code = check_out_updated_head(
+        effects,
+        git_run_info,
+        repo,
+        event_log_db,
+        *event_tx_id,
+        rewritten_oids,
+        head_info,
+        *skipped_head_updated_oid,
+        check_out_commit_options,
+    )?;
+    interrupted_rebase_db.clear()?;
+    Ok(exit_code)
+}
+
+/// Roll back the changes made to the repository by an interrupted in-memory
+/// rebase: restore the branches and `HEAD` to where they were before the
+/// rebase, and hide the new commits in favor of the old ones.
+#[instrument]
+pub fn abort_interrupted_rebase(
+    effects: &Effects,
+    git_run_info: &GitRunInfo,
+    repo: &Repo,
+    event_log_db: &EventLogDb,
+    event_tx_id: EventTransactionId,
+    now: SystemTime,
+    interrupted_rebase_db: &InterruptedRebaseDb,
+    interrupted_rebase: &InterruptedRebase,
+) -> EyreExitOr<()> {
+    let InterruptedRebase {
+        event_tx_id: _,
+        rebase_plan: _,
+        preserve_timestamps: _,
+        head_info,
+        skipped_head_updated_oid: _,
+        rewritten_oids,
+        original_branches,
+        stage,
+    } = interrupted_rebase;
+
+    // The repository isn't modified until the rewritten commits have been
+    // created, so there's nothing to roll back.
+    if *stage == InterruptedRebaseStage::RebaseCommits {
+        interrupted_rebase_db.clear()?;
+        return Ok(Ok(()));
+    }
+
+    let current_head_info = repo.get_head_info()?;
+    if current_head_info.oid.is_some() {
+        repo.detach_head(&current_head_info)?;
+    }
+
+    // Restore the branches in a single transaction with `git update-ref`, so
+    // that the `reference-transaction` hook records the moves.
+    let mut original_branches: Vec<_> = original_branches.iter().collect();
+    original_branches.sort_unstable();
+    let mut update_ref_stdin = String::new();
+    for (reference_name, commit_oid) in original_branches {
+        let current_oid = match repo.find_reference(reference_name)? {
+            Some(reference) => MaybeZeroOid::from(repo.resolve_reference(&reference)?.oid),
+            None => MaybeZeroOid::Zero,
+        };
+        writeln!(
+            update_ref_stdin,
+            "update {} {commit_oid} {current_oid}",
+            reference_name.as_str()
+        )?;
+    }
+    if !update_ref_stdin.is_empty() {
+        let GitRunResult { .. } = git_run_info
+            .run_silent(
+                repo,
+                Some(event_tx_id),
+                &["update-ref", "--stdin", "-m", "branchless: abort rebase"],
+                GitRunOpts {
+                    treat_git_failure_as_error: true,
+                    stdin: Some(update_ref_stdin.into_bytes()),
+                },
+            )
+            .wrap_err("Restoring branches")?;
+    }
+
+    let timestamp = now.duration_since(SystemTime::UNIX_EPOCH)?.as_secs_f64();
+    let mut events = Vec::new();
+    for (old_commit_oid, new_commit_oid) in rewritten_oids {
+        if *stage > InterruptedRebaseStage::MoveBranches {
+            events.push(Event::UnobsoleteEvent {
+                timestamp,
+                event_tx_id,
+                commit_oid: *old_commit_oid,
+            });
+        }
+        if let MaybeZeroOid::NonZero(new_commit_oid) = new_commit_oid {
+            events.push(Event::ObsoleteEvent {
+                timestamp,
+                event_tx_id,
+                commit_oid: *new_commit_oid,
+            });
+        }
+    }
+    event_log_db.add_events(events)?;
+
+    let target = match head_info {
+        ResolvedReferenceInfo {
+            oid: _,
+            reference_name: Some(reference_name),
+        } => Some(CheckoutTarget::Reference(reference_name.clone())),
+        ResolvedReferenceInfo {
+            oid: Some(oid),
+            reference_name: None,
+        } => Some(CheckoutTarget::Oid(*oid)),
+        ResolvedReferenceInfo {
+            oid: None,
+            reference_name: None,
+        } => None,
+    };
+    let exit_code = match target {
+        Some(target) => check_out_commit(
+            effects,
+            git_run_info,
+            repo,
+            event_log_db,
+            event_tx_id,
+            Some(target),
+            &CheckOutCommitOptions::default(),
+        )?,
+        None => Ok(()),
+    };
+    interrupted_rebase_db.clear()?;
+    Ok(exit_code)
+}
@@ -1,21 +1,39 @@
 //! Tools for editing the commit graph.
 
+mod edit;
 mod evolve;
 mod execute;
+mod interrupted;
 mod plan;
 pub mod rewrite_hooks;
+mod todo;
 
 use std::sync::Mutex;
 
-pub use evolve::{find_abandoned_children, find_rewrite_target};
+pub use edit::{
+    clear_edit_state, clear_edit_state_if_head_left_stack, read_edit_state, update_edit_state,
+    write_edit_state, EditState,
+};
+pub use evolve::{
+    find_abandoned_children, find_equivalent_commits, find_main_branch_rewrite,
+    find_rewrite_target, find_upstream_applied_commits, is_non_fast_forward,
+};
 pub use execute::{
-    execute_rebase_plan, move_branches, ExecuteRebasePlanOptions, ExecuteRebasePlanResult,
-    FailedMergeInfo, MergeConflictRemediation,
+    execute_rebase_plan, execute_rebase_plan_with_input, move_branches, ExecuteRebasePlanOptions,
+    ExecuteRebasePlanResult, FailedMergeInfo, MergeConflictRemediation,
+};
+pub use interrupted::{
+    abort_interrupted_rebase, continue_interrupted_rebase, InterruptedRebase, InterruptedRebaseDb,
+    InterruptedRebaseStage,
 };
 pub use plan::{
     BuildRebasePlanError, BuildRebasePlanOptions, OidOrLabel, RebaseCommand, RebasePlan,
     RebasePlanBuilder, RebasePlanPermissions,
 };
+pub use todo::{
+    autosquash_rebase_todo, find_autosquash_commits, parse_rebase_todo, render_rebase_todo,
+    RebaseTodoLine,
+};
 use tracing::instrument;
 
 use crate::core::task::{Resource, ResourcePool};
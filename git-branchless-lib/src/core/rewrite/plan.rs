@@ -8,6 +8,7 @@ use chashmap::CHashMap;
 use eyre::Context;
 use itertools::Itertools;
 use rayon::{prelude::*, ThreadPool};
+use serde::{Deserialize, Serialize};
 use tracing::{instrument, warn};
 
 use crate::core::dag::{sorted_commit_set, union_all, CommitSet, Dag};
@@ -18,7 +19,7 @@ use crate::core::task::ResourcePool;
 use crate::git::{Commit, NonZeroOid, PatchId, Repo};
 
 /// Represents the target for certain [`RebaseCommand`]s.
-#[derive(Clone, Debug)]
+#[derive(Clone, Debug, Serialize, Deserialize)]
 pub enum OidOrLabel {
     /// A commit hash to check out directly.
     Oid(NonZeroOid),
@@ -37,7 +38,7 @@ impl Display for OidOrLabel {
 }
 
 /// A command that can be applied for either in-memory or on-disk rebases.
-#[derive(Debug)]
+#[derive(Clone, Debug, Serialize, Deserialize)]
 pub enum RebaseCommand {
     /// Create a label (a reference stored in `refs/rewritten/`) pointing to the
     /// current rebase head for later use.
@@ -127,7 +128,7 @@ pub enum RebaseCommand {
 
 /// Represents a sequence of commands that can be executed to carry out a rebase
 /// operation.
-#[derive(Debug)]
+#[derive(Clone, Debug, Serialize, Deserialize)]
 pub struct RebasePlan {
     /// The first commit OID that will be checked out. This is necessary to
     /// support on-disk rebases.
@@ -221,6 +222,21 @@ impl RebasePlanPermissions {
                     public_commits_to_move,
                 }));
             }
+
+            let protected_commits = dag.query_ancestors(dag.protected_branches_commits.clone())?;
+            let protected_commits_to_move = protected_commits.intersection(&commits);
+            if !dag.set_is_empty(&protected_commits_to_move)? {
+                return Ok(Err(BuildRebasePlanError::MoveProtectedCommits {
+                    protected_commits_to_move,
+                }));
+            }
+
+            let tagged_commits_to_move = dag.protected_tags_commits.intersection(&commits);
+            if !dag.set_is_empty(&tagged_commits_to_move)? {
+                return Ok(Err(BuildRebasePlanError::MoveTaggedCommits {
+                    tagged_commits_to_move,
+                }));
+            }
         }
 
         Ok(Ok(RebasePlanPermissions {
@@ -629,6 +645,18 @@ pub enum BuildRebasePlanError {
         public_commits_to_move: CommitSet,
     },
 
+    /// The user was trying to move commits reachable from a protected branch.
+    MoveProtectedCommits {
+        /// The protected commits which the user was trying to move.
+        protected_commits_to_move: CommitSet,
+    },
+
+    /// The user was trying to move commits pointed to by tags.
+    MoveTaggedCommits {
+        /// The tagged commits which the user was trying to move.
+        tagged_commits_to_move: CommitSet,
+    },
+
     /// The user was trying to move commits that weren't verified before the
     /// rebase plan was built. This probably indicates a bug in the code.
     MoveIllegalCommits {
@@ -703,6 +731,60 @@ Retry with -f/--force-rewrite to proceed anyways.",
                 )?;
             }
 
+            BuildRebasePlanError::MoveProtectedCommits {
+                protected_commits_to_move,
+            } => {
+                let example_bad_commit_oid =
+                    dag.set_first(protected_commits_to_move)?.ok_or_else(|| {
+                        eyre::eyre!("BUG: could not get OID of a protected commit to move")
+                    })?;
+                let example_bad_commit_oid = NonZeroOid::try_from(example_bad_commit_oid)?;
+                let example_bad_commit = repo.find_commit_or_fail(example_bad_commit_oid)?;
+                writeln!(
+                    effects.get_output_stream(),
+                    "\
+You are trying to rewrite {}, such as: {}
+These commits are reachable from a branch matching branchless.protectedBranches,
+so they are likely shared with your collaborators.
+Retry with -f/--force-rewrite to proceed anyways.",
+                    Pluralize {
+                        determiner: None,
+                        amount: dag.set_count(protected_commits_to_move)?,
+                        unit: ("protected commit", "protected commits")
+                    },
+                    effects
+                        .get_glyphs()
+                        .render(example_bad_commit.friendly_describe(effects.get_glyphs())?)?,
+                )?;
+            }
+
+            BuildRebasePlanError::MoveTaggedCommits {
+                tagged_commits_to_move,
+            } => {
+                let example_bad_commit_oid =
+                    dag.set_first(tagged_commits_to_move)?.ok_or_else(|| {
+                        eyre::eyre!("BUG: could not get OID of a tagged commit to move")
+                    })?;
+                let example_bad_commit_oid = NonZeroOid::try_from(example_bad_commit_oid)?;
+                let example_bad_commit = repo.find_commit_or_fail(example_bad_commit_oid)?;
+                writeln!(
+                    effects.get_output_stream(),
+                    "\
+You are trying to rewrite {}, such as: {}
+Tags usually mark released versions, which shouldn't change.
+Retry with -f/--force-rewrite to proceed anyways, or set
+branchless.protectTags to false.",
+                    Pluralize {
+                        determiner: None,
+                        amount: dag.set_count(tagged_commits_to_move)?,
+                        unit: ("tagged commit", "tagged commits")
+                    },
+                    effects
+                        .get_glyphs()
+                        .render(example_bad_commit.friendly_describe(effects.get_glyphs())?)?,
+                )?;
+            }
+
             BuildRebasePlanError::MoveIllegalCommits {
                 illegal_commits_to_move,
             } => {
@@ -16,19 +16,27 @@ use tempfile::NamedTempFile;
 use tracing::instrument;
 
 use crate::core::check_out::CheckOutCommitOptions;
-use crate::core::config::{get_hint_enabled, print_hint_suppression_notice, Hint};
+use crate::core::config::{
+    get_hint_enabled, get_hooks_message_lint, get_hooks_post_rewrite, get_restack_auto,
+    get_restack_auto_restack_on_amend, get_restack_keep_empty_commits,
+    get_restack_prompt_abandoned, print_hint_suppression_notice, Hint,
+};
 use crate::core::dag::Dag;
 use crate::core::effects::Effects;
-use crate::core::eventlog::{Event, EventLogDb, EventReplayer};
+use crate::core::eventlog::{Event, EventLogDb, EventReplayer, EventTransactionId};
 use crate::core::formatting::Pluralize;
+use crate::core::hook_output::HookOutputDb;
+use crate::core::notes::mirror_rewrites_to_notes;
 use crate::core::repo_ext::RepoExt;
 use crate::git::{
     CategorizedReferenceName, GitRunInfo, MaybeZeroOid, NonZeroOid, ReferenceName, Repo,
     ResolvedReferenceInfo,
 };
 
-use super::execute::check_out_updated_head;
-use super::{find_abandoned_children, move_branches};
+use super::execute::{
+    check_out_updated_head, reapply_on_disk_auto_stash, should_keep_empty_commit,
+};
+use super::{find_abandoned_children, move_branches, update_edit_state};
 
 /// Get the path to the file which stores the list of "deferred commits".
 ///
@@ -126,6 +134,47 @@ fn add_rewritten_list_entries(
     Ok(())
 }
 
+/// Record commits which were amended during a rebase, such as by an `exec git
+/// commit --amend` command or after a `break`. Git only adds the commits it
+/// picks to the `rewritten-list` file, so without this, the original commits
+/// would be reported as rewritten into the pre-amend versions, and branches
+/// would be moved there at the end of the rebase.
+///
+/// Amends of commits which the rebase hasn't recorded yet, such as the ones
+/// Git makes itself to apply `fixup` commands, are ignored.
+#[instrument]
+fn add_amended_rewritten_list_entries(
+    tempfile_dir: &Path,
+    rewritten_list_path: &Path,
+    amended_oids: &[(NonZeroOid, MaybeZeroOid)],
+) -> eyre::Result<()> {
+    let mut entries = match File::open(rewritten_list_path) {
+        Ok(mut rewritten_list_file) => read_rewritten_list_entries(&mut rewritten_list_file)?,
+        Err(err) if err.kind() == std::io::ErrorKind::NotFound => return Ok(()),
+        Err(err) => return Err(err.into()),
+    };
+
+    let mut is_updated = false;
+    for (amended_old_oid, amended_new_oid) in amended_oids {
+        let mut is_rebased_commit = false;
+        for (_old_commit_oid, new_commit_oid) in entries.iter_mut() {
+            if *new_commit_oid == MaybeZeroOid::NonZero(*amended_old_oid) {
+                *new_commit_oid = *amended_new_oid;
+                is_rebased_commit = true;
+            }
+        }
+        if is_rebased_commit {
+            entries.push((*amended_old_oid, *amended_new_oid));
+            is_updated = true;
+        }
+    }
+
+    if is_updated {
+        write_rewritten_list(tempfile_dir, rewritten_list_path, entries.as_slice())?;
+    }
+    Ok(())
+}
+
 /// Handle Git's `post-rewrite` hook.
 ///
 /// See the man-page for `githooks(5)`.
@@ -141,25 +190,18 @@ pub fn hook_post_rewrite(
     let repo = Repo::from_current_dir()?;
     let is_spurious_event = rewrite_type == "amend" && repo.is_rebase_underway()?;
     if is_spurious_event {
+        let amended_oids = read_rewritten_list_entries(&mut stdin().lock())?;
+        add_amended_rewritten_list_entries(
+            &repo.get_tempfile_dir()?,
+            &repo.get_rebase_state_dir_path().join("rewritten-list"),
+            &amended_oids,
+        )?;
         return Ok(());
     }
 
     let conn = repo.get_db_conn()?;
     let event_log_db = EventLogDb::new(&conn)?;
-    let event_tx_id = event_log_db.make_transaction_id(now, "hook-post-rewrite")?;
-
-    {
-        let deferred_commit_oids = read_deferred_commits(&repo)?;
-        let commit_events = deferred_commit_oids
-            .into_iter()
-            .map(|commit_oid| Event::CommitEvent {
-                timestamp,
-                event_tx_id,
-                commit_oid,
-            })
-            .collect_vec();
-        event_log_db.add_events(commit_events)?;
-    }
+    let event_tx_id = event_log_db.make_hook_transaction_id(&repo, now, "hook-post-rewrite")?;
 
     let (rewritten_oids, rewrite_events) = {
         let rewritten_oids = read_rewritten_list_entries(&mut stdin().lock())?;
@@ -178,23 +220,82 @@ pub fn hook_post_rewrite(
         (rewritten_oids_map, events)
     };
 
+    let is_branchless_rebase = repo
+        .get_rebase_state_dir_path()
+        .join(EXTRA_POST_REWRITE_FILE_NAME)
+        .exists();
+    if is_branchless_rebase {
+        // In-memory rebases check the messages before moving any references,
+        // so do the same for on-disk rebases (including ones which fell back
+        // from in-memory rebases or which were continued after merge
+        // conflicts). Since no branches have been moved yet, the rebase can
+        // be undone by checking out the original `HEAD` again, without
+        // recording the new commits.
+        let new_commit_oids =
+            rewritten_oids
+                .iter()
+                .filter_map(|(old_oid, new_oid)| match new_oid {
+                    MaybeZeroOid::NonZero(new_oid) if new_oid != old_oid => Some(*new_oid),
+                    _ => None,
+                });
+        if !lint_commit_messages(effects, git_run_info, &repo, event_tx_id, new_commit_oids)? {
+            writeln!(
+                effects.get_output_stream(),
+                "Aborting since a commit message was rejected."
+            )?;
+            let previous_head_info = load_original_head_info(&repo)?;
+            match check_out_updated_head(
+                effects,
+                git_run_info,
+                &repo,
+                &event_log_db,
+                event_tx_id,
+                &HashMap::new(),
+                &previous_head_info,
+                None,
+                &CheckOutCommitOptions::default(),
+            )? {
+                Ok(()) => {}
+                Err(_exit_code) => {
+                    eyre::bail!("Could not check out your original `HEAD` commit.");
+                }
+            }
+            return Ok(());
+        }
+    }
+
+    {
+        let deferred_commit_oids = read_deferred_commits(&repo)?;
+        let commit_events = deferred_commit_oids
+            .into_iter()
+            .map(|commit_oid| Event::CommitEvent {
+                timestamp,
+                event_tx_id,
+                commit_oid,
+            })
+            .collect_vec();
+        event_log_db.add_events(commit_events)?;
+    }
+
     let message_rewritten_commits = Pluralize {
         determiner: None,
         amount: rewritten_oids.len(),
         unit: ("rewritten commit", "rewritten commits"),
     }
     .to_string();
-    writeln!(
-        effects.get_output_stream(),
-        "branchless: processing {message_rewritten_commits}"
+    HookOutputDb::new(&conn)?.print_hook_message(
+        effects,
+        event_tx_id,
+        format!("processing {message_rewritten_commits}"),
     )?;
     event_log_db.add_events(rewrite_events)?;
+    mirror_rewrites_to_notes(&repo, rewritten_oids.iter().map(|(k, v)| (*k, *v)))?;
 
-    if repo
-        .get_rebase_state_dir_path()
-        .join(EXTRA_POST_REWRITE_FILE_NAME)
-        .exists()
-    {
+    // Update the edit state before checking out the rewritten `HEAD`, so that
+    // the `post-checkout` hook doesn't consider it to have left the stack.
+    let is_editing = update_edit_state(&repo, &rewritten_oids)?;
+
+    if is_branchless_rebase {
         // Make sure to resolve `ORIG_HEAD` before we potentially delete the
         // branch it points to, so that we can get the original OID of `HEAD`.
         let previous_head_info = load_original_head_info(&repo)?;
@@ -217,6 +318,56 @@ pub fn hook_post_rewrite(
                 eyre::bail!("Could not check out your updated `HEAD` commit.");
             }
         }
+        reapply_on_disk_auto_stash(effects, git_run_info, &repo, event_tx_id)?;
+    }
+
+    notify_post_rewrite_command(
+        effects,
+        git_run_info,
+        &repo,
+        event_tx_id,
+        rewrite_type,
+        &rewritten_oids,
+    )?;
+
+    let should_restack =
+        if rewrite_type == "amend" && (is_editing || get_restack_auto_restack_on_amend(&repo)?) {
+            true
+        } else if get_restack_auto(&repo)? && !is_branchless_rebase {
+            // Rebases started by git-branchless already move the descendants
+            // of the rewritten commits.
+            has_abandoned_children(
+                effects,
+                &repo,
+                &event_log_db,
+                rewritten_oids.keys().copied(),
+            )?
+        } else {
+            false
+        };
+    if should_restack {
+        // Restack in-memory only, since we're still inside the `git commit`
+        // or `git rebase` invocation and shouldn't start an on-disk rebase.
+        let mut args = vec![
+            "branchless".to_string(),
+            "restack".to_string(),
+            "--in-memory".to_string(),
+        ];
+        args.extend(rewritten_oids.keys().sorted().map(|oid| oid.to_string()));
+        match git_run_info.run(effects, Some(event_tx_id), &args)? {
+            Ok(()) => return Ok(()),
+            Err(_exit_code) => {
+                writeln!(
+                    effects.get_output_stream(),
+                    "branchless: could not automatically restack the descendants of the {}",
+                    if rewrite_type == "amend" {
+                        "amended commit"
+                    } else {
+                        "rewritten commits"
+                    }
+                )?;
+            }
+        }
     }
 
     let should_check_abandoned_commits = get_hint_enabled(&repo, Hint::RestackWarnAbandoned)?;
@@ -224,7 +375,6 @@ pub fn hook_post_rewrite(
         let printed_hint = warn_abandoned(
             effects,
             &repo,
-            &conn,
             &event_log_db,
             rewritten_oids.keys().copied(),
         )?;
@@ -236,14 +386,154 @@ pub fn hook_post_rewrite(
     Ok(())
 }
 
+/// Invoke the `branchless.hooks.postRewrite` command, if configured, so that
+/// external tools can update their own references to the rewritten commits.
+///
+/// The command receives a JSON object on stdin of the form:
+///
+/// ```json
+/// {
+///   "rewrite_type": "rebase",
+///   "event_tx_id": 12,
+///   "rewritten_oids": [{"old_oid": "abc...", "new_oid": "def..."}]
+/// }
+/// ```
+///
+/// where `new_oid` is `null` if the commit was deleted rather than rewritten.
+#[instrument(skip(rewritten_oids))]
+pub fn notify_post_rewrite_command(
+    effects: &Effects,
+    git_run_info: &GitRunInfo,
+    repo: &Repo,
+    event_tx_id: EventTransactionId,
+    rewrite_type: &str,
+    rewritten_oids: &HashMap<NonZeroOid, MaybeZeroOid>,
+) -> eyre::Result<()> {
+    let command = match get_hooks_post_rewrite(repo)? {
+        Some(command) => command,
+        None => return Ok(()),
+    };
+    if rewritten_oids.is_empty() {
+        return Ok(());
+    }
+
+    let event_tx_id_json = match event_tx_id {
+        EventTransactionId::Id(id) => serde_json::Value::from(id),
+        EventTransactionId::Suppressed => serde_json::Value::Null,
+    };
+    let rewritten_oids_json: Vec<serde_json::Value> = rewritten_oids
+        .iter()
+        .sorted_by_key(|(old_oid, _new_oid)| **old_oid)
+        .map(|(old_oid, new_oid)| {
+            let new_oid = match new_oid {
+                MaybeZeroOid::NonZero(new_oid) => serde_json::Value::from(new_oid.to_string()),
+                MaybeZeroOid::Zero => serde_json::Value::Null,
+            };
+            serde_json::json!({
+                "old_oid": old_oid.to_string(),
+                "new_oid": new_oid,
+            })
+        })
+        .collect();
+    let payload = serde_json::json!({
+        "rewrite_type": rewrite_type,
+        "event_tx_id": event_tx_id_json,
+        "rewritten_oids": rewritten_oids_json,
+    });
+    let payload = serde_json::to_string(&payload).wrap_err("Serializing post-rewrite payload")?;
+
+    let exit_status =
+        git_run_info.run_shell_hook(effects, repo, &command, event_tx_id, Some(payload.into()))?;
+    if !exit_status.success() {
+        writeln!(
+            effects.get_error_stream(),
+            "branchless: the branchless.hooks.postRewrite command failed ({exit_status}): {command}"
+        )?;
+    }
+    Ok(())
+}
+
+/// Invoke the `branchless.hooks.messageLint` command, if configured, on the
+/// message of each of the given commits, which were newly created by rewriting
+/// commits (such as with `git move`, `git reword`, or `git amend`).
+///
+/// The command receives the commit message on stdin. Returns `false` if the
+/// command rejected any of the messages, in which case an error has already
+/// been printed, and the caller should abort before moving any references.
+#[instrument(skip(commit_oids))]
+pub fn lint_commit_messages(
+    effects: &Effects,
+    git_run_info: &GitRunInfo,
+    repo: &Repo,
+    event_tx_id: EventTransactionId,
+    commit_oids: impl IntoIterator<Item = NonZeroOid>,
+) -> eyre::Result<bool> {
+    let command = match get_hooks_message_lint(repo)? {
+        Some(command) => command,
+        None => return Ok(true),
+    };
+
+    let glyphs = effects.get_glyphs();
+    for commit_oid in commit_oids.into_iter().sorted() {
+        let commit = repo.find_commit_or_fail(commit_oid)?;
+        let exit_status = git_run_info.run_shell_hook(
+            effects,
+            repo,
+            &command,
+            event_tx_id,
+            Some(commit.get_message_raw()),
+        )?;
+        if !exit_status.success() {
+            writeln!(
+                effects.get_error_stream(),
+                "branchless: the branchless.hooks.messageLint command rejected the message of commit {} ({exit_status}): {command}",
+                glyphs.render(commit.friendly_describe(glyphs)?)?,
+            )?;
+            return Ok(false);
+        }
+    }
+    Ok(true)
+}
+
+/// Whether any of the given rewritten commits have visible children which
+/// weren't moved onto the new version of the commit.
 #[instrument(skip(old_commit_oids))]
-fn warn_abandoned(
+fn has_abandoned_children(
     effects: &Effects,
     repo: &Repo,
-    conn: &rusqlite::Connection,
     event_log_db: &EventLogDb,
     old_commit_oids: impl IntoIterator<Item = NonZeroOid>,
 ) -> eyre::Result<bool> {
+    let references_snapshot = repo.get_references_snapshot()?;
+    let event_replayer = EventReplayer::from_event_log_db(effects, repo, event_log_db)?;
+    let event_cursor = event_replayer.make_default_cursor();
+    let dag = Dag::open_and_sync(
+        effects,
+        repo,
+        &event_replayer,
+        event_cursor,
+        &references_snapshot,
+    )?;
+    for old_commit_oid in old_commit_oids {
+        if let Some((_rewritten_oid, abandoned_children)) =
+            find_abandoned_children(&dag, &event_replayer, event_cursor, old_commit_oid)?
+        {
+            if !abandoned_children.is_empty() {
+                return Ok(true);
+            }
+        }
+    }
+    Ok(false)
+}
+
+/// Find the commits and branches which were abandoned by rewriting the given
+/// commits.
+fn find_abandoned(
+    effects: &Effects,
+    repo: &Repo,
+    event_log_db: &EventLogDb,
+    old_commit_oids: &[NonZeroOid],
+) -> eyre::Result<(HashSet<NonZeroOid>, HashSet<String>)> {
     // The caller will have added events to the event log database, so make sure
     // to construct a fresh `EventReplayer` here.
     let references_snapshot = repo.get_references_snapshot()?;
@@ -257,25 +547,37 @@ fn warn_abandoned(
         &references_snapshot,
     )?;
 
-    let (all_abandoned_children, all_abandoned_branches) = {
-        let mut all_abandoned_children: HashSet<NonZeroOid> = HashSet::new();
-        let mut all_abandoned_branches: HashSet<&str> = HashSet::new();
-        for old_commit_oid in old_commit_oids {
-            let abandoned_result =
-                find_abandoned_children(&dag, &event_replayer, event_cursor, old_commit_oid)?;
-            let (_rewritten_oid, abandoned_children) = match abandoned_result {
-                Some(abandoned_result) => abandoned_result,
-                None => continue,
-            };
-            all_abandoned_children.extend(abandoned_children.iter());
-            if let Some(branch_names) = references_snapshot.branch_oid_to_names.get(&old_commit_oid)
-            {
-                all_abandoned_branches
-                    .extend(branch_names.iter().map(|branch_name| branch_name.as_str()));
-            }
+    let mut all_abandoned_children: HashSet<NonZeroOid> = HashSet::new();
+    let mut all_abandoned_branches: HashSet<String> = HashSet::new();
+    for old_commit_oid in old_commit_oids.iter().copied() {
+        let abandoned_result =
+            find_abandoned_children(&dag, &event_replayer, event_cursor, old_commit_oid)?;
+        let (_rewritten_oid, abandoned_children) = match abandoned_result {
+            Some(abandoned_result) => abandoned_result,
+            None => continue,
+        };
+        all_abandoned_children.extend(abandoned_children.iter());
+        if let Some(branch_names) = references_snapshot.branch_oid_to_names.get(&old_commit_oid) {
+            all_abandoned_branches.extend(
+                branch_names
+                    .iter()
+                    .map(|branch_name| branch_name.as_str().to_owned()),
+            );
         }
-        (all_abandoned_children, all_abandoned_branches)
-    };
+    }
+    Ok((all_abandoned_children, all_abandoned_branches))
+}
+
+#[instrument(skip(old_commit_oids))]
+fn warn_abandoned(
+    effects: &Effects,
+    repo: &Repo,
+    event_log_db: &EventLogDb,
+    old_commit_oids: impl IntoIterator<Item = NonZeroOid>,
+) -> eyre::Result<bool> {
+    let old_commit_oids = old_commit_oids.into_iter().collect_vec();
+    let (all_abandoned_children, all_abandoned_branches) =
+        find_abandoned(effects, repo, event_log_db, &old_commit_oids)?;
     let num_abandoned_children = all_abandoned_children.len();
     let num_abandoned_branches = all_abandoned_branches.len();
 
@@ -343,6 +645,94 @@ branchless:   - {git_undo}: undo the operation
     }
 }
 
+/// Offer to restack the commits and branches abandoned by the rewrites in the
+/// given event transaction, as part of the same transaction.
+///
+/// The answer is read from `input`. Git hooks can't prompt the user, since
+/// their standard input is provided by Git, so this should be called by the
+/// command which started the transaction once Git has finished.
+#[instrument(skip(input))]
+pub fn prompt_restack_abandoned(
+    effects: &Effects,
+    git_run_info: &GitRunInfo,
+    repo: &Repo,
+    event_tx_id: EventTransactionId,
+    input: &mut impl BufRead,
+) -> eyre::Result<()> {
+    if !get_restack_prompt_abandoned(repo)? {
+        return Ok(());
+    }
+
+    let conn = repo.get_db_conn()?;
+    let event_log_db = EventLogDb::new(&conn)?;
+    let old_commit_oids: Vec<NonZeroOid> = event_log_db
+        .get_events()?
+        .into_iter()
+        .filter_map(|event| match event {
+            Event::RewriteEvent {
+                timestamp: _,
+                event_tx_id: rewrite_event_tx_id,
+                old_commit_oid: MaybeZeroOid::NonZero(old_commit_oid),
+                new_commit_oid: _,
+            } if rewrite_event_tx_id == event_tx_id => Some(old_commit_oid),
+            _ => None,
+        })
+        .sorted()
+        .dedup()
+        .collect();
+    if old_commit_oids.is_empty() {
+        return Ok(());
+    }
+
+    let (all_abandoned_children, all_abandoned_branches) =
+        find_abandoned(effects, repo, &event_log_db, &old_commit_oids)?;
+    let what = if !all_abandoned_children.is_empty() {
+        Pluralize {
+            determiner: None,
+            amount: all_abandoned_children.len(),
+            unit: ("abandoned commit", "abandoned commits"),
+        }
+        .to_string()
+    } else if !all_abandoned_branches.is_empty() {
+        "the abandoned branches".to_string()
+    } else {
+        return Ok(());
+    };
+
+    write!(
+        effects.get_output_stream(),
+        "branchless: Restack {what} now? [y/N] "
+    )?;
+    let mut user_input = String::new();
+    let should_restack = match input.read_line(&mut user_input) {
+        Ok(_size) => {
+            let user_input = user_input.trim();
+            user_input == "y" || user_input == "Y"
+        }
+        Err(_) => false,
+    };
+    if !should_restack {
+        return Ok(());
+    }
+
+    let mut args = vec![
+        "branchless".to_string(),
+        "restack".to_string(),
+        "--in-memory".to_string(),
+    ];
+    args.extend(old_commit_oids.iter().map(|oid| oid.to_string()));
+    match git_run_info.run(effects, Some(event_tx_id), &args)? {
+        Ok(()) => {}
+        Err(_exit_code) => {
+            writeln!(
+                effects.get_output_stream(),
+                "branchless: could not restack the abandoned commits"
+            )?;
+        }
+    }
+    Ok(())
+}
+
 const ORIGINAL_HEAD_OID_FILE_NAME: &str = "branchless_original_head_oid";
 const ORIGINAL_HEAD_FILE_NAME: &str = "branchless_original_head";
 
@@ -462,7 +852,8 @@ pub fn hook_register_extra_post_rewrite_hook() -> eyre::Result<()> {
 
 /// For rebases, detect empty commits (which have probably been applied
 /// upstream) and write them to the `rewritten-list` file, so that they're later
-/// passed to the `post-rewrite` hook.
+/// passed to the `post-rewrite` hook. Commits which should be kept even when
+/// empty (see `branchless.restack.keepEmptyCommits`) are left alone.
 pub fn hook_drop_commit_if_empty(
     effects: &Effects,
     old_commit_oid: NonZeroOid,
@@ -481,6 +872,11 @@ pub fn hook_drop_commit_if_empty(
     if !head_commit.is_empty() {
         return Ok(());
     }
+    if let Some(old_commit) = repo.find_commit(old_commit_oid)? {
+        if should_keep_empty_commit(&old_commit, get_restack_keep_empty_commits(&repo)?)? {
+            return Ok(());
+        }
+    }
 
     let only_parent_oid = match head_commit.get_only_parent_oid() {
         Some(only_parent_oid) => only_parent_oid,
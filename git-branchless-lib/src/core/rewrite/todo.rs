@@ -0,0 +1,404 @@
+//! Parse and edit the todo list of an interactive rebase.
+//!
+//! Git stores the remaining commands of a `git rebase -i` in the
+//! `git-rebase-todo` file, and passes it to `sequence.editor` before the rebase
+//! starts and during `git rebase --edit-todo`. Lines other than commit
+//! commands, such as `exec`, `break`, `label`, `reset`, and comments, are kept
+//! verbatim, so that editing the todo list of an on-disk rebase started by
+//! git-branchless doesn't break its bookkeeping.
+
+use std::collections::HashMap;
+use std::fmt::{Display, Write};
+
+use bstr::ByteSlice;
+use tracing::instrument;
+
+use crate::core::dag::{CommitSet, Dag};
+use crate::git::{NonZeroOid, Repo};
+
+/// A line in the todo list of an interactive rebase.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum RebaseTodoLine {
+    /// A command which applies or drops a commit.
+    Commit {
+        /// The command, in its long form (such as `pick` or `fixup`).
+        command: String,
+
+        /// Options between the command and the commit, such as the `-C` of
+        /// `fixup -C`.
+        options: Vec<String>,
+
+        /// The commit, as written in the todo list. This is usually an
+        /// abbreviated OID.
+        commit: String,
+
+        /// The rest of the line, which is usually the commit summary.
+        rest: String,
+    },
+
+    /// Any other line, such as an `exec` command, a comment, or a blank line.
+    Other(String),
+}
+
+impl Display for RebaseTodoLine {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            RebaseTodoLine::Commit {
+                command,
+                options,
+                commit,
+                rest,
+            } => {
+                write!(f, "{command}")?;
+                for option in options {
+                    write!(f, " {option}")?;
+                }
+                write!(f, " {commit}")?;
+                if !rest.is_empty() {
+                    write!(f, " {rest}")?;
+                }
+                Ok(())
+            }
+            RebaseTodoLine::Other(line) => write!(f, "{line}"),
+        }
+    }
+}
+
+fn expand_commit_command(command: &str) -> Option<&'static str> {
+    match command {
+        "p" | "pick" => Some("pick"),
+        "r" | "reword" => Some("reword"),
+        "e" | "edit" => Some("edit"),
+        "s" | "squash" => Some("squash"),
+        "f" | "fixup" => Some("fixup"),
+        "d" | "drop" => Some("drop"),
+        _ => None,
+    }
+}
+
+impl RebaseTodoLine {
+    /// Parse a single line of a todo list.
+    pub fn parse(line: &str) -> Self {
+        let mut words = line.split_whitespace();
+        let command = match words.next().and_then(expand_commit_command) {
+            Some(command) => command,
+            None => return RebaseTodoLine::Other(line.to_string()),
+        };
+
+        let mut options = Vec::new();
+        let mut commit = None;
+        for word in words.by_ref() {
+            if word.starts_with('-') {
+                options.push(word.to_string());
+            } else {
+                commit = Some(word.to_string());
+                break;
+            }
+        }
+        match commit {
+            Some(commit) => RebaseTodoLine::Commit {
+                command: command.to_string(),
+                options,
+                commit,
+                rest: words.collect::<Vec<_>>().join(" "),
+            },
+            None => RebaseTodoLine::Other(line.to_string()),
+        }
+    }
+}
+
+/// Parse the contents of a todo list into its lines.
+pub fn parse_rebase_todo(contents: &str) -> Vec<RebaseTodoLine> {
+    contents.lines().map(RebaseTodoLine::parse).collect()
+}
+
+/// Render the lines of a todo list back into the contents of a todo list.
+pub fn render_rebase_todo(lines: &[RebaseTodoLine]) -> String {
+    let mut result = String::new();
+    for line in lines {
+        // Writing to a `String` can't fail.
+        writeln!(result, "{line}").unwrap();
+    }
+    result
+}
+
+/// The kind of commit which `git commit --fixup` and `git commit --squash`
+/// produce, as identified by the prefix of its summary.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum AutosquashKind {
+    Fixup,
+    Squash,
+    Amend,
+}
+
+/// Get the kind of autosquash commit and its target from its summary, such as
+/// `fixup! create foo.txt`. Repeated prefixes (`fixup! fixup! ...`) refer to
+/// the same target.
+fn parse_autosquash_summary(summary: &str) -> Option<(AutosquashKind, &str)> {
+    let (kind, mut target) = if let Some(target) = summary.strip_prefix("fixup! ") {
+        (AutosquashKind::Fixup, target)
+    } else if let Some(target) = summary.strip_prefix("squash! ") {
+        (AutosquashKind::Squash, target)
+    } else if let Some(target) = summary.strip_prefix("amend! ") {
+        (AutosquashKind::Amend, target)
+    } else {
+        return None;
+    };
+    while let Some(rest) = ["fixup! ", "squash! ", "amend! "]
+        .iter()
+        .find_map(|prefix| target.strip_prefix(prefix))
+    {
+        target = rest;
+    }
+    Some((kind, target.trim()))
+}
+
+/// Whether the autosquash target `target` refers to the commit written as
+/// `commit` in the todo list, by either its summary or a prefix of its OID.
+fn is_autosquash_target(target: &str, commit: &str, summary: &str) -> bool {
+    if summary == target {
+        return true;
+    }
+    let is_oid_prefix = target.len() >= 4 && target.chars().all(|c| c.is_ascii_hexdigit());
+    is_oid_prefix && (commit.starts_with(target) || target.starts_with(commit))
+}
+
+/// Move the `fixup!`, `squash!`, and `amend!` commits in the todo list after
+/// the commits which they target, and turn them into the corresponding `fixup`
+/// or `squash` commands, like `git rebase --autosquash` does. Only commits in
+/// the todo list are considered as targets.
+///
+/// `get_summary` is called with each commit as written in the todo list, and
+/// should return its summary. If it returns `None`, the rest of the todo line
+/// is used as the summary instead.
+#[instrument(skip(get_summary))]
+pub fn autosquash_rebase_todo(
+    lines: Vec<RebaseTodoLine>,
+    mut get_summary: impl FnMut(&str) -> eyre::Result<Option<String>>,
+) -> eyre::Result<Vec<RebaseTodoLine>> {
+    let mut summaries: HashMap<usize, String> = HashMap::new();
+    for (index, line) in lines.iter().enumerate() {
+        if let RebaseTodoLine::Commit {
+            command: _,
+            options: _,
+            commit,
+            rest,
+        } = line
+        {
+            let summary = get_summary(commit)?.unwrap_or_else(|| rest.clone());
+            summaries.insert(index, summary);
+        }
+    }
+
+    // Map from the index of each target line to the lines to move after it.
+    let mut fixups: HashMap<usize, Vec<(usize, AutosquashKind)>> = HashMap::new();
+    // Map from the index of each moved line to the index of its target.
+    let mut moved_lines: HashMap<usize, usize> = HashMap::new();
+    for (index, line) in lines.iter().enumerate() {
+        let command = match line {
+            RebaseTodoLine::Commit {
+                command,
+                options: _,
+                commit: _,
+                rest: _,
+            } => command,
+            RebaseTodoLine::Other(_) => continue,
+        };
+        if command != "pick" {
+            continue;
+        }
+        let (kind, target) = match parse_autosquash_summary(&summaries[&index]) {
+            Some(autosquash) => autosquash,
+            None => continue,
+        };
+
+        let target_index =
+            lines[..index]
+                .iter()
+                .enumerate()
+                .find_map(|(target_index, target_line)| match target_line {
+                    RebaseTodoLine::Commit {
+                        command: _,
+                        options: _,
+                        commit,
+                        rest: _,
+                    } if is_autosquash_target(target, commit, &summaries[&target_index]) => {
+                        Some(target_index)
+                    }
+                    RebaseTodoLine::Commit { .. } | RebaseTodoLine::Other(_) => None,
+                });
+        if let Some(target_index) = target_index {
+            // If the target was itself moved, then squash into its target
+            // instead, as `git rebase --autosquash` does.
+            let target_index = moved_lines
+                .get(&target_index)
+                .copied()
+                .unwrap_or(target_index);
+            fixups.entry(target_index).or_default().push((index, kind));
+            moved_lines.insert(index, target_index);
+        }
+    }
+
+    let mut result = Vec::with_capacity(lines.len());
+    for (index, line) in lines.iter().enumerate() {
+        if moved_lines.contains_key(&index) {
+            continue;
+        }
+        result.push(line.clone());
+        for (fixup_index, kind) in fixups.remove(&index).unwrap_or_default() {
+            if let RebaseTodoLine::Commit {
+                command: _,
+                options: _,
+                commit,
+                rest,
+            } = &lines[fixup_index]
+            {
+                let (command, options) = match kind {
+                    AutosquashKind::Fixup => ("fixup", vec![]),
+                    AutosquashKind::Squash => ("squash", vec![]),
+                    AutosquashKind::Amend => ("fixup", vec!["-C".to_string()]),
+                };
+                result.push(RebaseTodoLine::Commit {
+                    command: command.to_string(),
+                    options,
+                    commit: commit.clone(),
+                    rest: rest.clone(),
+                });
+            }
+        }
+    }
+    Ok(result)
+}
+
+/// Find the `fixup!` commits in `commits`, along with the commits which they
+/// should be folded into, using the same rules as [`autosquash_rebase_todo`].
+/// Only ancestors of each commit in `commits` are considered as its target.
+///
+/// The result is suitable for [`RebasePlanBuilder::fixup_commit`], which keeps
+/// the message of the target commit. `squash!` and `amend!` commits are not
+/// returned, since folding them in would lose the message changes which they
+/// carry.
+///
+/// [`RebasePlanBuilder::fixup_commit`]: super::RebasePlanBuilder::fixup_commit
+#[instrument]
+pub fn find_autosquash_commits(
+    repo: &Repo,
+    dag: &Dag,
+    commits: &CommitSet,
+) -> eyre::Result<Vec<(NonZeroOid, NonZeroOid)>> {
+    let mut summaries = Vec::new();
+    for commit_oid in dag.sort(commits)? {
+        let commit = repo.find_commit_or_fail(commit_oid)?;
+        let summary = commit.get_summary()?.to_str_lossy().into_owned();
+        summaries.push((commit_oid, summary));
+    }
+
+    let mut targets: HashMap<NonZeroOid, NonZeroOid> = HashMap::new();
+    let mut result = Vec::new();
+    for (index, (commit_oid, summary)) in summaries.iter().enumerate() {
+        let target = match parse_autosquash_summary(summary) {
+            Some((AutosquashKind::Fixup, target)) => target,
+            Some((AutosquashKind::Squash | AutosquashKind::Amend, _)) | None => continue,
+        };
+
+        let mut target_oid = None;
+        for (candidate_oid, candidate_summary) in summaries[..index].iter() {
+            if is_autosquash_target(target, &candidate_oid.to_string(), candidate_summary)
+                && dag.query_is_ancestor(*candidate_oid, *commit_oid)?
+            {
+                target_oid = Some(*candidate_oid);
+                break;
+            }
+        }
+        if let Some(target_oid) = target_oid {
+            // If the target is itself folded into another commit, then fold
+            // into that commit instead.
+            let target_oid = targets.get(&target_oid).copied().unwrap_or(target_oid);
+            targets.insert(*commit_oid, target_oid);
+            result.push((*commit_oid, target_oid));
+        }
+    }
+    Ok(result)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_rebase_todo_round_trip() {
+        let contents = "\
+pick 62fc20d create test1.txt
+f -C 96d1c37 amend! create test1.txt
+exec git branchless hook-detect-empty-commit 62fc20d2a290daea0d52bdc2ed2ad4be6491010e
+break
+label onto
+
+# Rebase f777ecc..96d1c37 onto f777ecc (2 commands)
+";
+        let lines = parse_rebase_todo(contents);
+        assert_eq!(
+            lines[..2],
+            [
+                RebaseTodoLine::Commit {
+                    command: "pick".to_string(),
+                    options: vec![],
+                    commit: "62fc20d".to_string(),
+                    rest: "create test1.txt".to_string(),
+                },
+                RebaseTodoLine::Commit {
+                    command: "fixup".to_string(),
+                    options: vec!["-C".to_string()],
+                    commit: "96d1c37".to_string(),
+                    rest: "amend! create test1.txt".to_string(),
+                },
+            ]
+        );
+        assert_eq!(lines[3], RebaseTodoLine::Other("break".to_string()));
+        assert_eq!(
+            render_rebase_todo(&lines),
+            contents.replace("f -C", "fixup -C")
+        );
+    }
+
+    #[test]
+    fn test_autosquash_rebase_todo() -> eyre::Result<()> {
+        let summaries: HashMap<&str, &str> = [
+            ("aaaa111", "create test1.txt"),
+            ("bbbb222", "create test2.txt"),
+            ("cccc333", "fixup! create test1.txt"),
+            ("dddd444", "squash! fixup! create test1.txt"),
+            ("eeee555", "fixup! bbbb222"),
+            ("ffff666", "fixup! create nonexistent.txt"),
+        ]
+        .into_iter()
+        .collect();
+        let lines = parse_rebase_todo(
+            "\
+pick aaaa111
+pick bbbb222
+exec make test
+pick cccc333
+pick dddd444
+pick eeee555
+pick ffff666
+",
+        );
+        let lines = autosquash_rebase_todo(lines, |commit| {
+            Ok(summaries.get(commit).map(|summary| summary.to_string()))
+        })?;
+        assert_eq!(
+            render_rebase_todo(&lines),
+            "\
+pick aaaa111
+fixup cccc333
+squash dddd444
+pick bbbb222
+fixup eeee555
+exec make test
+pick ffff666
+"
+        );
+        Ok(())
+    }
+}
@@ -0,0 +1,160 @@
+//! Named stacks of commits.
+//!
+//! A stack can be given a name such as `my-feature`, which can then be used
+//! anywhere a revset is expected to refer to the whole stack (see the
+//! `stack()` revset function). Only the tip of the stack is stored on disk;
+//! the rest of the stack is computed from the commit graph when the name is
+//! used. The tip follows its commit when it's rewritten, so that a stack keeps
+//! its name as its commits are amended, rebased, etc.
+
+use std::str::FromStr;
+
+use eyre::Context;
+use tracing::instrument;
+
+use crate::git::NonZeroOid;
+
+/// Stores the names assigned to stacks on disk.
+pub struct StackDb<'conn> {
+    conn: &'conn rusqlite::Connection,
+}
+
+impl std::fmt::Debug for StackDb<'_> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "<StackDb path={:?}>", self.conn.path())
+    }
+}
+
+/// Create the `named_stacks` table. This is called when the event log is
+/// opened, since rewrite events update the table.
+#[instrument]
+pub(crate) fn init_tables(conn: &rusqlite::Connection) -> eyre::Result<()> {
+    conn.execute(
+        "
+CREATE TABLE IF NOT EXISTS named_stacks (
+    name TEXT NOT NULL PRIMARY KEY,
+    tip_oid TEXT NOT NULL
+)
+",
+        rusqlite::params![],
+    )
+    .wrap_err("Creating `named_stacks` table")?;
+    Ok(())
+}
+
+/// Move the tips of any named stacks to the new versions of the rewritten
+/// commits.
+///
+/// This is called whenever rewrite events are added to the event log, so that
+/// stack names are stable across rewrites.
+#[instrument(skip(rewrites))]
+pub(crate) fn transfer_stack_tips(
+    conn: &rusqlite::Connection,
+    rewrites: impl IntoIterator<Item = (NonZeroOid, NonZeroOid)>,
+) -> eyre::Result<()> {
+    let mut stmt = conn.prepare_cached(
+        "
+UPDATE named_stacks
+SET tip_oid = :new_commit_oid
+WHERE tip_oid = :old_commit_oid
+",
+    )?;
+    for (old_commit_oid, new_commit_oid) in rewrites {
+        stmt.execute(rusqlite::named_params! {
+            ":old_commit_oid": old_commit_oid.to_string(),
+            ":new_commit_oid": new_commit_oid.to_string(),
+        })?;
+    }
+    Ok(())
+}
+
+impl<'conn> StackDb<'conn> {
+    /// Constructor.
+    #[instrument]
+    pub fn new(conn: &'conn rusqlite::Connection) -> eyre::Result<Self> {
+        init_tables(conn)?;
+        Ok(StackDb { conn })
+    }
+
+    /// Give the stack ending at `tip_oid` the provided name. If there is
+    /// already a stack with that name, it's replaced.
+    #[instrument]
+    pub fn set_stack(&self, name: &str, tip_oid: NonZeroOid) -> eyre::Result<()> {
+        self.conn.execute(
+            "
+INSERT OR REPLACE INTO named_stacks
+(name, tip_oid)
+VALUES
+(:name, :tip_oid)
+",
+            rusqlite::named_params! {
+                ":name": name,
+                ":tip_oid": tip_oid.to_string(),
+            },
+        )?;
+        Ok(())
+    }
+
+    /// Forget the stack with the given name. Returns whether there was such a
+    /// stack.
+    #[instrument]
+    pub fn delete_stack(&self, name: &str) -> eyre::Result<bool> {
+        let num_deleted = self.conn.execute(
+            "
+DELETE FROM named_stacks
+WHERE name = :name
+",
+            rusqlite::named_params! {
+                ":name": name,
+            },
+        )?;
+        Ok(num_deleted > 0)
+    }
+
+    /// Get the tip of the stack with the given name, if any.
+    #[instrument]
+    pub fn get_stack_tip(&self, name: &str) -> eyre::Result<Option<NonZeroOid>> {
+        let mut stmt = self.conn.prepare_cached(
+            "
+SELECT tip_oid
+FROM named_stacks
+WHERE name = :name
+",
+        )?;
+        let mut rows = stmt.query(rusqlite::named_params! {
+            ":name": name,
+        })?;
+        let tip_oid: String = match rows.next()? {
+            Some(row) => row.get("tip_oid")?,
+            None => return Ok(None),
+        };
+        let tip_oid = NonZeroOid::from_str(&tip_oid)
+            .wrap_err_with(|| format!("Parsing commit OID: {tip_oid:?}"))?;
+        Ok(Some(tip_oid))
+    }
+
+    /// Get the names and tips of all named stacks, sorted by name.
+    #[instrument]
+    pub fn get_stacks(&self) -> eyre::Result<Vec<(String, NonZeroOid)>> {
+        let mut stmt = self.conn.prepare_cached(
+            "
+SELECT name, tip_oid
+FROM named_stacks
+ORDER BY name
+",
+        )?;
+        let rows = stmt.query_map(rusqlite::named_params! {}, |row| {
+            let name: String = row.get("name")?;
+            let tip_oid: String = row.get("tip_oid")?;
+            Ok((name, tip_oid))
+        })?;
+        let mut result = Vec::new();
+        for row in rows {
+            let (name, tip_oid) = row?;
+            let tip_oid = NonZeroOid::from_str(&tip_oid)
+                .wrap_err_with(|| format!("Parsing commit OID: {tip_oid:?}"))?;
+            result.push((name, tip_oid));
+        }
+        Ok(result)
+    }
+}
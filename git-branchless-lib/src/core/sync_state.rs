@@ -0,0 +1,311 @@
+//! Share which commits are visible between clones of a repository, such as
+//! between two machines used by the same person.
+//!
+//! The events which affect the visibility of commits (commits, rewrites, and
+//! hiding and unhiding) are serialized into a commit which is stored at
+//! [`SYNC_STATE_REF_NAME`]. The commit's parents are the heads of the visible
+//! draft commits, so that pushing the reference to a remote also pushes the
+//! commits themselves.
+//!
+//! Only the events about the visible draft commits, and about the commits
+//! which were already synced, are stored. Commits which were hidden before
+//! they were ever synced, and secret commits, aren't mentioned at all.
+
+use std::collections::{HashMap, HashSet};
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+
+use eyre::Context;
+use serde::{Deserialize, Serialize};
+use tracing::instrument;
+
+use crate::core::eventlog::{Event, EventTransactionId};
+use crate::core::gc::get_event_commit_oids;
+use crate::git::{hydrate_tree, FileMode, MaybeZeroOid, NonZeroOid, Repo, Signature};
+
+/// The reference at which the synced state is stored, both locally and on the
+/// remote.
+pub const SYNC_STATE_REF_NAME: &str = "refs/branchless/state";
+
+/// The name of the file in the state commit which contains the events.
+const EVENTS_FILE_NAME: &str = "events.json";
+
+/// An event which affects the visibility of a commit, in the form in which
+/// it's stored in the synced state.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "kebab-case")]
+pub enum SyncedEvent {
+    /// See [`Event::CommitEvent`].
+    Commit {
+        /// The time of the event, in seconds since the Unix epoch.
+        timestamp: f64,
+        /// The commit which was created.
+        commit_oid: String,
+    },
+
+    /// See [`Event::ObsoleteEvent`].
+    Obsolete {
+        /// The time of the event, in seconds since the Unix epoch.
+        timestamp: f64,
+        /// The commit which was hidden.
+        commit_oid: String,
+    },
+
+    /// See [`Event::UnobsoleteEvent`].
+    Unobsolete {
+        /// The time of the event, in seconds since the Unix epoch.
+        timestamp: f64,
+        /// The commit which was unhidden.
+        commit_oid: String,
+    },
+
+    /// See [`Event::RewriteEvent`].
+    Rewrite {
+        /// The time of the event, in seconds since the Unix epoch.
+        timestamp: f64,
+        /// The commit which was rewritten.
+        old_commit_oid: String,
+        /// The commit which it was rewritten into.
+        new_commit_oid: String,
+    },
+}
+
+impl SyncedEvent {
+    fn from_event(event: &Event) -> Option<Self> {
+        match event {
+            Event::CommitEvent {
+                timestamp,
+                event_tx_id: _,
+                commit_oid,
+            } => Some(SyncedEvent::Commit {
+                timestamp: *timestamp,
+                commit_oid: commit_oid.to_string(),
+            }),
+            Event::ObsoleteEvent {
+                timestamp,
+                event_tx_id: _,
+                commit_oid,
+            } => Some(SyncedEvent::Obsolete {
+                timestamp: *timestamp,
+                commit_oid: commit_oid.to_string(),
+            }),
+            Event::UnobsoleteEvent {
+                timestamp,
+                event_tx_id: _,
+                commit_oid,
+            } => Some(SyncedEvent::Unobsolete {
+                timestamp: *timestamp,
+                commit_oid: commit_oid.to_string(),
+            }),
+            Event::RewriteEvent {
+                timestamp,
+                event_tx_id: _,
+                old_commit_oid,
+                new_commit_oid,
+            } => Some(SyncedEvent::Rewrite {
+                timestamp: *timestamp,
+                old_commit_oid: old_commit_oid.to_string(),
+                new_commit_oid: new_commit_oid.to_string(),
+            }),
+            Event::RefUpdateEvent { .. }
+            | Event::WorkingCopySnapshot { .. }
+            | Event::CopyEvent { .. } => None,
+        }
+    }
+
+    fn into_event(self, event_tx_id: EventTransactionId) -> eyre::Result<Event> {
+        let parse_oid = |oid: &str| {
+            NonZeroOid::from_str(oid).wrap_err_with(|| format!("Parsing commit OID: {oid:?}"))
+        };
+        let parse_maybe_zero_oid = |oid: &str| {
+            MaybeZeroOid::from_str(oid).wrap_err_with(|| format!("Parsing commit OID: {oid:?}"))
+        };
+        let event = match self {
+            SyncedEvent::Commit {
+                timestamp,
+                commit_oid,
+            } => Event::CommitEvent {
+                timestamp,
+                event_tx_id,
+                commit_oid: parse_oid(&commit_oid)?,
+            },
+            SyncedEvent::Obsolete {
+                timestamp,
+                commit_oid,
+            } => Event::ObsoleteEvent {
+                timestamp,
+                event_tx_id,
+                commit_oid: parse_oid(&commit_oid)?,
+            },
+            SyncedEvent::Unobsolete {
+                timestamp,
+                commit_oid,
+            } => Event::UnobsoleteEvent {
+                timestamp,
+                event_tx_id,
+                commit_oid: parse_oid(&commit_oid)?,
+            },
+            SyncedEvent::Rewrite {
+                timestamp,
+                old_commit_oid,
+                new_commit_oid,
+            } => Event::RewriteEvent {
+                timestamp,
+                event_tx_id,
+                old_commit_oid: parse_maybe_zero_oid(&old_commit_oid)?,
+                new_commit_oid: parse_maybe_zero_oid(&new_commit_oid)?,
+            },
+        };
+        Ok(event)
+    }
+
+    /// A key identifying the event, used to avoid importing an event which is
+    /// already present.
+    fn key(&self) -> eyre::Result<String> {
+        Ok(serde_json::to_string(self)?)
+    }
+}
+
+/// Create a commit containing the visibility events among `events` which
+/// only refer to commits in `commit_oids`, with `heads` as its parents. At
+/// most the newest `max_events` of them are kept. Returns the OID of the new
+/// commit, which isn't referenced by anything.
+#[instrument(skip(events, commit_oids))]
+pub fn write_sync_state(
+    repo: &Repo,
+    events: &[Event],
+    commit_oids: &HashSet<NonZeroOid>,
+    heads: Vec<NonZeroOid>,
+    max_events: usize,
+) -> eyre::Result<NonZeroOid> {
+    let mut synced_events: Vec<SyncedEvent> = events
+        .iter()
+        .filter(|event| {
+            get_event_commit_oids(event)
+                .iter()
+                .all(|commit_oid| commit_oids.contains(commit_oid))
+        })
+        .filter_map(SyncedEvent::from_event)
+        .collect();
+    if synced_events.len() > max_events {
+        synced_events.drain(..synced_events.len() - max_events);
+    }
+    let contents = serde_json::to_vec_pretty(&synced_events)?;
+    let blob_oid = repo.create_blob_from_contents(&contents)?;
+    let tree_oid = hydrate_tree(
+        repo,
+        None,
+        HashMap::from([(
+            PathBuf::from(EVENTS_FILE_NAME),
+            Some((blob_oid, FileMode::Blob)),
+        )]),
+    )?;
+    let tree = repo.find_tree_or_fail(tree_oid)?;
+
+    let mut parents = Vec::new();
+    for head_oid in heads {
+        parents.push(repo.find_commit_or_fail(head_oid)?);
+    }
+    let signature = Signature::automated()?;
+    let commit_oid = repo.create_commit(
+        None,
+        &signature,
+        &signature,
+        "branchless: synced state",
+        &tree,
+        parents.iter().collect(),
+    )?;
+    Ok(commit_oid)
+}
+
+/// Read the visibility events stored in the given state commit.
+#[instrument]
+pub fn read_sync_state(repo: &Repo, commit_oid: NonZeroOid) -> eyre::Result<Vec<SyncedEvent>> {
+    let commit = repo.find_commit_or_fail(commit_oid)?;
+    let tree = commit.get_tree()?;
+    let entry = match tree.get_path(Path::new(EVENTS_FILE_NAME))? {
+        Some(entry) => entry,
+        None => eyre::bail!("Synced state commit {commit_oid} does not contain {EVENTS_FILE_NAME}"),
+    };
+    let blob = repo.find_blob_or_fail(entry.get_oid())?;
+    let synced_events = serde_json::from_slice(blob.get_content())
+        .wrap_err_with(|| format!("Parsing {EVENTS_FILE_NAME} in commit {commit_oid}"))?;
+    Ok(synced_events)
+}
+
+/// Get the commits which the given synced events refer to.
+#[instrument(skip(synced_events))]
+pub fn get_synced_commit_oids(synced_events: &[SyncedEvent]) -> eyre::Result<HashSet<NonZeroOid>> {
+    let mut result = HashSet::new();
+    for synced_event in synced_events {
+        let event = synced_event
+            .clone()
+            .into_event(EventTransactionId::Suppressed)?;
+        result.extend(get_event_commit_oids(&event));
+    }
+    Ok(result)
+}
+
+/// Convert the synced events which aren't already among `local_events` into
+/// events belonging to the given transaction, so that they can be added to the
+/// local event log.
+#[instrument(skip(local_events, synced_events))]
+pub fn find_missing_events(
+    local_events: &[Event],
+    synced_events: Vec<SyncedEvent>,
+    event_tx_id: EventTransactionId,
+) -> eyre::Result<Vec<Event>> {
+    let mut seen_keys = HashSet::new();
+    for event in local_events {
+        if let Some(synced_event) = SyncedEvent::from_event(event) {
+            seen_keys.insert(synced_event.key()?);
+        }
+    }
+
+    let mut result = Vec::new();
+    for synced_event in synced_events {
+        if seen_keys.insert(synced_event.key()?) {
+            result.push(synced_event.into_event(event_tx_id)?);
+        }
+    }
+    result.sort_by_key(|event| event.get_timestamp());
+    Ok(result)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use crate::core::eventlog::testing::new_event_transaction_id;
+
+    #[test]
+    fn test_find_missing_events() -> eyre::Result<()> {
+        let event_tx_id = new_event_transaction_id(1);
+        let commit_oid = NonZeroOid::from_str("62fc20d2a290daea0d52bdc2ed2ad4be6491010e")?;
+        let local_events = vec![Event::CommitEvent {
+            timestamp: 1.0,
+            event_tx_id,
+            commit_oid,
+        }];
+        let synced_events = vec![
+            SyncedEvent::Commit {
+                timestamp: 1.0,
+                commit_oid: commit_oid.to_string(),
+            },
+            SyncedEvent::Obsolete {
+                timestamp: 2.0,
+                commit_oid: commit_oid.to_string(),
+            },
+        ];
+        let missing_events = find_missing_events(&local_events, synced_events, event_tx_id)?;
+        assert_eq!(
+            missing_events,
+            vec![Event::ObsoleteEvent {
+                timestamp: 2.0,
+                event_tx_id,
+                commit_oid,
+            }]
+        );
+        Ok(())
+    }
+}
@@ -0,0 +1,340 @@
+//! Reading Git's commit-graph file.
+//!
+//! `git commit-graph write` (also run by `git gc` and `git maintenance`)
+//! serializes the parents and generation numbers of commits into
+//! `objects/info/commit-graph`, or into a chain of files under
+//! `objects/info/commit-graphs` when written with `--split`. Looking up a
+//! commit's parents in this file is much cheaper than loading and parsing the
+//! commit object, which matters when walking many commits at once, such as
+//! when syncing a large number of new main branch commits into the DAG.
+//!
+//! See <https://git-scm.com/docs/gitformat-commit-graph> for the file format.
+
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+use std::time::SystemTime;
+
+use eyre::{bail, ensure, Context};
+use lazy_static::lazy_static;
+use tracing::instrument;
+
+use crate::git::oid::make_non_zero_oid;
+use crate::git::NonZeroOid;
+
+const SIGNATURE: &[u8; 4] = b"CGPH";
+const HEADER_LEN: usize = 8;
+const CHUNK_LOOKUP_ENTRY_LEN: usize = 12;
+const HASH_LEN: usize = 20;
+const COMMIT_DATA_LEN: usize = HASH_LEN + 16;
+
+const CHUNK_OID_FANOUT: u32 = u32::from_be_bytes(*b"OIDF");
+const CHUNK_OID_LOOKUP: u32 = u32::from_be_bytes(*b"OIDL");
+const CHUNK_COMMIT_DATA: u32 = u32::from_be_bytes(*b"CDAT");
+const CHUNK_EXTRA_EDGES: u32 = u32::from_be_bytes(*b"EDGE");
+
+const PARENT_NONE: u32 = 0x7000_0000;
+const PARENT_EXTRA_EDGE: u32 = 0x8000_0000;
+const LAST_EDGE: u32 = 0x8000_0000;
+
+/// The metadata of a commit-graph file which changes whenever the file is
+/// rewritten. Git replaces commit-graph files rather than modifying them in
+/// place, so this is enough to tell whether a cached copy is still current.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+struct LayerStamp {
+    modified: SystemTime,
+    len: u64,
+}
+
+lazy_static! {
+    /// The commit-graph files which have already been read by this process,
+    /// so that the DAG can be synced several times per command without
+    /// reading the whole file each time.
+    static ref LAYER_CACHE: Mutex<HashMap<PathBuf, (LayerStamp, Arc<Layer>)>> =
+        Default::default();
+}
+
+/// A single commit-graph file. When the commit-graph is split across several
+/// files, parent positions index into the combined list of commits of this
+/// layer and all of the layers below it.
+struct Layer {
+    path: PathBuf,
+    data: Vec<u8>,
+    num_commits: u32,
+    fanout_offset: usize,
+    oid_lookup_offset: usize,
+    commit_data_offset: usize,
+    extra_edges_offset: Option<usize>,
+}
+
+impl Layer {
+    fn read(path: PathBuf) -> eyre::Result<Self> {
+        let data = std::fs::read(&path).wrap_err_with(|| format!("Reading {path:?}"))?;
+        ensure!(
+            data.len() >= HEADER_LEN && &data[..4] == SIGNATURE,
+            "Not a commit-graph file: {path:?}"
+        );
+        let version = data[4];
+        ensure!(
+            version == 1,
+            "Unsupported commit-graph version {version} in {path:?}"
+        );
+        let hash_version = data[5];
+        ensure!(
+            hash_version == 1,
+            "Unsupported commit-graph hash version {hash_version} in {path:?}"
+        );
+        let num_chunks = usize::from(data[6]);
+
+        let mut fanout_offset = None;
+        let mut oid_lookup_offset = None;
+        let mut commit_data_offset = None;
+        let mut extra_edges_offset = None;
+        for i in 0..num_chunks {
+            let entry_offset = HEADER_LEN + i * CHUNK_LOOKUP_ENTRY_LEN;
+            let chunk_id = read_u32(&data, entry_offset)?;
+            let chunk_offset = usize::try_from(read_u64(&data, entry_offset + 4)?)?;
+            ensure!(
+                chunk_offset <= data.len(),
+                "Chunk offset out of bounds in {path:?}"
+            );
+            match chunk_id {
+                CHUNK_OID_FANOUT => fanout_offset = Some(chunk_offset),
+                CHUNK_OID_LOOKUP => oid_lookup_offset = Some(chunk_offset),
+                CHUNK_COMMIT_DATA => commit_data_offset = Some(chunk_offset),
+                CHUNK_EXTRA_EDGES => extra_edges_offset = Some(chunk_offset),
+                _ => {}
+            }
+        }
+
+        let (fanout_offset, oid_lookup_offset, commit_data_offset) =
+            match (fanout_offset, oid_lookup_offset, commit_data_offset) {
+                (Some(fanout_offset), Some(oid_lookup_offset), Some(commit_data_offset)) => {
+                    (fanout_offset, oid_lookup_offset, commit_data_offset)
+                }
+                _ => bail!("Missing required chunk in {path:?}"),
+            };
+        let num_commits = read_u32(&data, fanout_offset + 255 * 4)?;
+        let num_commits_usize = usize::try_from(num_commits)?;
+        ensure!(
+            oid_lookup_offset + num_commits_usize * HASH_LEN <= data.len()
+                && commit_data_offset + num_commits_usize * COMMIT_DATA_LEN <= data.len(),
+            "Truncated commit-graph file: {path:?}"
+        );
+
+        Ok(Self {
+            path,
+            data,
+            num_commits,
+            fanout_offset,
+            oid_lookup_offset,
+            commit_data_offset,
+            extra_edges_offset,
+        })
+    }
+
+    /// Read the layer at `path`, reusing the copy read earlier by this process
+    /// if the file hasn't changed since.
+    fn read_cached(path: PathBuf) -> eyre::Result<Arc<Self>> {
+        let metadata = std::fs::metadata(&path).wrap_err_with(|| format!("Reading {path:?}"))?;
+        let stamp = LayerStamp {
+            modified: metadata
+                .modified()
+                .wrap_err_with(|| format!("Reading modification time of {path:?}"))?,
+            len: metadata.len(),
+        };
+
+        let mut cache = LAYER_CACHE
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner());
+        if let Some((cached_stamp, layer)) = cache.get(&path) {
+            if *cached_stamp == stamp {
+                return Ok(Arc::clone(layer));
+            }
+        }
+        let layer = Arc::new(Self::read(path.clone())?);
+        cache.insert(path, (stamp, Arc::clone(&layer)));
+        Ok(layer)
+    }
+
+    fn get_oid(&self, local_pos: u32) -> &[u8] {
+        let offset = self.oid_lookup_offset + local_pos as usize * HASH_LEN;
+        &self.data[offset..offset + HASH_LEN]
+    }
+
+    fn find(&self, oid: &[u8]) -> Option<u32> {
+        let first_byte = usize::from(oid[0]);
+        let lo = match first_byte {
+            0 => 0,
+            _ => read_u32(&self.data, self.fanout_offset + (first_byte - 1) * 4).ok()?,
+        };
+        let hi = read_u32(&self.data, self.fanout_offset + first_byte * 4).ok()?;
+        let (mut lo, mut hi) = (lo, hi.min(self.num_commits));
+        while lo < hi {
+            let mid = lo + (hi - lo) / 2;
+            match self.get_oid(mid).cmp(oid) {
+                std::cmp::Ordering::Less => lo = mid + 1,
+                std::cmp::Ordering::Greater => hi = mid,
+                std::cmp::Ordering::Equal => return Some(mid),
+            }
+        }
+        None
+    }
+
+    fn get_commit_data(&self, local_pos: u32) -> &[u8] {
+        let offset = self.commit_data_offset + local_pos as usize * COMMIT_DATA_LEN;
+        &self.data[offset..offset + COMMIT_DATA_LEN]
+    }
+}
+
+/// The commit-graph of a repository, which may consist of several layers.
+pub struct CommitGraph {
+    /// The layers of the commit-graph, from the base layer to the topmost
+    /// layer.
+    layers: Vec<Arc<Layer>>,
+}
+
+impl std::fmt::Debug for CommitGraph {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let paths: Vec<&Path> = self
+            .layers
+            .iter()
+            .map(|layer| layer.path.as_path())
+            .collect();
+        write!(f, "<CommitGraph layers={paths:?}>")
+    }
+}
+
+impl CommitGraph {
+    /// Read the commit-graph from the given `objects` directory. Returns
+    /// `None` if no commit-graph has been written. Files which haven't changed
+    /// since they were last read by this process aren't read again.
+    #[instrument]
+    pub fn open(objects_dir: &Path) -> eyre::Result<Option<Self>> {
+        let info_dir = objects_dir.join("info");
+        let chain_path = info_dir.join("commit-graphs").join("commit-graph-chain");
+        let layers = match std::fs::read_to_string(&chain_path) {
+            Ok(chain) => chain
+                .lines()
+                .map(str::trim)
+                .filter(|line| !line.is_empty())
+                .map(|hash| {
+                    Layer::read_cached(
+                        info_dir
+                            .join("commit-graphs")
+                            .join(format!("graph-{hash}.graph")),
+                    )
+                })
+                .collect::<eyre::Result<Vec<_>>>()?,
+            Err(err) if err.kind() == std::io::ErrorKind::NotFound => {
+                let path = info_dir.join("commit-graph");
+                if !path.exists() {
+                    return Ok(None);
+                }
+                vec![Layer::read_cached(path)?]
+            }
+            Err(err) => return Err(err).wrap_err_with(|| format!("Reading {chain_path:?}")),
+        };
+        if layers.is_empty() {
+            return Ok(None);
+        }
+        Ok(Some(Self { layers }))
+    }
+
+    /// The total number of commits in the commit-graph.
+    pub fn len(&self) -> usize {
+        self.layers
+            .iter()
+            .map(|layer| layer.num_commits as usize)
+            .sum()
+    }
+
+    /// Whether the commit-graph contains no commits.
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Find the global position of the given commit, searching the topmost
+    /// layer first.
+    fn find(&self, oid: NonZeroOid) -> Option<u32> {
+        let mut base = self.len() as u32;
+        for layer in self.layers.iter().rev() {
+            base -= layer.num_commits;
+            if let Some(local_pos) = layer.find(oid.as_bytes()) {
+                return Some(base + local_pos);
+            }
+        }
+        None
+    }
+
+    fn resolve(&self, pos: u32) -> Option<(&Layer, u32)> {
+        let mut base = 0;
+        for layer in &self.layers {
+            if pos < base + layer.num_commits {
+                return Some((layer, pos - base));
+            }
+            base += layer.num_commits;
+        }
+        None
+    }
+
+    fn get_oid(&self, pos: u32) -> Option<NonZeroOid> {
+        let (layer, local_pos) = self.resolve(pos)?;
+        let oid = git2::Oid::from_bytes(layer.get_oid(local_pos)).ok()?;
+        Some(make_non_zero_oid(oid))
+    }
+
+    fn get_parent_positions(&self, pos: u32) -> Option<Vec<u32>> {
+        let (layer, local_pos) = self.resolve(pos)?;
+        let commit_data = layer.get_commit_data(local_pos);
+        let parent1 = read_u32(commit_data, HASH_LEN).ok()?;
+        let parent2 = read_u32(commit_data, HASH_LEN + 4).ok()?;
+
+        let mut parents = Vec::new();
+        if parent1 == PARENT_NONE {
+            return Some(parents);
+        }
+        parents.push(parent1);
+        if parent2 == PARENT_NONE {
+            // Single-parent commit.
+        } else if parent2 & PARENT_EXTRA_EDGE == 0 {
+            parents.push(parent2);
+        } else {
+            let extra_edges_offset = layer.extra_edges_offset?;
+            let mut edge_index = (parent2 & !PARENT_EXTRA_EDGE) as usize;
+            loop {
+                let edge = read_u32(&layer.data, extra_edges_offset + edge_index * 4).ok()?;
+                parents.push(edge & !LAST_EDGE);
+                if edge & LAST_EDGE != 0 {
+                    break;
+                }
+                edge_index += 1;
+            }
+        }
+        Some(parents)
+    }
+
+    /// Get the parents of the given commit, or `None` if the commit isn't in
+    /// the commit-graph.
+    pub fn get_parent_oids(&self, oid: NonZeroOid) -> Option<Vec<NonZeroOid>> {
+        let pos = self.find(oid)?;
+        self.get_parent_positions(pos)?
+            .into_iter()
+            .map(|parent_pos| self.get_oid(parent_pos))
+            .collect()
+    }
+}
+
+fn read_u32(data: &[u8], offset: usize) -> eyre::Result<u32> {
+    match data.get(offset..offset + 4) {
+        Some(bytes) => Ok(u32::from_be_bytes(bytes.try_into()?)),
+        None => bail!("Unexpected end of commit-graph data at offset {offset}"),
+    }
+}
+
+fn read_u64(data: &[u8], offset: usize) -> eyre::Result<u64> {
+    match data.get(offset..offset + 8) {
+        Some(bytes) => Ok(u64::from_be_bytes(bytes.try_into()?)),
+        None => bail!("Unexpected end of commit-graph data at offset {offset}"),
+    }
+}
@@ -0,0 +1,399 @@
+//! Convert the contents of files in the working copy into the form in which
+//! they're stored in the object database, in the same way as `git add`.
+//!
+//! In-memory operations create blobs directly from the working copy, so they
+//! have to apply the conversions themselves: the `clean` command of a filter
+//! driver set with the `filter` attribute, followed by line ending
+//! normalization as determined by the `text`, `eol`, and `crlf` attributes and
+//! the `core.autocrlf` setting.
+//!
+//! Conversions which aren't implemented here are left to Git, by storing the
+//! file with `git hash-object --path`: the `ident` and `working-tree-encoding`
+//! attributes, and filter drivers which use a long-running `process` command.
+//!
+//! Files tracked with Git LFS (`filter=lfs`) are stored as small pointer files
+//! by the `git lfs clean` command. If a file in the working copy is already a
+//! pointer file (such as when it was checked out with `GIT_LFS_SKIP_SMUDGE`),
+//! then it's stored as-is without invoking Git LFS.
+//!
+//! See the man page for `gitattributes(5)` for more detail.
+
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::process::{Command, Stdio};
+use std::str::FromStr;
+
+use eyre::Context;
+use tracing::{instrument, warn};
+
+use crate::core::config::env_vars::get_path_to_git;
+use crate::util::get_sh;
+
+use super::config::{Config, ConfigRead};
+use super::repo::{Error, Repo, Result};
+use super::{MaybeZeroOid, NonZeroOid};
+
+/// The name of the filter driver used by Git LFS.
+const LFS_FILTER_DRIVER: &str = "lfs";
+
+/// The first line of a Git LFS pointer file.
+/// See <https://github.com/git-lfs/git-lfs/blob/main/docs/spec.md>.
+const LFS_POINTER_VERSION_LINE: &[u8] = b"version https://git-lfs.github.com/spec/v1\n";
+
+/// Pointer files are always smaller than this many bytes.
+const LFS_POINTER_MAX_SIZE: usize = 1024;
+
+/// Whether the contents are a Git LFS pointer file, rather than the contents
+/// of the large file itself.
+pub fn is_lfs_pointer(contents: &[u8]) -> bool {
+    if contents.len() >= LFS_POINTER_MAX_SIZE {
+        return false;
+    }
+    let lines = match contents.strip_prefix(LFS_POINTER_VERSION_LINE) {
+        Some(rest) => rest,
+        None => return false,
+    };
+    let mut has_oid = false;
+    let mut has_size = false;
+    for line in lines.split(|byte| *byte == b'\n') {
+        if let Some(oid) = line.strip_prefix(b"oid sha256:") {
+            has_oid = oid.len() == 64 && oid.iter().all(u8::is_ascii_hexdigit);
+        } else if let Some(size) = line.strip_prefix(b"size ") {
+            has_size = !size.is_empty() && size.iter().all(u8::is_ascii_digit);
+        }
+    }
+    has_oid && has_size
+}
+
+/// How line endings should be converted when storing a file.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum CrlfAction {
+    /// Store the file as-is.
+    None,
+
+    /// Always convert CRLF line endings to LF.
+    Normalize,
+
+    /// Convert CRLF line endings to LF, but only if the file looks like text
+    /// and isn't already stored with CRLF line endings.
+    AutoNormalize,
+}
+
+pub(super) fn get_attr<'a>(
+    repo: &'a Repo,
+    path: &Path,
+    name: &str,
+) -> eyre::Result<git2::AttrValue<'a>> {
+    let value = repo
+        .inner
+        .get_attr(path, name, git2::AttrCheckFlags::FILE_THEN_INDEX)
+        .wrap_err_with(|| format!("Reading attribute {name:?} for {path:?}"))?;
+    Ok(git2::AttrValue::from_string(value))
+}
+
+fn get_crlf_action(repo: &Repo, autocrlf: Option<&str>, path: &Path) -> eyre::Result<CrlfAction> {
+    let action = match get_attr(repo, path, "text")? {
+        git2::AttrValue::True => CrlfAction::Normalize,
+        git2::AttrValue::False => CrlfAction::None,
+        git2::AttrValue::String("auto") => CrlfAction::AutoNormalize,
+        _ => match get_attr(repo, path, "crlf")? {
+            // `crlf` is the deprecated spelling of `text`.
+            git2::AttrValue::True | git2::AttrValue::String("input") => CrlfAction::Normalize,
+            git2::AttrValue::False => CrlfAction::None,
+            _ => match get_attr(repo, path, "eol")? {
+                // Setting `eol` implies that the file is text.
+                git2::AttrValue::String("lf" | "crlf") => CrlfAction::Normalize,
+                _ => match autocrlf {
+                    Some("true" | "input") => CrlfAction::AutoNormalize,
+                    _ => CrlfAction::None,
+                },
+            },
+        },
+    };
+    Ok(action)
+}
+
+/// Whether the contents look like a binary file, using the same heuristic as
+/// Git: the contents contain a NUL byte or a carriage return which isn't
+/// followed by a line feed.
+fn is_binary(contents: &[u8]) -> bool {
+    contents.iter().enumerate().any(|(i, byte)| match byte {
+        b'\0' => true,
+        b'\r' => contents.get(i + 1) != Some(&b'\n'),
+        _ => false,
+    })
+}
+
+/// Whether the version of the file in the index contains carriage returns. If
+/// so, Git doesn't automatically normalize its line endings, so that files
+/// which were committed with CRLF line endings don't appear to be modified.
+fn has_cr_in_index(repo: &Repo, path: &Path) -> eyre::Result<bool> {
+    let index = repo.get_index()?;
+    match index.get_entry(path) {
+        Some(entry) => match entry.oid {
+            MaybeZeroOid::NonZero(oid) => match repo.find_blob(oid)? {
+                Some(blob) => Ok(blob.get_content().contains(&b'\r')),
+                None => Ok(false),
+            },
+            MaybeZeroOid::Zero => Ok(false),
+        },
+        None => Ok(false),
+    }
+}
+
+fn normalize_line_endings(contents: Vec<u8>) -> Vec<u8> {
+    if !contents.windows(2).any(|window| window == b"\r\n") {
+        return contents;
+    }
+    let mut result = Vec::with_capacity(contents.len());
+    let mut iter = contents.into_iter().peekable();
+    while let Some(byte) = iter.next() {
+        if byte == b'\r' && iter.peek() == Some(&b'\n') {
+            continue;
+        }
+        result.push(byte);
+    }
+    result
+}
+
+/// Quote a path for use in a shell command, as Git does when substituting
+/// `%f` in a filter driver command.
+pub(super) fn shell_quote(path: &Path) -> String {
+    format!("'{}'", path.to_string_lossy().replace('\'', r"'\''"))
+}
+
+/// Get the name of the filter driver which should be run for the given path,
+/// if any.
+fn get_filter_driver(repo: &Repo, path: &Path, contents: &[u8]) -> eyre::Result<Option<String>> {
+    let driver = match get_attr(repo, path, "filter")? {
+        git2::AttrValue::String(driver) => driver.to_owned(),
+        _ => return Ok(None),
+    };
+    if driver == LFS_FILTER_DRIVER && is_lfs_pointer(contents) {
+        // This is what `git lfs clean` would produce anyways, and it avoids
+        // invoking Git LFS for every file.
+        return Ok(None);
+    }
+    Ok(Some(driver))
+}
+
+/// Run the `clean` command of the given filter driver.
+fn apply_filter_driver(
+    config: &Config,
+    working_copy_path: &Path,
+    path: &Path,
+    driver: &str,
+    contents: Vec<u8>,
+) -> eyre::Result<Vec<u8>> {
+    let clean_command: Option<String> = config.get(format!("filter.{driver}.clean"))?;
+    let is_required: bool = config.get_or(format!("filter.{driver}.required"), false)?;
+    let clean_command = match clean_command {
+        Some(clean_command) => clean_command,
+        None if is_required => {
+            eyre::bail!(
+                "Filter driver {driver:?} for {path:?} is required, but has no clean command"
+            )
+        }
+        None if driver == LFS_FILTER_DRIVER => {
+            // Storing the large file itself would defeat the purpose of using
+            // Git LFS, and couldn't be undone once pushed.
+            eyre::bail!(
+                "{path:?} is tracked with Git LFS, but Git LFS is not installed (try running `git lfs install`)"
+            )
+        }
+        None => return Ok(contents),
+    };
+
+    let command = clean_command.replace("%f", &shell_quote(path));
+    let mut child = Command::new(get_sh().ok_or_else(|| eyre::eyre!("could not get sh"))?)
+        .current_dir(working_copy_path)
+        .arg("-c")
+        .arg(&command)
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::inherit())
+        .spawn()
+        .wrap_err_with(|| format!("Invoking filter command: {command:?}"))?;
+    // Write stdin from a separate thread, since the filter may start writing
+    // its output before it has read all of its input.
+    let mut stdin = child.stdin.take().unwrap();
+    let stdin_thread = std::thread::spawn(move || stdin.write_all(&contents).map(|()| contents));
+    let output = child
+        .wait_with_output()
+        .wrap_err_with(|| format!("Waiting for filter command: {command:?}"))?;
+    let contents = match stdin_thread.join().unwrap() {
+        Ok(contents) => contents,
+        Err(err) => return Err(err).wrap_err("Writing filter command stdin"),
+    };
+
+    if output.status.success() {
+        Ok(output.stdout)
+    } else if is_required {
+        eyre::bail!(
+            "Filter command {command:?} for {path:?} failed with status {}",
+            output.status
+        )
+    } else {
+        // Git uses the unfiltered contents if an optional filter fails.
+        warn!(?command, ?path, status = ?output.status, "Filter command failed");
+        Ok(contents)
+    }
+}
+
+/// Stores files from the working copy as blobs, converting their contents in
+/// the same way as `git add`.
+///
+/// The repository configuration is read once when the converter is created,
+/// so use the same converter for all of the files stored by an operation.
+pub struct WorkingCopyConverter<'repo> {
+    repo: &'repo Repo,
+    config: Config,
+    autocrlf: Option<String>,
+}
+
+impl std::fmt::Debug for WorkingCopyConverter<'_> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "<WorkingCopyConverter for {:?}>", self.repo)
+    }
+}
+
+impl<'repo> WorkingCopyConverter<'repo> {
+    /// Constructor.
+    pub fn new(repo: &'repo Repo) -> Result<Self> {
+        let config = Config::from(repo.inner.config().map_err(Error::ReadConfig)?);
+        let autocrlf = config
+            .get("core.autocrlf")
+            .map_err(|err| Error::Other(format!("could not read core.autocrlf: {err}")))?;
+        Ok(Self {
+            repo,
+            config,
+            autocrlf,
+        })
+    }
+
+    /// Create a blob for the file at `path` (relative to the root of the
+    /// working copy at `working_copy_path`) with the given contents.
+    #[instrument(skip(contents))]
+    pub fn create_blob(
+        &self,
+        working_copy_path: &Path,
+        path: &Path,
+        contents: Vec<u8>,
+    ) -> eyre::Result<NonZeroOid> {
+        let driver = get_filter_driver(self.repo, path, &contents)?;
+        if self.should_defer_to_git(path, driver.as_deref())? {
+            return hash_object_with_git(working_copy_path, path, contents);
+        }
+
+        let contents = match driver {
+            Some(driver) => {
+                apply_filter_driver(&self.config, working_copy_path, path, &driver, contents)?
+            }
+            None => contents,
+        };
+        let contents = match get_crlf_action(self.repo, self.autocrlf.as_deref(), path)? {
+            CrlfAction::None => contents,
+            CrlfAction::Normalize => normalize_line_endings(contents),
+            CrlfAction::AutoNormalize => {
+                if is_binary(&contents) || has_cr_in_index(self.repo, path)? {
+                    contents
+                } else {
+                    normalize_line_endings(contents)
+                }
+            }
+        };
+        Ok(self.repo.create_blob_from_contents(&contents)?)
+    }
+
+    /// Whether storing the file requires a conversion which isn't implemented
+    /// here.
+    fn should_defer_to_git(&self, path: &Path, driver: Option<&str>) -> eyre::Result<bool> {
+        if let git2::AttrValue::True = get_attr(self.repo, path, "ident")? {
+            return Ok(true);
+        }
+        if let git2::AttrValue::String(_) = get_attr(self.repo, path, "working-tree-encoding")? {
+            return Ok(true);
+        }
+        if let Some(driver) = driver {
+            let process_command: Option<String> =
+                self.config.get(format!("filter.{driver}.process"))?;
+            if process_command.is_some() {
+                return Ok(true);
+            }
+        }
+        Ok(false)
+    }
+}
+
+/// Store the file with `git hash-object`, which applies all of the
+/// conversions that `git add` would.
+fn hash_object_with_git(
+    working_copy_path: &Path,
+    path: &Path,
+    contents: Vec<u8>,
+) -> eyre::Result<NonZeroOid> {
+    let path_to_git = get_path_to_git().unwrap_or_else(|_| PathBuf::from("git"));
+    let mut child = Command::new(&path_to_git)
+        .current_dir(working_copy_path)
+        .arg("hash-object")
+        .arg("-w")
+        .arg("--stdin")
+        .arg("--path")
+        .arg(path)
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::inherit())
+        .spawn()
+        .wrap_err_with(|| format!("Invoking git hash-object for {path:?}"))?;
+    // As with filter commands, write stdin from a separate thread, since Git
+    // may invoke a filter which writes its output before reading all of its
+    // input.
+    let mut stdin = child.stdin.take().unwrap();
+    let stdin_thread = std::thread::spawn(move || stdin.write_all(&contents));
+    let output = child
+        .wait_with_output()
+        .wrap_err_with(|| format!("Waiting for git hash-object for {path:?}"))?;
+    stdin_thread
+        .join()
+        .unwrap()
+        .wrap_err("Writing git hash-object stdin")?;
+    if !output.status.success() {
+        eyre::bail!(
+            "git hash-object for {path:?} failed with status {}",
+            output.status
+        );
+    }
+
+    let oid = String::from_utf8(output.stdout).wrap_err("Decoding git hash-object output")?;
+    let oid = NonZeroOid::from_str(oid.trim())?;
+    Ok(oid)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_normalize_line_endings() {
+        assert_eq!(
+            normalize_line_endings(b"foo\r\nbar\n".to_vec()),
+            b"foo\nbar\n"
+        );
+        assert_eq!(
+            normalize_line_endings(b"foo\rbar\r".to_vec()),
+            b"foo\rbar\r"
+        );
+        assert!(is_binary(b"foo\rbar"));
+        assert!(!is_binary(b"foo\r\nbar"));
+    }
+
+    #[test]
+    fn test_is_lfs_pointer() {
+        let oid_line = format!("oid sha256:{}\n", "a".repeat(64));
+        let pointer = format!("version https://git-lfs.github.com/spec/v1\n{oid_line}size 12345\n");
+        assert!(is_lfs_pointer(pointer.as_bytes()));
+        let pointer = format!("version https://git-lfs.github.com/spec/v1\n{oid_line}");
+        assert!(!is_lfs_pointer(pointer.as_bytes()));
+        assert!(!is_lfs_pointer(b"large file contents\n"));
+    }
+}
@@ -15,6 +15,47 @@ pub struct Diff<'repo> {
     pub(super) inner: git2::Diff<'repo>,
 }
 
+impl Diff<'_> {
+    /// Detect renamed and copied files in this diff, so that they're reported
+    /// as a single delta with both an old and a new path, rather than as a
+    /// deletion and an addition.
+    pub fn find_renames_and_copies(&mut self) -> eyre::Result<()> {
+        self.inner
+            .find_similar(Some(
+                git2::DiffFindOptions::new().renames(true).copies(true),
+            ))
+            .wrap_err("Finding renamed and copied files in diff")?;
+        Ok(())
+    }
+
+    /// Render this diff as a patch, in the same format as `git diff`.
+    pub fn to_patch(&self) -> eyre::Result<Vec<u8>> {
+        let mut result = Vec::new();
+        self.inner
+            .print(git2::DiffFormat::Patch, |_delta, _hunk, line| {
+                match line.origin() {
+                    origin @ ('+' | '-' | ' ') => result.push(origin as u8),
+                    _ => {}
+                }
+                result.extend_from_slice(line.content());
+                true
+            })
+            .wrap_err("Printing diff as patch")?;
+        Ok(result)
+    }
+
+    /// Render a summary of the files changed by this diff and the number of
+    /// lines inserted and deleted in each, in the same format as `git diff
+    /// --stat`.
+    pub fn to_stat(&self, width: usize) -> eyre::Result<Vec<u8>> {
+        let stats = self.inner.stats().wrap_err("Calculating diff stats")?;
+        let buf = stats
+            .to_buf(git2::DiffStatsFormat::FULL, width)
+            .wrap_err("Printing diff stats")?;
+        Ok(buf.to_vec())
+    }
+}
+
 #[derive(Clone, Debug, PartialEq, Eq, Hash)]
 struct GitHunk {
     old_start: usize,
@@ -38,6 +79,8 @@ pub fn process_diff_for_record(repo: &Repo, diff: &Diff) -> eyre::Result<Vec<Fil
 
     #[derive(Clone, Debug)]
     struct Delta {
+        /// The path that the file was renamed or copied from, if any.
+        old_path: Option<PathBuf>,
         old_oid: git2::Oid,
         old_file_mode: git2::FileMode,
         new_oid: git2::Oid,
@@ -48,25 +91,38 @@ pub fn process_diff_for_record(repo: &Repo, diff: &Diff) -> eyre::Result<Vec<Fil
     diff.foreach(
         &mut |delta, _| {
             let mut deltas = deltas.lock().unwrap();
-            let old_file = delta.old_file().path().unwrap().into();
-            let new_file = delta.new_file().path().unwrap().into();
+            let old_file: PathBuf = delta.old_file().path().unwrap().into();
+            let new_file: PathBuf = delta.new_file().path().unwrap().into();
+            let old_path = match delta.status() {
+                git2::Delta::Renamed | git2::Delta::Copied => Some(old_file.clone()),
+                _ => None,
+            };
             let delta = Delta {
+                old_path,
                 old_oid: delta.old_file().id(),
                 old_file_mode: delta.old_file().mode(),
                 new_oid: delta.new_file().id(),
                 new_file_mode: delta.new_file().mode(),
                 content: DeltaFileContent::Hunks(Default::default()),
             };
-            deltas.insert(old_file, delta.clone());
+            // A renamed or copied file is only reported under its new path.
+            if delta.old_path.is_none() {
+                deltas.insert(old_file, delta.clone());
+            }
             deltas.insert(new_file, delta);
             true
         },
         Some(&mut |delta, _| {
             let mut deltas = deltas.lock().unwrap();
 
-            let old_file = delta.old_file().path().unwrap().into();
-            let new_file = delta.new_file().path().unwrap().into();
+            let old_file: PathBuf = delta.old_file().path().unwrap().into();
+            let new_file: PathBuf = delta.new_file().path().unwrap().into();
+            let old_path = match delta.status() {
+                git2::Delta::Renamed | git2::Delta::Copied => Some(old_file.clone()),
+                _ => None,
+            };
             let delta = Delta {
+                old_path,
                 old_oid: delta.old_file().id(),
                 old_file_mode: delta.old_file().mode(),
                 new_oid: delta.new_file().id(),
@@ -76,7 +132,10 @@ pub fn process_diff_for_record(repo: &Repo, diff: &Diff) -> eyre::Result<Vec<Fil
                     new_num_bytes: delta.new_file().size(),
                 },
             };
-            deltas.insert(old_file, delta.clone());
+            // A renamed or copied file is only reported under its new path.
+            if delta.old_path.is_none() {
+                deltas.insert(old_file, delta.clone());
+            }
             deltas.insert(new_file, delta);
             true
         }),
@@ -106,6 +165,7 @@ pub fn process_diff_for_record(repo: &Repo, diff: &Diff) -> eyre::Result<Vec<Fil
     let mut result = Vec::new();
     for (path, delta) in deltas {
         let Delta {
+            old_path,
             old_oid,
             old_file_mode,
             new_oid,
@@ -119,7 +179,7 @@ pub fn process_diff_for_record(repo: &Repo, diff: &Diff) -> eyre::Result<Vec<Fil
 
         if new_oid.is_zero() {
             result.push(File {
-                old_path: None,
+                old_path: old_path.map(Cow::Owned),
                 path: Cow::Owned(path),
                 file_mode: Some(old_file_mode),
                 sections: vec![Section::FileMode {
@@ -137,7 +197,7 @@ pub fn process_diff_for_record(repo: &Repo, diff: &Diff) -> eyre::Result<Vec<Fil
                 new_num_bytes,
             } => {
                 result.push(File {
-                    old_path: None,
+                    old_path: old_path.map(Cow::Owned),
                     path: Cow::Owned(path),
                     file_mode: Some(old_file_mode),
                     sections: vec![Section::Binary {
@@ -363,7 +423,15 @@ pub fn process_diff_for_record(repo: &Repo, diff: &Diff) -> eyre::Result<Vec<Fil
             }
         }
 
-        let file_mode_section = if old_file_mode != new_file_mode {
+        let file_mode_section = if old_path.is_some() {
+            // Selecting a renamed or copied file creates it at its new path,
+            // just like selecting an added file.
+            vec![Section::FileMode {
+                is_checked: false,
+                before: FileMode::absent(),
+                after: new_file_mode,
+            }]
+        } else if old_file_mode != new_file_mode {
             vec![Section::FileMode {
                 is_checked: false,
                 before: old_file_mode,
@@ -373,7 +441,7 @@ pub fn process_diff_for_record(repo: &Repo, diff: &Diff) -> eyre::Result<Vec<Fil
             vec![]
         };
         result.push(File {
-            old_path: None,
+            old_path: old_path.map(Cow::Owned),
             path: Cow::Owned(path),
             file_mode: Some(old_file_mode),
             sections: [file_mode_section, file_sections].concat().to_vec(),
@@ -69,6 +69,20 @@ impl Index {
         self.inner.has_conflicts()
     }
 
+    /// Get the paths which have unresolved merge conflicts in the index.
+    pub fn get_conflicting_paths(&self) -> eyre::Result<Vec<PathBuf>> {
+        let mut paths = Vec::new();
+        for conflict in self.inner.conflicts()? {
+            let conflict = conflict?;
+            if let Some(entry) = conflict.our.or(conflict.their).or(conflict.ancestor) {
+                paths.push(PathBuf::from(
+                    String::from_utf8_lossy(&entry.path).into_owned(),
+                ));
+            }
+        }
+        Ok(paths)
+    }
+
     /// Get the (stage 0) entry for the given path.
     pub fn get_entry(&self, path: &Path) -> Option<IndexEntry> {
         self.get_entry_in_stage(path, Stage::Stage0)
@@ -88,6 +102,18 @@ impl Index {
                 },
             })
     }
+
+    /// Whether the given path has the `skip-worktree` bit set, such as when
+    /// it's outside of the sparse-checkout cone. Such paths are not expected
+    /// to be present in the working copy, so their absence on disk doesn't
+    /// mean that they've been deleted.
+    pub fn is_skip_worktree(&self, path: &Path) -> bool {
+        match self.inner.get_path(path, i32::from(Stage::Stage0)) {
+            Some(entry) => git2::IndexEntryExtendedFlag::from_bits_truncate(entry.flags_extended)
+                .contains(git2::IndexEntryExtendedFlag::SKIP_WORKTREE),
+            None => false,
+        }
+    }
 }
 
 /// The command to update the index, as defined by `git update-index`.
@@ -163,5 +189,42 @@ pub fn update_index(
             },
         )
         .wrap_err("Updating index")?;
+
+    // `git update-index --index-info` clears the `skip-worktree` bit, so
+    // restore it for any updated paths which were outside of the
+    // sparse-checkout cone. Otherwise, they would subsequently appear to have
+    // been deleted from the working copy.
+    let skip_worktree_paths = {
+        let mut buf = Vec::new();
+        for command in commands {
+            use std::io::Write;
+
+            match command {
+                UpdateIndexCommand::Update {
+                    path,
+                    stage: Stage::Stage0,
+                    ..
+                } if index.is_skip_worktree(path) => {
+                    write!(&mut buf, "{}\0", path.display())?;
+                }
+                UpdateIndexCommand::Update { .. } | UpdateIndexCommand::Delete { .. } => {}
+            }
+        }
+        buf
+    };
+    if !skip_worktree_paths.is_empty() {
+        let GitRunResult { .. } = git_run_info
+            .run_silent(
+                repo,
+                Some(event_tx_id),
+                &["update-index", "-z", "--skip-worktree", "--stdin"],
+                GitRunOpts {
+                    treat_git_failure_as_error: true,
+                    stdin: Some(skip_worktree_paths),
+                },
+            )
+            .wrap_err("Restoring skip-worktree bits")?;
+    }
+
     Ok(())
 }
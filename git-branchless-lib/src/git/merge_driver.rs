@@ -0,0 +1,154 @@
+//! Run custom merge drivers during in-memory merges.
+//!
+//! A merge driver is set for a path with the `merge` attribute in
+//! `.gitattributes`, and defined with the `merge.<driver>.driver` setting. Its
+//! command is run with `%O`, `%A`, and `%B` replaced by the paths to temporary
+//! files holding the base, current (ours), and other (theirs) versions of the
+//! file, `%S`, `%X`, and `%Y` by the conflict labels of those versions, `%L` by
+//! the conflict marker size, `%P` by the path of the file, and `%%` by `%`.
+//! The driver leaves the merged contents in the `%A` file, and exits with a
+//! non-zero status if the file still has conflicts.
+//!
+//! `libgit2` only implements the built-in `text`, `binary`, and `union`
+//! drivers, so custom drivers have to be run by us.
+//!
+//! See the man page for `gitattributes(5)` for more detail.
+
+use std::path::Path;
+use std::process::{Command, Stdio};
+
+use eyre::Context;
+use tracing::instrument;
+
+use crate::util::get_sh;
+
+use super::convert::{get_attr, shell_quote};
+use super::repo::Repo;
+
+/// The default length of conflict markers, if not set with the
+/// `conflict-marker-size` attribute.
+const DEFAULT_CONFLICT_MARKER_SIZE: &str = "7";
+
+/// The three versions of a file to merge.
+#[derive(Debug)]
+pub struct MergeDriverInput<'a> {
+    /// The contents of the file in the merge base.
+    pub base: &'a [u8],
+
+    /// The contents of the file in the current commit.
+    pub ours: &'a [u8],
+
+    /// The contents of the file in the commit being merged.
+    pub theirs: &'a [u8],
+
+    /// The conflict label of the merge base.
+    pub base_label: &'a str,
+
+    /// The conflict label of the current commit.
+    pub ours_label: &'a str,
+
+    /// The conflict label of the commit being merged.
+    pub theirs_label: &'a str,
+}
+
+/// Replace the placeholders in a merge driver command in a single pass, so
+/// that the replaced values are never expanded again. Unknown placeholders are
+/// kept as-is, as Git does.
+fn expand_command(command: &str, replacements: &[(char, &str)]) -> String {
+    let mut result = String::with_capacity(command.len());
+    let mut chars = command.chars();
+    while let Some(c) = chars.next() {
+        if c != '%' {
+            result.push(c);
+            continue;
+        }
+        match chars.next() {
+            Some('%') => result.push('%'),
+            Some(placeholder) => {
+                match replacements
+                    .iter()
+                    .find(|(key, _value)| *key == placeholder)
+                {
+                    Some((_key, value)) => result.push_str(value),
+                    None => {
+                        result.push('%');
+                        result.push(placeholder);
+                    }
+                }
+            }
+            None => result.push('%'),
+        }
+    }
+    result
+}
+
+/// Merge the file at `path` (relative to the root of the working copy) by
+/// running the merge driver command `command` from the working copy at
+/// `working_copy_path`.
+///
+/// Returns the merged contents, or `None` if the driver left conflicts in the
+/// file.
+#[instrument(skip(input))]
+pub fn run_merge_driver(
+    repo: &Repo,
+    working_copy_path: &Path,
+    command: &str,
+    path: &Path,
+    input: MergeDriverInput,
+) -> eyre::Result<Option<Vec<u8>>> {
+    let marker_size = match get_attr(repo, path, "conflict-marker-size")? {
+        git2::AttrValue::String(marker_size) => marker_size.to_owned(),
+        _ => DEFAULT_CONFLICT_MARKER_SIZE.to_owned(),
+    };
+
+    let temp_dir = tempfile::tempdir().wrap_err("Creating merge driver temporary directory")?;
+    let base_path = temp_dir.path().join("base");
+    let ours_path = temp_dir.path().join("ours");
+    let theirs_path = temp_dir.path().join("theirs");
+    let MergeDriverInput {
+        base,
+        ours,
+        theirs,
+        base_label,
+        ours_label,
+        theirs_label,
+    } = input;
+    for (file_path, contents) in [
+        (&base_path, base),
+        (&ours_path, ours),
+        (&theirs_path, theirs),
+    ] {
+        std::fs::write(file_path, contents)
+            .wrap_err_with(|| format!("Writing merge driver input to {file_path:?}"))?;
+    }
+
+    let command = expand_command(
+        command,
+        &[
+            ('O', &shell_quote(&base_path)),
+            ('A', &shell_quote(&ours_path)),
+            ('B', &shell_quote(&theirs_path)),
+            ('S', &shell_quote(Path::new(base_label))),
+            ('X', &shell_quote(Path::new(ours_label))),
+            ('Y', &shell_quote(Path::new(theirs_label))),
+            ('L', &marker_size),
+            ('P', &shell_quote(path)),
+        ],
+    );
+    let status = Command::new(get_sh().ok_or_else(|| eyre::eyre!("could not get sh"))?)
+        .current_dir(working_copy_path)
+        .arg("-c")
+        .arg(&command)
+        .stdin(Stdio::null())
+        .stdout(Stdio::null())
+        .stderr(Stdio::inherit())
+        .status()
+        .wrap_err_with(|| format!("Invoking merge driver command: {command:?}"))?;
+    if !status.success() {
+        return Ok(None);
+    }
+
+    let contents = std::fs::read(&ours_path)
+        .wrap_err_with(|| format!("Reading merge driver output from {ours_path:?}"))?;
+    Ok(Some(contents))
+}
@@ -1,8 +1,12 @@
 //! Tools for interfacing with the Git repository.
 
+mod commit_graph;
 mod config;
+mod convert;
 mod diff;
 mod index;
+mod merge_driver;
+mod notes;
 mod object;
 mod oid;
 mod reference;
@@ -13,22 +17,26 @@ mod status;
 mod test;
 mod tree;
 
+pub use commit_graph::CommitGraph;
 pub use config::{Config, ConfigRead, ConfigValue, ConfigWrite};
+pub use convert::WorkingCopyConverter;
 pub use diff::{process_diff_for_record, Diff};
 pub use index::{update_index, Index, IndexEntry, Stage, UpdateIndexCommand};
+pub use notes::{list_notes, read_note, write_notes};
 pub use object::Commit;
 pub use oid::{MaybeZeroOid, NonZeroOid};
 pub use reference::{
     Branch, BranchType, CategorizedReferenceName, Reference, ReferenceName, ReferenceTarget,
 };
+pub(crate) use repo::Signature;
 pub use repo::{
-    message_prettify, AmendFastOptions, CherryPickFastOptions, CreateCommitFastError,
-    Error as RepoError, GitVersion, PatchId, Repo, ResolvedReferenceInfo, Result as RepoResult,
-    Time,
+    fetch_missing_objects, message_prettify, AmendFastOptions, CherryPickFastOptions,
+    CreateCommitFastError, Error as RepoError, GitVersion, PatchId, Repo, ResolvedReferenceInfo,
+    Result as RepoResult, Time,
 };
 pub use run::{GitRunInfo, GitRunOpts, GitRunResult};
 pub use snapshot::{WorkingCopyChangesType, WorkingCopySnapshot};
-pub use status::{FileMode, FileStatus, StatusEntry};
+pub use status::{FileMode, FileStatus, StatusEntry, SubmoduleState};
 pub use test::{
     get_latest_test_command_path, get_test_locks_dir, get_test_tree_dir, get_test_worktrees_dir,
     make_test_command_slug, SerializedNonZeroOid, SerializedTestResult, TestCommand,
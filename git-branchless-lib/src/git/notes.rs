@@ -0,0 +1,125 @@
+//! Read and write Git notes, which attach messages to objects without changing
+//! them. See the man page for `git-notes(1)`.
+
+use std::collections::HashMap;
+use std::path::PathBuf;
+use std::time::SystemTime;
+
+use eyre::Context;
+use tracing::instrument;
+
+use super::oid::make_non_zero_oid;
+use super::repo::{Repo, Signature};
+use super::{hydrate_tree, FileMode, NonZeroOid, ReferenceName, Tree};
+
+/// Get the message of the note attached to `oid` under `notes_ref`, if any.
+#[instrument]
+pub fn read_note(
+    repo: &Repo,
+    notes_ref: &ReferenceName,
+    oid: NonZeroOid,
+) -> eyre::Result<Option<String>> {
+    match repo.inner.find_note(Some(notes_ref.as_str()), oid.inner) {
+        Ok(note) => Ok(Some(
+            String::from_utf8_lossy(note.message_bytes()).into_owned(),
+        )),
+        Err(err) if err.code() == git2::ErrorCode::NotFound => Ok(None),
+        Err(err) => Err(err).wrap_err_with(|| format!("Reading note for {oid} from {notes_ref:?}")),
+    }
+}
+
+/// Find the path of the note attached to `oid` in the tree of a notes
+/// reference. Git nests notes under two-character fanout directories once
+/// there are many of them, so the note may not be at the top level.
+fn find_note_path(tree: &Tree, oid: NonZeroOid) -> eyre::Result<Option<PathBuf>> {
+    let hex = oid.to_string();
+    let mut prefix = PathBuf::new();
+    let mut offset = 0;
+    while offset + 2 < hex.len() {
+        let path = prefix.join(&hex[offset..]);
+        if tree.get_path(&path)?.is_some() {
+            return Ok(Some(path));
+        }
+
+        prefix.push(&hex[offset..offset + 2]);
+        offset += 2;
+        if tree.get_path(&prefix)?.is_none() {
+            break;
+        }
+    }
+    Ok(None)
+}
+
+/// Attach notes with the given messages to objects under `notes_ref`,
+/// replacing any existing notes for those objects. All of the notes are
+/// written in a single commit to the notes reference.
+#[instrument]
+pub fn write_notes(
+    repo: &Repo,
+    notes_ref: &ReferenceName,
+    messages: &HashMap<NonZeroOid, String>,
+) -> eyre::Result<()> {
+    if messages.is_empty() {
+        return Ok(());
+    }
+
+    let parent_commit = match repo.find_reference(notes_ref)? {
+        Some(reference) => reference.peel_to_commit()?,
+        None => None,
+    };
+    let parent_tree = match &parent_commit {
+        Some(parent_commit) => Some(parent_commit.get_tree()?),
+        None => None,
+    };
+
+    let mut entries = HashMap::new();
+    for (oid, message) in messages {
+        let path = match &parent_tree {
+            Some(parent_tree) => find_note_path(parent_tree, *oid)?,
+            None => None,
+        };
+        let path = path.unwrap_or_else(|| PathBuf::from(oid.to_string()));
+        let blob_oid = repo.create_blob_from_contents(message.as_bytes())?;
+        entries.insert(path, Some((blob_oid, FileMode::Blob)));
+    }
+    let tree_oid = hydrate_tree(repo, parent_tree.as_ref(), entries)?;
+    let tree = repo.find_tree_or_fail(tree_oid)?;
+
+    let signature = Signature::automated()?.update_timestamp(SystemTime::now())?;
+    repo.create_commit(
+        Some(notes_ref.as_str()),
+        &signature,
+        &signature,
+        "Notes added by git-branchless",
+        &tree,
+        parent_commit.iter().collect(),
+    )
+    .wrap_err_with(|| format!("Writing notes to {notes_ref:?}"))?;
+    Ok(())
+}
+
+/// Get all of the notes under `notes_ref`, as pairs of the annotated object's
+/// OID and the note's message.
+#[instrument]
+pub fn list_notes(
+    repo: &Repo,
+    notes_ref: &ReferenceName,
+) -> eyre::Result<Vec<(NonZeroOid, String)>> {
+    let notes = match repo.inner.notes(Some(notes_ref.as_str())) {
+        Ok(notes) => notes,
+        Err(err) if err.code() == git2::ErrorCode::NotFound => return Ok(Vec::new()),
+        Err(err) => return Err(err).wrap_err_with(|| format!("Listing notes in {notes_ref:?}")),
+    };
+
+    let mut result = Vec::new();
+    for note in notes {
+        let (note_oid, annotated_oid) =
+            note.wrap_err_with(|| format!("Listing notes in {notes_ref:?}"))?;
+        let blob = repo.find_blob_or_fail(make_non_zero_oid(note_oid))?;
+        result.push((
+            make_non_zero_oid(annotated_oid),
+            String::from_utf8_lossy(blob.get_content()).into_owned(),
+        ));
+    }
+    Ok(result)
+}
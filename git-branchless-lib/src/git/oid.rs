@@ -4,6 +4,7 @@ use std::fmt::Display;
 use std::str::FromStr;
 
 use eyre::Context;
+use serde::{Deserialize, Serialize};
 use tracing::instrument;
 
 use crate::git::repo::wrap_git_error;
@@ -91,6 +92,19 @@ impl FromStr for NonZeroOid {
     }
 }
 
+impl Serialize for NonZeroOid {
+    fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.collect_str(self)
+    }
+}
+
+impl<'de> Deserialize<'de> for NonZeroOid {
+    fn deserialize<D: serde::Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let value = String::deserialize(deserializer)?;
+        value.parse().map_err(serde::de::Error::custom)
+    }
+}
+
 impl From<NonZeroOid> for git2::Oid {
     fn from(oid: NonZeroOid) -> Self {
         oid.inner
@@ -113,7 +113,18 @@ impl<'repo> Reference<'repo> {
     pub fn peel_to_commit(&self) -> Result<Option<Commit<'repo>>> {
         let object = match self.inner.peel(git2::ObjectType::Commit) {
             Ok(object) => object,
-            Err(err) if err.code() == git2::ErrorCode::NotFound => return Ok(None),
+            Err(err)
+                if matches!(
+                    err.code(),
+                    git2::ErrorCode::NotFound
+                        | git2::ErrorCode::InvalidSpec
+                        | git2::ErrorCode::Peel
+                ) =>
+            {
+                // The reference points to an object which can't be peeled to
+                // a commit, such as a tree or blob.
+                return Ok(None);
+            }
             Err(err) => return Err(Error::ResolveReference(err)),
         };
         match object.into_commit() {
@@ -157,6 +168,15 @@ pub enum CategorizedReferenceName<'a> {
         prefix: &'static str,
     },
 
+    /// The reference represents a tag.
+    Tag {
+        /// The full name of the reference.
+        name: &'a str,
+
+        /// The string `refs/tags/`.
+        prefix: &'static str,
+    },
+
     /// Some other kind of reference which isn't a branch at all.
     OtherRef {
         /// The full name of the reference.
@@ -178,6 +198,11 @@ impl<'a> CategorizedReferenceName<'a> {
                 name,
                 prefix: "refs/remotes/",
             }
+        } else if name.starts_with("refs/tags/") {
+            Self::Tag {
+                name,
+                prefix: "refs/tags/",
+            }
         } else {
             Self::OtherRef { name }
         }
@@ -189,6 +214,7 @@ impl<'a> CategorizedReferenceName<'a> {
         let name = match self {
             Self::LocalBranch { name, prefix: _ } => name,
             Self::RemoteBranch { name, prefix: _ } => name,
+            Self::Tag { name, prefix: _ } => name,
             Self::OtherRef { name } => name,
         };
         (*name).to_owned()
@@ -201,6 +227,7 @@ impl<'a> CategorizedReferenceName<'a> {
         let (name, prefix): (_, &'static str) = match self {
             Self::LocalBranch { name, prefix } => (name, prefix),
             Self::RemoteBranch { name, prefix } => (name, prefix),
+            Self::Tag { name, prefix } => (name, prefix),
             Self::OtherRef { name } => (name, ""),
         };
         name.strip_prefix(prefix).unwrap_or(name).to_owned()
@@ -217,6 +244,7 @@ impl<'a> CategorizedReferenceName<'a> {
             CategorizedReferenceName::RemoteBranch { .. } => {
                 format!("remote branch {name}")
             }
+            CategorizedReferenceName::Tag { .. } => format!("tag {name}"),
             CategorizedReferenceName::OtherRef { .. } => format!("ref {name}"),
         }
     }
@@ -31,17 +31,20 @@ use tracing::{instrument, warn};
 use crate::core::effects::{Effects, OperationType};
 use crate::core::eventlog::EventTransactionId;
 use crate::core::formatting::Glyphs;
+use crate::git::commit_graph::CommitGraph;
 use crate::git::config::{Config, ConfigRead};
+use crate::git::convert::WorkingCopyConverter;
+use crate::git::merge_driver::{run_merge_driver, MergeDriverInput};
 use crate::git::object::Blob;
 use crate::git::oid::{make_non_zero_oid, MaybeZeroOid, NonZeroOid};
 use crate::git::reference::ReferenceNameError;
-use crate::git::run::GitRunInfo;
+use crate::git::run::{GitRunInfo, GitRunOpts, GitRunResult};
 use crate::git::tree::{dehydrate_tree, get_changed_paths_between_trees, hydrate_tree, Tree};
 use crate::git::{Branch, BranchType, Commit, Reference, ReferenceName};
 
 use super::index::{Index, IndexEntry};
 use super::snapshot::WorkingCopySnapshot;
-use super::status::FileMode;
+use super::status::{FileMode, FileStatus};
 use super::{tree, Diff, StatusEntry};
 
 #[allow(missing_docs)]
@@ -102,6 +105,12 @@ pub enum Error {
     #[error("could not create blob from {path}: {source}")]
     CreateBlobFromPath { source: eyre::Error, path: PathBuf },
 
+    #[error("could not determine file mode of {path}: {source}")]
+    ReadWorkingCopyFileMode { source: eyre::Error, path: PathBuf },
+
+    #[error("could not read checked-out commit for submodule at {path}: {source}")]
+    ReadSubmoduleHead { source: git2::Error, path: PathBuf },
+
     #[error("could not find commit {oid}: {source}")]
     FindCommit {
         source: git2::Error,
@@ -195,6 +204,21 @@ pub enum Error {
     #[error("could not create working copy snapshot: {0}")]
     CreateSnapshot(#[source] eyre::Error),
 
+    #[error("could not read shallow commits from {path}: {source}")]
+    ReadShallowCommits { source: eyre::Error, path: PathBuf },
+
+    #[error("could not read commit-graph: {0}")]
+    ReadCommitGraph(#[source] eyre::Error),
+
+    #[error("could not read promisor remotes: {0}")]
+    ReadPromisorRemotes(#[source] eyre::Error),
+
+    #[error("could not read merge driver for {path}: {source}")]
+    ReadMergeDriver { source: eyre::Error, path: PathBuf },
+
+    #[error("could not read object database: {0}")]
+    ReadObjectDatabase(#[source] git2::Error),
+
     #[error("could not create reference: {0}")]
     CreateReference(#[source] git2::Error),
 
@@ -415,6 +439,15 @@ pub enum CreateCommitFastError {
     #[error(transparent)]
     HydrateTree(tree::Error),
 
+    /// Some objects needed for the cherry-pick aren't present locally. This
+    /// can happen in a partial clone, in which case they would need to be
+    /// fetched from a promisor remote first.
+    #[error("{} objects needed for the cherry-pick are missing", oids.len())]
+    MissingObjects {
+        /// The OIDs of the missing objects.
+        oids: Vec<NonZeroOid>,
+    },
+
     #[error(transparent)]
     Repo(#[from] Error),
 
@@ -625,7 +658,16 @@ impl Repo {
     pub fn get_dag_dir(&self) -> Result<PathBuf> {
         // Updated from `dag` to `dag2` for `esl01-dag==0.3.0`, since it may
         // not be backwards-compatible.
-        Ok(self.get_branchless_dir()?.join("dag2"))
+        let dag_dir_name = if self.is_shallow() {
+            // In a shallow clone, the commits at the shallow boundary are
+            // added to the DAG without their parents. Keep that DAG separate,
+            // so that their real parents are picked up once the repository is
+            // unshallowed.
+            "dag2-shallow"
+        } else {
+            "dag2"
+        };
+        Ok(self.get_branchless_dir()?.join(dag_dir_name))
     }
 
     /// Get the directory to store man-pages. Note that this is the `man`
@@ -656,6 +698,15 @@ impl Repo {
             source: err,
             path: path.clone(),
         })?;
+
+        // Use write-ahead logging, so that writes (such as from hooks invoked
+        // many times during a `git fetch`) don't need to wait for `fsync` on
+        // every transaction, and don't block concurrent readers. The journal
+        // mode is persisted in the database file, so this is cheap after the
+        // first time.
+        conn.pragma_update_and_check(None, "journal_mode", "WAL", |row| row.get::<_, String>(0))
+            .and_then(|_journal_mode| conn.pragma_update(None, "synchronous", "NORMAL"))
+            .map_err(|err| Error::OpenDatabase { source: err, path })?;
         Ok(conn)
     }
 
@@ -940,6 +991,22 @@ impl Repo {
         Ok(all_references)
     }
 
+    /// Get all references whose names match the given glob, such as
+    /// `refs/tags/*`.
+    #[instrument]
+    pub fn get_references_glob(&self, glob: &str) -> Result<Vec<Reference>> {
+        let mut references = Vec::new();
+        for reference in self
+            .inner
+            .references_glob(glob)
+            .map_err(Error::GetReferences)?
+        {
+            let reference = reference.map_err(Error::ReadReference)?;
+            references.push(Reference { inner: reference });
+        }
+        Ok(references)
+    }
+
     /// Check if the repository has staged or unstaged changes. Untracked files
     /// are not included. This operation may take a while.
     #[instrument]
@@ -1022,6 +1089,14 @@ impl Repo {
             statuses.push(entry);
         }
 
+        // Paths outside of the sparse-checkout cone are absent from the
+        // working copy by design, so don't report them as deleted.
+        statuses.retain(|entry| {
+            !(entry.index_status == FileStatus::Unmodified
+                && entry.working_copy_status == FileStatus::Deleted
+                && index.is_skip_worktree(&entry.path))
+        });
+
         let snapshot = WorkingCopySnapshot::create(self, index, head_info, &statuses)
             .map_err(Error::CreateSnapshot)?;
         Ok((snapshot, statuses))
@@ -1138,6 +1213,122 @@ impl Repo {
         }
     }
 
+    /// Determine whether this repository is a shallow clone, i.e. some
+    /// commits' parents are missing from the object database.
+    pub fn is_shallow(&self) -> bool {
+        self.inner.is_shallow()
+    }
+
+    /// Get the commits at the boundary of a shallow clone, whose parents are
+    /// missing from the object database. Returns an empty set if this
+    /// repository isn't a shallow clone.
+    #[instrument]
+    pub fn get_shallow_commit_oids(&self) -> Result<HashSet<NonZeroOid>> {
+        let path = self.inner.commondir().join("shallow");
+        let contents = match std::fs::read_to_string(&path) {
+            Ok(contents) => contents,
+            Err(err) if err.kind() == std::io::ErrorKind::NotFound => return Ok(HashSet::new()),
+            Err(err) => {
+                return Err(Error::ReadShallowCommits {
+                    source: err.into(),
+                    path,
+                })
+            }
+        };
+        contents
+            .lines()
+            .map(str::trim)
+            .filter(|line| !line.is_empty())
+            .map(|line| {
+                NonZeroOid::from_str(line).map_err(|err| Error::ReadShallowCommits {
+                    source: err,
+                    path: path.clone(),
+                })
+            })
+            .collect()
+    }
+
+    /// Get the commit-graph written by `git commit-graph write`, if any.
+    /// Returns `None` if no commit-graph has been written or if it's been
+    /// disabled with `core.commitGraph`.
+    #[instrument]
+    pub fn get_commit_graph(&self) -> Result<Option<CommitGraph>> {
+        let config = self.get_readonly_config()?;
+        let enabled: bool = config
+            .get_or("core.commitGraph", true)
+            .map_err(Error::ReadCommitGraph)?;
+        if !enabled {
+            return Ok(None);
+        }
+        CommitGraph::open(&self.inner.commondir().join("objects")).map_err(Error::ReadCommitGraph)
+    }
+
+    /// Get the names of the promisor remotes for this repository, i.e. the
+    /// remotes from which objects missing from a partial clone can be
+    /// fetched. Returns an empty list if this repository isn't a partial clone.
+    #[instrument]
+    pub fn get_promisor_remote_names(&self) -> Result<Vec<String>> {
+        let config = self.get_readonly_config()?;
+        let mut result = Vec::new();
+        if let Some(remote_name) = config
+            .get::<String, _>("extensions.partialClone")
+            .map_err(Error::ReadPromisorRemotes)?
+        {
+            result.push(remote_name);
+        }
+        for (key, _value) in config
+            .list(r"^remote\..*\.promisor$")
+            .map_err(Error::ReadPromisorRemotes)?
+        {
+            let is_promisor = config
+                .get_or(&key, false)
+                .map_err(Error::ReadPromisorRemotes)?;
+            let remote_name = key
+                .strip_prefix("remote.")
+                .and_then(|key| key.strip_suffix(".promisor"));
+            if let (true, Some(remote_name)) = (is_promisor, remote_name) {
+                if !result.iter().any(|name| name == remote_name) {
+                    result.push(remote_name.to_owned());
+                }
+            }
+        }
+        Ok(result)
+    }
+
+    /// Find the blobs at the given paths in the given commits which aren't
+    /// present in the object database, such as in a blobless partial clone.
+    #[instrument]
+    pub fn find_missing_blobs(
+        &self,
+        commits: &[&Commit],
+        paths: &[&Path],
+    ) -> Result<Vec<NonZeroOid>> {
+        let odb = self.inner.odb().map_err(Error::ReadObjectDatabase)?;
+        let mut result = HashSet::new();
+        for commit in commits {
+            let tree = commit.get_tree()?;
+            for path in paths {
+                let entry = match tree.get_path(path).map_err(Error::ReadTreeEntry)? {
+                    Some(entry) => entry,
+                    None => continue,
+                };
+                match entry.get_filemode() {
+                    FileMode::Blob
+                    | FileMode::BlobExecutable
+                    | FileMode::BlobGroupWritable
+                    | FileMode::Link => {
+                        let oid = entry.get_oid();
+                        if !odb.exists(oid.inner) {
+                            result.insert(oid);
+                        }
+                    }
+                    FileMode::Commit | FileMode::Tree | FileMode::Unreadable => {}
+                }
+            }
+        }
+        Ok(result.into_iter().sorted().collect())
+    }
+
     /// Like `find_blob`, but raises a generic error if the blob could not be
     /// found.
     #[instrument]
@@ -1169,32 +1360,169 @@ impl Repo {
 
     /// Read a file from disk and create a blob corresponding to its contents.
     /// If the file doesn't exist on disk, returns `None` instead.
+    ///
+    /// The contents are converted by `converter` in the same way as `git add`
+    /// would, by applying any filter drivers and line ending normalization
+    /// configured with `.gitattributes` or `core.autocrlf`.
     #[instrument]
-    pub fn create_blob_from_path(&self, path: &Path) -> Result<Option<NonZeroOid>> {
+    pub fn create_blob_from_path(
+        &self,
+        converter: &WorkingCopyConverter,
+        path: &Path,
+    ) -> Result<Option<NonZeroOid>> {
         // Can't use `self.inner.blob_path`, because it will read the file from
         // the main repository instead of from the current worktree.
+        let working_copy_path =
+            self.get_working_copy_path()
+                .ok_or_else(|| Error::CreateBlobFromPath {
+                    source: eyre::eyre!(
+                        "Repository at {:?} has no working copy path (is bare)",
+                        self.get_path()
+                    ),
+                    path: path.to_path_buf(),
+                })?;
+        let relative_path = path
+            .strip_prefix(&working_copy_path)
+            .unwrap_or(path)
+            .to_path_buf();
+        let path = working_copy_path.join(path);
+        let is_symlink = match std::fs::symlink_metadata(&path) {
+            Ok(metadata) => metadata.file_type().is_symlink(),
+            Err(err) if err.kind() == std::io::ErrorKind::NotFound => return Ok(None),
+            Err(err) => {
+                return Err(Error::CreateBlobFromPath {
+                    source: err.into(),
+                    path,
+                })
+            }
+        };
+        let blob = if is_symlink {
+            // Git stores the target of a symlink as the contents of its blob,
+            // so don't follow the symlink.
+            let contents = match std::fs::read_link(&path) {
+                Ok(target) => symlink_target_to_blob_contents(&target).map_err(|err| {
+                    Error::CreateBlobFromPath {
+                        source: err,
+                        path: path.clone(),
+                    }
+                })?,
+                Err(err) => {
+                    return Err(Error::CreateBlobFromPath {
+                        source: err.into(),
+                        path,
+                    })
+                }
+            };
+            self.create_blob_from_contents(&contents)?
+        } else {
+            let contents = match std::fs::read(&path) {
+                Ok(contents) => contents,
+                Err(err) if err.kind() == std::io::ErrorKind::NotFound => return Ok(None),
+                Err(err) => {
+                    return Err(Error::CreateBlobFromPath {
+                        source: err.into(),
+                        path,
+                    })
+                }
+            };
+            converter
+                .create_blob(&working_copy_path, &relative_path, contents)
+                .map_err(|err| Error::CreateBlobFromPath { source: err, path })?
+        };
+        Ok(Some(blob))
+    }
+
+    /// Determine the mode with which the file at the given path in the working
+    /// copy should be stored in a tree, in the same way as `git add`. If the
+    /// file doesn't exist on disk, returns `None` instead.
+    ///
+    /// `previous_mode` is the mode with which the file was previously stored,
+    /// if any. It's kept when the file system can't represent it, such as when
+    /// `core.fileMode` or `core.symlinks` is disabled.
+    #[instrument]
+    pub fn get_working_copy_file_mode(
+        &self,
+        path: &Path,
+        previous_mode: Option<FileMode>,
+    ) -> Result<Option<FileMode>> {
         let path = self
             .get_working_copy_path()
-            .ok_or_else(|| Error::CreateBlobFromPath {
-                source: eyre::eyre!(
-                    "Repository at {:?} has no working copy path (is bare)",
-                    self.get_path()
-                ),
-                path: path.to_path_buf(),
-            })?
+            .ok_or(Error::NoWorkingCopyPath)?
             .join(path);
-        let contents = match std::fs::read(&path) {
-            Ok(contents) => contents,
+        let metadata = match std::fs::symlink_metadata(&path) {
+            Ok(metadata) => metadata,
             Err(err) if err.kind() == std::io::ErrorKind::NotFound => return Ok(None),
             Err(err) => {
-                return Err(Error::CreateBlobFromPath {
+                return Err(Error::ReadWorkingCopyFileMode {
                     source: err.into(),
                     path,
                 })
             }
         };
-        let blob = self.create_blob_from_contents(&contents)?;
-        Ok(Some(blob))
+        let config = self.get_readonly_config()?;
+        let get_config_bool = |key: &str| {
+            config
+                .get_or(key, true)
+                .map_err(|err| Error::ReadWorkingCopyFileMode {
+                    source: err,
+                    path: path.clone(),
+                })
+        };
+        let trust_executable_bit = get_config_bool("core.fileMode")?;
+        let trust_symlinks = get_config_bool("core.symlinks")?;
+        Ok(Some(FileMode::from_working_copy_metadata(
+            &metadata,
+            previous_mode,
+            trust_executable_bit,
+            trust_symlinks,
+        )))
+    }
+
+    /// Get the commit currently checked out in the submodule at the given
+    /// path, to be recorded as a gitlink entry in a tree. If the submodule
+    /// doesn't exist on disk, returns `None` instead.
+    ///
+    /// If the submodule hasn't been populated (such as when it hasn't been
+    /// initialized with `git submodule update --init`), then there's no
+    /// checked-out commit, so the commit recorded in the index is returned
+    /// instead, as Git does.
+    #[instrument]
+    pub fn get_submodule_head_oid(&self, path: &Path) -> Result<Option<NonZeroOid>> {
+        let relative_path = path;
+        let path = self
+            .get_working_copy_path()
+            .ok_or(Error::NoWorkingCopyPath)?
+            .join(path);
+        if !path.exists() {
+            return Ok(None);
+        }
+        let submodule_repo = match git2::Repository::open(&path) {
+            Ok(submodule_repo) => submodule_repo,
+            Err(err) if err.code() == git2::ErrorCode::NotFound => {
+                warn!(?path, "Submodule is not populated, so leaving it unchanged");
+                let index = self.get_index()?;
+                return Ok(match index.get_entry(relative_path) {
+                    Some(IndexEntry {
+                        oid: MaybeZeroOid::NonZero(oid),
+                        ..
+                    }) => Some(oid),
+                    Some(_) | None => None,
+                });
+            }
+            Err(err) => {
+                return Err(Error::ReadSubmoduleHead {
+                    source: err,
+                    path: path.clone(),
+                })
+            }
+        };
+        let head = submodule_repo
+            .head()
+            .map_err(|err| Error::ReadSubmoduleHead {
+                source: err,
+                path: path.clone(),
+            })?;
+        Ok(head.target().map(make_non_zero_oid))
     }
 
     /// Create a blob corresponding to the provided byte slice.
@@ -1233,6 +1561,77 @@ impl Repo {
         Ok(make_non_zero_oid(oid))
     }
 
+    /// Create a copy of `commit` with the emails of its author and committer
+    /// replaced according to `map_email`, which returns the new email for an
+    /// email that should change. Every other byte of the commit object is kept
+    /// as is, so names that aren't valid UTF-8, timestamps and the message
+    /// encoding survive unchanged. Any cryptographic signature is dropped,
+    /// since it would no longer be valid.
+    ///
+    /// Returns `None` if `map_email` didn't change either email.
+    #[instrument(skip(map_email))]
+    pub fn rewrite_signature_emails(
+        &self,
+        commit: &Commit,
+        map_email: impl Fn(&[u8]) -> Option<Vec<u8>>,
+    ) -> Result<Option<NonZeroOid>> {
+        let header = commit.inner.raw_header_bytes();
+        let mut new_header: Vec<u8> = Vec::with_capacity(header.len());
+        let mut changed = false;
+        let mut in_signature = false;
+        for line in header
+            .strip_suffix(b"\n")
+            .unwrap_or(header)
+            .split(|&c| c == b'\n')
+        {
+            if line.starts_with(b" ") {
+                // Continuation of the previous header.
+                if !in_signature {
+                    new_header.extend_from_slice(line);
+                    new_header.push(b'\n');
+                }
+                continue;
+            }
+            in_signature = line.starts_with(b"gpgsig ") || line.starts_with(b"gpgsig-sha256 ");
+            if in_signature {
+                continue;
+            }
+
+            let is_identity = line.starts_with(b"author ") || line.starts_with(b"committer ");
+            let email_range = match (
+                line.iter().position(|&c| c == b'<'),
+                line.iter().rposition(|&c| c == b'>'),
+            ) {
+                (Some(start), Some(end)) if is_identity && start < end => Some((start + 1, end)),
+                _ => None,
+            };
+            match email_range.and_then(|(start, end)| {
+                map_email(&line[start..end]).map(|email| (start, end, email))
+            }) {
+                Some((start, end, email)) => {
+                    changed = true;
+                    new_header.extend_from_slice(&line[..start]);
+                    new_header.extend_from_slice(&email);
+                    new_header.extend_from_slice(&line[end..]);
+                }
+                None => new_header.extend_from_slice(line),
+            }
+            new_header.push(b'\n');
+        }
+        if !changed {
+            return Ok(None);
+        }
+
+        let mut contents = new_header;
+        contents.push(b'\n');
+        contents.extend_from_slice(commit.inner.message_raw_bytes());
+        let odb = self.inner.odb().map_err(Error::ReadObjectDatabase)?;
+        let oid = odb
+            .write(git2::ObjectType::Commit, &contents)
+            .map_err(Error::CreateCommit)?;
+        Ok(Some(make_non_zero_oid(oid)))
+    }
+
     /// Cherry-pick a commit in memory and return the resulting index.
     #[instrument]
     pub fn cherry_pick_commit(
@@ -1290,10 +1689,38 @@ impl Repo {
             .collect_vec();
         let changed_paths = changed_pathbufs.iter().map(PathBuf::borrow).collect_vec();
 
+        // libgit2 can't fetch objects on demand, so report any blobs missing
+        // from a partial clone up front rather than failing partway through
+        // the merge.
+        if !self.get_promisor_remote_names()?.is_empty() {
+            let mut commits = vec![patch_commit, target_commit];
+            let patch_parents = patch_commit.get_parents();
+            commits.extend(patch_parents.iter());
+            let oids = self.find_missing_blobs(&commits, &changed_paths)?;
+            if !oids.is_empty() {
+                return Err(CreateCommitFastError::MissingObjects { oids });
+            }
+        }
+
+        // libgit2 doesn't run custom merge drivers, so merge the paths which
+        // need one ourselves, and leave the rest to libgit2. Conflicts are
+        // left to Git, which writes conflict markers in the style set by
+        // `merge.conflictStyle`.
+        let (merge_driver_entries, merge_driver_conflicting_paths) =
+            self.merge_with_custom_drivers(patch_commit, target_commit, &changed_paths)?;
+        let merge_paths = changed_paths
+            .iter()
+            .copied()
+            .filter(|path| {
+                !merge_driver_entries.contains_key(*path)
+                    && !merge_driver_conflicting_paths.contains(*path)
+            })
+            .collect_vec();
+
         let dehydrated_patch_commit =
-            self.dehydrate_commit(patch_commit, changed_paths.as_slice(), true)?;
+            self.dehydrate_commit(patch_commit, merge_paths.as_slice(), true)?;
         let dehydrated_target_commit =
-            self.dehydrate_commit(target_commit, changed_paths.as_slice(), false)?;
+            self.dehydrate_commit(target_commit, merge_paths.as_slice(), false)?;
 
         let rebased_index =
             self.cherry_pick_commit(&dehydrated_patch_commit, &dehydrated_target_commit, 0)?;
@@ -1346,12 +1773,22 @@ impl Repo {
                     warn!("BUG: A merge conflict was detected, but there were no entries in `conflicting_paths`. Maybe the wrong index entry was used?")
                 }
 
+                let mut conflicting_paths = conflicting_paths;
+                conflicting_paths.extend(merge_driver_conflicting_paths);
                 return Err(CreateCommitFastError::MergeConflict { conflicting_paths });
             }
+            if !merge_driver_conflicting_paths.is_empty() {
+                return Err(CreateCommitFastError::MergeConflict {
+                    conflicting_paths: merge_driver_conflicting_paths,
+                });
+            }
             let rebased_entries: HashMap<PathBuf, Option<(NonZeroOid, FileMode)>> =
                 changed_pathbufs
                     .into_iter()
                     .map(|changed_path| {
+                        if let Some(entry) = merge_driver_entries.get(&changed_path) {
+                            return (changed_path, *entry);
+                        }
                         let value = match rebased_index.get_entry(&changed_path) {
                             Some(IndexEntry {
                                 oid: MaybeZeroOid::Zero,
@@ -1383,6 +1820,175 @@ impl Repo {
         Ok(rebased_tree)
     }
 
+    /// Merge the paths which need a custom merge driver to cherry-pick
+    /// `patch_commit` onto `target_commit`, by running the driver. A path only
+    /// has to be merged if `target_commit` has a different version of it than
+    /// both `patch_commit` and its parent.
+    ///
+    /// Returns the merged entries, and the paths which are still in conflict.
+    #[instrument]
+    fn merge_with_custom_drivers(
+        &self,
+        patch_commit: &Commit,
+        target_commit: &Commit,
+        changed_paths: &[&Path],
+    ) -> Result<(
+        HashMap<PathBuf, Option<(NonZeroOid, FileMode)>>,
+        HashSet<PathBuf>,
+    )> {
+        let mut merged_entries = HashMap::new();
+        let mut conflicting_paths = HashSet::new();
+        let parent_commit = match patch_commit.get_only_parent() {
+            Some(parent_commit) => parent_commit,
+            None => return Ok((merged_entries, conflicting_paths)),
+        };
+        let patch_tree = patch_commit.get_tree()?;
+        let parent_tree = parent_commit.get_tree()?;
+        let target_tree = target_commit.get_tree()?;
+        let config = self.get_readonly_config()?;
+
+        for path in changed_paths {
+            let get_entry = |tree: &Tree| -> Result<Option<(NonZeroOid, FileMode)>> {
+                let entry = tree.get_path(path).map_err(Error::ReadTreeEntry)?;
+                Ok(entry.map(|entry| (entry.get_oid(), entry.get_filemode())))
+            };
+            let patch_entry = get_entry(&patch_tree)?;
+            let parent_entry = get_entry(&parent_tree)?;
+            let target_entry = get_entry(&target_tree)?;
+            if target_entry == parent_entry || target_entry == patch_entry {
+                continue;
+            }
+            let command = match self.get_custom_merge_driver_command(&config, path)? {
+                Some(command) => command,
+                None => continue,
+            };
+
+            // Git reports a conflict without running the driver if the file
+            // was deleted on one side.
+            let (patch_entry, parent_entry, target_entry) =
+                match (patch_entry, parent_entry, target_entry) {
+                    (Some(patch_entry), Some(parent_entry), Some(target_entry)) => {
+                        (patch_entry, parent_entry, target_entry)
+                    }
+                    _ => {
+                        conflicting_paths.insert(path.to_path_buf());
+                        continue;
+                    }
+                };
+            let working_copy_path = match self.get_working_copy_path() {
+                Some(working_copy_path) => working_copy_path,
+                None => {
+                    conflicting_paths.insert(path.to_path_buf());
+                    continue;
+                }
+            };
+
+            let (patch_oid, patch_file_mode) = patch_entry;
+            let (parent_oid, parent_file_mode) = parent_entry;
+            let (target_oid, target_file_mode) = target_entry;
+            // Git only runs merge drivers on regular files. Leave symlinks and
+            // submodules to the regular merge.
+            let is_regular_file = |file_mode: FileMode| match file_mode {
+                FileMode::Blob | FileMode::BlobExecutable | FileMode::BlobGroupWritable => true,
+                FileMode::Commit | FileMode::Link | FileMode::Tree | FileMode::Unreadable => false,
+            };
+            if ![patch_file_mode, parent_file_mode, target_file_mode]
+                .into_iter()
+                .all(is_regular_file)
+            {
+                continue;
+            }
+
+            let patch_blob = self.find_blob_or_fail(patch_oid)?;
+            let parent_blob = self.find_blob_or_fail(parent_oid)?;
+            let target_blob = self.find_blob_or_fail(target_oid)?;
+            let theirs_label = format!(
+                "{} ({})",
+                patch_commit.get_short_oid()?,
+                patch_commit.get_summary()?
+            );
+            let ours_label = format!(
+                "{} ({})",
+                target_commit.get_short_oid()?,
+                target_commit.get_summary()?
+            );
+            let base_label = format!("parent of {theirs_label}");
+            let contents = match run_merge_driver(
+                self,
+                &working_copy_path,
+                &command,
+                path,
+                MergeDriverInput {
+                    base: parent_blob.get_content(),
+                    ours: target_blob.get_content(),
+                    theirs: patch_blob.get_content(),
+                    base_label: &base_label,
+                    ours_label: &ours_label,
+                    theirs_label: &theirs_label,
+                },
+            ) {
+                Ok(contents) => contents,
+                Err(err) => {
+                    // Report the path as conflicting, so that the merge can be
+                    // retried on-disk, where Git runs the driver itself.
+                    warn!(?err, ?path, "Could not run merge driver");
+                    None
+                }
+            };
+            match contents {
+                Some(contents) => {
+                    let oid = self.create_blob_from_contents(&contents)?;
+                    let file_mode = if patch_file_mode != parent_file_mode {
+                        patch_file_mode
+                    } else {
+                        target_file_mode
+                    };
+                    merged_entries.insert(path.to_path_buf(), Some((oid, file_mode)));
+                }
+                None => {
+                    conflicting_paths.insert(path.to_path_buf());
+                }
+            }
+        }
+        Ok((merged_entries, conflicting_paths))
+    }
+
+    /// Get the command of the custom merge driver which Git uses to merge the
+    /// given path, as set with the `merge` attribute in `.gitattributes` and
+    /// defined with `merge.<driver>.driver` in `config`. The built-in `text`,
+    /// `binary`, and `union` drivers are handled by `libgit2` as well, so
+    /// they're not returned.
+    #[instrument(skip(config))]
+    pub fn get_custom_merge_driver_command(
+        &self,
+        config: &impl ConfigRead,
+        path: &Path,
+    ) -> Result<Option<String>> {
+        let value = self
+            .inner
+            .get_attr(path, "merge", git2::AttrCheckFlags::FILE_THEN_INDEX)
+            .map_err(|err| Error::ReadMergeDriver {
+                source: err.into(),
+                path: path.to_owned(),
+            })?;
+        let driver = match git2::AttrValue::from_string(value) {
+            git2::AttrValue::String(driver) => driver,
+            _ => return Ok(None),
+        };
+        if ["text", "binary", "union"].contains(&driver) {
+            return Ok(None);
+        }
+
+        // Git falls back to the built-in text driver if the named driver
+        // isn't defined.
+        config
+            .get(format!("merge.{driver}.driver"))
+            .map_err(|err| Error::ReadMergeDriver {
+                source: err,
+                path: path.to_owned(),
+            })
+    }
+
     #[instrument]
     fn dehydrate_commit(
         &self,
@@ -1504,44 +2110,76 @@ impl Repo {
             .get_working_copy_path()
             .ok_or(Error::NoWorkingCopyPath)?;
         let repo_path = &repo_path;
+        let index = self.get_index()?;
+        let index = &index;
+        let converter = WorkingCopyConverter::new(self)?;
+        let converter = &converter;
+        let dehydrated_parent_tree = &dehydrated_parent_tree;
         let new_tree_entries: HashMap<PathBuf, Option<(NonZeroOid, FileMode)>> = match opts {
             AmendFastOptions::FromWorkingCopy { status_entries } => status_entries
                 .iter()
                 .flat_map(|entry| {
-                    entry.paths().into_iter().map(
-                        move |path| -> Result<(PathBuf, Option<(NonZeroOid, FileMode)>)> {
-                            let file_path = repo_path.join(&path);
-                            // Try to create a new blob OID based on the current on-disk
-                            // contents of the file in the working copy.
-                            let entry = self
-                                .create_blob_from_path(&file_path)?
-                                .map(|oid| (oid, entry.working_copy_file_mode));
-                            Ok((path, entry))
-                        },
-                    )
+                    entry
+                        .paths()
+                        .into_iter()
+                        // Paths outside of the sparse-checkout cone aren't
+                        // present on disk, so keep their contents from the
+                        // parent commit rather than treating them as deleted.
+                        .filter(move |path| !index.is_skip_worktree(path))
+                        // The source of a copy is unchanged, so keep its
+                        // contents from the parent commit.
+                        .filter(move |path| entry.copy_source() != Some(path.as_path()))
+                        .map(
+                            move |path| -> Result<(PathBuf, Option<(NonZeroOid, FileMode)>)> {
+                                let file_path = repo_path.join(&path);
+                                let entry = if entry.rename_source() == Some(path.as_path()) {
+                                    // The source of a rename is always deleted,
+                                    // even if an untracked file has been
+                                    // created at the same path.
+                                    None
+                                } else if entry.working_copy_file_mode == FileMode::Commit {
+                                    // Submodules are recorded as a gitlink to the
+                                    // commit which is checked out in the submodule.
+                                    self.get_submodule_head_oid(&file_path)?
+                                        .map(|oid| (oid, FileMode::Commit))
+                                } else {
+                                    // Try to create a new blob OID based on the current on-disk
+                                    // contents of the file in the working copy. The mode is
+                                    // read from disk as well, keeping the mode from the parent
+                                    // commit where the file system can't represent it.
+                                    let previous_mode = dehydrated_parent_tree
+                                        .get_path(&path)
+                                        .map_err(Error::ReadTree)?
+                                        .map(|entry| entry.get_filemode());
+                                    let file_mode = self
+                                        .get_working_copy_file_mode(&file_path, previous_mode)?
+                                        .unwrap_or(entry.working_copy_file_mode);
+                                    self.create_blob_from_path(converter, &file_path)?
+                                        .map(|oid| (oid, file_mode))
+                                };
+                                Ok((path, entry))
+                            },
+                        )
                 })
                 .collect::<Result<HashMap<_, _>>>()?,
-            AmendFastOptions::FromIndex { paths } => {
-                let index = self.get_index()?;
-                paths
-                    .iter()
-                    .filter_map(|path| match index.get_entry(path) {
-                        Some(IndexEntry {
-                            oid: MaybeZeroOid::Zero,
-                            ..
-                        }) => {
-                            warn!(?path, "index entry was zero");
-                            None
-                        }
-                        Some(IndexEntry {
-                            oid: MaybeZeroOid::NonZero(oid),
-                            file_mode,
-                            ..
-                        }) => Some((path.clone(), Some((oid, file_mode)))),
-                        None => Some((path.clone(), None)),
-                    })
-                    .collect::<HashMap<_, _>>()
-            }
+            AmendFastOptions::FromIndex { paths } => paths
+                .iter()
+                .filter_map(|path| match index.get_entry(path) {
+                    Some(IndexEntry {
+                        oid: MaybeZeroOid::Zero,
+                        ..
+                    }) => {
+                        warn!(?path, "index entry was zero");
+                        None
+                    }
+                    Some(IndexEntry {
+                        oid: MaybeZeroOid::NonZero(oid),
+                        file_mode,
+                        ..
+                    }) => Some((path.clone(), Some((oid, file_mode)))),
+                    None => Some((path.clone(), None)),
+                })
+                .collect::<HashMap<_, _>>(),
             AmendFastOptions::FromCommit { commit } => {
                 let amended_tree = self.cherry_pick_fast(
                     commit,
@@ -1641,6 +2279,39 @@ impl<'repo> Signature<'repo> {
         Ok(Signature { inner: signature })
     }
 
+    /// Replace the name and email of this signature, keeping its time.
+    #[instrument]
+    pub fn update_identity(self, name: &str, email: &str) -> Result<Signature<'repo>> {
+        let signature = git2::Signature::new(name, email, &self.inner.when())
+            .map_err(Error::CreateSignature)?;
+        Ok(Signature { inner: signature })
+    }
+
+    /// Replace the time of this signature with the given number of seconds
+    /// since the Unix epoch, in the timezone with the given offset from UTC.
+    #[instrument]
+    pub fn update_time(self, seconds: i64, offset_minutes: i32) -> Result<Signature<'repo>> {
+        let time = git2::Time::new(seconds, offset_minutes);
+        let name = match self.inner.name() {
+            Some(name) => name,
+            None => {
+                return Err(Error::DecodeUtf8 {
+                    item: "signature name",
+                })
+            }
+        };
+        let email = match self.inner.email() {
+            Some(email) => email,
+            None => {
+                return Err(Error::DecodeUtf8 {
+                    item: "signature email",
+                })
+            }
+        };
+        let signature = git2::Signature::new(name, email, &time).map_err(Error::CreateSignature)?;
+        Ok(Signature { inner: signature })
+    }
+
     /// Get the time when this signature was applied.
     pub fn get_time(&self) -> Time {
         Time {
@@ -1698,3 +2369,63 @@ impl Time {
         NaiveDateTime::from_timestamp_opt(self.inner.seconds(), 0)
     }
 }
+
+/// Convert the target of a symlink on disk into the contents of the blob which
+/// Git would store for it.
+#[cfg(unix)]
+fn symlink_target_to_blob_contents(target: &Path) -> eyre::Result<Vec<u8>> {
+    use std::os::unix::ffi::OsStrExt;
+    Ok(target.as_os_str().as_bytes().to_vec())
+}
+
+/// Convert the target of a symlink on disk into the contents of the blob which
+/// Git would store for it. Git always uses `/` as the path separator in symlink
+/// targets, even on Windows.
+#[cfg(not(unix))]
+fn symlink_target_to_blob_contents(target: &Path) -> eyre::Result<Vec<u8>> {
+    let target = target
+        .to_str()
+        .ok_or_else(|| eyre::eyre!("Symlink target is not valid UTF-8: {:?}", target))?;
+    Ok(target.replace('\\', "/").into_bytes())
+}
+
+/// Fetch the given objects from the repository's promisor remotes, such as
+/// the blobs which are missing from a partial clone. Returns whether the
+/// objects were fetched successfully.
+///
+/// This batches the objects into a single `git fetch` call per remote, rather
+/// than relying on Git to fetch each object on demand.
+#[instrument]
+pub fn fetch_missing_objects(
+    git_run_info: &GitRunInfo,
+    repo: &Repo,
+    event_tx_id: Option<EventTransactionId>,
+    oids: &[NonZeroOid],
+) -> eyre::Result<bool> {
+    let stdin: String = oids.iter().map(|oid| format!("{oid}\n")).collect();
+    for remote_name in repo.get_promisor_remote_names()? {
+        let GitRunResult { exit_code, .. } = git_run_info.run_silent(
+            repo,
+            event_tx_id,
+            &[
+                "-c",
+                "fetch.negotiationAlgorithm=noop",
+                "fetch",
+                &remote_name,
+                "--no-tags",
+                "--no-write-fetch-head",
+                "--recurse-submodules=no",
+                "--filter=blob:none",
+                "--stdin",
+            ],
+            GitRunOpts {
+                treat_git_failure_as_error: false,
+                stdin: Some(stdin.clone().into_bytes()),
+            },
+        )?;
+        if exit_code.is_success() {
+            return Ok(true);
+        }
+    }
+    Ok(false)
+}
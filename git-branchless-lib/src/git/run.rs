@@ -16,6 +16,7 @@ use tracing::{instrument, warn};
 use crate::core::config::get_main_worktree_hooks_dir;
 use crate::core::effects::{Effects, OperationType};
 use crate::core::eventlog::{EventTransactionId, BRANCHLESS_TRANSACTION_ID_ENV_VAR};
+use crate::core::hook_output::{get_hook_output_mode, HookOutputDb, HookOutputMode};
 use crate::git::repo::Repo;
 use crate::util::{get_sh, ExitCode, EyreExitOr};
 
@@ -159,6 +160,16 @@ impl GitRunInfo {
         stdout_thread.join().unwrap();
         stderr_thread.join().unwrap();
 
+        // Now that the hooks run by the subprocess have finished, report any
+        // of their messages which were skipped after the last one printed.
+        if let Some(event_tx_id) = event_tx_id {
+            if get_hook_output_mode() == HookOutputMode::Summary {
+                let repo = Repo::from_dir(working_directory)?;
+                let conn = repo.get_db_conn()?;
+                HookOutputDb::new(&conn)?.flush_skipped_messages(&effects, event_tx_id)?;
+            }
+        }
+
         // On Unix, if the child process was terminated by a signal, we need to call
         // some Unix-specific functions to access the signal that terminated it. For
         // simplicity, just return `1` in those cases.
@@ -393,7 +404,7 @@ impl GitRunInfo {
         event_tx_id: EventTransactionId,
         args: &[&str],
         stdin: Option<BString>,
-    ) -> eyre::Result<()> {
+    ) -> eyre::Result<Option<ExitStatus>> {
         let hook_dir = get_main_worktree_hooks_dir(self, repo, Some(event_tx_id))?;
         if !hook_dir.exists() {
             warn!(
@@ -401,7 +412,7 @@ impl GitRunInfo {
                 ?hook_name,
                 "Git hooks dir did not exist, so could not invoke hook"
             );
-            return Ok(());
+            return Ok(None);
         }
 
         let GitRunInfo {
@@ -421,43 +432,95 @@ impl GitRunInfo {
             std::env::join_paths(path_components).wrap_err("Joining path components")?
         };
 
-        if hook_dir.join(hook_name).exists() {
-            let mut child = Command::new(get_sh().ok_or_else(|| eyre!("could not get sh"))?)
-                .current_dir(self.working_directory(repo))
-                .arg("-c")
-                .arg(format!("{hook_name} \"$@\""))
-                .arg(hook_name) // "$@" expands "$1" "$2" "$3" ... but we also must specify $0.
-                .args(args)
-                .env_clear()
-                .envs(env.iter())
-                .env(BRANCHLESS_TRANSACTION_ID_ENV_VAR, event_tx_id.to_string())
-                .env("PATH", &path)
-                .stdin(Stdio::piped())
-                .stdout(Stdio::piped())
-                .stderr(Stdio::piped())
-                .spawn()
-                .wrap_err_with(|| format!("Invoking {} hook with PATH: {:?}", &hook_name, &path))?;
-
-            if let Some(stdin) = stdin {
-                child
-                    .stdin
-                    .as_mut()
-                    .unwrap()
-                    .write_all(&stdin)
-                    .wrap_err("Writing hook process stdin")?;
-            }
+        if !hook_dir.join(hook_name).exists() {
+            return Ok(None);
+        }
 
-            let stdout = child.stdout.take();
-            let stdout_thread = self.spawn_writer_thread(stdout, effects.get_output_stream());
-            let stderr = child.stderr.take();
-            let stderr_thread = self.spawn_writer_thread(stderr, effects.get_error_stream());
+        let mut command = Command::new(get_sh().ok_or_else(|| eyre!("could not get sh"))?);
+        command
+            .current_dir(self.working_directory(repo))
+            .arg("-c")
+            .arg(format!("{hook_name} \"$@\""))
+            .arg(hook_name) // "$@" expands "$1" "$2" "$3" ... but we also must specify $0.
+            .args(args)
+            .env_clear()
+            .envs(env.iter())
+            .env(BRANCHLESS_TRANSACTION_ID_ENV_VAR, event_tx_id.to_string())
+            .env("PATH", &path);
+        let exit_status = self
+            .run_hook_command(effects, command, stdin)
+            .wrap_err_with(|| format!("Invoking {} hook with PATH: {:?}", &hook_name, &path))?;
+        Ok(Some(exit_status))
+    }
+
+    /// Run the hook process described by `command`, forwarding its output to
+    /// the user while `stdin` is written to it.
+    ///
+    /// The output is read on separate threads before writing `stdin`, so that
+    /// a hook which prints more than fits in the pipe buffer before reading
+    /// its input doesn't deadlock. A hook which exits without reading all of
+    /// its input isn't an error.
+    fn run_hook_command(
+        &self,
+        effects: &Effects,
+        mut command: Command,
+        stdin: Option<BString>,
+    ) -> eyre::Result<ExitStatus> {
+        let mut child = command
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::piped())
+            .spawn()
+            .wrap_err("Spawning hook process")?;
 
-            let _ignored: ExitStatus =
-                child.wait().wrap_err("Waiting for child process to exit")?;
-            stdout_thread.join().unwrap();
-            stderr_thread.join().unwrap();
+        let stdout = child.stdout.take();
+        let stdout_thread = self.spawn_writer_thread(stdout, effects.get_output_stream());
+        let stderr = child.stderr.take();
+        let stderr_thread = self.spawn_writer_thread(stderr, effects.get_error_stream());
+
+        if let (Some(stdin), Some(mut child_stdin)) = (stdin, child.stdin.take()) {
+            match child_stdin.write_all(&stdin) {
+                Ok(()) => {}
+                Err(err) if err.kind() == std::io::ErrorKind::BrokenPipe => {}
+                Err(err) => return Err(err).wrap_err("Writing hook process stdin"),
+            }
         }
-        Ok(())
+
+        let exit_status = child.wait().wrap_err("Waiting for child process to exit")?;
+        stdout_thread.join().unwrap();
+        stderr_thread.join().unwrap();
+        Ok(exit_status)
+    }
+
+    /// Run a user-provided shell command as a hook, such as one set via
+    /// `git config`, rather than one installed in the Git hooks directory.
+    ///
+    /// Returns the exit status of the command. A failing command is not
+    /// treated as an error; it's up to the caller to decide how to report it.
+    #[instrument(skip(stdin))]
+    pub fn run_shell_hook(
+        &self,
+        effects: &Effects,
+        repo: &Repo,
+        command: &str,
+        event_tx_id: EventTransactionId,
+        stdin: Option<BString>,
+    ) -> eyre::Result<ExitStatus> {
+        let GitRunInfo {
+            path_to_git: _,
+            working_directory: _,
+            env,
+        } = self;
+        let mut hook_command = Command::new(get_sh().ok_or_else(|| eyre!("could not get sh"))?);
+        hook_command
+            .current_dir(self.working_directory(repo))
+            .arg("-c")
+            .arg(command)
+            .env_clear()
+            .envs(env.iter())
+            .env(BRANCHLESS_TRANSACTION_ID_ENV_VAR, event_tx_id.to_string());
+        self.run_hook_command(effects, hook_command, stdin)
+            .wrap_err_with(|| format!("Invoking hook command: {command:?}"))
     }
 
     /// Run a provided Git hook if it exists for the repository.
@@ -473,13 +536,59 @@ impl GitRunInfo {
         args: &[S],
         stdin: Option<BString>,
     ) -> eyre::Result<()> {
-        self.run_hook_inner(
+        let _ignored: Option<ExitStatus> = self.run_hook_inner(
             effects,
             repo,
             hook_name,
             event_tx_id,
             args.iter().map(AsRef::as_ref).collect_vec().as_slice(),
             stdin,
-        )
+        )?;
+        Ok(())
+    }
+
+    /// Run a Git hook which takes the path to a commit message file as its
+    /// first argument, such as `prepare-commit-msg` or `commit-msg`, on the
+    /// given message. `args` are passed to the hook after the path.
+    ///
+    /// Returns the message as edited by the hook, or the exit code of the hook
+    /// if it rejected the message. If the hook isn't installed, the message is
+    /// returned unchanged. The message is passed to the hook in a temporary
+    /// file in the Git directory, which is deleted afterwards.
+    #[instrument(skip(message))]
+    pub fn run_message_hook(
+        &self,
+        effects: &Effects,
+        repo: &Repo,
+        hook_name: &str,
+        event_tx_id: EventTransactionId,
+        message: &str,
+        args: &[&str],
+    ) -> eyre::Result<Result<String, ExitCode>> {
+        let message_file = tempfile::Builder::new()
+            .prefix("BRANCHLESS_EDITMSG-")
+            .tempfile_in(repo.get_path())
+            .wrap_err("Creating commit message file")?;
+        let message_path = message_file.path();
+        std::fs::write(message_path, message)
+            .wrap_err_with(|| format!("Writing commit message to {message_path:?}"))?;
+        let message_path_str = message_path.to_string_lossy();
+        let hook_args = std::iter::once(message_path_str.as_ref())
+            .chain(args.iter().copied())
+            .collect_vec();
+        let exit_status =
+            match self.run_hook_inner(effects, repo, hook_name, event_tx_id, &hook_args, None)? {
+                Some(exit_status) => exit_status,
+                None => return Ok(Ok(message.to_string())),
+            };
+        if !exit_status.success() {
+            return Ok(Err(ExitCode(exit_status.code().unwrap_or(1).try_into()?)));
+        }
+        let message = std::fs::read_to_string(message_path)
+            .wrap_err_with(|| format!("Reading commit message from {message_path:?}"))?;
+        message_file
+            .close()
+            .wrap_err("Deleting commit message file")?;
+        Ok(Ok(message))
     }
 }
@@ -30,6 +30,7 @@ use tracing::instrument;
 use crate::core::formatting::Pluralize;
 use crate::git::FileStatus;
 
+use super::convert::WorkingCopyConverter;
 use super::index::{Index, IndexEntry, Stage};
 use super::repo::Signature;
 use super::status::FileMode;
@@ -121,7 +122,12 @@ impl<'repo> WorkingCopySnapshot<'repo> {
         let head_reference_name: Option<ReferenceName> = head_info.reference_name.clone();
 
         let commit_unstaged_oid: NonZeroOid = {
-            Self::create_commit_for_unstaged_changes(repo, head_commit.as_ref(), status_entries)?
+            Self::create_commit_for_unstaged_changes(
+                repo,
+                index,
+                head_commit.as_ref(),
+                status_entries,
+            )?
         };
 
         let commit_stage0 = Self::create_commit_for_stage(
@@ -299,6 +305,7 @@ branchless: automated working copy snapshot
     #[instrument]
     fn create_commit_for_unstaged_changes(
         repo: &Repo,
+        index: &Index,
         head_commit: Option<&Commit>,
         status_entries: &[StatusEntry],
     ) -> eyre::Result<NonZeroOid> {
@@ -325,12 +332,28 @@ branchless: automated working copy snapshot
                 entry
                     .paths()
                     .into_iter()
-                    .map(|path| (path, entry.working_copy_file_mode))
+                    // The source of a copy is unchanged, so it's left as it is
+                    // in `HEAD`.
+                    .filter(move |path| entry.copy_source() != Some(path.as_path()))
+                    .map(move |path| {
+                        // The source of a rename is always deleted, even if an
+                        // untracked file has been created at the same path.
+                        let file_mode = if entry.rename_source() == Some(path.as_path()) {
+                            FileMode::Unreadable
+                        } else {
+                            entry.working_copy_file_mode
+                        };
+                        (path, file_mode)
+                    })
             })
+            // Paths outside of the sparse-checkout cone aren't present on disk,
+            // so their contents are left as they are in `HEAD`.
+            .filter(|(path, _file_mode)| !index.is_skip_worktree(path))
             .collect();
         let num_changes = changed_paths.len();
 
         let head_tree = head_commit.map(|commit| commit.get_tree()).transpose()?;
+        let converter = WorkingCopyConverter::new(repo)?;
         let hydrate_entries = {
             let mut result = HashMap::new();
             for (path, file_mode) in changed_paths {
@@ -341,8 +364,14 @@ branchless: automated working copy snapshot
                     // existence on disk because it's no longer being tracked by
                     // the index.
                     None
+                } else if file_mode == FileMode::Commit {
+                    // Submodules are recorded as a gitlink to the commit which
+                    // is checked out in the submodule. Any uncommitted changes
+                    // inside the submodule itself can't be captured.
+                    repo.get_submodule_head_oid(&path)?
+                        .map(|commit_oid| (commit_oid, file_mode))
                 } else {
-                    repo.create_blob_from_path(&path)?
+                    repo.create_blob_from_path(&converter, &path)?
                         .map(|blob_oid| (blob_oid, file_mode))
                 };
                 result.insert(path, entry);
@@ -354,8 +383,17 @@ branchless: automated working copy snapshot
             repo.find_tree_or_fail(tree_oid)?
         };
 
+        let num_dirty_submodules = status_entries
+            .iter()
+            .filter(|entry| {
+                entry
+                    .submodule_state
+                    .map_or(false, |submodule_state| submodule_state.is_dirty())
+            })
+            .count();
+
         let signature = Signature::automated()?;
-        let message = format!(
+        let mut message = format!(
             "branchless: working copy snapshot data: {}",
             Pluralize {
                 determiner: None,
@@ -363,6 +401,16 @@ branchless: automated working copy snapshot
                 unit: ("unstaged change", "unstaged changes"),
             }
         );
+        if num_dirty_submodules > 0 {
+            message += &format!(
+                " ({} with uncommitted changes not captured)",
+                Pluralize {
+                    determiner: None,
+                    amount: num_dirty_submodules,
+                    unit: ("submodule", "submodules"),
+                }
+            );
+        }
         let commit = repo.create_commit(
             None,
             &signature,
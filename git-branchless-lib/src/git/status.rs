@@ -1,4 +1,5 @@
-use std::path::PathBuf;
+use std::fs::Metadata;
+use std::path::{Path, PathBuf};
 use std::str::FromStr;
 
 use bstr::ByteVec;
@@ -73,6 +74,45 @@ pub enum FileMode {
     Commit,
 }
 
+impl FileMode {
+    /// Determine the mode with which a file in the working copy should be
+    /// stored, given its metadata, in the same way as `git add`.
+    ///
+    /// If `trust_executable_bit` is `false` (as with `core.fileMode=false`),
+    /// then the executable bit on disk is ignored and the executable bit of
+    /// `previous_mode` is kept instead. Likewise, if `trust_symlinks` is
+    /// `false` (as with `core.symlinks=false`), then a file which was
+    /// previously a symlink is kept as a symlink.
+    pub fn from_working_copy_metadata(
+        metadata: &Metadata,
+        previous_mode: Option<FileMode>,
+        trust_executable_bit: bool,
+        trust_symlinks: bool,
+    ) -> Self {
+        if metadata.file_type().is_symlink() {
+            return FileMode::Link;
+        }
+        match previous_mode {
+            Some(FileMode::Link) if !trust_symlinks => FileMode::Link,
+            Some(FileMode::BlobExecutable) if !trust_executable_bit => FileMode::BlobExecutable,
+            _ if trust_executable_bit && is_executable(metadata) => FileMode::BlobExecutable,
+            _ => FileMode::Blob,
+        }
+    }
+}
+
+#[cfg(unix)]
+fn is_executable(metadata: &Metadata) -> bool {
+    use std::os::unix::fs::PermissionsExt;
+    // Git only checks whether the file is executable by its owner.
+    metadata.permissions().mode() & 0o100 != 0
+}
+
+#[cfg(not(unix))]
+fn is_executable(_metadata: &Metadata) -> bool {
+    false
+}
+
 impl From<git2::FileMode> for FileMode {
     fn from(file_mode: git2::FileMode) -> Self {
         match file_mode {
@@ -160,6 +200,47 @@ impl ToString for FileMode {
     }
 }
 
+/// The state of a submodule in the working copy.
+/// See <https://git-scm.com/docs/git-status#_changed_tracked_entries>.
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq)]
+pub struct SubmoduleState {
+    /// The commit checked out in the submodule differs from the one recorded
+    /// in the superproject.
+    pub commit_changed: bool,
+    /// The submodule has changes to its own tracked files.
+    pub has_tracked_changes: bool,
+    /// The submodule has untracked files.
+    pub has_untracked_changes: bool,
+}
+
+impl SubmoduleState {
+    /// Determine if the submodule has uncommitted changes of its own. These
+    /// can't be recorded in the superproject, since only the commit checked
+    /// out in the submodule is tracked there.
+    pub fn is_dirty(&self) -> bool {
+        self.has_tracked_changes || self.has_untracked_changes
+    }
+}
+
+impl FromStr for SubmoduleState {
+    type Err = eyre::Error;
+
+    // Parses the four-character submodule state of a status entry, which is
+    // either `N...` for a non-submodule or `S<c><m><u>` for a submodule.
+    fn from_str(state: &str) -> eyre::Result<Self> {
+        match state.as_bytes() {
+            [b'S', commit_changed, has_tracked_changes, has_untracked_changes] => {
+                Ok(SubmoduleState {
+                    commit_changed: *commit_changed == b'C',
+                    has_tracked_changes: *has_tracked_changes == b'M',
+                    has_untracked_changes: *has_untracked_changes == b'U',
+                })
+            }
+            _ => eyre::bail!("invalid submodule state: {}", state),
+        }
+    }
+}
+
 /// The status of a file in the repo.
 #[derive(Clone, Debug, PartialEq, Eq)]
 pub struct StatusEntry {
@@ -173,6 +254,9 @@ pub struct StatusEntry {
     pub path: PathBuf,
     /// The original path of the file (for renamed files).
     pub orig_path: Option<PathBuf>,
+    /// The state of the submodule at this path, or `None` if the path isn't a
+    /// submodule.
+    pub submodule_state: Option<SubmoduleState>,
 }
 
 impl StatusEntry {
@@ -184,6 +268,25 @@ impl StatusEntry {
         }
         result
     }
+
+    /// Returns the path that the file was renamed from, if this entry is a
+    /// rename. The file no longer exists at that path, even if an untracked
+    /// file has since been created there.
+    pub fn rename_source(&self) -> Option<&Path> {
+        match (self.index_status, self.working_copy_status) {
+            (FileStatus::Renamed, _) | (_, FileStatus::Renamed) => self.orig_path.as_deref(),
+            _ => None,
+        }
+    }
+
+    /// Returns the path that the file was copied from, if this entry is a
+    /// copy. The file at that path is left unchanged by the copy.
+    pub fn copy_source(&self) -> Option<&Path> {
+        match (self.index_status, self.working_copy_status) {
+            (FileStatus::Copied, _) | (_, FileStatus::Copied) => self.orig_path.as_deref(),
+            _ => None,
+        }
+    }
 }
 
 impl TryFrom<&[u8]> for StatusEntry {
@@ -197,7 +300,7 @@ impl TryFrom<&[u8]> for StatusEntry {
             static ref STATUS_PORCELAIN_V2_REGEXP: Regex = Regex::new(concat!(
                 r#"^(?P<prefix>1|2|u) "#,                                    // Prefix.
                 r#"(?P<index_status>[\w.])(?P<working_copy_status>[\w.]) "#, // Status indicators.
-                r#"[\w.]+ "#,                                                // Submodule state.
+                r#"(?P<submodule_state>[\w.]+) "#,                           // Submodule state.
                 r#"(\d{6} ){2,3}(?P<working_copy_filemode>\d{6}) "#,         // HEAD, Index, and Working Copy file modes;
                                                                              // or stage1, stage2, stage3, and working copy file modes.
                 r#"([a-f\d]+ ){2,3}([CR]\d{1,3} )?"#,                        // HEAD and Index object IDs, and optionally the rename/copy score;
@@ -234,6 +337,20 @@ impl TryFrom<&[u8]> for StatusEntry {
                     })
                     .and_then(|working_copy_file_mode| working_copy_file_mode.parse::<FileMode>())
             })?;
+        let submodule_state = status_line_parts
+            .name("submodule_state")
+            .ok_or_else(|| eyre::eyre!("no submodule state in status line"))
+            .and_then(|m| {
+                std::str::from_utf8(m.as_bytes())
+                    .map_err(|err| eyre::eyre!("unable to decode submodule state: {:?}", err))
+            })
+            .and_then(|submodule_state| {
+                if submodule_state.starts_with('N') {
+                    Ok(None)
+                } else {
+                    submodule_state.parse::<SubmoduleState>().map(Some)
+                }
+            })?;
         let path = status_line_parts
             .name("path")
             .ok_or_else(|| eyre::eyre!("no path in status line"))?
@@ -250,6 +367,7 @@ impl TryFrom<&[u8]> for StatusEntry {
             orig_path: orig_path
                 .map(|orig_path| orig_path.to_vec().into_path_buf())
                 .transpose()?,
+            submodule_state,
         })
     }
 }
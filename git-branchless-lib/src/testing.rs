@@ -428,6 +428,14 @@ then you can only run tests in the main `git-branchless` and \
         ])?;
         self.run(&["config", "branchless.restack.preserveTimestamps", "true"])?;
 
+        // Give each hook invocation its own transaction, so that the
+        // transaction IDs in tests don't depend on the platform.
+        self.run(&[
+            "config",
+            "branchless.eventlog.groupExternalCommands",
+            "false",
+        ])?;
+
         // Disable warnings of the following form on Windows:
         //
         // ```
@@ -561,6 +569,106 @@ then you can only run tests in the main `git-branchless` and \
         self.commit_file_with_contents_and_message(name, time, contents, "create")
     }
 
+    /// Generate a synthetic history with `fast-import`, which is much faster
+    /// than making each commit individually. The main branch gets
+    /// `num_main_commits` commits, and each of the `num_stacks` stacks gets
+    /// `stack_size` commits on top of a main branch commit and a branch named
+    /// `stack-<n>` pointing to its tip. The stacks are based on commits spread
+    /// evenly across the main branch, so that they need to be restacked to be
+    /// on top of it.
+    #[instrument]
+    pub fn generate_stacks(
+        &self,
+        main_branch_name: &str,
+        num_main_commits: usize,
+        num_stacks: usize,
+        stack_size: usize,
+    ) -> eyre::Result<GeneratedStacks> {
+        let mut stream = String::new();
+        let mut next_mark = 1;
+        let mut add_commit =
+            |stream: &mut String, branch_name: &str, from_mark: Option<usize>, path: &str| {
+                let mark = next_mark;
+                next_mark += 1;
+                let message = format!("create {path}\n");
+                let contents = format!("{path} contents {mark}\n");
+                stream.push_str(&format!("commit refs/heads/{branch_name}\n"));
+                stream.push_str(&format!("mark :{mark}\n"));
+                stream.push_str(&format!(
+                    "committer {DUMMY_NAME} <{DUMMY_EMAIL}> {} +0000\n",
+                    1_600_000_000 + mark
+                ));
+                stream.push_str(&format!("data {}\n{message}", message.len()));
+                if let Some(from_mark) = from_mark {
+                    stream.push_str(&format!("from :{from_mark}\n"));
+                }
+                stream.push_str(&format!("M 644 inline {path}\n"));
+                stream.push_str(&format!("data {}\n{contents}\n", contents.len()));
+                mark
+            };
+
+        let mut main_marks = Vec::new();
+        for i in 0..num_main_commits {
+            let path = format!("main/file{}.txt", i % 100);
+            main_marks.push(add_commit(&mut stream, main_branch_name, None, &path));
+        }
+        let mut stack_marks = Vec::new();
+        for i in 0..num_stacks {
+            let base_mark = main_marks
+                .get(num_main_commits * (i + 1) / (num_stacks + 1))
+                .or_else(|| main_marks.last())
+                .copied();
+            let branch_name = format!("stack-{i}");
+            let mut marks = Vec::new();
+            for j in 0..stack_size {
+                let from_mark = if j == 0 { base_mark } else { None };
+                let path = format!("stack{i}/file{j}.txt");
+                marks.push(add_commit(&mut stream, &branch_name, from_mark, &path));
+            }
+            stack_marks.push(marks);
+        }
+
+        let marks_path = self.repo_path.join(".git").join("generated-stacks-marks");
+        self.run_with_options(
+            &[
+                "fast-import",
+                "--quiet",
+                &format!("--export-marks={}", marks_path.display()),
+            ],
+            &GitRunOptions {
+                input: Some(stream),
+                ..Default::default()
+            },
+        )?;
+        let mark_to_oid = fs::read_to_string(&marks_path)?
+            .lines()
+            .map(|line| -> eyre::Result<(usize, NonZeroOid)> {
+                let (mark, oid) = line
+                    .strip_prefix(':')
+                    .and_then(|line| line.split_once(' '))
+                    .ok_or_else(|| eyre::eyre!("Could not parse mark line: {line:?}"))?;
+                Ok((mark.parse()?, oid.parse()?))
+            })
+            .collect::<eyre::Result<HashMap<usize, NonZeroOid>>>()?;
+        fs::remove_file(&marks_path)?;
+
+        let get_oid = |mark: &usize| -> eyre::Result<NonZeroOid> {
+            mark_to_oid
+                .get(mark)
+                .copied()
+                .ok_or_else(|| eyre::eyre!("No commit was created for mark :{mark}"))
+        };
+        let main_oid = match main_marks.last() {
+            Some(mark) => Some(get_oid(mark)?),
+            None => None,
+        };
+        let stacks = stack_marks
+            .iter()
+            .map(|marks| marks.iter().map(get_oid).collect::<eyre::Result<Vec<_>>>())
+            .collect::<eyre::Result<Vec<_>>>()?;
+        Ok(GeneratedStacks { main_oid, stacks })
+    }
+
     /// Commit a file with default contents. The `time` argument is used to set
     /// the commit timestamp, which is factored into the commit hash.
     pub fn commit_file(&self, name: &str, time: isize) -> eyre::Result<NonZeroOid> {
@@ -663,10 +771,33 @@ then you can only run tests in the main `git-branchless` and \
     pub fn clear_event_log(&self) -> eyre::Result<()> {
         let event_log_path = self.repo_path.join(".git/branchless/db.sqlite3");
         std::fs::remove_file(event_log_path)?;
+        // Also remove any write-ahead log files, in case they weren't cleaned
+        // up when the database was last closed.
+        for suffix in ["-wal", "-shm"] {
+            let path = self
+                .repo_path
+                .join(format!(".git/branchless/db.sqlite3{suffix}"));
+            match std::fs::remove_file(path) {
+                Ok(()) => {}
+                Err(err) if err.kind() == std::io::ErrorKind::NotFound => {}
+                Err(err) => return Err(err.into()),
+            }
+        }
         Ok(())
     }
 }
 
+/// The commits created by [`Git::generate_stacks`].
+#[derive(Debug)]
+pub struct GeneratedStacks {
+    /// The head commit of the main branch, if any main branch commits were
+    /// created.
+    pub main_oid: Option<NonZeroOid>,
+
+    /// The commits of each stack, from its root to its tip.
+    pub stacks: Vec<Vec<NonZeroOid>>,
+}
+
 /// Wrapper around a `Git` instance which cleans up the repository once dropped.
 pub struct GitWrapper {
     repo_dir: TempDir,
@@ -727,6 +858,19 @@ pub fn make_git() -> eyre::Result<GitWrapper> {
     Ok(GitWrapper { repo_dir, git })
 }
 
+/// Create a temporary directory and a `Git` instance to use with it, using the
+/// given Git executable rather than the one configured for tests. This can be
+/// used to set up scratch repositories outside of tests, such as for
+/// benchmarking.
+pub fn make_git_with_executable(
+    path_to_git: PathBuf,
+    git_exec_path: PathBuf,
+) -> eyre::Result<GitWrapper> {
+    let repo_dir = tempfile::tempdir()?;
+    let git = Git::new(path_to_git, repo_dir.path().to_path_buf(), git_exec_path);
+    Ok(GitWrapper { repo_dir, git })
+}
+
 /// Represents a pair of directories that will be cleaned up after this value
 /// dropped. The two directories need to be `init`ed and `clone`ed by the
 /// caller, respectively.
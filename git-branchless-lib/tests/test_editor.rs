@@ -0,0 +1,34 @@
+use branchless::core::editor::edit_message;
+use branchless::testing::make_git;
+
+#[test]
+fn test_edit_message_noop_editor() -> eyre::Result<()> {
+    let git = make_git()?;
+    git.init_repo()?;
+
+    // The test environment sets `GIT_EDITOR` to `:`.
+    let git_run_info = git.get_git_run_info();
+    let repo = git.get_repo()?;
+    let message = edit_message(&git_run_info, &repo, "original message")?;
+    assert_eq!(message, "original message");
+
+    Ok(())
+}
+
+#[test]
+#[cfg(unix)]
+fn test_edit_message_respects_git_editor() -> eyre::Result<()> {
+    let git = make_git()?;
+    git.init_repo()?;
+
+    let mut git_run_info = git.get_git_run_info();
+    git_run_info.env.insert(
+        "GIT_EDITOR".into(),
+        r#"sh -c 'printf "edited message\n" > "$1"' sh"#.into(),
+    );
+    let repo = git.get_repo()?;
+    let message = edit_message(&git_run_info, &repo, "original message")?;
+    assert_eq!(message, "edited message");
+
+    Ok(())
+}
@@ -1,4 +1,5 @@
 use std::str::FromStr;
+use std::time::{Duration, SystemTime};
 
 use branchless::core::eventlog::testing::{new_event_cursor, new_event_transaction_id};
 use branchless::core::eventlog::{
@@ -160,3 +161,140 @@ fn test_advance_cursor_by_transaction() -> eyre::Result<()> {
 
     Ok(())
 }
+
+#[test]
+fn test_make_cursor_before_transaction() -> eyre::Result<()> {
+    let mut event_replayer = new_event_replayer("refs/heads/master".into());
+    for (timestamp, event_tx_id) in (0..).zip(&[1, 1, 2, 2, 3]) {
+        let timestamp = f64::from(timestamp);
+        event_replayer.process_event(&Event::UnobsoleteEvent {
+            timestamp,
+            event_tx_id: new_event_transaction_id(*event_tx_id),
+            commit_oid: NonZeroOid::from_str("abc")?,
+        });
+    }
+
+    assert_eq!(
+        event_replayer.make_cursor_before_transaction(new_event_transaction_id(1)),
+        Some(new_event_cursor(0)),
+    );
+    assert_eq!(
+        event_replayer.make_cursor_before_transaction(new_event_transaction_id(2)),
+        Some(new_event_cursor(2)),
+    );
+    assert_eq!(
+        event_replayer.make_cursor_before_transaction(new_event_transaction_id(3)),
+        Some(new_event_cursor(4)),
+    );
+    assert_eq!(
+        event_replayer.make_cursor_before_transaction(new_event_transaction_id(4)),
+        None,
+    );
+
+    Ok(())
+}
+
+#[test]
+fn test_make_cursor_at_time() -> eyre::Result<()> {
+    let mut event_replayer = new_event_replayer("refs/heads/master".into());
+    for (timestamp, event_tx_id) in [(1.0, 1), (2.0, 2), (3.0, 2), (4.0, 3)] {
+        event_replayer.process_event(&Event::UnobsoleteEvent {
+            timestamp,
+            event_tx_id: new_event_transaction_id(event_tx_id),
+            commit_oid: NonZeroOid::from_str("abc")?,
+        });
+    }
+    let time = |seconds: f64| SystemTime::UNIX_EPOCH + Duration::from_secs_f64(seconds);
+
+    assert_eq!(
+        event_replayer.make_cursor_at_time(time(0.5)),
+        new_event_cursor(0)
+    );
+    assert_eq!(
+        event_replayer.make_cursor_at_time(time(2.0)),
+        new_event_cursor(2)
+    );
+    assert_eq!(
+        event_replayer.make_cursor_at_time(time(2.5)),
+        new_event_cursor(2)
+    );
+    assert_eq!(
+        event_replayer.make_cursor_at_time(time(5.0)),
+        new_event_cursor(4)
+    );
+
+    // The second transaction wasn't complete at these times, so it's excluded.
+    assert_eq!(
+        event_replayer.make_transaction_cursor_at_time(time(2.0)),
+        new_event_cursor(1)
+    );
+    assert_eq!(
+        event_replayer.make_transaction_cursor_at_time(time(2.5)),
+        new_event_cursor(1)
+    );
+    assert_eq!(
+        event_replayer.make_transaction_cursor_at_time(time(3.5)),
+        new_event_cursor(3)
+    );
+    assert_eq!(
+        event_replayer.make_transaction_cursor_at_time(time(5.0)),
+        new_event_cursor(4)
+    );
+
+    Ok(())
+}
+
+#[test]
+fn test_make_cursor_at_time_out_of_order() -> eyre::Result<()> {
+    let mut event_replayer = new_event_replayer("refs/heads/master".into());
+    // The third event was recorded by a process whose clock was behind.
+    for (timestamp, event_tx_id) in [(1.0, 1), (3.0, 2), (2.0, 3), (4.0, 4)] {
+        event_replayer.process_event(&Event::UnobsoleteEvent {
+            timestamp,
+            event_tx_id: new_event_transaction_id(event_tx_id),
+            commit_oid: NonZeroOid::from_str("abc")?,
+        });
+    }
+    let time = |seconds: f64| SystemTime::UNIX_EPOCH + Duration::from_secs_f64(seconds);
+
+    assert_eq!(
+        event_replayer.make_cursor_at_time(time(2.5)),
+        new_event_cursor(1)
+    );
+    assert_eq!(
+        event_replayer.make_cursor_at_time(time(3.5)),
+        new_event_cursor(3)
+    );
+
+    Ok(())
+}
+
+#[test]
+fn test_transaction_labels() -> eyre::Result<()> {
+    let git = make_git()?;
+    git.init_repo()?;
+
+    let repo = git.get_repo()?;
+    let conn = repo.get_db_conn()?;
+    let event_log_db = EventLogDb::new(&conn)?;
+    let now = SystemTime::UNIX_EPOCH;
+    let first_tx_id = event_log_db.make_transaction_id(now, "first")?;
+    let second_tx_id = event_log_db.make_transaction_id(now, "second")?;
+    event_log_db.set_transaction_label(second_tx_id, "do the second thing")?;
+
+    let transactions = event_log_db.get_transactions()?;
+    let transactions: Vec<(EventTransactionId, &str)> = transactions
+        .iter()
+        .filter(|transaction| [first_tx_id, second_tx_id].contains(&transaction.event_tx_id))
+        .map(|transaction| (transaction.event_tx_id, transaction.get_description()))
+        .collect();
+    assert_eq!(
+        transactions,
+        vec![
+            (first_tx_id, "first"),
+            (second_tx_id, "do the second thing"),
+        ]
+    );
+
+    Ok(())
+}
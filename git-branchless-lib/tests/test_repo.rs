@@ -1,8 +1,10 @@
-use std::path::PathBuf;
+use std::collections::HashSet;
+use std::path::{Path, PathBuf};
 
+use branchless::core::repo_ext::RepoExt;
 use branchless::git::{
-    AmendFastOptions, BranchType, CherryPickFastOptions, FileMode, FileStatus, GitVersion, Repo,
-    StatusEntry,
+    AmendFastOptions, BranchType, CherryPickFastOptions, CreateCommitFastError, FileMode,
+    FileStatus, GitVersion, Repo, StatusEntry, Tree,
 };
 use branchless::testing::{make_git, make_git_worktree, GitWorktreeWrapper};
 
@@ -75,6 +77,92 @@ fn test_cherry_pick_fast() -> eyre::Result<()> {
     Ok(())
 }
 
+#[test]
+fn test_cherry_pick_fast_custom_merge_driver() -> eyre::Result<()> {
+    let git = make_git()?;
+    git.init_repo()?;
+
+    git.write_file(".gitattributes", "test1.txt merge=theirs\n")?;
+    git.commit_file_with_contents("test1", 1, "line 1\nline 2\nline 3\n")?;
+    git.run(&["checkout", "-b", "foo"])?;
+    let foo_oid = git.commit_file_with_contents("test1", 2, "updated line 1\nline 2\nline 3\n")?;
+    git.run(&["checkout", "master"])?;
+    let master_oid =
+        git.commit_file_with_contents("test1", 3, "line 1\nline 2\nupdated line 3\n")?;
+    let options = CherryPickFastOptions {
+        reuse_parent_tree_if_possible: false,
+    };
+
+    // The merge driver isn't defined, so the built-in text driver is used.
+    {
+        let repo = git.get_repo()?;
+        let foo_commit = repo.find_commit_or_fail(foo_oid)?;
+        let master_commit = repo.find_commit_or_fail(master_oid)?;
+        let tree = repo.cherry_pick_fast(&foo_commit, &master_commit, &options)?;
+        assert_eq!(
+            tree.get_oid_for_path(Path::new("test1.txt"))?,
+            Some(
+                repo.create_blob_from_contents(b"updated line 1\nline 2\nupdated line 3\n")?
+                    .into()
+            )
+        );
+    }
+
+    git.run(&["config", "merge.theirs.driver", "cat %B > %A"])?;
+    {
+        let repo = git.get_repo()?;
+        let foo_commit = repo.find_commit_or_fail(foo_oid)?;
+        let master_commit = repo.find_commit_or_fail(master_oid)?;
+        let tree = repo.cherry_pick_fast(&foo_commit, &master_commit, &options)?;
+        assert_eq!(
+            tree.get_oid_for_path(Path::new("test1.txt"))?,
+            Some(
+                repo.create_blob_from_contents(b"updated line 1\nline 2\nline 3\n")?
+                    .into()
+            )
+        );
+    }
+
+    git.run(&["config", "merge.theirs.driver", "false"])?;
+    {
+        let repo = git.get_repo()?;
+        let foo_commit = repo.find_commit_or_fail(foo_oid)?;
+        let master_commit = repo.find_commit_or_fail(master_oid)?;
+        match repo.cherry_pick_fast(&foo_commit, &master_commit, &options) {
+            Err(CreateCommitFastError::MergeConflict { conflicting_paths }) => {
+                assert_eq!(
+                    conflicting_paths,
+                    HashSet::from([PathBuf::from("test1.txt")])
+                );
+            }
+            result => panic!("Expected a merge conflict, got: {result:?}"),
+        }
+    }
+
+    // `%S`, `%X`, and `%Y` are replaced by the conflict labels, and `%%` by a
+    // literal `%`.
+    git.run(&[
+        "config",
+        "merge.theirs.driver",
+        "printf '%%s|%%s|%%s' %S %X %Y > %A",
+    ])?;
+    {
+        let repo = git.get_repo()?;
+        let foo_commit = repo.find_commit_or_fail(foo_oid)?;
+        let master_commit = repo.find_commit_or_fail(master_oid)?;
+        let tree = repo.cherry_pick_fast(&foo_commit, &master_commit, &options)?;
+        let foo_label = format!("{} (create test1.txt)", foo_commit.get_short_oid()?);
+        let master_label = format!("{} (create test1.txt)", master_commit.get_short_oid()?);
+        let expected = format!("parent of {foo_label}|{master_label}|{foo_label}");
+        assert_eq!(
+            tree.get_oid_for_path(Path::new("test1.txt"))?,
+            Some(repo.create_blob_from_contents(expected.as_bytes())?.into())
+        );
+    }
+
+    Ok(())
+}
+
 #[test]
 fn test_amend_fast_from_index() -> eyre::Result<()> {
     let git = make_git()?;
@@ -174,6 +262,7 @@ fn test_amend_fast_from_working_tree() -> eyre::Result<()> {
                 working_copy_file_mode: FileMode::Blob,
                 path: "initial.txt".into(),
                 orig_path: None,
+                submodule_state: None,
             }],
         },
     )?;
@@ -205,6 +294,7 @@ fn test_amend_fast_from_working_tree() -> eyre::Result<()> {
                 working_copy_file_mode: FileMode::Blob,
                 path: "file2.txt".into(),
                 orig_path: None,
+                submodule_state: None,
             }],
         },
     )?;
@@ -238,6 +328,7 @@ fn test_amend_fast_from_working_tree() -> eyre::Result<()> {
                 working_copy_file_mode: FileMode::Blob,
                 path: "initial.txt".into(),
                 orig_path: None,
+                submodule_state: None,
             }],
         },
     )?;
@@ -253,6 +344,203 @@ fn test_amend_fast_from_working_tree() -> eyre::Result<()> {
     Ok(())
 }
 
+#[test]
+fn test_amend_fast_applies_gitattributes() -> eyre::Result<()> {
+    let git = make_git()?;
+    git.init_repo()?;
+
+    git.write_file(".gitattributes", "*.txt text\n*.upper filter=upper\n")?;
+    git.run(&["add", ".gitattributes"])?;
+    git.run(&["config", "filter.upper.clean", "tr a-z A-Z"])?;
+    let initial_oid = git.commit_file_with_contents("initial", 2, "initial contents")?;
+    git.write_file_txt("initial", "line 1\r\nline 2\r\n")?;
+    git.write_file("file.upper", "lowercase contents\n")?;
+
+    let repo = git.get_repo()?;
+    let initial_commit = repo.find_commit_or_fail(initial_oid)?;
+    let tree = repo.amend_fast(
+        &initial_commit,
+        &AmendFastOptions::FromWorkingCopy {
+            status_entries: vec![
+                StatusEntry {
+                    index_status: FileStatus::Unmodified,
+                    working_copy_status: FileStatus::Modified,
+                    working_copy_file_mode: FileMode::Blob,
+                    path: "initial.txt".into(),
+                    orig_path: None,
+                    submodule_state: None,
+                },
+                StatusEntry {
+                    index_status: FileStatus::Unmodified,
+                    working_copy_status: FileStatus::Added,
+                    working_copy_file_mode: FileMode::Blob,
+                    path: "file.upper".into(),
+                    orig_path: None,
+                    submodule_state: None,
+                },
+            ],
+        },
+    )?;
+
+    assert_eq!(
+        tree.get_oid_for_path(Path::new("initial.txt"))?,
+        Some(repo.create_blob_from_contents(b"line 1\nline 2\n")?.into())
+    );
+    assert_eq!(
+        tree.get_oid_for_path(Path::new("file.upper"))?,
+        Some(
+            repo.create_blob_from_contents(b"LOWERCASE CONTENTS\n")?
+                .into()
+        )
+    );
+
+    Ok(())
+}
+
+#[test]
+fn test_amend_fast_defers_ident_to_git() -> eyre::Result<()> {
+    let git = make_git()?;
+    git.init_repo()?;
+
+    git.write_file(".gitattributes", "*.txt ident\n")?;
+    git.run(&["add", ".gitattributes"])?;
+    let initial_oid = git.commit_file_with_contents("initial", 2, "initial contents")?;
+    git.write_file_txt("initial", "$Id: 0123456789abcdef $\n")?;
+
+    let repo = git.get_repo()?;
+    let initial_commit = repo.find_commit_or_fail(initial_oid)?;
+    let tree = repo.amend_fast(
+        &initial_commit,
+        &AmendFastOptions::FromWorkingCopy {
+            status_entries: vec![StatusEntry {
+                index_status: FileStatus::Unmodified,
+                working_copy_status: FileStatus::Modified,
+                working_copy_file_mode: FileMode::Blob,
+                path: "initial.txt".into(),
+                orig_path: None,
+                submodule_state: None,
+            }],
+        },
+    )?;
+
+    // Git collapses the expanded `$Id$` keyword when storing the file.
+    assert_eq!(
+        tree.get_oid_for_path(Path::new("initial.txt"))?,
+        Some(repo.create_blob_from_contents(b"$Id$\n")?.into())
+    );
+
+    Ok(())
+}
+
+#[test]
+fn test_amend_fast_lfs_pointer() -> eyre::Result<()> {
+    let git = make_git()?;
+    git.init_repo()?;
+
+    git.write_file(".gitattributes", "*.bin filter=lfs\n")?;
+    git.run(&["add", ".gitattributes"])?;
+    let initial_oid = git.commit_file_with_contents("initial", 2, "initial contents")?;
+    // Git LFS shouldn't be invoked for files which are already pointers.
+    git.run(&["config", "filter.lfs.clean", "false"])?;
+    git.run(&["config", "filter.lfs.required", "true"])?;
+    let pointer = format!(
+        "version https://git-lfs.github.com/spec/v1\noid sha256:{}\nsize 12345\n",
+        "a".repeat(64)
+    );
+    git.write_file("large.bin", &pointer)?;
+
+    let repo = git.get_repo()?;
+    let initial_commit = repo.find_commit_or_fail(initial_oid)?;
+    let tree = repo.amend_fast(
+        &initial_commit,
+        &AmendFastOptions::FromWorkingCopy {
+            status_entries: vec![StatusEntry {
+                index_status: FileStatus::Unmodified,
+                working_copy_status: FileStatus::Added,
+                working_copy_file_mode: FileMode::Blob,
+                path: "large.bin".into(),
+                orig_path: None,
+                submodule_state: None,
+            }],
+        },
+    )?;
+    assert_eq!(
+        tree.get_oid_for_path(Path::new("large.bin"))?,
+        Some(repo.create_blob_from_contents(pointer.as_bytes())?.into())
+    );
+
+    Ok(())
+}
+
+#[test]
+#[cfg(unix)]
+fn test_amend_fast_file_mode() -> eyre::Result<()> {
+    use std::fs::Permissions;
+    use std::os::unix::fs::PermissionsExt;
+
+    let git = make_git()?;
+    git.init_repo()?;
+
+    let initial_oid = git.commit_file_with_contents("initial", 2, "initial contents")?;
+    let repo = git.get_repo()?;
+    let initial_commit = repo.find_commit_or_fail(initial_oid)?;
+    let status_entries = vec![StatusEntry {
+        index_status: FileStatus::Unmodified,
+        working_copy_status: FileStatus::Modified,
+        working_copy_file_mode: FileMode::Blob,
+        path: "initial.txt".into(),
+        orig_path: None,
+        submodule_state: None,
+    }];
+    let get_file_mode = |tree: &Tree| -> eyre::Result<Option<FileMode>> {
+        Ok(tree
+            .get_path(Path::new("initial.txt"))?
+            .map(|entry| entry.get_filemode()))
+    };
+
+    // The executable bit is read from disk.
+    git.set_file_permissions("initial", Permissions::from_mode(0o755))?;
+    let tree = repo.amend_fast(
+        &initial_commit,
+        &AmendFastOptions::FromWorkingCopy {
+            status_entries: status_entries.clone(),
+        },
+    )?;
+    assert_eq!(get_file_mode(&tree)?, Some(FileMode::BlobExecutable));
+
+    // If the executable bit on disk can't be trusted, then the mode from the
+    // parent commit is kept.
+    let executable_oid = repo.create_commit(
+        None,
+        &initial_commit.get_author(),
+        &initial_commit.get_committer(),
+        "make initial.txt executable",
+        &tree,
+        vec![&initial_commit],
+    )?;
+    let executable_commit = repo.find_commit_or_fail(executable_oid)?;
+    git.run(&["config", "core.fileMode", "false"])?;
+    git.set_file_permissions("initial", Permissions::from_mode(0o644))?;
+    git.write_file_txt("initial", "updated contents")?;
+    let tree = repo.amend_fast(
+        &executable_commit,
+        &AmendFastOptions::FromWorkingCopy {
+            status_entries: status_entries.clone(),
+        },
+    )?;
+    assert_eq!(get_file_mode(&tree)?, Some(FileMode::BlobExecutable));
+
+    // Otherwise, the executable bit can be removed.
+    git.run(&["config", "core.fileMode", "true"])?;
+    let tree = repo.amend_fast(
+        &executable_commit,
+        &AmendFastOptions::FromWorkingCopy { status_entries },
+    )?;
+    assert_eq!(get_file_mode(&tree)?, Some(FileMode::Blob));
+
+    Ok(())
+}
+
 #[test]
 fn test_branch_debug() -> eyre::Result<()> {
     let git = make_git()?;
@@ -303,3 +591,149 @@ fn test_worktree_working_copy_path() -> eyre::Result<()> {
 
     Ok(())
 }
+
+#[test]
+fn test_get_promisor_remote_names() -> eyre::Result<()> {
+    let git = make_git()?;
+    git.init_repo()?;
+
+    {
+        let repo = git.get_repo()?;
+        assert!(repo.get_promisor_remote_names()?.is_empty());
+    }
+
+    git.run(&["config", "extensions.partialClone", "origin"])?;
+    git.run(&["config", "remote.origin.promisor", "true"])?;
+    git.run(&["config", "remote.other.promisor", "true"])?;
+    git.run(&["config", "remote.disabled.promisor", "false"])?;
+    {
+        let repo = git.get_repo()?;
+        assert_eq!(
+            repo.get_promisor_remote_names()?,
+            vec!["origin".to_string(), "other".to_string()]
+        );
+    }
+
+    Ok(())
+}
+
+#[test]
+fn test_get_db_conn_uses_wal() -> eyre::Result<()> {
+    let git = make_git()?;
+    git.init_repo()?;
+
+    let repo = git.get_repo()?;
+    let conn = repo.get_db_conn()?;
+    let journal_mode: String = conn.query_row("PRAGMA journal_mode", [], |row| row.get(0))?;
+    assert_eq!(journal_mode, "wal");
+
+    Ok(())
+}
+
+#[test]
+fn test_get_commit_graph() -> eyre::Result<()> {
+    let git = make_git()?;
+    git.init_repo()?;
+
+    {
+        let repo = git.get_repo()?;
+        assert!(repo.get_commit_graph()?.is_none());
+    }
+
+    let test1_oid = git.commit_file("test1", 1)?;
+    git.run(&["checkout", "-b", "other"])?;
+    let test2_oid = git.commit_file("test2", 2)?;
+    git.run(&["checkout", "master"])?;
+    let test3_oid = git.commit_file("test3", 3)?;
+    git.run(&["merge", "--no-ff", "other", "-m", "merge"])?;
+    let merge_oid = git.get_repo()?.get_head_info()?.oid.unwrap();
+    git.run(&["commit-graph", "write", "--reachable"])?;
+
+    {
+        let repo = git.get_repo()?;
+        let commit_graph = repo.get_commit_graph()?.unwrap();
+        assert_eq!(commit_graph.len(), 5);
+        assert_eq!(
+            commit_graph.get_parent_oids(merge_oid),
+            Some(vec![test3_oid, test2_oid])
+        );
+        assert_eq!(
+            commit_graph.get_parent_oids(test2_oid),
+            Some(vec![test1_oid])
+        );
+    }
+
+    let test4_oid = git.commit_file("test4", 4)?;
+    git.run(&["commit-graph", "write", "--reachable", "--split=no-merge"])?;
+    {
+        let repo = git.get_repo()?;
+        let commit_graph = repo.get_commit_graph()?.unwrap();
+        assert_eq!(commit_graph.len(), 6);
+        assert_eq!(
+            commit_graph.get_parent_oids(test4_oid),
+            Some(vec![merge_oid])
+        );
+    }
+
+    git.run(&["config", "core.commitGraph", "false"])?;
+    {
+        let repo = git.get_repo()?;
+        assert!(repo.get_commit_graph()?.is_none());
+    }
+
+    Ok(())
+}
+
+#[test]
+fn test_get_main_branch_reference_name_pinned() -> eyre::Result<()> {
+    let git = make_git()?;
+    git.init_repo()?;
+    let test1_oid = git.commit_file("test1", 1)?.to_string();
+    git.run(&["tag", "v1"])?;
+
+    for (main_commit_spec, expected) in [
+        ("v1", "refs/tags/v1".to_string()),
+        ("tags/v1", "refs/tags/v1".to_string()),
+        ("refs/tags/v1", "refs/tags/v1".to_string()),
+        ("master", "refs/heads/master".to_string()),
+        ("FETCH_HEAD", "FETCH_HEAD".to_string()),
+        ("v1~0", "v1~0".to_string()),
+        (test1_oid.as_str(), test1_oid.clone()),
+    ] {
+        git.run(&["config", "branchless.core.mainCommit", main_commit_spec])?;
+        let repo = git.get_repo()?;
+        assert_eq!(
+            repo.get_main_branch_reference_name()?.as_str(),
+            expected,
+            "{main_commit_spec}"
+        );
+    }
+
+    Ok(())
+}
+
+#[test]
+fn test_get_references_snapshot_tags() -> eyre::Result<()> {
+    let git = make_git()?;
+    git.init_repo()?;
+    let test1_oid = git.commit_file("test1", 1)?;
+    git.run(&["tag", "v1"])?;
+    git.run(&["tag", "-a", "-m", "annotated", "release/v1"])?;
+    git.run(&["tag", "tree-tag", "HEAD^{tree}"])?;
+    git.run(&["branch", "not-a-tag"])?;
+
+    let repo = git.get_repo()?;
+    let references_snapshot = repo.get_references_snapshot()?;
+    let mut tag_names: Vec<String> = references_snapshot
+        .tag_oid_to_names
+        .get(&test1_oid)
+        .into_iter()
+        .flatten()
+        .map(|name| name.as_str().to_string())
+        .collect();
+    tag_names.sort();
+    assert_eq!(tag_names, vec!["refs/tags/release/v1", "refs/tags/v1"]);
+    assert_eq!(references_snapshot.tag_oid_to_names.len(), 1);
+
+    Ok(())
+}
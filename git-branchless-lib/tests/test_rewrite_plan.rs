@@ -1,3 +1,4 @@
+use std::collections::HashMap;
 use std::time::SystemTime;
 
 use branchless::core::dag::Dag;
@@ -12,8 +13,9 @@ use branchless::core::eventlog::{EventLogDb, EventReplayer};
 use branchless::core::formatting::Glyphs;
 use branchless::core::repo_ext::RepoExt;
 use branchless::core::rewrite::{
-    execute_rebase_plan, BuildRebasePlanOptions, ExecuteRebasePlanOptions, ExecuteRebasePlanResult,
-    RebasePlan, RebasePlanBuilder, RepoResource,
+    continue_interrupted_rebase, execute_rebase_plan, execute_rebase_plan_with_input,
+    BuildRebasePlanOptions, ExecuteRebasePlanOptions, ExecuteRebasePlanResult, InterruptedRebase,
+    InterruptedRebaseDb, InterruptedRebaseStage, RebasePlan, RebasePlanBuilder, RepoResource,
 };
 use branchless::testing::{make_git, Git};
 
@@ -709,6 +711,188 @@ fn test_plan_fixup_parent_into_child() -> eyre::Result<()> {
 
 /// Helper function to handle the boilerplate involved in creating, building
 /// and executing the rebase plan.
+#[test]
+fn test_resume_in_memory_rebase_in_working_copy() -> eyre::Result<()> {
+    let git = make_git()?;
+
+    if !git.supports_committer_date_is_author_date()? {
+        return Ok(());
+    }
+    git.init_repo()?;
+
+    let base_oid = git.commit_file("test1", 1)?;
+    git.detach_head()?;
+    let test2_oid = git.commit_file("test2", 2)?;
+    let conflict_oid = git.commit_file_with_contents("conflict", 3, "conflict 1\n")?;
+    git.run(&["branch", "foo", &conflict_oid.to_string()])?;
+    git.run(&["checkout", &base_oid.to_string()])?;
+    let dest_oid = git.commit_file_with_contents("conflict", 3, "conflict 2\n")?;
+
+    let effects = Effects::new_suppress_for_test(Glyphs::text());
+    let repo = git.get_repo()?;
+    let conn = repo.get_db_conn()?;
+    let event_log_db = EventLogDb::new(&conn)?;
+    let event_replayer = EventReplayer::from_event_log_db(&effects, &repo, &event_log_db)?;
+    let event_cursor = event_replayer.make_default_cursor();
+    let references_snapshot = repo.get_references_snapshot()?;
+    let dag = Dag::open_and_sync(
+        &effects,
+        &repo,
+        &event_replayer,
+        event_cursor,
+        &references_snapshot,
+    )?;
+
+    let pool = ThreadPoolBuilder::new().build()?;
+    let repo_pool = RepoResource::new_pool(&repo)?;
+    let build_options = BuildRebasePlanOptions {
+        force_rewrite_public_commits: false,
+        dump_rebase_constraints: false,
+        dump_rebase_plan: false,
+        detect_duplicate_commits_via_patch_id: true,
+    };
+    let permissions = omnipotent_rebase_plan_permissions(&dag, build_options)?;
+    let mut builder = RebasePlanBuilder::new(&dag, permissions);
+    builder.move_subtree(test2_oid, vec![dest_oid])?;
+    let rebase_plan = match builder.build(&effects, &pool, &repo_pool)? {
+        Ok(Some(rebase_plan)) => rebase_plan,
+        Ok(None) => eyre::bail!("Expected a rebase plan"),
+        Err(rebase_plan_error) => {
+            eyre::bail!("Error building rebase plan: {:#?}", rebase_plan_error)
+        }
+    };
+
+    let now = SystemTime::UNIX_EPOCH;
+    let options = ExecuteRebasePlanOptions {
+        now,
+        event_tx_id: event_log_db.make_transaction_id(now, "test plan")?,
+        preserve_timestamps: false,
+        force_in_memory: false,
+        force_on_disk: false,
+        resolve_merge_conflicts: false,
+        check_out_commit_options: CheckOutCommitOptions {
+            additional_args: Default::default(),
+            reset: false,
+            render_smartlog: false,
+        },
+    };
+    let git_run_info = git.get_git_run_info();
+    let result = execute_rebase_plan_with_input(
+        &effects,
+        &git_run_info,
+        &repo,
+        &event_log_db,
+        &rebase_plan,
+        &options,
+        Some(&mut "y\n".as_bytes()),
+    )?;
+    assert!(matches!(
+        result,
+        ExecuteRebasePlanResult::Failed { exit_code: _ }
+    ));
+    assert_eq!(repo.get_current_operation_type(), Some("rebase"));
+
+    git.resolve_file("conflict", "resolved\n")?;
+    git.run(&["rebase", "--continue"])?;
+    assert_eq!(repo.get_current_operation_type(), None);
+    {
+        let (stdout, _stderr) = git.run(&["log", "--format=%s", &format!("{dest_oid}..foo")])?;
+        assert_eq!(stdout, "create conflict.txt\ncreate test2.txt\n");
+        let (stdout, _stderr) = git.run(&["show", "foo:conflict.txt"])?;
+        assert_eq!(stdout, "resolved\n");
+    }
+
+    Ok(())
+}
+
+#[test]
+fn test_continue_rebase_interrupted_before_rewriting_commits() -> eyre::Result<()> {
+    let git = make_git()?;
+    git.init_repo()?;
+
+    let base_oid = git.commit_file("test1", 1)?;
+    git.detach_head()?;
+    let test2_oid = git.commit_file("test2", 2)?;
+    git.run(&["branch", "foo", &test2_oid.to_string()])?;
+    git.run(&["checkout", &base_oid.to_string()])?;
+    let dest_oid = git.commit_file("test3", 3)?;
+
+    let effects = Effects::new_suppress_for_test(Glyphs::text());
+    let repo = git.get_repo()?;
+    let conn = repo.get_db_conn()?;
+    let event_log_db = EventLogDb::new(&conn)?;
+    let event_replayer = EventReplayer::from_event_log_db(&effects, &repo, &event_log_db)?;
+    let event_cursor = event_replayer.make_default_cursor();
+    let references_snapshot = repo.get_references_snapshot()?;
+    let dag = Dag::open_and_sync(
+        &effects,
+        &repo,
+        &event_replayer,
+        event_cursor,
+        &references_snapshot,
+    )?;
+
+    let pool = ThreadPoolBuilder::new().build()?;
+    let repo_pool = RepoResource::new_pool(&repo)?;
+    let build_options = BuildRebasePlanOptions {
+        force_rewrite_public_commits: false,
+        dump_rebase_constraints: false,
+        dump_rebase_plan: false,
+        detect_duplicate_commits_via_patch_id: true,
+    };
+    let permissions = omnipotent_rebase_plan_permissions(&dag, build_options)?;
+    let mut builder = RebasePlanBuilder::new(&dag, permissions);
+    builder.move_subtree(test2_oid, vec![dest_oid])?;
+    let rebase_plan = match builder.build(&effects, &pool, &repo_pool)? {
+        Ok(Some(rebase_plan)) => rebase_plan,
+        Ok(None) => eyre::bail!("Expected a rebase plan"),
+        Err(rebase_plan_error) => {
+            eyre::bail!("Error building rebase plan: {:#?}", rebase_plan_error)
+        }
+    };
+
+    // Simulate a rebase which was interrupted before any of its commits were
+    // rewritten.
+    let interrupted_rebase_db = InterruptedRebaseDb::new(&conn)?;
+    interrupted_rebase_db.save(&InterruptedRebase {
+        event_tx_id: event_log_db.make_transaction_id(SystemTime::UNIX_EPOCH, "test plan")?,
+        rebase_plan: Some(rebase_plan),
+        preserve_timestamps: false,
+        head_info: repo.get_head_info()?,
+        skipped_head_updated_oid: None,
+        rewritten_oids: HashMap::new(),
+        original_branches: HashMap::new(),
+        stage: InterruptedRebaseStage::RebaseCommits,
+    })?;
+    let interrupted_rebase = match interrupted_rebase_db.load()? {
+        Some(interrupted_rebase) => interrupted_rebase,
+        None => eyre::bail!("Expected an interrupted rebase"),
+    };
+
+    let git_run_info = git.get_git_run_info();
+    let result = continue_interrupted_rebase(
+        &effects,
+        &git_run_info,
+        &repo,
+        &event_log_db,
+        &interrupted_rebase_db,
+        &interrupted_rebase,
+        &CheckOutCommitOptions {
+            additional_args: Default::default(),
+            reset: false,
+            render_smartlog: false,
+        },
+    )?;
+    assert!(matches!(result, Ok(())));
+    assert!(interrupted_rebase_db.load()?.is_none());
+    {
+        let (stdout, _stderr) = git.run(&["log", "--format=%s", &format!("{dest_oid}..foo")])?;
+        assert_eq!(stdout, "create test2.txt\n");
+    }
+
+    Ok(())
+}
+
 fn create_and_execute_plan(
     git: &Git,
     builder_callback_fn: impl Fn(&mut RebasePlanBuilder) -> eyre::Result<()>,
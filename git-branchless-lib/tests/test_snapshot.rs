@@ -1,3 +1,4 @@
+use std::path::Path;
 use std::time::SystemTime;
 
 use branchless::core::effects::Effects;
@@ -48,6 +49,7 @@ fn test_has_conflicts() -> eyre::Result<()> {
                 working_copy_file_mode: Blob,
                 path: "test2.txt",
                 orig_path: None,
+                submodule_state: None,
             },
         ]
         "###);
@@ -58,3 +60,47 @@ fn test_has_conflicts() -> eyre::Result<()> {
 
     Ok(())
 }
+
+#[test]
+fn test_snapshot_rename_with_untracked_source() -> eyre::Result<()> {
+    let git = make_git()?;
+    git.init_repo()?;
+    git.commit_file("test1", 1)?;
+
+    git.run(&["mv", "test1.txt", "renamed.txt"])?;
+    git.write_file_txt("test1", "untracked contents")?;
+
+    let glyphs = Glyphs::text();
+    let effects = Effects::new_suppress_for_test(glyphs);
+    let git_run_info = git.get_git_run_info();
+    let repo = git.get_repo()?;
+    let (snapshot, status) = repo.get_status(
+        &effects,
+        &git_run_info,
+        &repo.get_index()?,
+        &repo.get_head_info()?,
+        None,
+    )?;
+    insta::assert_debug_snapshot!(status, @r###"
+        [
+            StatusEntry {
+                index_status: Renamed,
+                working_copy_status: Unmodified,
+                working_copy_file_mode: Blob,
+                path: "renamed.txt",
+                orig_path: Some(
+                    "test1.txt",
+                ),
+                submodule_state: None,
+            },
+        ]
+        "###);
+
+    // The untracked file at the old path shouldn't be included in the
+    // snapshot.
+    let unstaged_tree = snapshot.commit_unstaged.get_tree()?;
+    assert!(unstaged_tree.get_path(Path::new("test1.txt"))?.is_none());
+    assert!(unstaged_tree.get_path(Path::new("renamed.txt"))?.is_some());
+
+    Ok(())
+}
@@ -1,8 +1,8 @@
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
 
 use branchless::core::effects::Effects;
 use branchless::core::formatting::Glyphs;
-use branchless::git::{FileMode, FileStatus, StatusEntry, WorkingCopyChangesType};
+use branchless::git::{FileMode, FileStatus, StatusEntry, SubmoduleState, WorkingCopyChangesType};
 use branchless::testing::make_git;
 
 #[test]
@@ -16,6 +16,7 @@ fn test_parse_status_line() {
                 working_copy_status: FileStatus::Modified,
                 path: "repo.rs".into(),
                 orig_path: None,
+                submodule_state: None,
                 working_copy_file_mode: FileMode::Blob,
             }
         );
@@ -29,6 +30,7 @@ fn test_parse_status_line() {
                 working_copy_status: FileStatus::Modified,
                 path: "filename with spaces.rs".into(),
                 orig_path: None,
+                submodule_state: None,
                 working_copy_file_mode: FileMode::Blob,
             }
         );
@@ -42,6 +44,7 @@ fn test_parse_status_line() {
                 working_copy_status: FileStatus::Unmodified,
                 path: "repo.rs".into(),
                 orig_path: None,
+                submodule_state: None,
                 working_copy_file_mode: FileMode::BlobExecutable,
             }
         );
@@ -55,6 +58,7 @@ fn test_parse_status_line() {
                 working_copy_status: FileStatus::Unmodified,
                 path: "filename with spaces.rs".into(),
                 orig_path: None,
+                submodule_state: None,
                 working_copy_file_mode: FileMode::BlobExecutable,
             }
         );
@@ -69,6 +73,7 @@ fn test_parse_status_line() {
             working_copy_status: FileStatus::Deleted,
             path: "new_file.rs".into(),
             orig_path: Some("old_file.rs".into()),
+            submodule_state: None,
             working_copy_file_mode: FileMode::Blob,
         }
     );
@@ -76,6 +81,25 @@ fn test_parse_status_line() {
         entry.paths(),
         vec![PathBuf::from("new_file.rs"), PathBuf::from("old_file.rs")]
     );
+    assert_eq!(entry.rename_source(), Some(Path::new("old_file.rs")));
+    assert_eq!(entry.copy_source(), None);
+
+    let entry: StatusEntry = StatusEntry::try_from(
+            "2 C. N... 100644 100644 100644 9daeafb9864cf43055ae93beb0afd6c7d144bfa4 9daeafb9864cf43055ae93beb0afd6c7d144bfa4 C100 new_file.rs\x00old_file.rs".as_bytes(),
+        ).unwrap();
+    assert_eq!(
+        entry,
+        StatusEntry {
+            index_status: FileStatus::Copied,
+            working_copy_status: FileStatus::Unmodified,
+            path: "new_file.rs".into(),
+            orig_path: Some("old_file.rs".into()),
+            submodule_state: None,
+            working_copy_file_mode: FileMode::Blob,
+        }
+    );
+    assert_eq!(entry.rename_source(), None);
+    assert_eq!(entry.copy_source(), Some(Path::new("old_file.rs")));
 
     assert_eq!(
             StatusEntry::try_from(
@@ -86,6 +110,7 @@ fn test_parse_status_line() {
                 working_copy_status: FileStatus::Unmodified,
                 path: "repo.rs".into(),
                 orig_path: None,
+                submodule_state: None,
                 working_copy_file_mode: FileMode::BlobExecutable,
             }
         );
@@ -99,9 +124,46 @@ fn test_parse_status_line() {
                 working_copy_status: FileStatus::Unmodified,
                 path: "filename with spaces.rs".into(),
                 orig_path: None,
+                submodule_state: None,
                 working_copy_file_mode: FileMode::BlobExecutable,
             }
         );
+
+    assert_eq!(
+            StatusEntry::try_from(
+                "1 .M SCMU 160000 160000 160000 51fcbe2362663a19d132767b69c2c7829023f3da 51fcbe2362663a19d132767b69c2c7829023f3da submodule".as_bytes(),
+            ).unwrap(),
+            StatusEntry {
+                index_status: FileStatus::Unmodified,
+                working_copy_status: FileStatus::Modified,
+                path: "submodule".into(),
+                orig_path: None,
+                submodule_state: Some(SubmoduleState {
+                    commit_changed: true,
+                    has_tracked_changes: true,
+                    has_untracked_changes: true,
+                }),
+                working_copy_file_mode: FileMode::Commit,
+            }
+        );
+
+    assert_eq!(
+            StatusEntry::try_from(
+                "1 .M S..U 160000 160000 160000 51fcbe2362663a19d132767b69c2c7829023f3da 51fcbe2362663a19d132767b69c2c7829023f3da submodule".as_bytes(),
+            ).unwrap(),
+            StatusEntry {
+                index_status: FileStatus::Unmodified,
+                working_copy_status: FileStatus::Modified,
+                path: "submodule".into(),
+                orig_path: None,
+                submodule_state: Some(SubmoduleState {
+                    commit_changed: false,
+                    has_tracked_changes: false,
+                    has_untracked_changes: true,
+                }),
+                working_copy_file_mode: FileMode::Commit,
+            }
+        );
 }
 
 #[test]
@@ -206,21 +268,24 @@ fn test_get_status() -> eyre::Result<()> {
                 working_copy_status: FileStatus::Deleted,
                 working_copy_file_mode: FileMode::Unreadable,
                 path: "initial.txt".into(),
-                orig_path: None
+                orig_path: None,
+                submodule_state: None,
             },
             StatusEntry {
                 index_status: FileStatus::Added,
                 working_copy_status: FileStatus::Unmodified,
                 working_copy_file_mode: FileMode::Blob,
                 path: "new_file.txt".into(),
-                orig_path: None
+                orig_path: None,
+                submodule_state: None,
             },
             StatusEntry {
                 index_status: FileStatus::Renamed,
                 working_copy_status: FileStatus::Unmodified,
                 working_copy_file_mode: FileMode::Blob,
                 path: "renamed.txt".into(),
-                orig_path: Some("test1.txt".into())
+                orig_path: Some("test1.txt".into()),
+                submodule_state: None,
             }
         ]
     );
@@ -280,3 +345,37 @@ fn test_get_status() -> eyre::Result<()> {
 
     Ok(())
 }
+
+#[test]
+fn test_get_status_skip_worktree() -> eyre::Result<()> {
+    let git = make_git()?;
+    let git_run_info = git.get_git_run_info();
+    git.init_repo()?;
+    git.commit_file("test1", 1)?;
+
+    // Simulate `test1.txt` being outside of the sparse-checkout cone.
+    git.run(&["update-index", "--skip-worktree", "test1.txt"])?;
+    git.delete_file("test1")?;
+
+    let glyphs = Glyphs::text();
+    let effects = Effects::new_suppress_for_test(glyphs);
+    let repo = git.get_repo()?;
+    let index = repo.get_index()?;
+    assert!(index.is_skip_worktree(Path::new("test1.txt")));
+    assert!(!index.is_skip_worktree(Path::new("initial.txt")));
+
+    let (snapshot, status) = repo.get_status(
+        &effects,
+        &git_run_info,
+        &index,
+        &repo.get_head_info()?,
+        None,
+    )?;
+    assert_eq!(status, vec![]);
+    assert_eq!(
+        snapshot.get_working_copy_changes_type()?,
+        WorkingCopyChangesType::None
+    );
+
+    Ok(())
+}
@@ -15,6 +15,7 @@
 use std::collections::HashMap;
 use std::convert::TryFrom;
 use std::fmt::Write;
+use std::io::BufRead;
 use std::time::SystemTime;
 
 use eden_dag::VertexName;
@@ -32,9 +33,11 @@ use lib::core::config::{
 use lib::core::dag::{sorted_commit_set, union_all, CommitSet, Dag};
 use lib::core::effects::Effects;
 use lib::core::eventlog::{EventLogDb, EventReplayer};
+use lib::core::formatting::Pluralize;
 use lib::core::rewrite::{
-    execute_rebase_plan, BuildRebasePlanOptions, ExecuteRebasePlanOptions, ExecuteRebasePlanResult,
-    MergeConflictRemediation, RebasePlanBuilder, RebasePlanPermissions, RepoResource,
+    execute_rebase_plan_with_input, find_autosquash_commits, BuildRebasePlanOptions,
+    ExecuteRebasePlanOptions, ExecuteRebasePlanResult, MergeConflictRemediation, RebasePlanBuilder,
+    RebasePlanPermissions, RepoResource,
 };
 use lib::git::{GitRunInfo, NonZeroOid, Repo};
 
@@ -63,7 +66,10 @@ fn resolve_base_commit(
 }
 
 /// Move a subtree from one place to another.
-#[instrument]
+///
+/// If `input` is provided, then the user may be asked how to proceed if the
+/// move can't be carried out in-memory.
+#[instrument(skip(input))]
 pub fn r#move(
     effects: &Effects,
     git_run_info: &GitRunInfo,
@@ -75,6 +81,8 @@ pub fn r#move(
     move_options: &MoveOptions,
     fixup: bool,
     insert: bool,
+    autosquash: bool,
+    input: Option<&mut dyn BufRead>,
 ) -> EyreExitOr<()> {
     let sources_provided = !sources.is_empty();
     let bases_provided = !bases.is_empty();
@@ -217,6 +225,19 @@ pub fn r#move(
         for base_oid in dag.commit_set_to_vec(&base_oids)? {
             let merge_base_oid =
                 dag.query_gca_one(vec![base_oid, dest_oid].into_iter().collect::<CommitSet>())?;
+            if merge_base_oid.is_none() && repo.is_shallow() {
+                // Without a merge-base, the base would resolve to the shallow
+                // boundary, which would move far more commits than intended.
+                writeln!(
+                    effects.get_output_stream(),
+                    "Could not find a common ancestor of {base_oid} and {dest_oid}, since this repository is a shallow clone."
+                )?;
+                writeln!(
+                    effects.get_output_stream(),
+                    "To fetch the full history, run: git fetch --unshallow"
+                )?;
+                return Ok(Err(ExitCode(1)));
+            }
             let base_commit_oid = resolve_base_commit(&dag, merge_base_oid, base_oid)?;
             result.push(CommitSet::from(base_commit_oid))
         }
@@ -265,6 +286,22 @@ pub fn r#move(
     } = *move_options;
     let now = SystemTime::now();
     let event_tx_id = event_log_db.make_transaction_id(now, "move")?;
+    let label = {
+        let moved_commits =
+            dag.filter_visible_commits(dag.query_descendants(source_oids.clone())?.union(
+                &union_all(&exact_components.values().cloned().collect::<Vec<_>>()),
+            ))?;
+        let dest_commit = repo.find_commit_or_fail(dest_oid)?;
+        format!(
+            "move {} onto {}",
+            Pluralize {
+                determiner: None,
+                amount: dag.set_count(&moved_commits)?,
+                unit: ("commit", "commits"),
+            },
+            dest_commit.get_short_oid()?
+        )
+    };
     let pool = ThreadPoolBuilder::new().build()?;
     let repo_pool = RepoResource::new_pool(&repo)?;
     let rebase_plan = {
@@ -471,6 +508,16 @@ pub fn r#move(
                 builder.move_subtree(dest_child, vec![source_head])?;
             }
         }
+
+        if autosquash {
+            let moved_commits =
+                dag.filter_visible_commits(dag.query_descendants(source_oids.clone())?.union(
+                    &union_all(&exact_components.values().cloned().collect::<Vec<_>>()),
+                ))?;
+            for (fixup_oid, target_oid) in find_autosquash_commits(&repo, &dag, &moved_commits)? {
+                builder.fixup_commit(fixup_oid, target_oid)?;
+            }
+        }
         builder.build(effects, &pool, &repo_pool)?
     };
     let result = match rebase_plan {
@@ -488,13 +535,14 @@ pub fn r#move(
                 resolve_merge_conflicts,
                 check_out_commit_options: Default::default(),
             };
-            execute_rebase_plan(
+            execute_rebase_plan_with_input(
                 effects,
                 git_run_info,
                 &repo,
                 &event_log_db,
                 &rebase_plan,
                 &options,
+                input,
             )?
         }
         Err(err) => {
@@ -504,7 +552,10 @@ pub fn r#move(
     };
 
     match result {
-        ExecuteRebasePlanResult::Succeeded { rewritten_oids: _ } => Ok(Ok(())),
+        ExecuteRebasePlanResult::Succeeded { rewritten_oids: _ } => {
+            event_log_db.set_transaction_label(event_tx_id, label)?;
+            Ok(Ok(()))
+        }
 
         ExecuteRebasePlanResult::DeclinedToMerge { failed_merge_info } => {
             failed_merge_info.describe(effects, &repo, MergeConflictRemediation::Retry)?;
@@ -22,16 +22,19 @@ use cursive::utils::markup::StyledString;
 
 use lib::core::check_out::{check_out_commit, CheckOutCommitOptions, CheckoutTarget};
 use lib::core::repo_ext::RepoExt;
+use lib::core::rewrite::{clear_edit_state, find_abandoned_children, read_edit_state, EditState};
+use lib::try_exit_code;
 use lib::util::{ExitCode, EyreExitOr};
 use tracing::{instrument, warn};
 
-use git_branchless_opts::{SwitchOptions, TraverseCommitsOptions};
-use git_branchless_revset::resolve_default_smartlog_commits;
+use git_branchless_opts::{ResolveRevsetOptions, Revset, SwitchOptions, TraverseCommitsOptions};
+use git_branchless_revset::{resolve_commits, resolve_default_smartlog_commits};
 use git_branchless_smartlog::make_smartlog_graph;
+use lib::core::commit_metadata::CommitMetadataDescriptor;
 use lib::core::config::get_next_interactive;
-use lib::core::dag::{sorted_commit_set, CommitSet, Dag};
+use lib::core::dag::{sorted_commit_set, union_all, CommitSet, Dag};
 use lib::core::effects::Effects;
-use lib::core::eventlog::{EventLogDb, EventReplayer};
+use lib::core::eventlog::{EventLogDb, EventReplayer, EventTransactionId};
 use lib::core::formatting::Pluralize;
 use lib::core::node_descriptors::{
     BranchesDescriptor, CommitMessageDescriptor, CommitOidDescriptor,
@@ -52,7 +55,7 @@ pub enum Command {
 }
 
 /// The number of commits to traverse.
-#[derive(Clone, Copy, Debug)]
+#[derive(Clone, Debug)]
 pub enum Distance {
     /// Traverse this number of commits or branches.
     NumCommits {
@@ -68,6 +71,19 @@ pub enum Distance {
         /// If `true`, find the farthest commit with a branch attached to it.
         move_by_branches: bool,
     },
+
+    /// Traverse this number of commits in the given set, skipping over any
+    /// commits not in the set.
+    Matching {
+        /// The number of matching commits to traverse.
+        amount: usize,
+
+        /// The revset which the commits were resolved from, for display.
+        revset: Revset,
+
+        /// The commits which may be traversed to.
+        commits: CommitSet,
+    },
 }
 
 /// Some commits have multiple children, which makes `next` ambiguous. These
@@ -94,7 +110,7 @@ fn advance(
     commit_descriptors: &mut [&mut dyn NodeDescriptor],
     current_oid: NonZeroOid,
     command: Command,
-    distance: Distance,
+    distance: &Distance,
     towards: Option<Towards>,
 ) -> eyre::Result<Option<NonZeroOid>> {
     let towards = match towards {
@@ -108,7 +124,7 @@ fn advance(
         }
     };
 
-    let public_commits = dag.query_ancestors(dag.main_branch_commit.clone())?;
+    let public_commits = dag.query_ancestors(dag.query_main_heads())?;
 
     let glyphs = effects.get_glyphs();
     let mut current_oid = current_oid;
@@ -130,6 +146,14 @@ fn advance(
                     Ok(nearest_descendant_branches)
                 };
 
+                let nearest_descendant_matches = |commits: &CommitSet| -> eyre::Result<CommitSet> {
+                    let descendant_commits = dag.query_descendants(child_commits()?)?;
+                    let descendant_matches =
+                        dag.filter_visible_commits(descendant_commits.intersection(commits))?;
+                    let nearest_descendant_matches = dag.query_roots(descendant_matches)?;
+                    Ok(nearest_descendant_matches)
+                };
+
                 let children = match distance {
                     Distance::AllTheWay {
                         move_by_branches: false,
@@ -146,6 +170,12 @@ fn advance(
                         amount: _,
                         move_by_branches: true,
                     } => descendant_branches()?,
+
+                    Distance::Matching {
+                        amount: _,
+                        revset: _,
+                        commits,
+                    } => nearest_descendant_matches(commits)?,
                 };
 
                 sorted_commit_set(repo, dag, &children)?
@@ -162,6 +192,12 @@ fn advance(
                     let nearest_ancestor_branches = dag.query_heads_ancestors(ancestor_branches)?;
                     Ok(nearest_ancestor_branches)
                 };
+                let nearest_ancestor_matches = |commits: &CommitSet| -> eyre::Result<CommitSet> {
+                    let ancestor_commits = dag.query_ancestors(parent_commits()?)?;
+                    let ancestor_matches = ancestor_commits.intersection(commits);
+                    let nearest_ancestor_matches = dag.query_heads_ancestors(ancestor_matches)?;
+                    Ok(nearest_ancestor_matches)
+                };
 
                 let parents = match distance {
                     Distance::AllTheWay {
@@ -193,6 +229,12 @@ fn advance(
                         amount: _,
                         move_by_branches: true,
                     } => ancestor_branches()?,
+
+                    Distance::Matching {
+                        amount: _,
+                        revset: _,
+                        commits,
+                    } => nearest_ancestor_matches(commits)?,
                 };
 
                 sorted_commit_set(repo, dag, &parents)?
@@ -203,8 +245,13 @@ fn advance(
             Distance::NumCommits {
                 amount,
                 move_by_branches: _,
+            }
+            | Distance::Matching {
+                amount,
+                revset: _,
+                commits: _,
             } => {
-                if i == amount {
+                if i == *amount {
                     break;
                 }
             }
@@ -238,16 +285,24 @@ fn advance(
 
         current_oid = match (towards, candidate_commits.as_slice()) {
             (_, []) => {
+                let message = match distance {
+                    Distance::Matching {
+                        amount: _,
+                        revset,
+                        commits: _,
+                    } => format!(
+                        "No more {} commits matching {} to go to after traversing {}.",
+                        pluralize.unit.0, revset, pluralize,
+                    ),
+                    Distance::NumCommits { .. } | Distance::AllTheWay { .. } => format!(
+                        "No more {} commits to go to after traversing {}.",
+                        pluralize.unit.0, pluralize,
+                    ),
+                };
                 writeln!(
                     effects.get_output_stream(),
                     "{}",
-                    glyphs.render(StyledString::styled(
-                        format!(
-                            "No more {} commits to go to after traversing {}.",
-                            pluralize.unit.0, pluralize,
-                        ),
-                        BaseColor::Yellow.light()
-                    ))?
+                    glyphs.render(StyledString::styled(message, BaseColor::Yellow.light()))?
                 )?;
 
                 if i == 0 {
@@ -305,6 +360,55 @@ fn advance(
     Ok(Some(current_oid))
 }
 
+/// If a commit is being edited with `git branchless edit`, then stop editing
+/// it. If it was amended, restack any of its descendants which weren't already
+/// moved onto the amended commit, so that they can be traversed.
+fn finish_edit(
+    effects: &Effects,
+    git_run_info: &GitRunInfo,
+    repo: &Repo,
+    event_log_db: &EventLogDb,
+    event_tx_id: EventTransactionId,
+) -> EyreExitOr<()> {
+    let EditState {
+        original_commit_oid,
+        current_commit_oid,
+    } = match read_edit_state(repo)? {
+        Some(edit_state) => edit_state,
+        None => return Ok(Ok(())),
+    };
+    clear_edit_state(repo)?;
+    if original_commit_oid == current_commit_oid {
+        return Ok(Ok(()));
+    }
+
+    let event_replayer = EventReplayer::from_event_log_db(effects, repo, event_log_db)?;
+    let event_cursor = event_replayer.make_default_cursor();
+    let references_snapshot = repo.get_references_snapshot()?;
+    let dag = Dag::open_and_sync(
+        effects,
+        repo,
+        &event_replayer,
+        event_cursor,
+        &references_snapshot,
+    )?;
+    match find_abandoned_children(&dag, &event_replayer, event_cursor, original_commit_oid)? {
+        Some((_rewritten_oid, abandoned_child_oids)) if !abandoned_child_oids.is_empty() => {
+            git_run_info.run(
+                effects,
+                Some(event_tx_id),
+                &[
+                    "branchless",
+                    "restack",
+                    "--in-memory",
+                    &original_commit_oid.to_string(),
+                ],
+            )
+        }
+        Some(_) | None => Ok(Ok(())),
+    }
+}
+
 /// Go forward or backward a certain number of commits.
 #[instrument]
 pub fn traverse_commits(
@@ -317,6 +421,10 @@ pub fn traverse_commits(
         num_commits,
         all_the_way,
         move_by_branches,
+        ref matching,
+        conflict,
+        failing,
+        unsubmitted,
         oldest,
         newest,
         interactive,
@@ -324,21 +432,16 @@ pub fn traverse_commits(
         force,
     } = *options;
 
-    let distance = match (all_the_way, num_commits) {
-        (false, None) => Distance::NumCommits {
-            amount: 1,
-            move_by_branches,
-        },
-
-        (false, Some(amount)) => Distance::NumCommits {
-            amount,
-            move_by_branches,
-        },
-
-        (true, None) => Distance::AllTheWay { move_by_branches },
-
-        (true, Some(_)) => {
-            eyre::bail!("num_commits and --all cannot both be set")
+    let matching = match (matching, conflict, failing, unsubmitted) {
+        (Some(revset), false, false, false) => Some(revset.clone()),
+        (None, true, false, false) => Some(Revset("conflicts()".to_string())),
+        (None, false, true, false) => Some(Revset("tests.failed()".to_string())),
+        (None, false, false, true) => Some(Revset("draft() - submitted()".to_string())),
+        (None, false, false, false) => None,
+        (_, _, _, _) => {
+            eyre::bail!(
+                "Only one of --matching, --conflict, --failing, and --unsubmitted can be set"
+            )
         }
     };
 
@@ -354,8 +457,6 @@ pub fn traverse_commits(
 
     let now = SystemTime::now();
     let repo = Repo::from_current_dir()?;
-    let head_info = repo.get_head_info()?;
-    let references_snapshot = repo.get_references_snapshot()?;
     let conn = repo.get_db_conn()?;
     let event_log_db = EventLogDb::new(&conn)?;
     let event_tx_id = event_log_db.make_transaction_id(
@@ -365,9 +466,20 @@ pub fn traverse_commits(
             Command::Prev => "prev",
         },
     )?;
+    if let Command::Next = command {
+        try_exit_code!(finish_edit(
+            effects,
+            git_run_info,
+            &repo,
+            &event_log_db,
+            event_tx_id
+        )?);
+    }
+    let head_info = repo.get_head_info()?;
+    let references_snapshot = repo.get_references_snapshot()?;
     let event_replayer = EventReplayer::from_event_log_db(effects, &repo, &event_log_db)?;
     let event_cursor = event_replayer.make_default_cursor();
-    let dag = Dag::open_and_sync(
+    let mut dag = Dag::open_and_sync(
         effects,
         &repo,
         &event_replayer,
@@ -375,6 +487,49 @@ pub fn traverse_commits(
         &references_snapshot,
     )?;
 
+    let distance = match (matching, all_the_way, num_commits) {
+        (Some(revset), false, num_commits) => {
+            let commits = match resolve_commits(
+                effects,
+                &repo,
+                &mut dag,
+                &[revset.clone()],
+                &ResolveRevsetOptions::default(),
+            ) {
+                Ok(commit_sets) => union_all(&commit_sets),
+                Err(err) => {
+                    err.describe(effects)?;
+                    return Ok(Err(ExitCode(1)));
+                }
+            };
+            Distance::Matching {
+                amount: num_commits.unwrap_or(1),
+                revset,
+                commits,
+            }
+        }
+
+        (Some(_), true, _) => {
+            eyre::bail!("--matching and --all cannot both be set")
+        }
+
+        (None, false, None) => Distance::NumCommits {
+            amount: 1,
+            move_by_branches,
+        },
+
+        (None, false, Some(amount)) => Distance::NumCommits {
+            amount,
+            move_by_branches,
+        },
+
+        (None, true, None) => Distance::AllTheWay { move_by_branches },
+
+        (None, true, Some(_)) => {
+            eyre::bail!("num_commits and --all cannot both be set")
+        }
+    };
+
     let head_oid = match references_snapshot.head_oid {
         Some(head_oid) => head_oid,
         None => {
@@ -396,11 +551,12 @@ pub fn traverse_commits(
                 &Redactor::Disabled,
             )?,
             &mut DifferentialRevisionDescriptor::new(&repo, &Redactor::Disabled)?,
+            &mut CommitMetadataDescriptor::new(&repo, &Redactor::Disabled)?,
             &mut CommitMessageDescriptor::new(&Redactor::Disabled)?,
         ],
         head_oid,
         command,
-        distance,
+        &distance,
         towards,
     )?;
     let current_oid = match current_oid {
@@ -415,7 +571,8 @@ pub fn traverse_commits(
         | Distance::NumCommits {
             amount: _,
             move_by_branches: false,
-        } => CheckoutTarget::Oid(current_oid),
+        }
+        | Distance::Matching { .. } => CheckoutTarget::Oid(current_oid),
 
         Distance::AllTheWay {
             move_by_branches: true,
@@ -544,6 +701,7 @@ pub fn switch(
                         &Redactor::Disabled,
                     )?,
                     &mut DifferentialRevisionDescriptor::new(&repo, &Redactor::Disabled)?,
+                    &mut CommitMetadataDescriptor::new(&repo, &Redactor::Disabled)?,
                     &mut CommitMessageDescriptor::new(&Redactor::Disabled)?,
                 ],
             )? {
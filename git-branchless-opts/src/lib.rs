@@ -15,6 +15,7 @@ use std::path::{Path, PathBuf};
 use std::str::FromStr;
 
 use clap::{Args, Command as ClapCommand, CommandFactory, Parser, ValueEnum};
+use itertools::Itertools;
 use lib::git::NonZeroOid;
 
 /// A revset expression. Can be a commit hash, branch name, or one of the
@@ -110,6 +111,42 @@ pub struct TraverseCommitsOptions {
     #[clap(action, short = 'b', long = "branch")]
     pub move_by_branches: bool,
 
+    /// Move the specified number of commits matching the given revset rather
+    /// than commits, skipping over any commits which don't match.
+    #[clap(
+        value_parser,
+        long = "matching",
+        conflicts_with_all(&["all_the_way", "move_by_branches"])
+    )]
+    pub matching: Option<Revset>,
+
+    /// Move to commits which contain unresolved merge conflict markers. Same
+    /// as `--matching 'conflicts()'`.
+    #[clap(
+        action,
+        long = "conflict",
+        conflicts_with_all(&["all_the_way", "move_by_branches", "matching"])
+    )]
+    pub conflict: bool,
+
+    /// Move to commits which failed the last run of `git test`. Same as
+    /// `--matching 'tests.failed()'`.
+    #[clap(
+        action,
+        long = "failing",
+        conflicts_with_all(&["all_the_way", "move_by_branches", "matching", "conflict"])
+    )]
+    pub failing: bool,
+
+    /// Move to commits which haven't been submitted for code review yet. Same
+    /// as `--matching 'draft() - submitted()'`.
+    #[clap(
+        action,
+        long = "unsubmitted",
+        conflicts_with_all(&["all_the_way", "move_by_branches", "matching", "conflict", "failing"])
+    )]
+    pub unsubmitted: bool,
+
     /// When encountering multiple next commits, choose the oldest.
     #[clap(action, short = 'o', long = "oldest")]
     pub oldest: bool,
@@ -190,6 +227,13 @@ pub enum HookSubcommand {
     /// Internal use.
     PreAutoGc,
     /// Internal use.
+    PreRebase {
+        /// The upstream and, if provided, the branch being rebased. See
+        /// githooks(5).
+        #[clap(value_parser)]
+        args: Vec<String>,
+    },
+    /// Internal use.
     PostApplypatch,
     /// Internal use.
     PostCheckout {
@@ -233,8 +277,25 @@ pub enum HookSubcommand {
         #[clap(value_parser)]
         commit_oid: String,
     },
+    /// A hook which this version of git-branchless doesn't know about, such
+    /// as one installed by a newer version. It's ignored rather than failing
+    /// the Git operation which invoked it.
+    #[clap(external_subcommand)]
+    Unknown(Vec<String>),
 }
 
+/// The version of the protocol between the installed hook scripts and the
+/// `hook` subcommand. This should be incremented whenever the hook scripts
+/// change in a way which the `hook` subcommand needs to know about.
+pub const HOOK_PROTOCOL_VERSION: u32 = 1;
+
+/// The environment variable which hook scripts set to the
+/// [`HOOK_PROTOCOL_VERSION`] they were installed with. An environment variable
+/// is used rather than a flag so that versions of git-branchless which predate
+/// it can still run the hook scripts. Hook scripts which don't set it are
+/// treated as protocol version 0.
+pub const HOOK_PROTOCOL_VERSION_ENV_VAR: &str = "BRANCHLESS_HOOK_PROTOCOL_VERSION";
+
 /// Internal use.
 #[derive(Debug, Parser)]
 pub struct HookArgs {
@@ -243,6 +304,165 @@ pub struct HookArgs {
     pub subcommand: HookSubcommand,
 }
 
+/// Inspect or maintain the caches which git-branchless keeps for this
+/// repository, such as the commit graph.
+#[derive(Debug, Parser)]
+pub struct CacheArgs {
+    /// The subcommand to run.
+    #[clap(subcommand)]
+    pub subcommand: CacheSubcommand,
+}
+
+/// `cache` subcommands.
+#[derive(Debug, Parser)]
+pub enum CacheSubcommand {
+    /// Show information about the caches.
+    Stats,
+
+    /// Delete the caches. They will be rebuilt when next needed.
+    Clear,
+
+    /// Delete the caches and rebuild them immediately.
+    Rebuild,
+}
+
+/// Tools for developing and debugging git-branchless itself.
+#[derive(Debug, Parser)]
+pub struct DebugArgs {
+    /// The subcommand to run.
+    #[clap(subcommand)]
+    pub subcommand: DebugSubcommand,
+}
+
+/// `debug` subcommands.
+#[derive(Debug, Parser)]
+pub enum DebugSubcommand {
+    /// Generate a synthetic repository in a temporary directory and time the
+    /// core operations on it, such as building the commit graph, replaying
+    /// the event log, and planning a restack. The repository is deleted
+    /// afterwards.
+    Benchmark {
+        /// The number of commits to create on the main branch.
+        #[clap(value_parser, long = "num-commits", default_value_t = 10000)]
+        num_commits: usize,
+
+        /// The number of stacks of draft commits to create.
+        #[clap(value_parser, long = "num-stacks", default_value_t = 100)]
+        num_stacks: usize,
+
+        /// The number of commits in each stack.
+        #[clap(value_parser, long = "stack-size", default_value_t = 5)]
+        stack_size: usize,
+    },
+
+    /// Print the commit graph which the smartlog would render, including
+    /// hidden commits and the edges from rewritten commits to their
+    /// replacements, in a format which can be visualized by other tools.
+    DumpGraph {
+        /// The commits to include. Defaults to the same commits as the
+        /// smartlog.
+        #[clap(value_parser)]
+        revset: Option<Revset>,
+
+        /// The format to print the graph in.
+        #[clap(value_enum, long = "format", default_value_t = DumpGraphFormat::Dot)]
+        format: DumpGraphFormat,
+
+        /// Options for resolving revset expressions.
+        #[clap(flatten)]
+        resolve_revset_options: ResolveRevsetOptions,
+    },
+}
+
+/// The format for `git branchless debug dump-graph`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, ValueEnum)]
+pub enum DumpGraphFormat {
+    /// Graphviz DOT, which can be rendered with `dot -Tsvg`.
+    Dot,
+
+    /// A Mermaid flowchart, which can be embedded in Markdown.
+    Mermaid,
+}
+
+/// Name stacks of commits. A named stack can be referred to by its name
+/// anywhere a revset is expected, such as `git sync my-feature`, in which case
+/// it stands for all the commits in the stack (see the `stack()` revset
+/// function).
+#[derive(Debug, Parser)]
+pub struct StackArgs {
+    /// The subcommand to run.
+    #[clap(subcommand)]
+    pub subcommand: StackSubcommand,
+}
+
+/// `stack` subcommands.
+#[derive(Debug, Parser)]
+pub enum StackSubcommand {
+    /// Name the stack containing the provided commit. The commit becomes the
+    /// tip of the stack, and is kept up to date as it's rewritten.
+    Create {
+        /// The name to give the stack.
+        #[clap(value_parser)]
+        name: String,
+
+        /// The tip of the stack.
+        #[clap(value_parser, default_value = "@")]
+        tip: Revset,
+
+        /// Options for resolving revset expressions.
+        #[clap(flatten)]
+        resolve_revset_options: ResolveRevsetOptions,
+    },
+
+    /// List the named stacks and their tips.
+    List,
+
+    /// Check out the tip of the stack with the given name.
+    Switch {
+        /// The name of the stack to switch to.
+        #[clap(value_parser)]
+        name: String,
+
+        /// Move any uncommitted changes onto the tip of the stack. If they
+        /// conflict with it, the working copy is left unchanged.
+        #[clap(action, long = "carry")]
+        carry: bool,
+    },
+
+    /// Forget the name of a stack. The commits in the stack are not affected.
+    Delete {
+        /// The name of the stack to forget.
+        #[clap(value_parser)]
+        name: String,
+    },
+}
+
+/// A shell for which to generate completions.
+#[derive(Clone, Copy, Debug, ValueEnum)]
+pub enum CompletionShell {
+    /// Bash.
+    Bash,
+
+    /// Zsh.
+    Zsh,
+
+    /// Fish.
+    Fish,
+}
+
+/// Generate shell completions.
+#[derive(Debug, Parser)]
+pub struct CompletionsArgs {
+    /// The shell to generate completions for.
+    #[clap(value_enum, required_unless_present("list_commits"))]
+    pub shell: Option<CompletionShell>,
+
+    /// Internal use. List the commits and branches which can be completed as
+    /// commit arguments, one per line.
+    #[clap(action, long = "list-commits", hide = true)]
+    pub list_commits: bool,
+}
+
 /// Initialize the branchless workflow for this repository.
 #[derive(Debug, Parser)]
 pub struct InitArgs {
@@ -254,8 +474,22 @@ pub struct InitArgs {
     ///
     /// If not set, it will be auto-detected. If it can't be auto-detected,
     /// then you will be prompted to enter a value for the main branch name.
+    ///
+    /// If passed without a value, re-detect the main branch (for example,
+    /// after the upstream repository renamed it) and update the main branch
+    /// setting, without reinstalling anything else.
     #[clap(value_parser, long = "main-branch", conflicts_with = "uninstall")]
-    pub main_branch_name: Option<String>,
+    pub main_branch_name: Option<Option<String>>,
+
+    /// Install a custom alias, given as `NAME=EXPANSION`, which invokes `git
+    /// branchless EXPANSION`. For example, `--alias "up=next --all"` makes
+    /// `git up` run `git branchless next --all`. Any additional arguments
+    /// passed to the alias are passed through.
+    ///
+    /// The alias is saved as `branchless.alias.NAME`, and is reinstalled
+    /// whenever `git branchless init` is run. May be passed multiple times.
+    #[clap(value_parser, long = "alias", conflicts_with = "uninstall")]
+    pub aliases: Vec<String>,
 }
 
 /// Install git-branchless's man-pages to the given path.
@@ -335,6 +569,20 @@ pub struct SmartlogArgs {
     #[clap(value_parser, long = "event-id")]
     pub event_id: Option<isize>,
 
+    /// Show the smartlog as it was at the given point in time, without
+    /// undoing anything. This may be an event ID (as with `--event-id`) or a
+    /// date or time, such as `yesterday`, `2 hours ago` or `@1700000000`.
+    /// Branches are shown as they were at that time, including ones which
+    /// have since been deleted.
+    #[clap(value_parser, long = "at", conflicts_with("event_id"))]
+    pub at: Option<String>,
+
+    /// Replay the event log from the database at this path, such as
+    /// `.git/branchless/db.sqlite3` in another clone of the repository,
+    /// instead of this repository's. The database is not modified.
+    #[clap(value_parser, long = "event-log")]
+    pub event_log: Option<PathBuf>,
+
     /// The commits to render. These commits, plus any related commits, will
     /// be rendered.
     #[clap(value_parser)]
@@ -345,9 +593,65 @@ pub struct SmartlogArgs {
     #[clap(long)]
     pub reverse: bool,
 
+    /// Group the draft commits by stack, with a header line for each stack
+    /// showing its branches, number of commits, and when it was last updated.
+    #[clap(action, long = "group-by-stack")]
+    pub group_by_stack: bool,
+
+    /// Show only the header line of each stack rather than its commits.
+    /// Implies `--group-by-stack`.
+    #[clap(action, long = "collapse")]
+    pub collapse: bool,
+
+    /// How to order stacks of commits which share a parent. The most recent
+    /// stack is shown last, nearest to `HEAD`.
+    #[clap(value_enum, long = "sort", default_value_t = SmartlogSort::Topo)]
+    pub sort: SmartlogSort,
+
+    /// Show at most this many draft commits. The least-recently-updated stacks
+    /// of commits beyond the limit are folded into a summary line. Defaults
+    /// to the value of `branchless.smartlog.limit`, if set.
+    #[clap(value_parser, long = "limit")]
+    pub limit: Option<usize>,
+
+    /// Show all commits, even if a limit is configured.
+    #[clap(action, long = "no-limit", conflicts_with("limit"))]
+    pub no_limit: bool,
+
+    /// Don't pipe the output into a pager, even if `stdout` is a terminal.
+    #[clap(action, long = "no-pager")]
+    pub no_pager: bool,
+
     /// Options for resolving revset expressions.
     #[clap(flatten)]
     pub resolve_revset_options: ResolveRevsetOptions,
+
+    /// Only show the stacks of draft commits which touch at least one of these
+    /// paths, relative to the current directory. The stack containing `HEAD`
+    /// is always shown. Defaults to the value of `branchless.smartlog.paths`,
+    /// if set.
+    #[clap(value_parser, last = true)]
+    pub paths: Vec<PathBuf>,
+
+    /// Show all stacks of draft commits, even if `branchless.smartlog.paths`
+    /// is configured.
+    #[clap(action, long = "all-paths", conflicts_with("paths"))]
+    pub all_paths: bool,
+}
+
+/// How to order stacks of commits which share a parent in the smartlog.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, ValueEnum)]
+pub enum SmartlogSort {
+    /// Order stacks by the commit time of their first commit.
+    #[default]
+    Topo,
+
+    /// Order stacks by the latest commit time of any of their commits.
+    Date,
+
+    /// Order stacks by when any of their commits was last created, rewritten,
+    /// hidden, or unhidden, according to the event log.
+    Activity,
 }
 
 /// The Git hosting provider to use, called a "forge".
@@ -405,6 +709,18 @@ pub struct SubmitArgs {
     /// created. (Still triggers a fetch.)
     #[clap(short = 'n', long = "dry-run")]
     pub dry_run: bool,
+
+    /// If the remote supports it, add a section to the description of each
+    /// code review listing the code reviews in its stack, and keep it up to
+    /// date as the stack is restacked or reordered.
+    #[clap(action, long = "stack-navigation")]
+    pub stack_navigation: bool,
+
+    /// Force-push branches even if they were updated on the remote since they
+    /// were last pushed, discarding the commits which aren't in the local
+    /// branches.
+    #[clap(action, long = "force")]
+    pub force: bool,
 }
 
 /// Run a command on each commit in a given set and aggregate the results.
@@ -418,6 +734,14 @@ pub struct TestArgs {
 /// FIXME: write man-page text
 #[derive(Debug, Parser)]
 pub enum Command {
+    /// Abort the operation which stopped at a merge conflict or was
+    /// interrupted, rolling back its changes.
+    ///
+    /// For example, use this to cancel a move whose merge conflict you chose
+    /// to resolve in the working copy, or which was interrupted while updating
+    /// branches.
+    Abort,
+
     /// Amend the current HEAD commit.
     Amend {
         /// Options for moving commits.
@@ -429,16 +753,126 @@ pub enum Command {
         /// formatting or refactoring changes.
         #[clap(long)]
         reparent: bool,
+
+        /// Amend the changes into the given ancestor of the current HEAD
+        /// commit instead, and restack its descendants (including HEAD)
+        /// in-memory.
+        #[clap(value_parser, long = "to", conflicts_with = "reparent")]
+        to: Option<Revset>,
+
+        /// Interactively select which changes to amend. The rest of the
+        /// changes are left in the working copy.
+        #[clap(action, short = 'p', long = "patch")]
+        patch: bool,
     },
 
     /// Gather information about recent operations to upload as part of a bug
     /// report.
     BugReport,
 
+    /// Inspect or maintain the caches which git-branchless keeps for this
+    /// repository, such as the commit graph.
+    Cache(CacheArgs),
+
+    /// Generate shell completions for `git branchless` and its aliases, such
+    /// as `git hide`.
+    ///
+    /// For example, for Bash, add this to your `.bashrc`: `eval "$(git
+    /// branchless completions bash)"`.
+    Completions(CompletionsArgs),
+
+    /// Continue the operation which stopped at a merge conflict or was
+    /// interrupted.
+    ///
+    /// For example, use this to finish a move whose merge conflict you chose
+    /// to resolve in the working copy (once it's resolved), or which was
+    /// interrupted while updating branches.
+    Continue,
+
+    /// Tools for developing and debugging git-branchless itself.
+    Debug(DebugArgs),
+
+    /// Show the cumulative diff of a stack of commits against its merge-base
+    /// with the main branch.
+    Diff {
+        /// The commits whose stack to diff. Unless `--per-commit` is passed,
+        /// they must have a single head.
+        #[clap(value_parser, default_value = "stack()")]
+        revset: Revset,
+
+        /// Options for resolving revset expressions.
+        #[clap(flatten)]
+        resolve_revset_options: ResolveRevsetOptions,
+
+        /// Show a summary of the changed files rather than the full patch.
+        #[clap(action, long = "stat")]
+        stat: bool,
+
+        /// Show the diff of each commit individually, rather than the
+        /// cumulative diff.
+        #[clap(action, long = "per-commit")]
+        per_commit: bool,
+    },
+
     /// Use the partial commit selector UI as a Git-compatible difftool; see
     /// git-difftool(1) for more information on Git difftools.
     Difftool(scm_record::scm_diff_editor::Opts),
 
+    /// Check out a commit in the middle of a stack to edit it, leaving its
+    /// descendants in place. While the commit is being edited, amending it
+    /// with `git amend` or `git commit --amend` restacks its descendants onto
+    /// the amended commit. Run `git next` to finish editing and move onto its
+    /// descendants.
+    Edit {
+        /// The commit to edit.
+        #[clap(value_parser)]
+        revset: Revset,
+
+        /// Options for resolving revset expressions.
+        #[clap(flatten)]
+        resolve_revset_options: ResolveRevsetOptions,
+
+        /// Edit the commit even if it or its descendants are public, which
+        /// other people may have access to.
+        #[clap(action, short = 'f', long = "force-rewrite", visible_alias = "fr")]
+        force_rewrite_public_commits: bool,
+    },
+
+    /// Edit the todo list of an interactive rebase in place, without opening
+    /// an editor. This is meant to be used as Git's sequence editor, such as
+    /// with `git -c sequence.editor='git branchless edit-todo
+    /// --autosquash-stack' rebase -i main` or `git rebase --edit-todo`.
+    ///
+    /// `exec`, `break`, `label`, and `reset` commands are kept in place, so
+    /// this can also be used on the todo list of an on-disk rebase started by
+    /// git-branchless.
+    EditTodo {
+        /// Move `fixup!`, `squash!`, and `amend!` commits after the commits in
+        /// the todo list which they target, and turn them into `fixup` or
+        /// `squash` commands, like `git rebase --autosquash`.
+        #[clap(action, long = "autosquash-stack")]
+        autosquash_stack: bool,
+
+        /// The path to the todo list, as passed by Git.
+        #[clap(value_parser)]
+        path: PathBuf,
+    },
+
+    /// Stop tracking commits entirely, as if branchless had never seen them.
+    ///
+    /// Unlike `git hide`, this doesn't hide the commits' descendants or
+    /// successors, and it can't be undone with `git undo`, since the events
+    /// recording the commits are removed from the event log.
+    Forget {
+        /// Zero or more commits to forget.
+        #[clap(value_parser)]
+        revsets: Vec<Revset>,
+
+        /// Options for resolving revset expressions.
+        #[clap(flatten)]
+        resolve_revset_options: ResolveRevsetOptions,
+    },
+
     /// Run internal garbage collection.
     Gc,
 
@@ -467,12 +901,61 @@ pub enum Command {
     #[clap(hide = true)]
     Hook(HookArgs),
 
+    /// Import history-tracking data from another version control system into
+    /// the event log, such as after migrating a repository to Git.
+    Import {
+        /// The path to a Mercurial repository which was converted to this
+        /// repository with hg-git. Its obsolescence markers are imported as
+        /// rewrites, and its phase roots as commit phases.
+        #[clap(value_parser, long = "from-hg", required_unless_present("from_notes"))]
+        from_hg: Option<PathBuf>,
+
+        /// Import the rewrites recorded in the `refs/notes/branchless` notes,
+        /// such as ones fetched from another clone of this repository which
+        /// has `branchless.notes.mirrorRewrites` set.
+        #[clap(action, long = "from-notes", conflicts_with("from_hg"))]
+        from_notes: bool,
+    },
+
     /// Initialize the branchless workflow for this repository.
     Init(InitArgs),
 
     /// Install git-branchless's man-pages to the given path.
     InstallManPages(InstallManPagesArgs),
 
+    /// Land the bottom of a stack onto the main branch.
+    ///
+    /// The given commits, along with their draft ancestors, are moved on top
+    /// of the main branch if necessary, and then the main branch is
+    /// fast-forwarded to them. The rest of the stack is restacked onto the new
+    /// main branch. This is carried out as a single operation, which can be
+    /// reverted with `git undo`.
+    Land {
+        /// The commits to land. All of their draft ancestors will be landed as
+        /// well.
+        #[clap(value_parser, default_value = "roots(stack())")]
+        revset: Revset,
+
+        /// Options for resolving revset expressions.
+        #[clap(flatten)]
+        resolve_revset_options: ResolveRevsetOptions,
+
+        /// Push the landed commits to the main branch of its remote before
+        /// updating the local main branch. If the push is rejected, such as
+        /// because the remote main branch has new commits, the local main
+        /// branch isn't updated.
+        #[clap(action, long = "push")]
+        push: bool,
+    },
+
+    /// List recent operations, such as moves and amends, along with a summary
+    /// of their effects on the repository.
+    LogOperations {
+        /// The maximum number of operations to list.
+        #[clap(value_parser, short = 'n', long = "limit", default_value_t = 20)]
+        limit: usize,
+    },
+
     /// Move a subtree of commits from one location to another.
     ///
     /// By default, `git move` tries to move the entire current stack if you
@@ -531,6 +1014,13 @@ pub enum Command {
         /// Only supported if the moved subtree has a single head.
         #[clap(action, short = 'I', long = "insert")]
         insert: bool,
+
+        /// Fold any `fixup!` commits among the moved commits into the commits
+        /// which they target, like `git rebase --autosquash`. `squash!` and
+        /// `amend!` commits are left in place, since their messages would be
+        /// lost.
+        #[clap(action, long = "autosquash", conflicts_with = "fixup")]
+        autosquash: bool,
     },
 
     /// Move to a later commit in the current stack.
@@ -540,6 +1030,57 @@ pub enum Command {
         traverse_commits_options: TraverseCommitsOptions,
     },
 
+    /// Show the rewrite history of a commit: all of the commits which it was
+    /// rewritten from or into, and the operations which rewrote them.
+    Obslog {
+        /// The commit whose history should be shown.
+        #[clap(value_parser, default_value = "@")]
+        revset: Revset,
+
+        /// Options for resolving revset expressions.
+        #[clap(flatten)]
+        resolve_revset_options: ResolveRevsetOptions,
+    },
+
+    /// Inspect the operation which is in progress, if any, such as a rebase
+    /// stopped at a merge conflict or an interrupted in-memory rebase.
+    Operation {
+        /// The subcommand to run.
+        #[clap(subcommand)]
+        subcommand: OperationSubcommand,
+    },
+
+    /// Show or set the phase of commits.
+    ///
+    /// A commit is public if it's reachable from the main branch, pushed to a
+    /// protected remote (see `branchless.core.protectedRemotes`), or an
+    /// ancestor of a commit explicitly recorded as public. Public commits are
+    /// never rewritten or hidden without `--force-rewrite`. A commit recorded
+    /// as secret, and its descendants, are never pushed by `git submit`. All
+    /// other commits are draft.
+    Phase {
+        /// The commits whose phase should be shown or set.
+        #[clap(value_parser, default_value = "@")]
+        revsets: Vec<Revset>,
+
+        /// Options for resolving revset expressions.
+        #[clap(flatten)]
+        resolve_revset_options: ResolveRevsetOptions,
+
+        /// Record the commits as public.
+        #[clap(action, long = "public", conflicts_with_all(&["draft", "secret"]))]
+        public: bool,
+
+        /// Record the commits as draft, removing any phase previously recorded
+        /// for them or their descendants.
+        #[clap(action, long = "draft", conflicts_with = "secret")]
+        draft: bool,
+
+        /// Record the commits as secret.
+        #[clap(action, long = "secret")]
+        secret: bool,
+    },
+
     /// Move to an earlier commit in the current stack.
     Prev {
         /// Options for traversing commits.
@@ -547,6 +1088,23 @@ pub enum Command {
         traverse_commits_options: TraverseCommitsOptions,
     },
 
+    /// Stop keeping old hidden commits alive, so that they can be deleted by
+    /// garbage collection.
+    Prune {
+        /// Only prune commits which were hidden at least this many days ago.
+        #[clap(value_parser, long = "older-than", default_value = "30")]
+        older_than_days: u64,
+
+        /// List the commits which would be pruned without pruning them.
+        #[clap(action, long = "dry-run")]
+        dry_run: bool,
+
+        /// Run `git gc` afterwards to delete the pruned commits from the
+        /// repository.
+        #[clap(action, long = "gc", conflicts_with("dry_run"))]
+        gc: bool,
+    },
+
     /// Query the commit graph using the "revset" language and print matching
     /// commits.
     ///
@@ -579,6 +1137,13 @@ pub enum Command {
         /// Options for moving commits.
         #[clap(flatten)]
         move_options: MoveOptions,
+
+        /// Fold any `fixup!` commits in the restacked stacks into the commits
+        /// which they target, like `git rebase --autosquash`. `squash!` and
+        /// `amend!` commits are left in place, since their messages would be
+        /// lost.
+        #[clap(action, long = "autosquash")]
+        autosquash: bool,
     },
 
     /// Create a commit by interactively selecting which changes to include.
@@ -620,6 +1185,85 @@ pub enum Command {
         /// use with `git rebase --autosquash`) targeting the supplied commit.
         #[clap(value_parser, long = "fixup", conflicts_with_all(&["messages", "discard"]))]
         commit_to_fixup: Option<Revset>,
+
+        /// A template to apply to the message of each commit, without opening
+        /// the editor. The placeholders `{message}`, `{summary}`, and `{body}`
+        /// are replaced with the commit's original message, its first line,
+        /// and the rest of the message, respectively.
+        ///
+        /// For example, to add a ticket number to every commit in the current
+        /// stack: `git reword --template '[ABC-123] {message}'`
+        #[clap(
+            value_parser,
+            long = "template",
+            conflicts_with_all(&["messages", "discard", "commit_to_fixup"])
+        )]
+        message_template: Option<String>,
+
+        /// Set the author of the commits, in the form `Name <email>`. If no
+        /// message is provided, then the original messages are kept.
+        #[clap(value_parser, long = "author")]
+        author: Option<String>,
+
+        /// Set the author date of the commits, as an RFC 3339 date
+        /// (`2023-01-02T03:04:05+01:00`), an RFC 2822 date, or in Git's
+        /// internal format (`@1672625045 +0100`). If no message is provided,
+        /// then the original messages are kept.
+        #[clap(value_parser, long = "date")]
+        date: Option<String>,
+
+        /// Add a trailer, in the form `Key: value`, to the message of each
+        /// commit, unless it's already present. The placeholders `{change_id}`
+        /// and `{author}` in the value are replaced with the commit's
+        /// Gerrit-style change ID and its author, respectively. If no message
+        /// is provided, then the original messages are kept.
+        #[clap(value_parser, long = "add-trailer")]
+        add_trailers: Vec<String>,
+
+        /// Remove the trailers with the given key from the message of each
+        /// commit. If no message is provided, then the original messages are
+        /// kept.
+        #[clap(value_parser, long = "remove-trailer")]
+        remove_trailers: Vec<String>,
+
+        /// Add the trailers configured for the given forge with
+        /// `branchless.trailers.<forge>`, which holds one `Key: value` trailer
+        /// per line, in the same way as `--add-trailer`. For `gerrit`, this
+        /// defaults to `Change-Id: {change_id}`.
+        #[clap(value_parser, long = "trailers")]
+        trailers_forge: Option<String>,
+    },
+
+    /// Rewrite the author and committer emails of many commits at once, such
+    /// as after an email address has changed. The commits are rewritten
+    /// in-memory in a single operation, which can be reverted with `git undo`.
+    RewriteMetadata {
+        /// The commits whose metadata should be rewritten.
+        #[clap(value_parser, default_value = "draft()")]
+        revsets: Vec<Revset>,
+
+        /// Options for resolving revset expressions.
+        #[clap(flatten)]
+        resolve_revset_options: ResolveRevsetOptions,
+
+        /// Replace an author or committer email with another, in the form
+        /// `old@example.com:new@example.com`. May be passed multiple times.
+        /// Emails are matched case-insensitively.
+        #[clap(value_parser, long = "map-author", required = true)]
+        author_mappings: Vec<String>,
+
+        /// Force rewriting public commits, even though other people may have
+        /// access to those commits.
+        #[clap(action, short = 'f', long = "force-rewrite", visible_alias = "fr")]
+        force_rewrite_public_commits: bool,
+    },
+
+    /// Serve the smartlog graph and the event log over a local HTTP server,
+    /// for use by GUI and editor integrations.
+    Serve {
+        /// The port to listen on. If 0, any available port is used.
+        #[clap(value_parser, long = "port", default_value_t = 0)]
+        port: u16,
     },
 
     /// `smartlog` command.
@@ -633,6 +1277,18 @@ pub enum Command {
         subcommand: SnapshotSubcommand,
     },
 
+    /// Name stacks of commits, so that the name can be used to refer to the
+    /// whole stack in other commands.
+    Stack(StackArgs),
+
+    /// Report statistics about how the repository has been worked on, such as
+    /// how often commits are amended, based on the event log.
+    Stats {
+        /// Only consider events from this many days ago onwards.
+        #[clap(value_parser, long = "days", default_value_t = 30)]
+        days: u64,
+    },
+
     /// Push commits to a remote.
     Submit(SubmitArgs),
 
@@ -656,6 +1312,12 @@ pub enum Command {
         )]
         pull: bool,
 
+        /// If the main branch was rewritten, such as after upstream
+        /// force-pushed it, move the stacks which were based on its old
+        /// commits onto the new main branch.
+        #[clap(action, long = "onto-new-main")]
+        onto_new_main: bool,
+
         /// Options for moving commits.
         #[clap(flatten)]
         move_options: MoveOptions,
@@ -670,19 +1332,47 @@ pub enum Command {
         resolve_revset_options: ResolveRevsetOptions,
     },
 
+    /// Share which commits are visible with other clones of this repository,
+    /// such as on another machine, by way of a remote.
+    SyncState {
+        /// The subcommand to run.
+        #[clap(subcommand)]
+        subcommand: SyncStateSubcommand,
+    },
+
     /// Run a command on each commit in a given set and aggregate the results.
     Test(TestArgs),
 
     /// Browse or return to a previous state of the repository.
     Undo {
         /// Interactively browse through previous states of the repository
-        /// before selecting one to return to.
+        /// before selecting one to return to, then choose which of the
+        /// resulting actions to apply.
         #[clap(action, short = 'i', long = "interactive")]
         interactive: bool,
 
+        /// Undo the given operation and all operations after it. The operation
+        /// can be given as its ID from `git branchless log-operations`, or as
+        /// text to match against the descriptions of recent operations (such
+        /// as `move`). Otherwise, it's parsed as a point in time, such as
+        /// `10 minutes ago` or `@1700000000`, and every operation after that
+        /// time is undone.
+        #[clap(value_parser, long = "to", conflicts_with("interactive"))]
+        to: Option<String>,
+
         /// Skip confirmation and apply changes immediately.
         #[clap(action, short = 'y', long = "yes")]
         yes: bool,
+
+        /// If there are uncommitted changes in the working copy, discard them
+        /// when checking out the previous commit. (Use with caution!)
+        #[clap(action, short = 'f', long = "force", conflicts_with("merge"))]
+        force: bool,
+
+        /// If there are uncommitted changes in the working copy, attempt to
+        /// merge them into the previous commit when checking it out.
+        #[clap(action, short = 'm', long = "merge")]
+        merge: bool,
     },
 
     /// Unhide previously-hidden commits from the smartlog.
@@ -700,6 +1390,39 @@ pub enum Command {
         recursive: bool,
     },
 
+    /// Check the event log for inconsistencies with the repository, such as
+    /// visible commits which were deleted by `git gc --prune=now`, branches
+    /// which no longer exist, and cycles of rewrites.
+    ///
+    /// This only reports the inconsistencies. Run `git branchless repair
+    /// --no-dry-run` to resolve them.
+    Verify,
+
+    /// Create a linked worktree checked out at the tip of a stack.
+    ///
+    /// This makes it possible to work on several stacks in parallel. The
+    /// worktree is created next to the main working copy unless `--path` is
+    /// given.
+    Worktree {
+        /// The stack to check out, or a commit in it.
+        #[clap(value_parser, default_value = "stack()")]
+        revset: Revset,
+
+        /// Options for resolving revset expressions.
+        #[clap(flatten)]
+        resolve_revset_options: ResolveRevsetOptions,
+
+        /// The path to create the worktree at, or with `--remove`, the path of
+        /// the worktree to remove.
+        #[clap(value_parser, long = "path")]
+        path: Option<PathBuf>,
+
+        /// Remove the linked worktrees which are checked out to commits in the
+        /// stack, rather than creating one.
+        #[clap(action, long = "remove")]
+        remove: bool,
+    },
+
     /// Wrap a Git command inside a branchless transaction.
     Wrap {
         /// The `git` executable to invoke.
@@ -716,7 +1439,8 @@ pub enum Command {
 #[derive(Clone, Debug, ValueEnum)]
 pub enum ColorSetting {
     /// Automatically determine whether to display colors from the terminal and environment variables.
-    /// This is the default behavior.
+    /// Colors are not displayed if the NO_COLOR environment variable is set. This is the default
+    /// behavior.
     Auto,
     /// Always display terminal colors.
     Always,
@@ -724,6 +1448,19 @@ pub enum ColorSetting {
     Never,
 }
 
+/// The format in which to report the progress of long-running operations.
+#[derive(Clone, Debug, ValueEnum)]
+pub enum OutputFormat {
+    /// Only report progress with human-readable output. This is the default
+    /// behavior.
+    Human,
+    /// Additionally write one JSON object per line for each step of a
+    /// long-running operation, such as rebasing a commit or testing a commit.
+    /// Each object has an `event` field, which is one of `planned`, `started`,
+    /// `finished`, or `conflicted`.
+    JsonLines,
+}
+
 /// How to execute tests.
 #[derive(Clone, Copy, Debug, ValueEnum)]
 pub enum TestExecutionStrategy {
@@ -765,6 +1502,25 @@ pub struct GlobalArgs {
     /// Flag to force enable or disable terminal colors.
     #[clap(value_parser, long = "color", value_enum, global = true)]
     pub color: Option<ColorSetting>,
+
+    /// The format in which to report the progress of long-running operations,
+    /// such as `sync`, `test`, and `restack`, for use by editor integrations.
+    /// With `json-lines`, the progress is written to the file set with
+    /// `--output-file`, so that it isn't mixed up with the regular output.
+    #[clap(value_parser, long = "output", value_enum, global = true)]
+    pub output: Option<OutputFormat>,
+
+    /// Write the progress selected by `--output` to this file. Required with
+    /// `--output json-lines`. For example, pass `/dev/fd/3` to write to a
+    /// file descriptor opened by the calling process.
+    #[clap(
+        value_parser,
+        long = "output-file",
+        global = true,
+        requires = "output",
+        required_if_eq("output", "json-lines")
+    )]
+    pub output_file: Option<PathBuf>,
 }
 
 /// Branchless workflow for Git.
@@ -791,12 +1547,53 @@ pub enum SnapshotSubcommand {
     /// On success, prints the snapshot commit hash to stdout.
     Create,
 
+    /// List the snapshots recorded in the event log, from newest to oldest,
+    /// along with the operation which took each of them.
+    List,
+
     /// Restore the working copy contents from the provided snapshot.
     Restore {
         /// The commit hash for the snapshot.
         #[clap(value_parser)]
         snapshot_oid: NonZeroOid,
     },
+
+    /// Show the staged and unstaged changes in the provided snapshot, relative
+    /// to the commit which was checked out when it was taken.
+    Show {
+        /// The commit hash for the snapshot.
+        #[clap(value_parser)]
+        snapshot_oid: NonZeroOid,
+    },
+}
+
+/// `operation` subcommands.
+#[derive(Debug, Parser)]
+pub enum OperationSubcommand {
+    /// Show the operation which is in progress and how far it got. Use `git
+    /// branchless continue` or `git branchless abort` to finish it or roll it
+    /// back.
+    Status,
+}
+
+/// `sync-state` subcommands.
+#[derive(Debug, Parser)]
+pub enum SyncStateSubcommand {
+    /// Push the commits in progress and the record of which commits are
+    /// visible to the remote, first merging in any state already pushed there.
+    Push {
+        /// The remote to push to. Defaults to `branchless.syncState.remote`.
+        #[clap(value_parser)]
+        remote: Option<String>,
+    },
+
+    /// Pull the commits in progress and the record of which commits are
+    /// visible from the remote, so that they're reflected in the smartlog.
+    Pull {
+        /// The remote to pull from. Defaults to `branchless.syncState.remote`.
+        #[clap(value_parser)]
+        remote: Option<String>,
+    },
 }
 
 /// `test` subcommands.
@@ -954,6 +1751,93 @@ pub fn write_man_pages(man_dir: &Path) -> std::io::Result<()> {
     Ok(())
 }
 
+/// The Git aliases installed by `git branchless init` which accept commits as
+/// arguments, and so should complete commit hashes and branch names.
+const COMMIT_ARGUMENT_ALIASES: &[&str] = &[
+    "hide", "move", "obslog", "query", "restack", "reword", "sl", "smartlog", "submit", "sw",
+    "sync", "test", "unhide",
+];
+
+/// Write the completion script for `git-branchless` and its subcommands to
+/// `out`. The script also hooks into the completions shipped with Git, so that
+/// `git branchless <TAB>` and aliases like `git hide <TAB>` are completed.
+pub fn write_completions(
+    shell: CompletionShell,
+    out: &mut dyn std::io::Write,
+) -> std::io::Result<()> {
+    // As with the man-pages, explicitly set the name of the command.
+    let mut app = Opts::command().name("git-branchless");
+    let subcommand_names = app
+        .get_subcommands()
+        .filter(|subcommand| !subcommand.is_hide_set())
+        .map(|subcommand| subcommand.get_name().to_string())
+        .collect_vec();
+    let list_commits_command = "git branchless completions --list-commits 2>/dev/null";
+
+    match shell {
+        CompletionShell::Bash => {
+            clap_complete::generate(clap_complete::Shell::Bash, &mut app, "git-branchless", out);
+            writeln!(out)?;
+            writeln!(out, "__git_branchless_complete_commits() {{")?;
+            writeln!(out, "    __gitcomp_nl \"$({list_commits_command})\"")?;
+            writeln!(out, "}}")?;
+            writeln!(out, "_git_branchless() {{")?;
+            writeln!(
+                out,
+                "    local subcommands=\"{}\"",
+                subcommand_names.join(" ")
+            )?;
+            writeln!(
+                out,
+                "    local subcommand=\"$(__git_find_on_cmdline \"$subcommands\")\""
+            )?;
+            writeln!(out, "    if [ -z \"$subcommand\" ]; then")?;
+            writeln!(out, "        __gitcomp \"$subcommands\"")?;
+            writeln!(out, "    else")?;
+            writeln!(out, "        __git_branchless_complete_commits")?;
+            writeln!(out, "    fi")?;
+            writeln!(out, "}}")?;
+            for alias in COMMIT_ARGUMENT_ALIASES {
+                writeln!(
+                    out,
+                    "_git_{alias}() {{ __git_branchless_complete_commits; }}"
+                )?;
+            }
+        }
+
+        CompletionShell::Zsh => {
+            clap_complete::generate(clap_complete::Shell::Zsh, &mut app, "git-branchless", out);
+            writeln!(out)?;
+            writeln!(out, "__git_branchless_commits() {{")?;
+            writeln!(out, "    local -a commits")?;
+            writeln!(out, "    commits=(${{(f)\"$({list_commits_command})\"}})")?;
+            writeln!(out, "    compadd -- $commits")?;
+            writeln!(out, "}}")?;
+            for alias in COMMIT_ARGUMENT_ALIASES {
+                writeln!(out, "_git-{alias}() {{ __git_branchless_commits }}")?;
+            }
+        }
+
+        CompletionShell::Fish => {
+            clap_complete::generate(clap_complete::Shell::Fish, &mut app, "git-branchless", out);
+            writeln!(out)?;
+            writeln!(
+                out,
+                "complete -c git -n '__fish_seen_subcommand_from branchless; and not __fish_seen_subcommand_from {}' -f -a '{}'",
+                subcommand_names.join(" "),
+                subcommand_names.join(" ")
+            )?;
+            for alias in COMMIT_ARGUMENT_ALIASES {
+                writeln!(
+                    out,
+                    "complete -c git -n '__fish_seen_subcommand_from {alias}' -f -a '({list_commits_command})'"
+                )?;
+            }
+        }
+    }
+    Ok(())
+}
+
 fn generate_man_page(man1_dir: &Path, name: &str, command: &ClapCommand) -> std::io::Result<()> {
     let rendered_man_page = {
         let mut buffer = Vec::new();
@@ -1035,6 +1919,32 @@ pub fn rewrite_args(args: Vec<OsString>) -> Vec<OsString> {
         other => other.to_vec(),
     };
 
+    // Allow `git branchless wrap -- <command>`. Otherwise, the wrapped command
+    // would be parsed as a positional argument rather than as an external
+    // subcommand.
+    let args = match args.as_slice() {
+        [first, subcommand, rest @ ..] if subcommand == "wrap" => {
+            let mut new_args = vec![first.clone(), subcommand.clone()];
+            let mut rest = rest.iter();
+            while let Some(arg) = rest.next() {
+                if arg == "--" {
+                    break;
+                }
+                new_args.push(arg.clone());
+                if arg == "--git-executable" {
+                    new_args.extend(rest.next().cloned());
+                } else if !arg.to_string_lossy().starts_with("--git-executable=") {
+                    // This is the start of the wrapped command, so any `--`
+                    // afterwards belongs to it.
+                    break;
+                }
+            }
+            new_args.extend(rest.cloned());
+            new_args
+        }
+        other => other.to_vec(),
+    };
+
     args
 }
 
@@ -1128,5 +2038,43 @@ mod tests {
                 OsString::from("abc123"),
             ]
         );
+
+        assert_eq!(
+            rewrite_args(vec![
+                OsString::from("git-branchless"),
+                OsString::from("wrap"),
+                OsString::from("--git-executable"),
+                OsString::from("git"),
+                OsString::from("--"),
+                OsString::from("checkout"),
+                OsString::from("--"),
+                OsString::from("foo"),
+            ]),
+            vec![
+                OsString::from("git-branchless"),
+                OsString::from("wrap"),
+                OsString::from("--git-executable"),
+                OsString::from("git"),
+                OsString::from("checkout"),
+                OsString::from("--"),
+                OsString::from("foo"),
+            ]
+        );
+        assert_eq!(
+            rewrite_args(vec![
+                OsString::from("git-branchless"),
+                OsString::from("wrap"),
+                OsString::from("checkout"),
+                OsString::from("--"),
+                OsString::from("foo"),
+            ]),
+            vec![
+                OsString::from("git-branchless"),
+                OsString::from("wrap"),
+                OsString::from("checkout"),
+                OsString::from("--"),
+                OsString::from("foo"),
+            ]
+        );
     }
 }
@@ -87,7 +87,7 @@ fn test_query_eval_error() -> eyre::Result<()> {
             },
         )?;
         insta::assert_snapshot!(stderr, @r###"
-        Evaluation error for expression 'foo()': no function with the name 'foo' could be found; these functions are available: all, ancestors, ancestors.nth, author.date, author.email, author.name, branches, children, committer.date, committer.email, committer.name, current, descendants, difference, draft, exactly, heads, intersection, main, merges, message, none, not, only, parents, parents.nth, paths.changed, public, range, roots, siblings, stack, tests.failed, tests.fixable, tests.passed, union
+        Evaluation error for expression 'foo()': no function with the name 'foo' could be found; these functions are available: all, ancestors, ancestors.nth, author.date, author.email, author.name, branches, children, committer.date, committer.email, committer.name, conflicts, current, descendants, difference, draft, exactly, heads, intersection, main, merges, message, none, not, only, parents, parents.nth, paths.changed, public, range, roots, siblings, stack, submitted, tests.failed, tests.fixable, tests.passed, union
         "###);
         insta::assert_snapshot!(stdout, @"");
     }
@@ -208,3 +208,33 @@ fn test_query_hidden_commits() -> eyre::Result<()> {
 
     Ok(())
 }
+
+#[test]
+fn test_query_main_branches() -> eyre::Result<()> {
+    let git = make_git()?;
+    git.init_repo()?;
+
+    git.commit_file("test1", 1)?;
+    git.run(&["checkout", "-b", "release/1.0"])?;
+    git.commit_file("test2", 2)?;
+    git.run(&["checkout", "master"])?;
+
+    {
+        let (stdout, _stderr) = git.branchless("query", &["draft()"])?;
+        insta::assert_snapshot!(stdout, @r###"
+        96d1c37 create test2.txt
+        "###);
+    }
+
+    git.run(&["config", "branchless.core.mainBranches", "release/*"])?;
+    {
+        let (stdout, _stderr) = git.branchless("query", &["draft()"])?;
+        insta::assert_snapshot!(stdout, @"");
+    }
+    {
+        let (stdout, _stderr) = git.branchless("query", &["public()"])?;
+        assert!(stdout.contains("96d1c37 create test2.txt"));
+    }
+
+    Ok(())
+}
@@ -16,11 +16,11 @@ use std::time::SystemTime;
 
 use git_branchless_invoke::CommandContext;
 use git_branchless_opts::RecordArgs;
-use git_branchless_reword::edit_message;
 use itertools::Itertools;
 use lib::core::check_out::{check_out_commit, CheckOutCommitOptions, CheckoutTarget};
 use lib::core::config::{get_commit_template, get_restack_preserve_timestamps};
 use lib::core::dag::{CommitSet, Dag};
+use lib::core::editor::edit_message;
 use lib::core::effects::{Effects, OperationType};
 use lib::core::eventlog::{EventLogDb, EventReplayer, EventTransactionId};
 use lib::core::formatting::Pluralize;
@@ -255,27 +255,59 @@ fn record_interactive(
     event_tx_id: EventTransactionId,
     messages: Vec<String>,
 ) -> EyreExitOr<()> {
+    let message = try_exit_code!(stage_changes_interactive(
+        effects,
+        git_run_info,
+        repo,
+        snapshot,
+        event_tx_id,
+        Some(messages.iter().join("\n\n")),
+    )?)
+    .unwrap_or_default();
+
+    let args = {
+        let mut args = vec!["commit"];
+        if !message.is_empty() {
+            args.extend(["--message", &message]);
+        }
+        args
+    };
+    git_run_info.run_direct_no_wrapping(Some(event_tx_id), &args)
+}
+
+/// Interactively select which of the unstaged changes in the working copy
+/// snapshot to stage, using the same UI as `git record --interactive`, and
+/// write the selected changes to the index. The rest of the changes are left
+/// in the working copy.
+///
+/// If `message` is provided, then the user can also edit it as the commit
+/// message for the selected changes, and the edited message is returned.
+#[instrument]
+pub fn stage_changes_interactive(
+    effects: &Effects,
+    git_run_info: &GitRunInfo,
+    repo: &Repo,
+    snapshot: &WorkingCopySnapshot,
+    event_tx_id: EventTransactionId,
+    message: Option<String>,
+) -> EyreExitOr<Option<String>> {
     let old_tree = snapshot.commit_stage0.get_tree()?;
     let new_tree = snapshot.commit_unstaged.get_tree()?;
     let files = {
         let (effects, _progress) = effects.start_operation(OperationType::CalculateDiff);
-        let diff = repo.get_diff_between_trees(
+        let mut diff = repo.get_diff_between_trees(
             &effects,
             Some(&old_tree),
             &new_tree,
             // We manually add context to the git-record output, so suppress the context lines here.
             0,
         )?;
+        diff.find_renames_and_copies()?;
         process_diff_for_record(repo, &diff)?
     };
     let record_state = RecordState {
         is_read_only: false,
-        commits: vec![
-            Commit {
-                message: Some(messages.iter().join("\n\n")),
-            },
-            Commit { message: None },
-        ],
+        commits: vec![Commit { message }, Commit { message: None }],
         files,
     };
 
@@ -337,11 +369,11 @@ fn record_interactive(
             return Ok(Err(ExitCode(1)));
         }
     };
-    let message = commits[0].message.clone().unwrap_or_default();
+    let message = commits[0].message.clone();
 
-    let update_index_script: Vec<UpdateIndexCommand> = result
+    let update_index_script: Vec<Vec<UpdateIndexCommand>> = result
         .into_iter()
-        .map(|file| -> eyre::Result<UpdateIndexCommand> {
+        .map(|file| -> eyre::Result<Vec<UpdateIndexCommand>> {
             let mode = {
                 let default_mode = FileMode::Blob;
                 match file.get_file_mode() {
@@ -393,9 +425,24 @@ fn record_interactive(
                     oid,
                 },
             };
-            Ok(command)
+
+            // If a renamed file was selected, then it must also be removed
+            // from its old path. (The old path of a copied file still exists
+            // in the new tree, so it's left alone.)
+            let mut commands = vec![command];
+            if let (Some(UpdateIndexCommand::Update { .. }), Some(old_path)) =
+                (commands.first(), &file.old_path)
+            {
+                if new_tree.get_oid_for_path(old_path)?.is_none() {
+                    commands.push(UpdateIndexCommand::Delete {
+                        path: old_path.clone().into_owned(),
+                    });
+                }
+            }
+            Ok(commands)
         })
         .try_collect()?;
+    let update_index_script = update_index_script.into_iter().flatten().collect_vec();
     let index = repo.get_index()?;
     update_index(
         git_run_info,
@@ -404,15 +451,7 @@ fn record_interactive(
         event_tx_id,
         &update_index_script,
     )?;
-
-    let args = {
-        let mut args = vec!["commit"];
-        if !message.is_empty() {
-            args.extend(["--message", &message]);
-        }
-        args
-    };
-    git_run_info.run_direct_no_wrapping(Some(event_tx_id), &args)
+    Ok(Ok(message))
 }
 
 #[instrument]
@@ -507,6 +546,14 @@ fn insert_before_siblings(
             return Ok(Err(ExitCode(1)));
         }
 
+        Err(
+            err @ (BuildRebasePlanError::MoveProtectedCommits { .. }
+            | BuildRebasePlanError::MoveTaggedCommits { .. }),
+        ) => {
+            err.describe(effects, &repo, &dag)?;
+            return Ok(Err(ExitCode(1)));
+        }
+
         Err(err @ BuildRebasePlanError::MoveIllegalCommits { .. }) => {
             err.describe(effects, &repo, &dag)?;
             return Ok(Err(ExitCode(1)));
@@ -741,3 +741,56 @@ fn test_record_interactive_commit_message_template() -> eyre::Result<()> {
 
     Ok(())
 }
+
+#[test]
+#[cfg(unix)]
+fn test_record_runs_commit_message_hooks() -> eyre::Result<()> {
+    use std::{fs, os::unix::prelude::PermissionsExt};
+
+    let git = make_git()?;
+
+    if !git.supports_reference_transactions()? {
+        return Ok(());
+    }
+    git.init_repo()?;
+
+    let hooks_dir = git.repo_path.join(".git").join("hooks");
+    let write_hook = |name: &str, contents: &str| -> eyre::Result<()> {
+        let path = hooks_dir.join(name);
+        fs::write(&path, format!("#!/bin/sh\n{contents}\n"))?;
+        fs::set_permissions(&path, fs::Permissions::from_mode(0o755))?;
+        Ok(())
+    };
+    write_hook(
+        "prepare-commit-msg",
+        r#"test "$2" = message && printf '[ABC-123] %s' "$(cat "$1")" > "$1""#,
+    )?;
+    write_hook(
+        "commit-msg",
+        r#"grep -q 'forbidden' "$1" && exit 1; printf '\nHook-Ran: yes\n' >> "$1""#,
+    )?;
+
+    git.commit_file("test1", 1)?;
+    git.write_file_txt("test1", "contents1\n")?;
+    {
+        git.branchless("record", &["-m", "foo"])?;
+        let (stdout, _stderr) = git.run(&["log", "-1", "--format=%B"])?;
+        assert_eq!(stdout, "[ABC-123] foo\n\nHook-Ran: yes\n\n");
+    }
+
+    git.write_file_txt("test1", "contents2\n")?;
+    {
+        git.branchless_with_options(
+            "record",
+            &["-m", "forbidden"],
+            &GitRunOptions {
+                expected_exit_code: 1,
+                ..Default::default()
+            },
+        )?;
+        let (stdout, _stderr) = git.run(&["log", "-1", "--format=%s"])?;
+        assert_eq!(stdout, "[ABC-123] foo\n");
+    }
+
+    Ok(())
+}
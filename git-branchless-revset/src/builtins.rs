@@ -7,11 +7,11 @@ use lib::core::repo_ext::RepoExt;
 use lib::core::rewrite::find_rewrite_target;
 use lib::git::{
     get_latest_test_command_path, get_test_tree_dir, CategorizedReferenceName, Commit,
-    MaybeZeroOid, Repo, SerializedNonZeroOid, SerializedTestResult, TEST_ABORT_EXIT_CODE,
-    TEST_INDETERMINATE_EXIT_CODE, TEST_SUCCESS_EXIT_CODE,
+    MaybeZeroOid, NonZeroOid, Repo, SerializedNonZeroOid, SerializedTestResult,
+    TEST_ABORT_EXIT_CODE, TEST_INDETERMINATE_EXIT_CODE, TEST_SUCCESS_EXIT_CODE,
 };
 use std::borrow::Cow;
-use std::collections::HashMap;
+use std::collections::{HashMap, HashSet};
 use std::convert::TryFrom;
 use tracing::{instrument, warn};
 
@@ -63,6 +63,8 @@ lazy_static! {
             ("exactly", &fn_exactly),
             ("current", &fn_current),
             ("merges", &fn_merges),
+            ("conflicts", &fn_conflicts),
+            ("submitted", &fn_submitted),
             ("tests.passed", &fn_tests_passed),
             ("tests.failed", &fn_tests_failed),
             ("tests.fixable", &fn_tests_fixable),
@@ -211,6 +213,7 @@ fn fn_branches(ctx: &mut Context, name: &str, args: &[Expr]) -> EvalResult {
                         }
                         // we only care about local branches
                         CategorizedReferenceName::RemoteBranch { .. }
+                        | CategorizedReferenceName::Tag { .. }
                         | CategorizedReferenceName::OtherRef { .. } => None,
                     },
                 )
@@ -290,6 +293,13 @@ fn fn_draft(ctx: &mut Context, name: &str, args: &[Expr]) -> EvalResult {
 #[instrument]
 fn fn_stack(ctx: &mut Context, name: &str, args: &[Expr]) -> EvalResult {
     let arg = eval0_or_1(ctx, name, args)?.unwrap_or_else(|| ctx.dag.head_commit.clone());
+    query_stack(ctx, arg)
+}
+
+/// Get the draft commits in the stacks containing the given commits. This is
+/// also used to resolve named stacks (see [`lib::core::stacks`]).
+#[instrument]
+pub(super) fn query_stack(ctx: &mut Context, arg: CommitSet) -> EvalResult {
     let draft_commits = ctx
         .dag
         .query_draft_commits()
@@ -580,6 +590,86 @@ fn fn_merges(ctx: &mut Context, name: &str, args: &[Expr]) -> EvalResult {
     )
 }
 
+/// Whether the given file contents contain a conflict region, delimited by
+/// `<<<<<<<` and `>>>>>>>` lines.
+fn has_conflict_markers(contents: &[u8]) -> bool {
+    let is_marker_line = |line: &[u8], marker: &[u8]| {
+        line == marker || (line.starts_with(marker) && line.get(marker.len()) == Some(&b' '))
+    };
+    let mut in_conflict = false;
+    for line in contents.lines() {
+        if is_marker_line(line, b"<<<<<<<") {
+            in_conflict = true;
+        } else if in_conflict && is_marker_line(line, b">>>>>>>") {
+            return true;
+        }
+    }
+    false
+}
+
+#[instrument]
+fn fn_conflicts(ctx: &mut Context, name: &str, args: &[Expr]) -> EvalResult {
+    eval0(ctx, name, args)?;
+    // Use a "pattern matcher" that examines the files changed by each commit
+    // for conflict markers, such as when a conflicted merge was committed
+    // before being resolved.
+    make_pattern_matcher(
+        ctx,
+        name,
+        args,
+        Box::new(move |repo: &Repo, commit: &Commit| {
+            let touched_paths = repo
+                .get_paths_touched_by_commit(commit)
+                .map_err(PatternError::Repo)?;
+            let tree = commit.get_tree().map_err(PatternError::Repo)?;
+            for path in touched_paths {
+                let blob_oid = match tree.get_oid_for_path(&path).map_err(PatternError::Repo)? {
+                    Some(MaybeZeroOid::NonZero(blob_oid)) => blob_oid,
+                    Some(MaybeZeroOid::Zero) | None => continue,
+                };
+                if let Some(blob) = repo.find_blob(blob_oid).map_err(PatternError::Repo)? {
+                    if has_conflict_markers(blob.get_content()) {
+                        return Ok(true);
+                    }
+                }
+            }
+            Ok(false)
+        }),
+    )
+}
+
+#[instrument]
+fn fn_submitted(ctx: &mut Context, name: &str, args: &[Expr]) -> EvalResult {
+    eval0(ctx, name, args)?;
+    // A commit is considered to be submitted for code review if a local branch
+    // pointing to it was pushed (has an upstream branch), or if it has a
+    // Phabricator revision.
+    let pushed_branch_oids: HashSet<NonZeroOid> = {
+        let mut result = HashSet::new();
+        for branch in ctx.repo.get_all_local_branches()? {
+            if branch.get_upstream_branch()?.is_some() {
+                result.extend(branch.get_oid()?);
+            }
+        }
+        result
+    };
+    make_pattern_matcher(
+        ctx,
+        name,
+        args,
+        Box::new(move |_repo: &Repo, commit: &Commit| {
+            if pushed_branch_oids.contains(&commit.get_oid()) {
+                return Ok(true);
+            }
+            let message = commit.get_message_raw();
+            let result = message
+                .lines()
+                .any(|line| line.starts_with(b"Differential Revision:"));
+            Ok(result)
+        }),
+    )
+}
+
 fn read_all_test_results(repo: &Repo, commit: &Commit) -> Option<Vec<SerializedTestResult>> {
     let commit_test_dir = get_test_tree_dir(repo, commit).ok()?;
     let mut all_results = Vec::new();
@@ -10,10 +10,12 @@ use thiserror::Error;
 
 use lib::core::dag::{CommitSet, Dag};
 use lib::core::formatting::Pluralize;
-use lib::git::{ConfigRead, Repo, RepoError, ResolvedReferenceInfo};
+use lib::core::nicknames::NicknameDb;
+use lib::core::stacks::StackDb;
+use lib::git::{ConfigRead, NonZeroOid, Repo, RepoError, ResolvedReferenceInfo};
 use tracing::instrument;
 
-use super::builtins::FUNCTIONS;
+use super::builtins::{query_stack, FUNCTIONS};
 use super::parser::{parse, ParseError};
 use super::pattern::{Pattern, PatternError};
 use super::Expr;
@@ -135,6 +137,26 @@ fn eval_inner(ctx: &mut Context, expr: &Expr) -> EvalResult {
     }
 }
 
+/// Look up the commit with the given nickname (see
+/// [`lib::core::nicknames`]).
+#[instrument]
+fn lookup_nickname(ctx: &Context, name: &str) -> Result<Option<NonZeroOid>, EvalError> {
+    let conn = ctx.repo.get_db_conn()?;
+    let nickname_db = NicknameDb::new(&conn).map_err(EvalError::OtherError)?;
+    nickname_db
+        .get_commit_oid(name)
+        .map_err(EvalError::OtherError)
+}
+
+/// Look up the tip of the stack with the given name (see
+/// [`lib::core::stacks`]).
+#[instrument]
+fn lookup_stack_tip(ctx: &Context, name: &str) -> Result<Option<NonZeroOid>, EvalError> {
+    let conn = ctx.repo.get_db_conn()?;
+    let stack_db = StackDb::new(&conn).map_err(EvalError::OtherError)?;
+    stack_db.get_stack_tip(name).map_err(EvalError::OtherError)
+}
+
 #[instrument]
 pub(super) fn eval_name(ctx: &mut Context, name: &str) -> EvalResult {
     if name == "." || name == "@" {
@@ -157,11 +179,23 @@ pub(super) fn eval_name(ctx: &mut Context, name: &str) -> EvalResult {
             let commit_set: CommitSet = commit.get_oid().into();
             commit_set
         }
-        Ok(None) | Err(_) => {
-            return Err(EvalError::UnboundName {
-                name: name.to_owned(),
-            })
-        }
+        Ok(None) | Err(_) => match lookup_nickname(ctx, name)? {
+            Some(commit_oid) => commit_oid.into(),
+            None => match lookup_stack_tip(ctx, name)? {
+                Some(tip_oid) => {
+                    let tip: CommitSet = tip_oid.into();
+                    ctx.dag
+                        .sync_from_oids(ctx.effects, ctx.repo, CommitSet::empty(), tip.clone())
+                        .map_err(EvalError::OtherError)?;
+                    return query_stack(ctx, tip);
+                }
+                None => {
+                    return Err(EvalError::UnboundName {
+                        name: name.to_owned(),
+                    })
+                }
+            },
+        },
     };
 
     ctx.dag
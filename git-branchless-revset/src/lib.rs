@@ -20,6 +20,7 @@ mod resolve;
 pub use ast::Expr;
 pub use eval::eval;
 pub use parser::parse;
+pub use pattern::parse_time;
 pub use resolve::{check_revset_syntax, resolve_commits, resolve_default_smartlog_commits};
 
 use lalrpop_util::lalrpop_mod;
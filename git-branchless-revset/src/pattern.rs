@@ -2,7 +2,7 @@ use std::convert::TryFrom;
 use std::fmt::Debug;
 use std::sync::{Arc, Mutex};
 
-use chrono::{Local, NaiveDateTime};
+use chrono::{DateTime, Local, NaiveDateTime, TimeZone};
 use chrono_english::{parse_date_string, parse_duration, DateError, Dialect, Interval};
 use chronoutil::RelativeDuration;
 use eden_dag::nameset::hints::{Flags, Hints};
@@ -96,21 +96,12 @@ impl Pattern {
         }
 
         fn parse_date(pattern: &str) -> Result<NaiveDateTime, PatternError> {
-            if let Ok(date) = parse_date_string(pattern, Local::now(), Dialect::Us) {
-                return Ok(date.naive_local());
+            match parse_time(pattern) {
+                Some(date) => Ok(date.naive_local()),
+                None => Err(PatternError::ConstructMatcher(eyre::eyre!(
+                    "cannot parse date: {pattern}"
+                ))),
             }
-            if let Ok(interval) = parse_duration(pattern) {
-                let delta = match interval {
-                    Interval::Seconds(seconds) => RelativeDuration::seconds(seconds.into()),
-                    Interval::Days(days) => RelativeDuration::days(days.into()),
-                    Interval::Months(months) => RelativeDuration::months(months),
-                };
-                let date = Local::now().naive_local() + delta;
-                return Ok(date);
-            }
-            Err(PatternError::ConstructMatcher(eyre::eyre!(
-                "cannot parse date: {pattern}"
-            )))
         }
 
         if let Some(pattern) = pattern.strip_prefix("before:") {
@@ -126,6 +117,36 @@ impl Pattern {
     }
 }
 
+/// Parse a date or time relative to the current time, such as `2023-01-01`,
+/// `yesterday`, `-3 days`, or `10 minutes ago`, or a Unix timestamp in seconds
+/// prefixed with `@`, such as `@1700000000`. Returns `None` if it couldn't be
+/// parsed.
+pub fn parse_time(input: &str) -> Option<DateTime<Local>> {
+    let input = input.trim();
+    if let Some(timestamp) = input.strip_prefix('@') {
+        let timestamp: i64 = timestamp.parse().ok()?;
+        return Local.timestamp_opt(timestamp, 0).single();
+    }
+
+    let now = Local::now();
+    if let Ok(date) = parse_date_string(input, now, Dialect::Us) {
+        return Some(date);
+    }
+    let (input, is_past) = match input.strip_suffix(" ago") {
+        Some(input) => (input.trim_end(), true),
+        None => (input, false),
+    };
+    if let Ok(interval) = parse_duration(input) {
+        let delta = match interval {
+            Interval::Seconds(seconds) => RelativeDuration::seconds(seconds.into()),
+            Interval::Days(days) => RelativeDuration::days(days.into()),
+            Interval::Months(months) => RelativeDuration::months(months),
+        };
+        return Some(if is_past { now - delta } else { now + delta });
+    }
+    None
+}
+
 pub(super) trait PatternMatcher: Sync + Send {
     fn get_description(&self) -> &str;
     fn matches_commit(&self, repo: &Repo, commit: &Commit) -> Result<bool, PatternError>;
@@ -270,4 +291,22 @@ mod tests {
 
         Ok(())
     }
+
+    #[test]
+    fn test_parse_time() {
+        assert_eq!(
+            parse_time("@1700000000").map(|time| time.timestamp()),
+            Some(1700000000)
+        );
+        assert_eq!(parse_time("@foo"), None);
+
+        let now = Local::now();
+        let ten_minutes_ago = parse_time("10 minutes ago").unwrap();
+        assert!(ten_minutes_ago < now);
+        assert!(ten_minutes_ago > now - chrono::Duration::minutes(11));
+        let in_ten_minutes = parse_time("10 minutes").unwrap();
+        assert!(in_ten_minutes > now);
+
+        assert_eq!(parse_time("not a time"), None);
+    }
 }
@@ -9,8 +9,6 @@
 )]
 #![allow(clippy::too_many_arguments, clippy::blocks_in_if_conditions)]
 
-pub mod dialoguer_edit;
-
 use lib::core::check_out::CheckOutCommitOptions;
 use lib::core::repo_ext::RepoExt;
 use lib::util::{ExitCode, EyreExitOr};
@@ -22,23 +20,23 @@ use std::fs::File;
 use std::time::SystemTime;
 
 use bstr::{ByteSlice, ByteVec};
-use chrono::Local;
-use dialoguer_edit::Editor;
+use chrono::{DateTime, FixedOffset, Local};
 
 use eyre::Context;
 use tracing::{instrument, warn};
 
 use lib::core::config::{
-    get_comment_char, get_commit_template, get_editor, get_restack_preserve_timestamps,
+    get_comment_char, get_commit_template, get_restack_preserve_timestamps, get_trailer_template,
 };
 use lib::core::dag::{sorted_commit_set, union_all, CommitSet, Dag};
+use lib::core::editor::edit_message;
 use lib::core::effects::Effects;
-use lib::core::eventlog::{EventLogDb, EventReplayer};
-use lib::core::formatting::{Glyphs, Pluralize};
+use lib::core::eventlog::{EventLogDb, EventReplayer, EventTransactionId};
+use lib::core::formatting::Pluralize;
 use lib::core::node_descriptors::{render_node_descriptors, CommitOidDescriptor, NodeObject};
 use lib::core::rewrite::{
     execute_rebase_plan, BuildRebasePlanOptions, ExecuteRebasePlanOptions, ExecuteRebasePlanResult,
-    RebasePlanBuilder, RebasePlanPermissions, RepoResource,
+    FailedMergeInfo, RebasePlanBuilder, RebasePlanPermissions, RepoResource,
 };
 use lib::git::{message_prettify, Commit, GitRunInfo, MaybeZeroOid, NonZeroOid, Repo};
 
@@ -54,28 +52,172 @@ pub enum InitialCommitMessages {
     /// The user wants to fixup a commit.
     FixUp(Revset),
 
+    /// The user wants to keep the original messages, such as when only
+    /// updating the author metadata of the commits.
+    Keep,
+
     /// The user provided explicit messages.
     Messages(Vec<String>),
+
+    /// The user provided a template to render with each commit's original
+    /// message. See [`render_message_template`].
+    Template(String),
 }
 
-/// Open the user's configured commit editor seeded with the provided message.
-#[instrument]
-pub fn edit_message(git_run_info: &GitRunInfo, repo: &Repo, message: &str) -> eyre::Result<String> {
-    let mut editor = Editor::new();
-    let (editor, editor_program) = match get_editor(git_run_info, repo)? {
-        Some(editor_program) => (editor.executable(&editor_program), editor_program),
-        None => (&mut editor, "<default>".into()),
+/// The author metadata provided by the user, to apply to each reworded commit.
+#[derive(Debug, Default)]
+pub struct AuthorUpdates {
+    /// The new author, in the form `Name <email>`.
+    pub author: Option<String>,
+
+    /// The new author date. See [`parse_author_date`] for the accepted
+    /// formats.
+    pub date: Option<String>,
+}
+
+/// The trailer updates provided by the user, to apply to each reworded commit.
+#[derive(Debug, Default)]
+pub struct TrailerUpdates {
+    /// Trailers to add, in the form `Key: value`. See
+    /// [`render_trailer_template`] for the placeholders which can be used in
+    /// the value.
+    pub add: Vec<String>,
+
+    /// The keys of the trailers to remove.
+    pub remove: Vec<String>,
+
+    /// The forge whose configured trailers should be added, if any. See
+    /// [`get_trailer_template`].
+    pub forge: Option<String>,
+}
+
+impl TrailerUpdates {
+    /// Whether there are no trailers to add or remove.
+    pub fn is_empty(&self) -> bool {
+        let Self { add, remove, forge } = self;
+        add.is_empty() && remove.is_empty() && forge.is_none()
+    }
+}
+
+/// Get the trailers to add for a forge whose trailers haven't been configured.
+fn get_default_trailer_template(forge: &str) -> Option<&'static str> {
+    match forge {
+        "gerrit" => Some("Change-Id: {change_id}"),
+        _ => None,
+    }
+}
+
+/// Parse a trailer in the form `Key: value` into its key and value.
+pub fn parse_trailer(trailer: &str) -> Option<(String, String)> {
+    let (key, value) = trailer.split_once(':')?;
+    let value = value.trim();
+    if key.is_empty()
+        || value.is_empty()
+        || !key.chars().all(|c| c.is_ascii_alphanumeric() || c == '-')
+    {
+        return None;
+    }
+    Some((key.to_string(), value.to_string()))
+}
+
+/// Render the value of a trailer template for a commit. The placeholder
+/// `{change_id}` is replaced with the value of the commit's `Change-Id` trailer,
+/// or with a new Gerrit-style change ID derived from the commit's OID if it
+/// doesn't have one, and `{author}` is replaced with the commit's author in the
+/// form `Name <email>`.
+pub fn render_trailer_template(template: &str, commit: &Commit) -> eyre::Result<String> {
+    let change_id = match commit
+        .get_trailers()?
+        .into_iter()
+        .find(|(key, _value)| key.eq_ignore_ascii_case("Change-Id"))
+    {
+        Some((_key, change_id)) => change_id,
+        None => format!("I{}", commit.get_oid()),
     };
-    if editor_program == ":" {
-        // Special case in Git: treat `:` as a no-op editor.
-        return Ok(message.to_string());
+    let author = commit.get_author();
+    let author = format!(
+        "{} <{}>",
+        author.get_name().unwrap_or_default(),
+        author.get_email().unwrap_or_default()
+    );
+    Ok(template
+        .replace("{change_id}", &change_id)
+        .replace("{author}", &author))
+}
+
+/// Update the trailers in the last paragraph of a commit message. The trailers
+/// whose keys are in `remove` (compared case-insensitively) are removed, and
+/// then each trailer in `add` is added, unless it's already present.
+pub fn update_trailers(message: &str, remove: &[String], add: &[(String, String)]) -> String {
+    let message = message.trim_end();
+    let (body, trailer_lines) = match message.rsplit_once("\n\n") {
+        Some((body, last_paragraph))
+            if !last_paragraph.starts_with(char::is_whitespace)
+                && last_paragraph.lines().all(|line| {
+                    line.starts_with(char::is_whitespace) || parse_trailer(line).is_some()
+                }) =>
+        {
+            (body.trim_end(), last_paragraph.lines().collect())
+        }
+        _ => (message, Vec::new()),
+    };
+
+    let mut trailers: Vec<String> = Vec::new();
+    let mut is_removed = false;
+    for line in trailer_lines {
+        // Continuation lines belong to the preceding trailer.
+        if !line.starts_with(char::is_whitespace) {
+            is_removed = match parse_trailer(line) {
+                Some((key, _value)) => remove.iter().any(|r| r.eq_ignore_ascii_case(&key)),
+                None => false,
+            };
+        }
+        if !is_removed {
+            trailers.push(line.to_string());
+        }
+    }
+    for (key, value) in add {
+        let is_present = trailers.iter().any(|line| match parse_trailer(line) {
+            Some((k, v)) => k.eq_ignore_ascii_case(key) && &v == value,
+            None => false,
+        });
+        if !is_present {
+            trailers.push(format!("{key}: {value}"));
+        }
+    }
+
+    if trailers.is_empty() {
+        format!("{body}\n")
+    } else {
+        format!("{body}\n\n{}\n", trailers.join("\n"))
     }
-    let result = editor
-        .require_save(false)
-        .edit(message)
-        .with_context(|| format!("Invoking editor: '{}'", editor_program.to_string_lossy()))?
-        .expect("`Editor::edit` should not return `None` when `require_save` is `false`");
-    Ok(result)
+}
+
+/// Parse an author in the form `Name <email>` into its name and email.
+pub fn parse_author_identity(author: &str) -> Option<(String, String)> {
+    let (name, rest) = author.trim().split_once('<')?;
+    let email = rest.strip_suffix('>')?.trim();
+    let name = name.trim();
+    if name.is_empty() || email.contains(['<', '>']) {
+        return None;
+    }
+    Some((name.to_string(), email.to_string()))
+}
+
+/// Parse an author date in RFC 3339 format (`2023-01-02T03:04:05+01:00`),
+/// RFC 2822 format (`Mon, 2 Jan 2023 03:04:05 +0100`), or Git's internal
+/// format (`@1672625045 +0100`).
+pub fn parse_author_date(date: &str) -> Option<DateTime<FixedOffset>> {
+    let date = date.trim();
+    if let Ok(date) = DateTime::parse_from_rfc3339(date) {
+        return Some(date);
+    }
+    if let Ok(date) = DateTime::parse_from_rfc2822(date) {
+        return Some(date);
+    }
+    let date = date.strip_prefix('@').unwrap_or(date);
+    let (seconds, offset) = date.split_once(' ').unwrap_or((date, "+0000"));
+    DateTime::parse_from_str(&format!("{seconds} {}", offset.trim()), "%s %z").ok()
 }
 
 /// Reword a commit and restack its descendants.
@@ -85,9 +227,39 @@ pub fn reword(
     revsets: Vec<Revset>,
     resolve_revset_options: &ResolveRevsetOptions,
     messages: InitialCommitMessages,
+    author_updates: AuthorUpdates,
+    trailer_updates: TrailerUpdates,
     git_run_info: &GitRunInfo,
     force_rewrite_public_commits: bool,
 ) -> EyreExitOr<()> {
+    let AuthorUpdates { author, date } = author_updates;
+    let identity = match author {
+        Some(author) => match parse_author_identity(&author) {
+            Some(identity) => Some(identity),
+            None => {
+                writeln!(
+                    effects.get_error_stream(),
+                    "Invalid author {author:?}: expected the form 'Name <email>'.\nAborting."
+                )?;
+                return Ok(Err(ExitCode(1)));
+            }
+        },
+        None => None,
+    };
+    let date = match date {
+        Some(date) => match parse_author_date(&date) {
+            Some(date) => Some(date),
+            None => {
+                writeln!(
+                    effects.get_error_stream(),
+                    "Invalid date {date:?}: expected an RFC 3339 date, an RFC 2822 date, or '@<seconds> <+hhmm>'.\nAborting."
+                )?;
+                return Ok(Err(ExitCode(1)));
+            }
+        },
+        None => None,
+    };
+
     let repo = Repo::from_current_dir()?;
     let references_snapshot = repo.get_references_snapshot()?;
     let conn = repo.get_db_conn()?;
@@ -102,6 +274,49 @@ pub fn reword(
         &references_snapshot,
     )?;
 
+    let TrailerUpdates {
+        add: mut trailers_to_add,
+        remove: trailers_to_remove,
+        forge,
+    } = trailer_updates;
+    if let Some(forge) = forge {
+        let template = match get_trailer_template(&repo, &forge)? {
+            Some(template) => template,
+            None => match get_default_trailer_template(&forge) {
+                Some(template) => template.to_string(),
+                None => {
+                    writeln!(
+                        effects.get_error_stream(),
+                        "No trailers are configured for {forge:?}. Configure them by running:\n\n    git config branchless.trailers.{forge} 'Key: value'\n\nAborting."
+                    )?;
+                    return Ok(Err(ExitCode(1)));
+                }
+            },
+        };
+        trailers_to_add.extend(
+            template
+                .lines()
+                .filter(|line| !line.trim().is_empty())
+                .map(|line| line.to_string()),
+        );
+    }
+    let trailers_to_add = {
+        let mut result = Vec::new();
+        for trailer in trailers_to_add {
+            match parse_trailer(&trailer) {
+                Some(trailer) => result.push(trailer),
+                None => {
+                    writeln!(
+                        effects.get_error_stream(),
+                        "Invalid trailer {trailer:?}: expected the form 'Key: value'.\nAborting."
+                    )?;
+                    return Ok(Err(ExitCode(1)));
+                }
+            }
+        }
+        result
+    };
+
     let commits = match resolve_commits_from_hashes(
         &repo,
         &mut dag,
@@ -131,7 +346,10 @@ pub fn reword(
     };
 
     let messages = match messages {
-        InitialCommitMessages::Discard | InitialCommitMessages::Messages(_) => messages,
+        InitialCommitMessages::Discard
+        | InitialCommitMessages::Keep
+        | InitialCommitMessages::Messages(_)
+        | InitialCommitMessages::Template(_) => messages,
         InitialCommitMessages::FixUp(revset) => {
             let commits_to_fixup = resolve_commits_from_hashes(
                 &repo,
@@ -172,10 +390,30 @@ pub fn reword(
         }
     };
 
+    let now = SystemTime::now();
+    let event_tx_id = event_log_db.make_transaction_id(now, "reword")?;
+    let prepare_message_fn = |commit: &Commit, message: &str, source_args: &[&str]| {
+        run_prepare_commit_msg_hook(
+            effects,
+            git_run_info,
+            &repo,
+            event_tx_id,
+            commit,
+            message,
+            source_args,
+        )
+    };
     let edit_message_fn = |message: &str| edit_message(git_run_info, &repo, message);
 
-    let messages = match prepare_messages(&repo, messages, &commits, edit_message_fn)? {
+    let messages = match prepare_messages(
+        &repo,
+        messages,
+        &commits,
+        prepare_message_fn,
+        edit_message_fn,
+    )? {
         PrepareMessagesResult::Succeeded { messages } => messages,
+        PrepareMessagesResult::HookFailed { exit_code } => return Ok(Err(exit_code)),
         PrepareMessagesResult::IdenticalMessage => {
             writeln!(
                 effects.get_output_stream(),
@@ -250,40 +488,103 @@ pub fn reword(
         }
     };
 
+    let mut replacements = Vec::new();
+    for commit in commits.iter() {
+        let message = messages.get(&commit.get_oid()).unwrap();
+        let message = if trailers_to_add.is_empty() && trailers_to_remove.is_empty() {
+            message.clone()
+        } else {
+            let mut trailers = Vec::new();
+            for (key, template) in trailers_to_add.iter() {
+                trailers.push((key.clone(), render_trailer_template(template, commit)?));
+            }
+            update_trailers(message, &trailers_to_remove, &trailers)
+        };
+        let message =
+            match run_commit_msg_hook(effects, git_run_info, &repo, event_tx_id, commit, &message)?
+            {
+                Ok(message) => message,
+                Err(exit_code) => return Ok(Err(exit_code)),
+            };
+        let mut author = commit.get_author();
+        if let Some((name, email)) = &identity {
+            author = author.update_identity(name, email)?;
+        }
+        if let Some(date) = &date {
+            author = author.update_time(date.timestamp(), date.offset().local_minus_utc() / 60)?;
+        }
+        // This looks funny, but just means "leave everything but the
+        // message and author as is"
+        let replacement_oid =
+            commit.amend_commit(None, Some(&author), None, Some(message.as_str()), None)?;
+        replacements.push((commit.get_oid(), commit.get_parent_oids(), replacement_oid));
+    }
+
+    match replace_commits(
+        effects,
+        git_run_info,
+        &repo,
+        &dag,
+        &event_log_db,
+        permissions,
+        now,
+        event_tx_id,
+        &replacements,
+    )? {
+        Ok(Some(rewritten_oids)) => {
+            render_status_report(&repo, effects, &commits, &rewritten_oids)?;
+            Ok(Ok(()))
+        }
+        Ok(None) => Ok(Ok(())),
+        Err(exit_code) => Ok(Err(exit_code)),
+    }
+}
+
+/// Replace commits with rewritten versions of themselves in a single in-memory
+/// rebase, restacking their descendants onto the replacements. Each entry of
+/// `replacements` holds the OID of a commit to replace, its parent OIDs, and
+/// the OID of its replacement, which must have the same parents and tree.
+///
+/// Returns the rewritten OIDs of the rebase, if available.
+#[instrument(skip(replacements))]
+pub fn replace_commits(
+    effects: &Effects,
+    git_run_info: &GitRunInfo,
+    repo: &Repo,
+    dag: &Dag,
+    event_log_db: &EventLogDb,
+    permissions: RebasePlanPermissions,
+    now: SystemTime,
+    event_tx_id: EventTransactionId,
+    replacements: &[(NonZeroOid, Vec<NonZeroOid>, NonZeroOid)],
+) -> EyreExitOr<Option<HashMap<NonZeroOid, MaybeZeroOid>>> {
     let rebase_plan = {
         let pool = ThreadPoolBuilder::new().build()?;
-        let repo_pool = RepoResource::new_pool(&repo)?;
-        let mut builder = RebasePlanBuilder::new(&dag, permissions);
-
-        for commit in commits.iter() {
-            let message = messages.get(&commit.get_oid()).unwrap();
-            // This looks funny, but just means "leave everything but the message as is"
-            let replacement_oid =
-                commit.amend_commit(None, None, None, Some(message.as_str()), None)?;
-            builder.move_subtree(commit.get_oid(), commit.get_parent_oids())?;
-            builder.replace_commit(commit.get_oid(), replacement_oid)?;
+        let repo_pool = RepoResource::new_pool(repo)?;
+        let mut builder = RebasePlanBuilder::new(dag, permissions);
+        for (commit_oid, parent_oids, replacement_oid) in replacements {
+            builder.move_subtree(*commit_oid, parent_oids.clone())?;
+            builder.replace_commit(*commit_oid, *replacement_oid)?;
         }
 
         match builder.build(effects, &pool, &repo_pool)? {
             Ok(Some(rebase_plan)) => rebase_plan,
             Ok(None) => {
                 eyre::bail!(
-                    "BUG: rebase plan indicates nothing to do, but rewording should always do something."
+                    "BUG: rebase plan indicates nothing to do, but some commits should have been replaced."
                 );
             }
             Err(err) => {
-                err.describe(effects, &repo, &dag)?;
+                err.describe(effects, repo, dag)?;
                 return Ok(Err(ExitCode(1)));
             }
         }
     };
 
-    let now = SystemTime::now();
-    let event_tx_id = event_log_db.make_transaction_id(now, "reword")?;
     let execute_options = ExecuteRebasePlanOptions {
         now,
         event_tx_id,
-        preserve_timestamps: get_restack_preserve_timestamps(&repo)?,
+        preserve_timestamps: get_restack_preserve_timestamps(repo)?,
         force_in_memory: true,
         force_on_disk: false,
         resolve_merge_conflicts: false,
@@ -296,28 +597,36 @@ pub fn reword(
     let result = execute_rebase_plan(
         effects,
         git_run_info,
-        &repo,
-        &event_log_db,
+        repo,
+        event_log_db,
         &rebase_plan,
         &execute_options,
     )?;
 
     match result {
-        ExecuteRebasePlanResult::Succeeded {
-            rewritten_oids: Some(rewritten_oids),
+        ExecuteRebasePlanResult::Succeeded { rewritten_oids } => Ok(Ok(rewritten_oids)),
+        ExecuteRebasePlanResult::DeclinedToMerge {
+            failed_merge_info: FailedMergeInfo::CannotRebaseMergeInMemory { commit_oid },
         } => {
-            render_status_report(&repo, effects, &commits, &rewritten_oids)?;
-            Ok(Ok(()))
+            writeln!(
+                effects.get_error_stream(),
+                "The merge commit {} would have to be restacked, but merge commits can't be restacked in-memory.",
+                effects.get_glyphs().render(
+                    repo.friendly_describe_commit_from_oid(effects.get_glyphs(), commit_oid)?
+                )?,
+            )?;
+            writeln!(
+                effects.get_error_stream(),
+                "Leave out the commits below the merge commit, or rebase it manually first."
+            )?;
+            Ok(Err(ExitCode(1)))
         }
-        ExecuteRebasePlanResult::Succeeded {
-            rewritten_oids: None,
-        } => Ok(Ok(())),
         ExecuteRebasePlanResult::DeclinedToMerge {
-            failed_merge_info: _,
+            failed_merge_info: FailedMergeInfo::Conflict { .. },
         } => {
             writeln!(
                 effects.get_error_stream(),
-                "BUG: Merge failed, but rewording shouldn't cause any merge failures."
+                "BUG: Merge failed, but replacing commits with the same tree shouldn't cause any merge conflicts."
             )?;
             Ok(Err(ExitCode(1)))
         }
@@ -325,6 +634,67 @@ pub fn reword(
     }
 }
 
+/// Run the `prepare-commit-msg` hook on the message of a commit, as `git
+/// commit --amend` would before opening the editor, and return the message as
+/// edited by the hook. `source_args` describe where the message came from, as
+/// documented in `githooks(5)`.
+fn run_prepare_commit_msg_hook(
+    effects: &Effects,
+    git_run_info: &GitRunInfo,
+    repo: &Repo,
+    event_tx_id: EventTransactionId,
+    commit: &Commit,
+    message: &str,
+    source_args: &[&str],
+) -> EyreExitOr<String> {
+    match git_run_info.run_message_hook(
+        effects,
+        repo,
+        "prepare-commit-msg",
+        event_tx_id,
+        message,
+        source_args,
+    )? {
+        // Only clean up messages which were edited by the hook, so that
+        // messages are otherwise kept exactly as provided.
+        Ok(edited_message) if edited_message == message => Ok(Ok(edited_message)),
+        Ok(edited_message) => Ok(Ok(message_prettify(&edited_message, None)?)),
+        Err(exit_code) => {
+            let glyphs = effects.get_glyphs();
+            writeln!(
+                effects.get_error_stream(),
+                "Aborting reword, since the prepare-commit-msg hook failed for commit: {}",
+                glyphs.render(commit.friendly_describe(glyphs)?)?
+            )?;
+            Ok(Err(exit_code))
+        }
+    }
+}
+
+/// Run the `commit-msg` hook on the new message of a commit, and return the
+/// message as edited by the hook.
+fn run_commit_msg_hook(
+    effects: &Effects,
+    git_run_info: &GitRunInfo,
+    repo: &Repo,
+    event_tx_id: EventTransactionId,
+    commit: &Commit,
+    message: &str,
+) -> EyreExitOr<String> {
+    match git_run_info.run_message_hook(effects, repo, "commit-msg", event_tx_id, message, &[])? {
+        Ok(message) => Ok(Ok(message)),
+        Err(exit_code) => {
+            let glyphs = effects.get_glyphs();
+            writeln!(
+                effects.get_error_stream(),
+                "Aborting reword, since the commit-msg hook rejected the message of commit: {}",
+                glyphs.render(commit.friendly_describe(glyphs)?)?
+            )?;
+            Ok(Err(exit_code))
+        }
+    }
+}
+
 /// Turn a list of ref-ish strings into a list of Commits.
 fn resolve_commits_from_hashes<'repo>(
     repo: &'repo Repo,
@@ -347,6 +717,57 @@ fn resolve_commits_from_hashes<'repo>(
     Ok(Some(commits))
 }
 
+/// Render the given message template for a commit. The placeholders
+/// `{message}`, `{summary}`, and `{body}` are replaced with the commit's
+/// original message, the first line of the message, and the remainder of the
+/// message after the first line, respectively.
+pub fn render_message_template(template: &str, commit: &Commit) -> eyre::Result<String> {
+    let original_message = commit
+        .get_message_raw()
+        .to_str()
+        .with_context(|| {
+            eyre::eyre!(
+                "Could not decode commit message for commit: {:?}",
+                commit.get_oid()
+            )
+        })?
+        .trim()
+        .to_string();
+    let (summary, body) = match original_message.split_once('\n') {
+        Some((summary, body)) => (summary, body.trim()),
+        None => (original_message.as_str(), ""),
+    };
+    let placeholders = [
+        ("{message}", original_message.as_str()),
+        ("{summary}", summary),
+        ("{body}", body),
+    ];
+
+    // Replace all placeholders in a single pass, so that placeholder-like text
+    // in the original message isn't itself replaced.
+    let mut message = String::new();
+    let mut rest = template;
+    while let Some(idx) = rest.find('{') {
+        message.push_str(&rest[..idx]);
+        rest = &rest[idx..];
+        match placeholders
+            .iter()
+            .find(|(placeholder, _value)| rest.starts_with(placeholder))
+        {
+            Some((placeholder, value)) => {
+                message.push_str(value);
+                rest = &rest[placeholder.len()..];
+            }
+            None => {
+                message.push('{');
+                rest = &rest[1..];
+            }
+        }
+    }
+    message.push_str(rest);
+    Ok(message)
+}
+
 /// The result of building the reword message.
 #[must_use]
 #[derive(Debug)]
@@ -357,6 +778,9 @@ enum PrepareMessagesResult {
     /// The reworded message matches the original message.
     IdenticalMessage,
 
+    /// The `prepare-commit-msg` hook failed.
+    HookFailed { exit_code: ExitCode },
+
     MismatchedCommits {
         duplicates: Vec<String>,
         missing: Vec<String>,
@@ -372,11 +796,16 @@ enum PrepareMessagesResult {
 
 /// Prepares the message(s) that will be used for rewording. These are mapped from each commit's
 /// NonZeroOid to the relevant message.
-#[instrument(skip(edit_message_fn))]
+///
+/// Each commit's message is passed through `prepare_message_fn`, along with the
+/// source arguments for the `prepare-commit-msg` hook, before the editor is
+/// opened, or before it's used as is if the editor isn't opened.
+#[instrument(skip(prepare_message_fn, edit_message_fn))]
 fn prepare_messages(
     repo: &Repo,
     messages: InitialCommitMessages,
     commits: &[Commit],
+    prepare_message_fn: impl Fn(&Commit, &str, &[&str]) -> EyreExitOr<String>,
     edit_message_fn: impl Fn(&str) -> eyre::Result<String>,
 ) -> eyre::Result<PrepareMessagesResult> {
     let comment_char = get_comment_char(repo)?;
@@ -388,6 +817,48 @@ fn prepare_messages(
         InitialCommitMessages::FixUp(_) => {
             eyre::bail!("BUG: Fixup should have already been handled!")
         }
+        InitialCommitMessages::Keep => {
+            let mut messages = HashMap::new();
+            for commit in commits {
+                let message = commit
+                    .get_message_raw()
+                    .to_str()
+                    .with_context(|| {
+                        eyre::eyre!(
+                            "Could not decode commit message for commit: {:?}",
+                            commit.get_oid()
+                        )
+                    })?
+                    .to_string();
+                let commit_oid = commit.get_oid().to_string();
+                let message = match prepare_message_fn(commit, &message, &["commit", &commit_oid])?
+                {
+                    Ok(message) => message,
+                    Err(exit_code) => return Ok(PrepareMessagesResult::HookFailed { exit_code }),
+                };
+                if message.trim().is_empty() {
+                    return Ok(PrepareMessagesResult::EmptyMessage);
+                }
+                messages.insert(commit.get_oid(), message);
+            }
+            return Ok(PrepareMessagesResult::Succeeded { messages });
+        }
+        InitialCommitMessages::Template(ref template) => {
+            let mut messages = HashMap::new();
+            for commit in commits {
+                let message = render_message_template(template, commit)?;
+                let message = message_prettify(message.as_str(), None)?;
+                let message = match prepare_message_fn(commit, &message, &["message"])? {
+                    Ok(message) => message,
+                    Err(exit_code) => return Ok(PrepareMessagesResult::HookFailed { exit_code }),
+                };
+                if message.trim().is_empty() {
+                    return Ok(PrepareMessagesResult::EmptyMessage);
+                }
+                messages.insert(commit.get_oid(), message);
+            }
+            return Ok(PrepareMessagesResult::Succeeded { messages });
+        }
         InitialCommitMessages::Messages(ref messages) => {
             let message = messages.clone().join("\n\n");
             let message = message.trim();
@@ -402,21 +873,27 @@ fn prepare_messages(
             return Ok(PrepareMessagesResult::EmptyMessage);
         }
 
-        let messages = commits
-            .iter()
-            .map(|commit| (commit.get_oid(), message.clone()))
-            .collect();
+        let mut messages = HashMap::new();
+        for commit in commits {
+            let message = match prepare_message_fn(commit, &message, &["message"])? {
+                Ok(message) => message,
+                Err(exit_code) => return Ok(PrepareMessagesResult::HookFailed { exit_code }),
+            };
+            if message.trim().is_empty() {
+                return Ok(PrepareMessagesResult::EmptyMessage);
+            }
+            messages.insert(commit.get_oid(), message);
+        }
 
         return Ok(PrepareMessagesResult::Succeeded { messages });
     };
 
-    let possible_template_message = message.trim();
-    let possible_template_message = if possible_template_message.is_empty() {
-        String::from("\n")
+    let template_message = message.trim().to_string();
+    let template_source_args: &[&str] = if template_message.is_empty() {
+        &[]
     } else {
-        format!("{possible_template_message}\n\n")
+        &["template"]
     };
-    let possible_template_message = possible_template_message.as_str();
     let discarded_message_header = format!("{comment_char} Original message:\n{comment_char} ");
     let discarded_message_header = discarded_message_header.as_str();
     let discarded_message_padding = format!("\n{comment_char} ");
@@ -439,8 +916,19 @@ fn prepare_messages(
             .to_string();
 
         let msg = if discard_messages {
+            let possible_template_message =
+                match prepare_message_fn(commit, &template_message, template_source_args)? {
+                    Ok(message) => message,
+                    Err(exit_code) => return Ok(PrepareMessagesResult::HookFailed { exit_code }),
+                };
+            let possible_template_message = possible_template_message.trim();
+            let possible_template_message = if possible_template_message.is_empty() {
+                String::from("\n")
+            } else {
+                format!("{possible_template_message}\n\n")
+            };
             [
-                possible_template_message,
+                possible_template_message.as_str(),
                 discarded_message_header,
                 original_message
                     .split('\n')
@@ -450,7 +938,11 @@ fn prepare_messages(
             ]
             .concat()
         } else {
-            original_message
+            let commit_oid = commit.get_oid().to_string();
+            match prepare_message_fn(commit, &original_message, &["commit", &commit_oid])? {
+                Ok(message) => message.trim().to_string(),
+                Err(exit_code) => return Ok(PrepareMessagesResult::HookFailed { exit_code }),
+            }
         };
 
         let msg = if commits.len() == 1 {
@@ -650,7 +1142,7 @@ fn render_status_report(
     commits: &[Commit],
     rewritten_oids: &HashMap<NonZeroOid, MaybeZeroOid>,
 ) -> eyre::Result<()> {
-    let glyphs = Glyphs::detect();
+    let glyphs = effects.get_glyphs();
     let num_commits = commits.len();
     for original_commit in commits {
         let replacement_oid = match rewritten_oids.get(&original_commit.get_oid()) {
@@ -678,14 +1170,14 @@ fn render_status_report(
             glyphs.render(
                 // Commit doesn't offer `friendly_describe_oid`, so we'll do it ourselves
                 render_node_descriptors(
-                    &glyphs,
+                    glyphs,
                     &NodeObject::Commit {
                         commit: original_commit.clone(),
                     },
                     &mut [&mut CommitOidDescriptor::new(true)?],
                 )?
             )?,
-            glyphs.render(replacement_commit.friendly_describe(&glyphs)?)?
+            glyphs.render(replacement_commit.friendly_describe(glyphs)?)?
         )?;
     }
 
@@ -719,6 +1211,7 @@ mod tests {
                 &repo,
                 InitialCommitMessages::Discard,
                 &[head_commit.clone()],
+                |_commit, message, _source_args| Ok(Ok(message.to_string())),
                 |message| {
                     insta::assert_snapshot!(message.trim(), @r###"
 
@@ -747,6 +1240,7 @@ This is a template!
                 &repo,
                 InitialCommitMessages::Discard,
                 &[head_commit],
+                |_commit, message, _source_args| Ok(Ok(message.to_string())),
                 |message| {
                     insta::assert_snapshot!(message.trim(), @r###"
                     This is a template!
@@ -766,6 +1260,54 @@ This is a template!
         Ok(())
     }
 
+    #[test]
+    fn test_reword_renders_message_template() -> eyre::Result<()> {
+        let git = make_git()?;
+        git.init_repo()?;
+        let repo = git.get_repo()?;
+
+        let test1_oid = git.commit_file_with_contents_and_message(
+            "test1",
+            1,
+            "test1 contents\n",
+            "create test1.txt\n\nSome details about",
+        )?;
+        let test2_oid = git.commit_file("test2", 2)?;
+        let test1_commit = repo.find_commit_or_fail(test1_oid)?;
+        let test2_commit = repo.find_commit_or_fail(test2_oid)?;
+
+        insta::assert_snapshot!(
+            render_message_template("{summary} [ABC-123]\n\n{body}", &test1_commit)?,
+            @r###"
+        create test1.txt [ABC-123]
+
+        Some details about test1.txt
+        "###
+        );
+
+        let result = prepare_messages(
+            &repo,
+            InitialCommitMessages::Template("[ABC-123] {message} {unknown}".to_string()),
+            &[test1_commit, test2_commit],
+            |_commit, message, _source_args| Ok(Ok(message.to_string())),
+            |_message| eyre::bail!("The editor should not be opened"),
+        )?;
+        let messages = match result {
+            PrepareMessagesResult::Succeeded { messages } => messages,
+            other => eyre::bail!("Unexpected result: {other:?}"),
+        };
+        assert_eq!(
+            messages.get(&test1_oid).map(String::as_str),
+            Some("[ABC-123] create test1.txt\n\nSome details about test1.txt {unknown}\n")
+        );
+        assert_eq!(
+            messages.get(&test2_oid).map(String::as_str),
+            Some("[ABC-123] create test2.txt {unknown}\n")
+        );
+
+        Ok(())
+    }
+
     #[test]
     fn test_reword_builds_multi_commit_messages() -> eyre::Result<()> {
         let git = make_git()?;
@@ -782,6 +1324,7 @@ This is a template!
                 &repo,
                 InitialCommitMessages::Messages([].to_vec()),
                 &[test1_commit.clone(), test2_commit.clone()],
+                |_commit, message, _source_args| Ok(Ok(message.to_string())),
                 |message| {
                     insta::assert_snapshot!(message.trim(), @r###"
                     ++ reword 62fc20d
@@ -892,4 +1435,66 @@ This is a template!
 
         Ok(())
     }
+
+    #[test]
+    fn test_update_trailers() {
+        let add = |key: &str, value: &str| vec![(key.to_string(), value.to_string())];
+        let remove = |key: &str| vec![key.to_string()];
+
+        assert_eq!(
+            update_trailers("foo\n", &[], &add("Reviewed-by", "Jane")),
+            "foo\n\nReviewed-by: Jane\n"
+        );
+        assert_eq!(
+            update_trailers(
+                "foo\n\nbar\n\nChange-Id: I123\n",
+                &[],
+                &add("Reviewed-by", "Jane")
+            ),
+            "foo\n\nbar\n\nChange-Id: I123\nReviewed-by: Jane\n"
+        );
+        assert_eq!(
+            update_trailers("foo\n\nChange-Id: I123\n", &[], &add("change-id", "I123")),
+            "foo\n\nChange-Id: I123\n"
+        );
+        assert_eq!(
+            update_trailers(
+                "foo\n\nChange-Id: I123\nPull-Request: https://example.com/1\n  continued\n",
+                &remove("pull-request"),
+                &[]
+            ),
+            "foo\n\nChange-Id: I123\n"
+        );
+        assert_eq!(
+            update_trailers("foo\n\nChange-Id: I123\n", &remove("Change-Id"), &[]),
+            "foo\n"
+        );
+        assert_eq!(
+            update_trailers("foo: bar\n", &remove("foo"), &[]),
+            "foo: bar\n"
+        );
+        assert_eq!(parse_trailer("Not a trailer: foo"), None);
+    }
+
+    #[test]
+    fn test_parse_author_metadata() {
+        assert_eq!(
+            parse_author_identity("Jane Doe <jane@example.com>"),
+            Some(("Jane Doe".to_string(), "jane@example.com".to_string()))
+        );
+        assert_eq!(parse_author_identity("Jane Doe"), None);
+        assert_eq!(parse_author_identity("<jane@example.com>"), None);
+
+        let parse = |date: &str| {
+            parse_author_date(date).map(|date| (date.timestamp(), date.offset().local_minus_utc()))
+        };
+        assert_eq!(parse("@1672625045 +0100"), Some((1672625045, 3600)));
+        assert_eq!(parse("1672625045"), Some((1672625045, 0)));
+        assert_eq!(parse("2023-01-02T03:04:05+01:00"), Some((1672625045, 3600)));
+        assert_eq!(
+            parse("Mon, 2 Jan 2023 03:04:05 +0100"),
+            Some((1672625045, 3600))
+        );
+        assert_eq!(parse("yesterday"), None);
+    }
 }
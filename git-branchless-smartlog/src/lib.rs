@@ -13,39 +13,51 @@
 #![allow(clippy::too_many_arguments, clippy::blocks_in_if_conditions)]
 
 use std::cmp::Ordering;
+use std::collections::HashSet;
+use std::ffi::OsStr;
 use std::fmt::Write;
+use std::io::Write as _;
+use std::path::{Component, Path, PathBuf};
+use std::process::{Command, Stdio};
 use std::time::SystemTime;
 
+use cursive_core::theme::Effect;
+use cursive_core::utils::markup::StyledString;
 use git_branchless_invoke::CommandContext;
 use git_branchless_opts::{Revset, SmartlogArgs};
 use lib::core::config::{
-    get_hint_enabled, get_hint_string, get_smartlog_default_revset, print_hint_suppression_notice,
-    Hint,
+    get_hint_enabled, get_hint_string, get_pager, get_smartlog_default_revset, get_smartlog_limit,
+    get_smartlog_paths, print_hint_suppression_notice, Hint,
 };
-use lib::core::repo_ext::RepoExt;
+use lib::core::repo_ext::{RepoExt, RepoReferencesSnapshot};
 use lib::core::rewrite::find_rewrite_target;
 use lib::util::{ExitCode, EyreExitOr};
-use tracing::instrument;
+use tracing::{instrument, warn};
 
+use lib::core::commit_metadata::CommitMetadataDescriptor;
 use lib::core::dag::{CommitSet, Dag};
 use lib::core::effects::Effects;
-use lib::core::eventlog::{EventLogDb, EventReplayer};
-use lib::core::formatting::Pluralize;
+use lib::core::eventlog::{EventCursor, EventLogDb, EventReplayer, ReadOnlyEventLogDb};
+use lib::core::formatting::{Pluralize, StyledStringBuilder};
 use lib::core::node_descriptors::{
-    BranchesDescriptor, CommitMessageDescriptor, CommitOidDescriptor,
-    DifferentialRevisionDescriptor, ObsolescenceExplanationDescriptor, Redactor,
-    RelativeTimeDescriptor,
+    BranchesDescriptor, CommitMessageDescriptor, CommitOidDescriptor, CopySourceDescriptor,
+    DifferentialRevisionDescriptor, NicknameDescriptor, NodeDescriptor,
+    ObsolescenceExplanationDescriptor, Redactor, RelativeTimeDescriptor, TagsDescriptor,
 };
-use lib::git::{GitRunInfo, Repo};
+use lib::git::{CategorizedReferenceName, GitRunInfo, NonZeroOid, Repo, Time};
 
-pub use graph::{make_smartlog_graph, SmartlogGraph};
+pub use graph::{make_smartlog_graph, sort_smartlog_graph, SmartlogGraph};
 pub use render::{render_graph, SmartlogOptions};
 
-use git_branchless_revset::resolve_commits;
+use git_branchless_revset::{parse_time, resolve_commits};
 
 mod graph {
-    use std::collections::HashMap;
+    use std::collections::{HashMap, HashSet};
     use std::convert::TryFrom;
+    use std::time::SystemTime;
+
+    use git_branchless_opts::SmartlogSort;
+    use rayon::prelude::*;
 
     use lib::core::gc::mark_commit_reachable;
     use tracing::instrument;
@@ -57,7 +69,7 @@ mod graph {
     use lib::git::{Commit, Time};
     use lib::git::{NonZeroOid, Repo};
 
-    #[derive(Debug)]
+    #[derive(Clone, Debug)]
     pub struct AncestorInfo {
         pub oid: NonZeroOid,
         pub distance: usize,
@@ -69,7 +81,7 @@ mod graph {
         pub is_merge_child: bool,
     }
     /// Node contained in the smartlog commit graph.
-    #[derive(Debug)]
+    #[derive(Clone, Debug)]
     pub struct Node<'repo> {
         /// The underlying commit object.
         pub object: NodeObject<'repo>,
@@ -142,6 +154,31 @@ mod graph {
             commits.reverse();
             commits
         }
+
+        /// Get the graph consisting of only the given commits. Links to nodes
+        /// outside of it are dropped, so that nodes whose parents aren't
+        /// included are rendered as roots.
+        pub fn subgraph(&self, oids: &HashSet<NonZeroOid>) -> SmartlogGraph<'repo> {
+            let nodes = self
+                .nodes
+                .iter()
+                .filter(|(oid, _node)| oids.contains(oid))
+                .map(|(oid, node)| {
+                    let mut node = node.clone();
+                    node.parents.retain(|oid| oids.contains(oid));
+                    node.children.retain(|child| oids.contains(&child.oid));
+                    node.descendants
+                        .retain(|descendant| oids.contains(&descendant.oid));
+                    if let Some(AncestorInfo { oid, distance: _ }) = node.ancestor_info {
+                        if !oids.contains(&oid) {
+                            node.ancestor_info = None;
+                        }
+                    }
+                    (*oid, node)
+                })
+                .collect();
+            SmartlogGraph { nodes }
+        }
     }
 
     impl std::fmt::Debug for SmartlogGraph<'_> {
@@ -164,94 +201,133 @@ mod graph {
         commits: &CommitSet,
     ) -> eyre::Result<SmartlogGraph<'repo>> {
         let mut graph: HashMap<NonZeroOid, Node> = {
+            let (_effects, progress) = effects.start_operation(OperationType::GetMergeBase);
+            let commit_oids = dag.commit_set_to_vec(commits)?;
+            progress.notify_progress(0, commit_oids.len());
+
+            // The merge-base queries are independent of each other, and make up
+            // most of the time spent building the graph in large repositories,
+            // so they're run in parallel. Only the DAG is accessed from the
+            // worker threads, since `Repo` can't be shared between threads.
+            let vertex_oids: HashSet<NonZeroOid> = commit_oids
+                .into_par_iter()
+                .map(|commit_oid| -> eyre::Result<Vec<NonZeroOid>> {
+                    let vertex = CommitSet::from(commit_oid);
+                    let merge_bases = dag.query_gca_all(dag.main_branch_commit.union(&vertex))?;
+                    let vertices = vertex.union(&merge_bases);
+                    dag.commit_set_to_vec(&vertices)
+                })
+                .inspect(|_| progress.notify_progress_inc(1))
+                .collect::<eyre::Result<Vec<_>>>()?
+                .into_iter()
+                .flatten()
+                .collect();
+            let vertex_infos = vertex_oids
+                .into_par_iter()
+                .map(|oid| -> eyre::Result<(NonZeroOid, bool, bool)> {
+                    let is_main = dag.is_public_commit(oid)?;
+                    let is_obsolete = dag.set_contains(&dag.query_obsolete_commits(), oid)?;
+                    Ok((oid, is_main, is_obsolete))
+                })
+                .collect::<eyre::Result<Vec<_>>>()?;
+
             let mut result = HashMap::new();
-            for vertex in dag.commit_set_to_vec(commits)? {
-                let vertex = CommitSet::from(vertex);
-                let merge_bases = dag.query_gca_all(dag.main_branch_commit.union(&vertex))?;
-                let vertices = vertex.union(&merge_bases);
-
-                for oid in dag.commit_set_to_vec(&vertices)? {
-                    let object = match repo.find_commit(oid)? {
-                        Some(commit) => NodeObject::Commit { commit },
-                        None => {
-                            // Assume that this commit was garbage collected.
-                            NodeObject::GarbageCollected { oid }
-                        }
-                    };
+            for (oid, is_main, is_obsolete) in vertex_infos {
+                let object = match repo.find_commit(oid)? {
+                    Some(commit) => NodeObject::Commit { commit },
+                    None => {
+                        // Assume that this commit was garbage collected.
+                        NodeObject::GarbageCollected { oid }
+                    }
+                };
 
-                    result.insert(
-                        oid,
-                        Node {
-                            object,
-                            parents: Vec::new(),  // populated below
-                            children: Vec::new(), // populated below
-                            ancestor_info: None,
-                            descendants: Vec::new(), // populated below
-                            is_main: dag.is_public_commit(oid)?,
-                            is_obsolete: dag.set_contains(&dag.query_obsolete_commits(), oid)?,
-                            num_omitted_descendants: 0, // populated below
-                        },
-                    );
-                }
+                result.insert(
+                    oid,
+                    Node {
+                        object,
+                        parents: Vec::new(),  // populated below
+                        children: Vec::new(), // populated below
+                        ancestor_info: None,
+                        descendants: Vec::new(), // populated below
+                        is_main,
+                        is_obsolete,
+                        num_omitted_descendants: 0, // populated below
+                    },
+                );
             }
             result
         };
 
-        let mut immediate_links: Vec<(NonZeroOid, NonZeroOid, bool)> = Vec::new();
-        let mut non_immediate_links: Vec<(NonZeroOid, NonZeroOid, bool)> = Vec::new();
-
-        let non_main_node_oids =
-            graph
-                .iter()
-                .filter_map(|(child_oid, node)| if !node.is_main { Some(child_oid) } else { None });
+        let non_main_node_oids: Vec<NonZeroOid> = graph
+            .iter()
+            .filter(|(_, node)| !node.is_main)
+            .map(|(child_oid, _)| *child_oid)
+            .collect();
 
         let graph_vertices: CommitSet = graph.keys().cloned().collect();
-        for child_oid in non_main_node_oids {
-            let parent_vertices = dag.query_parent_names(CommitVertex::from(*child_oid))?;
-
-            // Find immediate parent-child links.
-            match parent_vertices.as_slice() {
-                [] => {}
-                [first_parent_vertex, merge_parent_vertices @ ..] => {
-                    if dag.set_contains(&graph_vertices, first_parent_vertex.clone())? {
-                        let first_parent_oid = NonZeroOid::try_from(first_parent_vertex.clone())?;
-                        immediate_links.push((*child_oid, first_parent_oid, false));
-                    }
-                    for merge_parent_vertex in merge_parent_vertices {
-                        if dag.set_contains(&graph_vertices, merge_parent_vertex.clone())? {
-                            let merge_parent_oid =
-                                NonZeroOid::try_from(merge_parent_vertex.clone())?;
-                            immediate_links.push((*child_oid, merge_parent_oid, true));
+        let links = non_main_node_oids
+            .into_par_iter()
+            .map(|child_oid| -> eyre::Result<_> {
+                let mut immediate_links: Vec<(NonZeroOid, NonZeroOid, bool)> = Vec::new();
+                let mut non_immediate_links: Vec<(NonZeroOid, NonZeroOid, bool)> = Vec::new();
+                let parent_vertices = dag.query_parent_names(CommitVertex::from(child_oid))?;
+
+                // Find immediate parent-child links.
+                match parent_vertices.as_slice() {
+                    [] => {}
+                    [first_parent_vertex, merge_parent_vertices @ ..] => {
+                        if dag.set_contains(&graph_vertices, first_parent_vertex.clone())? {
+                            let first_parent_oid =
+                                NonZeroOid::try_from(first_parent_vertex.clone())?;
+                            immediate_links.push((child_oid, first_parent_oid, false));
+                        }
+                        for merge_parent_vertex in merge_parent_vertices {
+                            if dag.set_contains(&graph_vertices, merge_parent_vertex.clone())? {
+                                let merge_parent_oid =
+                                    NonZeroOid::try_from(merge_parent_vertex.clone())?;
+                                immediate_links.push((child_oid, merge_parent_oid, true));
+                            }
                         }
                     }
                 }
-            }
 
-            // Find non-immediate ancestor links.
-            for excluded_parent_vertex in parent_vertices {
-                if dag.set_contains(&graph_vertices, excluded_parent_vertex.clone())? {
-                    continue;
-                }
+                // Find non-immediate ancestor links.
+                for excluded_parent_vertex in parent_vertices {
+                    if dag.set_contains(&graph_vertices, excluded_parent_vertex.clone())? {
+                        continue;
+                    }
 
-                // Find the nearest ancestor that is included in the graph and
-                // also on the same branch.
+                    // Find the nearest ancestor that is included in the graph and
+                    // also on the same branch.
 
-                let parent_set = CommitSet::from(excluded_parent_vertex);
-                let merge_base = dag.query_gca_one(dag.main_branch_commit.union(&parent_set))?;
+                    let parent_set = CommitSet::from(excluded_parent_vertex);
+                    let merge_base =
+                        dag.query_gca_one(dag.main_branch_commit.union(&parent_set))?;
 
-                let path_to_main_branch = match merge_base {
-                    Some(merge_base) => dag.query_range(CommitSet::from(merge_base), parent_set)?,
-                    None => CommitSet::empty(),
-                };
-                let nearest_branch_ancestor =
-                    dag.query_heads_ancestors(path_to_main_branch.intersection(&graph_vertices))?;
+                    let path_to_main_branch = match merge_base {
+                        Some(merge_base) => {
+                            dag.query_range(CommitSet::from(merge_base), parent_set)?
+                        }
+                        None => CommitSet::empty(),
+                    };
+                    let nearest_branch_ancestor = dag
+                        .query_heads_ancestors(path_to_main_branch.intersection(&graph_vertices))?;
 
-                let ancestor_oids = dag.commit_set_to_vec(&nearest_branch_ancestor)?;
-                for ancestor_oid in ancestor_oids.iter() {
-                    non_immediate_links.push((*ancestor_oid, *child_oid, false));
+                    let ancestor_oids = dag.commit_set_to_vec(&nearest_branch_ancestor)?;
+                    for ancestor_oid in ancestor_oids.iter() {
+                        non_immediate_links.push((*ancestor_oid, child_oid, false));
+                    }
                 }
-            }
-        }
+
+                Ok((immediate_links, non_immediate_links))
+            })
+            .collect::<eyre::Result<Vec<_>>>()?;
+        let (immediate_links, non_immediate_links): (Vec<_>, Vec<_>) = links.into_iter().unzip();
+        let immediate_links = immediate_links.into_iter().flatten().collect::<Vec<_>>();
+        let non_immediate_links = non_immediate_links
+            .into_iter()
+            .flatten()
+            .collect::<Vec<_>>();
 
         for (child_oid, parent_oid, is_merge_link) in immediate_links.iter() {
             graph.get_mut(child_oid).unwrap().parents.push(*parent_oid);
@@ -332,6 +408,80 @@ mod graph {
         }
     }
 
+    /// Reorder the children of each node in the graph according to `sort`.
+    /// The graph returned by [`make_smartlog_graph`] is already ordered
+    /// according to [`SmartlogSort::Topo`].
+    ///
+    /// With [`SmartlogSort::Date`] and [`SmartlogSort::Activity`], each child
+    /// is ordered by the latest time among itself and its descendants in the
+    /// graph, so that the most recently touched stack is rendered last.
+    #[instrument(skip(graph))]
+    pub fn sort_smartlog_graph(
+        graph: &mut SmartlogGraph,
+        event_replayer: &EventReplayer,
+        event_cursor: EventCursor,
+        sort: SmartlogSort,
+    ) -> eyre::Result<()> {
+        if sort == SmartlogSort::Topo {
+            return Ok(());
+        }
+
+        let mut node_times: HashMap<NonZeroOid, Option<SystemTime>> = HashMap::new();
+        for (oid, node) in graph.nodes.iter() {
+            let commit_time = match &node.object {
+                NodeObject::Commit { commit } => Some(commit.get_time().to_system_time()?),
+                NodeObject::GarbageCollected { oid: _ } => None,
+            };
+            let time = match sort {
+                SmartlogSort::Topo | SmartlogSort::Date => commit_time,
+                SmartlogSort::Activity => event_replayer
+                    .get_cursor_commit_latest_event(event_cursor, *oid)
+                    .map(|event| event.get_timestamp())
+                    .or(commit_time),
+            };
+            node_times.insert(*oid, time);
+        }
+
+        fn get_subtree_time(
+            graph: &SmartlogGraph,
+            node_times: &HashMap<NonZeroOid, Option<SystemTime>>,
+            subtree_times: &mut HashMap<NonZeroOid, Option<SystemTime>>,
+            oid: NonZeroOid,
+        ) -> Option<SystemTime> {
+            if let Some(time) = subtree_times.get(&oid) {
+                return *time;
+            }
+            let node = &graph.nodes[&oid];
+            let mut result = node_times[&oid];
+            for child in node.children.iter().chain(node.descendants.iter()) {
+                if graph.nodes.contains_key(&child.oid) {
+                    result = result.max(get_subtree_time(
+                        graph,
+                        node_times,
+                        subtree_times,
+                        child.oid,
+                    ));
+                }
+            }
+            subtree_times.insert(oid, result);
+            result
+        }
+
+        let mut subtree_times = HashMap::new();
+        for oid in graph.nodes.keys() {
+            get_subtree_time(graph, &node_times, &mut subtree_times, *oid);
+        }
+        for node in graph.nodes.values_mut() {
+            node.children.sort_by_key(
+                |ChildInfo {
+                     oid,
+                     is_merge_child,
+                 }| (subtree_times[oid], *is_merge_child, oid.to_string()),
+            );
+        }
+        Ok(())
+    }
+
     /// Construct the smartlog graph for the repo.
     #[instrument]
     pub fn make_smartlog_graph<'repo>(
@@ -367,6 +517,7 @@ mod render {
     use std::cmp::Ordering;
     use std::collections::HashSet;
     use std::convert::TryFrom;
+    use std::path::PathBuf;
 
     use cursive_core::theme::{BaseColor, Effect};
     use cursive_core::utils::markup::StyledString;
@@ -379,7 +530,7 @@ mod render {
     use lib::core::node_descriptors::{render_node_descriptors, NodeDescriptor};
     use lib::git::{NonZeroOid, Repo};
 
-    use git_branchless_opts::{ResolveRevsetOptions, Revset};
+    use git_branchless_opts::{ResolveRevsetOptions, Revset, SmartlogSort};
 
     use super::graph::{AncestorInfo, ChildInfo, SmartlogGraph};
 
@@ -730,6 +881,14 @@ mod render {
         /// as an offset from the current event.
         pub event_id: Option<isize>,
 
+        /// The point in time at which to show the smartlog, as either an event
+        /// ID or a date or time. Ignored if `event_id` is provided.
+        pub at: Option<String>,
+
+        /// Replay the event log from the database at this path instead of
+        /// the repository's own, without modifying it.
+        pub event_log_path: Option<PathBuf>,
+
         /// The commits to render. These commits, plus any related commits, will
         /// be rendered. If not provided, the user's default revset will be used
         /// instead.
@@ -741,7 +900,458 @@ mod render {
         /// Reverse the ordering of items in the smartlog output, list the most
         /// recent commits first.
         pub reverse: bool,
+
+        /// Group the draft commits by stack, with a header line for each
+        /// stack.
+        pub group_by_stack: bool,
+
+        /// Render only the header line of each stack. Implies
+        /// `group_by_stack`.
+        pub collapse: bool,
+
+        /// How to order stacks of commits which share a parent.
+        pub sort: SmartlogSort,
+
+        /// The maximum number of draft commits to render. If not provided,
+        /// the value of `branchless.smartlog.limit` will be used instead.
+        pub limit: Option<usize>,
+
+        /// Render all commits, even if a limit is configured.
+        pub no_limit: bool,
+
+        /// Pipe the output into the user's pager if `stdout` is a terminal.
+        pub paginate: bool,
+
+        /// Only render the stacks of draft commits which touch one of these
+        /// paths, relative to the working directory. If not provided, the
+        /// value of `branchless.smartlog.paths` will be used instead.
+        pub paths: Vec<PathBuf>,
+
+        /// Render the stacks of draft commits regardless of which paths they
+        /// touch, even if `branchless.smartlog.paths` is configured.
+        pub all_paths: bool,
+    }
+}
+
+/// Make a cursor for the given event ID. If negative, the event ID is treated as
+/// an offset from the current event.
+fn make_event_id_cursor(event_replayer: &EventReplayer, event_id: isize) -> EventCursor {
+    match event_id.cmp(&0) {
+        Ordering::Less => {
+            event_replayer.advance_cursor(event_replayer.make_default_cursor(), event_id)
+        }
+        Ordering::Equal | Ordering::Greater => event_replayer.make_cursor(event_id),
+    }
+}
+
+/// A stack of draft commits in the smartlog.
+#[derive(Debug)]
+struct DraftStack {
+    /// The commits in the stack.
+    commits: CommitSet,
+
+    /// Whether the stack contains `HEAD`.
+    contains_head: bool,
+
+    /// The latest commit time among the commits in the stack.
+    latest_time: Option<Time>,
+}
+
+/// Split `draft_commits` into stacks, each consisting of the descendants of
+/// one root which don't belong to an earlier stack. The stack containing
+/// `HEAD` comes first, followed by the others from most to least recently
+/// updated.
+#[instrument]
+fn find_draft_stacks(
+    repo: &Repo,
+    dag: &Dag,
+    draft_commits: &CommitSet,
+    head_oid: Option<NonZeroOid>,
+) -> eyre::Result<Vec<DraftStack>> {
+    let mut stacks = Vec::new();
+    let mut seen_commits = CommitSet::empty();
+    for root_oid in dag.commit_set_to_vec(&dag.query_roots(draft_commits.clone())?)? {
+        let stack = dag
+            .query_descendants(CommitSet::from(root_oid))?
+            .intersection(draft_commits)
+            .difference(&seen_commits);
+        seen_commits = seen_commits.union(&stack);
+        let contains_head = match head_oid {
+            Some(head_oid) => dag.set_contains(&stack, head_oid)?,
+            None => false,
+        };
+        let mut latest_time = None;
+        for commit_oid in dag.commit_set_to_vec(&stack)? {
+            let time = repo.find_commit_or_fail(commit_oid)?.get_time();
+            latest_time = latest_time.max(Some(time));
+        }
+        stacks.push(DraftStack {
+            commits: stack,
+            contains_head,
+            latest_time,
+        });
+    }
+    stacks.sort_by(|lhs, rhs| {
+        rhs.contains_head
+            .cmp(&lhs.contains_head)
+            .then_with(|| rhs.latest_time.cmp(&lhs.latest_time))
+    });
+    Ok(stacks)
+}
+
+/// Remove the least-recently-updated stacks of draft commits from `commits`
+/// until at most `limit` draft commits remain. The stack containing `HEAD` is
+/// always kept. Returns the remaining commits and the number of draft commits
+/// which were removed.
+#[instrument]
+fn fold_old_stacks(
+    repo: &Repo,
+    dag: &Dag,
+    commits: CommitSet,
+    limit: usize,
+    head_oid: Option<NonZeroOid>,
+) -> eyre::Result<(CommitSet, usize)> {
+    let draft_commits = commits.intersection(dag.query_draft_commits()?);
+    if dag.set_count(&draft_commits)? <= limit {
+        return Ok((commits, 0));
+    }
+
+    let stacks = find_draft_stacks(repo, dag, &draft_commits, head_oid)?;
+    let mut num_shown_commits = 0;
+    let mut folded_commits = CommitSet::empty();
+    for DraftStack {
+        commits: stack,
+        contains_head,
+        latest_time: _,
+    } in stacks
+    {
+        let num_commits = dag.set_count(&stack)?;
+        if contains_head
+            || (num_shown_commits + num_commits <= limit && dag.set_is_empty(&folded_commits)?)
+        {
+            num_shown_commits += num_commits;
+        } else {
+            folded_commits = folded_commits.union(&stack);
+        }
+    }
+
+    // Also remove the main branch commits which were only rendered as the
+    // bases of the folded stacks.
+    let remaining_commits = commits.difference(&folded_commits);
+    let remaining_commits = remove_orphaned_bases(
+        dag,
+        remaining_commits,
+        &draft_commits,
+        &folded_commits,
+        head_oid,
+    )?;
+    let num_folded_commits = dag.set_count(&folded_commits)?;
+    Ok((remaining_commits, num_folded_commits))
+}
+
+/// Remove the parents of `removed_commits` from `remaining_commits` if they
+/// were only going to be rendered as the bases of the removed commits.
+fn remove_orphaned_bases(
+    dag: &Dag,
+    remaining_commits: CommitSet,
+    draft_commits: &CommitSet,
+    removed_commits: &CommitSet,
+    head_oid: Option<NonZeroOid>,
+) -> eyre::Result<CommitSet> {
+    let kept_commits = dag
+        .query_ancestors(remaining_commits.intersection(draft_commits))?
+        .union(&dag.branch_commits)
+        .union(&head_oid.into_iter().collect());
+    let orphaned_commits = dag
+        .query_parents(removed_commits.clone())?
+        .intersection(&remaining_commits)
+        .difference(&kept_commits);
+    Ok(remaining_commits.difference(&orphaned_commits))
+}
+
+/// Convert the user-provided `paths`, which are relative to
+/// `working_directory`, into paths relative to the root of the repository.
+/// Returns `None` if one of the paths is outside of the repository.
+fn make_repo_relative_paths(
+    repo: &Repo,
+    working_directory: &Path,
+    paths: &[PathBuf],
+) -> eyre::Result<Option<Vec<PathBuf>>> {
+    let repo_root = match repo.get_working_copy_path() {
+        Some(repo_root) => std::fs::canonicalize(repo_root)?,
+        None => eyre::bail!("Cannot filter by paths in a bare repository"),
+    };
+    let working_directory = std::fs::canonicalize(working_directory)?;
+
+    let mut result = Vec::new();
+    for path in paths {
+        // Normalize the path lexically, since it may not exist in the current
+        // working copy.
+        let mut absolute_path = PathBuf::new();
+        for component in working_directory.join(path).components() {
+            match component {
+                Component::CurDir => {}
+                Component::ParentDir => {
+                    absolute_path.pop();
+                }
+                Component::Prefix(_) | Component::RootDir | Component::Normal(_) => {
+                    absolute_path.push(component)
+                }
+            }
+        }
+        match absolute_path.strip_prefix(&repo_root) {
+            Ok(relative_path) => result.push(relative_path.to_owned()),
+            Err(_) => return Ok(None),
+        }
+    }
+    Ok(Some(result))
+}
+
+/// A filter on the paths touched by a commit, relative to the root of the
+/// repository.
+#[derive(Debug)]
+enum PathFilter {
+    /// Match the given path and any paths under it.
+    Prefix(PathBuf),
+
+    /// Match paths according to the given glob pattern.
+    Glob(glob::Pattern),
+}
+
+impl PathFilter {
+    /// Parse a path filter from the configuration. Values which contain glob
+    /// metacharacters are treated as glob patterns.
+    fn from_config_value(value: &str) -> Result<Self, glob::PatternError> {
+        if value.contains(|c| matches!(c, '*' | '?' | '[')) {
+            Ok(Self::Glob(glob::Pattern::new(value)?))
+        } else {
+            Ok(Self::Prefix(PathBuf::from(value.trim_end_matches('/'))))
+        }
+    }
+
+    fn matches(&self, path: &Path) -> bool {
+        match self {
+            Self::Prefix(prefix) => prefix.as_os_str().is_empty() || path.starts_with(prefix),
+            Self::Glob(pattern) => pattern.matches_path(path),
+        }
+    }
+}
+
+/// Remove the stacks of draft commits from `commits` which don't touch any
+/// path matched by `path_filters`. The stack containing `HEAD` is always kept.
+#[instrument]
+fn filter_stacks_by_paths(
+    repo: &Repo,
+    dag: &Dag,
+    commits: CommitSet,
+    path_filters: &[PathFilter],
+    head_oid: Option<NonZeroOid>,
+) -> eyre::Result<CommitSet> {
+    let draft_commits = commits.intersection(dag.query_draft_commits()?);
+    let stacks = find_draft_stacks(repo, dag, &draft_commits, head_oid)?;
+    let mut removed_commits = CommitSet::empty();
+    for DraftStack {
+        commits: stack,
+        contains_head,
+        latest_time: _,
+    } in stacks
+    {
+        if contains_head {
+            continue;
+        }
+
+        let mut touches_paths = false;
+        for commit_oid in dag.commit_set_to_vec(&stack)? {
+            let commit = repo.find_commit_or_fail(commit_oid)?;
+            let touched_paths = repo.get_paths_touched_by_commit(&commit)?;
+            if touched_paths.iter().any(|touched_path| {
+                path_filters
+                    .iter()
+                    .any(|path_filter| path_filter.matches(touched_path))
+            }) {
+                touches_paths = true;
+                break;
+            }
+        }
+        if !touches_paths {
+            removed_commits = removed_commits.union(&stack);
+        }
+    }
+
+    let remaining_commits = commits.difference(&removed_commits);
+    remove_orphaned_bases(
+        dag,
+        remaining_commits,
+        &draft_commits,
+        &removed_commits,
+        head_oid,
+    )
+}
+
+/// Render a header line summarizing the given stack, such as `Stack foo, bar:
+/// 2 commits, last updated 3h ago`.
+#[instrument]
+fn render_stack_header(
+    repo: &Repo,
+    dag: &Dag,
+    references_snapshot: &RepoReferencesSnapshot,
+    stack: &DraftStack,
+) -> eyre::Result<StyledString> {
+    let mut branch_names = Vec::new();
+    for commit_oid in dag.commit_set_to_vec(&stack.commits)? {
+        if let Some(names) = references_snapshot.branch_oid_to_names.get(&commit_oid) {
+            branch_names.extend(
+                names
+                    .iter()
+                    .map(|name| CategorizedReferenceName::new(name).render_suffix()),
+            );
+        }
+    }
+    branch_names.sort_unstable();
+
+    let mut header = if branch_names.is_empty() {
+        "Stack with no branches".to_string()
+    } else {
+        format!("Stack {}", branch_names.join(", "))
+    };
+    write!(
+        header,
+        ": {}",
+        Pluralize {
+            determiner: None,
+            amount: dag.set_count(&stack.commits)?,
+            unit: ("commit", "commits"),
+        }
+    )?;
+    let now = SystemTime::now();
+    if let (true, Some(latest_time)) = (
+        RelativeTimeDescriptor::new(repo, now)?.is_enabled(),
+        &stack.latest_time,
+    ) {
+        write!(
+            header,
+            ", last updated {} ago",
+            RelativeTimeDescriptor::describe_time_delta(now, latest_time.to_system_time()?)?
+        )?;
+    }
+    Ok(StyledStringBuilder::new()
+        .append_styled(header, Effect::Bold)
+        .build())
+}
+
+/// Render the smartlog graph with each stack of draft commits rendered
+/// separately under a header line, after the rest of the graph. If `collapse`
+/// is set, only the header lines of the stacks are rendered. Returns the lines
+/// in the order they should be printed.
+#[instrument(skip(commit_descriptors, graph))]
+fn render_grouped_by_stack(
+    effects: &Effects,
+    repo: &Repo,
+    dag: &Dag,
+    graph: &SmartlogGraph,
+    references_snapshot: &RepoReferencesSnapshot,
+    commit_descriptors: &mut [&mut dyn NodeDescriptor],
+    reverse: bool,
+    collapse: bool,
+) -> eyre::Result<Vec<StyledString>> {
+    let graph_commits: CommitSet = graph.nodes.keys().copied().collect();
+    let draft_commits = graph_commits.intersection(dag.query_draft_commits()?);
+    let mut stacks = find_draft_stacks(repo, dag, &draft_commits, references_snapshot.head_oid)?;
+    // Render the most relevant stacks last, nearest to the prompt.
+    stacks.reverse();
+
+    let draft_oids: HashSet<NonZeroOid> =
+        dag.commit_set_to_vec(&draft_commits)?.into_iter().collect();
+    let other_oids: HashSet<NonZeroOid> = graph
+        .nodes
+        .keys()
+        .filter(|oid| !draft_oids.contains(oid))
+        .copied()
+        .collect();
+    let mut groups: Vec<(Option<StyledString>, Vec<StyledString>)> = vec![(
+        None,
+        render_graph(
+            effects,
+            repo,
+            dag,
+            &graph.subgraph(&other_oids),
+            references_snapshot.head_oid,
+            commit_descriptors,
+        )?,
+    )];
+    let mut headers = Vec::new();
+    for stack in stacks {
+        let header = render_stack_header(repo, dag, references_snapshot, &stack)?;
+        if collapse {
+            headers.push(header);
+            continue;
+        }
+        let stack_oids: HashSet<NonZeroOid> =
+            dag.commit_set_to_vec(&stack.commits)?.into_iter().collect();
+        let lines = render_graph(
+            effects,
+            repo,
+            dag,
+            &graph.subgraph(&stack_oids),
+            references_snapshot.head_oid,
+            commit_descriptors,
+        )?;
+        groups.push((Some(header), lines));
+    }
+    if !headers.is_empty() {
+        groups.push((None, headers));
     }
+
+    if reverse {
+        groups.reverse();
+    }
+    let mut result = Vec::new();
+    for (group_idx, (header, mut lines)) in groups.into_iter().enumerate() {
+        if group_idx > 0 {
+            result.push(StyledString::new());
+        }
+        // The header stays at the top of its group even when reversed.
+        result.extend(header);
+        if reverse {
+            lines.reverse();
+        }
+        result.extend(lines);
+    }
+    Ok(result)
+}
+
+/// Send `output` to the given pager, as Git would. If the pager can't be
+/// started, the output is written directly instead.
+#[instrument(skip(output))]
+fn write_to_pager(effects: &Effects, pager: &OsStr, output: &str) -> eyre::Result<()> {
+    let mut command = Command::new("sh");
+    command.arg("-c").arg(pager).stdin(Stdio::piped());
+    // Same defaults as Git, so that short output isn't paged and colors are
+    // preserved.
+    if std::env::var_os("LESS").is_none() {
+        command.env("LESS", "FRX");
+    }
+    if std::env::var_os("LV").is_none() {
+        command.env("LV", "-c");
+    }
+    let mut child = match command.spawn() {
+        Ok(child) => child,
+        Err(err) => {
+            warn!(?err, ?pager, "Could not start pager");
+            write!(effects.get_output_stream(), "{output}")?;
+            return Ok(());
+        }
+    };
+    if let Some(mut stdin) = child.stdin.take() {
+        match stdin.write_all(output.as_bytes()) {
+            Ok(()) => {}
+            // The user may quit the pager before reading all of the output.
+            Err(err) if err.kind() == std::io::ErrorKind::BrokenPipe => {}
+            Err(err) => return Err(err.into()),
+        }
+    }
+    child.wait()?;
+    Ok(())
 }
 
 /// Display a nice graph of commits you've recently worked on.
@@ -753,25 +1363,62 @@ pub fn smartlog(
 ) -> EyreExitOr<()> {
     let SmartlogOptions {
         event_id,
+        at,
+        event_log_path,
         revset,
         resolve_revset_options,
         reverse,
+        group_by_stack,
+        collapse,
+        sort,
+        limit,
+        no_limit,
+        paginate,
+        paths,
+        all_paths,
     } = options;
 
     let repo = Repo::from_dir(&git_run_info.working_directory)?;
     let head_info = repo.get_head_info()?;
     let conn = repo.get_db_conn()?;
     let event_log_db = EventLogDb::new(&conn)?;
-    let event_replayer = EventReplayer::from_event_log_db(effects, &repo, &event_log_db)?;
+    let event_replayer = match event_log_path {
+        Some(event_log_path) => {
+            if !event_log_path.is_file() {
+                writeln!(
+                    effects.get_error_stream(),
+                    "No event log database found at: {}",
+                    event_log_path.display()
+                )?;
+                return Ok(Err(ExitCode(1)));
+            }
+            let event_log_db = ReadOnlyEventLogDb::open(&event_log_path)?;
+            EventReplayer::from_event_log_db(effects, &repo, &event_log_db)?
+        }
+        None => EventReplayer::from_event_log_db(effects, &repo, &event_log_db)?,
+    };
     let (references_snapshot, event_cursor) = {
         let default_cursor = event_replayer.make_default_cursor();
-        match event_id {
+        let event_cursor = match (event_id, at) {
+            (None, None) => None,
+            (Some(event_id), _) => Some(make_event_id_cursor(&event_replayer, event_id)),
+            (None, Some(at)) => match at.parse::<isize>() {
+                Ok(event_id) => Some(make_event_id_cursor(&event_replayer, event_id)),
+                Err(_) => match parse_time(&at) {
+                    Some(time) => Some(event_replayer.make_transaction_cursor_at_time(time.into())),
+                    None => {
+                        writeln!(
+                            effects.get_error_stream(),
+                            "Could not parse event ID or time: {at}"
+                        )?;
+                        return Ok(Err(ExitCode(1)));
+                    }
+                },
+            },
+        };
+        match event_cursor {
             None => (repo.get_references_snapshot()?, default_cursor),
-            Some(event_id) => {
-                let event_cursor = match event_id.cmp(&0) {
-                    Ordering::Less => event_replayer.advance_cursor(default_cursor, event_id),
-                    Ordering::Equal | Ordering::Greater => event_replayer.make_cursor(event_id),
-                };
+            Some(event_cursor) => {
                 let references_snapshot =
                     event_replayer.get_references_snapshot(&repo, event_cursor)?;
                 (references_snapshot, event_cursor)
@@ -802,7 +1449,66 @@ pub fn smartlog(
             }
         };
 
-    let graph = make_smartlog_graph(
+    let path_filters = if !paths.is_empty() {
+        match make_repo_relative_paths(&repo, &git_run_info.working_directory, &paths)? {
+            Some(paths) => paths.into_iter().map(PathFilter::Prefix).collect(),
+            None => {
+                writeln!(
+                    effects.get_error_stream(),
+                    "Paths must be inside the repository: {}",
+                    paths
+                        .iter()
+                        .map(|path| path.display().to_string())
+                        .collect::<Vec<_>>()
+                        .join(", ")
+                )?;
+                return Ok(Err(ExitCode(1)));
+            }
+        }
+    } else if all_paths {
+        Vec::new()
+    } else {
+        let mut path_filters = Vec::new();
+        for value in get_smartlog_paths(&repo)? {
+            match PathFilter::from_config_value(&value) {
+                Ok(path_filter) => path_filters.push(path_filter),
+                Err(err) => {
+                    writeln!(
+                        effects.get_error_stream(),
+                        "Invalid path pattern in branchless.smartlog.paths: {value}: {err}"
+                    )?;
+                    return Ok(Err(ExitCode(1)));
+                }
+            }
+        }
+        path_filters
+    };
+    let commits = if path_filters.is_empty() {
+        commits
+    } else {
+        filter_stacks_by_paths(
+            &repo,
+            &dag,
+            commits,
+            &path_filters,
+            references_snapshot.head_oid,
+        )?
+    };
+
+    let limit = if no_limit {
+        None
+    } else {
+        match limit {
+            Some(limit) => Some(limit),
+            None => get_smartlog_limit(&repo)?,
+        }
+    };
+    let (commits, num_folded_commits) = match limit {
+        Some(limit) => fold_old_stacks(&repo, &dag, commits, limit, references_snapshot.head_oid)?,
+        None => (commits, 0),
+    };
+
+    let mut graph = make_smartlog_graph(
         effects,
         &repo,
         &dag,
@@ -810,41 +1516,97 @@ pub fn smartlog(
         event_cursor,
         &commits,
     )?;
-
-    let mut lines = render_graph(
-        &effects.reverse_order(reverse),
-        &repo,
-        &dag,
-        &graph,
-        references_snapshot.head_oid,
-        &mut [
-            &mut CommitOidDescriptor::new(true)?,
-            &mut RelativeTimeDescriptor::new(&repo, SystemTime::now())?,
-            &mut ObsolescenceExplanationDescriptor::new(
-                &event_replayer,
-                event_replayer.make_default_cursor(),
-            )?,
-            &mut BranchesDescriptor::new(
-                &repo,
-                &head_info,
-                &references_snapshot,
-                &Redactor::Disabled,
-            )?,
-            &mut DifferentialRevisionDescriptor::new(&repo, &Redactor::Disabled)?,
-            &mut CommitMessageDescriptor::new(&Redactor::Disabled)?,
-        ],
-    )?
-    .into_iter();
-    while let Some(line) = if reverse {
-        lines.next_back()
+    sort_smartlog_graph(&mut graph, &event_replayer, event_cursor, sort)?;
+
+    let commit_descriptors: &mut [&mut dyn NodeDescriptor] = &mut [
+        &mut CommitOidDescriptor::new(true)?,
+        &mut RelativeTimeDescriptor::new(&repo, SystemTime::now())?,
+        &mut ObsolescenceExplanationDescriptor::new(
+            &event_replayer,
+            event_replayer.make_default_cursor(),
+        )?,
+        &mut CopySourceDescriptor::new(&repo, &event_replayer, event_cursor)?,
+        &mut BranchesDescriptor::new(&repo, &head_info, &references_snapshot, &Redactor::Disabled)?,
+        &mut TagsDescriptor::new(&repo, &references_snapshot, &Redactor::Disabled)?,
+        &mut NicknameDescriptor::new(&repo, &dag, &commits)?,
+        &mut DifferentialRevisionDescriptor::new(&repo, &Redactor::Disabled)?,
+        &mut CommitMetadataDescriptor::new(&repo, &Redactor::Disabled)?,
+        &mut CommitMessageDescriptor::new(&Redactor::Disabled)?,
+    ];
+    let render_effects = effects.reverse_order(reverse);
+    let lines = if group_by_stack || collapse {
+        render_grouped_by_stack(
+            &render_effects,
+            &repo,
+            &dag,
+            &graph,
+            &references_snapshot,
+            commit_descriptors,
+            reverse,
+            collapse,
+        )?
     } else {
-        lines.next()
-    } {
-        writeln!(
-            effects.get_output_stream(),
-            "{}",
-            effects.get_glyphs().render(line)?
+        let mut lines = render_graph(
+            &render_effects,
+            &repo,
+            &dag,
+            &graph,
+            references_snapshot.head_oid,
+            commit_descriptors,
         )?;
+        if reverse {
+            lines.reverse();
+        }
+        lines
+    };
+
+    // Render into a buffer first, so that the output can be sent to a pager.
+    let mut output = String::new();
+    // The folded stacks are the least-recently-updated ones, so they would
+    // have been rendered at the top of the smartlog (or the bottom, if
+    // reversed).
+    let folded_line = if num_folded_commits > 0 {
+        let line = StyledStringBuilder::new()
+            .append_plain(effects.get_glyphs().vertical_ellipsis)
+            .append_plain(" ")
+            .append_styled(
+                format!(
+                    "and {} (use --no-limit to show all)",
+                    Pluralize {
+                        determiner: None,
+                        amount: num_folded_commits,
+                        unit: ("more commit", "more commits"),
+                    }
+                ),
+                Effect::Dim,
+            )
+            .build();
+        Some(effects.get_glyphs().render(line)?)
+    } else {
+        None
+    };
+    if let (false, Some(folded_line)) = (reverse, &folded_line) {
+        writeln!(output, "{folded_line}")?;
+    }
+    for line in lines {
+        writeln!(output, "{}", effects.get_glyphs().render(line)?)?;
+    }
+    if let (true, Some(folded_line)) = (reverse, &folded_line) {
+        writeln!(output, "{folded_line}")?;
+    }
+
+    let pager = if paginate && console::Term::stdout().is_term() {
+        // If Git is already paging our output, don't start another pager.
+        match std::env::var_os("GIT_PAGER_IN_USE") {
+            Some(_) => None,
+            None => get_pager(git_run_info, &repo)?,
+        }
+    } else {
+        None
+    };
+    match pager {
+        Some(pager) => write_to_pager(effects, &pager, &output)?,
+        None => write!(effects.get_output_stream(), "{output}")?,
     }
 
     if !resolve_revset_options.show_hidden_commits
@@ -886,6 +1648,20 @@ pub fn smartlog(
         }
     }
 
+    if repo.is_shallow() && get_hint_enabled(&repo, Hint::SmartlogShallowClone)? {
+        writeln!(
+            effects.get_output_stream(),
+            "{}: this repository is a shallow clone, so commits before the shallow boundary are not shown",
+            effects.get_glyphs().render(get_hint_string())?,
+        )?;
+        writeln!(
+            effects.get_output_stream(),
+            "{}: to fetch the full history, run: git fetch --unshallow",
+            effects.get_glyphs().render(get_hint_string())?,
+        )?;
+        print_hint_suppression_notice(effects, Hint::SmartlogShallowClone)?;
+    }
+
     Ok(Ok(()))
 }
 
@@ -898,9 +1674,19 @@ pub fn command_main(ctx: CommandContext, args: SmartlogArgs) -> EyreExitOr<()> {
     } = ctx;
     let SmartlogArgs {
         event_id,
+        at,
+        event_log,
         revset,
-        resolve_revset_options,
         reverse,
+        group_by_stack,
+        collapse,
+        sort,
+        limit,
+        no_limit,
+        no_pager,
+        resolve_revset_options,
+        paths,
+        all_paths,
     } = args;
 
     smartlog(
@@ -908,9 +1694,19 @@ pub fn command_main(ctx: CommandContext, args: SmartlogArgs) -> EyreExitOr<()> {
         &git_run_info,
         SmartlogOptions {
             event_id,
+            at,
+            event_log_path: event_log,
             revset,
             resolve_revset_options,
             reverse,
+            group_by_stack,
+            collapse,
+            sort,
+            limit,
+            no_limit,
+            paginate: !no_pager,
+            paths,
+            all_paths,
         },
     )
 }
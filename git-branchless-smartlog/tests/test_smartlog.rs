@@ -1,4 +1,8 @@
-use lib::testing::{extract_hint_command, make_git, GitRunOptions};
+use lib::core::nicknames::NicknameDb;
+use lib::testing::{
+    extract_hint_command, make_git, make_git_with_remote_repo, GitInitOptions, GitRunOptions,
+    GitWrapperWithRemoteRepo,
+};
 
 #[test]
 fn test_init_smartlog() -> eyre::Result<()> {
@@ -370,6 +374,31 @@ fn test_custom_main_branch() -> eyre::Result<()> {
     Ok(())
 }
 
+#[test]
+fn test_main_commit_pinned_to_tag() -> eyre::Result<()> {
+    let git = make_git()?;
+
+    git.init_repo()?;
+    git.commit_file("test1", 1)?;
+    git.run(&["tag", "v1"])?;
+    git.detach_head()?;
+    git.commit_file("test2", 2)?;
+    git.run(&["config", "branchless.core.mainCommit", "v1"])?;
+    git.run(&["branch", "-D", "master"])?;
+
+    {
+        let stdout = git.smartlog()?;
+        insta::assert_snapshot!(stdout, @r###"
+        :
+        O 62fc20d (tag v1) create test1.txt
+        |
+        @ 96d1c37 create test2.txt
+        "###);
+    }
+
+    Ok(())
+}
+
 #[test]
 fn test_show_rewritten_commit_hash() -> eyre::Result<()> {
     let git = make_git()?;
@@ -742,3 +771,528 @@ fn test_default_smartlog_revset() -> eyre::Result<()> {
 
     Ok(())
 }
+
+#[test]
+fn test_smartlog_commit_metadata_providers() -> eyre::Result<()> {
+    let git = make_git()?;
+    git.init_repo()?;
+
+    git.detach_head()?;
+    git.commit_file_with_contents_and_message("test1", 1, "test1 contents\n", "PROJ-123 create")?;
+    git.commit_file("test2", 2)?;
+
+    {
+        let smartlog = git.smartlog()?;
+        insta::assert_snapshot!(smartlog, @r###"
+        O f777ecc (master) create initial.txt
+        |
+        o 39276a8 PROJ-123 create test1.txt
+        |
+        @ e924232 create test2.txt
+        "###);
+    }
+
+    git.run(&[
+        "config",
+        "branchless.commitMetadata.providers",
+        "jira, nonexistent",
+    ])?;
+
+    {
+        let smartlog = git.smartlog()?;
+        insta::assert_snapshot!(smartlog, @r###"
+        O f777ecc (master) create initial.txt
+        |
+        o 39276a8 PROJ-123 PROJ-123 create test1.txt
+        |
+        @ e924232 create test2.txt
+        "###);
+    }
+
+    Ok(())
+}
+
+#[test]
+fn test_smartlog_shallow_clone() -> eyre::Result<()> {
+    let GitWrapperWithRemoteRepo {
+        temp_dir: _guard,
+        original_repo,
+        cloned_repo,
+    } = make_git_with_remote_repo()?;
+
+    original_repo.init_repo()?;
+    original_repo.commit_file("test1", 1)?;
+    original_repo.commit_file("test2", 2)?;
+
+    original_repo.clone_repo_into(&cloned_repo, &["--branch", "master", "--depth", "1"])?;
+    cloned_repo.init_repo_with_options(&GitInitOptions {
+        make_initial_commit: false,
+        ..Default::default()
+    })?;
+    cloned_repo.detach_head()?;
+    cloned_repo.commit_file("test3", 3)?;
+
+    {
+        let (stdout, _stderr) = cloned_repo.branchless("smartlog", &[])?;
+        assert!(stdout.contains("96d1c37 (master) create test2.txt"));
+        assert!(stdout.contains("@ 70deb1e create test3.txt"));
+        assert!(!stdout.contains("create test1.txt"));
+        assert!(stdout.contains(
+            "hint: this repository is a shallow clone, so commits before the shallow boundary are not shown\n\
+            hint: to fetch the full history, run: git fetch --unshallow\n\
+            hint: disable this hint by running: git config --global branchless.hint.smartlogShallowClone false\n"
+        ));
+    }
+
+    Ok(())
+}
+
+#[test]
+fn test_smartlog_limit() -> eyre::Result<()> {
+    let git = make_git()?;
+    git.init_repo()?;
+
+    git.detach_head()?;
+    git.commit_file("test1", 1)?;
+    git.commit_file("test2", 2)?;
+    git.run(&["checkout", "master"])?;
+    git.detach_head()?;
+    git.commit_file("test3", 3)?;
+    git.commit_file("test4", 4)?;
+
+    {
+        let (stdout, _stderr) = git.branchless("smartlog", &["--limit", "2"])?;
+        assert!(stdout.starts_with(": and 2 more commits (use --no-limit to show all)\n"));
+        assert!(!stdout.contains("create test1.txt"));
+        assert!(!stdout.contains("create test2.txt"));
+        assert!(stdout.contains("create test3.txt"));
+        assert!(stdout.contains("create test4.txt"));
+    }
+
+    {
+        let (stdout, _stderr) = git.branchless("smartlog", &["--limit", "2", "--reverse"])?;
+        assert!(stdout.ends_with(": and 2 more commits (use --no-limit to show all)\n"));
+    }
+
+    git.run(&["config", "branchless.smartlog.limit", "3"])?;
+    {
+        let (stdout, _stderr) = git.branchless("smartlog", &[])?;
+        assert!(stdout.contains("and 2 more commits"));
+        assert!(!stdout.contains("create test1.txt"));
+    }
+
+    {
+        let (stdout, _stderr) = git.branchless("smartlog", &["--no-limit"])?;
+        assert!(!stdout.contains("more commits"));
+        assert!(stdout.contains("create test1.txt"));
+        assert!(stdout.contains("create test2.txt"));
+    }
+
+    Ok(())
+}
+
+#[test]
+fn test_smartlog_paths() -> eyre::Result<()> {
+    let git = make_git()?;
+    git.init_repo()?;
+
+    git.detach_head()?;
+    git.write_file("project/foo.txt", "foo\n")?;
+    git.run(&["add", "."])?;
+    git.run(&["commit", "-m", "create project/foo.txt"])?;
+    git.commit_file("test1", 1)?;
+    git.run(&["checkout", "master"])?;
+    git.detach_head()?;
+    git.commit_file("test2", 2)?;
+    git.run(&["checkout", "master"])?;
+
+    {
+        let (stdout, _stderr) = git.branchless("smartlog", &["--", "project"])?;
+        assert!(stdout.contains("create project/foo.txt"), "{stdout}");
+        assert!(stdout.contains("create test1.txt"), "{stdout}");
+        assert!(!stdout.contains("create test2.txt"), "{stdout}");
+    }
+
+    {
+        let (stdout, _stderr) = git.branchless("smartlog", &["--", "test2.txt"])?;
+        assert!(!stdout.contains("create project/foo.txt"), "{stdout}");
+        assert!(stdout.contains("create test2.txt"), "{stdout}");
+    }
+
+    {
+        let (_stdout, stderr) = git.branchless_with_options(
+            "smartlog",
+            &["--", "../outside"],
+            &GitRunOptions {
+                expected_exit_code: 1,
+                ..Default::default()
+            },
+        )?;
+        assert!(
+            stderr.contains("Paths must be inside the repository: ../outside"),
+            "{stderr}"
+        );
+    }
+
+    Ok(())
+}
+
+#[test]
+fn test_smartlog_paths_config() -> eyre::Result<()> {
+    let git = make_git()?;
+    git.init_repo()?;
+
+    git.detach_head()?;
+    git.write_file("services/foo/src/main.txt", "foo\n")?;
+    git.run(&["add", "."])?;
+    git.run(&["commit", "-m", "create services/foo/src/main.txt"])?;
+    git.run(&["checkout", "master"])?;
+    git.detach_head()?;
+    git.write_file("services/bar/main.txt", "bar\n")?;
+    git.run(&["add", "."])?;
+    git.run(&["commit", "-m", "create services/bar/main.txt"])?;
+    git.run(&["checkout", "master"])?;
+
+    git.run(&["config", "branchless.smartlog.paths", "services/foo/**"])?;
+    {
+        let (stdout, _stderr) = git.branchless("smartlog", &[])?;
+        assert!(
+            stdout.contains("create services/foo/src/main.txt"),
+            "{stdout}"
+        );
+        assert!(!stdout.contains("create services/bar/main.txt"), "{stdout}");
+    }
+
+    {
+        let (stdout, _stderr) = git.branchless("smartlog", &["--all-paths"])?;
+        assert!(
+            stdout.contains("create services/foo/src/main.txt"),
+            "{stdout}"
+        );
+        assert!(stdout.contains("create services/bar/main.txt"), "{stdout}");
+    }
+
+    {
+        let (stdout, _stderr) = git.branchless("smartlog", &["--", "services/bar"])?;
+        assert!(
+            !stdout.contains("create services/foo/src/main.txt"),
+            "{stdout}"
+        );
+        assert!(stdout.contains("create services/bar/main.txt"), "{stdout}");
+    }
+
+    Ok(())
+}
+
+#[test]
+fn test_smartlog_color() -> eyre::Result<()> {
+    let git = make_git()?;
+    git.init_repo()?;
+    git.commit_file("test1", 1)?;
+
+    {
+        let (stdout, _stderr) = git.branchless("smartlog", &["--color", "always"])?;
+        assert!(stdout.contains('\u{1b}'));
+    }
+
+    {
+        let (stdout, _stderr) = git.branchless_with_options(
+            "smartlog",
+            &[],
+            &GitRunOptions {
+                env: [
+                    ("CLICOLOR_FORCE".to_string(), "1".to_string()),
+                    ("NO_COLOR".to_string(), "1".to_string()),
+                ]
+                .into_iter()
+                .collect(),
+                ..Default::default()
+            },
+        )?;
+        assert!(!stdout.contains('\u{1b}'));
+    }
+
+    {
+        let (stdout, _stderr) = git.branchless("smartlog", &["--color", "never"])?;
+        assert!(!stdout.contains('\u{1b}'));
+    }
+
+    Ok(())
+}
+
+#[test]
+fn test_smartlog_nicknames() -> eyre::Result<()> {
+    let git = make_git()?;
+    git.init_repo()?;
+    git.run(&["config", "branchless.commitDescriptors.nicknames", "true"])?;
+    git.detach_head()?;
+    git.commit_file("test1", 1)?;
+    let test2_oid = git.commit_file("test2", 2)?;
+
+    let stdout = git.smartlog()?;
+    let nickname = {
+        let repo = git.get_repo()?;
+        let conn = repo.get_db_conn()?;
+        let nickname_db = NicknameDb::new(&conn)?;
+        nickname_db.get_nickname(test2_oid)?.unwrap()
+    };
+    assert!(stdout.contains(&format!("96d1c37 {nickname} create test2.txt")));
+
+    {
+        let (stdout, _stderr) = git.branchless("query", &[&nickname])?;
+        insta::assert_snapshot!(stdout, @"96d1c37 create test2.txt
+");
+    }
+
+    git.run(&["commit", "--amend", "-m", "amended test2"])?;
+    {
+        let repo = git.get_repo()?;
+        let head_oid = repo.get_head_info()?.oid.unwrap();
+        let conn = repo.get_db_conn()?;
+        let nickname_db = NicknameDb::new(&conn)?;
+        assert_eq!(nickname_db.get_nickname(head_oid)?, Some(nickname));
+    }
+
+    Ok(())
+}
+
+#[test]
+fn test_smartlog_at() -> eyre::Result<()> {
+    let git = make_git()?;
+
+    git.init_repo()?;
+    git.detach_head()?;
+    git.commit_file("test1", 1)?;
+    git.run(&["branch", "foo"])?;
+    git.run(&["branch", "-D", "foo"])?;
+
+    {
+        let (stdout, _stderr) = git.branchless("smartlog", &["--at=-1"])?;
+        insta::assert_snapshot!(stdout, @r###"
+        O f777ecc (master) create initial.txt
+        |
+        @ 62fc20d (foo) create test1.txt
+        "###);
+    }
+
+    {
+        let (stdout, _stderr) = git.branchless("smartlog", &["--at", "tomorrow"])?;
+        insta::assert_snapshot!(stdout, @r###"
+        O f777ecc (master) create initial.txt
+        |
+        @ 62fc20d create test1.txt
+        "###);
+    }
+
+    {
+        let (_stdout, stderr) = git.branchless_with_options(
+            "smartlog",
+            &["--at", "not a time"],
+            &GitRunOptions {
+                expected_exit_code: 1,
+                ..Default::default()
+            },
+        )?;
+        insta::assert_snapshot!(stderr, @"Could not parse event ID or time: not a time
+");
+    }
+
+    Ok(())
+}
+
+#[test]
+fn test_smartlog_event_log() -> eyre::Result<()> {
+    let git = make_git()?;
+
+    git.init_repo()?;
+    git.detach_head()?;
+    git.commit_file("test1", 1)?;
+    git.run(&["checkout", "master"])?;
+
+    let branchless_dir = git.repo_path.join(".git").join("branchless");
+    let backup_path = branchless_dir.join("backup.sqlite3");
+    std::fs::copy(branchless_dir.join("db.sqlite3"), &backup_path)?;
+    git.branchless("hide", &["62fc20d"])?;
+
+    {
+        let stdout = git.smartlog()?;
+        insta::assert_snapshot!(stdout, @"@ f777ecc (> master) create initial.txt
+");
+    }
+
+    {
+        let (stdout, _stderr) =
+            git.branchless("smartlog", &["--event-log", backup_path.to_str().unwrap()])?;
+        insta::assert_snapshot!(stdout, @r###"
+        @ f777ecc (> master) create initial.txt
+        |
+        o 62fc20d create test1.txt
+        "###);
+    }
+
+    {
+        let (_stdout, stderr) = git.branchless_with_options(
+            "smartlog",
+            &["--event-log", "nonexistent.sqlite3"],
+            &GitRunOptions {
+                expected_exit_code: 1,
+                ..Default::default()
+            },
+        )?;
+        insta::assert_snapshot!(stderr, @"No event log database found at: nonexistent.sqlite3
+");
+    }
+
+    Ok(())
+}
+
+#[test]
+fn test_smartlog_group_by_stack() -> eyre::Result<()> {
+    let git = make_git()?;
+
+    git.init_repo()?;
+    git.detach_head()?;
+    git.commit_file("test1", 1)?;
+    git.run(&["branch", "foo"])?;
+    git.run(&["checkout", "master"])?;
+    git.detach_head()?;
+    git.commit_file("test2", 2)?;
+
+    {
+        let (stdout, _stderr) = git.branchless("smartlog", &["--group-by-stack"])?;
+        insta::assert_snapshot!(stdout, @r###"
+        O f777ecc (master) create initial.txt
+
+        Stack foo: 1 commit
+        :
+        o 62fc20d (foo) create test1.txt
+
+        Stack with no branches: 1 commit
+        :
+        @ fe65c1f create test2.txt
+        "###);
+    }
+
+    {
+        let (stdout, _stderr) = git.branchless("smartlog", &["--collapse"])?;
+        insta::assert_snapshot!(stdout, @r###"
+        O f777ecc (master) create initial.txt
+
+        Stack foo: 1 commit
+        Stack with no branches: 1 commit
+        "###);
+    }
+
+    Ok(())
+}
+
+#[test]
+fn test_smartlog_sort() -> eyre::Result<()> {
+    let git = make_git()?;
+
+    git.init_repo()?;
+    git.detach_head()?;
+    git.commit_file("test1", 1)?;
+    git.run(&["checkout", "master"])?;
+    git.detach_head()?;
+    git.commit_file("test2", 2)?;
+    git.branchless("hide", &["62fc20d"])?;
+    git.branchless("unhide", &["62fc20d"])?;
+
+    {
+        let (stdout, _stderr) = git.branchless("smartlog", &["--sort", "topo"])?;
+        insta::assert_snapshot!(stdout, @r###"
+        O f777ecc (master) create initial.txt
+        |\
+        | o 62fc20d create test1.txt
+        |
+        @ fe65c1f create test2.txt
+        "###);
+    }
+
+    {
+        let (stdout, _stderr) = git.branchless("smartlog", &["--sort", "activity"])?;
+        insta::assert_snapshot!(stdout, @r###"
+        O f777ecc (master) create initial.txt
+        |\
+        | @ fe65c1f create test2.txt
+        |
+        o 62fc20d create test1.txt
+        "###);
+    }
+
+    Ok(())
+}
+
+#[test]
+fn test_smartlog_corrupt_commit_graph() -> eyre::Result<()> {
+    let git = make_git()?;
+
+    git.init_repo()?;
+    git.commit_file("test1", 1)?;
+    std::fs::write(
+        git.repo_path
+            .join(".git")
+            .join("objects")
+            .join("info")
+            .join("commit-graph"),
+        "not a commit-graph",
+    )?;
+    git.detach_head()?;
+    git.commit_file("test2", 2)?;
+
+    {
+        let (stdout, stderr) = git.branchless("smartlog", &[])?;
+        assert!(
+            stderr.contains("Warning: ignoring the commit-graph: could not read commit-graph"),
+            "{stderr}"
+        );
+        insta::assert_snapshot!(stdout, @r###"
+        :
+        O 62fc20d (master) create test1.txt
+        |
+        @ 96d1c37 create test2.txt
+        "###);
+    }
+
+    Ok(())
+}
+
+#[test]
+fn test_smartlog_copy_sources() -> eyre::Result<()> {
+    let git = make_git()?;
+
+    git.init_repo()?;
+    git.run(&["config", "branchless.commitDescriptors.copySources", "true"])?;
+    git.detach_head()?;
+    let test1_oid = git.commit_file("test1", 1)?;
+    git.commit_file("test2", 2)?;
+
+    // Reverting several commits goes through the sequencer, a plain
+    // cherry-pick leaves only `CHERRY_PICK_HEAD`, and a single revert is only
+    // identified by its message.
+    git.run(&["revert", "--no-edit", "HEAD", "HEAD~"])?;
+    git.run(&["cherry-pick", &test1_oid.to_string()])?;
+    git.run(&["revert", "--no-edit", "HEAD"])?;
+
+    {
+        let stdout = git.smartlog()?;
+        insta::assert_snapshot!(stdout, @r###"
+        O f777ecc (master) create initial.txt
+        |
+        o 62fc20d create test1.txt
+        |
+        o 96d1c37 create test2.txt
+        |
+        o 5b7ca88 (reverts 96d1c37a) Revert "create test2.txt"
+        |
+        o 023008d (reverts 62fc20d2) Revert "create test1.txt"
+        |
+        o b61db50 (copied from 62fc20d2) create test1.txt
+        |
+        @ bb03986 (reverts b61db50a) Revert "create test1.txt"
+        "###);
+    }
+
+    Ok(())
+}
@@ -3,13 +3,17 @@ use std::fmt::Write;
 use std::time::SystemTime;
 
 use itertools::Itertools;
-use lib::core::config::get_main_branch_name;
+use lib::core::config::{
+    get_main_branch_name, get_submit_branch_name_template, get_submit_push_unchanged_commits,
+};
 use lib::core::dag::{CommitSet, Dag};
 use lib::core::effects::{Effects, OperationType};
-use lib::core::eventlog::EventLogDb;
+use lib::core::eventlog::{Event, EventLogDb, EventTransactionId};
+use lib::core::formatting::Pluralize;
 use lib::core::repo_ext::{RepoExt, RepoReferencesSnapshot};
 use lib::git::{
-    Branch, BranchType, CategorizedReferenceName, GitRunInfo, NonZeroOid, ReferenceName, Repo,
+    Branch, BranchType, CategorizedReferenceName, ConfigRead, GitRunInfo, MaybeZeroOid, NonZeroOid,
+    ReferenceName, Repo,
 };
 use lib::try_exit_code;
 use lib::util::{ExitCode, EyreExitOr};
@@ -17,6 +21,59 @@ use tracing::warn;
 
 use crate::{CommitStatus, CreateStatus, Forge, SubmitOptions, SubmitStatus};
 
+/// Render the name of the branch to create for a commit which doesn't have
+/// one. See `branchless.submit.branchNameTemplate`.
+fn render_branch_name_template(
+    repo: &Repo,
+    template: &str,
+    commit_oid: NonZeroOid,
+) -> eyre::Result<String> {
+    let mut result = template.to_owned();
+    if result.contains("{user}") {
+        let email: Option<String> = repo.get_readonly_config()?.get("user.email")?;
+        let user = match email {
+            Some(email) => email.split('@').next().unwrap_or_default().to_owned(),
+            None => eyre::bail!(
+                "`branchless.submit.branchNameTemplate` uses `{{user}}`, but `user.email` is not set"
+            ),
+        };
+        result = result.replace("{user}", &user);
+    }
+    let commit = repo.find_commit_or_fail(commit_oid)?;
+    result = result
+        .replace("{short_oid}", &commit.get_short_oid()?)
+        .replace("{oid}", &commit_oid.to_string());
+    Ok(result)
+}
+
+/// Find the commits on the remote branch which aren't included in the local
+/// branch, from newest to oldest, following first parents.
+fn find_remote_only_commits(
+    repo: &Repo,
+    local_oid: NonZeroOid,
+    remote_oid: NonZeroOid,
+) -> eyre::Result<Vec<NonZeroOid>> {
+    let merge_base_oid = repo.find_merge_base(local_oid, remote_oid)?;
+    let mut result = Vec::new();
+    let mut current_oid = Some(remote_oid);
+    while let Some(commit_oid) = current_oid {
+        if Some(commit_oid) == merge_base_oid {
+            break;
+        }
+        result.push(commit_oid);
+        current_oid = repo
+            .find_commit_or_fail(commit_oid)?
+            .get_parent_oids()
+            .first()
+            .copied();
+    }
+    Ok(result)
+}
+
+/// The messages of the event log transactions in which `git submit` pushes
+/// branches.
+const PUSH_TRANSACTION_MESSAGES: &[&str] = &["submit", "submit unsubmitted commits"];
+
 #[derive(Debug)]
 pub struct BranchForge<'a> {
     pub effects: &'a Effects,
@@ -25,6 +82,48 @@ pub struct BranchForge<'a> {
     pub dag: &'a Dag,
     pub event_log_db: &'a EventLogDb<'a>,
     pub references_snapshot: &'a RepoReferencesSnapshot,
+
+    /// Whether to overwrite remote branches which were updated since they
+    /// were last pushed.
+    pub force: bool,
+
+    /// For each local branch, the OID which its remote branch is expected to
+    /// still point to when it's pushed. This is populated by `query_status`.
+    pub expected_remote_oids: HashMap<String, NonZeroOid>,
+}
+
+impl BranchForge<'_> {
+    /// Whether the local commit differs from the remote commit only in its
+    /// base, as determined by their patch IDs and messages. This is only
+    /// checked for commits directly on top of a public commit, i.e. when the
+    /// main branch moved underneath them. If the commit is stacked on top of
+    /// draft commits instead, then the remote branch would be left pointing
+    /// to the old versions of those commits, so it still needs to be pushed.
+    fn is_only_base_changed(
+        &self,
+        local_oid: NonZeroOid,
+        remote_oid: NonZeroOid,
+    ) -> eyre::Result<bool> {
+        let local_commit = self.repo.find_commit_or_fail(local_oid)?;
+        let remote_commit = match self.repo.find_commit(remote_oid)? {
+            Some(remote_commit) => remote_commit,
+            None => return Ok(false),
+        };
+        let is_on_public_commit = match local_commit.get_parent_oids().as_slice() {
+            [parent_oid] => self.dag.is_public_commit(*parent_oid)?,
+            _ => false,
+        };
+        if !is_on_public_commit
+            || remote_commit.get_parent_count() != 1
+            || local_commit.get_message_raw() != remote_commit.get_message_raw()
+        {
+            return Ok(false);
+        }
+
+        let local_patch_id = self.repo.get_patch_id(self.effects, &local_commit)?;
+        let remote_patch_id = self.repo.get_patch_id(self.effects, &remote_commit)?;
+        Ok(local_patch_id.is_some() && local_patch_id == remote_patch_id)
+    }
 }
 
 impl Forge for BranchForge<'_> {
@@ -59,6 +158,7 @@ impl Forge for BranchForge<'_> {
                 let branch_name = match CategorizedReferenceName::new(&branch_reference_name) {
                     name @ CategorizedReferenceName::LocalBranch { .. } => name.render_suffix(),
                     CategorizedReferenceName::RemoteBranch { .. }
+                    | CategorizedReferenceName::Tag { .. }
                     | CategorizedReferenceName::OtherRef { .. } => continue,
                 };
                 if branch_name == main_branch_name {
@@ -80,6 +180,65 @@ impl Forge for BranchForge<'_> {
             branch_infos
         };
 
+        // Before fetching, find the state of each remote branch as we last
+        // pushed it, so that we can tell whether somebody else has updated it
+        // since. This is its most recent update in the event log made by
+        // `git submit`, or its current value if it was never pushed by us.
+        let last_pushed_remote_oids: HashMap<ReferenceName, Option<NonZeroOid>> = {
+            let mut last_pushed_remote_oids = HashMap::new();
+            for branch_info in branch_infos.values() {
+                if let Some(upstream_branch) = branch_info.branch.get_upstream_branch()? {
+                    last_pushed_remote_oids.insert(
+                        upstream_branch.get_reference_name()?,
+                        upstream_branch.get_oid()?,
+                    );
+                }
+            }
+            if !last_pushed_remote_oids.is_empty() {
+                let mut is_push_transaction: HashMap<EventTransactionId, bool> = HashMap::new();
+                for event in self.event_log_db.get_events()? {
+                    if let Event::RefUpdateEvent {
+                        timestamp: _,
+                        event_tx_id,
+                        ref_name,
+                        old_oid: _,
+                        new_oid,
+                        message: _,
+                    } = event
+                    {
+                        let oid = match last_pushed_remote_oids.get_mut(&ref_name) {
+                            Some(oid) => oid,
+                            None => continue,
+                        };
+                        let is_push = match is_push_transaction.get(&event_tx_id) {
+                            Some(is_push) => *is_push,
+                            None => {
+                                let is_push = match event_tx_id {
+                                    EventTransactionId::Id(_) => PUSH_TRANSACTION_MESSAGES
+                                        .contains(
+                                            &self
+                                                .event_log_db
+                                                .get_transaction_message(event_tx_id)?
+                                                .as_str(),
+                                        ),
+                                    EventTransactionId::Suppressed => false,
+                                };
+                                is_push_transaction.insert(event_tx_id, is_push);
+                                is_push
+                            }
+                        };
+                        if is_push {
+                            *oid = match new_oid {
+                                MaybeZeroOid::NonZero(new_oid) => Some(new_oid),
+                                MaybeZeroOid::Zero => None,
+                            };
+                        }
+                    }
+                }
+            }
+            last_pushed_remote_oids
+        };
+
         // Fetch latest branches so that we know which commits are out-of-date
         // and need to be pushed.
         let event_tx_id = self
@@ -132,7 +291,9 @@ impl Forge for BranchForge<'_> {
         }
 
         // Determine status of each commit/branch.
+        let push_unchanged_commits = get_submit_push_unchanged_commits(self.repo)?;
         let mut commit_statuses = HashMap::new();
+        let mut updated_remote_branches = Vec::new();
         for (commit_oid, branches) in &self.references_snapshot.branch_oid_to_names {
             let branch_infos = branches
                 .iter()
@@ -160,16 +321,53 @@ impl Forge for BranchForge<'_> {
                         remote_branch_name: None,
                     },
 
-                    Some(upstream_branch) => CommitStatus {
-                        submit_status: if branch.get_oid()? == upstream_branch.get_oid()? {
-                            SubmitStatus::UpToDate
-                        } else {
-                            SubmitStatus::NeedsUpdate
-                        },
-                        remote_name: remote_name.clone(),
-                        local_branch_name: Some(branch_name.clone()),
-                        remote_branch_name: Some(upstream_branch.get_name()?.to_owned()),
-                    },
+                    Some(upstream_branch) => {
+                        // Like `push.useForceIfIncludes`, only overwrite the
+                        // remote branch if it's still in the state we last
+                        // pushed, or if the local branch already includes its
+                        // changes.
+                        if let (Some(local_oid), Some(remote_oid)) =
+                            (branch.get_oid()?, upstream_branch.get_oid()?)
+                        {
+                            let last_pushed_remote_oid = last_pushed_remote_oids
+                                .get(&upstream_branch.get_reference_name()?)
+                                .copied()
+                                .flatten();
+                            if !self.force
+                                && last_pushed_remote_oid != Some(remote_oid)
+                                && self.repo.find_merge_base(local_oid, remote_oid)?
+                                    != Some(remote_oid)
+                            {
+                                updated_remote_branches.push((
+                                    branch_name.clone(),
+                                    upstream_branch.get_name()?.to_owned(),
+                                    local_oid,
+                                    remote_oid,
+                                ));
+                            }
+                            self.expected_remote_oids
+                                .insert(branch_name.clone(), remote_oid);
+                        }
+
+                        let submit_status = match (branch.get_oid()?, upstream_branch.get_oid()?) {
+                            (local_oid, remote_oid) if local_oid == remote_oid => {
+                                SubmitStatus::UpToDate
+                            }
+                            (Some(local_oid), Some(remote_oid))
+                                if !push_unchanged_commits
+                                    && self.is_only_base_changed(local_oid, remote_oid)? =>
+                            {
+                                SubmitStatus::OnlyBaseChanged
+                            }
+                            _ => SubmitStatus::NeedsUpdate,
+                        };
+                        CommitStatus {
+                            submit_status,
+                            remote_name: remote_name.clone(),
+                            local_branch_name: Some(branch_name.clone()),
+                            remote_branch_name: Some(upstream_branch.get_name()?.to_owned()),
+                        }
+                    }
                 },
 
                 _branch_infos => CommitStatus {
@@ -182,6 +380,71 @@ impl Forge for BranchForge<'_> {
             commit_statuses.insert(*commit_oid, commit_status);
         }
 
+        if !updated_remote_branches.is_empty() {
+            updated_remote_branches.sort();
+            for (branch_name, remote_branch_name, local_oid, remote_oid) in updated_remote_branches
+            {
+                let remote_only_commit_oids =
+                    find_remote_only_commits(self.repo, local_oid, remote_oid)?;
+                writeln!(
+                    self.effects.get_output_stream(),
+                    "Branch {branch_name} was updated on the remote since it was last pushed. {remote_branch_name} has {} which {} not in {branch_name}:",
+                    Pluralize {
+                        determiner: None,
+                        amount: remote_only_commit_oids.len(),
+                        unit: ("commit", "commits"),
+                    },
+                    if remote_only_commit_oids.len() == 1 {
+                        "is"
+                    } else {
+                        "are"
+                    },
+                )?;
+                for commit_oid in remote_only_commit_oids {
+                    writeln!(
+                        self.effects.get_output_stream(),
+                        "  {}",
+                        self.effects.get_glyphs().render(
+                            self.repo.friendly_describe_commit_from_oid(
+                                self.effects.get_glyphs(),
+                                commit_oid
+                            )?
+                        )?
+                    )?;
+                }
+            }
+            writeln!(
+                self.effects.get_output_stream(),
+                "\
+Refusing to force-push, since this would discard those commits. To keep them,
+incorporate them into your local branches first. To discard them, retry this
+operation with --force."
+            )?;
+            return Ok(Err(ExitCode(1)));
+        }
+
+        // Give a generated branch name to each draft commit which doesn't have
+        // a branch, so that it can be created when submitting.
+        if let Some(template) = get_submit_branch_name_template(self.repo)? {
+            for commit_oid in self.dag.commit_set_to_vec(&commit_set)? {
+                if commit_statuses.contains_key(&commit_oid)
+                    || self.dag.is_public_commit(commit_oid)?
+                {
+                    continue;
+                }
+                let branch_name = render_branch_name_template(self.repo, &template, commit_oid)?;
+                commit_statuses.insert(
+                    commit_oid,
+                    CommitStatus {
+                        submit_status: SubmitStatus::Unsubmitted,
+                        remote_name: None,
+                        local_branch_name: Some(branch_name),
+                        remote_branch_name: None,
+                    },
+                );
+            }
+        }
+
         Ok(Ok(commit_statuses))
     }
 
@@ -214,10 +477,8 @@ No upstream repository was associated with {} and no value was
 specified for `remote.pushDefault`, so cannot push these branches: {}
 Configure a value with: git config remote.pushDefault <remote>
 These remotes are available: {}",
-                    CategorizedReferenceName::new(
-                        &self.repo.get_main_branch()?.get_reference_name()?,
-                    )
-                    .friendly_describe(),
+                    CategorizedReferenceName::new(&self.repo.get_main_branch_reference_name()?)
+                        .friendly_describe(),
                     unsubmitted_branch_names.join(", "),
                     self.repo.get_all_remote_names()?.join(", "),
                 )?;
@@ -228,6 +489,23 @@ These remotes are available: {}",
         if unsubmitted_branch_names.is_empty() {
             Ok(Ok(Default::default()))
         } else {
+            // Branches with generated names don't exist locally yet, so create
+            // them before pushing.
+            for (commit_oid, commit_status) in &commits {
+                if let Some(local_branch_name) = &commit_status.local_branch_name {
+                    let reference_name =
+                        ReferenceName::from(format!("refs/heads/{local_branch_name}"));
+                    if self.repo.find_reference(&reference_name)?.is_none() {
+                        self.repo.create_reference(
+                            &reference_name,
+                            *commit_oid,
+                            false,
+                            "submit",
+                        )?;
+                    }
+                }
+            }
+
             // This will fail if somebody else created the branch on the remote and we don't
             // know about it.
             let mut args = vec!["push", "--set-upstream", &push_remote];
@@ -294,8 +572,20 @@ These remotes are available: {}",
             .sum();
         progress.notify_progress(0, total_num_branches);
         for (remote_name, branch_names) in branches_by_remote {
-            let mut args = vec!["push", "--force-with-lease", &remote_name];
-            args.extend(branch_names.iter().map(|s| s.as_str()));
+            // Pass the expected remote OID explicitly, since the
+            // remote-tracking branches were just fetched, so an implicit
+            // `--force-with-lease` would always succeed.
+            let mut args = vec!["push".to_owned()];
+            for branch_name in &branch_names {
+                args.push(match self.expected_remote_oids.get(branch_name) {
+                    Some(expected_remote_oid) => {
+                        format!("--force-with-lease={branch_name}:{expected_remote_oid}")
+                    }
+                    None => format!("--force-with-lease={branch_name}"),
+                });
+            }
+            args.push(remote_name.clone());
+            args.extend(branch_names.iter().cloned());
             match self.git_run_info.run(&effects, Some(event_tx_id), &args)? {
                 Ok(()) => {}
                 Err(exit_code) => {
@@ -312,4 +602,9 @@ These remotes are available: {}",
 
         Ok(Ok(()))
     }
+
+    fn supports_stack_navigation(&self) -> bool {
+        // Branches don't have descriptions, so there's nothing to update.
+        true
+    }
 }
@@ -48,4 +48,8 @@ impl Forge for GithubForge<'_> {
     ) -> EyreExitOr<()> {
         unimplemented!("stub")
     }
+
+    fn supports_stack_navigation(&self) -> bool {
+        false
+    }
 }
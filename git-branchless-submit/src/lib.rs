@@ -12,8 +12,9 @@
 mod branch_forge;
 pub mod github;
 pub mod phabricator;
+pub mod stack_navigation;
 
-use std::collections::{BTreeSet, HashMap, HashSet};
+use std::collections::{BTreeSet, HashMap};
 use std::fmt::{Debug, Write};
 use std::time::SystemTime;
 
@@ -29,7 +30,7 @@ use lib::core::effects::Effects;
 use lib::core::eventlog::{EventLogDb, EventReplayer};
 use lib::core::formatting::{Pluralize, StyledStringBuilder};
 use lib::core::repo_ext::{RepoExt, RepoReferencesSnapshot};
-use lib::git::{GitRunInfo, NonZeroOid, ReferenceName, Repo};
+use lib::git::{CategorizedReferenceName, GitRunInfo, NonZeroOid, ReferenceName, Repo};
 use lib::try_exit_code;
 use lib::util::{ExitCode, EyreExitOr};
 
@@ -65,6 +66,11 @@ pub enum SubmitStatus {
     /// The commit exists locally but is associated with a different remote
     /// commit, so it needs to be updated.
     NeedsUpdate,
+
+    /// The commit exists locally and is associated with a different remote
+    /// commit, but only its base changed, not its contents, so it doesn't
+    /// need to be updated.
+    OnlyBaseChanged,
 }
 
 /// Information about each commit.
@@ -105,6 +111,13 @@ pub struct SubmitOptions {
 
     /// An optional message to include with the create or update operation.
     pub message: Option<String>,
+
+    /// Add a section to the description of each code review which lists the
+    /// code reviews in its stack, and regenerate it for every code review in
+    /// the stack.
+    ///
+    /// If the forge has no code review descriptions, then has no effect.
+    pub stack_navigation: bool,
 }
 
 /// The result of creating a commit.
@@ -142,6 +155,9 @@ pub trait Forge: Debug {
         commits: HashMap<NonZeroOid, CommitStatus>,
         options: &SubmitOptions,
     ) -> EyreExitOr<()>;
+
+    /// Whether this forge can honor [`SubmitOptions::stack_navigation`].
+    fn supports_stack_navigation(&self) -> bool;
 }
 
 /// `submit` command.
@@ -159,6 +175,8 @@ pub fn command_main(ctx: CommandContext, args: SubmitArgs) -> EyreExitOr<()> {
         forge,
         message,
         dry_run,
+        stack_navigation,
+        force,
     } = args;
     submit(
         &effects,
@@ -171,6 +189,8 @@ pub fn command_main(ctx: CommandContext, args: SubmitArgs) -> EyreExitOr<()> {
         forge,
         message,
         dry_run,
+        stack_navigation,
+        force,
     )
 }
 
@@ -185,6 +205,8 @@ fn submit(
     forge_kind: Option<ForgeKind>,
     message: Option<String>,
     dry_run: bool,
+    stack_navigation: bool,
+    force: bool,
 ) -> EyreExitOr<()> {
     let repo = Repo::from_current_dir()?;
     let conn = repo.get_db_conn()?;
@@ -214,6 +236,17 @@ fn submit(
         }
     };
 
+    // Secret commits must never be pushed.
+    let secret_commits = commit_set.intersection(&dag.query_secret_commits()?);
+    let commit_set = commit_set.difference(&secret_commits);
+    let secret_branch_names: BTreeSet<String> = dag
+        .commit_set_to_vec(&secret_commits)?
+        .into_iter()
+        .filter_map(|commit_oid| references_snapshot.branch_oid_to_names.get(&commit_oid))
+        .flatten()
+        .map(|reference_name| CategorizedReferenceName::new(reference_name).render_suffix())
+        .collect();
+
     let raw_test_options = RawTestOptions {
         exec: Some("<dummy>".to_string()),
         command: None,
@@ -258,6 +291,7 @@ fn submit(
         execution_strategy,
         num_jobs,
         message,
+        stack_navigation,
     };
 
     let mut forge = select_forge(
@@ -269,16 +303,25 @@ fn submit(
         &references_snapshot,
         &revset,
         forge_kind,
+        force,
     );
+    if stack_navigation && !forge.supports_stack_navigation() {
+        writeln!(
+            effects.get_error_stream(),
+            "The selected forge doesn't support --stack-navigation."
+        )?;
+        return Ok(Err(ExitCode(1)));
+    }
     let statuses = try_exit_code!(forge.query_status(commit_set)?);
     debug!(?statuses, "Commit statuses");
 
-    let (unsubmitted_commits, commits_to_update, commits_to_skip): (
+    let (unsubmitted_commits, commits_to_update, commits_to_skip, unchanged_commits): (
+        HashMap<NonZeroOid, CommitStatus>,
         HashMap<NonZeroOid, CommitStatus>,
         HashMap<NonZeroOid, CommitStatus>,
         HashMap<NonZeroOid, CommitStatus>,
     ) = statuses.into_iter().fold(Default::default(), |acc, elem| {
-        let (mut unsubmitted, mut to_update, mut to_skip) = acc;
+        let (mut unsubmitted, mut to_update, mut to_skip, mut unchanged) = acc;
         let (commit_oid, commit_status) = elem;
         match commit_status {
             CommitStatus {
@@ -308,6 +351,15 @@ fn submit(
                 to_skip.insert(commit_oid, commit_status);
             }
 
+            CommitStatus {
+                submit_status: SubmitStatus::OnlyBaseChanged,
+                remote_name: _,
+                local_branch_name: Some(_),
+                remote_branch_name: _,
+            } => {
+                unchanged.insert(commit_oid, commit_status);
+            }
+
             // Don't know what to do in these cases 🙃.
             CommitStatus {
                 submit_status: SubmitStatus::Unknown,
@@ -316,13 +368,13 @@ fn submit(
                 remote_branch_name: _,
             }
             | CommitStatus {
-                submit_status: SubmitStatus::UpToDate,
+                submit_status: SubmitStatus::UpToDate | SubmitStatus::OnlyBaseChanged,
                 remote_name: _,
                 local_branch_name: None,
                 remote_branch_name: _,
             } => {}
         }
-        (unsubmitted, to_update, to_skip)
+        (unsubmitted, to_update, to_skip, unchanged)
     });
 
     let (created_branches, uncreated_branches): (BTreeSet<String>, BTreeSet<String>) = {
@@ -337,11 +389,6 @@ fn submit(
         } else if create {
             let create_statuses =
                 try_exit_code!(forge.create(unsubmitted_commits, &submit_options)?);
-            let all_branches: HashSet<_> = references_snapshot
-                .branch_oid_to_names
-                .values()
-                .flatten()
-                .collect();
             let mut created_branches = BTreeSet::new();
             for (_commit_oid, create_status) in create_statuses {
                 let CreateStatus {
@@ -351,7 +398,8 @@ fn submit(
                 let branch_reference_name =
                     ReferenceName::from(format!("refs/heads/{local_branch_name}"));
                 created_branches.insert(local_branch_name);
-                if !all_branches.contains(&branch_reference_name) {
+                // The forge may have already created the branch itself.
+                if repo.find_reference(&branch_reference_name)?.is_none() {
                     repo.create_reference(
                         &branch_reference_name,
                         final_commit_oid,
@@ -366,6 +414,10 @@ fn submit(
         }
     };
 
+    let unchanged_branch_names: BTreeSet<String> = unchanged_commits
+        .values()
+        .flat_map(|commit_status| commit_status.local_branch_name.clone())
+        .collect();
     let (updated_branch_names, skipped_branch_names): (BTreeSet<String>, BTreeSet<String>) = {
         let updated_branch_names = commits_to_update
             .iter()
@@ -451,6 +503,29 @@ fn submit(
                 .join(", ")
         )?;
     }
+    if !unchanged_branch_names.is_empty() {
+        writeln!(
+            effects.get_output_stream(),
+            "{} {} (only the base changed): {}",
+            if dry_run { "Would skip" } else { "Skipped" },
+            Pluralize {
+                determiner: None,
+                amount: unchanged_branch_names.len(),
+                unit: ("branch", "branches")
+            },
+            unchanged_branch_names
+                .into_iter()
+                .map(|branch_name| effects
+                    .get_glyphs()
+                    .render(
+                        StyledStringBuilder::new()
+                            .append_styled(branch_name, *STYLE_SKIPPED)
+                            .build(),
+                    )
+                    .expect("Rendering branch name"))
+                .join(", ")
+        )?;
+    }
     if !uncreated_branches.is_empty() {
         writeln!(
             effects.get_output_stream(),
@@ -482,6 +557,29 @@ create and push them, retry this operation with the --create option.",
             if dry_run { "are" } else { "were" },
         )?;
     }
+    if !secret_branch_names.is_empty() {
+        writeln!(
+            effects.get_output_stream(),
+            "{} {} (secret): {}",
+            if dry_run { "Would skip" } else { "Skipped" },
+            Pluralize {
+                determiner: None,
+                amount: secret_branch_names.len(),
+                unit: ("branch", "branches")
+            },
+            secret_branch_names
+                .into_iter()
+                .map(|branch_name| effects
+                    .get_glyphs()
+                    .render(
+                        StyledStringBuilder::new()
+                            .append_styled(branch_name, *STYLE_SKIPPED)
+                            .build(),
+                    )
+                    .expect("Rendering branch name"))
+                .join(", ")
+        )?;
+    }
 
     Ok(Ok(()))
 }
@@ -496,6 +594,7 @@ fn select_forge<'a>(
     references_snapshot: &'a RepoReferencesSnapshot,
     revset: &'a Revset,
     forge: Option<ForgeKind>,
+    force: bool,
 ) -> Box<dyn Forge + 'a> {
     let forge_kind = match forge {
         Some(forge_kind) => {
@@ -532,6 +631,8 @@ fn select_forge<'a>(
             dag,
             event_log_db,
             references_snapshot,
+            force,
+            expected_remote_oids: Default::default(),
         }),
 
         ForgeKind::Github => Box::new(GithubForge {
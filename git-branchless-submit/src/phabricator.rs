@@ -2,6 +2,7 @@
 
 use std::collections::{HashMap, HashSet};
 use std::fmt::{self, Debug, Display, Write};
+use std::fs::OpenOptions;
 use std::io;
 use std::path::PathBuf;
 use std::process::{Command, Stdio};
@@ -21,7 +22,7 @@ use lib::core::check_out::CheckOutCommitOptions;
 use lib::core::dag::{CommitSet, Dag};
 use lib::core::effects::{Effects, OperationType};
 use lib::core::eventlog::EventLogDb;
-use lib::core::formatting::StyledStringBuilder;
+use lib::core::formatting::{Pluralize, StyledStringBuilder};
 use lib::core::rewrite::{
     execute_rebase_plan, BuildRebasePlanError, BuildRebasePlanOptions, ExecuteRebasePlanOptions,
     ExecuteRebasePlanResult, RebasePlanBuilder, RebasePlanPermissions, RepoResource,
@@ -35,6 +36,7 @@ use serde::{Deserialize, Serialize};
 use thiserror::Error;
 use tracing::{instrument, warn};
 
+use crate::stack_navigation::{render_stack_navigation, replace_stack_navigation};
 use crate::{CommitStatus, CreateStatus, Forge, SubmitOptions, SubmitStatus, STYLE_PUSHED};
 
 /// Wrapper around the Phabricator "ID" type. (This is *not* a PHID, just a
@@ -67,10 +69,17 @@ struct DifferentialEditRequest {
     transactions: Vec<DifferentialEditTransaction>,
 }
 
-#[derive(Debug, Default, Serialize, Eq, PartialEq)]
+#[derive(Debug, Serialize, Eq, PartialEq)]
 struct DifferentialEditTransaction {
     r#type: String,
-    value: Vec<Phid>,
+    value: DifferentialEditValue,
+}
+
+#[derive(Debug, Serialize, Eq, PartialEq)]
+#[serde(untagged)]
+enum DifferentialEditValue {
+    Phids(Vec<Phid>),
+    Text(String),
 }
 
 #[derive(Debug, Deserialize)]
@@ -113,6 +122,9 @@ struct DifferentialQueryRevisionResponse {
     #[serde(default)]
     hashes: Vec<(String, String)>,
 
+    #[serde(default)]
+    summary: String,
+
     #[serde(default)]
     auxiliary: DifferentialQueryAuxiliaryResponse,
 }
@@ -219,6 +231,10 @@ pub type Result<T> = std::result::Result<T, Error>;
 /// forge will make mock calls instead of actually invoking `arc`.
 pub const SHOULD_MOCK_ENV_KEY: &str = "BRANCHLESS_SUBMIT_PHABRICATOR_MOCK";
 
+/// When mocking, the edits which would have been made to revisions are
+/// appended to this file in the `.git` directory, one JSON request per line.
+pub const MOCK_EDITS_FILE_NAME: &str = "branchless-phabricator-mock-edits.jsonl";
+
 fn should_mock() -> bool {
     std::env::var_os(SHOULD_MOCK_ENV_KEY).is_some()
 }
@@ -328,6 +344,7 @@ impl Forge for PhabricatorForge<'_> {
             execution_strategy,
             num_jobs,
             message: _,
+            stack_navigation,
         } = options;
 
         let commit_set = commits.keys().copied().collect();
@@ -560,6 +577,12 @@ Differential Revision: https://phabricator.example.com/D000$(git rev-list --coun
             Ok(()) => {}
             Err(exit_code) => return Ok(Err(exit_code)),
         }
+        if *stack_navigation {
+            match self.update_stack_navigation(&final_commit_oids, &final_commit_oids)? {
+                Ok(()) => {}
+                Err(exit_code) => return Ok(Err(exit_code)),
+            }
+        }
 
         Ok(Ok(create_statuses))
     }
@@ -576,6 +599,7 @@ Differential Revision: https://phabricator.example.com/D000$(git rev-list --coun
             execution_strategy,
             num_jobs,
             message,
+            stack_navigation,
         } = options;
 
         let commit_set = commits.keys().copied().collect();
@@ -693,15 +717,20 @@ Differential Revision: https://phabricator.example.com/D000$(git rev-list --coun
             return Ok(Err(ExitCode(1)));
         }
 
-        try_exit_code!(self.update_dependencies(
-            &success_commits
-                .into_iter()
-                .map(|(commit_oid, _test_output)| commit_oid)
-                .collect(),
-            &CommitSet::empty()
-        )?);
+        let success_commits: CommitSet = success_commits
+            .into_iter()
+            .map(|(commit_oid, _test_output)| commit_oid)
+            .collect();
+        try_exit_code!(self.update_dependencies(&success_commits, &CommitSet::empty())?);
+        if *stack_navigation {
+            try_exit_code!(self.update_stack_navigation(&success_commits, &CommitSet::empty())?);
+        }
         Ok(Ok(()))
     }
+
+    fn supports_stack_navigation(&self) -> bool {
+        true
+    }
 }
 
 impl PhabricatorForge<'_> {
@@ -802,6 +831,7 @@ impl PhabricatorForge<'_> {
                     id,
                     phid: _,
                     hashes: _,
+                    summary: _,
                     auxiliary:
                         DifferentialQueryAuxiliaryResponse {
                             phabricator_depends_on,
@@ -825,6 +855,7 @@ impl PhabricatorForge<'_> {
                         id,
                         phid,
                         hashes: _,
+                        summary: _,
                         auxiliary: _,
                     } = revision;
                     (phid, id)
@@ -945,8 +976,6 @@ impl PhabricatorForge<'_> {
         id: Id,
         parent_revision_ids: Vec<Id>,
     ) -> eyre::Result<std::result::Result<(), ExitCode>> {
-        let effects = self.effects;
-
         if should_mock() {
             return Ok(Ok(()));
         }
@@ -963,10 +992,29 @@ impl PhabricatorForge<'_> {
             id,
             transactions: vec![DifferentialEditTransaction {
                 r#type: "parents.set".to_string(),
-                value: parent_revision_phids,
+                value: DifferentialEditValue::Phids(parent_revision_phids),
             }],
         };
+        self.edit_revision(&request, "update dependencies")
+    }
+
+    /// Apply the edits in `request` to a revision. If this fails, the error
+    /// is reported as having failed to carry out `action`.
+    fn edit_revision(
+        &self,
+        request: &DifferentialEditRequest,
+        action: &str,
+    ) -> eyre::Result<std::result::Result<(), ExitCode>> {
+        if should_mock() {
+            let path = self.repo.get_path().join(MOCK_EDITS_FILE_NAME);
+            let mut line = serde_json::to_string(request)?;
+            line.push('\n');
+            let mut file = OpenOptions::new().create(true).append(true).open(path)?;
+            io::Write::write_all(&mut file, line.as_bytes())?;
+            return Ok(Ok(()));
+        }
 
+        let effects = self.effects;
         let args = vec![
             "call-conduit".to_string(),
             "--".to_string(),
@@ -982,7 +1030,7 @@ impl PhabricatorForge<'_> {
                 source: err,
                 args: args.clone(),
             })?;
-        serde_json::to_writer_pretty(child.stdin.take().unwrap(), &request).map_err(|err| {
+        serde_json::to_writer_pretty(child.stdin.take().unwrap(), request).map_err(|err| {
             Error::CommunicateWithArc {
                 source: err,
                 args: args.clone(),
@@ -998,7 +1046,7 @@ impl PhabricatorForge<'_> {
             let ExitCode(exit_code_isize) = exit_code;
             writeln!(
                 effects.get_output_stream(),
-                "Could not update dependencies when running `arc {args}` (exit code {exit_code_isize}):",
+                "Could not {action} when running `arc {args}` (exit code {exit_code_isize}):",
             )?;
             writeln!(
                 effects.get_output_stream(),
@@ -1011,9 +1059,95 @@ impl PhabricatorForge<'_> {
         Ok(Ok(()))
     }
 
+    /// Regenerate the stack navigation section of the summary of each revision
+    /// in the stacks containing `commits`. See [`crate::stack_navigation`].
+    fn update_stack_navigation(
+        &self,
+        commits: &CommitSet,
+        newly_created_commits: &CommitSet,
+    ) -> eyre::Result<std::result::Result<(), ExitCode>> {
+        // Newly-created commits won't have been observed by the DAG, so add them in manually here.
+        let draft_commits = self.dag.query_draft_commits()?.union(newly_created_commits);
+        let stack_commits = self
+            .dag
+            .query_ancestors(commits.clone())?
+            .union(&self.dag.query_descendants(commits.clone())?)
+            .intersection(&draft_commits);
+        let mut ids = Vec::new();
+        let mut links = Vec::new();
+        for commit_oid in self.dag.sort(&stack_commits)? {
+            if let Some((id, url)) = self.parse_differential_revision(commit_oid)? {
+                links.push(Self::render_revision_link(&id, url));
+                ids.push(id);
+            }
+        }
+        if ids.is_empty() {
+            return Ok(Ok(()));
+        }
+
+        let summaries: HashMap<Id, String> = if should_mock() {
+            Default::default()
+        } else {
+            self.query_revisions(&DifferentialQueryRequest {
+                ids: ids.clone(),
+                phids: Default::default(),
+            })?
+            .into_iter()
+            .map(|revision| (revision.id, revision.summary))
+            .collect()
+        };
+        for (index, id) in ids.iter().enumerate() {
+            let section = render_stack_navigation(&links, index);
+            let summary = summaries.get(id).map(String::as_str).unwrap_or_default();
+            let request = DifferentialEditRequest {
+                id: id.clone(),
+                transactions: vec![DifferentialEditTransaction {
+                    r#type: "summary".to_string(),
+                    value: DifferentialEditValue::Text(replace_stack_navigation(summary, &section)),
+                }],
+            };
+            try_exit_code!(self.edit_revision(&request, "update stack navigation")?);
+        }
+
+        let effects = self.effects;
+        writeln!(
+            effects.get_output_stream(),
+            "Updated stack navigation of {}: {}",
+            Pluralize {
+                determiner: None,
+                amount: ids.len(),
+                unit: ("revision", "revisions"),
+            },
+            effects.get_glyphs().render(StyledStringBuilder::join(
+                ", ",
+                ids.iter().map(Self::render_id).collect()
+            ))?,
+        )?;
+        Ok(Ok(()))
+    }
+
     /// Given a commit for D123, returns a string like "123" by parsing the
     /// commit message.
     pub fn get_revision_id(&self, commit_oid: NonZeroOid) -> Result<Option<Id>> {
+        let revision = self.parse_differential_revision(commit_oid)?;
+        Ok(revision.map(|(id, _url)| id))
+    }
+
+    /// Render a Remarkup link to a revision, if its URL is known.
+    fn render_revision_link(id: &Id, url: Option<String>) -> String {
+        match url {
+            Some(url) => format!("[[{url} | {id}]]"),
+            // Phabricator links bare revision IDs itself.
+            None => id.to_string(),
+        }
+    }
+
+    /// Parse the `Differential Revision` line of the message of a commit into
+    /// the revision ID and, if it was given as a URL, the revision URL.
+    fn parse_differential_revision(
+        &self,
+        commit_oid: NonZeroOid,
+    ) -> Result<Option<(Id, Option<String>)>> {
         let commit =
             self.repo
                 .find_commit_or_fail(commit_oid)
@@ -1028,8 +1162,10 @@ impl PhabricatorForge<'_> {
                 r"(?mx)
 ^
 Differential[\ ]Revision:[\ ]
-    (.+ /)?
-    D(?P<diff>[0-9]+)
+    (?P<url>
+        (.+ /)?
+        D(?P<diff>[0-9]+)
+    )
 $",
             )
             .expect("Failed to compile `extract_diff_number` regex");
@@ -1041,7 +1177,9 @@ $",
         let diff_number = &captures["diff"];
         let diff_number = String::from_utf8(diff_number.to_vec())
             .expect("Regex should have confirmed that this string was only ASCII digits");
-        Ok(Some(Id(diff_number)))
+        let url = String::from_utf8_lossy(&captures["url"]).into_owned();
+        let url = if url.contains("://") { Some(url) } else { None };
+        Ok(Some((Id(diff_number), url)))
     }
 
     fn render_failed_test(
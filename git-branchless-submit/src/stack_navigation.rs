@@ -0,0 +1,59 @@
+//! Render the "stack navigation" section which `git submit --stack-navigation`
+//! adds to the description of each code review in a stack.
+//!
+//! The section lists every code review in the stack, from the bottom of the
+//! stack to the top, and marks the code review it belongs to. It's always kept
+//! at the end of the description, so that it can be regenerated when the stack
+//! is restacked or reordered without disturbing the rest of the description.
+
+/// The first line of the stack navigation section, which is followed by one
+/// list item per code review.
+pub const STACK_NAVIGATION_HEADER: &str = "Stack (updated by `git submit`):";
+
+/// Render the stack navigation section for the code review at `current_index`
+/// of `reviews`, which are links to each code review in stack order.
+pub fn render_stack_navigation(reviews: &[String], current_index: usize) -> String {
+    let mut result = format!("{STACK_NAVIGATION_HEADER}\n");
+    for (index, review) in reviews.iter().enumerate() {
+        if index == current_index {
+            result.push_str(&format!("- **{review}** (this one)\n"));
+        } else {
+            result.push_str(&format!("- {review}\n"));
+        }
+    }
+    result
+}
+
+/// Find the byte offset of the stack navigation section of `description`.
+/// The section must start with a line consisting of
+/// [`STACK_NAVIGATION_HEADER`] and contain only list items after it, so that
+/// the header appearing elsewhere in the description isn't mistaken for it.
+fn find_stack_navigation(description: &str) -> Option<usize> {
+    let mut offset = 0;
+    let mut section_offset = None;
+    for line in description.split_inclusive('\n') {
+        let trimmed_line = line.trim_end();
+        if trimmed_line == STACK_NAVIGATION_HEADER {
+            section_offset = Some(offset);
+        } else if !trimmed_line.is_empty() && !trimmed_line.starts_with("- ") {
+            section_offset = None;
+        }
+        offset += line.len();
+    }
+    section_offset
+}
+
+/// Replace the stack navigation section at the end of `description` with
+/// `section`, or add it to the end of `description` if there isn't one yet.
+pub fn replace_stack_navigation(description: &str, section: &str) -> String {
+    let description = match find_stack_navigation(description) {
+        Some(index) => &description[..index],
+        None => description,
+    };
+    let description = description.trim_end();
+    if description.is_empty() {
+        section.to_string()
+    } else {
+        format!("{description}\n\n{section}")
+    }
+}
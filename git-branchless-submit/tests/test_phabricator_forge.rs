@@ -230,3 +230,127 @@ fn test_submit_phabricator_update() -> eyre::Result<()> {
 
     Ok(())
 }
+
+#[test]
+fn test_submit_phabricator_stack_navigation() -> eyre::Result<()> {
+    let git = make_git()?;
+    git.init_repo()?;
+
+    git.detach_head()?;
+    git.commit_file("test1", 1)?;
+    git.commit_file("test2", 2)?;
+
+    {
+        let (stdout, _stderr) = git.branchless_with_options(
+            "submit",
+            &["--create", "--forge", "phabricator", "--stack-navigation"],
+            &GitRunOptions {
+                env: mock_env(&git),
+                ..Default::default()
+            },
+        )?;
+        assert!(
+            stdout.contains("Updated stack navigation of 2 revisions: D0002, D0003"),
+            "{stdout}"
+        );
+    }
+
+    let edits_path = git
+        .repo_path
+        .join(".git")
+        .join(git_branchless_submit::phabricator::MOCK_EDITS_FILE_NAME);
+    {
+        let edits = std::fs::read_to_string(&edits_path)?;
+        insta::assert_snapshot!(edits, @r###"
+        {"objectIdentifier":"0002","transactions":[{"type":"summary","value":"Stack (updated by `git submit`):\n- **[[https://phabricator.example.com/D0002 | D0002]]** (this one)\n- [[https://phabricator.example.com/D0003 | D0003]]\n"}]}
+        {"objectIdentifier":"0003","transactions":[{"type":"summary","value":"Stack (updated by `git submit`):\n- [[https://phabricator.example.com/D0002 | D0002]]\n- **[[https://phabricator.example.com/D0003 | D0003]]** (this one)\n"}]}
+        "###);
+    }
+    std::fs::remove_file(&edits_path)?;
+
+    // Updating only the top of the stack still updates the navigation of
+    // the whole stack.
+    {
+        let (stdout, _stderr) = git.branchless_with_options(
+            "submit",
+            &["--forge", "phabricator", "--stack-navigation", "HEAD"],
+            &GitRunOptions {
+                env: mock_env(&git),
+                ..Default::default()
+            },
+        )?;
+        assert!(
+            stdout.contains("Updated stack navigation of 2 revisions: D0002, D0003"),
+            "{stdout}"
+        );
+    }
+
+    {
+        let edits = std::fs::read_to_string(&edits_path)?;
+        let edited_ids = edits
+            .lines()
+            .map(|line| line.split('"').nth(3).unwrap_or_default())
+            .collect::<Vec<_>>();
+        assert_eq!(edited_ids, vec!["0002", "0003"]);
+    }
+
+    Ok(())
+}
+
+#[test]
+fn test_submit_github_stack_navigation_unsupported() -> eyre::Result<()> {
+    let git = make_git()?;
+    git.init_repo()?;
+
+    git.detach_head()?;
+    git.commit_file("test1", 1)?;
+
+    {
+        let (_stdout, stderr) = git.branchless_with_options(
+            "submit",
+            &["--forge", "github", "--stack-navigation"],
+            &GitRunOptions {
+                expected_exit_code: 1,
+                ..Default::default()
+            },
+        )?;
+        insta::assert_snapshot!(stderr, @r###"
+        The selected forge doesn't support --stack-navigation.
+        "###);
+    }
+
+    Ok(())
+}
+
+#[test]
+fn test_render_stack_navigation() {
+    use git_branchless_submit::stack_navigation::{
+        render_stack_navigation, replace_stack_navigation,
+    };
+
+    let reviews = vec!["D1".to_string(), "D2".to_string(), "D3".to_string()];
+    let section = render_stack_navigation(&reviews, 1);
+    insta::assert_snapshot!(section, @r###"
+    Stack (updated by `git submit`):
+    - D1
+    - **D2** (this one)
+    - D3
+    "###);
+
+    let description = replace_stack_navigation("Fix the frobnicator.\n", &section);
+    assert_eq!(description, format!("Fix the frobnicator.\n\n{section}"));
+
+    let reordered_section = render_stack_navigation(&["D2".to_string(), "D1".to_string()], 0);
+    assert_eq!(
+        replace_stack_navigation(&description, &reordered_section),
+        format!("Fix the frobnicator.\n\n{reordered_section}")
+    );
+    assert_eq!(replace_stack_navigation("", &section), section);
+
+    // Only a section at the end of the description is replaced.
+    let description = format!("Fix the frobnicator.\n\n{section}\nMore details.\n");
+    assert_eq!(
+        replace_stack_navigation(&description, &reordered_section),
+        format!("{}\n\n{reordered_section}", description.trim_end())
+    );
+}
@@ -91,8 +91,6 @@ fn test_submit() -> eyre::Result<()> {
         To: file://<remote>
          * [new branch]      bar -> bar
          * [new branch]      qux -> qux
-        branchless: processing 1 update: remote branch origin/bar
-        branchless: processing 1 update: remote branch origin/qux
         "###);
         insta::assert_snapshot!(stdout, @r###"
         branchless: running command: <git-executable> push --set-upstream origin bar qux
@@ -123,11 +121,10 @@ fn test_submit() -> eyre::Result<()> {
         branchless: processing 1 update: branch qux
         To: file://<remote>
          + 20230db...bae8307 qux -> qux (forced update)
-        branchless: processing 1 update: remote branch origin/qux
         "###);
         insta::assert_snapshot!(stdout, @r###"
         branchless: running command: <git-executable> fetch origin refs/heads/bar refs/heads/qux
-        branchless: running command: <git-executable> push --force-with-lease origin qux
+        branchless: running command: <git-executable> push --force-with-lease=qux:20230db7fac2f6ddc4c5bc279caba9b996823696 origin qux
         Pushed 1 branch: qux
         Skipped 1 branch (already up-to-date): bar
         "###);
@@ -296,7 +293,6 @@ fn test_submit_up_to_date_branch() -> eyre::Result<()> {
         branchless: processing 1 update: branch feature
         To: file://<remote>
          * [new branch]      feature -> feature
-        branchless: processing 1 update: remote branch origin/feature
         "###);
         insta::assert_snapshot!(stdout, @r###"
         branchless: running command: <git-executable> push --set-upstream origin feature
@@ -321,3 +317,272 @@ fn test_submit_up_to_date_branch() -> eyre::Result<()> {
 
     Ok(())
 }
+
+#[test]
+fn test_submit_skips_secret_commits() -> eyre::Result<()> {
+    let GitWrapperWithRemoteRepo {
+        temp_dir: _guard,
+        original_repo,
+        cloned_repo,
+    } = make_git_with_remote_repo()?;
+
+    if original_repo.get_version()? < MIN_VERSION {
+        return Ok(());
+    }
+
+    {
+        original_repo.init_repo()?;
+        original_repo.commit_file("test1", 1)?;
+        original_repo.commit_file("test2", 2)?;
+
+        original_repo.clone_repo_into(&cloned_repo, &[])?;
+    }
+
+    cloned_repo.init_repo_with_options(&GitInitOptions {
+        make_initial_commit: false,
+        ..Default::default()
+    })?;
+    cloned_repo.run(&["checkout", "-b", "bar"])?;
+    cloned_repo.commit_file("test3", 3)?;
+    cloned_repo.run(&["checkout", "-b", "qux"])?;
+    cloned_repo.commit_file("test4", 4)?;
+    cloned_repo.branchless("phase", &["--secret", "qux"])?;
+
+    {
+        let (stdout, stderr) = cloned_repo.run(&["submit", "--create", "--dry-run"])?;
+        insta::assert_snapshot!(stderr, @"");
+        insta::assert_snapshot!(stdout, @r###"
+        Would create 1 branch: bar
+        Would skip 1 branch (secret): qux
+        "###);
+    }
+
+    Ok(())
+}
+
+#[test]
+fn test_submit_branch_name_template() -> eyre::Result<()> {
+    let GitWrapperWithRemoteRepo {
+        temp_dir: _guard,
+        original_repo,
+        cloned_repo,
+    } = make_git_with_remote_repo()?;
+
+    if original_repo.get_version()? < MIN_VERSION {
+        return Ok(());
+    }
+
+    {
+        original_repo.init_repo()?;
+        original_repo.commit_file("test1", 1)?;
+        original_repo.commit_file("test2", 2)?;
+
+        original_repo.clone_repo_into(&cloned_repo, &[])?;
+    }
+
+    cloned_repo.init_repo_with_options(&GitInitOptions {
+        make_initial_commit: false,
+        ..Default::default()
+    })?;
+    cloned_repo.detach_head()?;
+    cloned_repo.commit_file("test3", 3)?;
+
+    {
+        let (stdout, stderr) = cloned_repo.run(&["submit", "--create", "--dry-run"])?;
+        insta::assert_snapshot!(stderr, @"");
+        insta::assert_snapshot!(stdout, @"");
+    }
+
+    cloned_repo.run(&[
+        "config",
+        "branchless.submit.branchNameTemplate",
+        "{user}/branchless/{short_oid}",
+    ])?;
+    {
+        let (stdout, stderr) = cloned_repo.run(&["submit", "--create", "--dry-run"])?;
+        insta::assert_snapshot!(stderr, @"");
+        insta::assert_snapshot!(stdout, @r###"
+        Would create 1 branch: test/branchless/70deb1e
+        "###);
+    }
+
+    {
+        let (stdout, _stderr) = cloned_repo.run(&["submit", "--create"])?;
+        assert!(
+            stdout.contains("Created 1 branch: test/branchless/70deb1e"),
+            "{stdout}"
+        );
+    }
+
+    {
+        let (stdout, stderr) = original_repo.run(&["branch", "-a"])?;
+        insta::assert_snapshot!(stderr, @"");
+        insta::assert_snapshot!(stdout, @r###"
+        * master
+          test/branchless/70deb1e
+        "###);
+    }
+
+    Ok(())
+}
+
+#[test]
+fn test_submit_refuses_to_overwrite_remote_changes() -> eyre::Result<()> {
+    let GitWrapperWithRemoteRepo {
+        temp_dir: _guard,
+        original_repo,
+        cloned_repo,
+    } = make_git_with_remote_repo()?;
+
+    if original_repo.get_version()? < MIN_VERSION {
+        return Ok(());
+    }
+
+    {
+        original_repo.init_repo()?;
+        original_repo.commit_file("test1", 1)?;
+        original_repo.commit_file("test2", 2)?;
+
+        original_repo.clone_repo_into(&cloned_repo, &[])?;
+    }
+
+    cloned_repo.init_repo_with_options(&GitInitOptions {
+        make_initial_commit: false,
+        ..Default::default()
+    })?;
+    cloned_repo.run(&["checkout", "-b", "feature"])?;
+    cloned_repo.commit_file("test3", 3)?;
+    cloned_repo.run(&["submit", "--create"])?;
+
+    // Somebody else adds a commit to the branch on the remote.
+    original_repo.run(&["checkout", "feature"])?;
+    original_repo.commit_file("test4", 4)?;
+    original_repo.run(&["checkout", "master"])?;
+    let (remote_feature_oid, _stderr) = original_repo.run(&["rev-parse", "feature"])?;
+
+    cloned_repo.run(&["commit", "--amend", "-m", "updated message"])?;
+    {
+        let (stdout, _stderr) = cloned_repo.branchless_with_options(
+            "submit",
+            &[],
+            &GitRunOptions {
+                expected_exit_code: 1,
+                ..Default::default()
+            },
+        )?;
+        insta::assert_snapshot!(stdout, @r###"
+        branchless: running command: <git-executable> fetch origin refs/heads/feature
+        Branch feature was updated on the remote since it was last pushed. origin/feature has 2 commits which are not in feature:
+          355e173 create test4.txt
+          70deb1e create test3.txt
+        Refusing to force-push, since this would discard those commits. To keep them,
+        incorporate them into your local branches first. To discard them, retry this
+        operation with --force.
+        "###);
+    }
+
+    {
+        let (stdout, _stderr) = original_repo.run(&["rev-parse", "feature"])?;
+        assert_eq!(stdout, remote_feature_oid);
+    }
+
+    // Having fetched the remote changes doesn't mean that they can be
+    // discarded, so retrying the operation still fails.
+    {
+        let (stdout, _stderr) = cloned_repo.branchless_with_options(
+            "submit",
+            &[],
+            &GitRunOptions {
+                expected_exit_code: 1,
+                ..Default::default()
+            },
+        )?;
+        insta::assert_snapshot!(stdout, @r###"
+        branchless: running command: <git-executable> fetch origin refs/heads/feature
+        Branch feature was updated on the remote since it was last pushed. origin/feature has 2 commits which are not in feature:
+          355e173 create test4.txt
+          70deb1e create test3.txt
+        Refusing to force-push, since this would discard those commits. To keep them,
+        incorporate them into your local branches first. To discard them, retry this
+        operation with --force.
+        "###);
+    }
+
+    {
+        let (stdout, _stderr) = cloned_repo.branchless("submit", &["--force"])?;
+        insta::assert_snapshot!(stdout, @r###"
+        branchless: running command: <git-executable> fetch origin refs/heads/feature
+        branchless: running command: <git-executable> push --force-with-lease=feature:355e173bf9c5d2efac2e451da0cdad3fb82b869a origin feature
+        Pushed 1 branch: feature
+        "###);
+    }
+
+    {
+        let (stdout, _stderr) = original_repo.run(&["rev-parse", "feature"])?;
+        assert_ne!(stdout, remote_feature_oid);
+    }
+
+    Ok(())
+}
+
+#[test]
+fn test_submit_skips_unchanged_commits() -> eyre::Result<()> {
+    let GitWrapperWithRemoteRepo {
+        temp_dir: _guard,
+        original_repo,
+        cloned_repo,
+    } = make_git_with_remote_repo()?;
+
+    if original_repo.get_version()? < MIN_VERSION {
+        return Ok(());
+    }
+
+    {
+        original_repo.init_repo()?;
+        original_repo.commit_file("test1", 1)?;
+        original_repo.commit_file("test2", 2)?;
+
+        original_repo.clone_repo_into(&cloned_repo, &[])?;
+    }
+
+    cloned_repo.init_repo_with_options(&GitInitOptions {
+        make_initial_commit: false,
+        ..Default::default()
+    })?;
+    cloned_repo.run(&["checkout", "-b", "foo"])?;
+    cloned_repo.commit_file("test3", 3)?;
+    cloned_repo.run(&["submit", "--create"])?;
+
+    // Move the branch onto a new base without changing its contents.
+    cloned_repo.run(&["checkout", "master"])?;
+    cloned_repo.commit_file("test4", 4)?;
+    cloned_repo.branchless("move", &["-x", "foo", "-d", "master"])?;
+
+    {
+        let (stdout, _stderr) = cloned_repo.run(&["submit", "foo"])?;
+        assert!(
+            stdout.contains("Skipped 1 branch (only the base changed): foo"),
+            "{stdout}"
+        );
+        assert!(!stdout.contains("push --force-with-lease"), "{stdout}");
+    }
+
+    // Commits stacked on top of other draft commits are still pushed when
+    // only their base changed, since their parents changed too.
+    cloned_repo.run(&["checkout", "-b", "bar", "foo"])?;
+    cloned_repo.commit_file("test5", 5)?;
+    cloned_repo.run(&["submit", "--create", "bar"])?;
+    cloned_repo.branchless("reword", &["foo", "-m", "reworded test3"])?;
+    {
+        let (stdout, _stderr) = cloned_repo.run(&["submit", "bar"])?;
+        assert!(stdout.contains("Pushed 1 branch: bar"), "{stdout}");
+    }
+
+    cloned_repo.run(&["config", "branchless.submit.pushUnchangedCommits", "true"])?;
+    {
+        let (stdout, _stderr) = cloned_repo.run(&["submit", "foo"])?;
+        assert!(stdout.contains("Pushed 1 branch: foo"), "{stdout}");
+    }
+
+    Ok(())
+}
@@ -40,7 +40,7 @@ use lib::core::config::{
     print_hint_suppression_notice, Hint,
 };
 use lib::core::dag::{sorted_commit_set, CommitSet, Dag};
-use lib::core::effects::{icons, Effects, OperationIcon, OperationType};
+use lib::core::effects::{icons, Effects, OperationIcon, OperationType, StepEvent};
 use lib::core::eventlog::{
     EventLogDb, EventReplayer, EventTransactionId, BRANCHLESS_TRANSACTION_ID_ENV_VAR,
 };
@@ -863,6 +863,21 @@ pub struct FixInfo {
 }
 
 impl TestStatus {
+    /// A short name for the status, as reported in [`StepEvent::Finished`].
+    fn get_step_status(&self) -> &'static str {
+        match self {
+            TestStatus::CheckoutFailed => "checkout-failed",
+            TestStatus::SpawnTestFailed(_) => "spawn-failed",
+            TestStatus::TerminatedBySignal => "terminated-by-signal",
+            TestStatus::AlreadyInProgress => "already-in-progress",
+            TestStatus::ReadCacheFailed(_) => "read-cache-failed",
+            TestStatus::Indeterminate { .. } => "indeterminate",
+            TestStatus::Abort { .. } => "abort",
+            TestStatus::Failed { .. } => "failed",
+            TestStatus::Passed { .. } => "passed",
+        }
+    }
+
     #[instrument]
     fn get_icon(&self) -> &'static str {
         match self {
@@ -1354,6 +1369,13 @@ fn run_tests_inner<'a>(
         let (effects, progress) =
             effects.start_operation(OperationType::RunTests(Arc::new(command.to_string())));
         progress.notify_progress(0, commits.len());
+        effects.report_step(StepEvent::Planned {
+            operation: "test",
+            steps: commits
+                .iter()
+                .map(|commit| commit.get_oid().to_string())
+                .collect(),
+        });
         let commit_jobs = {
             let mut results = IndexMap::new();
             for commit in commits {
@@ -2206,6 +2228,10 @@ fn run_test(
         fix_options,
     } = options;
     let (effects, progress) = effects.start_operation(operation_type);
+    effects.report_step(StepEvent::Started {
+        operation: "test",
+        step: commit.get_oid().to_string(),
+    });
     progress.notify_status(
         OperationIcon::InProgress,
         format!(
@@ -2305,6 +2331,11 @@ fn run_test(
         },
         effects.get_glyphs().render(description)?,
     );
+    effects.report_step(StepEvent::Finished {
+        operation: "test",
+        step: commit.get_oid().to_string(),
+        status: test_output.test_status.get_step_status().to_string(),
+    });
     Ok(test_output)
 }
 
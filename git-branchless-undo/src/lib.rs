@@ -28,24 +28,30 @@ use cursive_core::views::{
 use cursive_core::{Cursive, CursiveRunner};
 use eyre::Context;
 use lib::core::check_out::{check_out_commit, CheckOutCommitOptions, CheckoutTarget};
+use lib::core::config::get_undo_create_snapshots;
 use lib::core::repo_ext::RepoExt;
 use lib::try_exit_code;
 use lib::util::{ExitCode, EyreExitOr};
 use tracing::instrument;
 
 use crate::tui::{with_siv, SingletonView};
-use git_branchless_revset::resolve_default_smartlog_commits;
+use git_branchless_revset::{parse_time, resolve_default_smartlog_commits};
 use git_branchless_smartlog::{make_smartlog_graph, render_graph};
+use lib::core::commit_metadata::CommitMetadataDescriptor;
 use lib::core::dag::{CommitSet, Dag};
 use lib::core::effects::Effects;
 use lib::core::eventlog::{Event, EventCursor, EventLogDb, EventReplayer, EventTransactionId};
 use lib::core::formatting::{Glyphs, Pluralize, StyledStringBuilder};
+use lib::core::gc::mark_commit_reachable;
 use lib::core::node_descriptors::{
     BranchesDescriptor, CommitMessageDescriptor, CommitOidDescriptor,
     DifferentialRevisionDescriptor, ObsolescenceExplanationDescriptor, Redactor,
     RelativeTimeDescriptor,
 };
-use lib::git::{CategorizedReferenceName, GitRunInfo, MaybeZeroOid, Repo, ResolvedReferenceInfo};
+use lib::git::{
+    CategorizedReferenceName, FileStatus, GitRunInfo, MaybeZeroOid, ReferenceName, Repo,
+    ResolvedReferenceInfo,
+};
 
 fn render_cursor_smartlog(
     effects: &Effects,
@@ -95,6 +101,7 @@ fn render_cursor_smartlog(
                 &Redactor::Disabled,
             )?,
             &mut DifferentialRevisionDescriptor::new(repo, &Redactor::Disabled)?,
+            &mut CommitMetadataDescriptor::new(repo, &Redactor::Disabled)?,
             &mut CommitMessageDescriptor::new(&Redactor::Disabled)?,
         ],
     )?;
@@ -389,6 +396,25 @@ fn describe_event(glyphs: &Glyphs, repo: &Repo, event: &Event) -> eyre::Result<V
                 .append(repo.friendly_describe_commit_from_oid(glyphs, *commit_oid)?)
                 .build()]
         }
+
+        Event::CopyEvent {
+            timestamp: _,
+            event_tx_id: _,
+            copy_type: _,
+            source_commit_oid,
+            new_commit_oid,
+        } => {
+            vec![
+                StyledStringBuilder::new()
+                    .append_plain("Copy commit ")
+                    .append(repo.friendly_describe_commit_from_oid(glyphs, *source_commit_oid)?)
+                    .build(),
+                StyledStringBuilder::new()
+                    .append_plain("         as ")
+                    .append(repo.friendly_describe_commit_from_oid(glyphs, *new_commit_oid)?)
+                    .build(),
+            ]
+        }
     };
     Ok(result)
 }
@@ -706,6 +732,10 @@ fn inverse_event(
         // this event occurred, we want to check out the working copy as it
         // existed at that point in time.
         event @ Event::WorkingCopySnapshot { .. } => event,
+
+        // Not invertible either, but these events are filtered out before
+        // being inverted, since they don't affect the state of the repository.
+        event @ Event::CopyEvent { .. } => event,
     };
     Ok(inverse_event)
 }
@@ -771,6 +801,152 @@ fn extract_checkout_target(
     Ok((checkout_target, new_events))
 }
 
+/// Find the event cursor immediately before the given operation, so that
+/// undoing to it will undo that operation and all subsequent operations.
+///
+/// The operation may be an event transaction ID, or otherwise text to search
+/// for in the descriptions of operations, in which case the most recent
+/// matching operation is used. Operations which didn't record any events are
+/// skipped. If no operation matches, the text is parsed as a time, such as `10
+/// minutes ago`, in which case every operation which wasn't complete by then
+/// is undone.
+fn find_operation_cursor(
+    event_log_db: &EventLogDb,
+    event_replayer: &EventReplayer,
+    operation: &str,
+) -> eyre::Result<Option<EventCursor>> {
+    if let Ok(event_tx_id) = operation.parse::<isize>() {
+        return Ok(
+            event_replayer.make_cursor_before_transaction(EventTransactionId::Id(event_tx_id))
+        );
+    }
+
+    let event_cursor = event_log_db
+        .get_transactions()?
+        .into_iter()
+        .rev()
+        .filter(|transaction| {
+            transaction.message == operation || transaction.get_description().contains(operation)
+        })
+        .find_map(|transaction| {
+            event_replayer.make_cursor_before_transaction(transaction.event_tx_id)
+        });
+    if event_cursor.is_some() {
+        return Ok(event_cursor);
+    }
+
+    let event_cursor = parse_time(operation)
+        .map(|time| event_replayer.make_transaction_cursor_at_time(time.into()));
+    Ok(event_cursor)
+}
+
+/// Determine how to check out the undo target given the uncommitted changes
+/// in the working copy, if any.
+///
+/// If there are uncommitted changes and neither `force` nor `merge` was
+/// requested, the changes are preserved in a working copy snapshot (if
+/// snapshots are enabled), or else the operation is aborted, rather than
+/// letting `git checkout` fail partway through the undo.
+fn handle_uncommitted_changes(
+    effects: &Effects,
+    git_run_info: &GitRunInfo,
+    repo: &Repo,
+    event_tx_id: EventTransactionId,
+    mut checkout_target: UndoCheckoutTarget,
+    force: bool,
+    merge: bool,
+) -> EyreExitOr<UndoCheckoutTarget> {
+    if force {
+        checkout_target
+            .options
+            .additional_args
+            .push("--force".into());
+        return Ok(Ok(checkout_target));
+    }
+    if merge {
+        checkout_target
+            .options
+            .additional_args
+            .push("--merge".into());
+        return Ok(Ok(checkout_target));
+    }
+
+    let head_info = repo.get_head_info()?;
+    let index = repo.get_index()?;
+    let (_snapshot, status) =
+        repo.get_status(effects, git_run_info, &index, &head_info, Some(event_tx_id))?;
+    let has_uncommitted_changes = status.iter().any(|entry| {
+        entry.index_status.is_changed()
+            || entry.working_copy_status.is_changed()
+            || entry.index_status == FileStatus::Unmerged
+            || entry.working_copy_status == FileStatus::Unmerged
+    });
+    if !has_uncommitted_changes {
+        return Ok(Ok(checkout_target));
+    }
+
+    if get_undo_create_snapshots(repo)? {
+        // `check_out_commit` snapshots the working copy before checking out
+        // the target, so it's safe to overwrite the uncommitted changes here.
+        writeln!(
+            effects.get_output_stream(),
+            "Your uncommitted changes will be saved in a working copy snapshot; run `git undo` to restore them."
+        )?;
+        checkout_target
+            .options
+            .additional_args
+            .push("--force".into());
+        Ok(Ok(checkout_target))
+    } else {
+        write!(
+            effects.get_output_stream(),
+            "\
+This operation would check out a different commit, but you have uncommitted
+changes in your working copy which might be overwritten as a result.
+Commit your changes and then try again, or re-run with --merge to carry them
+over or --force to discard them.
+"
+        )?;
+        Ok(Err(ExitCode(1)))
+    }
+}
+
+/// Parse the user's selection of which numbered actions to apply, such as `1
+/// 3-4` or `1,3,4`. Returns the zero-based indexes of the selected actions in
+/// ascending order, or an error message describing the invalid input.
+fn parse_event_selection(input: &str, num_events: usize) -> Result<Vec<usize>, String> {
+    let parse_number = |number: &str| -> Result<usize, String> {
+        match number.trim().parse::<usize>() {
+            Ok(number) if (1..=num_events).contains(&number) => Ok(number - 1),
+            Ok(number) => Err(format!(
+                "Action {number} is out of range (expected 1-{num_events})"
+            )),
+            Err(_) => Err(format!("Invalid action number: {number:?}")),
+        }
+    };
+
+    let mut result = Vec::new();
+    for item in input
+        .split(|c: char| c == ',' || c.is_whitespace())
+        .filter(|item| !item.is_empty())
+    {
+        match item.split_once('-') {
+            Some((start, end)) => {
+                let start = parse_number(start)?;
+                let end = parse_number(end)?;
+                if start > end {
+                    return Err(format!("Invalid action range: {item:?}"));
+                }
+                result.extend(start..=end);
+            }
+            None => result.push(parse_number(item)?),
+        }
+    }
+    result.sort_unstable();
+    result.dedup();
+    Ok(result)
+}
+
 #[instrument(skip(in_))]
 fn undo_events(
     in_: &mut impl Read,
@@ -780,8 +956,12 @@ fn undo_events(
     event_log_db: &mut EventLogDb,
     event_replayer: &EventReplayer,
     event_cursor: EventCursor,
+    select_events: bool,
     skip_confirmation: bool,
+    force: bool,
+    merge: bool,
 ) -> EyreExitOr<()> {
+    let mut reader = BufReader::new(in_);
     let now = SystemTime::now();
     let event_tx_id = event_log_db.make_transaction_id(now, "undo")?;
     let head_info = repo.get_head_info()?;
@@ -802,6 +982,10 @@ fn undo_events(
                 } if ref_name.as_str() == "HEAD"
             )
         })
+        // A copy event only records where a commit came from, so there's
+        // nothing to undo. The copied commit itself is hidden by undoing its
+        // `CommitEvent`.
+        .filter(|event| !matches!(event, Event::CopyEvent { .. }))
         .map(|event| inverse_event(event.clone(), now, event_tx_id))
         .collect::<eyre::Result<Vec<Event>>>()?;
 
@@ -812,22 +996,70 @@ fn undo_events(
         )?;
         return Ok(Ok(()));
     }
-    writeln!(effects.get_output_stream(), "Will apply these actions:")?;
-    let events = describe_events_numbered(effects.get_glyphs(), repo, &inverse_events)?;
-    for line in events {
-        writeln!(
+    let print_inverse_events = |inverse_events: &[Event]| -> eyre::Result<()> {
+        writeln!(effects.get_output_stream(), "Will apply these actions:")?;
+        let events = describe_events_numbered(effects.get_glyphs(), repo, inverse_events)?;
+        for line in events {
+            writeln!(
+                effects.get_output_stream(),
+                "{}",
+                effects.get_glyphs().render(line)?
+            )?;
+        }
+        Ok(())
+    };
+    print_inverse_events(&inverse_events)?;
+
+    let inverse_events = if select_events && inverse_events.len() > 1 {
+        write!(
             effects.get_output_stream(),
-            "{}",
-            effects.get_glyphs().render(line)?
+            "Select actions to apply (e.g. 1 3-4), or press enter to apply all: "
         )?;
-    }
+        let mut user_input = String::new();
+        reader.read_line(&mut user_input)?;
+        let selected_idxs = match parse_event_selection(&user_input, inverse_events.len()) {
+            Ok(selected_idxs) => selected_idxs,
+            Err(message) => {
+                writeln!(effects.get_output_stream(), "{message}")?;
+                writeln!(effects.get_output_stream(), "Aborted.")?;
+                return Ok(Err(ExitCode(1)));
+            }
+        };
+        if selected_idxs.is_empty() {
+            inverse_events
+        } else {
+            let inverse_events: Vec<Event> = inverse_events
+                .into_iter()
+                .enumerate()
+                .filter(|(idx, _event)| selected_idxs.contains(idx))
+                .map(|(_idx, event)| event)
+                .collect();
+            print_inverse_events(&inverse_events)?;
+            inverse_events
+        }
+    } else {
+        inverse_events
+    };
+
+    let (checkout_target, filtered_events) = extract_checkout_target(&inverse_events)?;
+    let checkout_target = match checkout_target {
+        Some(checkout_target) => Some(try_exit_code!(handle_uncommitted_changes(
+            effects,
+            git_run_info,
+            repo,
+            event_tx_id,
+            checkout_target,
+            force,
+            merge,
+        )?)),
+        None => None,
+    };
 
     let confirmed = if skip_confirmation {
         true
     } else {
         write!(effects.get_output_stream(), "Confirm? [yN] ")?;
         let mut user_input = String::new();
-        let mut reader = BufReader::new(in_);
         match reader.read_line(&mut user_input) {
             Ok(_size) => {
                 let user_input = user_input.trim();
@@ -848,7 +1080,6 @@ fn undo_events(
     }
     .to_string();
 
-    let (checkout_target, filtered_events) = extract_checkout_target(&inverse_events)?;
     if checkout_target.is_some() {
         repo.detach_head(&head_info)?;
     }
@@ -873,6 +1104,11 @@ fn undo_events(
                 message: _,
             } => match repo.find_reference(ref_name)? {
                 Some(mut reference) => {
+                    if is_remote_tracking_reference(ref_name) {
+                        if let Some(commit) = reference.peel_to_commit()? {
+                            mark_commit_reachable(repo, commit.get_oid())?;
+                        }
+                    }
                     reference.delete().wrap_err("Applying `RefUpdateEvent`")?;
                 }
                 None => {
@@ -899,14 +1135,32 @@ fn undo_events(
                 new_oid: MaybeZeroOid::NonZero(new_oid),
                 message: _,
             } => {
+                if repo.find_commit(*new_oid)?.is_none() {
+                    writeln!(
+                        effects.get_output_stream(),
+                        "Commit {new_oid} no longer exists, not restoring reference {}.",
+                        ref_name.as_str()
+                    )?;
+                    continue;
+                }
+
                 // Create or update the given reference.
                 repo.create_reference(ref_name, *new_oid, true, "branchless undo")?;
+                if is_remote_tracking_reference(ref_name) {
+                    // The remote-tracking branch may be deleted again by the
+                    // next `git fetch --prune`.
+                    mark_commit_reachable(repo, *new_oid)?;
+                }
             }
 
             Event::WorkingCopySnapshot { .. } => {
                 // Should be handled as the checkout target already.
             }
 
+            Event::CopyEvent { .. } => {
+                // Doesn't affect the state of the repository.
+            }
+
             Event::CommitEvent { .. }
             | Event::ObsoleteEvent { .. }
             | Event::UnobsoleteEvent { .. }
@@ -933,13 +1187,26 @@ fn undo_events(
     Ok(Ok(()))
 }
 
+/// Whether the reference is a remote-tracking branch. These are updated by
+/// `git fetch` rather than by the user, so their commits aren't otherwise kept
+/// alive by branchless.
+fn is_remote_tracking_reference(ref_name: &ReferenceName) -> bool {
+    matches!(
+        CategorizedReferenceName::new(ref_name),
+        CategorizedReferenceName::RemoteBranch { .. }
+    )
+}
+
 /// Restore the repository to a previous state interactively.
 #[instrument]
 pub fn undo(
     effects: &Effects,
     git_run_info: &GitRunInfo,
     interactive: bool,
+    to: Option<String>,
     skip_confirmation: bool,
+    force: bool,
+    merge: bool,
 ) -> EyreExitOr<()> {
     let repo = Repo::from_current_dir()?;
     let references_snapshot = repo.get_references_snapshot()?;
@@ -969,6 +1236,17 @@ pub fn undo(
                 Some(event_cursor) => event_cursor,
                 None => return Ok(Ok(())),
             }
+        } else if let Some(operation) = to {
+            match find_operation_cursor(&event_log_db, &event_replayer, &operation)? {
+                Some(event_cursor) => event_cursor,
+                None => {
+                    writeln!(
+                        effects.get_error_stream(),
+                        "No operation found matching: {operation}"
+                    )?;
+                    return Ok(Err(ExitCode(1)));
+                }
+            }
         } else {
             event_replayer.advance_cursor_by_transaction(event_replayer.make_default_cursor(), -1)
         }
@@ -982,7 +1260,10 @@ pub fn undo(
         &mut event_log_db,
         &event_replayer,
         event_cursor,
+        interactive,
         skip_confirmation,
+        force,
+        merge,
     )?;
     Ok(result)
 }
@@ -1027,6 +1308,33 @@ pub mod testing {
             event_replayer,
             event_cursor,
             false,
+            false,
+            false,
+            false,
+        )
+    }
+
+    pub fn undo_selected_events(
+        in_: &mut impl Read,
+        effects: &Effects,
+        repo: &Repo,
+        git_run_info: &GitRunInfo,
+        event_log_db: &mut EventLogDb,
+        event_replayer: &EventReplayer,
+        event_cursor: EventCursor,
+    ) -> EyreExitOr<()> {
+        super::undo_events(
+            in_,
+            effects,
+            repo,
+            git_run_info,
+            event_log_db,
+            event_replayer,
+            event_cursor,
+            true,
+            false,
+            false,
+            false,
         )
     }
 }
@@ -1079,4 +1387,29 @@ mod tests {
         "###);
         Ok(())
     }
+
+    #[test]
+    fn test_parse_event_selection() {
+        assert_eq!(parse_event_selection("", 3), Ok(vec![]));
+        assert_eq!(parse_event_selection("2\n", 3), Ok(vec![1]));
+        assert_eq!(parse_event_selection("3 1", 3), Ok(vec![0, 2]));
+        assert_eq!(parse_event_selection("1,2-3", 4), Ok(vec![0, 1, 2]));
+        assert_eq!(parse_event_selection("1-2 2", 4), Ok(vec![0, 1]));
+        assert_eq!(
+            parse_event_selection("0", 3),
+            Err("Action 0 is out of range (expected 1-3)".to_string())
+        );
+        assert_eq!(
+            parse_event_selection("4", 3),
+            Err("Action 4 is out of range (expected 1-3)".to_string())
+        );
+        assert_eq!(
+            parse_event_selection("3-1", 3),
+            Err("Invalid action range: \"3-1\"".to_string())
+        );
+        assert_eq!(
+            parse_event_selection("y", 3),
+            Err("Invalid action number: \"y\"".to_string())
+        );
+    }
 }
@@ -1,8 +1,8 @@
 //! Amend the current commit.
 //!
-//! This command amends the HEAD commit with changes to files
-//! that are already tracked in the repo. Following the amend,
-//! the command performs a restack.
+//! This command amends the HEAD commit (or one of its ancestors) with changes
+//! to files that are already tracked in the repo. Following the amend, the
+//! command performs a restack.
 
 use std::collections::HashMap;
 
@@ -12,7 +12,9 @@ use std::time::{SystemTime, UNIX_EPOCH};
 use bstr::ByteSlice;
 
 use eyre::Context;
-use git_branchless_opts::{MoveOptions, ResolveRevsetOptions};
+use git_branchless_opts::{MoveOptions, ResolveRevsetOptions, Revset};
+use git_branchless_record::stage_changes_interactive;
+use git_branchless_revset::resolve_commits;
 use itertools::Itertools;
 use lib::core::check_out::{check_out_commit, CheckOutCommitOptions, CheckoutTarget};
 use lib::core::config::get_restack_preserve_timestamps;
@@ -21,18 +23,32 @@ use lib::core::effects::Effects;
 use lib::core::eventlog::{Event, EventLogDb, EventReplayer};
 use lib::core::formatting::Pluralize;
 use lib::core::gc::mark_commit_reachable;
+use lib::core::notes::mirror_rewrites_to_notes;
 use lib::core::repo_ext::RepoExt;
+use lib::core::rewrite::rewrite_hooks::{lint_commit_messages, notify_post_rewrite_command};
 use lib::core::rewrite::{
-    execute_rebase_plan, move_branches, BuildRebasePlanOptions, ExecuteRebasePlanOptions,
-    ExecuteRebasePlanResult, RebasePlanBuilder, RebasePlanPermissions, RepoResource,
+    execute_rebase_plan, move_branches, update_edit_state, BuildRebasePlanOptions,
+    ExecuteRebasePlanOptions, ExecuteRebasePlanResult, FailedMergeInfo, RebasePlanBuilder,
+    RebasePlanPermissions, RepoResource,
+};
+use lib::git::{
+    AmendFastOptions, CherryPickFastOptions, CreateCommitFastError, GitRunInfo, MaybeZeroOid, Repo,
+    ResolvedReferenceInfo, WorkingCopyChangesType,
 };
-use lib::git::{AmendFastOptions, GitRunInfo, MaybeZeroOid, Repo, ResolvedReferenceInfo};
 use lib::try_exit_code;
 use lib::util::{ExitCode, EyreExitOr};
 use rayon::ThreadPoolBuilder;
 use tracing::instrument;
 
 /// Amends the existing HEAD commit.
+///
+/// If `to` is provided, then the changes are amended into that commit instead,
+/// which must be an ancestor of HEAD. Its descendants, including HEAD, are then
+/// restacked in-memory on top of the amended commit, so that the changes end up
+/// committed rather than in the working copy.
+///
+/// If `patch` is set, then the user selects which changes to amend, and the
+/// rest are left in the working copy.
 #[instrument]
 pub fn amend(
     effects: &Effects,
@@ -40,6 +56,8 @@ pub fn amend(
     resolve_revset_options: &ResolveRevsetOptions,
     move_options: &MoveOptions,
     reparent: bool,
+    to: Option<Revset>,
+    patch: bool,
 ) -> EyreExitOr<()> {
     let now = SystemTime::now();
     let timestamp = now.duration_since(SystemTime::UNIX_EPOCH)?.as_secs_f64();
@@ -70,6 +88,44 @@ pub fn amend(
     };
     let head_commit = repo.find_commit_or_fail(head_oid)?;
 
+    let target_oid = match &to {
+        None => head_oid,
+        Some(to) => {
+            let commit_sets = match resolve_commits(
+                effects,
+                &repo,
+                &mut dag,
+                &[to.clone()],
+                resolve_revset_options,
+            ) {
+                Ok(commit_sets) => commit_sets,
+                Err(err) => {
+                    err.describe(effects)?;
+                    return Ok(Err(ExitCode(1)));
+                }
+            };
+            match dag.commit_set_to_vec(&commit_sets[0])?.as_slice() {
+                [target_oid] => *target_oid,
+                other => {
+                    writeln!(
+                        effects.get_error_stream(),
+                        "The commit to amend must be exactly one commit, but {to} resolved to {} commits.",
+                        other.len()
+                    )?;
+                    return Ok(Err(ExitCode(1)));
+                }
+            }
+        }
+    };
+    if !dag.query_is_ancestor(target_oid, head_oid)? {
+        writeln!(
+            effects.get_error_stream(),
+            "Cannot amend {target_oid}, because it is not an ancestor of HEAD."
+        )?;
+        return Ok(Err(ExitCode(1)));
+    }
+    let target_commit = repo.find_commit_or_fail(target_oid)?;
+
     let index = repo.get_index()?;
     if index.has_conflicts() {
         writeln!(
@@ -85,7 +141,7 @@ pub fn amend(
         dump_rebase_plan: move_options.dump_rebase_plan,
         detect_duplicate_commits_via_patch_id: move_options.detect_duplicate_commits_via_patch_id,
     };
-    let commits_to_verify = dag.query_descendants(CommitSet::from(head_oid))?;
+    let commits_to_verify = dag.query_descendants(CommitSet::from(target_oid))?;
     let commits_to_verify = dag.filter_visible_commits(commits_to_verify)?;
     if let Err(err) =
         RebasePlanPermissions::verify_rewrite_set(&dag, build_options, &commits_to_verify)?
@@ -111,6 +167,43 @@ pub fn amend(
         }])?;
     }
 
+    let status = if patch {
+        if snapshot.get_working_copy_changes_type()? == WorkingCopyChangesType::Staged {
+            writeln!(
+                effects.get_output_stream(),
+                "Cannot select changes interactively while there are already staged changes."
+            )?;
+            writeln!(
+                effects.get_output_stream(),
+                "Either amend or unstage your changes and try again. Aborting."
+            )?;
+            return Ok(Err(ExitCode(1)));
+        }
+        try_exit_code!(stage_changes_interactive(
+            effects,
+            git_run_info,
+            &repo,
+            &snapshot,
+            event_tx_id,
+            None
+        )?);
+
+        // Amend only the changes which were just staged.
+        let index = repo.get_index()?;
+        let (_snapshot, status) =
+            repo.get_status(effects, git_run_info, &index, &head_info, Some(event_tx_id))?;
+        if !status.iter().any(|entry| entry.index_status.is_changed()) {
+            writeln!(
+                effects.get_output_stream(),
+                "No changes were selected. Nothing to amend."
+            )?;
+            return Ok(Ok(()));
+        }
+        status
+    } else {
+        status
+    };
+
     // Note that there may be paths which are in both of these entries in the
     // case that the given path has both staged and unstaged changes.
     let staged_entries = status
@@ -144,8 +237,55 @@ pub fn amend(
     }
 
     let amended_tree = repo.amend_fast(&head_commit, &opts)?;
+    let amended_tree = if target_oid == head_oid {
+        amended_tree
+    } else {
+        // Apply the changes to the target commit by cherry-picking them, as if
+        // they had been committed on top of HEAD.
+        let changes_oid = repo.create_commit(
+            None,
+            &head_commit.get_author(),
+            &head_commit.get_committer(),
+            "branchless: changes to amend",
+            &amended_tree,
+            vec![&head_commit],
+        )?;
+        let changes_commit = repo.find_commit_or_fail(changes_oid)?;
+        match repo.cherry_pick_fast(
+            &changes_commit,
+            &target_commit,
+            &CherryPickFastOptions {
+                reuse_parent_tree_if_possible: true,
+            },
+        ) {
+            Ok(tree) => tree,
+            Err(CreateCommitFastError::MergeConflict { conflicting_paths }) => {
+                let glyphs = effects.get_glyphs();
+                writeln!(
+                    effects.get_output_stream(),
+                    "The changes can't be amended into {}, since they conflict with it in {}:",
+                    glyphs.render(target_commit.friendly_describe(glyphs)?)?,
+                    Pluralize {
+                        determiner: None,
+                        amount: conflicting_paths.len(),
+                        unit: ("file", "files"),
+                    },
+                )?;
+                for path in conflicting_paths.iter().sorted() {
+                    writeln!(
+                        effects.get_output_stream(),
+                        "{} {}",
+                        glyphs.bullet_point,
+                        path.display()
+                    )?;
+                }
+                return Ok(Err(ExitCode(1)));
+            }
+            Err(other) => eyre::bail!(other),
+        }
+    };
 
-    let (author, committer) = (head_commit.get_author(), head_commit.get_committer());
+    let (author, committer) = (target_commit.get_author(), target_commit.get_committer());
     let (author, committer) = if get_restack_preserve_timestamps(&repo)? {
         (author, committer)
     } else {
@@ -155,7 +295,7 @@ pub fn amend(
         )
     };
 
-    let amended_commit_oid = head_commit.amend_commit(
+    let amended_commit_oid = target_commit.amend_commit(
         None,
         Some(&author),
         Some(&committer),
@@ -163,32 +303,62 @@ pub fn amend(
         Some(&amended_tree),
     )?;
 
-    // Switch to the new commit and move any branches. This is kind of a hack:
-    // ideally, we would use the same rebase plan machinery to accomplish this
-    // and also rebase any descendants. However, this operation should always
-    // succeed, and we want to execute it regardless of whether the rest of the
-    // rebase would succeed without conflicts, so instead we (re)write a bunch
-    // of logic to switch commits and move branches.
-    {
+    let is_amending_ancestor = target_oid != head_oid;
+    if !is_amending_ancestor {
+        if !lint_commit_messages(
+            effects,
+            git_run_info,
+            &repo,
+            event_tx_id,
+            [amended_commit_oid],
+        )? {
+            writeln!(
+                effects.get_output_stream(),
+                "Aborting since the commit message was rejected."
+            )?;
+            return Ok(Err(ExitCode(1)));
+        }
+
+        // Switch to the new commit and move any branches. This is kind of a
+        // hack: ideally, we would use the same rebase plan machinery to
+        // accomplish this and also rebase any descendants. However, this
+        // operation should always succeed, and we want to execute it
+        // regardless of whether the rest of the rebase would succeed without
+        // conflicts, so instead we (re)write a bunch of logic to switch
+        // commits and move branches.
         mark_commit_reachable(&repo, amended_commit_oid)
             .wrap_err("Marking commit as reachable for GC purposes.")?;
         event_log_db.add_events(vec![Event::RewriteEvent {
             timestamp: now.duration_since(UNIX_EPOCH)?.as_secs_f64(),
             event_tx_id,
-            old_commit_oid: MaybeZeroOid::NonZero(head_oid),
+            old_commit_oid: MaybeZeroOid::NonZero(target_oid),
             new_commit_oid: MaybeZeroOid::NonZero(amended_commit_oid),
         }])?;
+        mirror_rewrites_to_notes(
+            &repo,
+            [(target_oid, MaybeZeroOid::NonZero(amended_commit_oid))],
+        )?;
         dag.sync_from_oids(
             effects,
             &repo,
             CommitSet::empty(),
             CommitSet::from(amended_commit_oid),
         )?;
-        move_branches(effects, git_run_info, &repo, event_tx_id, &{
+        let rewritten_oids = {
             let mut result = HashMap::new();
-            result.insert(head_oid, MaybeZeroOid::NonZero(amended_commit_oid));
+            result.insert(target_oid, MaybeZeroOid::NonZero(amended_commit_oid));
             result
-        })?;
+        };
+        move_branches(effects, git_run_info, &repo, event_tx_id, &rewritten_oids)?;
+        notify_post_rewrite_command(
+            effects,
+            git_run_info,
+            &repo,
+            event_tx_id,
+            "amend",
+            &rewritten_oids,
+        )?;
+        update_edit_state(&repo, &rewritten_oids)?;
 
         let target = match &head_info.reference_name {
             Some(name) => CheckoutTarget::Reference(name.clone()),
@@ -217,14 +387,18 @@ pub fn amend(
             dump_rebase_constraints: move_options.dump_rebase_constraints,
             dump_rebase_plan: move_options.dump_rebase_plan,
         };
-        let children = dag.query_children(CommitSet::from(head_oid))?;
+        let children = dag.query_children(CommitSet::from(target_oid))?;
         let descendants = dag.query_descendants(children)?;
         let descendants = dag.filter_visible_commits(descendants)?;
-        let commits_to_verify = &descendants;
+        let commits_to_verify = if is_amending_ancestor {
+            descendants.union(&CommitSet::from(target_oid))
+        } else {
+            descendants.clone()
+        };
         let permissions = match RebasePlanPermissions::verify_rewrite_set(
             &dag,
             build_options,
-            commits_to_verify,
+            &commits_to_verify,
         )? {
             Ok(permissions) => permissions,
             Err(err) => {
@@ -234,45 +408,61 @@ pub fn amend(
         };
 
         let mut builder = RebasePlanBuilder::new(&dag, permissions);
-        for descendant_oid in dag.commit_set_to_vec(&descendants)? {
-            let descendant_commit = repo.find_commit_or_fail(descendant_oid)?;
-            let parent_oids: Vec<_> = descendant_commit
-                .get_parent_oids()
-                .into_iter()
-                .map(|parent_oid| {
-                    if parent_oid == head_oid {
-                        amended_commit_oid
-                    } else {
-                        parent_oid
-                    }
-                })
-                .collect();
-            builder.move_subtree(descendant_oid, parent_oids.clone())?;
+        if is_amending_ancestor {
+            // Replace the target commit as part of the same rebase which
+            // restacks its descendants, so that no branches are moved unless
+            // the whole operation succeeds.
+            builder.move_subtree(target_oid, target_commit.get_parent_oids())?;
+            builder.replace_commit(target_oid, amended_commit_oid)?;
 
-            // To keep the contents of all descendant commits the same, forcibly
-            // replace the children commits, and then rely on normal patch
-            // application to apply the rest.
+            // To keep the contents of all descendant commits the same, replace
+            // them with themselves, which only updates their parents.
             if reparent {
-                let parents: Vec<_> = parent_oids
+                for descendant_oid in dag.commit_set_to_vec(&descendants)? {
+                    builder.replace_commit(descendant_oid, descendant_oid)?;
+                }
+            }
+        } else {
+            for descendant_oid in dag.commit_set_to_vec(&descendants)? {
+                let descendant_commit = repo.find_commit_or_fail(descendant_oid)?;
+                let parent_oids: Vec<_> = descendant_commit
+                    .get_parent_oids()
                     .into_iter()
-                    .map(|parent_oid| repo.find_commit_or_fail(parent_oid))
-                    .try_collect()?;
-                let descendant_message = descendant_commit.get_message_raw();
-                let descendant_message = descendant_message.to_str().with_context(|| {
-                    eyre::eyre!(
-                        "Could not decode commit message for descendant commit: {:?}",
-                        descendant_commit
-                    )
-                })?;
-                let reparented_descendant_oid = repo.create_commit(
-                    None,
-                    &descendant_commit.get_author(),
-                    &descendant_commit.get_committer(),
-                    descendant_message,
-                    &descendant_commit.get_tree()?,
-                    parents.iter().collect(),
-                )?;
-                builder.replace_commit(descendant_oid, reparented_descendant_oid)?;
+                    .map(|parent_oid| {
+                        if parent_oid == target_oid {
+                            amended_commit_oid
+                        } else {
+                            parent_oid
+                        }
+                    })
+                    .collect();
+                builder.move_subtree(descendant_oid, parent_oids.clone())?;
+
+                // To keep the contents of all descendant commits the same,
+                // forcibly replace the children commits, and then rely on
+                // normal patch application to apply the rest.
+                if reparent {
+                    let parents: Vec<_> = parent_oids
+                        .into_iter()
+                        .map(|parent_oid| repo.find_commit_or_fail(parent_oid))
+                        .try_collect()?;
+                    let descendant_message = descendant_commit.get_message_raw();
+                    let descendant_message = descendant_message.to_str().with_context(|| {
+                        eyre::eyre!(
+                            "Could not decode commit message for descendant commit: {:?}",
+                            descendant_commit
+                        )
+                    })?;
+                    let reparented_descendant_oid = repo.create_commit(
+                        None,
+                        &descendant_commit.get_author(),
+                        &descendant_commit.get_committer(),
+                        descendant_message,
+                        &descendant_commit.get_tree()?,
+                        parents.iter().collect(),
+                    )?;
+                    builder.replace_commit(descendant_oid, reparented_descendant_oid)?;
+                }
             }
         }
 
@@ -288,16 +478,19 @@ pub fn amend(
     };
 
     if let Some(rebase_plan) = rebase_plan {
+        // When amending an ancestor, the amended changes are still in the
+        // working copy, so the rebase must not touch it: HEAD is restacked
+        // in-memory and then only reset to its new version.
         let execute_options = ExecuteRebasePlanOptions {
             now,
             event_tx_id,
-            force_in_memory: move_options.force_in_memory,
-            force_on_disk: move_options.force_on_disk,
+            force_in_memory: move_options.force_in_memory || is_amending_ancestor,
+            force_on_disk: move_options.force_on_disk && !is_amending_ancestor,
             preserve_timestamps: get_restack_preserve_timestamps(&repo)?,
             resolve_merge_conflicts: move_options.resolve_merge_conflicts,
             check_out_commit_options: CheckOutCommitOptions {
                 additional_args: Default::default(),
-                reset: false,
+                reset: is_amending_ancestor,
                 render_smartlog: false,
             },
         };
@@ -316,17 +509,60 @@ pub fn amend(
             ExecuteRebasePlanResult::Succeeded {
                 rewritten_oids: Some(rewritten_oids),
             } => {
+                // Resetting to the restacked HEAD leaves it detached, so
+                // reattach it to its branch, if any.
+                if let Some(reference_name) = &head_info.reference_name {
+                    if is_amending_ancestor && repo.get_head_info()?.reference_name.is_none() {
+                        try_exit_code!(check_out_commit(
+                            effects,
+                            git_run_info,
+                            &repo,
+                            &event_log_db,
+                            event_tx_id,
+                            Some(CheckoutTarget::Reference(reference_name.clone())),
+                            &CheckOutCommitOptions {
+                                additional_args: Default::default(),
+                                reset: false,
+                                render_smartlog: false,
+                            },
+                        )?);
+                    }
+                }
+                let num_restacked_commits = if is_amending_ancestor {
+                    // Don't count the amended commit itself.
+                    rewritten_oids.len() - 1
+                } else {
+                    rewritten_oids.len()
+                };
                 writeln!(
                     effects.get_output_stream(),
                     "Restacked {}.",
                     Pluralize {
                         determiner: None,
-                        amount: rewritten_oids.len(),
+                        amount: num_restacked_commits,
                         unit: ("commit", "commits")
                     }
                 )?;
             }
 
+            ExecuteRebasePlanResult::DeclinedToMerge { failed_merge_info }
+                if is_amending_ancestor =>
+            {
+                let (FailedMergeInfo::Conflict { commit_oid, .. }
+                | FailedMergeInfo::CannotRebaseMergeInMemory { commit_oid }) = failed_merge_info;
+                let glyphs = effects.get_glyphs();
+                writeln!(
+                    effects.get_output_stream(),
+                    "Aborting, since {} couldn't be restacked on top of the amended commit.",
+                    glyphs.render(repo.friendly_describe_commit_from_oid(glyphs, commit_oid)?)?,
+                )?;
+                writeln!(
+                    effects.get_output_stream(),
+                    "The changes were left in the working copy."
+                )?;
+                return Ok(Err(ExitCode(1)));
+            }
+
             ExecuteRebasePlanResult::DeclinedToMerge { failed_merge_info } => {
                 failed_merge_info.describe(
                     effects,
@@ -338,7 +574,7 @@ pub fn amend(
                     "Amending without restacking descendant commits: {}",
                     effects
                         .get_glyphs()
-                        .render(head_commit.friendly_describe(effects.get_glyphs())?)?
+                        .render(target_commit.friendly_describe(effects.get_glyphs())?)?
                 )?;
             }
 
@@ -348,6 +584,10 @@ pub fn amend(
         }
     }
 
+    event_log_db.set_transaction_label(
+        event_tx_id,
+        format!("amend {}", target_commit.get_short_oid()?),
+    )?;
     match opts {
         AmendFastOptions::FromIndex { paths } => {
             let staged_changes = Pluralize {
@@ -378,5 +618,19 @@ pub fn amend(
         }
     }
 
+    // Only the commit checked out in a submodule can be recorded, so let the
+    // user know if there were changes inside the submodule itself.
+    for entry in unstaged_entries.iter().filter(|entry| {
+        entry
+            .submodule_state
+            .map_or(false, |submodule_state| submodule_state.is_dirty())
+    }) {
+        writeln!(
+            effects.get_output_stream(),
+            "Submodule {} has uncommitted changes of its own, which were not amended.",
+            entry.path.display()
+        )?;
+    }
+
     Ok(Ok(()))
 }
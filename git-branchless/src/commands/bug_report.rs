@@ -14,6 +14,7 @@ use lib::util::EyreExitOr;
 
 use git_branchless_revset::resolve_default_smartlog_commits;
 use git_branchless_smartlog::{make_smartlog_graph, render_graph};
+use lib::core::commit_metadata::CommitMetadataDescriptor;
 use lib::core::dag::Dag;
 use lib::core::effects::Effects;
 use lib::core::eventlog::{Event, EventCursor, EventLogDb, EventReplayer};
@@ -25,7 +26,7 @@ use lib::core::node_descriptors::{
 };
 use lib::git::{GitRunInfo, Repo, ResolvedReferenceInfo};
 
-use git_branchless_init::{determine_hook_path, Hook, ALL_HOOKS};
+use git_branchless_init::{determine_hook_path, get_hooks_to_install, Hook};
 
 fn redact_event(redactor: &Redactor, event: &Event) -> String {
     let event = match event.clone() {
@@ -50,6 +51,13 @@ fn redact_event(redactor: &Redactor, event: &Event) -> String {
             timestamp: _,
             event_tx_id: _,
             commit_oid: _,
+        }
+        | Event::CopyEvent {
+            timestamp: _,
+            event_tx_id: _,
+            copy_type: _,
+            source_commit_oid: _,
+            new_commit_oid: _,
         }) => event,
 
         Event::RefUpdateEvent {
@@ -146,6 +154,7 @@ fn describe_event_cursor(
             &mut ObsolescenceExplanationDescriptor::new(event_replayer, event_cursor)?,
             &mut BranchesDescriptor::new(repo, head_info, references_snapshot, redactor)?,
             &mut DifferentialRevisionDescriptor::new(repo, redactor)?,
+            &mut CommitMetadataDescriptor::new(repo, redactor)?,
             &mut CommitMessageDescriptor::new(redactor)?,
         ],
     )?;
@@ -182,7 +191,7 @@ fn collect_events(effects: &Effects, git_run_info: &GitRunInfo) -> eyre::Result<
 
     let redactor = Redactor::new({
         let mut preserved_ref_names = HashSet::new();
-        preserved_ref_names.insert(repo.get_main_branch()?.get_reference_name()?);
+        preserved_ref_names.insert(repo.get_main_branch_reference_name()?);
         preserved_ref_names
     });
 
@@ -245,7 +254,7 @@ fn collect_hooks(git_run_info: &GitRunInfo) -> eyre::Result<ReportEntry> {
     let hooks_dir = get_main_worktree_hooks_dir(git_run_info, &repo, None)?;
     let hook_contents = {
         let mut result = Vec::new();
-        for (hook_type, _content) in ALL_HOOKS {
+        for (hook_type, _content) in get_hooks_to_install(&repo)? {
             let hook_path = match determine_hook_path(&repo, &hooks_dir, hook_type)? {
                 Hook::RegularHook { path } | Hook::MultiHook { path } => path,
             };
@@ -0,0 +1,110 @@
+//! Inspect and maintain the caches which git-branchless keeps for a repository.
+//!
+//! Currently, the only such cache is the commit graph (see
+//! [`lib::core::dag::Dag`]), which also answers merge-base queries.
+
+use std::fmt::Write;
+use std::path::Path;
+
+use git_branchless_opts::CacheSubcommand;
+use lib::core::dag::Dag;
+use lib::core::effects::Effects;
+use lib::core::eventlog::{EventLogDb, EventReplayer};
+use lib::core::formatting::Pluralize;
+use lib::core::repo_ext::RepoExt;
+use lib::git::Repo;
+use lib::util::EyreExitOr;
+use tracing::instrument;
+
+/// Get the total size of the files in the given directory, in bytes.
+fn get_dir_size(path: &Path) -> eyre::Result<u64> {
+    let mut result = 0;
+    if !path.exists() {
+        return Ok(result);
+    }
+    for entry in std::fs::read_dir(path)? {
+        let entry = entry?;
+        let metadata = entry.metadata()?;
+        if metadata.is_dir() {
+            result += get_dir_size(&entry.path())?;
+        } else {
+            result += metadata.len();
+        }
+    }
+    Ok(result)
+}
+
+/// Open the DAG and sync it, rebuilding it if it was cleared.
+fn open_dag(effects: &Effects, repo: &Repo) -> eyre::Result<Dag> {
+    let references_snapshot = repo.get_references_snapshot()?;
+    let conn = repo.get_db_conn()?;
+    let event_log_db = EventLogDb::new(&conn)?;
+    let event_replayer = EventReplayer::from_event_log_db(effects, repo, &event_log_db)?;
+    let event_cursor = event_replayer.make_default_cursor();
+    Dag::open_and_sync(
+        effects,
+        repo,
+        &event_replayer,
+        event_cursor,
+        &references_snapshot,
+    )
+}
+
+/// Run the given `cache` subcommand.
+#[instrument]
+pub fn cache(effects: &Effects, subcommand: CacheSubcommand) -> EyreExitOr<()> {
+    let repo = Repo::from_current_dir()?;
+    match subcommand {
+        CacheSubcommand::Stats => {
+            let dag_dir = repo.get_dag_dir()?;
+            let size = get_dir_size(&dag_dir)?;
+            let synced_main_branch_oid = Dag::get_synced_main_branch_oid(&repo)?;
+            let dag = open_dag(effects, &repo)?;
+            let num_commits = dag.set_count(&dag.query_all()?)?;
+            writeln!(effects.get_output_stream(), "Commit graph cache:")?;
+            writeln!(effects.get_output_stream(), "  Path: {}", dag_dir.display())?;
+            writeln!(effects.get_output_stream(), "  Size: {size} bytes")?;
+            writeln!(
+                effects.get_output_stream(),
+                "  Contains: {}",
+                Pluralize {
+                    determiner: None,
+                    amount: num_commits,
+                    unit: ("commit", "commits"),
+                }
+            )?;
+            writeln!(
+                effects.get_output_stream(),
+                "  Main branch when last synced: {}",
+                match synced_main_branch_oid {
+                    Some(oid) => oid.to_string(),
+                    None => "unknown".to_string(),
+                }
+            )?;
+        }
+
+        CacheSubcommand::Clear => {
+            Dag::clear_cache(&repo)?;
+            writeln!(
+                effects.get_output_stream(),
+                "Cleared the commit graph cache. It will be rebuilt when next needed."
+            )?;
+        }
+
+        CacheSubcommand::Rebuild => {
+            Dag::clear_cache(&repo)?;
+            let dag = open_dag(effects, &repo)?;
+            let num_commits = dag.set_count(&dag.query_all()?)?;
+            writeln!(
+                effects.get_output_stream(),
+                "Rebuilt the commit graph cache with {}.",
+                Pluralize {
+                    determiner: None,
+                    amount: num_commits,
+                    unit: ("commit", "commits"),
+                }
+            )?;
+        }
+    }
+    Ok(Ok(()))
+}
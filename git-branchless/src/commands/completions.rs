@@ -0,0 +1,78 @@
+//! Generate shell completions.
+//!
+//! The completion scripts themselves are generated by
+//! [`git_branchless_opts::write_completions`]. They call back into this
+//! command with `--list-commits` to complete commit arguments dynamically.
+
+use std::fmt::Write;
+
+use git_branchless_opts::{write_completions, CompletionShell};
+use lib::core::dag::Dag;
+use lib::core::effects::Effects;
+use lib::core::eventlog::{EventLogDb, EventReplayer};
+use lib::core::repo_ext::RepoExt;
+use lib::git::Repo;
+use lib::util::EyreExitOr;
+use tracing::instrument;
+
+/// Print the branch names and the short hashes of the visible draft commits,
+/// one per line, to be offered as completions for commit arguments.
+#[instrument]
+fn print_commit_candidates(effects: &Effects) -> EyreExitOr<()> {
+    let repo = Repo::from_current_dir()?;
+    let references_snapshot = repo.get_references_snapshot()?;
+    let conn = repo.get_db_conn()?;
+    let event_log_db = EventLogDb::new(&conn)?;
+    let event_replayer = EventReplayer::from_event_log_db(effects, &repo, &event_log_db)?;
+    let event_cursor = event_replayer.make_default_cursor();
+    let dag = Dag::open_and_sync(
+        effects,
+        &repo,
+        &event_replayer,
+        event_cursor,
+        &references_snapshot,
+    )?;
+
+    let mut branch_names = Vec::new();
+    for branch in repo.get_all_local_branches()? {
+        branch_names.push(branch.get_name()?.to_string());
+    }
+    branch_names.sort();
+    for branch_name in branch_names {
+        writeln!(effects.get_output_stream(), "{branch_name}")?;
+    }
+
+    let draft_commits = dag
+        .query_visible_commits_slow()?
+        .intersection(dag.query_draft_commits()?);
+    for commit_oid in dag.sort(&draft_commits)? {
+        let commit = repo.find_commit_or_fail(commit_oid)?;
+        writeln!(effects.get_output_stream(), "{}", commit.get_short_oid()?)?;
+    }
+
+    Ok(Ok(()))
+}
+
+/// Print the completion script for the given shell, or list the commits to
+/// offer as completions if `list_commits` is set.
+#[instrument]
+pub fn completions(
+    effects: &Effects,
+    shell: Option<CompletionShell>,
+    list_commits: bool,
+) -> EyreExitOr<()> {
+    let shell = match (shell, list_commits) {
+        (_, true) => return print_commit_candidates(effects),
+        (Some(shell), false) => shell,
+        (None, false) => eyre::bail!("No shell provided to generate completions for"),
+    };
+
+    let mut script = Vec::new();
+    write_completions(shell, &mut script)?;
+    write!(
+        effects.get_output_stream(),
+        "{}",
+        String::from_utf8(script)?
+    )?;
+    Ok(Ok(()))
+}
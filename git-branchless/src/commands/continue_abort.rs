@@ -0,0 +1,152 @@
+//! Continue, abort, or show the status of an operation which didn't finish.
+//!
+//! This covers operations which Git left in progress, such as a `git rebase`
+//! which stopped at a merge conflict (including one which you chose to resolve
+//! in the working copy from an in-memory rebase), as well as in-memory
+//! rebases which were interrupted before they finished. See
+//! [`lib::core::rewrite::InterruptedRebaseDb`].
+
+use std::fmt::Write;
+use std::time::SystemTime;
+
+use lib::core::check_out::CheckOutCommitOptions;
+use lib::core::effects::Effects;
+use lib::core::eventlog::EventLogDb;
+use lib::core::formatting::Pluralize;
+use lib::core::rewrite::{
+    abort_interrupted_rebase, continue_interrupted_rebase, InterruptedRebaseDb,
+    InterruptedRebaseStage,
+};
+use lib::git::{GitRunInfo, Repo};
+use lib::util::{ExitCode, EyreExitOr};
+use tracing::instrument;
+
+/// What to do with the operation in progress.
+#[derive(Clone, Copy, Debug)]
+pub enum Action {
+    /// Finish the operation.
+    Continue,
+
+    /// Roll back the operation.
+    Abort,
+}
+
+impl Action {
+    fn as_str(&self) -> &'static str {
+        match self {
+            Action::Continue => "continue",
+            Action::Abort => "abort",
+        }
+    }
+}
+
+/// Continue or abort the operation in progress. An operation which Git left
+/// in progress takes precedence over an interrupted in-memory rebase, since
+/// the former is what's occupying the working copy.
+#[instrument]
+pub fn continue_or_abort(
+    effects: &Effects,
+    git_run_info: &GitRunInfo,
+    action: Action,
+) -> EyreExitOr<()> {
+    let now = SystemTime::now();
+    let repo = Repo::from_current_dir()?;
+    let conn = repo.get_db_conn()?;
+    let event_log_db = EventLogDb::new(&conn)?;
+
+    if let Some(operation_type) = repo.get_current_operation_type() {
+        let event_tx_id = event_log_db.make_transaction_id(now, action.as_str())?;
+        let action_arg = format!("--{}", action.as_str());
+        return git_run_info.run(
+            effects,
+            Some(event_tx_id),
+            &[operation_type, action_arg.as_str()],
+        );
+    }
+
+    let interrupted_rebase_db = InterruptedRebaseDb::new(&conn)?;
+    let interrupted_rebase = match interrupted_rebase_db.load()? {
+        Some(interrupted_rebase) => interrupted_rebase,
+        None => {
+            writeln!(effects.get_output_stream(), "No operation in progress.")?;
+            return Ok(Err(ExitCode(1)));
+        }
+    };
+    match action {
+        Action::Continue => continue_interrupted_rebase(
+            effects,
+            git_run_info,
+            &repo,
+            &event_log_db,
+            &interrupted_rebase_db,
+            &interrupted_rebase,
+            &CheckOutCommitOptions::default(),
+        ),
+        Action::Abort => {
+            let event_tx_id = event_log_db.make_transaction_id(now, "abort")?;
+            abort_interrupted_rebase(
+                effects,
+                git_run_info,
+                &repo,
+                &event_log_db,
+                event_tx_id,
+                now,
+                &interrupted_rebase_db,
+                &interrupted_rebase,
+            )
+        }
+    }
+}
+
+/// Describe the operation in progress, if any.
+#[instrument]
+pub fn status(effects: &Effects) -> EyreExitOr<()> {
+    let repo = Repo::from_current_dir()?;
+    let conn = repo.get_db_conn()?;
+    let interrupted_rebase_db = InterruptedRebaseDb::new(&conn)?;
+
+    let mut any_in_progress = false;
+    if let Some(operation_type) = repo.get_current_operation_type() {
+        any_in_progress = true;
+        writeln!(
+            effects.get_output_stream(),
+            "A `git {operation_type}` is in progress."
+        )?;
+    }
+    if let Some(interrupted_rebase) = interrupted_rebase_db.load()? {
+        any_in_progress = true;
+        match interrupted_rebase.stage {
+            InterruptedRebaseStage::RebaseCommits => writeln!(
+                effects.get_output_stream(),
+                "An in-memory rebase was interrupted while {}.",
+                interrupted_rebase.stage,
+            )?,
+            InterruptedRebaseStage::MoveBranches
+            | InterruptedRebaseStage::PostRewrite
+            | InterruptedRebaseStage::CheckOut => writeln!(
+                effects.get_output_stream(),
+                "An in-memory rebase of {} was interrupted while {}.",
+                Pluralize {
+                    determiner: None,
+                    amount: interrupted_rebase.rewritten_oids.len(),
+                    unit: ("commit", "commits"),
+                },
+                interrupted_rebase.stage,
+            )?,
+        }
+    }
+
+    if any_in_progress {
+        writeln!(
+            effects.get_output_stream(),
+            "To finish it, run: git branchless continue"
+        )?;
+        writeln!(
+            effects.get_output_stream(),
+            "To roll it back, run: git branchless abort"
+        )?;
+    } else {
+        writeln!(effects.get_output_stream(), "No operation in progress.")?;
+    }
+    Ok(Ok(()))
+}
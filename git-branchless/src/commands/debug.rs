@@ -0,0 +1,399 @@
+//! Tools for developing and debugging git-branchless itself.
+//!
+//! `git branchless debug benchmark` generates a synthetic repository using the
+//! same helpers as the integration tests, and times the operations whose
+//! performance depends on the size of the repository, so that regressions can
+//! be measured the same way on every machine.
+//!
+//! `git branchless debug dump-graph` prints the graph which the smartlog is
+//! rendered from, so that it can be visualized or attached to bug reports.
+
+use std::collections::HashSet;
+use std::fmt::Write;
+use std::path::PathBuf;
+use std::process::Command;
+use std::time::{Duration, Instant, SystemTime};
+
+use git_branchless_opts::{DebugSubcommand, DumpGraphFormat, ResolveRevsetOptions, Revset};
+use git_branchless_revset::resolve_commits;
+use git_branchless_smartlog::make_smartlog_graph;
+use lib::core::config::get_smartlog_default_revset;
+use lib::core::dag::{union_all, CommitSet, Dag};
+use lib::core::effects::Effects;
+use lib::core::eventlog::{Event, EventLogDb, EventReplayer};
+use lib::core::formatting::Pluralize;
+use lib::core::node_descriptors::NodeObject;
+use lib::core::repo_ext::RepoExt;
+use lib::core::rewrite::{
+    find_rewrite_target, BuildRebasePlanOptions, RebaseCommand, RebasePlanBuilder,
+    RebasePlanPermissions, RepoResource,
+};
+use lib::git::{GitRunInfo, MaybeZeroOid, NonZeroOid, Repo};
+use lib::testing::{make_git_with_executable, GitInitOptions};
+use lib::util::{ExitCode, EyreExitOr};
+use rayon::ThreadPoolBuilder;
+use tracing::instrument;
+
+/// Run the given `debug` subcommand.
+#[instrument]
+pub fn debug(
+    effects: &Effects,
+    git_run_info: &GitRunInfo,
+    subcommand: DebugSubcommand,
+) -> EyreExitOr<()> {
+    match subcommand {
+        DebugSubcommand::Benchmark {
+            num_commits,
+            num_stacks,
+            stack_size,
+        } => benchmark(effects, git_run_info, num_commits, num_stacks, stack_size),
+
+        DebugSubcommand::DumpGraph {
+            revset,
+            format,
+            resolve_revset_options,
+        } => dump_graph(effects, revset, format, &resolve_revset_options),
+    }
+}
+
+/// Run `f` and return its result along with how long it took.
+fn time<T>(f: impl FnOnce() -> eyre::Result<T>) -> eyre::Result<(T, Duration)> {
+    let start = Instant::now();
+    let result = f()?;
+    Ok((result, start.elapsed()))
+}
+
+#[instrument]
+fn benchmark(
+    effects: &Effects,
+    git_run_info: &GitRunInfo,
+    num_commits: usize,
+    num_stacks: usize,
+    stack_size: usize,
+) -> EyreExitOr<()> {
+    if num_commits == 0 {
+        writeln!(
+            effects.get_error_stream(),
+            "The main branch must have at least one commit to benchmark."
+        )?;
+        return Ok(Err(ExitCode(1)));
+    }
+
+    let git_exec_path = {
+        let output = Command::new(&git_run_info.path_to_git)
+            .arg("--exec-path")
+            .output()?;
+        if !output.status.success() {
+            eyre::bail!("Could not determine the Git exec path");
+        }
+        PathBuf::from(String::from_utf8(output.stdout)?.trim())
+    };
+    let git = make_git_with_executable(git_run_info.path_to_git.clone(), git_exec_path)?;
+
+    // The operations below report their progress, which isn't of interest
+    // here and would only slow them down.
+    let benchmark_effects = effects.suppress();
+    let mut timings = Vec::new();
+
+    let (generated_stacks, elapsed) = time(|| {
+        git.init_repo_with_options(&GitInitOptions {
+            make_initial_commit: false,
+            run_branchless_init: false,
+        })?;
+        let generated_stacks =
+            git.generate_stacks("master", num_commits, num_stacks, stack_size)?;
+        git.run(&["symbolic-ref", "HEAD", "refs/heads/master"])?;
+        git.run(&["config", "branchless.core.mainBranch", "master"])?;
+        Ok(generated_stacks)
+    })?;
+    timings.push(("Generate repository", elapsed));
+    let main_oid = match generated_stacks.main_oid {
+        Some(main_oid) => main_oid,
+        None => eyre::bail!("No main branch commits were generated"),
+    };
+
+    let repo = Repo::from_dir(&git.repo_path)?;
+    let conn = repo.get_db_conn()?;
+    let event_log_db = EventLogDb::new(&conn)?;
+    {
+        // Record the stack commits as if they had been committed by the user,
+        // so that they're visible and there are events to replay.
+        let now = SystemTime::now();
+        let event_tx_id = event_log_db.make_transaction_id(now, "benchmark")?;
+        let timestamp = now.duration_since(SystemTime::UNIX_EPOCH)?.as_secs_f64();
+        let events = generated_stacks
+            .stacks
+            .iter()
+            .flatten()
+            .map(|commit_oid| Event::CommitEvent {
+                timestamp,
+                event_tx_id,
+                commit_oid: *commit_oid,
+            })
+            .collect();
+        event_log_db.add_events(events)?;
+    }
+
+    let (event_replayer, elapsed) =
+        time(|| EventReplayer::from_event_log_db(&benchmark_effects, &repo, &event_log_db))?;
+    timings.push(("Replay event log", elapsed));
+    let event_cursor = event_replayer.make_default_cursor();
+    let references_snapshot = repo.get_references_snapshot()?;
+
+    let open_dag = || {
+        Dag::open_and_sync(
+            &benchmark_effects,
+            &repo,
+            &event_replayer,
+            event_cursor,
+            &references_snapshot,
+        )
+    };
+    let (_dag, elapsed) = time(open_dag)?;
+    timings.push(("Build commit graph", elapsed));
+    let (dag, elapsed) = time(open_dag)?;
+    timings.push(("Open commit graph (cached)", elapsed));
+
+    let (num_rebased_commits, elapsed) = time(|| {
+        let stack_root_oids: Vec<_> = generated_stacks
+            .stacks
+            .iter()
+            .filter_map(|stack| stack.first().copied())
+            .collect();
+        let build_options = BuildRebasePlanOptions {
+            force_rewrite_public_commits: false,
+            detect_duplicate_commits_via_patch_id: true,
+            dump_rebase_constraints: false,
+            dump_rebase_plan: false,
+        };
+        let permissions = match RebasePlanPermissions::verify_rewrite_set(
+            &dag,
+            build_options,
+            &stack_root_oids.iter().copied().collect::<CommitSet>(),
+        )? {
+            Ok(permissions) => permissions,
+            Err(err) => eyre::bail!("Could not rewrite the generated stacks: {err:?}"),
+        };
+        let thread_pool = ThreadPoolBuilder::new().build()?;
+        let repo_pool = RepoResource::new_pool(&repo)?;
+        let mut builder = RebasePlanBuilder::new(&dag, permissions);
+        for stack_root_oid in stack_root_oids {
+            builder.move_subtree(stack_root_oid, vec![main_oid])?;
+        }
+        match builder.build(&benchmark_effects, &thread_pool, &repo_pool)? {
+            Ok(rebase_plan) => Ok(rebase_plan
+                .map(|rebase_plan| {
+                    rebase_plan
+                        .commands
+                        .iter()
+                        .filter(|command| matches!(command, RebaseCommand::Pick { .. }))
+                        .count()
+                })
+                .unwrap_or_default()),
+            Err(err) => eyre::bail!("Could not build a rebase plan: {err:?}"),
+        }
+    })?;
+    timings.push(("Plan restack", elapsed));
+
+    writeln!(
+        effects.get_output_stream(),
+        "Benchmarked a repository with {} on the main branch and {} of {}:",
+        Pluralize {
+            determiner: None,
+            amount: num_commits,
+            unit: ("commit", "commits"),
+        },
+        Pluralize {
+            determiner: None,
+            amount: num_stacks,
+            unit: ("stack", "stacks"),
+        },
+        Pluralize {
+            determiner: None,
+            amount: stack_size,
+            unit: ("commit", "commits"),
+        },
+    )?;
+    for (name, elapsed) in timings {
+        writeln!(effects.get_output_stream(), "  {name}: {elapsed:.2?}")?;
+    }
+    writeln!(
+        effects.get_output_stream(),
+        "The restack plan moved {}.",
+        Pluralize {
+            determiner: None,
+            amount: num_rebased_commits,
+            unit: ("commit", "commits"),
+        }
+    )?;
+    Ok(Ok(()))
+}
+
+/// The kind of an edge in the dumped graph.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum EdgeKind {
+    /// The target is a child of the source.
+    Child,
+
+    /// The target is a descendant of the source, but the commits between
+    /// them aren't part of the graph.
+    Descendant,
+
+    /// The source was rewritten into the target.
+    Rewritten,
+}
+
+/// Escape a string for use as a quoted DOT identifier.
+fn escape_dot(s: &str) -> String {
+    s.replace('\\', "\\\\").replace('"', "\\\"")
+}
+
+/// Escape a string for use as a quoted Mermaid label.
+fn escape_mermaid(s: &str) -> String {
+    s.replace('"', "#quot;")
+}
+
+#[instrument]
+fn dump_graph(
+    effects: &Effects,
+    revset: Option<Revset>,
+    format: DumpGraphFormat,
+    resolve_revset_options: &ResolveRevsetOptions,
+) -> EyreExitOr<()> {
+    let repo = Repo::from_current_dir()?;
+    let references_snapshot = repo.get_references_snapshot()?;
+    let conn = repo.get_db_conn()?;
+    let event_log_db = EventLogDb::new(&conn)?;
+    let event_replayer = EventReplayer::from_event_log_db(effects, &repo, &event_log_db)?;
+    let event_cursor = event_replayer.make_default_cursor();
+    let mut dag = Dag::open_and_sync(
+        effects,
+        &repo,
+        &event_replayer,
+        event_cursor,
+        &references_snapshot,
+    )?;
+
+    let revset = match revset {
+        Some(revset) => revset,
+        None => Revset(get_smartlog_default_revset(&repo)?),
+    };
+    let commits = match resolve_commits(effects, &repo, &mut dag, &[revset], resolve_revset_options)
+    {
+        Ok(commit_sets) => union_all(&commit_sets),
+        Err(err) => {
+            err.describe(effects)?;
+            return Ok(Err(ExitCode(1)));
+        }
+    };
+    let graph = make_smartlog_graph(
+        effects,
+        &repo,
+        &dag,
+        &event_replayer,
+        event_cursor,
+        &commits,
+    )?;
+
+    // Emit the nodes in topological order, so that the output is stable.
+    let node_oids: Vec<NonZeroOid> = {
+        let node_set: CommitSet = graph.nodes.keys().copied().collect();
+        let mut node_oids = dag.sort(&node_set)?;
+        let sorted_oids: HashSet<NonZeroOid> = node_oids.iter().copied().collect();
+        let mut missing_oids: Vec<NonZeroOid> = graph
+            .nodes
+            .keys()
+            .filter(|oid| !sorted_oids.contains(oid))
+            .copied()
+            .collect();
+        missing_oids.sort();
+        node_oids.extend(missing_oids);
+        node_oids
+    };
+
+    let mut nodes = Vec::new();
+    let mut edges = Vec::new();
+    for oid in node_oids.iter().copied() {
+        let node = &graph.nodes[&oid];
+        let label = match &node.object {
+            NodeObject::Commit { commit } => format!(
+                "{} {}",
+                commit.get_short_oid()?,
+                String::from_utf8_lossy(&commit.get_summary()?)
+            ),
+            NodeObject::GarbageCollected { oid } => format!("{oid} (garbage-collected)"),
+        };
+        nodes.push((oid, label, node.is_main, node.is_obsolete));
+
+        for child in &node.children {
+            edges.push((oid, child.oid, EdgeKind::Child));
+        }
+        for descendant in &node.descendants {
+            edges.push((oid, descendant.oid, EdgeKind::Descendant));
+        }
+        if node.is_obsolete {
+            if let Some(MaybeZeroOid::NonZero(rewritten_oid)) =
+                find_rewrite_target(&event_replayer, event_cursor, oid)
+            {
+                if graph.nodes.contains_key(&rewritten_oid) {
+                    edges.push((oid, rewritten_oid, EdgeKind::Rewritten));
+                }
+            }
+        }
+    }
+
+    let mut output = String::new();
+    match format {
+        DumpGraphFormat::Dot => {
+            writeln!(output, "digraph commits {{")?;
+            writeln!(output, "  rankdir=BT;")?;
+            for (oid, label, is_main, is_obsolete) in nodes {
+                let mut attributes = vec![format!("label=\"{}\"", escape_dot(&label))];
+                if is_main {
+                    attributes.push("shape=box".to_string());
+                }
+                if is_obsolete {
+                    attributes.push("style=dashed".to_string());
+                }
+                writeln!(output, "  \"{oid}\" [{}];", attributes.join(", "))?;
+            }
+            for (source_oid, target_oid, kind) in edges {
+                let attributes = match kind {
+                    EdgeKind::Child => "",
+                    EdgeKind::Descendant => " [style=dotted]",
+                    EdgeKind::Rewritten => " [style=dashed, label=\"rewritten\"]",
+                };
+                writeln!(
+                    output,
+                    "  \"{source_oid}\" -> \"{target_oid}\"{attributes};"
+                )?;
+            }
+            writeln!(output, "}}")?;
+        }
+
+        DumpGraphFormat::Mermaid => {
+            writeln!(output, "flowchart BT")?;
+            for (oid, label, is_main, is_obsolete) in nodes {
+                let label = escape_mermaid(&label);
+                if is_main {
+                    writeln!(output, "  c{oid}[\"{label}\"]")?;
+                } else {
+                    writeln!(output, "  c{oid}(\"{label}\")")?;
+                }
+                if is_obsolete {
+                    writeln!(output, "  style c{oid} stroke-dasharray: 5 5")?;
+                }
+            }
+            for (source_oid, target_oid, kind) in edges {
+                let arrow = match kind {
+                    EdgeKind::Child => "-->",
+                    EdgeKind::Descendant => "-.->",
+                    EdgeKind::Rewritten => "-.->|rewritten|",
+                };
+                writeln!(output, "  c{source_oid} {arrow} c{target_oid}")?;
+            }
+        }
+    }
+    write!(effects.get_output_stream(), "{output}")?;
+    Ok(Ok(()))
+}
@@ -0,0 +1,126 @@
+//! Show the diff of a whole stack of commits.
+//!
+//! This saves working out the right range to pass to `git diff`: by default,
+//! the diff is taken between the top of the current stack and its merge-base
+//! with the main branch.
+
+use std::convert::TryFrom;
+use std::fmt::Write;
+
+use git_branchless_opts::{ResolveRevsetOptions, Revset};
+use git_branchless_revset::resolve_commits;
+use lib::core::dag::{union_all, CommitSet, Dag};
+use lib::core::effects::Effects;
+use lib::core::eventlog::{EventLogDb, EventReplayer};
+use lib::core::repo_ext::RepoExt;
+use lib::git::{Diff, NonZeroOid, Repo};
+use lib::util::{ExitCode, EyreExitOr};
+use tracing::instrument;
+
+/// The width to render `--stat` output at, matching Git's default.
+const STAT_WIDTH: usize = 80;
+
+fn write_diff(effects: &Effects, diff: &Diff, stat: bool) -> eyre::Result<()> {
+    let output = if stat {
+        diff.to_stat(STAT_WIDTH)?
+    } else {
+        diff.to_patch()?
+    };
+    write!(
+        effects.get_output_stream(),
+        "{}",
+        String::from_utf8_lossy(&output)
+    )?;
+    Ok(())
+}
+
+/// Show the cumulative diff of the stack containing the given commits against
+/// its merge-base with the main branch, or the diff of each of the commits if
+/// `per_commit` is set.
+#[instrument]
+pub fn diff(
+    effects: &Effects,
+    revset: Revset,
+    resolve_revset_options: &ResolveRevsetOptions,
+    stat: bool,
+    per_commit: bool,
+) -> EyreExitOr<()> {
+    let glyphs = effects.get_glyphs();
+    let repo = Repo::from_current_dir()?;
+    let references_snapshot = repo.get_references_snapshot()?;
+    let conn = repo.get_db_conn()?;
+    let event_log_db = EventLogDb::new(&conn)?;
+    let event_replayer = EventReplayer::from_event_log_db(effects, &repo, &event_log_db)?;
+    let event_cursor = event_replayer.make_default_cursor();
+    let mut dag = Dag::open_and_sync(
+        effects,
+        &repo,
+        &event_replayer,
+        event_cursor,
+        &references_snapshot,
+    )?;
+
+    let commits = match resolve_commits(effects, &repo, &mut dag, &[revset], resolve_revset_options)
+    {
+        Ok(commit_sets) => union_all(&commit_sets),
+        Err(err) => {
+            err.describe(effects)?;
+            return Ok(Err(ExitCode(1)));
+        }
+    };
+
+    if per_commit {
+        for commit_oid in dag.sort(&commits)? {
+            writeln!(
+                effects.get_output_stream(),
+                "{}",
+                glyphs.render(repo.friendly_describe_commit_from_oid(glyphs, commit_oid)?)?,
+            )?;
+            let commit = repo.find_commit_or_fail(commit_oid)?;
+            match repo.get_patch_for_commit(effects, &commit)? {
+                Some(diff) => write_diff(effects, &diff, stat)?,
+                None => writeln!(
+                    effects.get_output_stream(),
+                    "(Not showing the diff of a merge commit.)"
+                )?,
+            }
+        }
+        return Ok(Ok(()));
+    }
+
+    let head_oid = match dag
+        .commit_set_to_vec(&dag.query_heads(commits)?)?
+        .as_slice()
+    {
+        [] => {
+            writeln!(effects.get_output_stream(), "No commits to diff.")?;
+            return Ok(Ok(()));
+        }
+        [head_oid] => *head_oid,
+        head_oids => {
+            writeln!(
+                effects.get_output_stream(),
+                "The commits to diff must form a single stack, but they have {} heads.",
+                head_oids.len()
+            )?;
+            writeln!(
+                effects.get_output_stream(),
+                "To see the diff of each commit instead, pass --per-commit."
+            )?;
+            return Ok(Err(ExitCode(1)));
+        }
+    };
+
+    let merge_base_tree =
+        match dag.query_gca_one(dag.main_branch_commit.union(&CommitSet::from(head_oid)))? {
+            Some(merge_base_oid) => {
+                let merge_base_oid = NonZeroOid::try_from(merge_base_oid)?;
+                Some(repo.find_commit_or_fail(merge_base_oid)?.get_tree()?)
+            }
+            None => None,
+        };
+    let head_tree = repo.find_commit_or_fail(head_oid)?.get_tree()?;
+    let diff = repo.get_diff_between_trees(effects, merge_base_tree.as_ref(), &head_tree, 3)?;
+    write_diff(effects, &diff, stat)?;
+    Ok(Ok(()))
+}
@@ -0,0 +1,150 @@
+//! Check out a commit in the middle of a stack to edit it.
+//!
+//! Any descendants left behind by earlier rewrites of the commit are first
+//! restacked onto it, so that the commit is checked out with all of its
+//! descendants above it. While it's being edited, amending it with `git amend`
+//! or `git commit --amend` restacks its descendants onto the amended commit,
+//! and `git next` finishes the edit, restacking any descendants which were
+//! left behind before moving onto them. Checking out a commit outside of its
+//! stack stops editing it.
+
+use std::fmt::Write;
+use std::time::SystemTime;
+
+use git_branchless_opts::{ResolveRevsetOptions, Revset};
+use git_branchless_revset::resolve_commits;
+use lib::core::check_out::{check_out_commit, CheckOutCommitOptions, CheckoutTarget};
+use lib::core::dag::{CommitSet, Dag};
+use lib::core::effects::Effects;
+use lib::core::eventlog::{EventLogDb, EventReplayer};
+use lib::core::repo_ext::RepoExt;
+use lib::core::rewrite::{
+    find_abandoned_children, write_edit_state, BuildRebasePlanOptions, EditState,
+    RebasePlanPermissions,
+};
+use lib::git::{GitRunInfo, Repo};
+use lib::try_exit_code;
+use lib::util::{ExitCode, EyreExitOr};
+use tracing::instrument;
+
+/// Check out the commit given by `revset` to edit it.
+#[instrument]
+pub fn edit(
+    effects: &Effects,
+    git_run_info: &GitRunInfo,
+    revset: Revset,
+    resolve_revset_options: &ResolveRevsetOptions,
+    force_rewrite_public_commits: bool,
+) -> EyreExitOr<()> {
+    let repo = Repo::from_current_dir()?;
+    let references_snapshot = repo.get_references_snapshot()?;
+    let conn = repo.get_db_conn()?;
+    let event_log_db = EventLogDb::new(&conn)?;
+    let event_replayer = EventReplayer::from_event_log_db(effects, &repo, &event_log_db)?;
+    let event_cursor = event_replayer.make_default_cursor();
+    let mut dag = Dag::open_and_sync(
+        effects,
+        &repo,
+        &event_replayer,
+        event_cursor,
+        &references_snapshot,
+    )?;
+
+    let commit_sets = match resolve_commits(
+        effects,
+        &repo,
+        &mut dag,
+        &[revset.clone()],
+        resolve_revset_options,
+    ) {
+        Ok(commit_sets) => commit_sets,
+        Err(err) => {
+            err.describe(effects)?;
+            return Ok(Err(ExitCode(1)));
+        }
+    };
+    let commit_oid = match dag.commit_set_to_vec(&commit_sets[0])?.as_slice() {
+        [commit_oid] => *commit_oid,
+        other => {
+            writeln!(
+                effects.get_error_stream(),
+                "The commit to edit must be exactly one commit, but {revset} resolved to {} commits.",
+                other.len()
+            )?;
+            return Ok(Err(ExitCode(1)));
+        }
+    };
+
+    // Find the older versions of the commit which still have descendants, so
+    // that those descendants can be restacked onto it.
+    let mut left_behind_oids = Vec::new();
+    let mut left_behind_children = CommitSet::empty();
+    for obsolete_oid in dag.commit_set_to_vec(&dag.query_obsolete_commits())? {
+        match find_abandoned_children(&dag, &event_replayer, event_cursor, obsolete_oid)? {
+            Some((rewritten_oid, abandoned_child_oids))
+                if rewritten_oid == commit_oid && !abandoned_child_oids.is_empty() =>
+            {
+                left_behind_oids.push(obsolete_oid);
+                left_behind_children = left_behind_children
+                    .union(&abandoned_child_oids.into_iter().collect::<CommitSet>());
+            }
+            Some(_) | None => {}
+        }
+    }
+
+    // Amending the commit will restack its descendants, so make sure that
+    // they can be rewritten before starting.
+    let descendants = dag.query_descendants(CommitSet::from(commit_oid))?;
+    let left_behind_descendants = dag.query_descendants(left_behind_children)?;
+    let commits_to_verify =
+        dag.filter_visible_commits(descendants.union(&left_behind_descendants))?;
+    let build_options = BuildRebasePlanOptions {
+        force_rewrite_public_commits,
+        dump_rebase_constraints: false,
+        dump_rebase_plan: false,
+        detect_duplicate_commits_via_patch_id: false,
+    };
+    if let Err(err) =
+        RebasePlanPermissions::verify_rewrite_set(&dag, build_options, &commits_to_verify)?
+    {
+        err.describe(effects, &repo, &dag)?;
+        return Ok(Err(ExitCode(1)));
+    }
+
+    let event_tx_id = event_log_db.make_transaction_id(SystemTime::now(), "edit")?;
+    if !left_behind_oids.is_empty() {
+        let mut args = vec![
+            "branchless".to_string(),
+            "restack".to_string(),
+            "--in-memory".to_string(),
+        ];
+        args.extend(left_behind_oids.iter().map(|oid| oid.to_string()));
+        try_exit_code!(git_run_info.run(effects, Some(event_tx_id), &args)?);
+    }
+    try_exit_code!(check_out_commit(
+        effects,
+        git_run_info,
+        &repo,
+        &event_log_db,
+        event_tx_id,
+        Some(CheckoutTarget::Oid(commit_oid)),
+        &CheckOutCommitOptions::default(),
+    )?);
+    write_edit_state(
+        &repo,
+        &EditState {
+            original_commit_oid: commit_oid,
+            current_commit_oid: commit_oid,
+        },
+    )?;
+
+    let commit = repo.find_commit_or_fail(commit_oid)?;
+    writeln!(
+        effects.get_output_stream(),
+        "Editing {}. Amend it with `git amend` or `git commit --amend` to restack its descendants, then run `git next` to move onto them.",
+        effects
+            .get_glyphs()
+            .render(commit.friendly_describe(effects.get_glyphs())?)?,
+    )?;
+    Ok(Ok(()))
+}
@@ -0,0 +1,33 @@
+//! Edit the todo list of an interactive rebase, for use as Git's
+//! `sequence.editor`.
+
+use std::path::Path;
+
+use eyre::Context;
+use lib::core::rewrite::{autosquash_rebase_todo, parse_rebase_todo, render_rebase_todo};
+use lib::git::Repo;
+use lib::util::EyreExitOr;
+use tracing::instrument;
+
+/// Rewrite the todo list at `path` in place, applying the requested edits.
+#[instrument]
+pub fn edit_todo(path: &Path, autosquash_stack: bool) -> EyreExitOr<()> {
+    let contents = std::fs::read_to_string(path)
+        .wrap_err_with(|| format!("Reading todo list at: {path:?}"))?;
+    let mut lines = parse_rebase_todo(&contents);
+
+    if autosquash_stack {
+        let repo = Repo::from_current_dir()?;
+        lines = autosquash_rebase_todo(lines, |commit| {
+            let summary = match repo.revparse_single_commit(commit)? {
+                Some(commit) => Some(commit.get_summary()?.to_string()),
+                None => None,
+            };
+            Ok(summary)
+        })?;
+    }
+
+    std::fs::write(path, render_rebase_todo(&lines))
+        .wrap_err_with(|| format!("Writing todo list to: {path:?}"))?;
+    Ok(Ok(()))
+}
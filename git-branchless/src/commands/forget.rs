@@ -0,0 +1,68 @@
+//! Remove commits from branchless's tracking entirely, as opposed to hiding
+//! them.
+
+use std::fmt::Write;
+
+use git_branchless_opts::{ResolveRevsetOptions, Revset};
+use lib::core::dag::{union_all, Dag};
+use lib::core::effects::Effects;
+use lib::core::eventlog::{EventLogDb, EventReplayer};
+use lib::core::gc::unmark_commit_reachable;
+use lib::core::repo_ext::RepoExt;
+use lib::git::Repo;
+use lib::util::{ExitCode, EyreExitOr};
+use tracing::instrument;
+
+use git_branchless_revset::resolve_commits;
+
+/// Forget the commits provided on the command-line.
+///
+/// The events recording the commits are removed from the event log, and the
+/// references keeping them alive are deleted. Rewrites involving them are kept
+/// as the creation or deletion of the other commit, so that the visibility of
+/// their predecessors and successors is unchanged. Reference updates are left
+/// alone.
+#[instrument]
+pub fn forget(
+    effects: &Effects,
+    revsets: Vec<Revset>,
+    resolve_revset_options: &ResolveRevsetOptions,
+) -> EyreExitOr<()> {
+    let glyphs = effects.get_glyphs();
+    let repo = Repo::from_current_dir()?;
+    let references_snapshot = repo.get_references_snapshot()?;
+    let conn = repo.get_db_conn()?;
+    let event_log_db = EventLogDb::new(&conn)?;
+    let event_replayer = EventReplayer::from_event_log_db(effects, &repo, &event_log_db)?;
+    let event_cursor = event_replayer.make_default_cursor();
+    let mut dag = Dag::open_and_sync(
+        effects,
+        &repo,
+        &event_replayer,
+        event_cursor,
+        &references_snapshot,
+    )?;
+
+    let commit_sets =
+        match resolve_commits(effects, &repo, &mut dag, &revsets, resolve_revset_options) {
+            Ok(commit_sets) => commit_sets,
+            Err(err) => {
+                err.describe(effects)?;
+                return Ok(Err(ExitCode(1)));
+            }
+        };
+    let commit_oids = dag.sort(&union_all(&commit_sets))?;
+
+    event_log_db.forget_commits(&commit_oids)?;
+    for commit_oid in commit_oids {
+        unmark_commit_reachable(&repo, commit_oid)?;
+        let commit = repo.find_commit_or_fail(commit_oid)?;
+        writeln!(
+            effects.get_output_stream(),
+            "Forgot commit: {}",
+            glyphs.render(commit.friendly_describe(glyphs)?)?,
+        )?;
+    }
+
+    Ok(Ok(()))
+}
@@ -1,19 +1,24 @@
 //! Handle obsoleting commits when explicitly requested by the user (as opposed to
 //! automatically as the result of a rewrite operation).
 
-use std::collections::HashMap;
+use std::collections::{HashMap, HashSet};
 use std::fmt::Write;
 use std::time::SystemTime;
 
+use bstr::BString;
 use git_branchless_opts::{ResolveRevsetOptions, Revset};
+use lib::core::config::get_hide_archive_branches;
 use lib::core::dag::{union_all, Dag};
 use lib::core::effects::Effects;
 use lib::core::eventlog::{CommitActivityStatus, Event};
-use lib::core::eventlog::{EventLogDb, EventReplayer};
-use lib::core::formatting::{Glyphs, Pluralize};
+use lib::core::eventlog::{EventLogDb, EventReplayer, EventTransactionId};
+use lib::core::formatting::Pluralize;
+use lib::core::gc::ARCHIVED_BRANCH_PREFIX;
 use lib::core::repo_ext::RepoExt;
 use lib::core::rewrite::move_branches;
-use lib::git::{CategorizedReferenceName, GitRunInfo, MaybeZeroOid, NonZeroOid, Repo};
+use lib::git::{
+    CategorizedReferenceName, GitRunInfo, MaybeZeroOid, NonZeroOid, ReferenceName, Repo,
+};
 use lib::util::{ExitCode, EyreExitOr};
 use tracing::instrument;
 
@@ -30,7 +35,7 @@ pub fn hide(
     recursive: bool,
 ) -> EyreExitOr<()> {
     let now = SystemTime::now();
-    let glyphs = Glyphs::detect();
+    let glyphs = effects.get_glyphs();
     let repo = Repo::from_current_dir()?;
     let references_snapshot = repo.get_references_snapshot()?;
     let conn = repo.get_db_conn()?;
@@ -45,6 +50,7 @@ pub fn hide(
         &references_snapshot,
     )?;
     let delete_branches = !no_delete_branches;
+    let archive_branches = delete_branches && get_hide_archive_branches(&repo)?;
 
     let commit_sets =
         match resolve_commits(effects, &repo, &mut dag, &revsets, resolve_revset_options) {
@@ -61,6 +67,64 @@ pub fn hide(
     } else {
         commits
     };
+
+    // Commits which were explicitly recorded as public have been shared with
+    // others, so they must not be hidden.
+    let public_commits =
+        commits.intersection(&dag.query_ancestors(dag.public_phase_commits.clone())?);
+    if !dag.set_is_empty(&public_commits)? {
+        writeln!(
+            effects.get_error_stream(),
+            "Cannot hide {} recorded as public:",
+            Pluralize {
+                determiner: None,
+                amount: dag.set_count(&public_commits)?,
+                unit: ("commit", "commits"),
+            }
+        )?;
+        for commit_oid in dag.sort(&public_commits)? {
+            let commit = repo.find_commit_or_fail(commit_oid)?;
+            writeln!(
+                effects.get_error_stream(),
+                "{}",
+                glyphs.render(commit.friendly_describe(glyphs)?)?,
+            )?;
+        }
+        writeln!(
+            effects.get_error_stream(),
+            "To hide them anyway, first run: git branchless phase --draft <commits>"
+        )?;
+        return Ok(Err(ExitCode(1)));
+    }
+
+    // Tags usually mark released versions, so the commits they point to
+    // shouldn't disappear from the smartlog.
+    let tagged_commits = commits.intersection(&dag.protected_tags_commits);
+    if !dag.set_is_empty(&tagged_commits)? {
+        writeln!(
+            effects.get_error_stream(),
+            "Cannot hide {} pointed to by tags:",
+            Pluralize {
+                determiner: None,
+                amount: dag.set_count(&tagged_commits)?,
+                unit: ("commit", "commits"),
+            }
+        )?;
+        for commit_oid in dag.sort(&tagged_commits)? {
+            let commit = repo.find_commit_or_fail(commit_oid)?;
+            writeln!(
+                effects.get_error_stream(),
+                "{}",
+                glyphs.render(commit.friendly_describe(glyphs)?)?,
+            )?;
+        }
+        writeln!(
+            effects.get_error_stream(),
+            "To hide them anyway, delete the tags or set branchless.protectTags to false."
+        )?;
+        return Ok(Err(ExitCode(1)));
+    }
+
     let commits = dag.sort(&commits)?;
     let commits = commits
         .into_iter()
@@ -69,6 +133,17 @@ pub fn hide(
 
     let timestamp = now.duration_since(SystemTime::UNIX_EPOCH)?.as_secs_f64();
     let event_tx_id = event_log_db.make_transaction_id(now, "hide")?;
+    event_log_db.set_transaction_label(
+        event_tx_id,
+        format!(
+            "hide {}",
+            Pluralize {
+                determiner: None,
+                amount: commits.len(),
+                unit: ("commit", "commits"),
+            }
+        ),
+    )?;
     let events = commits
         .iter()
         .map(|commit| Event::ObsoleteEvent {
@@ -85,7 +160,7 @@ pub fn hide(
         writeln!(
             effects.get_output_stream(),
             "Hid commit: {}",
-            glyphs.render(commit.friendly_describe(&glyphs)?)?,
+            glyphs.render(commit.friendly_describe(glyphs)?)?,
         )?;
         if let CommitActivityStatus::Obsolete =
             event_replayer.get_cursor_commit_activity_status(cursor, commit.get_oid())
@@ -112,6 +187,34 @@ pub fn hide(
                 repo.detach_head(&head_info)?;
             }
         }
+
+        if archive_branches {
+            // Keep a copy of each local branch outside of `refs/heads/` before
+            // deleting it, so that `git unhide` can restore it.
+            let main_branch_name = repo.get_main_branch_reference_name()?;
+            for commit in commits.iter() {
+                let branch_names = match references_snapshot
+                    .branch_oid_to_names
+                    .get(&commit.get_oid())
+                {
+                    Some(branch_names) => branch_names,
+                    None => continue,
+                };
+                for branch_name in branch_names {
+                    if branch_name == &main_branch_name {
+                        continue;
+                    }
+                    if let Some(suffix) = branch_name.as_str().strip_prefix("refs/heads/") {
+                        repo.create_reference(
+                            &ReferenceName::from(format!("{ARCHIVED_BRANCH_PREFIX}{suffix}")),
+                            commit.get_oid(),
+                            true,
+                            "branchless: archive branch",
+                        )?;
+                    }
+                }
+            }
+        }
         move_branches(
             effects,
             git_run_info,
@@ -133,13 +236,16 @@ pub fn hide(
         .collect();
     if !abandoned_branches.is_empty() {
         abandoned_branches.sort_unstable();
-        // This message will look like either of these:
+        // This message will look like one of these:
         // Abandoned X branches: <branches>
+        // Archived X branches: <branches>
         // Deleted X branches: <branches>
         writeln!(
             effects.get_output_stream(),
             "{} {}: {}",
-            if delete_branches {
+            if archive_branches {
+                "Archived"
+            } else if delete_branches {
                 "Deleted"
             } else {
                 "Abandoned"
@@ -186,12 +292,13 @@ pub fn hide(
 #[instrument]
 pub fn unhide(
     effects: &Effects,
+    git_run_info: &GitRunInfo,
     revsets: Vec<Revset>,
     resolve_revset_options: &ResolveRevsetOptions,
     recursive: bool,
 ) -> EyreExitOr<()> {
     let now = SystemTime::now();
-    let glyphs = Glyphs::detect();
+    let glyphs = effects.get_glyphs();
     let repo = Repo::from_current_dir()?;
     let references_snapshot = repo.get_references_snapshot()?;
     let conn = repo.get_db_conn()?;
@@ -230,6 +337,17 @@ pub fn unhide(
 
     let timestamp = now.duration_since(SystemTime::UNIX_EPOCH)?.as_secs_f64();
     let event_tx_id = event_log_db.make_transaction_id(now, "unhide")?;
+    event_log_db.set_transaction_label(
+        event_tx_id,
+        format!(
+            "unhide {}",
+            Pluralize {
+                determiner: None,
+                amount: commits.len(),
+                unit: ("commit", "commits"),
+            }
+        ),
+    )?;
     let events = commits
         .iter()
         .map(|commit| Event::UnobsoleteEvent {
@@ -242,11 +360,12 @@ pub fn unhide(
 
     let cursor = event_replayer.make_default_cursor();
     let num_commits = commits.len();
+    let commit_oids: HashSet<NonZeroOid> = commits.iter().map(|commit| commit.get_oid()).collect();
     for commit in commits {
         writeln!(
             effects.get_output_stream(),
             "Unhid commit: {}",
-            glyphs.render(commit.friendly_describe(&glyphs)?)?,
+            glyphs.render(commit.friendly_describe(glyphs)?)?,
         )?;
         if let CommitActivityStatus::Active =
             event_replayer.get_cursor_commit_activity_status(cursor, commit.get_oid())
@@ -258,6 +377,21 @@ pub fn unhide(
         }
     }
 
+    let restored_branches =
+        restore_archived_branches(effects, git_run_info, &repo, event_tx_id, &commit_oids)?;
+    if !restored_branches.is_empty() {
+        writeln!(
+            effects.get_output_stream(),
+            "Restored {}: {}",
+            Pluralize {
+                determiner: None,
+                amount: restored_branches.len(),
+                unit: ("branch", "branches"),
+            },
+            restored_branches.join(", ")
+        )?;
+    }
+
     writeln!(
         effects.get_output_stream(),
         "To hide {}, run: git undo",
@@ -270,3 +404,76 @@ pub fn unhide(
 
     Ok(Ok(()))
 }
+
+/// Move the branches which `git hide` archived for the given commits back under
+/// `refs/heads/`. A branch isn't restored if another branch with the same name
+/// has since been created.
+///
+/// Returns the names of the restored branches.
+#[instrument]
+fn restore_archived_branches(
+    effects: &Effects,
+    git_run_info: &GitRunInfo,
+    repo: &Repo,
+    event_tx_id: EventTransactionId,
+    commit_oids: &HashSet<NonZeroOid>,
+) -> eyre::Result<Vec<String>> {
+    let mut restored_branches = Vec::new();
+    let mut branch_moves = String::new();
+    for mut reference in repo.get_all_references()? {
+        let reference_name = reference.get_name()?;
+        let branch_name = match reference_name.as_str().strip_prefix(ARCHIVED_BRANCH_PREFIX) {
+            Some(branch_name) => branch_name.to_owned(),
+            None => continue,
+        };
+        let commit_oid = match reference.peel_to_commit()? {
+            Some(commit) => commit.get_oid(),
+            None => continue,
+        };
+        if !commit_oids.contains(&commit_oid) {
+            continue;
+        }
+
+        let branch_reference_name = ReferenceName::from(format!("refs/heads/{branch_name}"));
+        match repo.find_reference(&branch_reference_name)? {
+            Some(branch_reference) => {
+                if repo.resolve_reference(&branch_reference)?.oid != Some(commit_oid) {
+                    writeln!(
+                        effects.get_output_stream(),
+                        "Not restoring branch {branch_name}, since a branch with that name already exists."
+                    )?;
+                    continue;
+                }
+            }
+            None => {
+                repo.create_reference(
+                    &branch_reference_name,
+                    commit_oid,
+                    false,
+                    "branchless: restore archived branch",
+                )?;
+                branch_moves.push_str(&format!(
+                    "{} {commit_oid} {}\n",
+                    MaybeZeroOid::Zero,
+                    branch_reference_name.as_str()
+                ));
+            }
+        }
+        reference.delete()?;
+        restored_branches.push(branch_name);
+    }
+
+    if !branch_moves.is_empty() {
+        git_run_info.run_hook(
+            effects,
+            repo,
+            "reference-transaction",
+            event_tx_id,
+            &["committed"],
+            Some(BString::from(branch_moves)),
+        )?;
+    }
+
+    restored_branches.sort_unstable();
+    Ok(restored_branches)
+}
@@ -0,0 +1,244 @@
+//! Import history-tracking data from other version control systems.
+//!
+//! See [`lib::core::hg_import`] for the Mercurial data which is read, and
+//! [`lib::core::notes`] for the rewrites which are read from Git notes.
+
+use std::collections::HashSet;
+use std::fmt::Write;
+use std::path::Path;
+use std::time::SystemTime;
+
+use lib::core::effects::Effects;
+use lib::core::eventlog::{Event, EventLogDb};
+use lib::core::formatting::Pluralize;
+use lib::core::gc::mark_commit_reachable;
+use lib::core::hg_import::{
+    get_git_mapfile_path, read_hg_history, HgHistory, HgPhaseRootKind, ObsMarker,
+};
+use lib::core::notes::{read_rewrite_notes, REWRITE_NOTES_REF_NAME};
+use lib::core::phase::{Phase, PhaseDb};
+use lib::git::{MaybeZeroOid, NonZeroOid, Repo};
+use lib::util::{ExitCode, EyreExitOr};
+use tracing::instrument;
+
+/// Import the obsolescence markers and phase roots of the Mercurial repository
+/// at `hg_repo_path`.
+///
+/// Markers are converted into rewrite events (or obsolete events, for pruned
+/// changesets), so that the rewritten commits are hidden and the smartlog and
+/// `git obslog` reflect their history. Secret phase roots are recorded as
+/// secret, and the parents of draft phase roots are recorded as public.
+/// Markers and phase roots referring to changesets which don't correspond to a
+/// commit in this repository are skipped.
+#[instrument]
+pub fn import(effects: &Effects, hg_repo_path: &Path) -> EyreExitOr<()> {
+    let repo = Repo::from_current_dir()?;
+    let conn = repo.get_db_conn()?;
+    let event_log_db = EventLogDb::new(&conn)?;
+    let phase_db = PhaseDb::new(&conn)?;
+
+    let mapfile_path = get_git_mapfile_path(hg_repo_path);
+    if !mapfile_path.exists() {
+        writeln!(
+            effects.get_error_stream(),
+            "No hg-git mapping found at {}, so Mercurial changesets can't be matched to commits in this repository.",
+            mapfile_path.display()
+        )?;
+        return Ok(Err(ExitCode(1)));
+    }
+
+    let HgHistory {
+        markers,
+        phase_roots,
+        git_oids,
+    } = read_hg_history(hg_repo_path)?;
+    let lookup = |node: &str| -> eyre::Result<Option<NonZeroOid>> {
+        match git_oids.get(node) {
+            Some(oid) if repo.find_commit(*oid)?.is_some() => Ok(Some(*oid)),
+            Some(_) | None => Ok(None),
+        }
+    };
+
+    let event_tx_id = event_log_db.make_transaction_id(SystemTime::now(), "import")?;
+    let mut events = Vec::new();
+    let mut num_rewritten = 0;
+    let mut num_pruned = 0;
+    let mut num_skipped = 0;
+    for marker in markers {
+        let ObsMarker {
+            predecessor,
+            successors,
+            timestamp,
+        } = marker;
+        let predecessor_oid = match lookup(&predecessor)? {
+            Some(oid) => oid,
+            None => {
+                num_skipped += 1;
+                continue;
+            }
+        };
+
+        if successors.is_empty() {
+            events.push(Event::ObsoleteEvent {
+                timestamp,
+                event_tx_id,
+                commit_oid: predecessor_oid,
+            });
+            num_pruned += 1;
+            continue;
+        }
+
+        let mut successor_oids = Vec::new();
+        for successor in &successors {
+            if let Some(oid) = lookup(successor)? {
+                successor_oids.push(oid);
+            }
+        }
+        if successor_oids.len() != successors.len() {
+            num_skipped += 1;
+            continue;
+        }
+        for successor_oid in successor_oids {
+            events.push(Event::RewriteEvent {
+                timestamp,
+                event_tx_id,
+                old_commit_oid: MaybeZeroOid::NonZero(predecessor_oid),
+                new_commit_oid: MaybeZeroOid::NonZero(successor_oid),
+            });
+        }
+        num_rewritten += 1;
+    }
+    event_log_db.add_events(events)?;
+
+    let mut public_oids = Vec::new();
+    let mut secret_oids = Vec::new();
+    for (kind, node) in phase_roots {
+        let commit_oid = match lookup(&node)? {
+            Some(oid) => oid,
+            None => {
+                num_skipped += 1;
+                continue;
+            }
+        };
+        match kind {
+            HgPhaseRootKind::Draft => {
+                let commit = repo.find_commit_or_fail(commit_oid)?;
+                public_oids.extend(commit.get_parent_oids());
+            }
+            HgPhaseRootKind::Secret => secret_oids.push(commit_oid),
+        }
+    }
+    let num_phases = public_oids.len() + secret_oids.len();
+    phase_db.set_phase(public_oids, Phase::Public)?;
+    phase_db.set_phase(secret_oids, Phase::Secret)?;
+
+    writeln!(
+        effects.get_output_stream(),
+        "Imported {}, {}, and {}.",
+        Pluralize {
+            determiner: None,
+            amount: num_rewritten,
+            unit: ("rewritten commit", "rewritten commits"),
+        },
+        Pluralize {
+            determiner: None,
+            amount: num_pruned,
+            unit: ("pruned commit", "pruned commits"),
+        },
+        Pluralize {
+            determiner: None,
+            amount: num_phases,
+            unit: ("commit phase", "commit phases"),
+        },
+    )?;
+    if num_skipped > 0 {
+        writeln!(
+            effects.get_output_stream(),
+            "Skipped {} referring to changesets which aren't in this repository.",
+            Pluralize {
+                determiner: None,
+                amount: num_skipped,
+                unit: ("entry", "entries"),
+            },
+        )?;
+    }
+    Ok(Ok(()))
+}
+
+/// Import the rewrites recorded in the notes under `refs/notes/branchless`.
+///
+/// Rewrites which are already in the event log, and rewrites referring to
+/// commits which aren't in this repository, are skipped.
+#[instrument]
+pub fn import_notes(effects: &Effects) -> EyreExitOr<()> {
+    let repo = Repo::from_current_dir()?;
+    let conn = repo.get_db_conn()?;
+    let event_log_db = EventLogDb::new(&conn)?;
+
+    let existing_rewrites: HashSet<(MaybeZeroOid, MaybeZeroOid)> = event_log_db
+        .get_events()?
+        .into_iter()
+        .filter_map(|event| match event {
+            Event::RewriteEvent {
+                old_commit_oid,
+                new_commit_oid,
+                ..
+            } => Some((old_commit_oid, new_commit_oid)),
+            _ => None,
+        })
+        .collect();
+
+    let event_tx_id = event_log_db.make_transaction_id(SystemTime::now(), "import")?;
+    let timestamp = SystemTime::now()
+        .duration_since(SystemTime::UNIX_EPOCH)?
+        .as_secs_f64();
+    let mut events = Vec::new();
+    let mut num_skipped = 0;
+    for (old_commit_oid, new_commit_oid) in read_rewrite_notes(&repo)? {
+        if existing_rewrites.contains(&(MaybeZeroOid::NonZero(old_commit_oid), new_commit_oid)) {
+            continue;
+        }
+        let is_new_commit_present = match new_commit_oid {
+            MaybeZeroOid::NonZero(new_commit_oid) => repo.find_commit(new_commit_oid)?.is_some(),
+            MaybeZeroOid::Zero => true,
+        };
+        if repo.find_commit(old_commit_oid)?.is_none() || !is_new_commit_present {
+            num_skipped += 1;
+            continue;
+        }
+
+        if let MaybeZeroOid::NonZero(new_commit_oid) = new_commit_oid {
+            mark_commit_reachable(&repo, new_commit_oid)?;
+        }
+        events.push(Event::RewriteEvent {
+            timestamp,
+            event_tx_id,
+            old_commit_oid: MaybeZeroOid::NonZero(old_commit_oid),
+            new_commit_oid,
+        });
+    }
+    let num_rewritten = events.len();
+    event_log_db.add_events(events)?;
+
+    writeln!(
+        effects.get_output_stream(),
+        "Imported {} from {REWRITE_NOTES_REF_NAME}.",
+        Pluralize {
+            determiner: None,
+            amount: num_rewritten,
+            unit: ("rewrite", "rewrites"),
+        },
+    )?;
+    if num_skipped > 0 {
+        writeln!(
+            effects.get_output_stream(),
+            "Skipped {} referring to commits which aren't in this repository.",
+            Pluralize {
+                determiner: None,
+                amount: num_skipped,
+                unit: ("rewrite", "rewrites"),
+            },
+        )?;
+    }
+    Ok(Ok(()))
+}
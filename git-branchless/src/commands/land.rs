@@ -0,0 +1,343 @@
+//! Implements the `git branchless land` command.
+//!
+//! Landing is done locally: the commits are moved on top of the main branch if
+//! necessary, and then the main branch is fast-forwarded to them. With
+//! `--push`, the commits are pushed to the main branch of the remote first.
+//! Once the main branch contains the landed commits, they're public, so they're
+//! no longer shown as part of the stack.
+//!
+//! Landing through a forge's API, such as by merging a pull request, isn't
+//! supported, since forges may create different commits than the local ones.
+
+use std::fmt::Write;
+use std::time::SystemTime;
+
+use cursive_core::theme::BaseColor;
+use itertools::Itertools;
+use lib::core::check_out::CheckOutCommitOptions;
+use lib::core::config::{
+    get_main_commit_spec, get_restack_preserve_timestamps, MAIN_COMMIT_CONFIG_KEY,
+};
+use lib::core::dag::{union_all, CommitSet, Dag};
+use lib::core::effects::Effects;
+use lib::core::eventlog::{EventLogDb, EventReplayer};
+use lib::core::formatting::{Pluralize, StyledStringBuilder};
+use lib::core::repo_ext::RepoExt;
+use lib::core::rewrite::{
+    execute_rebase_plan, BuildRebasePlanOptions, ExecuteRebasePlanOptions, ExecuteRebasePlanResult,
+    MergeConflictRemediation, RebasePlanBuilder, RebasePlanPermissions, RepoResource,
+};
+use lib::git::{CategorizedReferenceName, GitRunInfo, MaybeZeroOid, Repo};
+use lib::util::{ExitCode, EyreExitOr};
+use rayon::ThreadPoolBuilder;
+use tracing::instrument;
+
+use git_branchless_opts::{ResolveRevsetOptions, Revset};
+use git_branchless_revset::resolve_commits;
+
+/// Land the given commits, along with their draft ancestors, onto the main
+/// branch, and restack the rest of their stack onto the new main branch.
+#[instrument]
+pub fn land(
+    effects: &Effects,
+    git_run_info: &GitRunInfo,
+    revset: Revset,
+    resolve_revset_options: &ResolveRevsetOptions,
+    push: bool,
+) -> EyreExitOr<()> {
+    let glyphs = effects.get_glyphs();
+    let repo = Repo::from_current_dir()?;
+    let references_snapshot = repo.get_references_snapshot()?;
+    let conn = repo.get_db_conn()?;
+    let event_log_db = EventLogDb::new(&conn)?;
+    let event_replayer = EventReplayer::from_event_log_db(effects, &repo, &event_log_db)?;
+    let event_cursor = event_replayer.make_default_cursor();
+    let mut dag = Dag::open_and_sync(
+        effects,
+        &repo,
+        &event_replayer,
+        event_cursor,
+        &references_snapshot,
+    )?;
+
+    if let Some(main_commit_spec) = get_main_commit_spec(&repo)? {
+        writeln!(
+            effects.get_output_stream(),
+            "The main branch is pinned to {main_commit_spec} with {MAIN_COMMIT_CONFIG_KEY}, so commits can't be landed onto it."
+        )?;
+        return Ok(Err(ExitCode(1)));
+    }
+
+    let commits = match resolve_commits(
+        effects,
+        &repo,
+        &mut dag,
+        &[revset.clone()],
+        resolve_revset_options,
+    ) {
+        Ok(commit_sets) => union_all(&commit_sets),
+        Err(err) => {
+            err.describe(effects)?;
+            return Ok(Err(ExitCode(1)));
+        }
+    };
+
+    let landed_commits = dag
+        .query_ancestors(commits)?
+        .intersection(dag.query_draft_commits()?);
+    if dag.set_is_empty(&landed_commits)? {
+        writeln!(
+            effects.get_output_stream(),
+            "There are no draft commits to land in: {}",
+            revset.0
+        )?;
+        return Ok(Ok(()));
+    }
+
+    let secret_commits = landed_commits.intersection(&dag.query_secret_commits()?);
+    if !dag.set_is_empty(&secret_commits)? {
+        writeln!(
+            effects.get_output_stream(),
+            "Refusing to land {}, since they're marked as secret:",
+            Pluralize {
+                determiner: None,
+                amount: dag.set_count(&secret_commits)?,
+                unit: ("commit", "commits"),
+            }
+        )?;
+        for commit_oid in dag.sort(&secret_commits)? {
+            writeln!(
+                effects.get_output_stream(),
+                "{} {}",
+                glyphs.bullet_point,
+                glyphs.render(repo.friendly_describe_commit_from_oid(glyphs, commit_oid)?)?,
+            )?;
+        }
+        return Ok(Err(ExitCode(1)));
+    }
+
+    let landed_heads = dag.commit_set_to_vec(&dag.query_heads(landed_commits.clone())?)?;
+    let landed_roots = dag.commit_set_to_vec(&dag.query_roots(landed_commits.clone())?)?;
+    let (root_oid, head_oid) = match (
+        landed_roots.into_iter().exactly_one(),
+        landed_heads.iter().copied().exactly_one(),
+    ) {
+        (Ok(root_oid), Ok(head_oid)) => (root_oid, head_oid),
+        _ => {
+            writeln!(
+                effects.get_output_stream(),
+                "The commits to land must form a single line of history, but they have {}:",
+                Pluralize {
+                    determiner: None,
+                    amount: landed_heads.len(),
+                    unit: ("head", "heads"),
+                }
+            )?;
+            for commit_oid in landed_heads {
+                writeln!(
+                    effects.get_output_stream(),
+                    "{} {}",
+                    glyphs.bullet_point,
+                    glyphs.render(repo.friendly_describe_commit_from_oid(glyphs, commit_oid)?)?,
+                )?;
+            }
+            return Ok(Err(ExitCode(1)));
+        }
+    };
+
+    let main_branch = repo.get_main_branch()?;
+    let main_branch_reference_name = main_branch.get_reference_name()?;
+    let main_branch_oid = repo.get_main_branch_oid()?;
+    let main_branch_description = glyphs.render(
+        StyledStringBuilder::new()
+            .append_styled(
+                CategorizedReferenceName::new(&main_branch_reference_name).friendly_describe(),
+                BaseColor::Green.dark(),
+            )
+            .build(),
+    )?;
+
+    let now = SystemTime::now();
+    let event_tx_id = event_log_db.make_transaction_id(now, "land")?;
+    let root_commit = repo.find_commit_or_fail(root_oid)?;
+    let new_head_oid = if root_commit.get_parent_oids() == vec![main_branch_oid] {
+        head_oid
+    } else {
+        // Move the entire stack, so that the commits which aren't being landed
+        // end up on top of the new main branch.
+        let build_options = BuildRebasePlanOptions {
+            force_rewrite_public_commits: false,
+            detect_duplicate_commits_via_patch_id: false,
+            dump_rebase_constraints: false,
+            dump_rebase_plan: false,
+        };
+        let permissions = match RebasePlanPermissions::verify_rewrite_set(
+            &dag,
+            build_options,
+            &CommitSet::from(root_oid),
+        )? {
+            Ok(permissions) => permissions,
+            Err(err) => {
+                err.describe(effects, &repo, &dag)?;
+                return Ok(Err(ExitCode(1)));
+            }
+        };
+        let thread_pool = ThreadPoolBuilder::new().build()?;
+        let repo_pool = RepoResource::new_pool(&repo)?;
+        let mut builder = RebasePlanBuilder::new(&dag, permissions);
+        builder.move_subtree(root_oid, vec![main_branch_oid])?;
+        let rebase_plan = match builder.build(effects, &thread_pool, &repo_pool)? {
+            Ok(Some(rebase_plan)) => rebase_plan,
+            Ok(None) => eyre::bail!("No rebase plan was built to move {root_oid:?}"),
+            Err(err) => {
+                err.describe(effects, &repo, &dag)?;
+                return Ok(Err(ExitCode(1)));
+            }
+        };
+
+        // Always rebase in-memory, so that a merge conflict leaves the
+        // repository untouched and the landed commits can be found afterwards.
+        let execute_options = ExecuteRebasePlanOptions {
+            now,
+            event_tx_id,
+            preserve_timestamps: get_restack_preserve_timestamps(&repo)?,
+            force_in_memory: true,
+            force_on_disk: false,
+            resolve_merge_conflicts: false,
+            check_out_commit_options: CheckOutCommitOptions {
+                additional_args: Default::default(),
+                reset: false,
+                render_smartlog: false,
+            },
+        };
+        match execute_rebase_plan(
+            effects,
+            git_run_info,
+            &repo,
+            &event_log_db,
+            &rebase_plan,
+            &execute_options,
+        )? {
+            ExecuteRebasePlanResult::Succeeded {
+                rewritten_oids: Some(rewritten_oids),
+            } => match rewritten_oids.get(&head_oid) {
+                Some(MaybeZeroOid::NonZero(new_head_oid)) => *new_head_oid,
+                Some(MaybeZeroOid::Zero) | None => {
+                    eyre::bail!("Could not find rewritten commit for {head_oid:?}")
+                }
+            },
+            ExecuteRebasePlanResult::Succeeded {
+                rewritten_oids: None,
+            } => eyre::bail!("No rewritten commits were returned by the in-memory rebase"),
+            ExecuteRebasePlanResult::DeclinedToMerge { failed_merge_info } => {
+                failed_merge_info.describe(effects, &repo, MergeConflictRemediation::Sync)?;
+                return Ok(Err(ExitCode(1)));
+            }
+            ExecuteRebasePlanResult::Failed { exit_code } => {
+                return Ok(Err(exit_code));
+            }
+        }
+    };
+
+    let new_head_commit = repo.find_commit_or_fail(new_head_oid)?;
+    if push {
+        let push_remote_name = match main_branch.get_push_remote_name()? {
+            Some(push_remote_name) => push_remote_name,
+            None => {
+                writeln!(
+                    effects.get_output_stream(),
+                    "{main_branch_description} has no remote to push to. Set branch.{}.remote, or land without --push.",
+                    main_branch.get_name()?,
+                )?;
+                return Ok(Err(ExitCode(1)));
+            }
+        };
+        // Push to the branch which the main branch tracks, which may have a
+        // different name on the remote.
+        let remote_reference_name = match main_branch.get_upstream_branch()? {
+            Some(upstream_branch) => {
+                let upstream_branch_name = upstream_branch.get_name()?;
+                match upstream_branch_name.strip_prefix(&format!("{push_remote_name}/")) {
+                    Some(name) => format!("refs/heads/{name}"),
+                    None => main_branch_reference_name.as_str().to_string(),
+                }
+            }
+            None => main_branch_reference_name.as_str().to_string(),
+        };
+        writeln!(
+            effects.get_output_stream(),
+            "Pushing {} to {push_remote_name}",
+            glyphs.render(new_head_commit.friendly_describe(glyphs)?)?,
+        )?;
+        let exit_code = git_run_info.run(
+            effects,
+            Some(event_tx_id),
+            &[
+                "push",
+                &push_remote_name,
+                &format!("{new_head_oid}:{remote_reference_name}"),
+            ],
+        )?;
+        if let Err(exit_code) = exit_code {
+            writeln!(
+                effects.get_output_stream(),
+                "Could not push to {push_remote_name}, so {main_branch_description} wasn't updated. To land onto the latest remote commits, run: git sync --pull"
+            )?;
+            writeln!(effects.get_output_stream(), "Then retry this operation.")?;
+            return Ok(Err(exit_code));
+        }
+    }
+
+    writeln!(
+        effects.get_output_stream(),
+        "Fast-forwarding {} to {}",
+        main_branch_description,
+        glyphs.render(new_head_commit.friendly_describe(glyphs)?)?,
+    )?;
+    let head_info = repo.get_head_info()?;
+    let args = if head_info.reference_name.as_ref() == Some(&main_branch_reference_name) {
+        // Update the index and working copy as well, so that the landed
+        // changes don't appear as staged changes in `git status`.
+        vec![
+            "reset".to_string(),
+            "--keep".to_string(),
+            new_head_oid.to_string(),
+        ]
+    } else {
+        vec![
+            "update-ref".to_string(),
+            main_branch_reference_name.as_str().to_string(),
+            new_head_oid.to_string(),
+            main_branch_oid.to_string(),
+        ]
+    };
+    if let Err(exit_code) = git_run_info.run(effects, Some(event_tx_id), &args)? {
+        writeln!(
+            effects.get_output_stream(),
+            "Could not update {main_branch_description}. The landed commits are at {}.",
+            glyphs.render(new_head_commit.friendly_describe(glyphs)?)?,
+        )?;
+        writeln!(
+            effects.get_output_stream(),
+            "To finish landing them, run: git {}",
+            args.join(" ")
+        )?;
+        writeln!(
+            effects.get_output_stream(),
+            "Or, to revert this operation, run: git undo"
+        )?;
+        return Ok(Err(exit_code));
+    }
+
+    writeln!(
+        effects.get_output_stream(),
+        "Landed {} onto {}.",
+        Pluralize {
+            determiner: None,
+            amount: dag.set_count(&landed_commits)?,
+            unit: ("commit", "commits"),
+        },
+        main_branch_description,
+    )?;
+    Ok(Ok(()))
+}
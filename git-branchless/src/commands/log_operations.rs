@@ -0,0 +1,136 @@
+//! List recent operations recorded in the event log.
+//!
+//! Each event transaction is treated as one operation, similar to `jj op log`.
+//! The transaction IDs shown can be passed to `git undo --to`.
+
+use std::collections::HashMap;
+use std::fmt::Write;
+use std::time::{Duration, SystemTime};
+
+use lib::core::effects::Effects;
+use lib::core::eventlog::{Event, EventLogDb, EventReplayer, EventTransactionId};
+use lib::core::formatting::Pluralize;
+use lib::core::node_descriptors::RelativeTimeDescriptor;
+use lib::git::{MaybeZeroOid, Repo};
+use lib::util::EyreExitOr;
+use tracing::instrument;
+
+/// Summarize the effects of the given events, such as `1 commit created, 2
+/// refs updated`.
+fn summarize_events(events: &[&Event]) -> String {
+    let mut num_commits_created = 0;
+    let mut num_commits_rewritten = 0;
+    let mut num_commits_hidden = 0;
+    let mut num_commits_unhidden = 0;
+    let mut num_commits_copied = 0;
+    let mut num_refs_updated = 0;
+    let mut num_snapshots = 0;
+    let mut head_moved = false;
+    for event in events {
+        match event {
+            Event::CommitEvent { .. } => num_commits_created += 1,
+            Event::RewriteEvent { .. } => num_commits_rewritten += 1,
+            Event::ObsoleteEvent { .. } => num_commits_hidden += 1,
+            Event::UnobsoleteEvent { .. } => num_commits_unhidden += 1,
+            Event::WorkingCopySnapshot { .. } => num_snapshots += 1,
+            Event::CopyEvent { .. } => num_commits_copied += 1,
+            Event::RefUpdateEvent {
+                ref_name,
+                new_oid: MaybeZeroOid::NonZero(_),
+                ..
+            } if ref_name.as_str() == "HEAD" => head_moved = true,
+            Event::RefUpdateEvent { ref_name, .. } if ref_name.as_str() == "HEAD" => {}
+            Event::RefUpdateEvent { .. } => num_refs_updated += 1,
+        }
+    }
+
+    let mut parts: Vec<String> = vec![
+        (num_commits_created, ("commit", "commits"), "created"),
+        (num_commits_rewritten, ("commit", "commits"), "rewritten"),
+        (num_commits_hidden, ("commit", "commits"), "hidden"),
+        (num_commits_unhidden, ("commit", "commits"), "unhidden"),
+        (num_commits_copied, ("commit", "commits"), "copied"),
+        (num_refs_updated, ("ref", "refs"), "updated"),
+        (num_snapshots, ("snapshot", "snapshots"), "taken"),
+    ]
+    .into_iter()
+    .filter(|(amount, _unit, _verb)| *amount > 0)
+    .map(|(amount, unit, verb)| {
+        let amount = Pluralize {
+            determiner: None,
+            amount,
+            unit,
+        };
+        format!("{amount} {verb}")
+    })
+    .collect();
+    if head_moved {
+        parts.push("HEAD moved".to_string());
+    }
+    parts.join(", ")
+}
+
+/// Print the most recent operations, from newest to oldest.
+#[instrument]
+pub fn log_operations(effects: &Effects, limit: usize) -> EyreExitOr<()> {
+    let now = SystemTime::now();
+    let repo = Repo::from_current_dir()?;
+    let conn = repo.get_db_conn()?;
+    let event_log_db = EventLogDb::new(&conn)?;
+    let event_replayer = EventReplayer::from_event_log_db(effects, &repo, &event_log_db)?;
+
+    let mut tx_events: HashMap<EventTransactionId, Vec<&Event>> = HashMap::new();
+    for event in event_replayer.get_events_since_cursor(event_replayer.make_cursor(0)) {
+        tx_events
+            .entry(event.get_event_tx_id())
+            .or_default()
+            .push(event);
+    }
+
+    let relative_time_provider = RelativeTimeDescriptor::new(&repo, now)?;
+    let transactions = event_log_db.get_transactions()?;
+    let transactions = transactions
+        .iter()
+        .rev()
+        .filter_map(|transaction| {
+            tx_events
+                .get(&transaction.event_tx_id)
+                .map(|events| (transaction, events))
+        })
+        .take(limit);
+    let mut any_transactions = false;
+    for (transaction, events) in transactions {
+        any_transactions = true;
+        let relative_time = if relative_time_provider.is_enabled() {
+            let timestamp = SystemTime::UNIX_EPOCH + Duration::from_secs_f64(transaction.timestamp);
+            format!(
+                " ({} ago)",
+                RelativeTimeDescriptor::describe_time_delta(now, timestamp)?
+            )
+        } else {
+            String::new()
+        };
+        let description = match &transaction.label {
+            Some(label) => format!("{}: {}", transaction.message, label),
+            None => transaction.message.clone(),
+        };
+        writeln!(
+            effects.get_output_stream(),
+            "{} {}{}",
+            transaction.event_tx_id,
+            description,
+            relative_time,
+        )?;
+        writeln!(
+            effects.get_output_stream(),
+            "    {}",
+            summarize_events(events)
+        )?;
+    }
+
+    if !any_transactions {
+        writeln!(effects.get_output_stream(), "No operations recorded.")?;
+    }
+
+    Ok(Ok(()))
+}
@@ -2,24 +2,60 @@
 
 mod amend;
 mod bug_report;
+mod cache;
+mod completions;
+mod continue_abort;
+mod debug;
+mod diff;
+mod edit;
+mod edit_todo;
+mod forget;
 mod hide;
+mod import;
+mod land;
+mod log_operations;
+mod obslog;
+mod phase;
+mod prune;
 mod repair;
 mod restack;
+mod rewrite_metadata;
+mod serve;
 mod snapshot;
+mod stack;
+mod stats;
 mod sync;
+mod sync_state;
+mod verify;
+mod worktree;
 mod wrap;
 
+use std::fmt::Write;
+use std::io::{stdin, BufRead, StdinLock};
+
+use continue_abort::Action;
 use git_branchless_invoke::CommandContext;
+use lib::core::phase::Phase;
 use lib::core::rewrite::MergeConflictRemediation;
 
 use lib::util::ExitCode;
 use lib::{core::gc, util::EyreExitOr};
 
 use git_branchless_opts::{
-    rewrite_args, Command, Opts, ResolveRevsetOptions, SnapshotSubcommand, WrappedCommand,
+    rewrite_args, Command, OperationSubcommand, Opts, ResolveRevsetOptions, SnapshotSubcommand,
+    SyncStateSubcommand, WrappedCommand,
 };
 use lib::git::GitRunInfo;
 
+/// Get the user's input, if they're at a terminal to answer prompts.
+fn get_attended_input() -> Option<StdinLock<'static>> {
+    if console::user_attended() {
+        Some(stdin().lock())
+    } else {
+        None
+    }
+}
+
 fn command_main(ctx: CommandContext, opts: Opts) -> EyreExitOr<()> {
     let CommandContext {
         effects,
@@ -30,20 +66,59 @@ fn command_main(ctx: CommandContext, opts: Opts) -> EyreExitOr<()> {
         command,
     } = opts;
 
+    // Hooks are run by Git in the middle of other operations, and `init`
+    // reinstalls everything anyways, so don't audit the hooks for them. A
+    // failure to reinstall them shouldn't prevent the command from running.
+    if !matches!(command, Command::Hook(_) | Command::Init(_)) {
+        if let Err(err) = git_branchless_init::reinstall_stale_hooks(&effects, &git_run_info) {
+            writeln!(
+                effects.get_error_stream(),
+                "Warning: could not reinstall stale hooks and aliases: {err:#}"
+            )?;
+        }
+    }
+
     let exit_code = match command {
         Command::Amend {
             move_options,
             reparent,
+            to,
+            patch,
         } => amend::amend(
             &effects,
             &git_run_info,
             &ResolveRevsetOptions::default(),
             &move_options,
             reparent,
+            to,
+            patch,
         )?,
 
+        Command::Abort => {
+            continue_abort::continue_or_abort(&effects, &git_run_info, Action::Abort)?
+        }
+
         Command::BugReport => bug_report::bug_report(&effects, &git_run_info)?,
 
+        Command::Cache(args) => cache::cache(&effects, args.subcommand)?,
+
+        Command::Completions(args) => {
+            completions::completions(&effects, args.shell, args.list_commits)?
+        }
+
+        Command::Continue => {
+            continue_abort::continue_or_abort(&effects, &git_run_info, Action::Continue)?
+        }
+
+        Command::Debug(args) => debug::debug(&effects, &git_run_info, args.subcommand)?,
+
+        Command::Diff {
+            revset,
+            resolve_revset_options,
+            stat,
+            per_commit,
+        } => diff::diff(&effects, revset, &resolve_revset_options, stat, per_commit)?,
+
         Command::Difftool(opts) => {
             let result = scm_record::scm_diff_editor::scm_diff_editor_main(opts);
             match result {
@@ -55,6 +130,28 @@ fn command_main(ctx: CommandContext, opts: Opts) -> EyreExitOr<()> {
             }
         }
 
+        Command::Edit {
+            revset,
+            resolve_revset_options,
+            force_rewrite_public_commits,
+        } => edit::edit(
+            &effects,
+            &git_run_info,
+            revset,
+            &resolve_revset_options,
+            force_rewrite_public_commits,
+        )?,
+
+        Command::EditTodo {
+            autosquash_stack,
+            path,
+        } => edit_todo::edit_todo(&path, autosquash_stack)?,
+
+        Command::Forget {
+            revsets,
+            resolve_revset_options,
+        } => forget::forget(&effects, revsets, &resolve_revset_options)?,
+
         Command::Switch { switch_options } => {
             git_branchless_navigation::switch(&effects, &git_run_info, &switch_options)?
         }
@@ -80,12 +177,34 @@ fn command_main(ctx: CommandContext, opts: Opts) -> EyreExitOr<()> {
             recursive,
         )?,
 
+        Command::Import {
+            from_hg,
+            from_notes: _,
+        } => match from_hg {
+            Some(hg_repo_path) => import::import(&effects, &hg_repo_path)?,
+            None => import::import_notes(&effects)?,
+        },
+
         Command::Init(args) => git_branchless_init::command_main(ctx, args)?,
 
         Command::InstallManPages(args) => {
             git_branchless_init::command_install_man_pages(ctx, args)?
         }
 
+        Command::Land {
+            revset,
+            resolve_revset_options,
+            push,
+        } => land::land(
+            &effects,
+            &git_run_info,
+            revset,
+            &resolve_revset_options,
+            push,
+        )?,
+
+        Command::LogOperations { limit } => log_operations::log_operations(&effects, limit)?,
+
         Command::Move {
             source,
             dest,
@@ -95,6 +214,7 @@ fn command_main(ctx: CommandContext, opts: Opts) -> EyreExitOr<()> {
             move_options,
             fixup,
             insert,
+            autosquash,
         } => git_branchless_move::r#move(
             &effects,
             &git_run_info,
@@ -106,6 +226,10 @@ fn command_main(ctx: CommandContext, opts: Opts) -> EyreExitOr<()> {
             &move_options,
             fixup,
             insert,
+            autosquash,
+            get_attended_input()
+                .as_mut()
+                .map(|input| input as &mut dyn BufRead),
         )?,
 
         Command::Next {
@@ -117,6 +241,31 @@ fn command_main(ctx: CommandContext, opts: Opts) -> EyreExitOr<()> {
             &traverse_commits_options,
         )?,
 
+        Command::Obslog {
+            revset,
+            resolve_revset_options,
+        } => obslog::obslog(&effects, revset, &resolve_revset_options)?,
+
+        Command::Operation { subcommand } => match subcommand {
+            OperationSubcommand::Status => continue_abort::status(&effects)?,
+        },
+
+        Command::Phase {
+            revsets,
+            resolve_revset_options,
+            public,
+            draft,
+            secret,
+        } => {
+            let phase = match (public, draft, secret) {
+                (true, _, _) => Some(Phase::Public),
+                (_, true, _) => Some(Phase::Draft),
+                (_, _, true) => Some(Phase::Secret),
+                (false, false, false) => None,
+            };
+            phase::phase(&effects, revsets, &resolve_revset_options, phase)?
+        }
+
         Command::Prev {
             traverse_commits_options,
         } => git_branchless_navigation::traverse_commits(
@@ -126,6 +275,12 @@ fn command_main(ctx: CommandContext, opts: Opts) -> EyreExitOr<()> {
             &traverse_commits_options,
         )?,
 
+        Command::Prune {
+            older_than_days,
+            dry_run,
+            gc,
+        } => prune::prune(&effects, &git_run_info, older_than_days, dry_run, gc)?,
+
         Command::Query(args) => git_branchless_query::command_main(ctx, args)?,
 
         Command::Repair { dry_run } => repair::repair(&effects, dry_run)?,
@@ -134,13 +289,18 @@ fn command_main(ctx: CommandContext, opts: Opts) -> EyreExitOr<()> {
             revsets,
             resolve_revset_options,
             move_options,
+            autosquash,
         } => restack::restack(
             &effects,
             &git_run_info,
             revsets,
             &resolve_revset_options,
             &move_options,
+            autosquash,
             MergeConflictRemediation::Retry,
+            get_attended_input()
+                .as_mut()
+                .map(|input| input as &mut dyn BufRead),
         )?,
 
         Command::Record(args) => git_branchless_record::command_main(ctx, args)?,
@@ -152,11 +312,28 @@ fn command_main(ctx: CommandContext, opts: Opts) -> EyreExitOr<()> {
             force_rewrite_public_commits,
             discard,
             commit_to_fixup,
+            message_template,
+            author,
+            date,
+            add_trailers,
+            remove_trailers,
+            trailers_forge,
         } => {
+            let trailer_updates = git_branchless_reword::TrailerUpdates {
+                add: add_trailers,
+                remove: remove_trailers,
+                forge: trailers_forge,
+            };
             let messages = if discard {
                 git_branchless_reword::InitialCommitMessages::Discard
             } else if let Some(commit_to_fixup) = commit_to_fixup {
                 git_branchless_reword::InitialCommitMessages::FixUp(commit_to_fixup)
+            } else if let Some(message_template) = message_template {
+                git_branchless_reword::InitialCommitMessages::Template(message_template)
+            } else if messages.is_empty()
+                && (author.is_some() || date.is_some() || !trailer_updates.is_empty())
+            {
+                git_branchless_reword::InitialCommitMessages::Keep
             } else {
                 git_branchless_reword::InitialCommitMessages::Messages(messages)
             };
@@ -165,24 +342,51 @@ fn command_main(ctx: CommandContext, opts: Opts) -> EyreExitOr<()> {
                 revsets,
                 &resolve_revset_options,
                 messages,
+                git_branchless_reword::AuthorUpdates { author, date },
+                trailer_updates,
                 &git_run_info,
                 force_rewrite_public_commits,
             )?
         }
 
+        Command::RewriteMetadata {
+            revsets,
+            resolve_revset_options,
+            author_mappings,
+            force_rewrite_public_commits,
+        } => rewrite_metadata::rewrite_metadata(
+            &effects,
+            &git_run_info,
+            revsets,
+            &resolve_revset_options,
+            &author_mappings,
+            force_rewrite_public_commits,
+        )?,
+
+        Command::Serve { port } => serve::serve(&effects, &git_run_info, port)?,
+
         Command::Smartlog(args) => git_branchless_smartlog::command_main(ctx, args)?,
 
         Command::Snapshot { subcommand } => match subcommand {
             SnapshotSubcommand::Create => snapshot::create(&effects, &git_run_info)?,
+            SnapshotSubcommand::List => snapshot::list(&effects, &git_run_info)?,
             SnapshotSubcommand::Restore { snapshot_oid } => {
                 snapshot::restore(&effects, &git_run_info, snapshot_oid)?
             }
+            SnapshotSubcommand::Show { snapshot_oid } => {
+                snapshot::show(&effects, &git_run_info, snapshot_oid)?
+            }
         },
 
+        Command::Stack(args) => stack::stack(&effects, &git_run_info, args.subcommand)?,
+
+        Command::Stats { days } => stats::stats(&effects, days)?,
+
         Command::Submit(args) => git_branchless_submit::command_main(ctx, args)?,
 
         Command::Sync {
             pull,
+            onto_new_main,
             move_options,
             revsets,
             resolve_revset_options,
@@ -190,22 +394,60 @@ fn command_main(ctx: CommandContext, opts: Opts) -> EyreExitOr<()> {
             &effects,
             &git_run_info,
             pull,
+            onto_new_main,
             &move_options,
             revsets,
             &resolve_revset_options,
         )?,
 
+        Command::SyncState { subcommand } => match subcommand {
+            SyncStateSubcommand::Pull { remote } => {
+                sync_state::pull(&effects, &git_run_info, remote)?
+            }
+            SyncStateSubcommand::Push { remote } => {
+                sync_state::push(&effects, &git_run_info, remote)?
+            }
+        },
+
         Command::Test(args) => git_branchless_test::command_main(ctx, args)?,
 
-        Command::Undo { interactive, yes } => {
-            git_branchless_undo::undo(&effects, &git_run_info, interactive, yes)?
+        Command::Undo {
+            interactive,
+            to,
+            yes,
+            force,
+            merge,
+        } => {
+            git_branchless_undo::undo(&effects, &git_run_info, interactive, to, yes, force, merge)?
         }
 
         Command::Unhide {
             revsets,
             resolve_revset_options,
             recursive,
-        } => hide::unhide(&effects, revsets, &resolve_revset_options, recursive)?,
+        } => hide::unhide(
+            &effects,
+            &git_run_info,
+            revsets,
+            &resolve_revset_options,
+            recursive,
+        )?,
+
+        Command::Verify => verify::verify(&effects)?,
+
+        Command::Worktree {
+            revset,
+            resolve_revset_options,
+            path,
+            remove,
+        } => worktree::worktree(
+            &effects,
+            &git_run_info,
+            revset,
+            &resolve_revset_options,
+            path,
+            remove,
+        )?,
 
         Command::Wrap {
             git_executable: explicit_git_executable,
@@ -218,7 +460,7 @@ fn command_main(ctx: CommandContext, opts: Opts) -> EyreExitOr<()> {
                 },
                 None => git_run_info,
             };
-            wrap::wrap(&git_run_info, args.as_slice())?
+            wrap::wrap(&effects, &git_run_info, args.as_slice())?
         }
     };
 
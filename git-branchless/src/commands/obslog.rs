@@ -0,0 +1,212 @@
+//! Show the rewrite history of a commit.
+//!
+//! This walks the `RewriteEvent`s in the event log to find every predecessor
+//! and successor of the given commit, similar to `hg obslog`. Any of those
+//! commits which were created by `git cherry-pick` or `git revert` are also
+//! shown with the commit that they were copied from.
+
+use std::collections::{HashSet, VecDeque};
+use std::fmt::Write;
+use std::time::SystemTime;
+
+use git_branchless_opts::{ResolveRevsetOptions, Revset};
+use git_branchless_revset::resolve_commits;
+use lib::core::dag::Dag;
+use lib::core::effects::Effects;
+use lib::core::eventlog::{CopyType, Event, EventLogDb, EventReplayer};
+use lib::core::node_descriptors::RelativeTimeDescriptor;
+use lib::core::repo_ext::RepoExt;
+use lib::git::{MaybeZeroOid, NonZeroOid, Repo};
+use lib::util::{ExitCode, EyreExitOr};
+use tracing::instrument;
+
+/// Find all rewrite events which are transitively connected to the given
+/// commit, along with the copy events for any of the commits involved, in the
+/// order that they were recorded.
+fn find_related_rewrite_events(events: &[Event], commit_oid: NonZeroOid) -> Vec<&Event> {
+    let rewrites: Vec<(usize, NonZeroOid, MaybeZeroOid)> = events
+        .iter()
+        .enumerate()
+        .filter_map(|(idx, event)| match event {
+            Event::RewriteEvent {
+                timestamp: _,
+                event_tx_id: _,
+                old_commit_oid: MaybeZeroOid::NonZero(old_commit_oid),
+                new_commit_oid,
+            } => Some((idx, *old_commit_oid, *new_commit_oid)),
+            _ => None,
+        })
+        .collect();
+
+    let mut seen_oids: HashSet<NonZeroOid> = HashSet::from([commit_oid]);
+    let mut seen_event_idxs: HashSet<usize> = HashSet::new();
+    let mut queue: VecDeque<NonZeroOid> = VecDeque::from([commit_oid]);
+    while let Some(oid) = queue.pop_front() {
+        for (idx, old_commit_oid, new_commit_oid) in rewrites.iter() {
+            let new_commit_oid = match new_commit_oid {
+                MaybeZeroOid::NonZero(new_commit_oid) => Some(*new_commit_oid),
+                MaybeZeroOid::Zero => None,
+            };
+            let neighbor_oid = if *old_commit_oid == oid {
+                new_commit_oid
+            } else if new_commit_oid == Some(oid) {
+                Some(*old_commit_oid)
+            } else {
+                continue;
+            };
+
+            seen_event_idxs.insert(*idx);
+            if let Some(neighbor_oid) = neighbor_oid {
+                if seen_oids.insert(neighbor_oid) {
+                    queue.push_back(neighbor_oid);
+                }
+            }
+        }
+    }
+
+    for (idx, event) in events.iter().enumerate() {
+        if let Event::CopyEvent { new_commit_oid, .. } = event {
+            if seen_oids.contains(new_commit_oid) {
+                seen_event_idxs.insert(idx);
+            }
+        }
+    }
+
+    let mut event_idxs: Vec<usize> = seen_event_idxs.into_iter().collect();
+    event_idxs.sort_unstable();
+    event_idxs.into_iter().map(|idx| &events[idx]).collect()
+}
+
+/// Show all the predecessors and successors of the given commit.
+#[instrument]
+pub fn obslog(
+    effects: &Effects,
+    revset: Revset,
+    resolve_revset_options: &ResolveRevsetOptions,
+) -> EyreExitOr<()> {
+    let now = SystemTime::now();
+    let glyphs = effects.get_glyphs();
+    let repo = Repo::from_current_dir()?;
+    let references_snapshot = repo.get_references_snapshot()?;
+    let conn = repo.get_db_conn()?;
+    let event_log_db = EventLogDb::new(&conn)?;
+    let event_replayer = EventReplayer::from_event_log_db(effects, &repo, &event_log_db)?;
+    let event_cursor = event_replayer.make_default_cursor();
+    let mut dag = Dag::open_and_sync(
+        effects,
+        &repo,
+        &event_replayer,
+        event_cursor,
+        &references_snapshot,
+    )?;
+
+    let commit_oid = match resolve_commits(
+        effects,
+        &repo,
+        &mut dag,
+        &[revset.clone()],
+        resolve_revset_options,
+    ) {
+        Ok(commit_sets) => match dag.commit_set_to_vec(&commit_sets[0])?.as_slice() {
+            [only_commit_oid] => *only_commit_oid,
+            other => {
+                let Revset(expr) = revset;
+                writeln!(
+                    effects.get_error_stream(),
+                    "Expected revset to expand to exactly 1 commit (got {}): {}",
+                    other.len(),
+                    expr,
+                )?;
+                return Ok(Err(ExitCode(1)));
+            }
+        },
+        Err(err) => {
+            err.describe(effects)?;
+            return Ok(Err(ExitCode(1)));
+        }
+    };
+
+    let events = event_log_db.get_events()?;
+    let rewrite_events = find_related_rewrite_events(&events, commit_oid);
+    if rewrite_events.is_empty() {
+        writeln!(
+            effects.get_output_stream(),
+            "No rewrites recorded for commit: {}",
+            glyphs.render(repo.friendly_describe_commit_from_oid(glyphs, commit_oid)?)?,
+        )?;
+        return Ok(Ok(()));
+    }
+
+    let relative_time_provider = RelativeTimeDescriptor::new(&repo, now)?;
+    for event in rewrite_events {
+        let operation = match event_log_db.get_transaction_message(event.get_event_tx_id()) {
+            Ok(message) => message,
+            Err(_) => "unknown operation".to_string(),
+        };
+        let relative_time = if relative_time_provider.is_enabled() {
+            format!(
+                " ({} ago)",
+                RelativeTimeDescriptor::describe_time_delta(now, event.get_timestamp())?
+            )
+        } else {
+            String::new()
+        };
+
+        let (old_commit_oid, new_commit_oid) = match event {
+            Event::RewriteEvent {
+                timestamp: _,
+                event_tx_id: _,
+                old_commit_oid: MaybeZeroOid::NonZero(old_commit_oid),
+                new_commit_oid,
+            } => (*old_commit_oid, *new_commit_oid),
+
+            Event::CopyEvent {
+                timestamp: _,
+                event_tx_id: _,
+                copy_type,
+                source_commit_oid,
+                new_commit_oid,
+            } => {
+                let description = match copy_type {
+                    CopyType::CherryPick => "copied from",
+                    CopyType::Revert => "reverts",
+                };
+                writeln!(
+                    effects.get_output_stream(),
+                    "{}",
+                    glyphs
+                        .render(repo.friendly_describe_commit_from_oid(glyphs, *new_commit_oid)?)?,
+                )?;
+                writeln!(
+                    effects.get_output_stream(),
+                    "    {description} {} by {operation}{relative_time}",
+                    glyphs.render(
+                        repo.friendly_describe_commit_from_oid(glyphs, *source_commit_oid)?
+                    )?,
+                )?;
+                continue;
+            }
+
+            _ => continue,
+        };
+
+        writeln!(
+            effects.get_output_stream(),
+            "{}",
+            glyphs.render(repo.friendly_describe_commit_from_oid(glyphs, old_commit_oid)?)?,
+        )?;
+        match new_commit_oid {
+            MaybeZeroOid::NonZero(new_commit_oid) => writeln!(
+                effects.get_output_stream(),
+                "    rewritten as {} by {operation}{relative_time}",
+                glyphs.render(repo.friendly_describe_commit_from_oid(glyphs, new_commit_oid)?)?,
+            )?,
+            MaybeZeroOid::Zero => writeln!(
+                effects.get_output_stream(),
+                "    deleted by {operation}{relative_time}",
+            )?,
+        }
+    }
+
+    Ok(Ok(()))
+}
@@ -0,0 +1,116 @@
+//! Show or set the phase (public, draft, or secret) of commits.
+//!
+//! See [`lib::core::phase`] for how phases are interpreted.
+
+use std::fmt::Write;
+
+use git_branchless_opts::{ResolveRevsetOptions, Revset};
+use git_branchless_revset::resolve_commits;
+use lib::core::dag::{union_all, Dag};
+use lib::core::effects::Effects;
+use lib::core::eventlog::{EventLogDb, EventReplayer};
+use lib::core::formatting::Pluralize;
+use lib::core::phase::{Phase, PhaseDb};
+use lib::core::repo_ext::RepoExt;
+use lib::git::Repo;
+use lib::util::{ExitCode, EyreExitOr};
+use tracing::instrument;
+
+/// Print the phase of the given commits, or record the given phase for them if
+/// provided.
+#[instrument]
+pub fn phase(
+    effects: &Effects,
+    revsets: Vec<Revset>,
+    resolve_revset_options: &ResolveRevsetOptions,
+    phase: Option<Phase>,
+) -> EyreExitOr<()> {
+    let glyphs = effects.get_glyphs();
+    let repo = Repo::from_current_dir()?;
+    let references_snapshot = repo.get_references_snapshot()?;
+    let conn = repo.get_db_conn()?;
+    let event_log_db = EventLogDb::new(&conn)?;
+    let event_replayer = EventReplayer::from_event_log_db(effects, &repo, &event_log_db)?;
+    let event_cursor = event_replayer.make_default_cursor();
+    let mut dag = Dag::open_and_sync(
+        effects,
+        &repo,
+        &event_replayer,
+        event_cursor,
+        &references_snapshot,
+    )?;
+
+    let commits = match resolve_commits(effects, &repo, &mut dag, &revsets, resolve_revset_options)
+    {
+        Ok(commit_sets) => union_all(&commit_sets),
+        Err(err) => {
+            err.describe(effects)?;
+            return Ok(Err(ExitCode(1)));
+        }
+    };
+
+    let phase = match phase {
+        Some(phase) => phase,
+        None => {
+            let secret_commits = dag.query_secret_commits()?;
+            for commit_oid in dag.sort(&commits)? {
+                let phase = if dag.is_public_commit(commit_oid)? {
+                    Phase::Public
+                } else if dag.set_contains(&secret_commits, commit_oid)? {
+                    Phase::Secret
+                } else {
+                    Phase::Draft
+                };
+                writeln!(
+                    effects.get_output_stream(),
+                    "{phase}: {}",
+                    glyphs.render(repo.friendly_describe_commit_from_oid(glyphs, commit_oid)?)?,
+                )?;
+            }
+            return Ok(Ok(()));
+        }
+    };
+
+    let phase_db = PhaseDb::new(&conn)?;
+    match phase {
+        Phase::Public => {}
+        Phase::Draft | Phase::Secret => {
+            // A commit recorded as public makes all of its ancestors public, so
+            // forget any such records for the descendants of these commits.
+            let public_descendants = dag
+                .query_descendants(commits.clone())?
+                .intersection(&dag.public_phase_commits);
+            phase_db.set_phase(dag.commit_set_to_vec(&public_descendants)?, Phase::Draft)?;
+        }
+    }
+    phase_db.set_phase(dag.commit_set_to_vec(&commits)?, phase)?;
+    writeln!(
+        effects.get_output_stream(),
+        "Recorded {} as {phase}.",
+        Pluralize {
+            determiner: None,
+            amount: dag.set_count(&commits)?,
+            unit: ("commit", "commits"),
+        },
+    )?;
+
+    let main_public_commits = commits.intersection(&dag.query_ancestors(dag.query_main_heads())?);
+    if phase != Phase::Public && !dag.set_is_empty(&main_public_commits)? {
+        writeln!(
+            effects.get_output_stream(),
+            "({} still public, since {} reachable from the main branch.)",
+            Pluralize {
+                determiner: None,
+                amount: dag.set_count(&main_public_commits)?,
+                unit: ("commit is", "commits are"),
+            },
+            if dag.set_count(&main_public_commits)? == 1 {
+                "it is"
+            } else {
+                "they are"
+            },
+        )?;
+    }
+
+    Ok(Ok(()))
+}
@@ -0,0 +1,90 @@
+//! Stop keeping old hidden commits alive.
+//!
+//! Branchless keeps a reference under `refs/branchless/` to each commit it
+//! has observed so that Git's garbage collection doesn't delete commits which
+//! may still be restored. Over time, long-lived repositories can accumulate
+//! many such references to hidden commits which nobody will ever restore.
+
+use std::fmt::Write;
+use std::time::{Duration, SystemTime};
+
+use lib::core::effects::Effects;
+use lib::core::eventlog::{EventLogDb, EventReplayer};
+use lib::core::formatting::Pluralize;
+use lib::core::gc::find_prunable_references;
+use lib::git::{GitRunInfo, Repo};
+use lib::try_exit_code;
+use lib::util::EyreExitOr;
+use tracing::instrument;
+
+const SECONDS_PER_DAY: u64 = 24 * 60 * 60;
+
+/// Remove the references keeping alive the commits which were hidden at least
+/// `older_than_days` days ago, and optionally run `git gc` to delete them.
+#[instrument]
+pub fn prune(
+    effects: &Effects,
+    git_run_info: &GitRunInfo,
+    older_than_days: u64,
+    dry_run: bool,
+    gc: bool,
+) -> EyreExitOr<()> {
+    let glyphs = effects.get_glyphs();
+    let now = SystemTime::now();
+    let repo = Repo::from_current_dir()?;
+    let conn = repo.get_db_conn()?;
+    let event_log_db = EventLogDb::new(&conn)?;
+    let event_replayer = EventReplayer::from_event_log_db(effects, &repo, &event_log_db)?;
+    let event_cursor = event_replayer.make_default_cursor();
+
+    let cutoff = now
+        .checked_sub(Duration::from_secs(
+            older_than_days.saturating_mul(SECONDS_PER_DAY),
+        ))
+        .unwrap_or(SystemTime::UNIX_EPOCH);
+    let mut prunable_references =
+        find_prunable_references(&repo, &event_replayer, event_cursor, cutoff)?;
+    prunable_references.sort_by_key(|(_reference, commit)| commit.get_time());
+
+    if prunable_references.is_empty() {
+        writeln!(effects.get_output_stream(), "No hidden commits to prune.")?;
+        return Ok(Ok(()));
+    }
+
+    writeln!(
+        effects.get_output_stream(),
+        "{} {}:",
+        if dry_run { "Would prune" } else { "Pruned" },
+        Pluralize {
+            determiner: None,
+            amount: prunable_references.len(),
+            unit: ("hidden commit", "hidden commits"),
+        }
+    )?;
+    for (mut reference, commit) in prunable_references {
+        writeln!(
+            effects.get_output_stream(),
+            "{}",
+            glyphs.render(commit.friendly_describe(glyphs)?)?
+        )?;
+        if !dry_run {
+            reference.delete()?;
+        }
+    }
+
+    if dry_run {
+        writeln!(
+            effects.get_output_stream(),
+            "(This was a dry-run; run without --dry-run to apply changes.)"
+        )?;
+    } else if gc {
+        try_exit_code!(git_run_info.run(effects, None, &["gc"])?);
+    } else {
+        writeln!(
+            effects.get_output_stream(),
+            "To delete them from the repository, run: git gc"
+        )?;
+    }
+
+    Ok(Ok(()))
+}
@@ -1,44 +1,230 @@
+use std::collections::HashMap;
 use std::fmt::Write;
 use std::{collections::HashSet, time::SystemTime};
 
 use itertools::Itertools;
-use lib::git::{CategorizedReferenceName, MaybeZeroOid};
+use lib::core::gc::get_event_commit_oids;
+use lib::git::{CategorizedReferenceName, MaybeZeroOid, NonZeroOid, ReferenceName};
 use lib::util::EyreExitOr;
 use lib::{
     core::{
         effects::{Effects, OperationType},
-        eventlog::{Event, EventLogDb, EventReplayer},
+        eventlog::{Event, EventCursor, EventLogDb, EventReplayer},
         formatting::Pluralize,
     },
     git::Repo,
 };
 
-pub fn repair(effects: &Effects, dry_run: bool) -> EyreExitOr<()> {
-    let repo = Repo::from_current_dir()?;
-    let conn = repo.get_db_conn()?;
-    let event_log_db = EventLogDb::new(&conn)?;
-    let event_replayer = EventReplayer::from_event_log_db(effects, &repo, &event_log_db)?;
-    let event_cursor = event_replayer.make_default_cursor();
+/// Find the cycles in the graph of commits to the commits which they were
+/// most recently rewritten into, such as `abc` being rewritten into `def` and
+/// `def` being rewritten back into `abc`, which leave all of the commits
+/// involved hidden. Each cycle is returned starting from its smallest OID, so
+/// that the output is deterministic.
+fn find_rewrite_cycles(successors: &HashMap<NonZeroOid, NonZeroOid>) -> Vec<Vec<NonZeroOid>> {
+    let mut result = Vec::new();
+    let mut visited: HashSet<NonZeroOid> = HashSet::new();
+    for start_oid in successors.keys().sorted() {
+        let mut path = Vec::new();
+        let mut current_oid = *start_oid;
+        loop {
+            if !visited.insert(current_oid) {
+                // Only a commit visited during this walk indicates a cycle;
+                // others were already checked by a previous walk.
+                if let Some(cycle_start) = path.iter().position(|oid| *oid == current_oid) {
+                    let mut cycle = path.split_off(cycle_start);
+                    let min_index = cycle.iter().position_min().unwrap_or_default();
+                    cycle.rotate_left(min_index);
+                    result.push(cycle);
+                }
+                break;
+            }
+            path.push(current_oid);
+            current_oid = match successors.get(&current_oid) {
+                Some(next_oid) => *next_oid,
+                None => break,
+            };
+        }
+    }
+    result
+}
+
+/// The inconsistencies between the event log and the repository which were
+/// found by [`find_inconsistencies`].
+pub struct Inconsistencies {
+    /// Visible commits which no longer exist in the repository, such as ones
+    /// deleted by `git gc --prune=now`.
+    broken_commits: Vec<NonZeroOid>,
+
+    /// Branches in the event log which no longer exist in the repository.
+    broken_branches: Vec<(NonZeroOid, ReferenceName)>,
+
+    /// Commits which were recorded as rewritten into themselves.
+    self_rewritten_commits: Vec<NonZeroOid>,
+
+    /// Cycles of rewrites, as found by [`find_rewrite_cycles`].
+    rewrite_cycles: Vec<Vec<NonZeroOid>>,
+
+    /// The number of events which refer to commits which no longer exist.
+    /// These can't be repaired, but `git undo` won't be able to restore the
+    /// repository to the state before them.
+    num_events_with_missing_commits: usize,
+}
+
+impl Inconsistencies {
+    /// Whether any of the inconsistencies can be resolved by `repair`.
+    pub fn is_repairable(&self) -> bool {
+        let Self {
+            broken_commits,
+            broken_branches,
+            self_rewritten_commits,
+            rewrite_cycles,
+            num_events_with_missing_commits: _,
+        } = self;
+        !broken_commits.is_empty()
+            || !broken_branches.is_empty()
+            || !self_rewritten_commits.is_empty()
+            || !rewrite_cycles.is_empty()
+    }
+
+    /// Write a line for each kind of inconsistency which was found, starting
+    /// with `verb`.
+    pub fn describe(&self, effects: &Effects, verb: &str) -> eyre::Result<()> {
+        let Self {
+            broken_commits,
+            broken_branches,
+            self_rewritten_commits,
+            rewrite_cycles,
+            num_events_with_missing_commits,
+        } = self;
+
+        if !broken_commits.is_empty() {
+            writeln!(
+                effects.get_output_stream(),
+                "{verb} {}: {}",
+                Pluralize {
+                    determiner: None,
+                    amount: broken_commits.len(),
+                    unit: ("broken commit", "broken commits")
+                },
+                broken_commits.iter().join(", "),
+            )?;
+        }
+        if !broken_branches.is_empty() {
+            writeln!(
+                effects.get_output_stream(),
+                "{verb} {}: {}",
+                Pluralize {
+                    determiner: None,
+                    amount: broken_branches.len(),
+                    unit: ("broken branch", "broken branches")
+                },
+                broken_branches
+                    .iter()
+                    .map(
+                        |(_oid, reference_name)| CategorizedReferenceName::new(reference_name)
+                            .render_suffix()
+                    )
+                    .sorted()
+                    .join(", "),
+            )?;
+        }
+        if !self_rewritten_commits.is_empty() {
+            writeln!(
+                effects.get_output_stream(),
+                "{verb} {}: {}",
+                Pluralize {
+                    determiner: None,
+                    amount: self_rewritten_commits.len(),
+                    unit: (
+                        "commit rewritten into itself",
+                        "commits rewritten into themselves"
+                    ),
+                },
+                self_rewritten_commits.iter().join(", "),
+            )?;
+        }
+        for cycle in rewrite_cycles.iter() {
+            writeln!(
+                effects.get_output_stream(),
+                "{verb} a cycle of rewrites: {} -> {}",
+                cycle.iter().join(" -> "),
+                cycle[0],
+            )?;
+        }
+        if *num_events_with_missing_commits > 0 {
+            writeln!(
+                effects.get_error_stream(),
+                "Warning: {} to commits which no longer exist, so they can't be undone.",
+                Pluralize {
+                    determiner: None,
+                    amount: *num_events_with_missing_commits,
+                    unit: ("event refers", "events refer"),
+                },
+            )?;
+        }
+        Ok(())
+    }
+}
+
+/// Check the event log for inconsistencies with the repository. This is shared
+/// by `repair`, which resolves them, and `verify`, which only reports them.
+pub fn find_inconsistencies(
+    effects: &Effects,
+    repo: &Repo,
+    event_log_db: &EventLogDb,
+    event_replayer: &EventReplayer,
+    event_cursor: EventCursor,
+) -> eyre::Result<Inconsistencies> {
+    let num_events_with_missing_commits = {
+        let (effects, progress) = effects.start_operation(OperationType::RepairEvents);
+        let _effects = effects;
+        let events = event_log_db.get_events()?;
+        progress.notify_progress(0, events.len());
+        let mut existing_commits: HashMap<NonZeroOid, bool> = HashMap::new();
+        let mut result = 0;
+        for event in events.iter() {
+            let mut is_missing_commit = false;
+            for oid in get_event_commit_oids(event) {
+                let exists = match existing_commits.get(&oid) {
+                    Some(exists) => *exists,
+                    None => {
+                        let exists = repo.find_commit(oid)?.is_some();
+                        existing_commits.insert(oid, exists);
+                        exists
+                    }
+                };
+                if !exists {
+                    is_missing_commit = true;
+                }
+            }
+            if is_missing_commit {
+                result += 1;
+            }
+            progress.notify_progress_inc(1);
+        }
+        result
+    };
 
     let broken_commits = {
         let (effects, progress) = effects.start_operation(OperationType::RepairCommits);
         let _effects = effects;
         let cursor_oids = event_replayer.get_cursor_oids(event_cursor);
         progress.notify_progress(0, cursor_oids.len());
-        let mut result = HashSet::new();
+        let mut result = Vec::new();
         for oid in cursor_oids {
             if repo.find_commit(oid)?.is_none() {
-                result.insert(oid);
+                result.push(oid);
             }
             progress.notify_progress_inc(1);
         }
+        result.sort();
         result
     };
 
     let broken_branches = {
         let (effects, progress) = effects.start_operation(OperationType::RepairBranches);
         let _effects = effects;
-        let references_snapshot = event_replayer.get_references_snapshot(&repo, event_cursor)?;
+        let references_snapshot = event_replayer.get_references_snapshot(repo, event_cursor)?;
         let branch_names = references_snapshot
             .branch_oid_to_names
             .into_iter()
@@ -56,71 +242,104 @@ pub fn repair(effects: &Effects, dry_run: bool) -> EyreExitOr<()> {
             }
             progress.notify_progress_inc(1);
         }
-        result
+        result.into_iter().collect_vec()
     };
 
-    let now = SystemTime::now();
-    let timestamp = now.duration_since(SystemTime::UNIX_EPOCH)?.as_secs_f64();
-    let event_tx_id = event_log_db.make_transaction_id(now, "repair")?;
-
-    let num_broken_commits = broken_commits.len();
-    let commit_events = broken_commits
-        .iter()
-        .map(|commit_oid| Event::ObsoleteEvent {
-            timestamp,
-            event_tx_id,
-            commit_oid: *commit_oid,
-        })
-        .collect_vec();
-    let num_broken_branches = broken_branches.len();
-    let branch_events =
-        broken_branches
+    let mut self_rewritten_commits = Vec::new();
+    let mut successors = HashMap::new();
+    for oid in event_replayer
+        .get_cursor_oids(event_cursor)
+        .into_iter()
+        .sorted()
+    {
+        if let Some(Event::RewriteEvent {
+            timestamp: _,
+            event_tx_id: _,
+            old_commit_oid: MaybeZeroOid::NonZero(old_commit_oid),
+            new_commit_oid: MaybeZeroOid::NonZero(new_commit_oid),
+        }) = event_replayer.get_cursor_commit_latest_event(event_cursor, oid)
+        {
+            if *old_commit_oid != oid {
+                continue;
+            }
+            if *new_commit_oid == oid {
+                self_rewritten_commits.push(oid);
+            } else {
+                successors.insert(oid, *new_commit_oid);
+            }
+        }
+    }
+    let rewrite_cycles = find_rewrite_cycles(&successors);
+
+    Ok(Inconsistencies {
+        broken_commits,
+        broken_branches,
+        self_rewritten_commits,
+        rewrite_cycles,
+        num_events_with_missing_commits,
+    })
+}
+
+pub fn repair(effects: &Effects, dry_run: bool) -> EyreExitOr<()> {
+    let repo = Repo::from_current_dir()?;
+    let conn = repo.get_db_conn()?;
+    let event_log_db = EventLogDb::new(&conn)?;
+    let event_replayer = EventReplayer::from_event_log_db(effects, &repo, &event_log_db)?;
+    let event_cursor = event_replayer.make_default_cursor();
+
+    let inconsistencies =
+        find_inconsistencies(effects, &repo, &event_log_db, &event_replayer, event_cursor)?;
+
+    if !dry_run {
+        let Inconsistencies {
+            broken_commits,
+            broken_branches,
+            self_rewritten_commits,
+            rewrite_cycles,
+            num_events_with_missing_commits: _,
+        } = &inconsistencies;
+
+        let now = SystemTime::now();
+        let timestamp = now.duration_since(SystemTime::UNIX_EPOCH)?.as_secs_f64();
+        let event_tx_id = event_log_db.make_transaction_id(now, "repair")?;
+
+        let commit_events = broken_commits
             .iter()
-            .map(|(old_oid, reference_name)| Event::RefUpdateEvent {
+            .map(|commit_oid| Event::ObsoleteEvent {
                 timestamp,
                 event_tx_id,
-                ref_name: reference_name.to_owned(),
-                old_oid: MaybeZeroOid::NonZero(*old_oid),
-                new_oid: MaybeZeroOid::Zero,
-                message: None,
+                commit_oid: *commit_oid,
+            });
+        let branch_events =
+            broken_branches
+                .iter()
+                .map(|(old_oid, reference_name)| Event::RefUpdateEvent {
+                    timestamp,
+                    event_tx_id,
+                    ref_name: reference_name.to_owned(),
+                    old_oid: MaybeZeroOid::NonZero(*old_oid),
+                    new_oid: MaybeZeroOid::Zero,
+                    message: None,
+                });
+        // Un-obsoleting one commit of each cycle makes it the newest version of
+        // the other commits in the cycle.
+        let unobsolete_events = self_rewritten_commits
+            .iter()
+            .chain(rewrite_cycles.iter().map(|cycle| &cycle[0]))
+            .map(|commit_oid| Event::UnobsoleteEvent {
+                timestamp,
+                event_tx_id,
+                commit_oid: *commit_oid,
             });
 
-    if !dry_run {
-        let events = commit_events.into_iter().chain(branch_events).collect_vec();
+        let events = commit_events
+            .chain(branch_events)
+            .chain(unobsolete_events)
+            .collect_vec();
         event_log_db.add_events(events)?;
     }
 
-    if num_broken_commits > 0 {
-        writeln!(
-            effects.get_output_stream(),
-            "Found and repaired {}: {}",
-            Pluralize {
-                determiner: None,
-                amount: num_broken_commits,
-                unit: ("broken commit", "broken commits")
-            },
-            broken_commits.into_iter().sorted().join(", "),
-        )?;
-    }
-    if num_broken_branches > 0 {
-        writeln!(
-            effects.get_output_stream(),
-            "Found and repaired {}: {}",
-            Pluralize {
-                determiner: None,
-                amount: num_broken_branches,
-                unit: ("broken branch", "broken branches")
-            },
-            broken_branches
-                .into_iter()
-                .map(
-                    |(_oid, reference_name)| CategorizedReferenceName::new(&reference_name)
-                        .render_suffix()
-                )
-                .sorted()
-                .join(", "),
-        )?;
-    }
+    inconsistencies.describe(effects, "Found and repaired")?;
 
     if dry_run {
         writeln!(
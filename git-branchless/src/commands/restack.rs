@@ -57,6 +57,7 @@
 
 use std::collections::{HashMap, HashSet};
 use std::fmt::Write;
+use std::io::BufRead;
 use std::time::SystemTime;
 
 use lib::core::check_out::CheckOutCommitOptions;
@@ -72,15 +73,19 @@ use git_branchless_smartlog::smartlog;
 use lib::core::config::get_restack_preserve_timestamps;
 use lib::core::dag::{union_all, CommitSet, Dag};
 use lib::core::effects::Effects;
-use lib::core::eventlog::{EventCursor, EventLogDb, EventReplayer};
+use lib::core::eventlog::{
+    CommitActivityStatus, Event, EventCursor, EventLogDb, EventReplayer, EventTransactionId,
+};
+use lib::core::notes::mirror_rewrites_to_notes;
 use lib::core::rewrite::{
-    execute_rebase_plan, find_abandoned_children, find_rewrite_target, move_branches,
-    BuildRebasePlanOptions, ExecuteRebasePlanOptions, ExecuteRebasePlanResult,
-    MergeConflictRemediation, RebasePlanBuilder, RebasePlanPermissions, RepoPool, RepoResource,
+    execute_rebase_plan_with_input, find_abandoned_children, find_autosquash_commits,
+    find_rewrite_target, find_upstream_applied_commits, move_branches, BuildRebasePlanOptions,
+    ExecuteRebasePlanOptions, ExecuteRebasePlanResult, MergeConflictRemediation, RebasePlanBuilder,
+    RebasePlanPermissions, RepoPool, RepoResource,
 };
-use lib::git::{GitRunInfo, NonZeroOid, Repo};
+use lib::git::{GitRunInfo, MaybeZeroOid, NonZeroOid, Repo};
 
-#[instrument(skip(commits))]
+#[instrument(skip(commits, input))]
 fn restack_commits(
     effects: &Effects,
     thread_pool: &ThreadPool,
@@ -91,11 +96,14 @@ fn restack_commits(
     event_cursor: EventCursor,
     git_run_info: &GitRunInfo,
     commits: Option<impl IntoIterator<Item = NonZeroOid>>,
+    autosquash: bool,
     build_options: BuildRebasePlanOptions,
     execute_options: &ExecuteRebasePlanOptions,
     merge_conflict_remediation: MergeConflictRemediation,
+    input: Option<&mut dyn BufRead>,
 ) -> EyreExitOr<()> {
     let repo = repo_pool.try_create()?;
+    let is_restacking_all = commits.is_none();
     let commit_set: CommitSet = match commits {
         Some(commits) => commits.into_iter().collect(),
         None => dag.query_obsolete_commits(),
@@ -114,8 +122,23 @@ fn restack_commits(
             let abandoned_children =
                 find_abandoned_children(dag, event_replayer, event_cursor, original_commit_oid)?;
             if let Some((rewritten_oid, abandoned_child_oids)) = abandoned_children {
+                // If the commit was discarded rather than rewritten, then its
+                // children belong on its nearest surviving ancestor instead.
+                let dest_oid = if rewritten_oid == original_commit_oid {
+                    match find_surviving_ancestor(
+                        &repo,
+                        event_replayer,
+                        event_cursor,
+                        original_commit_oid,
+                    )? {
+                        Some(ancestor_oid) => ancestor_oid,
+                        None => continue,
+                    }
+                } else {
+                    rewritten_oid
+                };
                 result.push(RebaseInfo {
-                    dest_oid: rewritten_oid,
+                    dest_oid,
                     abandoned_child_oids,
                 });
             }
@@ -123,6 +146,24 @@ fn restack_commits(
         result
     };
 
+    let autosquash_commits = if autosquash {
+        // Fold the `fixup!` commits anywhere in the stacks of the given
+        // commits, not only in the abandoned parts of them.
+        let draft_commits = dag.query_draft_commits()?;
+        let stack_commits = if is_restacking_all {
+            draft_commits.clone()
+        } else {
+            dag.query_descendants(dag.query_ancestors(commit_set)?.intersection(draft_commits))?
+        };
+        let stack_commits = stack_commits
+            .intersection(draft_commits)
+            .difference(&dag.query_obsolete_commits());
+        let stack_commits = dag.filter_visible_commits(stack_commits)?;
+        find_autosquash_commits(&repo, dag, &stack_commits)?
+    } else {
+        Default::default()
+    };
+
     let rebase_plan = {
         let permissions = match RebasePlanPermissions::verify_rewrite_set(
             dag,
@@ -135,6 +176,11 @@ fn restack_commits(
                          abandoned_child_oids,
                      }| abandoned_child_oids.iter().copied(),
                 )
+                .chain(
+                    autosquash_commits
+                        .iter()
+                        .flat_map(|(fixup_oid, target_oid)| [*fixup_oid, *target_oid]),
+                )
                 .collect(),
         )? {
             Ok(permissions) => permissions,
@@ -153,6 +199,9 @@ fn restack_commits(
                 builder.move_subtree(child_oid, vec![dest_oid])?;
             }
         }
+        for (fixup_oid, target_oid) in autosquash_commits {
+            builder.fixup_commit(fixup_oid, target_oid)?;
+        }
         match builder.build(effects, thread_pool, repo_pool)? {
             Ok(Some(rebase_plan)) => rebase_plan,
             Ok(None) => {
@@ -169,13 +218,14 @@ fn restack_commits(
         }
     };
 
-    let execute_rebase_plan_result = execute_rebase_plan(
+    let execute_rebase_plan_result = execute_rebase_plan_with_input(
         effects,
         git_run_info,
         &repo,
         event_log_db,
         &rebase_plan,
         execute_options,
+        input,
     )?;
     match execute_rebase_plan_result {
         ExecuteRebasePlanResult::Succeeded { rewritten_oids: _ } => {
@@ -206,6 +256,103 @@ fn restack_commits(
     }
 }
 
+/// Find the nearest ancestor of the given discarded commit which wasn't itself
+/// discarded or hidden. If that ancestor was rewritten, then its newest version
+/// is returned instead.
+#[instrument]
+fn find_surviving_ancestor(
+    repo: &Repo,
+    event_replayer: &EventReplayer,
+    event_cursor: EventCursor,
+    commit_oid: NonZeroOid,
+) -> eyre::Result<Option<NonZeroOid>> {
+    let mut current_oid = commit_oid;
+    loop {
+        let commit = repo.find_commit_or_fail(current_oid)?;
+        let parent_oid = match commit.get_parent_oids().first() {
+            Some(parent_oid) => *parent_oid,
+            None => return Ok(None),
+        };
+        match find_rewrite_target(event_replayer, event_cursor, parent_oid) {
+            Some(MaybeZeroOid::NonZero(rewritten_oid)) => return Ok(Some(rewritten_oid)),
+            Some(MaybeZeroOid::Zero) => {}
+            None => {
+                match event_replayer.get_cursor_commit_activity_status(event_cursor, parent_oid) {
+                    CommitActivityStatus::Active | CommitActivityStatus::Inactive => {
+                        return Ok(Some(parent_oid))
+                    }
+                    CommitActivityStatus::Obsolete => {}
+                }
+            }
+        }
+        current_oid = parent_oid;
+    }
+}
+
+/// Discard the draft commits which have already been applied to the main
+/// branch, such as after fetching the main branch once they've landed. They're
+/// recorded as rewritten into their upstream equivalents, so that their
+/// descendants and branches are then restacked on top of the landed changes
+/// like those of any other rewritten commit.
+///
+/// Returns the discarded commits.
+#[instrument(skip(commits))]
+fn discard_upstream_applied_commits(
+    effects: &Effects,
+    repo: &Repo,
+    dag: &Dag,
+    event_log_db: &EventLogDb,
+    event_tx_id: EventTransactionId,
+    now: SystemTime,
+    commits: Option<&HashSet<NonZeroOid>>,
+) -> eyre::Result<Vec<NonZeroOid>> {
+    // Commits which were already rewritten are handled by the usual restack.
+    let draft_commits = dag
+        .query_draft_commits()?
+        .difference(&dag.query_obsolete_commits());
+    let draft_commits = match commits {
+        Some(commits) => {
+            draft_commits.intersection(&dag.query_descendants(commits.iter().copied().collect())?)
+        }
+        None => draft_commits,
+    };
+    let upstream_applied_commits =
+        find_upstream_applied_commits(effects, repo, dag, draft_commits)?;
+
+    let timestamp = now.duration_since(SystemTime::UNIX_EPOCH)?.as_secs_f64();
+    let mut events = Vec::new();
+    for (commit_oid, upstream_oid) in upstream_applied_commits.iter() {
+        writeln!(
+            effects.get_output_stream(),
+            "Discarded commit (was already applied upstream as {}): {}",
+            effects.get_glyphs().render(
+                repo.friendly_describe_commit_from_oid(effects.get_glyphs(), *upstream_oid)?
+            )?,
+            effects.get_glyphs().render(
+                repo.friendly_describe_commit_from_oid(effects.get_glyphs(), *commit_oid)?
+            )?,
+        )?;
+        events.push(Event::RewriteEvent {
+            timestamp,
+            event_tx_id,
+            old_commit_oid: MaybeZeroOid::NonZero(*commit_oid),
+            new_commit_oid: MaybeZeroOid::NonZero(*upstream_oid),
+        });
+    }
+    event_log_db.add_events(events)?;
+    mirror_rewrites_to_notes(
+        repo,
+        upstream_applied_commits
+            .iter()
+            .map(|(commit_oid, upstream_oid)| (*commit_oid, MaybeZeroOid::NonZero(*upstream_oid))),
+    )?;
+
+    Ok(upstream_applied_commits
+        .into_iter()
+        .map(|(commit_oid, _upstream_oid)| commit_oid)
+        .collect())
+}
+
 #[instrument]
 fn restack_branches(
     effects: &Effects,
@@ -216,8 +363,11 @@ fn restack_branches(
     options: &ExecuteRebasePlanOptions,
 ) -> EyreExitOr<()> {
     let event_replayer = EventReplayer::from_event_log_db(effects, repo, event_log_db)?;
+    let event_cursor = event_replayer.make_default_cursor();
+    let main_branch_name = repo.get_main_branch_reference_name()?;
 
     let mut rewritten_oids = HashMap::new();
+    let mut discarded_branches = Vec::new();
     for branch in repo.get_all_local_branches()? {
         let branch_target = match branch.get_oid()? {
             Some(branch_target) => branch_target,
@@ -230,13 +380,27 @@ fn restack_branches(
             }
         };
 
-        if let Some(new_oid) = find_rewrite_target(
-            &event_replayer,
-            event_replayer.make_default_cursor(),
-            branch_target,
-        ) {
-            rewritten_oids.insert(branch_target, new_oid);
-        };
+        match find_rewrite_target(&event_replayer, event_cursor, branch_target) {
+            // The main branch is instead reset to its upstream branch by
+            // `move_branches`.
+            Some(MaybeZeroOid::Zero) if branch.get_reference_name()? != main_branch_name => {
+                let ancestor_oid =
+                    find_surviving_ancestor(repo, &event_replayer, event_cursor, branch_target)?;
+                match ancestor_oid {
+                    Some(ancestor_oid) => {
+                        rewritten_oids.insert(branch_target, MaybeZeroOid::NonZero(ancestor_oid));
+                        discarded_branches.push((branch.get_name()?.to_owned(), ancestor_oid));
+                    }
+                    None => {
+                        rewritten_oids.insert(branch_target, MaybeZeroOid::Zero);
+                    }
+                }
+            }
+            Some(new_oid) => {
+                rewritten_oids.insert(branch_target, new_oid);
+            }
+            None => {}
+        }
     }
 
     if rewritten_oids.is_empty() {
@@ -252,6 +416,16 @@ fn restack_branches(
             options.event_tx_id,
             &rewritten_oids,
         )?;
+        discarded_branches.sort();
+        for (branch_name, ancestor_oid) in discarded_branches {
+            writeln!(
+                effects.get_output_stream(),
+                "Moved branch {branch_name} to {}, since its commit was discarded.",
+                effects.get_glyphs().render(
+                    repo.friendly_describe_commit_from_oid(effects.get_glyphs(), ancestor_oid)?
+                )?,
+            )?;
+        }
         writeln!(effects.get_output_stream(), "Finished restacking branches.")?;
     }
     Ok(Ok(()))
@@ -259,15 +433,20 @@ fn restack_branches(
 
 /// Restack all abandoned commits.
 ///
+/// If `input` is provided, then the user may be asked how to proceed if the
+/// commits can't be restacked in-memory.
+///
 /// Returns an exit code (0 denotes successful exit).
-#[instrument]
+#[instrument(skip(input))]
 pub fn restack(
     effects: &Effects,
     git_run_info: &GitRunInfo,
     revsets: Vec<Revset>,
     resolve_revset_options: &ResolveRevsetOptions,
     move_options: &MoveOptions,
+    autosquash: bool,
     merge_conflict_remediation: MergeConflictRemediation,
+    input: Option<&mut dyn BufRead>,
 ) -> EyreExitOr<()> {
     let now = SystemTime::now();
     let repo = Repo::from_current_dir()?;
@@ -335,6 +514,40 @@ pub fn restack(
     let pool = ThreadPoolBuilder::new().build()?;
     let repo_pool = RepoResource::new_pool(&repo)?;
 
+    let discarded_commits = if detect_duplicate_commits_via_patch_id {
+        discard_upstream_applied_commits(
+            effects,
+            &repo,
+            &dag,
+            &event_log_db,
+            event_tx_id,
+            now,
+            commits.as_ref(),
+        )?
+    } else {
+        Default::default()
+    };
+    let (event_replayer, event_cursor, dag, commits) = if discarded_commits.is_empty() {
+        (event_replayer, event_cursor, dag, commits)
+    } else {
+        // Reload the event log and commit graph so that the discarded commits
+        // are restacked like any other obsolete commits.
+        let commits = commits.map(|mut commits| {
+            commits.extend(discarded_commits);
+            commits
+        });
+        let event_replayer = EventReplayer::from_event_log_db(effects, &repo, &event_log_db)?;
+        let event_cursor = event_replayer.make_default_cursor();
+        let dag = Dag::open_and_sync(
+            effects,
+            &repo,
+            &event_replayer,
+            event_cursor,
+            &references_snapshot,
+        )?;
+        (event_replayer, event_cursor, dag, commits)
+    };
+
     try_exit_code!(restack_commits(
         effects,
         &pool,
@@ -345,9 +558,11 @@ pub fn restack(
         event_cursor,
         git_run_info,
         commits,
+        autosquash,
         build_options,
         &execute_options,
         merge_conflict_remediation,
+        input,
     )?);
 
     try_exit_code!(restack_branches(
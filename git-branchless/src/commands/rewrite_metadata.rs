@@ -0,0 +1,166 @@
+//! Rewrite the author and committer metadata of many commits at once, such as
+//! after an email address has changed.
+//!
+//! The commits are rewritten in-memory as part of a single rebase, so the whole
+//! operation can be reverted with one `git undo`.
+
+use std::collections::HashMap;
+use std::fmt::Write;
+use std::time::SystemTime;
+
+use git_branchless_opts::{ResolveRevsetOptions, Revset};
+use git_branchless_revset::resolve_commits;
+use git_branchless_reword::replace_commits;
+use lib::core::dag::{sorted_commit_set, union_all, CommitSet, Dag};
+use lib::core::effects::Effects;
+use lib::core::eventlog::{EventLogDb, EventReplayer};
+use lib::core::formatting::Pluralize;
+use lib::core::repo_ext::RepoExt;
+use lib::core::rewrite::{BuildRebasePlanOptions, RebasePlanPermissions};
+use lib::git::{GitRunInfo, Repo};
+use lib::try_exit_code;
+use lib::util::{ExitCode, EyreExitOr};
+use tracing::instrument;
+
+/// Parse author mappings of the form `old@example.com:new@example.com` into a
+/// map from the lowercased old email to the new email.
+fn parse_author_mappings(mappings: &[String]) -> Result<HashMap<String, String>, String> {
+    let mut result = HashMap::new();
+    for mapping in mappings {
+        match mapping.split_once(':') {
+            Some((old_email, new_email))
+                if !old_email.trim().is_empty() && !new_email.trim().is_empty() =>
+            {
+                result.insert(
+                    old_email.trim().to_lowercase(),
+                    new_email.trim().to_string(),
+                );
+            }
+            _ => return Err(mapping.clone()),
+        }
+    }
+    Ok(result)
+}
+
+/// Get the new email for `email`, if it's mapped to a different one. Emails
+/// which aren't valid UTF-8 can't match any mapping.
+fn map_email(mappings: &HashMap<String, String>, email: &[u8]) -> Option<Vec<u8>> {
+    let email = std::str::from_utf8(email).ok()?;
+    match mappings.get(&email.to_lowercase()) {
+        Some(new_email) if new_email != email => Some(new_email.as_bytes().to_vec()),
+        Some(_) | None => None,
+    }
+}
+
+/// Replace the author and committer emails of the commits in `revsets`
+/// according to `author_mappings`, and restack their descendants.
+#[instrument]
+pub fn rewrite_metadata(
+    effects: &Effects,
+    git_run_info: &GitRunInfo,
+    revsets: Vec<Revset>,
+    resolve_revset_options: &ResolveRevsetOptions,
+    author_mappings: &[String],
+    force_rewrite_public_commits: bool,
+) -> EyreExitOr<()> {
+    let mappings = match parse_author_mappings(author_mappings) {
+        Ok(mappings) => mappings,
+        Err(mapping) => {
+            writeln!(
+                effects.get_error_stream(),
+                "Invalid author mapping {mapping:?}: expected the form 'old@example.com:new@example.com'."
+            )?;
+            return Ok(Err(ExitCode(1)));
+        }
+    };
+
+    let repo = Repo::from_current_dir()?;
+    let references_snapshot = repo.get_references_snapshot()?;
+    let conn = repo.get_db_conn()?;
+    let event_log_db = EventLogDb::new(&conn)?;
+    let event_replayer = EventReplayer::from_event_log_db(effects, &repo, &event_log_db)?;
+    let event_cursor = event_replayer.make_default_cursor();
+    let mut dag = Dag::open_and_sync(
+        effects,
+        &repo,
+        &event_replayer,
+        event_cursor,
+        &references_snapshot,
+    )?;
+
+    let commit_sets =
+        match resolve_commits(effects, &repo, &mut dag, &revsets, resolve_revset_options) {
+            Ok(commit_sets) => commit_sets,
+            Err(err) => {
+                err.describe(effects)?;
+                return Ok(Err(ExitCode(1)));
+            }
+        };
+    let commits = sorted_commit_set(&repo, &dag, &union_all(&commit_sets))?;
+
+    let mut replacements = Vec::new();
+    for commit in commits.iter() {
+        // Rewrite the raw commit object so that the rest of each signature is
+        // kept byte-for-byte, even if it's not valid UTF-8.
+        if let Some(replacement_oid) =
+            repo.rewrite_signature_emails(commit, |email| map_email(&mappings, email))?
+        {
+            replacements.push((commit.get_oid(), commit.get_parent_oids(), replacement_oid));
+        }
+    }
+
+    if replacements.is_empty() {
+        writeln!(
+            effects.get_output_stream(),
+            "No commits matched the author mappings; nothing to do."
+        )?;
+        return Ok(Ok(()));
+    }
+
+    let build_options = BuildRebasePlanOptions {
+        force_rewrite_public_commits,
+        dump_rebase_constraints: false,
+        dump_rebase_plan: false,
+        detect_duplicate_commits_via_patch_id: false,
+    };
+    let commits_to_rewrite: CommitSet = replacements
+        .iter()
+        .map(|(commit_oid, _parent_oids, _replacement_oid)| *commit_oid)
+        .collect();
+    let permissions = match RebasePlanPermissions::verify_rewrite_set(
+        &dag,
+        build_options,
+        &commits_to_rewrite,
+    )? {
+        Ok(permissions) => permissions,
+        Err(err) => {
+            err.describe(effects, &repo, &dag)?;
+            return Ok(Err(ExitCode(1)));
+        }
+    };
+
+    let now = SystemTime::now();
+    let event_tx_id = event_log_db.make_transaction_id(now, "rewrite-metadata")?;
+    try_exit_code!(replace_commits(
+        effects,
+        git_run_info,
+        &repo,
+        &dag,
+        &event_log_db,
+        permissions,
+        now,
+        event_tx_id,
+        &replacements,
+    )?);
+
+    writeln!(
+        effects.get_output_stream(),
+        "Rewrote the metadata of {}. If this was unintentional, run: git undo",
+        Pluralize {
+            determiner: None,
+            amount: replacements.len(),
+            unit: ("commit", "commits"),
+        }
+    )?;
+    Ok(Ok(()))
+}
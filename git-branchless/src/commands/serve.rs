@@ -0,0 +1,825 @@
+//! Serve the smartlog graph and the event log over a local HTTP server, so
+//! that GUI and editor clients can query and update the repository without
+//! starting a new process for each request.
+//!
+//! Requests are JSON-RPC 2.0 calls sent as `POST /rpc`. The supported methods
+//! are:
+//!
+//! - `smartlog`: get the commit graph which `git smartlog` would render. Takes
+//!   an optional `revset`.
+//! - `events`: get the events after the event ID `after`. If `timeout` (in
+//!   milliseconds) is provided and there are no such events yet, waits up to
+//!   that long (but no longer than 30 seconds) for one to be added.
+//! - `hide`: hide the commits in `revsets`, as `git hide` does. Takes an
+//!   optional `recursive` flag.
+//! - `checkout`: check out `target`, as `git branchless switch` does.
+//! - `shutdown`: stop the server.
+//!
+//! Requests are handled concurrently, except that `hide` and `checkout` run one
+//! at a time, since they modify the working copy and the event log.
+//!
+//! New events are also streamed as server-sent events from `GET /events`,
+//! starting after the event ID given by the `after` query parameter or the
+//! `Last-Event-ID` header, if any.
+//!
+//! Event IDs are the row IDs of the events in the event log database, so they
+//! stay the same as other events are added or removed.
+//!
+//! The server only listens on the loopback interface. Since other users of the
+//! machine can connect to it too, every request must include the token printed
+//! when the server starts as an `Authorization: Bearer <token>` header. To keep
+//! web pages from making requests to it, requests with an `Origin` header or a
+//! `Host` header other than the server's own address are also rejected.
+
+use std::fmt::Write as _;
+use std::io::{BufRead, BufReader, Read, Write};
+use std::net::{Ipv4Addr, SocketAddr, TcpListener, TcpStream};
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use std::sync::{Mutex, MutexGuard, PoisonError};
+use std::thread;
+use std::time::{Duration, Instant};
+
+use eyre::Context;
+use git_branchless_opts::{ResolveRevsetOptions, Revset, SwitchOptions};
+use git_branchless_revset::resolve_commits;
+use git_branchless_smartlog::make_smartlog_graph;
+use itertools::Itertools;
+use lib::core::config::get_smartlog_default_revset;
+use lib::core::dag::Dag;
+use lib::core::effects::Effects;
+use lib::core::eventlog::{Event, EventLogDb, EventReplayer};
+use lib::core::node_descriptors::NodeObject;
+use lib::core::repo_ext::RepoExt;
+use lib::git::{GitRunInfo, Repo};
+use lib::util::EyreExitOr;
+use serde::de::DeserializeOwned;
+use serde::Deserialize;
+use serde_json::{json, Value};
+use tracing::{instrument, warn};
+
+use super::hide::hide;
+
+/// How often to check the event log for new events.
+const POLL_INTERVAL: Duration = Duration::from_millis(250);
+
+/// How often to send a comment on an idle event stream, so that disconnected
+/// clients are noticed.
+const KEEP_ALIVE_INTERVAL: Duration = Duration::from_secs(15);
+
+/// The largest request body which will be accepted.
+const MAX_BODY_SIZE: usize = 1024 * 1024;
+
+/// The largest request line and headers which will be accepted, in total.
+const MAX_HEADER_SIZE: u64 = 64 * 1024;
+
+/// How long to wait for a client to send its request, or to accept data which
+/// is sent to it, before closing the connection.
+const IO_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// The longest that an `events` request will wait for new events, so that
+/// clients can't hold onto a connection indefinitely.
+const MAX_EVENTS_TIMEOUT: Duration = Duration::from_secs(30);
+
+/// The largest number of connections which are handled at once. Further
+/// connections are turned away until one of them is closed.
+const MAX_CONNECTIONS: usize = 16;
+
+// Error codes defined by the JSON-RPC 2.0 specification.
+const PARSE_ERROR: i64 = -32700;
+const METHOD_NOT_FOUND: i64 = -32601;
+const INVALID_PARAMS: i64 = -32602;
+const INTERNAL_ERROR: i64 = -32603;
+
+/// Error code for a command which failed, such as a `hide` of a commit which
+/// doesn't exist.
+const COMMAND_FAILED: i64 = -32000;
+
+/// State shared between the threads handling connections.
+struct Server<'a> {
+    effects: &'a Effects,
+    git_run_info: &'a GitRunInfo,
+    addr: SocketAddr,
+    allowed_hosts: Vec<String>,
+    token: String,
+    num_connections: AtomicUsize,
+    is_shutting_down: AtomicBool,
+
+    /// Held while handling a request which modifies the repository, since
+    /// connections are handled concurrently but they all share the same
+    /// working copy and event log.
+    mutation_lock: Mutex<()>,
+}
+
+/// Counts a connection as being handled until it's dropped, so that the count
+/// is decremented even if handling the connection panics.
+struct ConnectionGuard<'a> {
+    num_connections: &'a AtomicUsize,
+}
+
+impl Drop for ConnectionGuard<'_> {
+    fn drop(&mut self) {
+        self.num_connections.fetch_sub(1, Ordering::SeqCst);
+    }
+}
+
+/// Generate a random token for clients to authenticate with, using the
+/// operating system's random number generator.
+fn make_token() -> eyre::Result<String> {
+    let mut bytes = [0; 16];
+    getrandom::getrandom(&mut bytes)
+        .map_err(|err| eyre::eyre!("Generating authorization token: {err}"))?;
+    Ok(bytes.iter().map(|byte| format!("{byte:02x}")).collect())
+}
+
+/// Compare two byte strings in an amount of time which depends only on their
+/// lengths, so that the token can't be guessed by timing failed attempts.
+fn constant_time_eq(lhs: &[u8], rhs: &[u8]) -> bool {
+    lhs.len() == rhs.len()
+        && lhs
+            .iter()
+            .zip(rhs)
+            .fold(0, |acc, (lhs, rhs)| acc | (lhs ^ rhs))
+            == 0
+}
+
+struct Request {
+    method: String,
+    path: String,
+    query: Vec<(String, String)>,
+    headers: Vec<(String, String)>,
+    body: Vec<u8>,
+}
+
+impl Request {
+    fn get_header(&self, name: &str) -> Option<&str> {
+        self.headers
+            .iter()
+            .find(|(header_name, _)| header_name.eq_ignore_ascii_case(name))
+            .map(|(_, value)| value.as_str())
+    }
+
+    fn get_query_param(&self, name: &str) -> Option<&str> {
+        self.query
+            .iter()
+            .find(|(param_name, _)| param_name == name)
+            .map(|(_, value)| value.as_str())
+    }
+}
+
+/// Read a line of the request line or headers, failing if it's cut off by the
+/// limit on their size.
+fn read_header_line(reader: &mut impl BufRead, line: &mut String) -> eyre::Result<usize> {
+    let num_bytes = reader.read_line(line)?;
+    if num_bytes > 0 && !line.ends_with('\n') {
+        eyre::bail!("Request headers too large");
+    }
+    Ok(num_bytes)
+}
+
+/// Read a single HTTP request. Returns `None` if the connection was closed
+/// before a request was sent.
+fn read_request(reader: &mut impl BufRead) -> eyre::Result<Option<Request>> {
+    let mut header_reader = reader.by_ref().take(MAX_HEADER_SIZE);
+    let mut request_line = String::new();
+    if read_header_line(&mut header_reader, &mut request_line)? == 0 {
+        return Ok(None);
+    }
+    let (method, target) = match request_line.split_whitespace().collect_vec().as_slice() {
+        [method, target, _version] => (method.to_string(), target.to_string()),
+        _ => eyre::bail!("Malformed request line: {request_line:?}"),
+    };
+    let (path, query) = match target.split_once('?') {
+        Some((path, query)) => (path.to_owned(), query),
+        None => (target.clone(), ""),
+    };
+    let query = query
+        .split('&')
+        .filter(|param| !param.is_empty())
+        .map(|param| match param.split_once('=') {
+            Some((name, value)) => (name.to_owned(), value.to_owned()),
+            None => (param.to_owned(), String::new()),
+        })
+        .collect();
+
+    let mut headers = Vec::new();
+    loop {
+        let mut line = String::new();
+        if read_header_line(&mut header_reader, &mut line)? == 0 {
+            eyre::bail!("Connection closed while reading request headers");
+        }
+        let line = line.trim_end();
+        if line.is_empty() {
+            break;
+        }
+        if let Some((name, value)) = line.split_once(':') {
+            headers.push((name.trim().to_owned(), value.trim().to_owned()));
+        }
+    }
+
+    let content_length: usize = match headers
+        .iter()
+        .find(|(name, _)| name.eq_ignore_ascii_case("content-length"))
+    {
+        Some((_, value)) => value.parse().wrap_err("Parsing Content-Length")?,
+        None => 0,
+    };
+    if content_length > MAX_BODY_SIZE {
+        eyre::bail!("Request body too large: {content_length} bytes");
+    }
+    let mut body = vec![0; content_length];
+    reader.read_exact(&mut body)?;
+
+    Ok(Some(Request {
+        method,
+        path,
+        query,
+        headers,
+        body,
+    }))
+}
+
+fn write_response(
+    stream: &mut TcpStream,
+    status: &str,
+    content_type: &str,
+    body: &[u8],
+) -> eyre::Result<()> {
+    write!(
+        stream,
+        "HTTP/1.1 {status}\r\nContent-Type: {content_type}\r\nContent-Length: {}\r\nConnection: close\r\n\r\n",
+        body.len()
+    )?;
+    stream.write_all(body)?;
+    stream.flush()?;
+    Ok(())
+}
+
+/// Tell the client that the server is too busy to handle its connection.
+fn reject_connection(stream: &mut TcpStream) -> eyre::Result<()> {
+    stream.set_write_timeout(Some(IO_TIMEOUT))?;
+    write_response(
+        stream,
+        "503 Service Unavailable",
+        "text/plain",
+        b"Too many connections\n",
+    )
+}
+
+/// An error to be returned to the client in a JSON-RPC response.
+#[derive(Debug)]
+struct RpcError {
+    code: i64,
+    message: String,
+}
+
+type RpcResult = eyre::Result<Result<Value, RpcError>>;
+
+fn parse_params<T: DeserializeOwned>(params: Value) -> Result<T, RpcError> {
+    let params = match params {
+        Value::Null => json!({}),
+        params => params,
+    };
+    serde_json::from_value(params).map_err(|err| RpcError {
+        code: INVALID_PARAMS,
+        message: err.to_string(),
+    })
+}
+
+/// Convert the result of running a command into an RPC result. The command's
+/// own output is written to the server's output.
+fn command_result(result: EyreExitOr<()>) -> RpcResult {
+    match result? {
+        Ok(()) => Ok(Ok(Value::Null)),
+        Err(exit_code) => Ok(Err(RpcError {
+            code: COMMAND_FAILED,
+            message: format!(
+                "Command failed with exit code {}; see the server output for details",
+                exit_code.0
+            ),
+        })),
+    }
+}
+
+fn event_to_json(event_id: i64, event: &Event) -> Value {
+    let mut value = match event {
+        Event::RewriteEvent {
+            timestamp: _,
+            event_tx_id: _,
+            old_commit_oid,
+            new_commit_oid,
+        } => json!({
+            "type": "rewrite",
+            "old_commit_oid": old_commit_oid.to_string(),
+            "new_commit_oid": new_commit_oid.to_string(),
+        }),
+        Event::RefUpdateEvent {
+            timestamp: _,
+            event_tx_id: _,
+            ref_name,
+            old_oid,
+            new_oid,
+            message: _,
+        } => json!({
+            "type": "ref-update",
+            "ref_name": ref_name.as_str(),
+            "old_oid": old_oid.to_string(),
+            "new_oid": new_oid.to_string(),
+        }),
+        Event::CommitEvent {
+            timestamp: _,
+            event_tx_id: _,
+            commit_oid,
+        } => json!({
+            "type": "commit",
+            "commit_oid": commit_oid.to_string(),
+        }),
+        Event::ObsoleteEvent {
+            timestamp: _,
+            event_tx_id: _,
+            commit_oid,
+        } => json!({
+            "type": "obsolete",
+            "commit_oid": commit_oid.to_string(),
+        }),
+        Event::UnobsoleteEvent {
+            timestamp: _,
+            event_tx_id: _,
+            commit_oid,
+        } => json!({
+            "type": "unobsolete",
+            "commit_oid": commit_oid.to_string(),
+        }),
+        Event::WorkingCopySnapshot {
+            timestamp: _,
+            event_tx_id: _,
+            head_oid,
+            commit_oid,
+            ref_name,
+        } => json!({
+            "type": "snapshot",
+            "head_oid": head_oid.to_string(),
+            "commit_oid": commit_oid.to_string(),
+            "ref_name": ref_name.as_ref().map(|ref_name| ref_name.as_str()),
+        }),
+        Event::CopyEvent {
+            timestamp: _,
+            event_tx_id: _,
+            copy_type,
+            source_commit_oid,
+            new_commit_oid,
+        } => json!({
+            "type": "copy",
+            "copy_type": copy_type.as_str(),
+            "source_commit_oid": source_commit_oid.to_string(),
+            "new_commit_oid": new_commit_oid.to_string(),
+        }),
+    };
+    let timestamp = event
+        .get_timestamp()
+        .duration_since(std::time::UNIX_EPOCH)
+        .map(|duration| duration.as_secs_f64())
+        .unwrap_or_default();
+    value["id"] = json!(event_id);
+    value["timestamp"] = json!(timestamp);
+    value["event_tx_id"] = json!(event.get_event_tx_id().to_string());
+    value
+}
+
+/// Get the events with IDs greater than `after`, along with the ID of the most
+/// recent of them, or `after` if there are none.
+fn get_events_after(event_log_db: &EventLogDb, after: i64) -> eyre::Result<(Vec<Value>, i64)> {
+    let events = event_log_db.get_events_after(after)?;
+    let last_event_id = match events.last() {
+        Some((event_id, _event)) => *event_id,
+        None => after,
+    };
+    let events_json = events
+        .iter()
+        .map(|(event_id, event)| event_to_json(*event_id, event))
+        .collect();
+    Ok((events_json, last_event_id))
+}
+
+#[derive(Debug, Deserialize)]
+struct SmartlogParams {
+    #[serde(default)]
+    revset: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+struct EventsParams {
+    #[serde(default)]
+    after: i64,
+    #[serde(default)]
+    timeout: Option<u64>,
+}
+
+#[derive(Debug, Deserialize)]
+struct HideParams {
+    revsets: Vec<String>,
+    #[serde(default)]
+    recursive: bool,
+}
+
+#[derive(Debug, Deserialize)]
+struct CheckoutParams {
+    target: String,
+}
+
+impl Server<'_> {
+    /// Wait until no other request is modifying the repository. The lock
+    /// doesn't guard any data, so it's fine to take it even if an earlier
+    /// request panicked while holding it.
+    fn lock_mutations(&self) -> MutexGuard<'_, ()> {
+        self.mutation_lock
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+    }
+
+    #[instrument(skip(self))]
+    fn get_smartlog(&self, params: SmartlogParams) -> RpcResult {
+        let SmartlogParams { revset } = params;
+        let effects = self.effects.suppress();
+        let repo = Repo::from_current_dir()?;
+        let conn = repo.get_db_conn()?;
+        let event_log_db = EventLogDb::new(&conn)?;
+        let event_replayer = EventReplayer::from_event_log_db(&effects, &repo, &event_log_db)?;
+        let event_cursor = event_replayer.make_default_cursor();
+        let references_snapshot = repo.get_references_snapshot()?;
+        let mut dag = Dag::open_and_sync(
+            &effects,
+            &repo,
+            &event_replayer,
+            event_cursor,
+            &references_snapshot,
+        )?;
+
+        let revset = match revset {
+            Some(revset) => Revset(revset),
+            None => Revset(get_smartlog_default_revset(&repo)?),
+        };
+        let commits = match resolve_commits(
+            &effects,
+            &repo,
+            &mut dag,
+            &[revset],
+            &ResolveRevsetOptions::default(),
+        ) {
+            Ok(result) => match result.as_slice() {
+                [commit_set] => commit_set.clone(),
+                other => panic!("Expected exactly 1 result from resolve commits, got: {other:?}"),
+            },
+            Err(err) => {
+                return Ok(Err(RpcError {
+                    code: INVALID_PARAMS,
+                    message: err.to_string(),
+                }))
+            }
+        };
+        let graph = make_smartlog_graph(
+            &effects,
+            &repo,
+            &dag,
+            &event_replayer,
+            event_cursor,
+            &commits,
+        )?;
+
+        let mut nodes = Vec::new();
+        for (oid, node) in graph.nodes.iter().sorted_by_key(|(oid, _)| **oid) {
+            let summary = match &node.object {
+                NodeObject::Commit { commit } => {
+                    Some(String::from_utf8_lossy(&commit.get_summary()?).into_owned())
+                }
+                NodeObject::GarbageCollected { oid: _ } => None,
+            };
+            let branches = references_snapshot
+                .branch_oid_to_names
+                .get(oid)
+                .into_iter()
+                .flatten()
+                .map(|reference_name| reference_name.as_str().to_owned())
+                .sorted()
+                .collect_vec();
+            nodes.push(json!({
+                "oid": oid.to_string(),
+                "parents": node.parents.iter().map(|oid| oid.to_string()).collect_vec(),
+                "children": node.children.iter().map(|child| child.oid.to_string()).collect_vec(),
+                "summary": summary,
+                "branches": branches,
+                "is_head": references_snapshot.head_oid == Some(*oid),
+                "is_main": node.is_main,
+                "is_obsolete": node.is_obsolete,
+                "num_omitted_descendants": node.num_omitted_descendants,
+            }));
+        }
+        Ok(Ok(json!({
+            "head_oid": references_snapshot.head_oid.map(|oid| oid.to_string()),
+            "main_branch_oid": references_snapshot.main_branch_oid.to_string(),
+            "nodes": nodes,
+        })))
+    }
+
+    #[instrument(skip(self))]
+    fn get_events(&self, params: EventsParams) -> RpcResult {
+        let EventsParams { after, timeout } = params;
+        let repo = Repo::from_current_dir()?;
+        let conn = repo.get_db_conn()?;
+        let event_log_db = EventLogDb::new(&conn)?;
+
+        let timeout = Duration::from_millis(timeout.unwrap_or_default()).min(MAX_EVENTS_TIMEOUT);
+        let now = Instant::now();
+        let deadline = now.checked_add(timeout).unwrap_or(now);
+        let (events, last_event_id) = loop {
+            let (events, last_event_id) = get_events_after(&event_log_db, after)?;
+            if !events.is_empty()
+                || Instant::now() >= deadline
+                || self.is_shutting_down.load(Ordering::SeqCst)
+            {
+                break (events, last_event_id);
+            }
+            thread::sleep(POLL_INTERVAL);
+        };
+        Ok(Ok(json!({
+            "events": events,
+            "last_event_id": last_event_id,
+        })))
+    }
+
+    #[instrument(skip(self))]
+    fn hide(&self, params: HideParams) -> RpcResult {
+        let HideParams { revsets, recursive } = params;
+        let _mutation_guard = self.lock_mutations();
+        command_result(hide(
+            self.effects,
+            self.git_run_info,
+            revsets.into_iter().map(Revset).collect(),
+            &ResolveRevsetOptions::default(),
+            false,
+            recursive,
+        ))
+    }
+
+    #[instrument(skip(self))]
+    fn checkout(&self, params: CheckoutParams) -> RpcResult {
+        let CheckoutParams { target } = params;
+        let _mutation_guard = self.lock_mutations();
+        command_result(git_branchless_navigation::switch(
+            self.effects,
+            self.git_run_info,
+            &SwitchOptions {
+                interactive: false,
+                branch_name: None,
+                force: false,
+                merge: false,
+                detach: false,
+                target: Some(target),
+            },
+        ))
+    }
+
+    /// Stop accepting connections, and wake up the listener so that it
+    /// notices.
+    fn shutdown(&self) -> RpcResult {
+        self.is_shutting_down.store(true, Ordering::SeqCst);
+        TcpStream::connect(self.addr).wrap_err("Waking up server listener")?;
+        Ok(Ok(Value::Null))
+    }
+
+    fn handle_rpc(&self, body: &[u8]) -> Value {
+        #[derive(Deserialize)]
+        struct RpcRequest {
+            #[serde(default)]
+            id: Value,
+            method: String,
+            #[serde(default)]
+            params: Value,
+        }
+
+        let (id, result) = match serde_json::from_slice::<RpcRequest>(body) {
+            Ok(RpcRequest { id, method, params }) => {
+                let result = match method.as_str() {
+                    "smartlog" => match parse_params(params) {
+                        Ok(params) => self.get_smartlog(params),
+                        Err(err) => Ok(Err(err)),
+                    },
+                    "events" => match parse_params(params) {
+                        Ok(params) => self.get_events(params),
+                        Err(err) => Ok(Err(err)),
+                    },
+                    "hide" => match parse_params(params) {
+                        Ok(params) => self.hide(params),
+                        Err(err) => Ok(Err(err)),
+                    },
+                    "checkout" => match parse_params(params) {
+                        Ok(params) => self.checkout(params),
+                        Err(err) => Ok(Err(err)),
+                    },
+                    "shutdown" => self.shutdown(),
+                    method => Ok(Err(RpcError {
+                        code: METHOD_NOT_FOUND,
+                        message: format!("Unknown method: {method}"),
+                    })),
+                };
+                (id, result)
+            }
+            Err(err) => (
+                Value::Null,
+                Ok(Err(RpcError {
+                    code: PARSE_ERROR,
+                    message: err.to_string(),
+                })),
+            ),
+        };
+
+        match result {
+            Ok(Ok(result)) => json!({
+                "jsonrpc": "2.0",
+                "id": id,
+                "result": result,
+            }),
+            Ok(Err(RpcError { code, message })) => json!({
+                "jsonrpc": "2.0",
+                "id": id,
+                "error": { "code": code, "message": message },
+            }),
+            Err(err) => {
+                warn!(?err, "Failed to handle request");
+                json!({
+                    "jsonrpc": "2.0",
+                    "id": id,
+                    "error": { "code": INTERNAL_ERROR, "message": format!("{err:#}") },
+                })
+            }
+        }
+    }
+
+    /// Send each new event to the client as a server-sent event, until the
+    /// client disconnects or the server shuts down.
+    fn stream_events(&self, stream: &mut TcpStream, request: &Request) -> eyre::Result<()> {
+        let repo = Repo::from_current_dir()?;
+        let conn = repo.get_db_conn()?;
+        let event_log_db = EventLogDb::new(&conn)?;
+        let after = request
+            .get_query_param("after")
+            .or_else(|| request.get_header("Last-Event-ID"))
+            .and_then(|event_id| event_id.parse().ok());
+        let mut last_event_id = match after {
+            Some(after) => after,
+            None => event_log_db.get_last_event_row_id()?,
+        };
+
+        write!(
+            stream,
+            "HTTP/1.1 200 OK\r\nContent-Type: text/event-stream\r\nCache-Control: no-cache\r\nConnection: close\r\n\r\n"
+        )?;
+        stream.flush()?;
+
+        let mut last_write = Instant::now();
+        while !self.is_shutting_down.load(Ordering::SeqCst) {
+            let (events, new_last_event_id) = get_events_after(&event_log_db, last_event_id)?;
+            if !events.is_empty() {
+                let mut message = String::new();
+                for event in events {
+                    write!(message, "id: {}\ndata: {}\n\n", event["id"], event)?;
+                }
+                stream.write_all(message.as_bytes())?;
+                stream.flush()?;
+                last_event_id = new_last_event_id;
+                last_write = Instant::now();
+            } else if last_write.elapsed() >= KEEP_ALIVE_INTERVAL {
+                stream.write_all(b": keep-alive\n\n")?;
+                stream.flush()?;
+                last_write = Instant::now();
+            }
+            thread::sleep(POLL_INTERVAL);
+        }
+        Ok(())
+    }
+
+    fn handle_connection(&self, mut stream: TcpStream) -> eyre::Result<()> {
+        stream.set_read_timeout(Some(IO_TIMEOUT))?;
+        stream.set_write_timeout(Some(IO_TIMEOUT))?;
+        let mut reader = BufReader::new(stream.try_clone()?);
+        let request = match read_request(&mut reader) {
+            Ok(Some(request)) => request,
+            Ok(None) => return Ok(()),
+            Err(err) => {
+                warn!(?err, "Failed to read request");
+                return write_response(
+                    &mut stream,
+                    "400 Bad Request",
+                    "text/plain",
+                    b"Bad request\n",
+                );
+            }
+        };
+
+        let expected_authorization = format!("Bearer {}", self.token);
+        let is_authorized = match request.get_header("Authorization") {
+            Some(authorization) => {
+                constant_time_eq(authorization.as_bytes(), expected_authorization.as_bytes())
+            }
+            None => false,
+        };
+        if !is_authorized {
+            return write_response(
+                &mut stream,
+                "401 Unauthorized",
+                "text/plain",
+                b"Unauthorized\n",
+            );
+        }
+
+        let is_allowed = request.get_header("Origin").is_none()
+            && match request.get_header("Host") {
+                Some(host) => self.allowed_hosts.iter().any(|allowed| allowed == host),
+                None => true,
+            };
+        if !is_allowed {
+            return write_response(&mut stream, "403 Forbidden", "text/plain", b"Forbidden\n");
+        }
+
+        match (request.method.as_str(), request.path.as_str()) {
+            ("POST", "/rpc") => {
+                let response = self.handle_rpc(&request.body);
+                write_response(
+                    &mut stream,
+                    "200 OK",
+                    "application/json",
+                    &serde_json::to_vec(&response)?,
+                )
+            }
+            ("GET", "/events") => self.stream_events(&mut stream, &request),
+            _ => write_response(&mut stream, "404 Not Found", "text/plain", b"Not found\n"),
+        }
+    }
+}
+
+/// Serve requests on the given port of the loopback interface until a
+/// `shutdown` request is received. If the port is 0, then any available port
+/// is used.
+#[instrument]
+pub fn serve(effects: &Effects, git_run_info: &GitRunInfo, port: u16) -> EyreExitOr<()> {
+    // Make sure that we're in a repository before accepting any requests.
+    Repo::from_current_dir()?;
+
+    let listener = TcpListener::bind((Ipv4Addr::LOCALHOST, port))
+        .wrap_err_with(|| format!("Listening on port {port}"))?;
+    let addr = listener.local_addr()?;
+    let port = addr.port();
+    let server = Server {
+        effects,
+        git_run_info,
+        addr,
+        allowed_hosts: vec![format!("127.0.0.1:{port}"), format!("localhost:{port}")],
+        token: make_token()?,
+        num_connections: AtomicUsize::new(0),
+        is_shutting_down: AtomicBool::new(false),
+        mutation_lock: Mutex::new(()),
+    };
+    writeln!(
+        effects.get_output_stream(),
+        "Listening on http://127.0.0.1:{port}"
+    )?;
+    writeln!(
+        effects.get_output_stream(),
+        "Authorization: Bearer {}",
+        server.token
+    )?;
+
+    thread::scope(|scope| {
+        for stream in listener.incoming() {
+            if server.is_shutting_down.load(Ordering::SeqCst) {
+                break;
+            }
+            let mut stream = match stream {
+                Ok(stream) => stream,
+                Err(err) => {
+                    warn!(?err, "Failed to accept connection");
+                    continue;
+                }
+            };
+            if server.num_connections.fetch_add(1, Ordering::SeqCst) >= MAX_CONNECTIONS {
+                server.num_connections.fetch_sub(1, Ordering::SeqCst);
+                warn!("Too many connections; rejecting connection");
+                if let Err(err) = reject_connection(&mut stream) {
+                    warn!(?err, "Failed to reject connection");
+                }
+                continue;
+            }
+            let server = &server;
+            let connection_guard = ConnectionGuard {
+                num_connections: &server.num_connections,
+            };
+            scope.spawn(move || {
+                let _connection_guard = connection_guard;
+                if let Err(err) = server.handle_connection(stream) {
+                    warn!(?err, "Failed to handle connection");
+                }
+            });
+        }
+    });
+
+    Ok(Ok(()))
+}
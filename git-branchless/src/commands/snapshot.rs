@@ -9,8 +9,12 @@ use cursive_core::utils::markup::StyledString;
 use eyre::Context;
 use lib::core::check_out::{create_snapshot, restore_snapshot};
 use lib::core::effects::Effects;
-use lib::core::eventlog::EventLogDb;
-use lib::git::{GitRunInfo, GitRunResult, NonZeroOid, Repo, WorkingCopySnapshot};
+use lib::core::eventlog::{Event, EventLogDb, EventReplayer};
+use lib::core::node_descriptors::RelativeTimeDescriptor;
+use lib::git::{
+    Commit, GitRunInfo, GitRunResult, MaybeZeroOid, NonZeroOid, Repo, WorkingCopyChangesType,
+    WorkingCopySnapshot,
+};
 use lib::util::{ExitCode, EyreExitOr};
 
 pub fn create(effects: &Effects, git_run_info: &GitRunInfo) -> EyreExitOr<()> {
@@ -64,17 +68,170 @@ pub fn restore(
     let event_log_db = EventLogDb::new(&conn)?;
     let event_tx_id = event_log_db.make_transaction_id(SystemTime::now(), "snapshot restore")?;
 
-    let base_commit = repo.find_commit_or_fail(snapshot_oid)?;
-    let snapshot = match WorkingCopySnapshot::try_from_base_commit(&repo, &base_commit)? {
+    let snapshot = match find_snapshot(effects, &repo, snapshot_oid)? {
         Some(snapshot) => snapshot,
-        None => {
-            writeln!(
-                effects.get_error_stream(),
-                "Not a snapshot commit: {snapshot_oid}"
-            )?;
-            return Ok(Err(ExitCode(1)));
-        }
+        None => return Ok(Err(ExitCode(1))),
     };
 
     restore_snapshot(effects, git_run_info, &repo, event_tx_id, &snapshot)
 }
+
+/// Load the snapshot with the given base commit, printing an error if it's
+/// not a snapshot commit.
+fn find_snapshot(
+    effects: &Effects,
+    repo: &Repo,
+    snapshot_oid: NonZeroOid,
+) -> eyre::Result<Option<WorkingCopySnapshot>> {
+    let base_commit = repo.find_commit_or_fail(snapshot_oid)?;
+    let snapshot = WorkingCopySnapshot::try_from_base_commit(repo, &base_commit)?;
+    if snapshot.is_none() {
+        writeln!(
+            effects.get_error_stream(),
+            "Not a snapshot commit: {snapshot_oid}"
+        )?;
+    }
+    Ok(snapshot)
+}
+
+/// List the snapshots recorded in the event log, from newest to oldest.
+pub fn list(effects: &Effects, git_run_info: &GitRunInfo) -> EyreExitOr<()> {
+    let now = SystemTime::now();
+    let glyphs = effects.get_glyphs();
+    let repo = Repo::from_dir(&git_run_info.working_directory)?;
+    let conn = repo.get_db_conn()?;
+    let event_log_db = EventLogDb::new(&conn)?;
+    let event_replayer = EventReplayer::from_event_log_db(effects, &repo, &event_log_db)?;
+    let relative_time_provider = RelativeTimeDescriptor::new(&repo, now)?;
+
+    let mut any_snapshots = false;
+    for event in event_replayer
+        .get_events_since_cursor(event_replayer.make_cursor(0))
+        .iter()
+        .rev()
+    {
+        let (event_tx_id, head_oid, commit_oid) = match event {
+            Event::WorkingCopySnapshot {
+                timestamp: _,
+                event_tx_id,
+                head_oid,
+                commit_oid,
+                ref_name: _,
+            } => (*event_tx_id, *head_oid, *commit_oid),
+            _ => continue,
+        };
+        any_snapshots = true;
+
+        let relative_time = if relative_time_provider.is_enabled() {
+            format!(
+                " ({} ago)",
+                RelativeTimeDescriptor::describe_time_delta(now, event.get_timestamp())?
+            )
+        } else {
+            String::new()
+        };
+        writeln!(
+            effects.get_output_stream(),
+            "{} {}{}",
+            commit_oid,
+            event_log_db.get_transaction_message(event_tx_id)?,
+            relative_time,
+        )?;
+        let head_description = match head_oid {
+            MaybeZeroOid::NonZero(head_oid) => {
+                glyphs.render(repo.friendly_describe_commit_from_oid(glyphs, head_oid)?)?
+            }
+            MaybeZeroOid::Zero => "(no commit checked out)".to_string(),
+        };
+        writeln!(effects.get_output_stream(), "    {head_description}")?;
+    }
+
+    if !any_snapshots {
+        writeln!(effects.get_output_stream(), "No snapshots recorded.")?;
+    }
+
+    Ok(Ok(()))
+}
+
+/// Print the changes in the given snapshot relative to the commit which was
+/// checked out when it was taken, i.e. what `snapshot restore` would bring
+/// back.
+pub fn show(
+    effects: &Effects,
+    git_run_info: &GitRunInfo,
+    snapshot_oid: NonZeroOid,
+) -> EyreExitOr<()> {
+    let glyphs = effects.get_glyphs();
+    let repo = Repo::from_dir(&git_run_info.working_directory)?;
+    let snapshot = match find_snapshot(effects, &repo, snapshot_oid)? {
+        Some(snapshot) => snapshot,
+        None => return Ok(Err(ExitCode(1))),
+    };
+
+    let head_description = match &snapshot.head_commit {
+        Some(head_commit) => glyphs.render(head_commit.friendly_describe(glyphs)?)?,
+        None => "(no commit checked out)".to_string(),
+    };
+    writeln!(
+        effects.get_output_stream(),
+        "Snapshot {} of {}",
+        snapshot.base_commit.get_oid(),
+        head_description,
+    )?;
+
+    let changes_type = snapshot.get_working_copy_changes_type()?;
+    if changes_type == WorkingCopyChangesType::None {
+        writeln!(effects.get_output_stream(), "No changes in this snapshot.")?;
+        return Ok(Ok(()));
+    }
+
+    write_snapshot_diff(
+        effects,
+        &repo,
+        &snapshot.base_commit,
+        &snapshot.commit_stage0,
+        "Staged changes:",
+    )?;
+    write_snapshot_diff(
+        effects,
+        &repo,
+        &snapshot.commit_stage0,
+        &snapshot.commit_unstaged,
+        "Unstaged changes:",
+    )?;
+    if changes_type == WorkingCopyChangesType::Conflicts {
+        writeln!(
+            effects.get_output_stream(),
+            "This snapshot also contains unresolved merge conflicts."
+        )?;
+    }
+
+    Ok(Ok(()))
+}
+
+/// Write the diff between the trees of two of the snapshot's commits under the
+/// given heading, if there are any changes. The staged changes are relative to
+/// the snapshot's base tree, and the unstaged changes are relative to the
+/// staged ones, like `git diff --cached` and `git diff`.
+fn write_snapshot_diff(
+    effects: &Effects,
+    repo: &Repo,
+    old_commit: &Commit,
+    new_commit: &Commit,
+    heading: &str,
+) -> eyre::Result<()> {
+    if old_commit.get_tree_oid() == new_commit.get_tree_oid() {
+        return Ok(());
+    }
+
+    let old_tree = old_commit.get_tree()?;
+    let new_tree = new_commit.get_tree()?;
+    let diff = repo.get_diff_between_trees(effects, Some(&old_tree), &new_tree, 3)?;
+    writeln!(effects.get_output_stream(), "{heading}")?;
+    write!(
+        effects.get_output_stream(),
+        "{}",
+        String::from_utf8_lossy(&diff.to_patch()?)
+    )?;
+    Ok(())
+}
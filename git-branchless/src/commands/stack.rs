@@ -0,0 +1,281 @@
+//! Name stacks of commits.
+//!
+//! See [`lib::core::stacks`] for how named stacks are stored and kept up to
+//! date.
+
+use std::fmt::Write;
+use std::time::SystemTime;
+
+use git_branchless_opts::{ResolveRevsetOptions, Revset, StackSubcommand};
+use git_branchless_revset::resolve_commits;
+use itertools::Itertools;
+use lib::core::check_out::{
+    carry_snapshot_changes, check_out_commit, create_snapshot, write_carried_changes,
+    CheckOutCommitOptions, CheckoutTarget,
+};
+use lib::core::dag::Dag;
+use lib::core::effects::Effects;
+use lib::core::eventlog::{EventLogDb, EventReplayer};
+use lib::core::formatting::Pluralize;
+use lib::core::repo_ext::RepoExt;
+use lib::core::stacks::StackDb;
+use lib::git::{GitRunInfo, NonZeroOid, Repo, WorkingCopyChangesType};
+use lib::try_exit_code;
+use lib::util::{ExitCode, EyreExitOr};
+use tracing::instrument;
+
+/// Name the stack ending at the commit given by `tip`.
+#[instrument]
+fn create(
+    effects: &Effects,
+    repo: &Repo,
+    stack_db: &StackDb,
+    name: String,
+    tip: Revset,
+    resolve_revset_options: &ResolveRevsetOptions,
+) -> EyreExitOr<()> {
+    if let Ok(Some(commit)) = repo.revparse_single_commit(&name) {
+        writeln!(
+            effects.get_error_stream(),
+            "The name {name:?} already refers to commit {}, so it can't be used as a stack name.",
+            commit.get_short_oid()?
+        )?;
+        return Ok(Err(ExitCode(1)));
+    }
+
+    let references_snapshot = repo.get_references_snapshot()?;
+    let conn = repo.get_db_conn()?;
+    let event_log_db = EventLogDb::new(&conn)?;
+    let event_replayer = EventReplayer::from_event_log_db(effects, repo, &event_log_db)?;
+    let event_cursor = event_replayer.make_default_cursor();
+    let mut dag = Dag::open_and_sync(
+        effects,
+        repo,
+        &event_replayer,
+        event_cursor,
+        &references_snapshot,
+    )?;
+
+    let commit_sets = match resolve_commits(
+        effects,
+        repo,
+        &mut dag,
+        &[tip.clone()],
+        resolve_revset_options,
+    ) {
+        Ok(commit_sets) => commit_sets,
+        Err(err) => {
+            err.describe(effects)?;
+            return Ok(Err(ExitCode(1)));
+        }
+    };
+    let tip_oid = match dag.commit_set_to_vec(&commit_sets[0])?.as_slice() {
+        [tip_oid] => *tip_oid,
+        other => {
+            writeln!(
+                effects.get_error_stream(),
+                "The tip of a stack must be exactly one commit, but {tip} resolved to {} commits.",
+                other.len()
+            )?;
+            return Ok(Err(ExitCode(1)));
+        }
+    };
+    if dag.is_public_commit(tip_oid)? {
+        writeln!(
+            effects.get_error_stream(),
+            "The tip of a stack must be a draft commit, but {tip_oid} is public."
+        )?;
+        return Ok(Err(ExitCode(1)));
+    }
+
+    stack_db.set_stack(&name, tip_oid)?;
+    let glyphs = effects.get_glyphs();
+    writeln!(
+        effects.get_output_stream(),
+        "Named stack {name} with tip {}",
+        glyphs.render(repo.friendly_describe_commit_from_oid(glyphs, tip_oid)?)?
+    )?;
+    Ok(Ok(()))
+}
+
+/// Check out the tip of the stack with the given name.
+///
+/// If `carry` is set, then any uncommitted changes are moved onto the new tip
+/// by applying them from a working copy snapshot. If they don't apply cleanly,
+/// the conflicting paths are reported and the working copy is left unchanged.
+#[instrument]
+fn switch(
+    effects: &Effects,
+    git_run_info: &GitRunInfo,
+    repo: &Repo,
+    stack_db: &StackDb,
+    name: &str,
+    carry: bool,
+) -> EyreExitOr<()> {
+    let tip_oid = match stack_db.get_stack_tip(name)? {
+        Some(tip_oid) => tip_oid,
+        None => {
+            writeln!(effects.get_error_stream(), "No stack named {name}")?;
+            return Ok(Err(ExitCode(1)));
+        }
+    };
+
+    let conn = repo.get_db_conn()?;
+    let event_log_db = EventLogDb::new(&conn)?;
+    let event_tx_id = event_log_db.make_transaction_id(SystemTime::now(), "stack switch")?;
+    let target = Some(CheckoutTarget::Oid(tip_oid));
+    if !carry {
+        return check_out_commit(
+            effects,
+            git_run_info,
+            repo,
+            &event_log_db,
+            event_tx_id,
+            target,
+            &CheckOutCommitOptions::default(),
+        );
+    }
+
+    let snapshot = create_snapshot(effects, git_run_info, repo, &event_log_db, event_tx_id)?;
+    match snapshot.get_working_copy_changes_type()? {
+        WorkingCopyChangesType::None => {
+            return check_out_commit(
+                effects,
+                git_run_info,
+                repo,
+                &event_log_db,
+                event_tx_id,
+                target,
+                &CheckOutCommitOptions::default(),
+            );
+        }
+        WorkingCopyChangesType::Unstaged | WorkingCopyChangesType::Staged => {}
+        WorkingCopyChangesType::Conflicts => {
+            writeln!(
+                effects.get_error_stream(),
+                "The working copy has unresolved merge conflicts, so its changes can't be carried over to stack {name}."
+            )?;
+            return Ok(Err(ExitCode(1)));
+        }
+    }
+
+    let tip_commit = repo.find_commit_or_fail(tip_oid)?;
+    let carried_tree = match carry_snapshot_changes(repo, &snapshot, &tip_commit)? {
+        Ok(tree) => tree,
+        Err(conflicting_paths) => {
+            let glyphs = effects.get_glyphs();
+            writeln!(
+                effects.get_output_stream(),
+                "The uncommitted changes can't be carried over to stack {name}, since they conflict with its tip in {}:",
+                Pluralize {
+                    determiner: None,
+                    amount: conflicting_paths.len(),
+                    unit: ("file", "files"),
+                },
+            )?;
+            for path in conflicting_paths.iter().sorted() {
+                writeln!(
+                    effects.get_output_stream(),
+                    "{} {}",
+                    glyphs.bullet_point,
+                    path.display()
+                )?;
+            }
+            writeln!(
+                effects.get_output_stream(),
+                "The working copy was left unchanged."
+            )?;
+            return Ok(Err(ExitCode(1)));
+        }
+    };
+
+    // The changes were saved in the snapshot above, so it's safe to discard
+    // them from the working copy now.
+    try_exit_code!(git_run_info.run(effects, Some(event_tx_id), &["reset", "--hard", "HEAD"])?);
+    let exit_code = match check_out_commit(
+        effects,
+        git_run_info,
+        repo,
+        &event_log_db,
+        event_tx_id,
+        target,
+        &CheckOutCommitOptions {
+            render_smartlog: false,
+            ..Default::default()
+        },
+    )? {
+        Ok(()) => write_carried_changes(effects, git_run_info, event_tx_id, &carried_tree)?,
+        Err(exit_code) => Err(exit_code),
+    };
+    if let Err(exit_code) = exit_code {
+        write_snapshot_hint(effects, snapshot.base_commit.get_oid())?;
+        return Ok(Err(exit_code));
+    }
+
+    try_exit_code!(
+        git_run_info.run_direct_no_wrapping(Some(event_tx_id), &["branchless", "smartlog"])?
+    );
+    Ok(Ok(()))
+}
+
+/// Tell the user how to get back the uncommitted changes which couldn't be
+/// carried over.
+fn write_snapshot_hint(effects: &Effects, snapshot_oid: NonZeroOid) -> eyre::Result<()> {
+    writeln!(
+        effects.get_error_stream(),
+        "The uncommitted changes were saved in snapshot {snapshot_oid}. To restore them, run: git branchless snapshot restore {snapshot_oid}"
+    )?;
+    Ok(())
+}
+
+/// Run the given `stack` subcommand.
+#[instrument]
+pub fn stack(
+    effects: &Effects,
+    git_run_info: &GitRunInfo,
+    subcommand: StackSubcommand,
+) -> EyreExitOr<()> {
+    let repo = Repo::from_current_dir()?;
+    let conn = repo.get_db_conn()?;
+    let stack_db = StackDb::new(&conn)?;
+    match subcommand {
+        StackSubcommand::Create {
+            name,
+            tip,
+            resolve_revset_options,
+        } => create(
+            effects,
+            &repo,
+            &stack_db,
+            name,
+            tip,
+            &resolve_revset_options,
+        ),
+
+        StackSubcommand::Switch { name, carry } => {
+            switch(effects, git_run_info, &repo, &stack_db, &name, carry)
+        }
+
+        StackSubcommand::List => {
+            let glyphs = effects.get_glyphs();
+            for (name, tip_oid) in stack_db.get_stacks()? {
+                writeln!(
+                    effects.get_output_stream(),
+                    "{name}: {}",
+                    glyphs.render(repo.friendly_describe_commit_from_oid(glyphs, tip_oid)?)?
+                )?;
+            }
+            Ok(Ok(()))
+        }
+
+        StackSubcommand::Delete { name } => {
+            if stack_db.delete_stack(&name)? {
+                writeln!(effects.get_output_stream(), "Deleted stack {name}")?;
+                Ok(Ok(()))
+            } else {
+                writeln!(effects.get_error_stream(), "No stack named {name}")?;
+                Ok(Err(ExitCode(1)))
+            }
+        }
+    }
+}
@@ -0,0 +1,195 @@
+//! Report statistics about how the repository has been worked on.
+//!
+//! Everything is derived from the event log, except for the stack depth, which
+//! is computed from the commits which are currently visible.
+
+use std::collections::HashSet;
+use std::fmt::Write;
+use std::time::{Duration, SystemTime};
+
+use lib::core::dag::Dag;
+use lib::core::effects::Effects;
+use lib::core::eventlog::{Event, EventLogDb, EventReplayer, EventTransactionId};
+use lib::core::repo_ext::RepoExt;
+use lib::git::{MaybeZeroOid, NonZeroOid, Repo};
+use lib::util::EyreExitOr;
+use tracing::instrument;
+
+const SECONDS_PER_DAY: u64 = 24 * 60 * 60;
+
+/// Statistics aggregated from the events in the event log.
+#[derive(Debug, Default, PartialEq, Eq)]
+struct EventStats {
+    /// The number of commits amended, either with `git amend` or with `git
+    /// commit --amend`.
+    num_commits_amended: usize,
+
+    /// The number of operations which restacked any commits, such as `git
+    /// restack`, or `git amend` when the amended commit had descendants.
+    num_restacks: usize,
+
+    /// The number of commits which were hidden and later unhidden, such as by
+    /// `git unhide` or `git undo`.
+    num_commits_restored: usize,
+}
+
+/// Aggregate the events which happened at or after `since`.
+///
+/// Rewrites are classified by the commits involved rather than by the command
+/// which made them, since commands such as `git commit --amend` and `git
+/// rebase` are only observed through the same `post-rewrite` hook:
+///
+/// - A rewrite which keeps the parents of the commit is an amend.
+/// - A rewrite which moves the commit from its parent onto the commit which
+///   that parent was rewritten into is part of a restack.
+///
+/// Rewrites of commits which no longer exist can't be classified, and are
+/// ignored.
+#[instrument(skip(events))]
+fn compute_event_stats(
+    repo: &Repo,
+    events: &[Event],
+    since: SystemTime,
+) -> eyre::Result<EventStats> {
+    let rewrites: HashSet<(NonZeroOid, NonZeroOid)> = events
+        .iter()
+        .filter_map(|event| match event {
+            Event::RewriteEvent {
+                old_commit_oid: MaybeZeroOid::NonZero(old_commit_oid),
+                new_commit_oid: MaybeZeroOid::NonZero(new_commit_oid),
+                ..
+            } => Some((*old_commit_oid, *new_commit_oid)),
+            _ => None,
+        })
+        .collect();
+
+    let mut num_commits_amended = 0;
+    let mut restack_tx_ids: HashSet<EventTransactionId> = HashSet::new();
+    let mut hidden_commits: HashSet<NonZeroOid> = HashSet::new();
+    let mut num_commits_restored = 0;
+    for event in events {
+        match event {
+            Event::RewriteEvent {
+                old_commit_oid: MaybeZeroOid::NonZero(old_commit_oid),
+                new_commit_oid: MaybeZeroOid::NonZero(new_commit_oid),
+                ..
+            } if event.get_timestamp() >= since => {
+                let (old_commit, new_commit) = match (
+                    repo.find_commit(*old_commit_oid)?,
+                    repo.find_commit(*new_commit_oid)?,
+                ) {
+                    (Some(old_commit), Some(new_commit)) => (old_commit, new_commit),
+                    _ => continue,
+                };
+                let old_parent_oids = old_commit.get_parent_oids();
+                let new_parent_oids = new_commit.get_parent_oids();
+                if old_parent_oids == new_parent_oids {
+                    num_commits_amended += 1;
+                } else if old_parent_oids.len() == new_parent_oids.len()
+                    && old_parent_oids.iter().zip(new_parent_oids.iter()).any(
+                        |(old_parent_oid, new_parent_oid)| {
+                            rewrites.contains(&(*old_parent_oid, *new_parent_oid))
+                        },
+                    )
+                {
+                    restack_tx_ids.insert(event.get_event_tx_id());
+                }
+            }
+            Event::ObsoleteEvent { commit_oid, .. } => {
+                hidden_commits.insert(*commit_oid);
+            }
+            Event::UnobsoleteEvent { commit_oid, .. } => {
+                if hidden_commits.remove(commit_oid) && event.get_timestamp() >= since {
+                    num_commits_restored += 1;
+                }
+            }
+            _ => {}
+        }
+    }
+
+    Ok(EventStats {
+        num_commits_amended,
+        num_restacks: restack_tx_ids.len(),
+        num_commits_restored,
+    })
+}
+
+fn average(total: usize, count: usize) -> eyre::Result<f64> {
+    if count == 0 {
+        return Ok(0.0);
+    }
+    Ok(f64::from(u32::try_from(total)?) / f64::from(u32::try_from(count)?))
+}
+
+/// Get the number of draft commits in each stack, measured from the head of
+/// the stack down to the public commit it's based on.
+#[instrument]
+fn get_stack_depths(dag: &Dag) -> eyre::Result<Vec<usize>> {
+    let draft_commits = dag
+        .query_visible_commits_slow()?
+        .intersection(dag.query_draft_commits()?);
+    let heads = dag.commit_set_to_vec(&dag.query_heads(draft_commits.clone())?)?;
+    let mut result = Vec::new();
+    for head_oid in heads {
+        let stack = dag
+            .query_ancestors(head_oid.into())?
+            .intersection(&draft_commits);
+        result.push(dag.set_count(&stack)?);
+    }
+    Ok(result)
+}
+
+/// Print statistics about the events of the last `days` days and the current
+/// stacks of draft commits.
+#[instrument]
+pub fn stats(effects: &Effects, days: u64) -> EyreExitOr<()> {
+    let now = SystemTime::now();
+    let since = now
+        .checked_sub(Duration::from_secs(days.saturating_mul(SECONDS_PER_DAY)))
+        .unwrap_or(SystemTime::UNIX_EPOCH);
+
+    let repo = Repo::from_current_dir()?;
+    let conn = repo.get_db_conn()?;
+    let event_log_db = EventLogDb::new(&conn)?;
+    let event_replayer = EventReplayer::from_event_log_db(effects, &repo, &event_log_db)?;
+    let EventStats {
+        num_commits_amended,
+        num_restacks,
+        num_commits_restored,
+    } = compute_event_stats(&repo, &event_log_db.get_events()?, since)?;
+
+    let references_snapshot = repo.get_references_snapshot()?;
+    let event_cursor = event_replayer.make_default_cursor();
+    let dag = Dag::open_and_sync(
+        effects,
+        &repo,
+        &event_replayer,
+        event_cursor,
+        &references_snapshot,
+    )?;
+    let stack_depths = get_stack_depths(&dag)?;
+
+    let days_divisor = usize::try_from(days.max(1))?;
+    writeln!(effects.get_output_stream(), "Over the last {days} day(s):")?;
+    writeln!(
+        effects.get_output_stream(),
+        "  Commits amended: {num_commits_amended} ({:.1} per day)",
+        average(num_commits_amended, days_divisor)?
+    )?;
+    writeln!(
+        effects.get_output_stream(),
+        "  Restacks performed: {num_restacks}"
+    )?;
+    writeln!(
+        effects.get_output_stream(),
+        "  Commits hidden and later restored: {num_commits_restored}"
+    )?;
+    writeln!(
+        effects.get_output_stream(),
+        "Stacks of draft commits: {} (average depth: {:.1} commits)",
+        stack_depths.len(),
+        average(stack_depths.iter().sum(), stack_depths.len())?
+    )?;
+
+    Ok(Ok(()))
+}
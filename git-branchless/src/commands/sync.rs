@@ -13,19 +13,24 @@ use rayon::{ThreadPool, ThreadPoolBuilder};
 
 use git_branchless_opts::{MoveOptions, ResolveRevsetOptions, Revset};
 use git_branchless_revset::{check_revset_syntax, resolve_commits};
-use lib::core::config::get_restack_preserve_timestamps;
+use lib::core::config::{
+    get_main_commit_spec, get_restack_preserve_timestamps, MAIN_COMMIT_CONFIG_KEY,
+};
 use lib::core::dag::{sorted_commit_set, union_all, CommitSet, Dag};
-use lib::core::effects::{Effects, OperationType};
-use lib::core::eventlog::{EventLogDb, EventReplayer};
+use lib::core::effects::{Effects, OperationType, StepEvent};
+use lib::core::eventlog::{Event, EventLogDb, EventReplayer};
 use lib::core::formatting::{Pluralize, StyledStringBuilder};
+use lib::core::notes::mirror_rewrites_to_notes;
 use lib::core::rewrite::{
-    execute_rebase_plan, BuildRebasePlanError, BuildRebasePlanOptions, ExecuteRebasePlanOptions,
+    execute_rebase_plan, find_equivalent_commits, find_main_branch_rewrite, is_non_fast_forward,
+    BuildRebasePlanError, BuildRebasePlanOptions, ExecuteRebasePlanOptions,
     ExecuteRebasePlanResult, FailedMergeInfo, RebasePlan, RebasePlanBuilder, RebasePlanPermissions,
     RepoPool, RepoResource,
 };
 use lib::core::task::ResourcePool;
 use lib::git::{
-    CategorizedReferenceName, Commit, GitRunInfo, NonZeroOid, Repo, ResolvedReferenceInfo,
+    CategorizedReferenceName, Commit, GitRunInfo, MaybeZeroOid, NonZeroOid, Repo,
+    ResolvedReferenceInfo,
 };
 
 fn get_stack_roots(dag: &Dag, commit_sets: Vec<CommitSet>) -> eyre::Result<CommitSet> {
@@ -50,6 +55,7 @@ pub fn sync(
     effects: &Effects,
     git_run_info: &GitRunInfo,
     pull: bool,
+    onto_new_main: bool,
     move_options: &MoveOptions,
     revsets: Vec<Revset>,
     resolve_revset_options: &ResolveRevsetOptions,
@@ -102,6 +108,21 @@ pub fn sync(
     let repo_pool = RepoResource::new_pool(&repo)?;
 
     let head_info = repo.get_head_info()?;
+    if onto_new_main {
+        return execute_new_main_sync_plans(
+            effects,
+            git_run_info,
+            &repo,
+            &event_log_db,
+            build_options,
+            &execute_options,
+            &thread_pool,
+            &repo_pool,
+            &head_info,
+            revsets,
+            resolve_revset_options,
+        );
+    }
     if pull {
         try_exit_code!(execute_main_branch_sync_plan(
             effects,
@@ -154,6 +175,14 @@ fn execute_main_branch_sync_plan(
         &references_snapshot,
     )?;
 
+    if let Some(main_commit_spec) = get_main_commit_spec(repo)? {
+        writeln!(
+            effects.get_error_stream(),
+            "Warning: the main branch is pinned to {main_commit_spec} with {MAIN_COMMIT_CONFIG_KEY}, so it wasn't pulled. Update {MAIN_COMMIT_CONFIG_KEY} to sync onto a newer commit."
+        )?;
+        return Ok(Ok(()));
+    }
+
     let local_main_branch = repo.get_main_branch()?;
     let local_main_branch_oid = local_main_branch.get_oid()?;
     let local_main_branch_reference_name = local_main_branch.get_reference_name()?;
@@ -389,6 +418,187 @@ fn execute_sync_plans(
     )
 }
 
+/// Move the stacks which were based on the commits of the main branch before
+/// it was rewritten (such as by an upstream force-push) onto the tip of the new
+/// main branch. The old main branch commits are recorded as rewritten into
+/// their equivalent commits in the new main branch.
+fn execute_new_main_sync_plans(
+    effects: &Effects,
+    git_run_info: &GitRunInfo,
+    repo: &Repo,
+    event_log_db: &EventLogDb,
+    build_options: BuildRebasePlanOptions,
+    execute_options: &ExecuteRebasePlanOptions,
+    thread_pool: &ThreadPool,
+    repo_pool: &RepoPool,
+    head_info: &ResolvedReferenceInfo,
+    revsets: Vec<Revset>,
+    resolve_revset_options: &ResolveRevsetOptions,
+) -> EyreExitOr<()> {
+    let (old_main_oid, rewritten_main_oid) =
+        match find_main_branch_rewrite(repo, &event_log_db.get_events()?)? {
+            Some(main_branch_rewrite) => main_branch_rewrite,
+            None => {
+                writeln!(
+                    effects.get_output_stream(),
+                    "The main branch hasn't been rewritten, so there are no stacks to move onto it."
+                )?;
+                return Ok(Err(ExitCode(1)));
+            }
+        };
+
+    // The main branch may have been updated again since it was rewritten, so
+    // prefer its current upstream commit.
+    let local_main_branch = repo.get_main_branch()?;
+    let local_main_branch_oid = local_main_branch.get_oid()?;
+    let local_main_branch_reference_name = local_main_branch.get_reference_name()?;
+    let new_main_oid = local_main_branch
+        .get_upstream_branch_target()?
+        .or(local_main_branch_oid)
+        .unwrap_or(rewritten_main_oid);
+
+    // If the local main branch still points into the old history, then move it
+    // to the new main branch first, so that the old commits are treated as
+    // draft commits below.
+    if let Some(local_main_branch_oid) = local_main_branch_oid {
+        if repo.find_merge_base(local_main_branch_oid, old_main_oid)? == Some(local_main_branch_oid)
+            && is_non_fast_forward(repo, local_main_branch_oid, new_main_oid)?
+        {
+            let new_main_commit = repo.find_commit_or_fail(new_main_oid)?;
+            writeln!(
+                effects.get_output_stream(),
+                "Resetting {} to {}",
+                CategorizedReferenceName::new(&local_main_branch_reference_name)
+                    .friendly_describe(),
+                effects
+                    .get_glyphs()
+                    .render(new_main_commit.friendly_describe(effects.get_glyphs())?)?,
+            )?;
+            if head_info.reference_name.as_ref() == Some(&local_main_branch_reference_name) {
+                try_exit_code!(git_run_info.run(
+                    effects,
+                    Some(execute_options.event_tx_id),
+                    &["reset", "--keep", &new_main_oid.to_string()],
+                )?);
+            } else {
+                repo.create_reference(
+                    &local_main_branch_reference_name,
+                    new_main_oid,
+                    true,
+                    "sync",
+                )?;
+            }
+        }
+    }
+
+    let event_replayer = EventReplayer::from_event_log_db(effects, repo, event_log_db)?;
+    let event_cursor = event_replayer.make_default_cursor();
+    let references_snapshot = repo.get_references_snapshot()?;
+    let mut dag = Dag::open_and_sync(
+        effects,
+        repo,
+        &event_replayer,
+        event_cursor,
+        &references_snapshot,
+    )?;
+    dag.sync_from_oids(
+        effects,
+        repo,
+        CommitSet::from(new_main_oid),
+        CommitSet::from(old_main_oid),
+    )?;
+    let commit_sets =
+        match resolve_commits(effects, repo, &mut dag, &revsets, resolve_revset_options) {
+            Ok(commit_sets) => commit_sets,
+            Err(err) => {
+                err.describe(effects)?;
+                return Ok(Err(ExitCode(1)));
+            }
+        };
+
+    let old_main_commits =
+        dag.query_only(CommitSet::from(old_main_oid), CommitSet::from(new_main_oid))?;
+    let new_main_commits =
+        dag.query_only(CommitSet::from(new_main_oid), CommitSet::from(old_main_oid))?;
+    let equivalent_commits =
+        find_equivalent_commits(effects, repo, &dag, &old_main_commits, &new_main_commits)?;
+    let mut root_commit_oids = dag
+        .query_children(old_main_commits.clone())?
+        .difference(&old_main_commits)
+        .intersection(dag.query_draft_commits()?);
+    if !commit_sets.is_empty() {
+        root_commit_oids =
+            root_commit_oids.intersection(&dag.query_ancestors(union_all(&commit_sets))?);
+    }
+    let root_commits = sorted_commit_set(repo, &dag, &root_commit_oids)?;
+    if root_commits.is_empty() {
+        writeln!(
+            effects.get_output_stream(),
+            "No stacks are based on the old main branch, so there is nothing to move."
+        )?;
+        return Ok(Ok(()));
+    }
+
+    let permissions =
+        match RebasePlanPermissions::verify_rewrite_set(&dag, build_options, &root_commit_oids)? {
+            Ok(permissions) => permissions,
+            Err(err) => {
+                err.describe(effects, repo, &dag)?;
+                return Ok(Err(ExitCode(1)));
+            }
+        };
+    let builder = RebasePlanBuilder::new(&dag, permissions);
+    let mut root_commit_and_plans = Vec::new();
+    for root_commit in root_commits {
+        let mut builder = builder.clone();
+        builder.move_subtree(root_commit.get_oid(), vec![new_main_oid])?;
+        match builder.build(effects, thread_pool, repo_pool)? {
+            Ok(rebase_plan) => root_commit_and_plans.push((root_commit.get_oid(), rebase_plan)),
+            Err(err) => {
+                err.describe(effects, repo, &dag)?;
+                return Ok(Err(ExitCode(1)));
+            }
+        }
+    }
+
+    try_exit_code!(execute_plans(
+        effects,
+        git_run_info,
+        repo,
+        event_log_db,
+        execute_options,
+        root_commit_and_plans,
+    )?);
+
+    // Record the old main branch commits as rewritten into their equivalents,
+    // so that anything else still based on them can be restacked.
+    let timestamp = execute_options
+        .now
+        .duration_since(SystemTime::UNIX_EPOCH)?
+        .as_secs_f64();
+    let equivalent_commits = equivalent_commits.into_iter().sorted().collect_vec();
+    event_log_db.add_events(
+        equivalent_commits
+            .iter()
+            .map(|(old_commit_oid, new_commit_oid)| Event::RewriteEvent {
+                timestamp,
+                event_tx_id: execute_options.event_tx_id,
+                old_commit_oid: MaybeZeroOid::NonZero(*old_commit_oid),
+                new_commit_oid: MaybeZeroOid::NonZero(*new_commit_oid),
+            })
+            .collect(),
+    )?;
+    mirror_rewrites_to_notes(
+        repo,
+        equivalent_commits
+            .iter()
+            .map(|(old_commit_oid, new_commit_oid)| {
+                (*old_commit_oid, MaybeZeroOid::NonZero(*new_commit_oid))
+            }),
+    )?;
+    Ok(Ok(()))
+}
+
 fn execute_plans(
     effects: &Effects,
     git_run_info: &GitRunInfo,
@@ -404,12 +614,28 @@ fn execute_plans(
 
         let (effects, progress) = effects.start_operation(OperationType::SyncCommits);
         progress.notify_progress(0, root_commit_and_plans.len());
+        effects.report_step(StepEvent::Planned {
+            operation: "sync",
+            steps: root_commit_and_plans
+                .iter()
+                .map(|(root_commit_oid, _)| root_commit_oid.to_string())
+                .collect(),
+        });
 
         for (root_commit_oid, rebase_plan) in root_commit_and_plans {
             let root_commit = repo.find_commit_or_fail(root_commit_oid)?;
+            effects.report_step(StepEvent::Started {
+                operation: "sync",
+                step: root_commit_oid.to_string(),
+            });
             let rebase_plan = match rebase_plan {
                 Some(rebase_plan) => rebase_plan,
                 None => {
+                    effects.report_step(StepEvent::Finished {
+                        operation: "sync",
+                        step: root_commit_oid.to_string(),
+                        status: "up-to-date".to_string(),
+                    });
                     skipped_commits.push(root_commit);
                     continue;
                 }
@@ -426,9 +652,35 @@ fn execute_plans(
             progress.notify_progress_inc(1);
             match result {
                 ExecuteRebasePlanResult::Succeeded { rewritten_oids: _ } => {
+                    effects.report_step(StepEvent::Finished {
+                        operation: "sync",
+                        step: root_commit_oid.to_string(),
+                        status: "synced".to_string(),
+                    });
                     success_commits.push(root_commit);
                 }
                 ExecuteRebasePlanResult::DeclinedToMerge { failed_merge_info } => {
+                    effects.report_step(match &failed_merge_info {
+                        FailedMergeInfo::Conflict {
+                            commit_oid: _,
+                            conflicting_paths,
+                        } => StepEvent::Conflicted {
+                            operation: "sync",
+                            step: root_commit_oid.to_string(),
+                            paths: conflicting_paths
+                                .iter()
+                                .map(|path| path.to_string_lossy().into_owned())
+                                .sorted()
+                                .collect(),
+                        },
+                        FailedMergeInfo::CannotRebaseMergeInMemory { commit_oid: _ } => {
+                            StepEvent::Finished {
+                                operation: "sync",
+                                step: root_commit_oid.to_string(),
+                                status: "cannot-rebase-merge".to_string(),
+                            }
+                        }
+                    });
                     failed_merge_commits.push((root_commit, failed_merge_info));
                 }
                 ExecuteRebasePlanResult::Failed { exit_code } => {
@@ -0,0 +1,242 @@
+//! Share which commits are visible with other clones of the repository.
+//!
+//! See [`lib::core::sync_state`] for how the state is stored.
+
+use std::collections::HashSet;
+use std::fmt::Write;
+use std::time::SystemTime;
+
+use lib::core::config::{get_sync_state_max_events, get_sync_state_remote};
+use lib::core::dag::Dag;
+use lib::core::effects::Effects;
+use lib::core::eventlog::{Event, EventLogDb, EventReplayer, EventTransactionId};
+use lib::core::formatting::Pluralize;
+use lib::core::gc::mark_commit_reachable;
+use lib::core::repo_ext::RepoExt;
+use lib::core::sync_state::{
+    find_missing_events, get_synced_commit_oids, read_sync_state, write_sync_state, SyncedEvent,
+    SYNC_STATE_REF_NAME,
+};
+use lib::git::{GitRunInfo, GitRunResult, MaybeZeroOid, NonZeroOid, ReferenceName, Repo};
+use lib::try_exit_code;
+use lib::util::{ExitCode, EyreExitOr};
+use tracing::instrument;
+
+/// Use the provided remote, or the configured one if none was provided.
+fn resolve_remote(effects: &Effects, repo: &Repo, remote: Option<String>) -> EyreExitOr<String> {
+    match remote {
+        Some(remote) => Ok(Ok(remote)),
+        None => match get_sync_state_remote(repo)? {
+            Some(remote) => Ok(Ok(remote)),
+            None => {
+                writeln!(
+                    effects.get_error_stream(),
+                    "No remote was provided and `branchless.syncState.remote` is not set."
+                )?;
+                Ok(Err(ExitCode(1)))
+            }
+        },
+    }
+}
+
+/// Fetch the state stored on the remote into the local state reference.
+/// Returns whether the remote had any state.
+#[instrument]
+fn fetch_state(
+    effects: &Effects,
+    git_run_info: &GitRunInfo,
+    repo: &Repo,
+    event_tx_id: EventTransactionId,
+    remote: &str,
+) -> EyreExitOr<bool> {
+    let GitRunResult {
+        exit_code,
+        stdout,
+        stderr: _,
+    } = git_run_info.run_silent(
+        repo,
+        Some(event_tx_id),
+        &["ls-remote", remote, SYNC_STATE_REF_NAME],
+        Default::default(),
+    )?;
+    if !exit_code.is_success() {
+        writeln!(
+            effects.get_error_stream(),
+            "Failed to query remote: {remote}"
+        )?;
+        return Ok(Err(exit_code));
+    }
+    if stdout.is_empty() {
+        return Ok(Ok(false));
+    }
+
+    let refspec = format!("+{SYNC_STATE_REF_NAME}:{SYNC_STATE_REF_NAME}");
+    try_exit_code!(git_run_info.run(effects, Some(event_tx_id), &["fetch", remote, &refspec])?);
+    Ok(Ok(true))
+}
+
+/// Read the events stored at the local state reference, if any.
+#[instrument]
+fn read_local_state(repo: &Repo) -> eyre::Result<Vec<SyncedEvent>> {
+    let state_oid = match repo.find_reference(&ReferenceName::from(SYNC_STATE_REF_NAME))? {
+        Some(reference) => match reference.peel_to_commit()? {
+            Some(commit) => commit.get_oid(),
+            None => eyre::bail!("{SYNC_STATE_REF_NAME} does not point to a commit"),
+        },
+        None => return Ok(Vec::new()),
+    };
+    read_sync_state(repo, state_oid)
+}
+
+/// Add the events from the local state reference which aren't already in the
+/// event log. Returns the number of events added.
+#[instrument]
+fn import_state(
+    repo: &Repo,
+    event_log_db: &EventLogDb,
+    event_tx_id: EventTransactionId,
+) -> eyre::Result<usize> {
+    let synced_events = read_local_state(repo)?;
+    let missing_events =
+        find_missing_events(&event_log_db.get_events()?, synced_events, event_tx_id)?;
+
+    // Keep the pulled commits alive, as would have happened if they had been
+    // created locally.
+    for event in &missing_events {
+        match event {
+            Event::CommitEvent { commit_oid, .. } | Event::UnobsoleteEvent { commit_oid, .. } => {
+                mark_commit_reachable(repo, *commit_oid)?;
+            }
+            Event::RewriteEvent {
+                new_commit_oid: MaybeZeroOid::NonZero(commit_oid),
+                ..
+            } => {
+                mark_commit_reachable(repo, *commit_oid)?;
+            }
+            _ => {}
+        }
+    }
+
+    let num_events = missing_events.len();
+    event_log_db.add_events(missing_events)?;
+    Ok(num_events)
+}
+
+/// Pull the state from the remote.
+#[instrument]
+pub fn pull(
+    effects: &Effects,
+    git_run_info: &GitRunInfo,
+    remote: Option<String>,
+) -> EyreExitOr<()> {
+    let repo = Repo::from_current_dir()?;
+    let remote = try_exit_code!(resolve_remote(effects, &repo, remote)?);
+    let conn = repo.get_db_conn()?;
+    let event_log_db = EventLogDb::new(&conn)?;
+    let event_tx_id = event_log_db.make_transaction_id(SystemTime::now(), "sync-state pull")?;
+
+    if !try_exit_code!(fetch_state(
+        effects,
+        git_run_info,
+        &repo,
+        event_tx_id,
+        &remote
+    )?) {
+        writeln!(
+            effects.get_output_stream(),
+            "No synced state found on remote: {remote}"
+        )?;
+        return Ok(Ok(()));
+    }
+    let num_events = import_state(&repo, &event_log_db, event_tx_id)?;
+    writeln!(
+        effects.get_output_stream(),
+        "Pulled {} from {remote}.",
+        Pluralize {
+            determiner: None,
+            amount: num_events,
+            unit: ("new event", "new events"),
+        }
+    )?;
+    Ok(Ok(()))
+}
+
+/// Push the state to the remote. Any state already on the remote is pulled
+/// first, so that it's not lost.
+#[instrument]
+pub fn push(
+    effects: &Effects,
+    git_run_info: &GitRunInfo,
+    remote: Option<String>,
+) -> EyreExitOr<()> {
+    let repo = Repo::from_current_dir()?;
+    let remote = try_exit_code!(resolve_remote(effects, &repo, remote)?);
+    let conn = repo.get_db_conn()?;
+    let event_log_db = EventLogDb::new(&conn)?;
+    let event_tx_id = event_log_db.make_transaction_id(SystemTime::now(), "sync-state push")?;
+
+    if try_exit_code!(fetch_state(
+        effects,
+        git_run_info,
+        &repo,
+        event_tx_id,
+        &remote
+    )?) {
+        import_state(&repo, &event_log_db, event_tx_id)?;
+    }
+
+    let references_snapshot = repo.get_references_snapshot()?;
+    let event_replayer = EventReplayer::from_event_log_db(effects, &repo, &event_log_db)?;
+    let event_cursor = event_replayer.make_default_cursor();
+    let dag = Dag::open_and_sync(
+        effects,
+        &repo,
+        &event_replayer,
+        event_cursor,
+        &references_snapshot,
+    )?;
+    // Secret commits must never be pushed.
+    let draft_commits = dag
+        .query_visible_commits_slow()?
+        .intersection(dag.query_draft_commits()?)
+        .difference(&dag.query_secret_commits()?);
+    let heads = dag.commit_set_to_vec(&dag.query_heads(draft_commits.clone())?)?;
+
+    // Hidden commits are only mentioned if they were synced before, so that
+    // the other clones can hide them too.
+    let secret_commit_oids: HashSet<NonZeroOid> = dag
+        .commit_set_to_vec(&dag.query_secret_commits()?)?
+        .into_iter()
+        .collect();
+    let commit_oids: HashSet<NonZeroOid> = get_synced_commit_oids(&read_local_state(&repo)?)?
+        .into_iter()
+        .chain(dag.commit_set_to_vec(&draft_commits)?)
+        .filter(|commit_oid| !secret_commit_oids.contains(commit_oid))
+        .collect();
+    let state_oid = write_sync_state(
+        &repo,
+        &event_log_db.get_events()?,
+        &commit_oids,
+        heads,
+        get_sync_state_max_events(&repo)?,
+    )?;
+    repo.create_reference(
+        &ReferenceName::from(SYNC_STATE_REF_NAME),
+        state_oid,
+        true,
+        "sync-state push",
+    )?;
+    let refspec = format!("+{SYNC_STATE_REF_NAME}:{SYNC_STATE_REF_NAME}");
+    try_exit_code!(git_run_info.run(effects, Some(event_tx_id), &["push", &remote, &refspec])?);
+
+    writeln!(
+        effects.get_output_stream(),
+        "Pushed state for {} to {remote}.",
+        Pluralize {
+            determiner: None,
+            amount: dag.set_count(&draft_commits)?,
+            unit: ("draft commit", "draft commits"),
+        }
+    )?;
+    Ok(Ok(()))
+}
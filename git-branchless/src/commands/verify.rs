@@ -0,0 +1,45 @@
+//! Check the event log for consistency with the Git repository.
+//!
+//! The event log can fall out of sync with the repository when commits are
+//! deleted behind git-branchless's back, such as by `git gc --prune=now`, or
+//! when events are recorded by a buggy or interrupted operation. This command
+//! only reports the inconsistencies; `git branchless repair` runs the same
+//! checks and resolves them.
+
+use std::fmt::Write;
+
+use lib::core::effects::Effects;
+use lib::core::eventlog::{EventLogDb, EventReplayer};
+use lib::git::Repo;
+use lib::util::{ExitCode, EyreExitOr};
+use tracing::instrument;
+
+use super::repair::find_inconsistencies;
+
+/// Check the event log for inconsistencies with the repository. Exits with a
+/// non-zero code if any are found which can be repaired.
+#[instrument]
+pub fn verify(effects: &Effects) -> EyreExitOr<()> {
+    let repo = Repo::from_current_dir()?;
+    let conn = repo.get_db_conn()?;
+    let event_log_db = EventLogDb::new(&conn)?;
+    let event_replayer = EventReplayer::from_event_log_db(effects, &repo, &event_log_db)?;
+    let event_cursor = event_replayer.make_default_cursor();
+
+    let inconsistencies =
+        find_inconsistencies(effects, &repo, &event_log_db, &event_replayer, event_cursor)?;
+    inconsistencies.describe(effects, "Found")?;
+
+    if !inconsistencies.is_repairable() {
+        writeln!(
+            effects.get_output_stream(),
+            "The event log is consistent with the repository."
+        )?;
+        return Ok(Ok(()));
+    }
+    writeln!(
+        effects.get_output_stream(),
+        "To repair these inconsistencies, run: git branchless repair --no-dry-run"
+    )?;
+    Ok(Err(ExitCode(1)))
+}
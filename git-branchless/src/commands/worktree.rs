@@ -0,0 +1,258 @@
+//! Check out stacks in their own linked worktrees.
+//!
+//! This makes it possible to work on several stacks in parallel without
+//! switching back and forth in a single working copy. The worktree is created
+//! at the tip of the stack with a detached `HEAD`, and the checkout is recorded
+//! in the event log as part of a `worktree` operation.
+
+use std::fmt::Write;
+use std::path::{Path, PathBuf};
+use std::time::SystemTime;
+
+use lib::core::dag::{union_all, CommitSet, Dag};
+use lib::core::effects::Effects;
+use lib::core::eventlog::{EventLogDb, EventReplayer};
+use lib::core::repo_ext::RepoExt;
+use lib::git::{GitRunInfo, GitRunResult, NonZeroOid, Repo};
+use lib::try_exit_code;
+use lib::util::{ExitCode, EyreExitOr};
+use tracing::instrument;
+
+use git_branchless_opts::{ResolveRevsetOptions, Revset};
+use git_branchless_revset::resolve_commits;
+
+/// A linked worktree, as listed by `git worktree list --porcelain`.
+#[derive(Debug)]
+struct LinkedWorktree {
+    path: PathBuf,
+    head_oid: Option<NonZeroOid>,
+}
+
+/// Parse the output of `git worktree list --porcelain`, skipping the main
+/// worktree, which is always listed first.
+fn parse_linked_worktrees(output: &str) -> Vec<LinkedWorktree> {
+    let mut result = Vec::new();
+    for block in output.split("\n\n").skip(1) {
+        let mut path = None;
+        let mut head_oid = None;
+        for line in block.lines() {
+            if let Some(value) = line.strip_prefix("worktree ") {
+                path = Some(PathBuf::from(value));
+            } else if let Some(value) = line.strip_prefix("HEAD ") {
+                head_oid = value.parse().ok();
+            }
+        }
+        if let Some(path) = path {
+            result.push(LinkedWorktree { path, head_oid });
+        }
+    }
+    result
+}
+
+/// The path to create the worktree for the given commit at if none was
+/// provided: a sibling of the main working copy, named after it and the
+/// commit.
+fn default_worktree_path(repo: &Repo, commit_oid: NonZeroOid) -> eyre::Result<PathBuf> {
+    let main_repo = match repo.open_worktree_parent_repo()? {
+        Some(main_repo) => main_repo,
+        None => repo.try_clone()?,
+    };
+    let working_copy_path = match main_repo.get_working_copy_path() {
+        Some(working_copy_path) => working_copy_path,
+        None => eyre::bail!("The repository has no working copy to create a worktree next to"),
+    };
+    let name = match working_copy_path.file_name() {
+        Some(name) => name.to_string_lossy().into_owned(),
+        None => "worktree".to_string(),
+    };
+    let short_oid = repo.find_commit_or_fail(commit_oid)?.get_short_oid()?;
+    let parent_path = working_copy_path.parent().unwrap_or_else(|| Path::new("."));
+    Ok(parent_path.join(format!("{name}-{short_oid}")))
+}
+
+/// Create a linked worktree checked out at the tip of the stack containing the
+/// given commits, or with `remove`, remove the linked worktrees checked out to
+/// any commit in that stack.
+#[instrument]
+pub fn worktree(
+    effects: &Effects,
+    git_run_info: &GitRunInfo,
+    revset: Revset,
+    resolve_revset_options: &ResolveRevsetOptions,
+    path: Option<PathBuf>,
+    remove: bool,
+) -> EyreExitOr<()> {
+    let glyphs = effects.get_glyphs();
+    let repo = Repo::from_current_dir()?;
+    let references_snapshot = repo.get_references_snapshot()?;
+    let conn = repo.get_db_conn()?;
+    let event_log_db = EventLogDb::new(&conn)?;
+    let event_replayer = EventReplayer::from_event_log_db(effects, &repo, &event_log_db)?;
+    let event_cursor = event_replayer.make_default_cursor();
+    let mut dag = Dag::open_and_sync(
+        effects,
+        &repo,
+        &event_replayer,
+        event_cursor,
+        &references_snapshot,
+    )?;
+
+    let stack_revset = Revset(format!("stack({})", revset.0));
+    let stack = match resolve_commits(
+        effects,
+        &repo,
+        &mut dag,
+        &[stack_revset],
+        resolve_revset_options,
+    ) {
+        Ok(commit_sets) => union_all(&commit_sets),
+        Err(err) => {
+            err.describe(effects)?;
+            return Ok(Err(ExitCode(1)));
+        }
+    };
+
+    if remove {
+        return remove_worktrees(
+            effects,
+            git_run_info,
+            &repo,
+            &event_log_db,
+            &dag,
+            &stack,
+            &revset,
+            path,
+        );
+    }
+
+    let tip_oid = match dag.commit_set_to_vec(&dag.query_heads(stack)?)?.as_slice() {
+        [] => {
+            writeln!(
+                effects.get_output_stream(),
+                "There is no stack to check out in: {}",
+                revset.0
+            )?;
+            return Ok(Err(ExitCode(1)));
+        }
+        [tip_oid] => *tip_oid,
+        tip_oids => {
+            writeln!(
+                effects.get_output_stream(),
+                "The stack to check out must have a single head, but it has {}.",
+                tip_oids.len()
+            )?;
+            return Ok(Err(ExitCode(1)));
+        }
+    };
+
+    let path = match path {
+        Some(path) => path,
+        None => default_worktree_path(&repo, tip_oid)?,
+    };
+    let path_str = match path.to_str() {
+        Some(path_str) => path_str,
+        None => eyre::bail!("Worktree path is not valid UTF-8: {path:?}"),
+    };
+
+    let tip_oid_str = tip_oid.to_string();
+    let event_tx_id = event_log_db.make_transaction_id(SystemTime::now(), "worktree")?;
+    try_exit_code!(git_run_info.run(
+        effects,
+        Some(event_tx_id),
+        &["worktree", "add", "--detach", path_str, &tip_oid_str],
+    )?);
+
+    writeln!(
+        effects.get_output_stream(),
+        "Created worktree at {} for {}",
+        path.display(),
+        glyphs.render(repo.friendly_describe_commit_from_oid(glyphs, tip_oid)?)?,
+    )?;
+    Ok(Ok(()))
+}
+
+fn remove_worktrees(
+    effects: &Effects,
+    git_run_info: &GitRunInfo,
+    repo: &Repo,
+    event_log_db: &EventLogDb,
+    dag: &Dag,
+    stack: &CommitSet,
+    revset: &Revset,
+    path: Option<PathBuf>,
+) -> EyreExitOr<()> {
+    let GitRunResult {
+        exit_code,
+        stdout,
+        stderr: _,
+    } = git_run_info.run_silent(
+        repo,
+        None,
+        &["worktree", "list", "--porcelain"],
+        Default::default(),
+    )?;
+    if !exit_code.is_success() {
+        writeln!(effects.get_error_stream(), "Could not list worktrees.")?;
+        return Ok(Err(exit_code));
+    }
+
+    let path = match path {
+        Some(path) => match path.canonicalize() {
+            Ok(path) => Some(path),
+            Err(_) => {
+                writeln!(
+                    effects.get_output_stream(),
+                    "There is no worktree at: {}",
+                    path.display()
+                )?;
+                return Ok(Err(ExitCode(1)));
+            }
+        },
+        None => None,
+    };
+    let mut worktree_paths = Vec::new();
+    for linked_worktree in parse_linked_worktrees(&String::from_utf8_lossy(&stdout)) {
+        let LinkedWorktree {
+            path: worktree_path,
+            head_oid,
+        } = linked_worktree;
+        let is_match = match &path {
+            Some(path) => worktree_path.canonicalize().ok().as_ref() == Some(path),
+            None => match head_oid {
+                Some(head_oid) => dag.set_contains(stack, head_oid)?,
+                None => false,
+            },
+        };
+        if is_match {
+            worktree_paths.push(worktree_path);
+        }
+    }
+
+    if worktree_paths.is_empty() {
+        writeln!(
+            effects.get_output_stream(),
+            "There are no worktrees to remove for: {}",
+            revset.0
+        )?;
+        return Ok(Err(ExitCode(1)));
+    }
+
+    let event_tx_id = event_log_db.make_transaction_id(SystemTime::now(), "worktree remove")?;
+    for worktree_path in worktree_paths {
+        let worktree_path_str = match worktree_path.to_str() {
+            Some(worktree_path_str) => worktree_path_str,
+            None => eyre::bail!("Worktree path is not valid UTF-8: {worktree_path:?}"),
+        };
+        try_exit_code!(git_run_info.run(
+            effects,
+            Some(event_tx_id),
+            &["worktree", "remove", worktree_path_str],
+        )?);
+        writeln!(
+            effects.get_output_stream(),
+            "Removed worktree at {}",
+            worktree_path.display()
+        )?;
+    }
+    Ok(Ok(()))
+}
@@ -2,13 +2,16 @@
 //! processing.
 
 use std::convert::TryInto;
+use std::io::stdin;
 use std::process::Command;
 use std::time::SystemTime;
 
 use eyre::Context;
 use itertools::Itertools;
 
+use lib::core::effects::Effects;
 use lib::core::eventlog::{EventLogDb, EventTransactionId, BRANCHLESS_TRANSACTION_ID_ENV_VAR};
+use lib::core::rewrite::rewrite_hooks::prompt_restack_abandoned;
 use lib::git::{GitRunInfo, Repo};
 use lib::util::{ExitCode, EyreExitOr};
 
@@ -59,15 +62,31 @@ fn make_event_tx_id<S: AsRef<str> + std::fmt::Debug>(
     let repo = Repo::from_current_dir()?;
     let conn = repo.get_db_conn()?;
     let event_log_db = EventLogDb::new(&conn)?;
+    // If this command is itself being run inside a transaction, then the
+    // events are added to that transaction, which keeps its own label.
+    let is_nested = std::env::var_os(BRANCHLESS_TRANSACTION_ID_ENV_VAR).is_some();
     let event_tx_id = {
         let message = args.first().map(|s| s.as_ref()).unwrap_or("wrap");
         event_log_db.make_transaction_id(now, message)?
     };
+    if !is_nested {
+        let label = std::iter::once("git")
+            .chain(args.iter().map(AsRef::as_ref))
+            .join(" ");
+        event_log_db.set_transaction_label(event_tx_id, label)?;
+    }
     Ok(event_tx_id)
 }
 
-/// Run the provided Git command, but wrapped in an event transaction.
+/// Run the provided Git command, but wrapped in an event transaction. All
+/// events generated by the command (including by any Git hooks it invokes)
+/// are recorded in the same transaction, so that they can be undone together
+/// with `git undo`.
+///
+/// If run at a terminal, then afterwards, offer to restack any commits which
+/// were abandoned by the command.
 pub fn wrap<S: AsRef<str> + std::fmt::Debug>(
+    effects: &Effects,
     git_run_info: &GitRunInfo,
     args: &[S],
 ) -> EyreExitOr<()> {
@@ -76,5 +95,18 @@ pub fn wrap<S: AsRef<str> + std::fmt::Debug>(
     let event_tx_id = make_event_tx_id(args).ok();
 
     let exit_code = pass_through_git_command(git_run_info, args, event_tx_id)?;
+    match event_tx_id {
+        Some(event_tx_id) if exit_code.is_ok() && console::user_attended() => {
+            let repo = Repo::from_current_dir()?;
+            prompt_restack_abandoned(
+                effects,
+                git_run_info,
+                &repo,
+                event_tx_id,
+                &mut stdin().lock(),
+            )?;
+        }
+        Some(_) | None => {}
+    }
     Ok(exit_code)
 }
@@ -1,3 +1,4 @@
+use lib::testing::pty::{run_in_pty, PtyAction};
 use lib::testing::{make_git, remove_rebase_lines, trim_lines, GitRunOptions};
 
 #[test]
@@ -1245,3 +1246,262 @@ fn test_amend_merge_commit() -> eyre::Result<()> {
 
     Ok(())
 }
+
+#[test]
+fn test_amend_post_rewrite_hook_command() -> eyre::Result<()> {
+    let git = make_git()?;
+
+    if !git.supports_reference_transactions()? {
+        return Ok(());
+    }
+
+    git.init_repo()?;
+    git.detach_head()?;
+    git.commit_file("test1", 1)?;
+    git.commit_file("test2", 2)?;
+    git.run(&[
+        "config",
+        "branchless.hooks.postRewrite",
+        // Strip the nondeterministic transaction ID.
+        r#"sed 's/"event_tx_id":[0-9]*,//' >.git/post-rewrite.json"#,
+    ])?;
+
+    git.write_file_txt("test2", "updated contents")?;
+    git.branchless("amend", &[])?;
+
+    let payload = std::fs::read_to_string(git.repo_path.join(".git").join("post-rewrite.json"))?;
+    insta::assert_snapshot!(payload, @r###"{"rewrite_type":"amend","rewritten_oids":[{"new_oid":"7ac317b9d1dd1bbdf46e8ee692b9b9e280f28a50","old_oid":"96d1c37a3d4363611c49f7e52186e189a04c531f"}]}"###);
+
+    Ok(())
+}
+
+#[test]
+fn test_amend_post_rewrite_hook_command_large_output() -> eyre::Result<()> {
+    let git = make_git()?;
+
+    if !git.supports_reference_transactions()? {
+        return Ok(());
+    }
+
+    git.init_repo()?;
+    git.detach_head()?;
+    git.commit_file("test1", 1)?;
+    // Print more than fits in a pipe buffer without reading the payload.
+    git.run(&["config", "branchless.hooks.postRewrite", "seq 100000 >&2"])?;
+
+    git.write_file_txt("test1", "updated contents")?;
+    {
+        let (_stdout, stderr) = git.branchless("amend", &[])?;
+        assert!(stderr.lines().any(|line| line == "100000"));
+    }
+
+    Ok(())
+}
+
+#[test]
+#[cfg(unix)]
+fn test_amend_symlink() -> eyre::Result<()> {
+    use std::{fs, os::unix::fs::symlink};
+
+    let git = make_git()?;
+
+    git.init_repo()?;
+    git.detach_head()?;
+    git.commit_file("test1", 1)?;
+
+    let link_path = git.repo_path.join("link.txt");
+    symlink("test1.txt", &link_path)?;
+    git.run(&["add", "link.txt"])?;
+    git.run(&["commit", "-m", "add link"])?;
+
+    // The blob for a symlink should contain its target, rather than the
+    // contents of the file that it points to.
+    fs::remove_file(&link_path)?;
+    symlink("initial.txt", &link_path)?;
+    git.branchless("amend", &[])?;
+    {
+        let (stdout, _stderr) = git.run(&["cat-file", "-p", "HEAD:link.txt"])?;
+        assert_eq!(stdout, "initial.txt");
+    }
+    {
+        let (stdout, _stderr) = git.run(&["status", "--porcelain"])?;
+        assert_eq!(stdout, "");
+    }
+
+    Ok(())
+}
+
+#[test]
+fn test_amend_long_path() -> eyre::Result<()> {
+    let git = make_git()?;
+
+    git.init_repo()?;
+    git.detach_head()?;
+    git.commit_file("test1", 1)?;
+
+    // Longer than `MAX_PATH` (260 characters), which Git only supports on
+    // Windows if `core.longpaths` is set.
+    git.run(&["config", "core.longpaths", "true"])?;
+    let dir_path: std::path::PathBuf = std::iter::repeat("a".repeat(50)).take(6).collect();
+    let file_path = git.repo_path.join(dir_path).join("file.txt");
+    std::fs::create_dir_all(file_path.parent().unwrap())?;
+    std::fs::write(&file_path, "contents\n")?;
+    git.run(&["add", "."])?;
+    git.run(&["commit", "-m", "add long path"])?;
+
+    std::fs::write(&file_path, "new contents\n")?;
+    git.branchless("amend", &[])?;
+    {
+        let (stdout, _stderr) = git.run(&["status", "--porcelain"])?;
+        assert_eq!(stdout, "");
+    }
+
+    git.branchless("prev", &[])?;
+    assert!(!file_path.exists());
+    git.branchless("next", &[])?;
+    assert_eq!(std::fs::read_to_string(&file_path)?, "new contents\n");
+
+    Ok(())
+}
+
+#[test]
+fn test_amend_unpopulated_submodule() -> eyre::Result<()> {
+    let git = make_git()?;
+
+    git.init_repo()?;
+    git.detach_head()?;
+    let test1_oid = git.commit_file("test1", 1)?;
+
+    // Stage a gitlink entry without cloning an actual submodule. The empty
+    // directory stands in for an unpopulated submodule.
+    std::fs::create_dir(git.repo_path.join("submodule"))?;
+    git.run(&[
+        "update-index",
+        "--add",
+        "--cacheinfo",
+        &format!("160000,{test1_oid},submodule"),
+    ])?;
+    git.branchless("amend", &[])?;
+    {
+        let (stdout, _stderr) = git.run(&["ls-tree", "HEAD", "submodule"])?;
+        assert_eq!(stdout, format!("160000 commit {test1_oid}\tsubmodule\n"));
+    }
+
+    Ok(())
+}
+
+#[test]
+fn test_amend_to_ancestor() -> eyre::Result<()> {
+    let git = make_git()?;
+
+    if !git.supports_reference_transactions()? {
+        return Ok(());
+    }
+
+    git.init_repo()?;
+    git.detach_head()?;
+    git.commit_file("test1", 1)?;
+    git.commit_file("test2", 2)?;
+
+    git.write_file_txt("test1", "updated contents")?;
+    git.branchless("amend", &["--to", "HEAD~"])?;
+
+    {
+        let (stdout, _stderr) = git.run(&["status", "--short"])?;
+        assert_eq!(stdout, "");
+    }
+    {
+        let (stdout, _stderr) = git.run(&["show", "HEAD~:test1.txt"])?;
+        assert_eq!(stdout, "updated contents");
+    }
+    {
+        let (stdout, _stderr) = git.run(&["log", "--format=%s", "master..HEAD"])?;
+        insta::assert_snapshot!(stdout, @r###"
+        create test2.txt
+        create test1.txt
+        "###);
+    }
+
+    Ok(())
+}
+
+#[test]
+fn test_amend_to_ancestor_conflict() -> eyre::Result<()> {
+    let git = make_git()?;
+
+    if !git.supports_reference_transactions()? {
+        return Ok(());
+    }
+
+    git.init_repo()?;
+    git.detach_head()?;
+    git.commit_file_with_contents("test1", 1, "1\n")?;
+    git.commit_file_with_contents("test1", 2, "2\n")?;
+    git.commit_file_with_contents("test1", 3, "1\n")?;
+    let (original_log, _stderr) = git.run(&["log", "--format=%H", "master..HEAD"])?;
+
+    // The changes apply cleanly to the first commit, but the second commit
+    // can't be restacked on top of it.
+    git.write_file_txt("test1", "3\n")?;
+    {
+        let (stdout, _stderr) = git.branchless_with_options(
+            "amend",
+            &["--to", "HEAD~2"],
+            &GitRunOptions {
+                expected_exit_code: 1,
+                ..Default::default()
+            },
+        )?;
+        assert!(stdout.contains("The changes were left in the working copy."));
+    }
+
+    {
+        let (stdout, _stderr) = git.run(&["log", "--format=%H", "master..HEAD"])?;
+        assert_eq!(stdout, original_log);
+    }
+    {
+        let (stdout, _stderr) = git.run(&["status", "--short"])?;
+        assert_eq!(stdout, " M test1.txt\n");
+    }
+
+    Ok(())
+}
+
+#[test]
+fn test_amend_patch() -> eyre::Result<()> {
+    let git = make_git()?;
+
+    if !git.supports_reference_transactions()? {
+        return Ok(());
+    }
+
+    git.init_repo()?;
+    git.detach_head()?;
+    git.commit_file("test1", 1)?;
+    git.commit_file("test2", 2)?;
+
+    git.write_file_txt("test1", "new test1 contents\n")?;
+    git.write_file_txt("test2", "new test2 contents\n")?;
+    run_in_pty(
+        &git,
+        "amend",
+        &["--patch"],
+        &[
+            PtyAction::WaitUntilContains("test1.txt"),
+            PtyAction::Write(" "), // select the first file
+            PtyAction::WaitUntilContains("(×)"),
+            PtyAction::Write("c"),
+        ],
+    )?;
+
+    {
+        let (stdout, _stderr) = git.run(&["status", "--short"])?;
+        assert_eq!(stdout, " M test2.txt\n");
+    }
+    {
+        let (stdout, _stderr) = git.run(&["show", "HEAD:test1.txt"])?;
+        assert_eq!(stdout, "new test1 contents\n");
+    }
+
+    Ok(())
+}
@@ -136,3 +136,22 @@ fn test_sparse_checkout() -> eyre::Result<()> {
 
     Ok(())
 }
+
+#[test]
+fn test_invalid_style_config() -> eyre::Result<()> {
+    let git = make_git()?;
+
+    git.init_repo()?;
+    git.run(&["config", "branchless.style.theme", "bogus"])?;
+
+    {
+        let (stdout, stderr) = git.branchless("smartlog", &[])?;
+        insta::assert_snapshot!(stderr, @r###"
+        Warning: Unknown theme for `branchless.style.theme`: "bogus"; using the default style instead.
+        "###);
+        insta::assert_snapshot!(stdout, @"@ f777ecc (> master) create initial.txt
+");
+    }
+
+    Ok(())
+}
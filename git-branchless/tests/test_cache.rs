@@ -0,0 +1,103 @@
+use lib::testing::{make_git, make_git_with_remote_repo, GitInitOptions, GitWrapperWithRemoteRepo};
+
+#[test]
+fn test_cache_clear_and_rebuild() -> eyre::Result<()> {
+    let git = make_git()?;
+    git.init_repo()?;
+    git.commit_file("test1", 1)?;
+    git.commit_file("test2", 2)?;
+
+    {
+        let (stdout, _stderr) = git.branchless("cache", &["rebuild"])?;
+        insta::assert_snapshot!(stdout, @r###"
+        Rebuilt the commit graph cache with 3 commits.
+        "###);
+    }
+
+    {
+        let (stdout, _stderr) = git.branchless("cache", &["stats"])?;
+        assert!(stdout.contains("Contains: 3 commits"));
+        assert!(stdout
+            .contains("Main branch when last synced: 96d1c37a3d4363611c49f7e52186e189a04c531f"));
+    }
+
+    {
+        let (stdout, _stderr) = git.branchless("cache", &["clear"])?;
+        insta::assert_snapshot!(stdout, @r###"
+        Cleared the commit graph cache. It will be rebuilt when next needed.
+        "###);
+    }
+
+    {
+        let stdout = git.smartlog()?;
+        insta::assert_snapshot!(stdout, @r###"
+        :
+        @ 96d1c37 (> master) create test2.txt
+        "###);
+    }
+
+    Ok(())
+}
+
+#[test]
+fn test_cache_kept_when_main_branch_rewritten_locally() -> eyre::Result<()> {
+    let git = make_git()?;
+    git.init_repo()?;
+    git.commit_file("test1", 1)?;
+    git.smartlog()?;
+
+    let sentinel_path = git.get_repo()?.get_dag_dir()?.join("sentinel");
+    std::fs::write(&sentinel_path, "")?;
+
+    git.run(&["checkout", "--detach", "master^"])?;
+    git.commit_file("test2", 2)?;
+    git.run(&["branch", "-f", "master", "HEAD"])?;
+    git.smartlog()?;
+
+    let (main_branch_oid, _stderr) = git.run(&["rev-parse", "master"])?;
+    let (stdout, _stderr) = git.branchless("cache", &["stats"])?;
+    assert!(stdout.contains(&format!(
+        "Main branch when last synced: {}",
+        main_branch_oid.trim()
+    )));
+    assert!(sentinel_path.exists());
+
+    Ok(())
+}
+
+#[test]
+fn test_cache_invalidated_when_main_branch_rewritten_upstream() -> eyre::Result<()> {
+    let GitWrapperWithRemoteRepo {
+        temp_dir: _guard,
+        original_repo,
+        cloned_repo,
+    } = make_git_with_remote_repo()?;
+
+    original_repo.init_repo()?;
+    original_repo.commit_file("test1", 1)?;
+    original_repo.commit_file("test2", 2)?;
+
+    original_repo.clone_repo_into(&cloned_repo, &["--branch", "master"])?;
+    cloned_repo.init_repo_with_options(&GitInitOptions {
+        make_initial_commit: false,
+        ..Default::default()
+    })?;
+    cloned_repo.smartlog()?;
+
+    let sentinel_path = cloned_repo.get_repo()?.get_dag_dir()?.join("sentinel");
+    std::fs::write(&sentinel_path, "")?;
+
+    original_repo.run(&["reset", "--hard", "HEAD^"])?;
+    original_repo.commit_file("test3", 3)?;
+    cloned_repo.run(&["fetch"])?;
+    cloned_repo.run(&["reset", "--hard", "origin/master"])?;
+    cloned_repo.smartlog()?;
+    assert!(!sentinel_path.exists());
+
+    // The cache is only rebuilt once for each rewrite.
+    std::fs::write(&sentinel_path, "")?;
+    cloned_repo.smartlog()?;
+    assert!(sentinel_path.exists());
+
+    Ok(())
+}
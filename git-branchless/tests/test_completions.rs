@@ -0,0 +1,50 @@
+use lib::testing::make_git;
+
+#[test]
+fn test_completions_scripts() -> eyre::Result<()> {
+    let git = make_git()?;
+    git.init_repo()?;
+
+    {
+        let (stdout, _stderr) = git.branchless("completions", &["bash"])?;
+        assert!(stdout.contains("_git-branchless()"));
+        assert!(stdout.contains("_git_branchless() {"));
+        assert!(stdout.contains("_git_hide() { __git_branchless_complete_commits; }"));
+    }
+
+    {
+        let (stdout, _stderr) = git.branchless("completions", &["zsh"])?;
+        assert!(stdout.contains("#compdef git-branchless"));
+        assert!(stdout.contains("_git-hide() { __git_branchless_commits }"));
+    }
+
+    {
+        let (stdout, _stderr) = git.branchless("completions", &["fish"])?;
+        assert!(stdout.contains("complete -c git-branchless"));
+        assert!(stdout.contains("complete -c git -n '__fish_seen_subcommand_from hide' -f -a '(git branchless completions --list-commits 2>/dev/null)'"));
+    }
+
+    Ok(())
+}
+
+#[test]
+fn test_completions_list_commits() -> eyre::Result<()> {
+    let git = make_git()?;
+    git.init_repo()?;
+    git.detach_head()?;
+    git.commit_file("test1", 1)?;
+    git.run(&["branch", "foo"])?;
+    git.commit_file("test2", 2)?;
+
+    {
+        let (stdout, _stderr) = git.branchless("completions", &["--list-commits"])?;
+        insta::assert_snapshot!(stdout, @r###"
+        foo
+        master
+        62fc20d
+        96d1c37
+        "###);
+    }
+
+    Ok(())
+}
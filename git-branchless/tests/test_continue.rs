@@ -0,0 +1,218 @@
+use std::collections::HashMap;
+use std::str::FromStr;
+use std::time::SystemTime;
+
+use lib::core::eventlog::EventLogDb;
+use lib::core::gc::mark_commit_reachable;
+use lib::core::rewrite::{InterruptedRebase, InterruptedRebaseDb, InterruptedRebaseStage};
+use lib::git::{MaybeZeroOid, NonZeroOid, ReferenceName};
+use lib::testing::{make_git, Git, GitRunOptions};
+
+/// Simulate an in-memory rebase which rewrote `test2` and was interrupted
+/// before it moved any branches. Returns the OIDs of the original and
+/// rewritten commits.
+fn make_interrupted_rebase(git: &Git) -> eyre::Result<(NonZeroOid, NonZeroOid)> {
+    let test1_oid = git.commit_file("test1", 1)?;
+    git.detach_head()?;
+    let test2_oid = git.commit_file("test2", 2)?;
+    git.run(&["branch", "foo"])?;
+    let (stdout, _stderr) = git.run(&[
+        "commit-tree",
+        "-p",
+        &test1_oid.to_string(),
+        "-m",
+        "amended test2",
+        "HEAD^{tree}",
+    ])?;
+    let new_test2_oid = NonZeroOid::from_str(stdout.trim())?;
+
+    let repo = git.get_repo()?;
+    let conn = repo.get_db_conn()?;
+    let event_log_db = EventLogDb::new(&conn)?;
+    let event_tx_id = event_log_db.make_transaction_id(SystemTime::now(), "move")?;
+    mark_commit_reachable(&repo, new_test2_oid)?;
+    InterruptedRebaseDb::new(&conn)?.save(&InterruptedRebase {
+        event_tx_id,
+        rebase_plan: None,
+        preserve_timestamps: false,
+        head_info: repo.get_head_info()?,
+        skipped_head_updated_oid: None,
+        rewritten_oids: HashMap::from([(test2_oid, MaybeZeroOid::NonZero(new_test2_oid))]),
+        original_branches: HashMap::from([(ReferenceName::from("refs/heads/foo"), test2_oid)]),
+        stage: InterruptedRebaseStage::MoveBranches,
+    })?;
+
+    Ok((test2_oid, new_test2_oid))
+}
+
+#[test]
+fn test_continue_no_operation_in_progress() -> eyre::Result<()> {
+    let git = make_git()?;
+    git.init_repo()?;
+
+    for subcommand in ["continue", "abort"] {
+        let (stdout, _stderr) = git.branchless_with_options(
+            subcommand,
+            &[],
+            &GitRunOptions {
+                expected_exit_code: 1,
+                ..Default::default()
+            },
+        )?;
+        insta::assert_snapshot!(stdout, @"No operation in progress.
+");
+    }
+
+    Ok(())
+}
+
+#[test]
+fn test_continue_after_merge_conflict() -> eyre::Result<()> {
+    let git = make_git()?;
+
+    if !git.supports_committer_date_is_author_date()? {
+        return Ok(());
+    }
+    git.init_repo()?;
+
+    let base_oid = git.commit_file("test1", 1)?;
+    git.detach_head()?;
+    let other_oid = git.commit_file_with_contents("conflict", 2, "conflict 1\n")?;
+    git.run(&["checkout", &base_oid.to_string()])?;
+    git.commit_file_with_contents("conflict", 2, "conflict 2\n")?;
+
+    git.branchless_with_options(
+        "move",
+        &["--merge", "--source", &other_oid.to_string()],
+        &GitRunOptions {
+            expected_exit_code: 1,
+            ..Default::default()
+        },
+    )?;
+    assert_eq!(git.get_repo()?.get_current_operation_type(), Some("rebase"));
+
+    git.resolve_file("conflict", "resolved")?;
+    git.branchless("continue", &[])?;
+    assert_eq!(git.get_repo()?.get_current_operation_type(), None);
+
+    {
+        let stdout = git.smartlog()?;
+        insta::assert_snapshot!(stdout, @r###"
+        :
+        O 62fc20d (master) create test1.txt
+        |
+        @ 202143f create conflict.txt
+        |
+        o 42951b5 create conflict.txt
+        "###);
+    }
+
+    Ok(())
+}
+
+#[test]
+fn test_abort_after_merge_conflict() -> eyre::Result<()> {
+    let git = make_git()?;
+
+    if !git.supports_committer_date_is_author_date()? {
+        return Ok(());
+    }
+    git.init_repo()?;
+
+    let base_oid = git.commit_file("test1", 1)?;
+    git.detach_head()?;
+    let other_oid = git.commit_file_with_contents("conflict", 2, "conflict 1\n")?;
+    git.run(&["checkout", &base_oid.to_string()])?;
+    let head_oid = git.commit_file_with_contents("conflict", 2, "conflict 2\n")?;
+
+    git.branchless_with_options(
+        "move",
+        &["--merge", "--source", &other_oid.to_string()],
+        &GitRunOptions {
+            expected_exit_code: 1,
+            ..Default::default()
+        },
+    )?;
+    assert_eq!(git.get_repo()?.get_current_operation_type(), Some("rebase"));
+
+    git.branchless("abort", &[])?;
+    let repo = git.get_repo()?;
+    assert_eq!(repo.get_current_operation_type(), None);
+    assert_eq!(repo.get_head_info()?.oid, Some(head_oid));
+
+    Ok(())
+}
+
+#[test]
+fn test_continue_interrupted_rebase() -> eyre::Result<()> {
+    let git = make_git()?;
+    git.init_repo()?;
+    let (test2_oid, new_test2_oid) = make_interrupted_rebase(&git)?;
+
+    {
+        let (stdout, _stderr) = git.branchless("operation", &["status"])?;
+        insta::assert_snapshot!(stdout, @r###"
+        An in-memory rebase of 1 commit was interrupted while moving branches.
+        To finish it, run: git branchless continue
+        To roll it back, run: git branchless abort
+        "###);
+    }
+
+    git.branchless("continue", &[])?;
+    {
+        let repo = git.get_repo()?;
+        let foo_oid = match repo.find_reference(&ReferenceName::from("refs/heads/foo"))? {
+            Some(reference) => repo.resolve_reference(&reference)?.oid,
+            None => None,
+        };
+        assert_eq!(foo_oid, Some(new_test2_oid));
+        assert_eq!(repo.get_head_info()?.oid, Some(new_test2_oid));
+    }
+
+    let stdout = git.smartlog()?;
+    assert!(!stdout.contains(&test2_oid.to_string()[..7]), "{stdout}");
+
+    {
+        let (stdout, _stderr) = git.branchless("operation", &["status"])?;
+        insta::assert_snapshot!(stdout, @"No operation in progress.
+");
+    }
+
+    Ok(())
+}
+
+#[test]
+fn test_abort_interrupted_rebase() -> eyre::Result<()> {
+    let git = make_git()?;
+    git.init_repo()?;
+    let (test2_oid, new_test2_oid) = make_interrupted_rebase(&git)?;
+
+    // Simulate having moved the branch before being interrupted.
+    git.run(&["branch", "-f", "foo", &new_test2_oid.to_string()])?;
+    git.run(&["checkout", &new_test2_oid.to_string()])?;
+
+    git.branchless("abort", &[])?;
+    {
+        let repo = git.get_repo()?;
+        let foo_oid = match repo.find_reference(&ReferenceName::from("refs/heads/foo"))? {
+            Some(reference) => repo.resolve_reference(&reference)?.oid,
+            None => None,
+        };
+        assert_eq!(foo_oid, Some(test2_oid));
+        assert_eq!(repo.get_head_info()?.oid, Some(test2_oid));
+    }
+
+    let stdout = git.smartlog()?;
+    assert!(
+        !stdout.contains(&new_test2_oid.to_string()[..7]),
+        "{stdout}"
+    );
+
+    {
+        let (stdout, _stderr) = git.branchless("operation", &["status"])?;
+        insta::assert_snapshot!(stdout, @"No operation in progress.
+");
+    }
+
+    Ok(())
+}
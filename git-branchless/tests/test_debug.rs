@@ -0,0 +1,101 @@
+use lib::testing::make_git;
+
+#[test]
+fn test_debug_benchmark() -> eyre::Result<()> {
+    let git = make_git()?;
+    git.init_repo()?;
+
+    {
+        let (stdout, _stderr) = git.branchless(
+            "debug",
+            &[
+                "benchmark",
+                "--num-commits",
+                "20",
+                "--num-stacks",
+                "3",
+                "--stack-size",
+                "2",
+            ],
+        )?;
+        assert!(
+            stdout.starts_with(
+                "Benchmarked a repository with 20 commits on the main branch and 3 stacks of 2 commits:\n"
+            ),
+            "{stdout}"
+        );
+        for name in [
+            "Generate repository",
+            "Replay event log",
+            "Build commit graph",
+            "Open commit graph (cached)",
+            "Plan restack",
+        ] {
+            assert!(stdout.contains(&format!("  {name}: ")), "{stdout}");
+        }
+        assert!(
+            stdout.ends_with("The restack plan moved 6 commits.\n"),
+            "{stdout}"
+        );
+    }
+
+    Ok(())
+}
+
+#[test]
+fn test_debug_dump_graph() -> eyre::Result<()> {
+    let git = make_git()?;
+    git.init_repo()?;
+
+    git.detach_head()?;
+    let test1_oid = git.commit_file("test1", 1)?;
+    let test2_oid = git.commit_file("test2", 2)?;
+
+    {
+        let (stdout, _stderr) = git.branchless("debug", &["dump-graph"])?;
+        assert!(stdout.starts_with("digraph commits {\n"), "{stdout}");
+        assert!(
+            stdout.contains(&format!(
+                "  \"{test2_oid}\" [label=\"{} create test2.txt\"];\n",
+                &test2_oid.to_string()[..7]
+            )),
+            "{stdout}"
+        );
+        assert!(
+            stdout.contains(&format!("  \"{test1_oid}\" -> \"{test2_oid}\";\n")),
+            "{stdout}"
+        );
+        assert!(stdout.ends_with("}\n"), "{stdout}");
+    }
+
+    {
+        let (stdout, _stderr) = git.branchless("debug", &["dump-graph", "--format", "mermaid"])?;
+        assert!(stdout.starts_with("flowchart BT\n"), "{stdout}");
+        assert!(
+            stdout.contains(&format!("  c{test1_oid} --> c{test2_oid}\n")),
+            "{stdout}"
+        );
+    }
+
+    git.run(&["commit", "--amend", "-m", "amend test2.txt"])?;
+    let (amended_oid, _stderr) = git.run(&["rev-parse", "HEAD"])?;
+    let amended_oid = amended_oid.trim();
+    {
+        let (stdout, _stderr) = git.branchless(
+            "debug",
+            &[
+                "dump-graph",
+                "--hidden",
+                &format!("{test1_oid} | {test2_oid} | {amended_oid}"),
+            ],
+        )?;
+        assert!(
+            stdout.contains(&format!(
+                "  \"{test2_oid}\" -> \"{amended_oid}\" [style=dashed, label=\"rewritten\"];\n"
+            )),
+            "{stdout}"
+        );
+    }
+
+    Ok(())
+}
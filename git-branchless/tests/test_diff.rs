@@ -0,0 +1,83 @@
+use lib::testing::{make_git, GitRunOptions};
+
+#[test]
+fn test_diff_stack() -> eyre::Result<()> {
+    let git = make_git()?;
+    git.init_repo()?;
+
+    git.commit_file("test1", 1)?;
+    git.detach_head()?;
+    git.commit_file("test2", 2)?;
+    git.commit_file("test3", 3)?;
+
+    {
+        let (stdout, _stderr) = git.branchless("diff", &[])?;
+        insta::assert_snapshot!(stdout, @r###"
+        diff --git a/test2.txt b/test2.txt
+        new file mode 100644
+        index 0000000..4e512d2
+        --- /dev/null
+        +++ b/test2.txt
+        @@ -0,0 +1 @@
+        +test2 contents
+        diff --git a/test3.txt b/test3.txt
+        new file mode 100644
+        index 0000000..a474f4e
+        --- /dev/null
+        +++ b/test3.txt
+        @@ -0,0 +1 @@
+        +test3 contents
+        "###);
+    }
+
+    {
+        let (stdout, _stderr) = git.branchless("diff", &["--stat"])?;
+        insta::assert_snapshot!(stdout, @r###"
+         test2.txt | 1 +
+         test3.txt | 1 +
+         2 files changed, 2 insertions(+)
+        "###);
+    }
+
+    {
+        let (stdout, _stderr) = git.branchless("diff", &["--stat", "--per-commit"])?;
+        insta::assert_snapshot!(stdout, @r###"
+        96d1c37 create test2.txt
+         test2.txt | 1 +
+         1 file changed, 1 insertion(+)
+        70deb1e create test3.txt
+         test3.txt | 1 +
+         1 file changed, 1 insertion(+)
+        "###);
+    }
+
+    Ok(())
+}
+
+#[test]
+fn test_diff_multiple_heads() -> eyre::Result<()> {
+    let git = make_git()?;
+    git.init_repo()?;
+
+    git.detach_head()?;
+    let test1_oid = git.commit_file("test1", 1)?;
+    git.run(&["checkout", "master"])?;
+    let test2_oid = git.commit_file("test2", 2)?;
+
+    {
+        let (stdout, _stderr) = git.branchless_with_options(
+            "diff",
+            &[&format!("{test1_oid} + {test2_oid}")],
+            &GitRunOptions {
+                expected_exit_code: 1,
+                ..Default::default()
+            },
+        )?;
+        insta::assert_snapshot!(stdout, @r###"
+        The commits to diff must form a single stack, but they have 2 heads.
+        To see the diff of each commit instead, pass --per-commit.
+        "###);
+    }
+
+    Ok(())
+}
@@ -0,0 +1,140 @@
+use lib::testing::{make_git, GitRunOptions};
+
+#[test]
+fn test_edit_restacks_descendants() -> eyre::Result<()> {
+    let git = make_git()?;
+
+    if !git.supports_reference_transactions()? {
+        return Ok(());
+    }
+
+    git.init_repo()?;
+    git.detach_head()?;
+    let test1_oid = git.commit_file("test1", 1)?;
+    git.commit_file("test2", 2)?;
+    git.commit_file("test3", 3)?;
+
+    {
+        let (stdout, _stderr) = git.branchless("edit", &[&test1_oid.to_string()])?;
+        assert!(
+            stdout.contains("Editing 62fc20d create test1.txt."),
+            "{stdout}"
+        );
+    }
+
+    git.write_file_txt("test1", "updated contents\n")?;
+    git.run(&["commit", "--amend", "-a", "-m", "amend test1.txt"])?;
+
+    {
+        let stdout = git.smartlog()?;
+        assert!(!stdout.contains("create test1.txt"), "{stdout}");
+        assert_eq!(stdout.matches("create test2.txt").count(), 1, "{stdout}");
+        assert_eq!(stdout.matches("create test3.txt").count(), 1, "{stdout}");
+    }
+
+    git.branchless("next", &[])?;
+
+    {
+        let repo = git.get_repo()?;
+        let head_oid = repo.get_head_info()?.oid.unwrap();
+        let head_commit = repo.find_commit_or_fail(head_oid)?;
+        assert_eq!(head_commit.get_summary()?, "create test2.txt");
+        let parent_commit = head_commit.get_only_parent().unwrap();
+        assert_eq!(parent_commit.get_summary()?, "amend test1.txt");
+    }
+
+    Ok(())
+}
+
+#[test]
+fn test_edit_public_commit() -> eyre::Result<()> {
+    let git = make_git()?;
+    git.init_repo()?;
+    git.commit_file("test1", 1)?;
+
+    {
+        let (stdout, _stderr) = git.branchless_with_options(
+            "edit",
+            &["master"],
+            &GitRunOptions {
+                expected_exit_code: 1,
+                ..Default::default()
+            },
+        )?;
+        assert!(stdout.contains("You are trying to rewrite"), "{stdout}");
+    }
+
+    Ok(())
+}
+
+#[test]
+fn test_edit_restacks_left_behind_descendants() -> eyre::Result<()> {
+    let git = make_git()?;
+
+    if !git.supports_reference_transactions()? {
+        return Ok(());
+    }
+
+    git.init_repo()?;
+    git.detach_head()?;
+    let test1_oid = git.commit_file("test1", 1)?;
+    git.commit_file("test2", 2)?;
+    git.commit_file("test3", 3)?;
+
+    git.run(&["checkout", &test1_oid.to_string()])?;
+    git.run(&["commit", "--amend", "-m", "amend test1.txt"])?;
+
+    git.branchless("edit", &["HEAD"])?;
+
+    {
+        let stdout = git.smartlog()?;
+        assert!(!stdout.contains("create test1.txt"), "{stdout}");
+        assert_eq!(stdout.matches("create test2.txt").count(), 1, "{stdout}");
+        assert_eq!(stdout.matches("create test3.txt").count(), 1, "{stdout}");
+    }
+
+    {
+        let repo = git.get_repo()?;
+        let head_oid = repo.get_head_info()?.oid.unwrap();
+        let head_commit = repo.find_commit_or_fail(head_oid)?;
+        assert_eq!(head_commit.get_summary()?, "amend test1.txt");
+    }
+
+    Ok(())
+}
+
+#[test]
+fn test_edit_checkout_outside_stack_stops_editing() -> eyre::Result<()> {
+    let git = make_git()?;
+
+    if !git.supports_reference_transactions()? {
+        return Ok(());
+    }
+
+    git.init_repo()?;
+    git.detach_head()?;
+    let test1_oid = git.commit_file("test1", 1)?;
+    git.commit_file("test2", 2)?;
+    let test3_oid = git.commit_file("test3", 3)?;
+
+    let edit_state_path = git.get_repo()?.get_branchless_dir()?.join("edit-state");
+    git.branchless("edit", &[&test1_oid.to_string()])?;
+    assert!(edit_state_path.exists());
+
+    git.run(&["checkout", &test3_oid.to_string()])?;
+    assert!(edit_state_path.exists());
+
+    git.run(&["checkout", "master"])?;
+    assert!(!edit_state_path.exists());
+
+    git.run(&["checkout", &test1_oid.to_string()])?;
+    git.run(&["commit", "--amend", "-m", "amend test1.txt"])?;
+
+    {
+        let stdout = git.smartlog()?;
+        assert!(stdout.contains("create test1.txt"), "{stdout}");
+        assert_eq!(stdout.matches("create test2.txt").count(), 1, "{stdout}");
+    }
+
+    Ok(())
+}
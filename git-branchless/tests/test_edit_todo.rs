@@ -0,0 +1,74 @@
+use lib::testing::make_git;
+
+#[test]
+fn test_edit_todo_autosquash_stack() -> eyre::Result<()> {
+    let git = make_git()?;
+
+    if !git.supports_reference_transactions()? {
+        return Ok(());
+    }
+
+    git.init_repo()?;
+    git.detach_head()?;
+    git.commit_file("test1", 1)?;
+    git.commit_file("test2", 2)?;
+    git.write_file_txt("test1", "updated contents\n")?;
+    git.run(&["commit", "-a", "-m", "fixup! create test1.txt"])?;
+
+    git.run(&[
+        "-c",
+        "sequence.editor=git branchless edit-todo --autosquash-stack",
+        "rebase",
+        "-i",
+        "master",
+    ])?;
+
+    {
+        let (stdout, _stderr) = git.run(&["log", "--format=%s", "master..HEAD"])?;
+        insta::assert_snapshot!(stdout, @r###"
+        create test2.txt
+        create test1.txt
+        "###);
+    }
+
+    {
+        let (stdout, _stderr) = git.run(&["show", "HEAD^:test1.txt"])?;
+        assert_eq!(stdout, "updated contents\n");
+    }
+
+    {
+        let stdout = git.smartlog()?;
+        assert!(!stdout.contains("fixup!"), "{stdout}");
+    }
+
+    Ok(())
+}
+
+#[test]
+fn test_edit_todo_keeps_other_commands() -> eyre::Result<()> {
+    let git = make_git()?;
+    git.init_repo()?;
+
+    let todo_path = git.repo_path.join("todo");
+    std::fs::write(
+        &todo_path,
+        "\
+pick 62fc20d create test1.txt
+exec make test
+break
+p 96d1c37 fixup! create test1.txt
+",
+    )?;
+    git.branchless(
+        "edit-todo",
+        &["--autosquash-stack", todo_path.to_str().unwrap()],
+    )?;
+    insta::assert_snapshot!(std::fs::read_to_string(&todo_path)?, @r###"
+    pick 62fc20d create test1.txt
+    fixup 96d1c37 fixup! create test1.txt
+    exec make test
+    break
+    "###);
+
+    Ok(())
+}
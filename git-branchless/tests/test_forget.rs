@@ -0,0 +1,102 @@
+use lib::core::eventlog::{Event, EventLogDb};
+use lib::git::MaybeZeroOid;
+use lib::testing::make_git;
+
+#[test]
+fn test_forget_commit() -> eyre::Result<()> {
+    let git = make_git()?;
+
+    git.init_repo()?;
+    git.detach_head()?;
+    let test1_oid = git.commit_file("test1", 1)?;
+    git.run(&["checkout", "master"])?;
+    git.detach_head()?;
+    git.commit_file("test2", 2)?;
+
+    {
+        let (stdout, _stderr) = git.branchless("forget", &[&test1_oid.to_string()])?;
+        insta::assert_snapshot!(stdout, @r###"
+        Forgot commit: 62fc20d create test1.txt
+        "###);
+    }
+
+    {
+        let stdout = git.smartlog()?;
+        insta::assert_snapshot!(stdout, @r###"
+        O f777ecc (master) create initial.txt
+        |
+        @ fe65c1f create test2.txt
+        "###);
+    }
+
+    {
+        let (stdout, _stderr) = git.run(&["for-each-ref", "refs/branchless/"])?;
+        insta::assert_snapshot!(stdout, @r###"
+        fe65c1fe15584744e649b2c79d4cf9b0d878f92e commit	refs/branchless/fe65c1fe15584744e649b2c79d4cf9b0d878f92e
+        "###);
+    }
+
+    {
+        // The commit is no longer in the event log, so it can't be restored.
+        let (stdout, _stderr) = git.branchless("query", &["draft()"])?;
+        insta::assert_snapshot!(stdout, @r###"
+        fe65c1f create test2.txt
+        "###);
+    }
+
+    Ok(())
+}
+
+#[test]
+fn test_forget_keeps_ref_updates() -> eyre::Result<()> {
+    let git = make_git()?;
+
+    if !git.supports_reference_transactions()? {
+        return Ok(());
+    }
+
+    git.init_repo()?;
+    git.detach_head()?;
+    let test1_oid = git.commit_file("test1", 1)?;
+    git.run(&["branch", "foo"])?;
+    git.run(&["branch", "-f", "foo", "master"])?;
+
+    let is_ref_update_to_test1 = |event: &Event| match event {
+        Event::RefUpdateEvent { new_oid, .. } => *new_oid == MaybeZeroOid::NonZero(test1_oid),
+        _ => false,
+    };
+    let is_commit_event_for_test1 = |event: &Event| match event {
+        Event::CommitEvent { commit_oid, .. } => *commit_oid == test1_oid,
+        _ => false,
+    };
+
+    let repo = git.get_repo()?;
+    let conn = repo.get_db_conn()?;
+    let event_log_db = EventLogDb::new(&conn)?;
+    let events_before = event_log_db.get_events()?;
+    assert!(events_before.iter().any(is_commit_event_for_test1));
+
+    git.branchless("forget", &[&test1_oid.to_string()])?;
+
+    let events_after = event_log_db.get_events()?;
+    assert!(!events_after.iter().any(is_commit_event_for_test1));
+    assert_eq!(
+        events_after
+            .iter()
+            .filter(|event| is_ref_update_to_test1(event))
+            .count(),
+        events_before
+            .iter()
+            .filter(|event| is_ref_update_to_test1(event))
+            .count(),
+    );
+    assert_eq!(
+        events_after.len(),
+        events_before
+            .iter()
+            .filter(|event| !is_commit_event_for_test1(event))
+            .count(),
+    );
+
+    Ok(())
+}
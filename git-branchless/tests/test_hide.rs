@@ -44,6 +44,54 @@ fn test_hide_commit() -> eyre::Result<()> {
     Ok(())
 }
 
+#[test]
+fn test_hide_tagged_commit() -> eyre::Result<()> {
+    let git = make_git()?;
+
+    git.init_repo()?;
+    git.detach_head()?;
+    let test1_oid = git.commit_file("test1", 1)?;
+    git.run(&["tag", "v1"])?;
+    git.run(&["checkout", "master"])?;
+
+    {
+        let stdout = git.smartlog()?;
+        insta::assert_snapshot!(stdout, @r###"
+        @ f777ecc (> master) create initial.txt
+        |
+        o 62fc20d (tag v1) create test1.txt
+        "###);
+    }
+
+    {
+        let (stdout, stderr) = git.branchless_with_options(
+            "hide",
+            &[&test1_oid.to_string()],
+            &GitRunOptions {
+                expected_exit_code: 1,
+                ..Default::default()
+            },
+        )?;
+        insta::assert_snapshot!(stderr, @r###"
+        Cannot hide 1 commit pointed to by tags:
+        62fc20d create test1.txt
+        To hide them anyway, delete the tags or set branchless.protectTags to false.
+        "###);
+        insta::assert_snapshot!(stdout, @"");
+    }
+
+    git.run(&["config", "branchless.protectTags", "false"])?;
+    {
+        let (stdout, _stderr) = git.branchless("hide", &[&test1_oid.to_string()])?;
+        insta::assert_snapshot!(stdout, @r###"
+        Hid commit: 62fc20d create test1.txt
+        To unhide this 1 commit, run: git undo
+        "###);
+    }
+
+    Ok(())
+}
+
 #[test]
 fn test_hide_bad_commit() -> eyre::Result<()> {
     let git = make_git()?;
@@ -380,6 +428,63 @@ fn test_unhide() -> eyre::Result<()> {
     Ok(())
 }
 
+#[test]
+fn test_hide_archive_branches() -> eyre::Result<()> {
+    let git = make_git()?;
+
+    git.init_repo()?;
+    git.run(&["config", "branchless.hide.archiveBranches", "true"])?;
+    git.detach_head()?;
+    git.commit_file("test1", 1)?;
+    git.commit_file("test2", 2)?;
+    git.run(&["branch", "test"])?;
+    git.run(&["checkout", "master"])?;
+
+    {
+        let (stdout, _stderr) = git.branchless("hide", &["test", "test^"])?;
+        insta::assert_snapshot!(stdout, @r###"
+        Hid commit: 62fc20d create test1.txt
+        Hid commit: 96d1c37 create test2.txt
+        branchless: processing 1 update: branch test
+        Archived 1 branch: test
+        To unhide these 2 commits and restore 1 branch, run: git undo
+        "###);
+    }
+
+    {
+        git.branchless("gc", &[])?;
+        let (stdout, _stderr) = git.run(&["show-ref"])?;
+        insta::assert_snapshot!(stdout, @r###"
+        96d1c37a3d4363611c49f7e52186e189a04c531f refs/branchless/archived/test
+        f777ecc9b0db5ed372b2615695191a8a17f79f24 refs/heads/master
+        "###);
+    }
+
+    {
+        let (stdout, _stderr) = git.branchless("unhide", &["96d1c37", "62fc20d"])?;
+        insta::assert_snapshot!(stdout, @r###"
+        Unhid commit: 62fc20d create test1.txt
+        Unhid commit: 96d1c37 create test2.txt
+        branchless: processing 1 update: branch test
+        Restored 1 branch: test
+        To hide these 2 commits, run: git undo
+        "###);
+    }
+
+    {
+        let stdout = git.smartlog()?;
+        insta::assert_snapshot!(stdout, @r###"
+        @ f777ecc (> master) create initial.txt
+        |
+        o 62fc20d create test1.txt
+        |
+        o 96d1c37 (test) create test2.txt
+        "###);
+    }
+
+    Ok(())
+}
+
 #[test]
 fn test_hide_recursive() -> eyre::Result<()> {
     let git = make_git()?;
@@ -3,9 +3,12 @@ use lib::core::effects::Effects;
 use lib::core::eventlog::testing::{get_event_replayer_events, redact_event_timestamp};
 use lib::core::eventlog::{Event, EventLogDb, EventReplayer};
 use lib::core::formatting::Glyphs;
-use lib::git::GitVersion;
-use lib::testing::make_git;
+use lib::git::{GitVersion, MaybeZeroOid, NonZeroOid, ReferenceName};
+use lib::testing::{
+    make_git, make_git_with_remote_repo, GitInitOptions, GitRunOptions, GitWrapperWithRemoteRepo,
+};
 use lib::util::get_sh;
+use std::collections::HashMap;
 use std::process::Command;
 
 #[test]
@@ -85,6 +88,85 @@ fn test_abandoned_branch_message() -> eyre::Result<()> {
     Ok(())
 }
 
+#[test]
+fn test_auto_restack_on_amend() -> eyre::Result<()> {
+    let git = make_git()?;
+
+    if !git.supports_reference_transactions()? {
+        return Ok(());
+    }
+
+    git.init_repo()?;
+    git.run(&["config", "branchless.restack.autoRestackOnAmend", "true"])?;
+    git.detach_head()?;
+    git.commit_file("test1", 1)?;
+    git.run(&["branch", "bar"])?;
+    git.commit_file("test2", 2)?;
+    git.run(&["branch", "foo"])?;
+    git.run(&["checkout", "HEAD^"])?;
+
+    {
+        let (_stdout, stderr) = git.run(&["commit", "--amend", "-m", "amend test1"])?;
+        assert!(!stderr.contains("This operation abandoned"), "{stderr}");
+    }
+
+    {
+        let stdout = git.smartlog()?;
+        assert!(!stdout.contains("62fc20d"), "{stdout}");
+        assert!(!stdout.contains("96d1c37"), "{stdout}");
+        assert!(stdout.contains("(bar) amend test1"), "{stdout}");
+        assert!(stdout.contains("(foo) create test2.txt"), "{stdout}");
+
+        let (head_oid, _stderr) = git.run(&["rev-parse", "HEAD"])?;
+        let (foo_parent_oid, _stderr) = git.run(&["rev-parse", "foo^"])?;
+        assert_eq!(head_oid, foo_parent_oid);
+    }
+
+    Ok(())
+}
+
+#[test]
+fn test_auto_restack_after_rebase() -> eyre::Result<()> {
+    let git = make_git()?;
+
+    if !git.supports_reference_transactions()? {
+        return Ok(());
+    }
+
+    git.init_repo()?;
+    git.run(&["config", "branchless.restack.auto", "true"])?;
+    git.detach_head()?;
+    git.commit_file("test1", 1)?;
+    git.commit_file("test2", 2)?;
+    git.commit_file("test3", 3)?;
+    git.run(&["branch", "foo"])?;
+    git.run(&["checkout", "HEAD^"])?;
+
+    {
+        let (_stdout, stderr) = git.run_with_options(
+            &["rebase", "--force-rebase", "master"],
+            &GitRunOptions {
+                time: 4,
+                ..Default::default()
+            },
+        )?;
+        assert!(!stderr.contains("This operation abandoned"), "{stderr}");
+    }
+
+    {
+        let stdout = git.smartlog()?;
+        assert!(!stdout.contains("62fc20d"), "{stdout}");
+        assert!(!stdout.contains("96d1c37"), "{stdout}");
+        assert_eq!(stdout.matches("create test3.txt").count(), 1, "{stdout}");
+
+        let (head_oid, _stderr) = git.run(&["rev-parse", "HEAD"])?;
+        let (foo_parent_oid, _stderr) = git.run(&["rev-parse", "foo^"])?;
+        assert_eq!(head_oid, foo_parent_oid);
+    }
+
+    Ok(())
+}
+
 #[test]
 fn test_fixup_no_abandoned_commit_message() -> eyre::Result<()> {
     let git = make_git()?;
@@ -117,6 +199,44 @@ fn test_fixup_no_abandoned_commit_message() -> eyre::Result<()> {
     Ok(())
 }
 
+#[test]
+fn test_rebase_exec_amend_rewrite_tracking() -> eyre::Result<()> {
+    let git = make_git()?;
+
+    if !git.supports_reference_transactions()? {
+        return Ok(());
+    }
+
+    git.init_repo()?;
+    git.detach_head()?;
+    git.commit_file("test1", 1)?;
+    git.commit_file("test2", 2)?;
+
+    git.run_with_options(
+        &[
+            "rebase",
+            "--force-rebase",
+            "--exec",
+            "git commit --amend -m amended",
+            "master",
+        ],
+        &GitRunOptions {
+            time: 3,
+            ..Default::default()
+        },
+    )?;
+
+    // The commits as picked by the rebase, before they were amended by the
+    // `exec` commands, should be hidden.
+    {
+        let stdout = git.smartlog()?;
+        assert_eq!(stdout.matches("amended").count(), 2, "{stdout}");
+        assert!(!stdout.contains("create test"), "{stdout}");
+    }
+
+    Ok(())
+}
+
 #[test]
 fn test_rebase_individual_commit() -> eyre::Result<()> {
     let git = make_git()?;
@@ -213,11 +333,96 @@ fn test_pre_auto_gc() -> eyre::Result<()> {
     insta::assert_snapshot!(stdout, @r###"
     branchless: collecting garbage
     branchless: 0 dangling references deleted
+    branchless: 0 recent commits from the last 30 days kept alive
     "###);
 
     Ok(())
 }
 
+#[test]
+fn test_pre_auto_gc_keeps_recent_commits() -> eyre::Result<()> {
+    let git = make_git()?;
+
+    if !git.supports_reference_transactions()? {
+        return Ok(());
+    }
+    git.init_repo()?;
+
+    git.detach_head()?;
+    let test1_oid = git.commit_file("test1", 1)?;
+    git.branchless("hide", &[&test1_oid.to_string()])?;
+    let ref_name = format!("refs/branchless/{test1_oid}");
+
+    {
+        // The commit is hidden, so `git branchless gc` lets it be collected.
+        git.branchless("gc", &[])?;
+        let (stdout, _stderr) = git.run(&["for-each-ref", "--format=%(refname)", &ref_name])?;
+        assert_eq!(stdout, "");
+    }
+
+    {
+        // But it was hidden recently, so it's kept alive in case the hiding is
+        // undone.
+        let (stdout, _stderr) = git.branchless("hook", &["pre-auto-gc"])?;
+        assert!(
+            stdout.contains("branchless: 1 recent commit from the last 30 days kept alive"),
+            "{stdout}"
+        );
+        let (stdout, _stderr) = git.run(&["for-each-ref", "--format=%(refname)", &ref_name])?;
+        assert_eq!(stdout, format!("{ref_name}\n"));
+    }
+
+    {
+        git.run(&["config", "branchless.gc.pinRecentDays", "0"])?;
+        let (stdout, _stderr) = git.branchless("hook", &["pre-auto-gc"])?;
+        assert!(
+            stdout.contains("branchless: 1 dangling reference deleted"),
+            "{stdout}"
+        );
+        let (stdout, _stderr) = git.run(&["for-each-ref", "--format=%(refname)", &ref_name])?;
+        assert_eq!(stdout, "");
+    }
+
+    Ok(())
+}
+
+#[test]
+fn test_pre_rebase_snapshot() -> eyre::Result<()> {
+    let git = make_git()?;
+
+    git.init_repo()?;
+    git.run(&["config", "branchless.undo.snapshotBeforeRebase", "true"])?;
+    // The `pre-rebase` hook is only installed once the option is enabled.
+    git.branchless("init", &[])?;
+    git.detach_head()?;
+    let test1_oid = git.commit_file("test1", 1)?;
+    git.run(&["checkout", "master"])?;
+    git.commit_file("test2", 2)?;
+    git.run(&["checkout", &test1_oid.to_string()])?;
+
+    {
+        let (_stdout, stderr) = git.run(&["rebase", "master"])?;
+        assert!(
+            stderr.contains("branchless: creating working copy snapshot"),
+            "{stderr}"
+        );
+    }
+
+    let effects = Effects::new_suppress_for_test(Glyphs::text());
+    let repo = git.get_repo()?;
+    let conn = repo.get_db_conn()?;
+    let event_log_db = EventLogDb::new(&conn)?;
+    let event_replayer = EventReplayer::from_event_log_db(&effects, &repo, &event_log_db)?;
+    let events = get_event_replayer_events(&event_replayer);
+    assert!(events.iter().any(|event| matches!(
+        event,
+        Event::WorkingCopySnapshot { head_oid, .. }
+            if *head_oid == MaybeZeroOid::NonZero(test1_oid)
+    )));
+
+    Ok(())
+}
+
 #[test]
 fn test_merge_commit_recorded() -> eyre::Result<()> {
     let git = make_git()?;
@@ -343,6 +548,48 @@ fn test_merge_commit_recorded() -> eyre::Result<()> {
     Ok(())
 }
 
+#[test]
+fn test_fast_forward_and_squash_merges_not_recorded() -> eyre::Result<()> {
+    let git = make_git()?;
+
+    if !git.supports_reference_transactions()? {
+        return Ok(());
+    }
+
+    git.init_repo()?;
+    git.run(&["checkout", "-b", "foo"])?;
+    let test1_oid = git.commit_file("test1", 1)?;
+    git.run(&["checkout", "-b", "bar"])?;
+    git.commit_file("test2", 2)?;
+    git.run(&["checkout", "master"])?;
+    git.run(&["merge", "foo"])?;
+    git.run(&["merge", "--squash", "bar"])?;
+
+    let effects = Effects::new_suppress_for_test(Glyphs::text());
+    let repo = git.get_repo()?;
+    let conn = repo.get_db_conn()?;
+    let event_log_db = EventLogDb::new(&conn)?;
+    let event_replayer = EventReplayer::from_event_log_db(&effects, &repo, &event_log_db)?;
+    let commit_event_oids: Vec<NonZeroOid> = get_event_replayer_events(&event_replayer)
+        .iter()
+        .filter_map(|event| match event {
+            Event::CommitEvent { commit_oid, .. } => Some(*commit_oid),
+            _ => None,
+        })
+        .collect();
+    assert_eq!(
+        commit_event_oids
+            .iter()
+            .filter(|commit_oid| **commit_oid == test1_oid)
+            .count(),
+        1,
+        "{commit_event_oids:?}"
+    );
+    assert_eq!(commit_event_oids.len(), 2, "{commit_event_oids:?}");
+
+    Ok(())
+}
+
 #[test]
 fn test_git_am_recorded() -> eyre::Result<()> {
     let git = make_git()?;
@@ -385,3 +632,316 @@ fn test_git_am_recorded() -> eyre::Result<()> {
 
     Ok(())
 }
+
+#[test]
+fn test_ignored_ref_updates() -> eyre::Result<()> {
+    let git = make_git()?;
+
+    if !git.supports_reference_transactions()? {
+        return Ok(());
+    }
+
+    git.init_repo()?;
+    git.commit_file("test1", 1)?;
+
+    {
+        let (_stdout, stderr) = git.run(&["update-ref", "refs/notes/foo", "HEAD"])?;
+        assert!(stderr.contains("branchless: processing 1 update"));
+    }
+
+    git.run(&[
+        "config",
+        "branchless.eventlog.ignoreRefs",
+        "refs/notes/*, refs/pull/*",
+    ])?;
+    {
+        let (_stdout, stderr) = git.run(&["update-ref", "refs/notes/bar", "HEAD"])?;
+        insta::assert_snapshot!(stderr, @"");
+    }
+    {
+        let (_stdout, stderr) = git.run(&["update-ref", "refs/pull/1/head", "HEAD"])?;
+        insta::assert_snapshot!(stderr, @"");
+    }
+    {
+        let (_stdout, stderr) = git.run(&["update-ref", "refs/heads/foo", "HEAD"])?;
+        insta::assert_snapshot!(stderr, @r###"
+        branchless: processing 1 update: branch foo
+        "###);
+    }
+
+    Ok(())
+}
+
+#[test]
+fn test_remote_only_ref_updates() -> eyre::Result<()> {
+    let GitWrapperWithRemoteRepo {
+        temp_dir: _guard,
+        original_repo,
+        cloned_repo,
+    } = make_git_with_remote_repo()?;
+    if !original_repo.supports_reference_transactions()? {
+        return Ok(());
+    }
+
+    original_repo.init_repo()?;
+    original_repo.run(&["checkout", "-b", "feature"])?;
+    original_repo.commit_file("test1", 1)?;
+    original_repo.run(&["checkout", "master"])?;
+
+    original_repo.clone_repo_into(&cloned_repo, &["--branch", "master"])?;
+    cloned_repo.init_repo_with_options(&GitInitOptions {
+        make_initial_commit: false,
+        ..Default::default()
+    })?;
+
+    original_repo.run(&["checkout", "feature"])?;
+    let test2_oid = original_repo.commit_file("test2", 2)?;
+    original_repo.run(&["checkout", "master"])?;
+    {
+        // Only a remote-tracking branch moved, so the hook should record the
+        // update without doing any other processing.
+        let (_stdout, stderr) = cloned_repo.run(&["fetch"])?;
+        assert!(!stderr.contains("branchless: processing"), "{stderr}");
+
+        let effects = Effects::new_suppress_for_test(Glyphs::text());
+        let repo = cloned_repo.get_repo()?;
+        let conn = repo.get_db_conn()?;
+        let event_log_db = EventLogDb::new(&conn)?;
+        let event_replayer = EventReplayer::from_event_log_db(&effects, &repo, &event_log_db)?;
+        let events = get_event_replayer_events(&event_replayer);
+        assert!(events.iter().any(|event| matches!(
+            event,
+            Event::RefUpdateEvent { ref_name, new_oid, .. }
+                if *ref_name == ReferenceName::from("refs/remotes/origin/feature")
+                    && *new_oid == MaybeZeroOid::NonZero(test2_oid)
+        )));
+    }
+
+    original_repo.commit_file("test3", 3)?;
+    {
+        // The remote-tracking branch for the main branch still needs to be
+        // checked for rewrites.
+        let (_stdout, stderr) = cloned_repo.run(&["fetch"])?;
+        assert!(
+            stderr.contains("branchless: processing 1 update: remote branch origin/master"),
+            "{stderr}"
+        );
+    }
+
+    cloned_repo.run(&["config", "branchless.core.protectedRemotes", "origin"])?;
+    original_repo.run(&["checkout", "feature"])?;
+    original_repo.commit_file("test4", 4)?;
+    {
+        // Commits fetched from a protected remote still need to be marked as
+        // public.
+        let (_stdout, stderr) = cloned_repo.run(&["fetch"])?;
+        assert!(
+            stderr.contains("branchless: processing 1 update: remote branch origin/feature"),
+            "{stderr}"
+        );
+    }
+
+    Ok(())
+}
+
+#[test]
+fn test_group_external_command_events() -> eyre::Result<()> {
+    let git = make_git()?;
+
+    if !git.supports_reference_transactions()? {
+        return Ok(());
+    }
+
+    git.init_repo()?;
+    git.run(&[
+        "config",
+        "branchless.eventlog.groupExternalCommands",
+        "true",
+    ])?;
+    let test1_oid = git.commit_file("test1", 1)?;
+    let test2_oid = git.commit_file("test2", 2)?;
+
+    let effects = Effects::new_suppress_for_test(Glyphs::text());
+    let repo = git.get_repo()?;
+    let conn = repo.get_db_conn()?;
+    let event_log_db = EventLogDb::new(&conn)?;
+    let event_replayer = EventReplayer::from_event_log_db(&effects, &repo, &event_log_db)?;
+    let events = get_event_replayer_events(&event_replayer);
+    let get_event_tx_ids = |commit_oid: NonZeroOid| {
+        events
+            .iter()
+            .filter(|event| match event {
+                Event::CommitEvent {
+                    commit_oid: event_commit_oid,
+                    ..
+                } => *event_commit_oid == commit_oid,
+                Event::RefUpdateEvent {
+                    ref_name, new_oid, ..
+                } => ref_name.as_str() == "HEAD" && *new_oid == MaybeZeroOid::NonZero(commit_oid),
+                _ => false,
+            })
+            .map(|event| event.get_event_tx_id())
+            .collect::<Vec<_>>()
+    };
+
+    let test1_event_tx_ids = get_event_tx_ids(test1_oid);
+    assert_eq!(test1_event_tx_ids.len(), 2);
+    let test2_event_tx_ids = get_event_tx_ids(test2_oid);
+    assert_eq!(test2_event_tx_ids.len(), 2);
+    assert_ne!(test1_event_tx_ids[0], test2_event_tx_ids[0]);
+    if cfg!(target_os = "linux") {
+        // The reference updates and the commit event from each `git commit`
+        // are recorded in the same transaction.
+        assert_eq!(test1_event_tx_ids[0], test1_event_tx_ids[1]);
+        assert_eq!(test2_event_tx_ids[0], test2_event_tx_ids[1]);
+    } else {
+        // The Git command can't be identified, so each hook invocation gets
+        // its own transaction.
+        assert_ne!(test1_event_tx_ids[0], test1_event_tx_ids[1]);
+        assert_ne!(test2_event_tx_ids[0], test2_event_tx_ids[1]);
+    }
+
+    Ok(())
+}
+
+#[test]
+fn test_hook_output_modes() -> eyre::Result<()> {
+    let git = make_git()?;
+
+    if !git.supports_reference_transactions()? {
+        return Ok(());
+    }
+
+    git.init_repo()?;
+    git.run(&["checkout", "-b", "foo"])?;
+    git.commit_file("test1", 1)?;
+    git.commit_file("test2", 2)?;
+    git.commit_file("test3", 3)?;
+    git.run(&["checkout", "--detach", "master"])?;
+
+    {
+        let (_stdout, stderr) = git.run_with_options(
+            &["commit", "--allow-empty", "-m", "quiet commit"],
+            &GitRunOptions {
+                time: 4,
+                env: HashMap::from([("BRANCHLESS_HOOK_OUTPUT".to_string(), "quiet".to_string())]),
+                ..Default::default()
+            },
+        )?;
+        assert!(!stderr.contains("branchless:"), "{stderr}");
+    }
+
+    {
+        let (_stdout, stderr) = git.run_with_options(
+            &["cherry-pick", "foo~2", "foo~1", "foo"],
+            &GitRunOptions {
+                time: 5,
+                env: HashMap::from([("BRANCHLESS_HOOK_OUTPUT".to_string(), "summary".to_string())]),
+                ..Default::default()
+            },
+        )?;
+        assert!(stderr.contains("branchless: processing"), "{stderr}");
+        if cfg!(target_os = "linux") {
+            // All of the hooks are run by the same `git cherry-pick`, so the
+            // messages after the first one are coalesced.
+            assert!(
+                stderr.matches("branchless: processed commit").count() < 3,
+                "{stderr}"
+            );
+        } else {
+            assert!(
+                stderr.matches("branchless: processed commit").count() <= 3,
+                "{stderr}"
+            );
+        }
+    }
+
+    if cfg!(target_os = "linux") {
+        // The messages skipped at the end of the previous operation are
+        // reported by the next one.
+        let (_stdout, stderr) = git.run_with_options(
+            &["commit", "--allow-empty", "-m", "summary commit"],
+            &GitRunOptions {
+                time: 6,
+                env: HashMap::from([("BRANCHLESS_HOOK_OUTPUT".to_string(), "summary".to_string())]),
+                ..Default::default()
+            },
+        )?;
+        assert!(
+            stderr.contains("messages from a previous operation)")
+                || stderr.contains("message from a previous operation)"),
+            "{stderr}"
+        );
+    }
+
+    {
+        // Messages skipped by the hooks of a git-branchless command are
+        // reported once its Git subprocess finishes.
+        let (stdout, stderr) = git.branchless_with_options(
+            "prev",
+            &[],
+            &GitRunOptions {
+                env: HashMap::from([("BRANCHLESS_HOOK_OUTPUT".to_string(), "summary".to_string())]),
+                ..Default::default()
+            },
+        )?;
+        let output = format!("{stdout}{stderr}");
+        assert!(output.contains("from this operation)"), "{output}");
+    }
+
+    {
+        // The commits are still recorded even if the messages are skipped, so
+        // they're visible in the smartlog.
+        let stdout = git.smartlog()?;
+        assert!(stdout.contains("quiet commit"), "{stdout}");
+        assert_eq!(stdout.matches("create test3.txt").count(), 2, "{stdout}");
+    }
+
+    Ok(())
+}
+
+#[test]
+fn test_hook_protocol_compatibility() -> eyre::Result<()> {
+    let git = make_git()?;
+    git.init_repo()?;
+
+    {
+        // Hooks installed by a newer version may not be known to this one.
+        let (stdout, stderr) = git.branchless("hook", &["post-frobnicate", "foo"])?;
+        assert_eq!(stdout, "");
+        assert!(
+            stderr.contains("branchless: ignoring unknown hook post-frobnicate"),
+            "{stderr}"
+        );
+    }
+
+    {
+        let (_stdout, stderr) = git.branchless_with_options(
+            "hook",
+            &["post-commit"],
+            &GitRunOptions {
+                env: HashMap::from([(
+                    "BRANCHLESS_HOOK_PROTOCOL_VERSION".to_string(),
+                    "999".to_string(),
+                )]),
+                ..Default::default()
+            },
+        )?;
+        assert!(
+            stderr.contains(
+                "installed by a newer version of git-branchless (hook protocol version 999"
+            ),
+            "{stderr}"
+        );
+        assert!(stderr.contains("branchless: processed commit"), "{stderr}");
+    }
+
+    {
+        // Hook scripts which predate the protocol version don't set it.
+        let (_stdout, stderr) = git.branchless("hook", &["post-commit"])?;
+        assert!(!stderr.contains("newer version"), "{stderr}");
+        assert!(stderr.contains("branchless: processed commit"), "{stderr}");
+    }
+
+    Ok(())
+}
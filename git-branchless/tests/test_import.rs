@@ -0,0 +1,182 @@
+use lib::testing::{make_git, GitRunOptions};
+
+/// Encode an obsolescence marker in version 1 of Mercurial's obsstore format,
+/// with no parent data or metadata.
+fn encode_obsmarker(predecessor: &str, successors: &[&str]) -> Vec<u8> {
+    let decode_node = |node: &str| -> Vec<u8> {
+        (0..node.len())
+            .step_by(2)
+            .map(|i| u8::from_str_radix(&node[i..i + 2], 16).unwrap())
+            .collect()
+    };
+    let size = 19 + 20 * (1 + successors.len());
+    let mut result = Vec::new();
+    result.extend(u32::try_from(size).unwrap().to_be_bytes());
+    result.extend(0.0f64.to_be_bytes());
+    result.extend(0i16.to_be_bytes());
+    result.extend(0u16.to_be_bytes());
+    result.extend([u8::try_from(successors.len()).unwrap(), 255, 0]);
+    result.extend(decode_node(predecessor));
+    for successor in successors {
+        result.extend(decode_node(successor));
+    }
+    result
+}
+
+#[test]
+fn test_import_from_hg() -> eyre::Result<()> {
+    let git = make_git()?;
+
+    git.init_repo()?;
+    git.detach_head()?;
+    let test1_oid = git.commit_file("test1", 1)?;
+    git.run(&["checkout", "master"])?;
+    git.detach_head()?;
+    let test2_oid = git.commit_file("test2", 2)?;
+
+    let hg_node1 = "aa".repeat(20);
+    let hg_node2 = "bb".repeat(20);
+    let hg_node3 = "cc".repeat(20);
+    let hg_dir = git.repo_path.join("hg-repo").join(".hg");
+    std::fs::create_dir_all(hg_dir.join("store"))?;
+    std::fs::write(
+        hg_dir.join("git-mapfile"),
+        format!("{test1_oid} {hg_node1}\n{test2_oid} {hg_node2}\n"),
+    )?;
+    let mut obsstore = vec![1];
+    obsstore.extend(encode_obsmarker(&hg_node1, &[&hg_node2]));
+    obsstore.extend(encode_obsmarker(&hg_node3, &[]));
+    std::fs::write(hg_dir.join("store").join("obsstore"), obsstore)?;
+    std::fs::write(
+        hg_dir.join("store").join("phaseroots"),
+        format!("2 {hg_node2}\n"),
+    )?;
+
+    {
+        let (stdout, _stderr) = git.branchless("import", &["--from-hg", "hg-repo"])?;
+        insta::assert_snapshot!(stdout, @r###"
+        Imported 1 rewritten commit, 0 pruned commits, and 1 commit phase.
+        Skipped 1 entry referring to changesets which aren't in this repository.
+        "###);
+    }
+
+    {
+        let stdout = git.smartlog()?;
+        assert!(!stdout.contains("create test1.txt"), "{stdout}");
+        assert!(stdout.contains("create test2.txt"), "{stdout}");
+    }
+    {
+        let (stdout, _stderr) = git.branchless("phase", &["HEAD"])?;
+        assert!(stdout.starts_with("secret: "), "{stdout}");
+    }
+
+    Ok(())
+}
+
+#[test]
+fn test_import_from_hg_without_mapping() -> eyre::Result<()> {
+    let git = make_git()?;
+
+    git.init_repo()?;
+    std::fs::create_dir_all(git.repo_path.join("hg-repo").join(".hg"))?;
+
+    {
+        let (_stdout, stderr) = git.branchless_with_options(
+            "import",
+            &["--from-hg", "hg-repo"],
+            &GitRunOptions {
+                expected_exit_code: 1,
+                ..Default::default()
+            },
+        )?;
+        assert!(stderr.contains("No hg-git mapping found"), "{stderr}");
+    }
+
+    Ok(())
+}
+
+#[test]
+fn test_import_from_notes() -> eyre::Result<()> {
+    let git = make_git()?;
+
+    git.init_repo()?;
+    git.run(&["config", "branchless.notes.mirrorRewrites", "true"])?;
+    git.detach_head()?;
+    let test1_oid = git.commit_file("test1", 1)?;
+    git.run(&["commit", "--amend", "-m", "amended test1"])?;
+    let amended_oid = git.get_repo()?.get_head_info()?.oid.unwrap();
+
+    {
+        let (stdout, _stderr) = git.run(&[
+            "notes",
+            "--ref",
+            "branchless",
+            "show",
+            &test1_oid.to_string(),
+        ])?;
+        assert_eq!(stdout, format!("Rewritten into {amended_oid}\n"));
+    }
+
+    {
+        let (stdout, _stderr) = git.branchless("import", &["--from-notes"])?;
+        insta::assert_snapshot!(stdout, @"Imported 0 rewrites from refs/notes/branchless.
+");
+    }
+
+    git.run(&["checkout", "master"])?;
+    git.detach_head()?;
+    let test2_oid = git.commit_file("test2", 2)?;
+    git.run(&[
+        "notes",
+        "--ref",
+        "branchless",
+        "add",
+        "-m",
+        &format!("Rewritten into {test2_oid}"),
+        &amended_oid.to_string(),
+    ])?;
+
+    {
+        let (stdout, _stderr) = git.branchless("import", &["--from-notes"])?;
+        insta::assert_snapshot!(stdout, @"Imported 1 rewrite from refs/notes/branchless.
+");
+    }
+
+    {
+        let stdout = git.smartlog()?;
+        assert!(!stdout.contains("amended test1"), "{stdout}");
+        assert!(stdout.contains("create test2.txt"), "{stdout}");
+    }
+
+    Ok(())
+}
+
+#[test]
+fn test_mirror_rewrites_to_notes_single_commit() -> eyre::Result<()> {
+    let git = make_git()?;
+
+    git.init_repo()?;
+    git.run(&["config", "branchless.notes.mirrorRewrites", "true"])?;
+    git.detach_head()?;
+    git.commit_file("test1", 1)?;
+    let test2_oid = git.commit_file("test2", 2)?;
+    let test3_oid = git.commit_file("test3", 3)?;
+
+    git.branchless(
+        "move",
+        &["-s", &test2_oid.to_string(), "-d", "master", "--in-memory"],
+    )?;
+
+    {
+        let (stdout, _stderr) = git.run(&["rev-list", "--count", "refs/notes/branchless"])?;
+        assert_eq!(stdout, "1\n");
+    }
+
+    for oid in [test2_oid, test3_oid].iter() {
+        let (stdout, _stderr) =
+            git.run(&["notes", "--ref", "branchless", "show", &oid.to_string()])?;
+        assert!(stdout.starts_with("Rewritten into "), "{stdout}");
+    }
+
+    Ok(())
+}
@@ -3,7 +3,8 @@ use std::collections::HashMap;
 use eyre::Context;
 use lib::git::GitVersion;
 use lib::testing::{
-    make_git, make_git_worktree, GitInitOptions, GitRunOptions, GitWorktreeWrapper,
+    make_git, make_git_with_remote_repo, make_git_worktree, GitInitOptions, GitRunOptions,
+    GitWorktreeWrapper, GitWrapperWithRemoteRepo,
 };
 use regex::Regex;
 
@@ -60,6 +61,98 @@ echo Hello, world
     Ok(())
 }
 
+#[test]
+fn test_stale_hooks_reinstalled_after_upgrade() -> eyre::Result<()> {
+    let git = make_git()?;
+
+    git.init_repo()?;
+    let hook_path = git.repo_path.join(".git").join("hooks").join("post-commit");
+    std::fs::write(
+        &hook_path,
+        "#!/bin/sh
+## START BRANCHLESS CONFIG
+git branchless hook-post-commit \"$@\"
+## END BRANCHLESS CONFIG
+",
+    )?;
+    git.run(&[
+        "config",
+        "--file",
+        ".git/branchless/config",
+        "branchless.core.installedVersion",
+        "0.1.0",
+    ])?;
+
+    {
+        let (_stdout, stderr) = git.branchless("smartlog", &[])?;
+        assert!(
+            stderr
+                .contains("git-branchless was upgraded from 0.1.0, reinstalling: post-commit hook"),
+            "{stderr}"
+        );
+        let hook_contents = std::fs::read_to_string(&hook_path)?;
+        assert!(
+            hook_contents.contains("hook post-commit"),
+            "{hook_contents}"
+        );
+        assert!(
+            !hook_contents.contains("hook-post-commit"),
+            "{hook_contents}"
+        );
+    }
+
+    {
+        let (_stdout, stderr) = git.branchless("smartlog", &[])?;
+        assert!(!stderr.contains("reinstalling"), "{stderr}");
+    }
+
+    Ok(())
+}
+
+#[test]
+fn test_removed_and_customized_aliases_kept_after_upgrade() -> eyre::Result<()> {
+    let git = make_git()?;
+
+    git.init_repo()?;
+    git.run(&[
+        "config",
+        "--file",
+        ".git/branchless/config",
+        "--unset",
+        "alias.hide",
+    ])?;
+    git.run(&[
+        "config",
+        "--file",
+        ".git/branchless/config",
+        "alias.amend",
+        "branchless amend --reparent",
+    ])?;
+    git.run(&[
+        "config",
+        "--file",
+        ".git/branchless/config",
+        "branchless.core.installedVersion",
+        "0.1.0",
+    ])?;
+
+    git.branchless("smartlog", &[])?;
+    {
+        let (_stdout, stderr) = git.run_with_options(
+            &["config", "alias.hide"],
+            &GitRunOptions {
+                expected_exit_code: 1,
+                ..Default::default()
+            },
+        )?;
+        assert_eq!(stderr, "");
+        let (stdout, _stderr) = git.run(&["config", "alias.amend"])?;
+        assert_eq!(stdout, "branchless amend --reparent\n");
+    }
+
+    Ok(())
+}
+
 #[test]
 fn test_alias_installed() -> eyre::Result<()> {
     let git = make_git()?;
@@ -81,6 +174,60 @@ fn test_alias_installed() -> eyre::Result<()> {
     Ok(())
 }
 
+#[test]
+fn test_custom_aliases() -> eyre::Result<()> {
+    let git = make_git()?;
+
+    git.init_repo()?;
+    git.commit_file("test1", 1)?;
+    let test2_oid = git.commit_file("test2", 2)?;
+    git.run(&["checkout", "master~2"])?;
+
+    git.branchless("init", &["--alias", "up=next --all", "--alias", "fwd=next"])?;
+    {
+        let (stdout, _stderr) = git.run(&["config", "branchless.alias.up"])?;
+        insta::assert_snapshot!(stdout, @"next --all
+");
+        let (stdout, _stderr) = git.run(&["config", "alias.up"])?;
+        insta::assert_snapshot!(stdout, @"branchless next --all
+");
+    }
+
+    git.run(&["up"])?;
+    assert_eq!(git.get_repo()?.get_head_info()?.oid, Some(test2_oid));
+
+    // Extra arguments are passed through to the subcommand.
+    git.run(&["checkout", "master~2"])?;
+    git.run(&["fwd", "2"])?;
+    assert_eq!(git.get_repo()?.get_head_info()?.oid, Some(test2_oid));
+
+    // Aliases are reinstalled from the config by later invocations.
+    let config_path = git.get_repo()?.get_config_path()?;
+    let config_path = config_path.to_str().unwrap();
+    git.run(&["config", "--file", config_path, "--unset", "alias.up"])?;
+    git.branchless("init", &[])?;
+    {
+        let (stdout, _stderr) = git.run(&["config", "alias.up"])?;
+        insta::assert_snapshot!(stdout, @"branchless next --all
+");
+    }
+
+    {
+        let (_stdout, stderr) = git.branchless_with_options(
+            "init",
+            &["--alias", "up"],
+            &GitRunOptions {
+                expected_exit_code: 1,
+                ..Default::default()
+            },
+        )?;
+        insta::assert_snapshot!(stderr, @"Invalid alias (expected NAME=EXPANSION): up
+");
+    }
+
+    Ok(())
+}
+
 #[test]
 fn test_dont_install_existing_aliases() -> eyre::Result<()> {
     let git = make_git()?;
@@ -316,9 +463,9 @@ fn test_main_branch_not_found_error_message() -> eyre::Result<()> {
 
        0: branchless::core::eventlog::from_event_log_db with effects=<Output fancy=false> repo=<Git repository at: "<repo-path>/.git/"> event_log_db=<EventLogDb path=Some("<repo-path>/.git/branchless/db.sqlite3")>
           at some/file/path.rs:123
-       1: git_branchless_smartlog::smartlog with effects=<Output fancy=false> git_run_info=<GitRunInfo path_to_git="<git-executable>" working_directory="<repo-path>" env=not shown> options=SmartlogOptions { event_id: None, revset: None, resolve_revset_options: ResolveRevsetOptions { show_hidden_commits: false }, reverse: false }
+       1: git_branchless_smartlog::smartlog with effects=<Output fancy=false> git_run_info=<GitRunInfo path_to_git="<git-executable>" working_directory="<repo-path>" env=not shown> options=SmartlogOptions { event_id: None, at: None, event_log_path: None, revset: None, resolve_revset_options: ResolveRevsetOptions { show_hidden_commits: false }, reverse: false, group_by_stack: false, collapse: false, sort: Topo, limit: None, no_limit: false, paginate: true, paths: [], all_paths: false }
           at some/file/path.rs:123
-       2: git_branchless_smartlog::command_main with ctx=CommandContext { effects: <Output fancy=false>, git_run_info: <GitRunInfo path_to_git="<git-executable>" working_directory="<repo-path>" env=not shown> } args=SmartlogArgs { event_id: None, revset: None, reverse: false, resolve_revset_options: ResolveRevsetOptions { show_hidden_commits: false } }
+       2: git_branchless_smartlog::command_main with ctx=CommandContext { effects: <Output fancy=false>, git_run_info: <GitRunInfo path_to_git="<git-executable>" working_directory="<repo-path>" env=not shown> } args=SmartlogArgs { event_id: None, at: None, event_log: None, revset: None, reverse: false, group_by_stack: false, collapse: false, sort: Topo, limit: None, no_limit: false, no_pager: false, resolve_revset_options: ResolveRevsetOptions { show_hidden_commits: false } }
           at some/file/path.rs:123
 
     Suggestion:
@@ -329,6 +476,10 @@ fn test_main_branch_not_found_error_message() -> eyre::Result<()> {
 
         git branchless init --main-branch <branch>
 
+    Or pin the main branch to a tag or other revision by running:
+
+        git config branchless.core.mainCommit <revision>
+
     Note that remote main branches are no longer supported as of v0.6.0. See
     https://github.com/arxanas/git-branchless/discussions/595 for more details.
 
@@ -356,7 +507,7 @@ fn test_init_uninstall() -> eyre::Result<()> {
         insta::assert_snapshot!(stderr, @"");
         insta::assert_snapshot!(stdout, @r###"
         Removing config file: <repo-path>/.git/branchless/config
-        Uninstalling hooks: post-applypatch, post-checkout, post-commit, post-merge, post-rewrite, pre-auto-gc, reference-transaction
+        Uninstalling hooks: post-applypatch, post-checkout, post-commit, post-merge, post-rewrite, pre-auto-gc, pre-rebase, reference-transaction
         "###);
     }
 
@@ -673,7 +824,7 @@ fn test_install_man_pages() -> eyre::Result<()> {
     \fIPossible values:\fR
     .RS 14
     .IP \(bu 2
-    auto: Automatically determine whether to display colors from the terminal and environment variables. This is the default behavior
+    auto: Automatically determine whether to display colors from the terminal and environment variables. Colors are not displayed if the NO_COLOR environment variable is set. This is the default behavior
     .IP \(bu 2
     always: Always display terminal colors
     .IP \(bu 2
@@ -687,36 +838,81 @@ fn test_install_man_pages() -> eyre::Result<()> {
     Print version
     .SH SUBCOMMANDS
     .TP
+    git\-branchless\-abort(1)
+    Abort the operation which stopped at a merge conflict or was interrupted, rolling back its changes
+    .TP
     git\-branchless\-amend(1)
     Amend the current HEAD commit
     .TP
     git\-branchless\-bug\-report(1)
     Gather information about recent operations to upload as part of a bug report
     .TP
+    git\-branchless\-cache(1)
+    Inspect or maintain the caches which git\-branchless keeps for this repository, such as the commit graph
+    .TP
+    git\-branchless\-completions(1)
+    Generate shell completions for `git branchless` and its aliases, such as `git hide`
+    .TP
+    git\-branchless\-continue(1)
+    Continue the operation which stopped at a merge conflict or was interrupted
+    .TP
+    git\-branchless\-debug(1)
+    Tools for developing and debugging git\-branchless itself
+    .TP
+    git\-branchless\-diff(1)
+    Show the cumulative diff of a stack of commits against its merge\-base with the main branch
+    .TP
     git\-branchless\-difftool(1)
     Use the partial commit selector UI as a Git\-compatible difftool; see git\-difftool(1) for more information on Git difftools
     .TP
+    git\-branchless\-edit(1)
+    Check out a commit in the middle of a stack to edit it, leaving its descendants in place. While the commit is being edited, amending it with `git amend` or `git commit \-\-amend` restacks its descendants onto the amended commit. Run `git next` to finish editing and move onto its descendants
+    .TP
+    git\-branchless\-forget(1)
+    Stop tracking commits entirely, as if branchless had never seen them
+    .TP
     git\-branchless\-gc(1)
     Run internal garbage collection
     .TP
     git\-branchless\-hide(1)
     Hide the provided commits from the smartlog
     .TP
+    git\-branchless\-import(1)
+    Import history\-tracking data from another version control system into the event log, such as after migrating a repository to Git
+    .TP
     git\-branchless\-init(1)
     Initialize the branchless workflow for this repository
     .TP
     git\-branchless\-install\-man\-pages(1)
     Install git\-branchless\*(Aqs man\-pages to the given path
     .TP
+    git\-branchless\-land(1)
+    Land the bottom of a stack onto the main branch
+    .TP
+    git\-branchless\-log\-operations(1)
+    List recent operations, such as moves and amends, along with a summary of their effects on the repository
+    .TP
     git\-branchless\-move(1)
     Move a subtree of commits from one location to another
     .TP
     git\-branchless\-next(1)
     Move to a later commit in the current stack
     .TP
+    git\-branchless\-obslog(1)
+    Show the rewrite history of a commit: all of the commits which it was rewritten from or into, and the operations which rewrote them
+    .TP
+    git\-branchless\-operation(1)
+    Inspect the operation which is in progress, if any, such as a rebase stopped at a merge conflict or an interrupted in\-memory rebase
+    .TP
+    git\-branchless\-phase(1)
+    Show or set the phase of commits
+    .TP
     git\-branchless\-prev(1)
     Move to an earlier commit in the current stack
     .TP
+    git\-branchless\-prune(1)
+    Stop keeping old hidden commits alive, so that they can be deleted by garbage collection
+    .TP
     git\-branchless\-query(1)
     Query the commit graph using the "revset" language and print matching commits
     .TP
@@ -732,9 +928,18 @@ fn test_install_man_pages() -> eyre::Result<()> {
     git\-branchless\-reword(1)
     Reword commits
     .TP
+    git\-branchless\-serve(1)
+    Serve the smartlog graph and the event log over a local HTTP server, for use by GUI and editor integrations
+    .TP
     git\-branchless\-smartlog(1)
     `smartlog` command
     .TP
+    git\-branchless\-stack(1)
+    Name stacks of commits, so that the name can be used to refer to the whole stack in other commands
+    .TP
+    git\-branchless\-stats(1)
+    Report statistics about how the repository has been worked on, such as how often commits are amended, based on the event log
+    .TP
     git\-branchless\-submit(1)
     Push commits to a remote
     .TP
@@ -744,6 +949,9 @@ fn test_install_man_pages() -> eyre::Result<()> {
     git\-branchless\-sync(1)
     Move any local commit stacks on top of the main branch
     .TP
+    git\-branchless\-sync\-state(1)
+    Share which commits are visible with other clones of this repository, such as on another machine, by way of a remote
+    .TP
     git\-branchless\-test(1)
     Run a command on each commit in a given set and aggregate the results
     .TP
@@ -753,6 +961,12 @@ fn test_install_man_pages() -> eyre::Result<()> {
     git\-branchless\-unhide(1)
     Unhide previously\-hidden commits from the smartlog
     .TP
+    git\-branchless\-verify(1)
+    Check the event log for inconsistencies with the repository, such as visible commits which were deleted by `git gc \-\-prune=now`, branches which no longer exist, and cycles of rewrites
+    .TP
+    git\-branchless\-worktree(1)
+    Create a linked worktree checked out at the tip of a stack
+    .TP
     git\-branchless\-wrap(1)
     Wrap a Git command inside a branchless transaction
     .TP
@@ -765,3 +979,108 @@ fn test_install_man_pages() -> eyre::Result<()> {
     "###);
     Ok(())
 }
+
+#[test]
+fn test_init_detect_main_branch_from_remote_head() -> eyre::Result<()> {
+    let GitWrapperWithRemoteRepo {
+        temp_dir: _guard,
+        original_repo,
+        cloned_repo,
+    } = make_git_with_remote_repo()?;
+
+    original_repo.init_repo_with_options(&GitInitOptions {
+        run_branchless_init: false,
+        ..Default::default()
+    })?;
+    original_repo.run(&["checkout", "-b", "primary"])?;
+    original_repo.commit_file("test1", 1)?;
+    original_repo.run(&["branch", "-D", "master"])?;
+
+    original_repo.clone_repo_into(&cloned_repo, &[])?;
+    {
+        let (stdout, _stderr) = cloned_repo.run(&["symbolic-ref", "refs/remotes/origin/HEAD"])?;
+        assert_eq!(stdout, "refs/remotes/origin/primary\n");
+    }
+
+    let (stdout, _stderr) = cloned_repo.branchless("init", &[])?;
+    assert!(stdout.contains("Auto-detected your main branch as: primary"));
+    let (stdout, _stderr) = cloned_repo.run(&["config", "branchless.core.mainBranch"])?;
+    assert_eq!(stdout, "primary\n");
+
+    Ok(())
+}
+
+#[test]
+fn test_main_branch_renamed_upstream() -> eyre::Result<()> {
+    let GitWrapperWithRemoteRepo {
+        temp_dir: _guard,
+        original_repo,
+        cloned_repo,
+    } = make_git_with_remote_repo()?;
+
+    original_repo.init_repo()?;
+    original_repo.commit_file("test1", 1)?;
+    original_repo.clone_repo_into(&cloned_repo, &["--branch", "master"])?;
+    cloned_repo.init_repo_with_options(&GitInitOptions {
+        make_initial_commit: false,
+        ..Default::default()
+    })?;
+
+    original_repo.run(&["branch", "-m", "master", "main"])?;
+    cloned_repo.run(&["fetch", "origin"])?;
+    cloned_repo.run(&["remote", "set-head", "origin", "--auto"])?;
+    cloned_repo.run(&["checkout", "-b", "main", "origin/main"])?;
+    cloned_repo.run(&["branch", "-D", "master"])?;
+
+    let (stdout, _stderr) = cloned_repo.branchless("smartlog", &[])?;
+    assert!(stdout.contains("main"));
+    // Reading the main branch doesn't change the setting.
+    let (stdout, _stderr) = cloned_repo.run(&["config", "branchless.core.mainBranch"])?;
+    assert_eq!(stdout, "master\n");
+
+    cloned_repo.branchless("init", &["--main-branch"])?;
+    let (stdout, _stderr) = cloned_repo.run(&["config", "branchless.core.mainBranch"])?;
+    assert_eq!(stdout, "main\n");
+
+    Ok(())
+}
+
+#[test]
+fn test_init_redetect_main_branch() -> eyre::Result<()> {
+    let GitWrapperWithRemoteRepo {
+        temp_dir: _guard,
+        original_repo,
+        cloned_repo,
+    } = make_git_with_remote_repo()?;
+
+    original_repo.init_repo()?;
+    original_repo.commit_file("test1", 1)?;
+    original_repo.clone_repo_into(&cloned_repo, &["--branch", "master"])?;
+    cloned_repo.init_repo_with_options(&GitInitOptions {
+        make_initial_commit: false,
+        ..Default::default()
+    })?;
+
+    {
+        let (stdout, _stderr) = cloned_repo.branchless("init", &["--main-branch"])?;
+        insta::assert_snapshot!(stdout, @r###"
+        Your main branch is already set to: master
+        "###);
+    }
+
+    original_repo.run(&["branch", "-m", "master", "main"])?;
+    cloned_repo.run(&["fetch", "origin"])?;
+    cloned_repo.run(&["remote", "set-head", "origin", "--auto"])?;
+    cloned_repo.run(&["branch", "main", "origin/main"])?;
+
+    {
+        let (stdout, _stderr) = cloned_repo.branchless("init", &["--main-branch"])?;
+        insta::assert_snapshot!(stdout, @r###"
+        Updated your main branch from master to: main
+        "###);
+    }
+    let (stdout, _stderr) = cloned_repo.run(&["config", "branchless.core.mainBranch"])?;
+    assert_eq!(stdout, "main\n");
+
+    Ok(())
+}
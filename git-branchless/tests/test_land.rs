@@ -0,0 +1,163 @@
+use lib::testing::{
+    make_git, make_git_with_remote_repo, GitInitOptions, GitRunOptions, GitWrapperWithRemoteRepo,
+};
+
+#[test]
+fn test_land_fast_forward() -> eyre::Result<()> {
+    let git = make_git()?;
+
+    if !git.supports_reference_transactions()? {
+        return Ok(());
+    }
+    git.init_repo()?;
+
+    git.detach_head()?;
+    let test1_oid = git.commit_file("test1", 1)?;
+    git.commit_file("test2", 2)?;
+
+    {
+        let (stdout, _stderr) = git.branchless("land", &[])?;
+        assert!(stdout.contains("Landed 1 commit onto master."), "{stdout}");
+        let (stdout, _stderr) = git.run(&["rev-parse", "master"])?;
+        assert_eq!(stdout.trim(), test1_oid.to_string());
+    }
+
+    Ok(())
+}
+
+#[test]
+fn test_land_restacks_rest_of_stack() -> eyre::Result<()> {
+    let git = make_git()?;
+
+    if !git.supports_reference_transactions()? {
+        return Ok(());
+    }
+    git.init_repo()?;
+
+    git.detach_head()?;
+    git.commit_file("test1", 1)?;
+    let test2_oid = git.commit_file("test2", 2)?;
+    git.commit_file("test3", 3)?;
+    git.run(&["checkout", "master"])?;
+    let test4_oid = git.commit_file("test4", 4)?;
+    git.run(&["checkout", "--detach", "HEAD~"])?;
+
+    {
+        let (stdout, _stderr) = git.branchless("land", &[&test2_oid.to_string()])?;
+        assert!(stdout.contains("Landed 2 commits onto master."), "{stdout}");
+
+        let (stdout, _stderr) = git.run(&["log", "--format=%s", "master~2..master"])?;
+        assert_eq!(stdout, "create test2.txt\ncreate test1.txt\n");
+        let (stdout, _stderr) = git.run(&["rev-parse", "master~2"])?;
+        assert_eq!(stdout.trim(), test4_oid.to_string());
+
+        // The rest of the stack was moved onto the new main branch.
+        let (stdout, _stderr) = git.branchless("query", &["--raw", "parents(draft())"])?;
+        let (master_oid, _stderr) = git.run(&["rev-parse", "master"])?;
+        assert_eq!(stdout, master_oid);
+    }
+
+    {
+        git.branchless("undo", &["-y"])?;
+        let (stdout, _stderr) = git.run(&["rev-parse", "master"])?;
+        assert_eq!(stdout.trim(), test4_oid.to_string());
+    }
+
+    Ok(())
+}
+
+#[test]
+fn test_land_refuses_multiple_heads() -> eyre::Result<()> {
+    let git = make_git()?;
+
+    if !git.supports_reference_transactions()? {
+        return Ok(());
+    }
+    git.init_repo()?;
+
+    git.detach_head()?;
+    let test1_oid = git.commit_file("test1", 1)?;
+    git.commit_file("test2", 2)?;
+    git.run(&["checkout", &test1_oid.to_string()])?;
+    git.commit_file("test3", 3)?;
+
+    {
+        let (stdout, _stderr) = git.branchless_with_options(
+            "land",
+            &[&format!("children({test1_oid})")],
+            &GitRunOptions {
+                expected_exit_code: 1,
+                ..Default::default()
+            },
+        )?;
+        assert!(
+            stdout.contains(
+                "The commits to land must form a single line of history, but they have 2 heads:"
+            ),
+            "{stdout}"
+        );
+        let (stdout, _stderr) = git.run(&["rev-parse", "master"])?;
+        assert_ne!(stdout.trim(), test1_oid.to_string());
+    }
+
+    Ok(())
+}
+
+#[test]
+fn test_land_push() -> eyre::Result<()> {
+    let GitWrapperWithRemoteRepo {
+        temp_dir: _guard,
+        original_repo,
+        cloned_repo,
+    } = make_git_with_remote_repo()?;
+    if !original_repo.supports_reference_transactions()? {
+        return Ok(());
+    }
+
+    original_repo.init_repo()?;
+    original_repo.commit_file("test1", 1)?;
+    original_repo.clone_repo_into(&cloned_repo, &["--branch", "master"])?;
+    // Pushing to a checked-out branch is refused.
+    original_repo.detach_head()?;
+    cloned_repo.init_repo_with_options(&GitInitOptions {
+        make_initial_commit: false,
+        ..Default::default()
+    })?;
+    cloned_repo.detach_head()?;
+    let test2_oid = cloned_repo.commit_file("test2", 2)?;
+
+    {
+        let (stdout, _stderr) = cloned_repo.branchless("land", &["--push"])?;
+        assert!(stdout.contains("Landed 1 commit onto master."), "{stdout}");
+        let (stdout, _stderr) = original_repo.run(&["rev-parse", "master"])?;
+        assert_eq!(stdout.trim(), test2_oid.to_string());
+        let (stdout, _stderr) = cloned_repo.run(&["rev-parse", "master"])?;
+        assert_eq!(stdout.trim(), test2_oid.to_string());
+    }
+
+    // If the remote main branch has new commits, the push is rejected and
+    // the local main branch isn't updated.
+    original_repo.run(&["checkout", "master"])?;
+    original_repo.commit_file("test3", 3)?;
+    original_repo.detach_head()?;
+    cloned_repo.commit_file("test4", 4)?;
+
+    {
+        let (stdout, _stderr) = cloned_repo.branchless_with_options(
+            "land",
+            &["--push"],
+            &GitRunOptions {
+                expected_exit_code: 1,
+                ..Default::default()
+            },
+        )?;
+        assert!(
+            stdout.contains("To land onto the latest remote commits, run: git sync --pull"),
+            "{stdout}"
+        );
+        let (stdout, _stderr) = cloned_repo.run(&["rev-parse", "master"])?;
+        assert_eq!(stdout.trim(), test2_oid.to_string());
+    }
+
+    Ok(())
+}
@@ -0,0 +1,43 @@
+use lib::testing::{make_git, trim_lines};
+
+#[test]
+fn test_log_operations() -> eyre::Result<()> {
+    let git = make_git()?;
+
+    if !git.supports_reference_transactions()? {
+        return Ok(());
+    }
+
+    git.init_repo()?;
+
+    {
+        let (stdout, _stderr) = git.branchless("log-operations", &[])?;
+        insta::assert_snapshot!(stdout, @r###"
+        No operations recorded.
+        "###);
+    }
+
+    git.commit_file("test1", 1)?;
+    git.branchless("hide", &["--no-delete-branches", "HEAD"])?;
+
+    {
+        let (stdout, _stderr) = git.branchless("log-operations", &["-n", "1"])?;
+        insta::assert_snapshot!(stdout, @r###"
+        3 hide: hide 1 commit
+            1 commit hidden
+        "###);
+    }
+
+    {
+        let (stdout, _stderr) = git.branchless("undo", &["--to", "3", "-y"])?;
+        let stdout = trim_lines(stdout);
+        insta::assert_snapshot!(stdout, @r###"
+        Will apply these actions:
+        1. Unhide commit 62fc20d create test1.txt
+
+        Applied 1 inverse event.
+        "###);
+    }
+
+    Ok(())
+}
@@ -3458,6 +3458,187 @@ fn test_move_no_reapply_squashed_commits() -> eyre::Result<()> {
     Ok(())
 }
 
+#[test]
+fn test_move_keep_empty_commits() -> eyre::Result<()> {
+    let git = make_git()?;
+
+    if !git.supports_reference_transactions()? {
+        return Ok(());
+    }
+
+    git.init_repo()?;
+
+    git.detach_head()?;
+    let test1_oid = git.commit_file("test1", 1)?;
+    git.run(&[
+        "commit",
+        "--allow-empty",
+        "-m",
+        "marker",
+        "-m",
+        "branchless: keep-empty",
+    ])?;
+    let test2_oid = git.commit_file("test2", 2)?;
+
+    git.run(&["checkout", "master"])?;
+    git.run(&["cherry-pick", "--no-commit", &test1_oid.to_string()])?;
+    git.run(&["cherry-pick", "--no-commit", &test2_oid.to_string()])?;
+    git.run(&["commit", "-m", "squashed test1 and test2"])?;
+
+    // --on-disk
+    {
+        let git = git.duplicate_repo()?;
+        let (stdout, _stderr) = git.branchless(
+            "move",
+            &["--on-disk", "-b", &test2_oid.to_string(), "-d", "master"],
+        )?;
+        assert!(
+            stdout
+                .lines()
+                .any(|line| line.starts_with("Skipped now-empty commit: ")
+                    && line.ends_with(" create test1.txt")),
+            "{stdout}"
+        );
+        assert!(!stdout.contains("marker"), "{stdout}");
+
+        let stdout = git.smartlog()?;
+        assert!(stdout.contains("marker"), "{stdout}");
+        assert!(!stdout.contains("create test1.txt"), "{stdout}");
+        assert!(!stdout.contains("create test2.txt"), "{stdout}");
+    }
+
+    // --in-memory
+    {
+        let git = git.duplicate_repo()?;
+        let (stdout, _stderr) = git.branchless(
+            "move",
+            &["--in-memory", "-b", &test2_oid.to_string(), "-d", "master"],
+        )?;
+        assert!(
+            stdout
+                .lines()
+                .any(|line| line.starts_with("[1/3] Skipped now-empty commit: ")
+                    && line.ends_with(" create test1.txt")),
+            "{stdout}"
+        );
+        assert!(stdout.contains("[2/3] Committed as: "), "{stdout}");
+
+        let stdout = git.smartlog()?;
+        assert!(stdout.contains("marker"), "{stdout}");
+        assert!(!stdout.contains("create test1.txt"), "{stdout}");
+        assert!(!stdout.contains("create test2.txt"), "{stdout}");
+    }
+
+    // `branchless.restack.keepEmptyCommits`
+    {
+        git.run(&["config", "branchless.restack.keepEmptyCommits", "true"])?;
+        let (stdout, _stderr) = git.branchless(
+            "move",
+            &["--in-memory", "-b", &test2_oid.to_string(), "-d", "master"],
+        )?;
+        assert!(!stdout.contains("Skipped now-empty commit"), "{stdout}");
+
+        let stdout = git.smartlog()?;
+        assert!(stdout.contains("create test1.txt"), "{stdout}");
+        assert!(stdout.contains("marker"), "{stdout}");
+        assert!(stdout.contains("create test2.txt"), "{stdout}");
+    }
+
+    Ok(())
+}
+
+#[test]
+fn test_move_message_lint() -> eyre::Result<()> {
+    let git = make_git()?;
+
+    if !git.supports_reference_transactions()? {
+        return Ok(());
+    }
+
+    git.init_repo()?;
+    git.detach_head()?;
+    let test1_oid = git.commit_file("test1", 1)?;
+    git.run(&["checkout", "master"])?;
+    git.commit_file("test2", 2)?;
+
+    git.run(&["config", "branchless.hooks.messageLint", "grep -q ABC-"])?;
+    {
+        let (stdout, stderr) = git.branchless_with_options(
+            "move",
+            &["--in-memory", "-x", &test1_oid.to_string(), "-d", "master"],
+            &GitRunOptions {
+                expected_exit_code: 1,
+                ..Default::default()
+            },
+        )?;
+        assert!(
+            stderr.contains(
+                "branchless: the branchless.hooks.messageLint command rejected the message of commit"
+            ),
+            "{stderr}"
+        );
+        assert!(
+            stdout.contains("Aborting since a commit message was rejected."),
+            "{stdout}"
+        );
+
+        let stdout = git.smartlog()?;
+        assert!(stdout.contains("62fc20d create test1.txt"), "{stdout}");
+    }
+
+    git.run(&["config", "branchless.hooks.messageLint", "grep -q create"])?;
+    {
+        git.branchless(
+            "move",
+            &["--in-memory", "-x", &test1_oid.to_string(), "-d", "master"],
+        )?;
+
+        let stdout = git.smartlog()?;
+        assert!(!stdout.contains("62fc20d"), "{stdout}");
+        assert!(stdout.contains("create test1.txt"), "{stdout}");
+    }
+
+    Ok(())
+}
+
+#[test]
+fn test_move_message_lint_on_disk() -> eyre::Result<()> {
+    let git = make_git()?;
+
+    if !git.supports_reference_transactions()? {
+        return Ok(());
+    }
+
+    git.init_repo()?;
+    git.detach_head()?;
+    let test1_oid = git.commit_file("test1", 1)?;
+    git.run(&["checkout", "master"])?;
+    git.commit_file("test2", 2)?;
+
+    git.run(&["config", "branchless.hooks.messageLint", "grep -q ABC-"])?;
+    {
+        let (stdout, _stderr) = git.branchless(
+            "move",
+            &["--on-disk", "-x", &test1_oid.to_string(), "-d", "master"],
+        )?;
+        assert!(
+            stdout.contains("Aborting since a commit message was rejected."),
+            "{stdout}"
+        );
+
+        let stdout = git.smartlog()?;
+        insta::assert_snapshot!(stdout, @r###"
+        O f777ecc create initial.txt
+        |\
+        | o 62fc20d create test1.txt
+        |
+        @ fe65c1f (> master) create test2.txt
+        "###);
+    }
+
+    Ok(())
+}
+
 #[test]
 fn test_move_delete_checked_out_branch() -> eyre::Result<()> {
     let git = make_git()?;
@@ -3618,6 +3799,90 @@ fn test_move_with_unstaged_changes() -> eyre::Result<()> {
     Ok(())
 }
 
+#[test]
+fn test_move_with_unstaged_changes_auto_stash() -> eyre::Result<()> {
+    let git = make_git()?;
+
+    git.init_repo()?;
+    git.run(&["config", "branchless.restack.preserveTimestamps", "true"])?;
+    git.run(&["config", "branchless.autoStash", "true"])?;
+
+    git.commit_file("test1", 1)?;
+    git.commit_file("test2", 2)?;
+    git.run(&["checkout", "HEAD~"])?;
+    git.commit_file("test3", 3)?;
+
+    git.write_file_txt("test3", "new contents")?;
+    git.branchless("move", &["--on-disk", "-d", "master"])?;
+
+    {
+        let (stdout, _stderr) = git.run(&["status", "--short"])?;
+        assert_eq!(stdout, " M test3.txt\n");
+    }
+    {
+        let contents = std::fs::read_to_string(git.repo_path.join("test3.txt"))?;
+        assert_eq!(contents, "new contents");
+    }
+
+    Ok(())
+}
+
+#[test]
+fn test_move_auto_stash_reapplied_after_continue() -> eyre::Result<()> {
+    let git = make_git()?;
+
+    if !git.supports_reference_transactions()? {
+        return Ok(());
+    }
+    git.init_repo()?;
+    git.run(&["config", "branchless.restack.preserveTimestamps", "true"])?;
+    git.run(&["config", "branchless.autoStash", "true"])?;
+
+    git.commit_file("test1", 1)?;
+    git.run(&["checkout", "HEAD~"])?;
+    git.commit_file_with_contents("test1", 2, "conflicting contents")?;
+
+    git.write_file_txt("initial", "stashed contents")?;
+    {
+        let (stdout, _stderr) = git.branchless_with_options(
+            "move",
+            &["--on-disk", "-d", "master"],
+            &GitRunOptions {
+                expected_exit_code: 1,
+                ..Default::default()
+            },
+        )?;
+        assert!(
+            stdout.contains(
+                "The uncommitted changes will be reapplied once the rebase is continued."
+            ),
+            "{stdout}"
+        );
+    }
+
+    git.write_file_txt("test1", "resolved contents")?;
+    git.run(&["add", "test1.txt"])?;
+    {
+        // Git redirects the output of the `post-rewrite` hook to stderr.
+        let (stdout, stderr) = git.run(&["rebase", "--continue"])?;
+        assert!(
+            format!("{stdout}{stderr}").contains("Reapplied stashed uncommitted changes."),
+            "{stdout}{stderr}"
+        );
+    }
+
+    {
+        let (stdout, _stderr) = git.run(&["status", "--short"])?;
+        assert_eq!(stdout, " M initial.txt\n");
+    }
+    {
+        let contents = std::fs::read_to_string(git.repo_path.join("initial.txt"))?;
+        assert_eq!(contents, "stashed contents");
+    }
+
+    Ok(())
+}
+
 #[test]
 fn test_move_merge_commit() -> eyre::Result<()> {
     let git = make_git()?;
@@ -4821,6 +5086,83 @@ fn test_move_public_commit() -> eyre::Result<()> {
     Ok(())
 }
 
+#[test]
+fn test_move_protected_branch_commit() -> eyre::Result<()> {
+    let git = make_git()?;
+
+    if !git.supports_reference_transactions()? {
+        return Ok(());
+    }
+    git.init_repo()?;
+    git.detach_head()?;
+
+    git.commit_file("test1", 1)?;
+    git.commit_file("test2", 2)?;
+    git.run(&["branch", "release/1.0"])?;
+    git.run(&["config", "branchless.protectedBranches", "release/*"])?;
+
+    {
+        let (stdout, _stderr) = git.branchless_with_options(
+            "move",
+            &["-x", ".", "-d", "master"],
+            &GitRunOptions {
+                expected_exit_code: 1,
+                ..Default::default()
+            },
+        )?;
+        insta::assert_snapshot!(stdout, @r###"
+        You are trying to rewrite 1 protected commit, such as: 96d1c37 create test2.txt
+        These commits are reachable from a branch matching branchless.protectedBranches,
+        so they are likely shared with your collaborators.
+        Retry with -f/--force-rewrite to proceed anyways.
+        "###);
+    }
+
+    {
+        let (stdout, _stderr) = git.run(&["config", "branchless.protectedBranches", "hotfix/*"])?;
+        insta::assert_snapshot!(stdout, @"");
+    }
+    git.branchless("move", &["-x", ".", "-d", "master"])?;
+
+    Ok(())
+}
+
+#[test]
+fn test_move_tagged_commit() -> eyre::Result<()> {
+    let git = make_git()?;
+
+    if !git.supports_reference_transactions()? {
+        return Ok(());
+    }
+    git.init_repo()?;
+    git.detach_head()?;
+
+    git.commit_file("test1", 1)?;
+    git.commit_file("test2", 2)?;
+    git.run(&["tag", "v1"])?;
+
+    {
+        let (stdout, _stderr) = git.branchless_with_options(
+            "move",
+            &["-x", ".", "-d", "master"],
+            &GitRunOptions {
+                expected_exit_code: 1,
+                ..Default::default()
+            },
+        )?;
+        insta::assert_snapshot!(stdout, @r###"
+        You are trying to rewrite 1 tagged commit, such as: 96d1c37 create test2.txt
+        Tags usually mark released versions, which shouldn't change.
+        Retry with -f/--force-rewrite to proceed anyways, or set
+        branchless.protectTags to false.
+        "###);
+    }
+
+    git.branchless("move", &["-x", ".", "-d", "master", "-f"])?;
+
+    Ok(())
+}
+
 #[test]
 fn test_move_delete_branch_config_entry() -> eyre::Result<()> {
     let git = make_git()?;
@@ -6320,3 +6662,92 @@ fn test_worktree_rebase_in_memory() -> eyre::Result<()> {
 
     Ok(())
 }
+
+#[test]
+fn test_move_submodule_gitlink_in_memory() -> eyre::Result<()> {
+    let git = make_git()?;
+    git.init_repo()?;
+
+    let test1_oid = git.commit_file("test1", 1)?;
+    git.commit_file("test2", 2)?;
+
+    // Record a gitlink entry without cloning an actual submodule. The empty
+    // directory stands in for an unpopulated submodule.
+    git.run(&["checkout", &test1_oid.to_string()])?;
+    std::fs::create_dir(git.repo_path.join("submodule"))?;
+    git.run(&[
+        "update-index",
+        "--add",
+        "--cacheinfo",
+        &format!("160000,{test1_oid},submodule"),
+    ])?;
+    git.run(&["commit", "-m", "add submodule"])?;
+
+    git.branchless("move", &["--in-memory", "-d", "master"])?;
+
+    let (stdout, _stderr) = git.run(&["ls-tree", "HEAD", "submodule"])?;
+    assert_eq!(stdout, format!("160000 commit {test1_oid}\tsubmodule\n"));
+    let (stdout, _stderr) = git.run(&["rev-parse", "HEAD^"])?;
+    let (master_oid, _stderr) = git.run(&["rev-parse", "master"])?;
+    assert_eq!(stdout, master_oid);
+
+    Ok(())
+}
+
+#[test]
+fn test_move_autosquash() -> eyre::Result<()> {
+    let git = make_git()?;
+
+    git.init_repo()?;
+    git.detach_head()?;
+    git.commit_file("test1", 1)?;
+    let test2_oid = git.commit_file("test2", 2)?;
+    git.write_file_txt("test2", "updated test2 contents\n")?;
+    git.run(&["commit", "-a", "--fixup", &test2_oid.to_string()])?;
+    git.commit_file("test3", 3)?;
+
+    git.branchless(
+        "move",
+        &["-s", &test2_oid.to_string(), "-d", "master", "--autosquash"],
+    )?;
+
+    {
+        let (stdout, _stderr) = git.run(&["log", "--format=%s", "master..HEAD"])?;
+        assert_eq!(stdout, "create test3.txt\ncreate test2.txt\n");
+        let (stdout, _stderr) = git.run(&["show", "HEAD^:test2.txt"])?;
+        assert_eq!(stdout, "updated test2 contents\n");
+    }
+
+    Ok(())
+}
+
+#[test]
+fn test_move_custom_merge_driver() -> eyre::Result<()> {
+    let git = make_git()?;
+
+    git.init_repo()?;
+    git.write_file(".gitattributes", "test1.txt merge=theirs\n")?;
+    git.run(&["config", "merge.theirs.driver", "cat %B > %A"])?;
+    git.commit_file_with_contents("test1", 1, "line 1\nline 2\nline 3\n")?;
+    git.detach_head()?;
+    let test1_oid =
+        git.commit_file_with_contents("test1", 2, "updated line 1\nline 2\nline 3\n")?;
+    git.run(&["checkout", "master"])?;
+    git.commit_file_with_contents("test1", 3, "line 1\nline 2\nupdated line 3\n")?;
+    git.run(&["checkout", &test1_oid.to_string()])?;
+
+    git.branchless(
+        "move",
+        &["-s", &test1_oid.to_string(), "-d", "master", "--in-memory"],
+    )?;
+
+    {
+        let (stdout, _stderr) = git.run(&["show", "HEAD:test1.txt"])?;
+        assert_eq!(stdout, "updated line 1\nline 2\nline 3\n");
+        let (head_parent, _stderr) = git.run(&["rev-parse", "HEAD^"])?;
+        let (master_oid, _stderr) = git.run(&["rev-parse", "master"])?;
+        assert_eq!(head_parent, master_oid);
+    }
+
+    Ok(())
+}
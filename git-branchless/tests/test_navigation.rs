@@ -971,3 +971,78 @@ fn test_switch_detach() -> eyre::Result<()> {
 
     Ok(())
 }
+
+#[test]
+fn test_navigation_conflict() -> eyre::Result<()> {
+    let git = make_git()?;
+
+    git.init_repo()?;
+    git.detach_head()?;
+    let test1_oid = git.commit_file("test1", 1)?;
+    let test2_oid = git.commit_file("test2", 2)?;
+    let test3_oid = git.commit_file_with_contents(
+        "test3",
+        3,
+        "<<<<<<< HEAD\nours\n=======\ntheirs\n>>>>>>> other\n",
+    )?;
+    git.commit_file("test4", 4)?;
+    git.run(&["checkout", &test1_oid.to_string()])?;
+
+    {
+        git.branchless("next", &["--conflict"])?;
+        let (stdout, _stderr) = git.run(&["rev-parse", "HEAD"])?;
+        assert_eq!(stdout.trim(), test3_oid.to_string());
+    }
+
+    {
+        let (stdout, _stderr) = git.branchless_with_options(
+            "next",
+            &["--conflict"],
+            &GitRunOptions {
+                expected_exit_code: 1,
+                ..Default::default()
+            },
+        )?;
+        assert!(
+            stdout.contains(
+                "No more child commits matching conflicts() to go to after traversing 0 children."
+            ),
+            "{stdout}"
+        );
+    }
+
+    {
+        git.branchless("next", &[])?;
+        git.branchless("prev", &["--matching", "paths.changed(test2.txt)"])?;
+        let (stdout, _stderr) = git.run(&["rev-parse", "HEAD"])?;
+        assert_eq!(stdout.trim(), test2_oid.to_string());
+    }
+
+    Ok(())
+}
+
+#[test]
+fn test_navigation_unsubmitted() -> eyre::Result<()> {
+    let git = make_git()?;
+
+    git.init_repo()?;
+    git.detach_head()?;
+    let test1_oid = git.commit_file("test1", 1)?;
+    git.write_file_txt("test2", "test2 contents\n")?;
+    git.run(&["add", "."])?;
+    git.run(&[
+        "commit",
+        "-m",
+        "create test2.txt\n\nDifferential Revision: https://phabricator.example.com/D123",
+    ])?;
+    let test3_oid = git.commit_file("test3", 3)?;
+    git.run(&["checkout", &test1_oid.to_string()])?;
+
+    {
+        git.branchless("next", &["--unsubmitted"])?;
+        let (stdout, _stderr) = git.run(&["rev-parse", "HEAD"])?;
+        assert_eq!(stdout.trim(), test3_oid.to_string());
+    }
+
+    Ok(())
+}
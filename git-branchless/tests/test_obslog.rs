@@ -0,0 +1,74 @@
+use lib::testing::make_git;
+
+#[test]
+fn test_obslog_amend() -> eyre::Result<()> {
+    let git = make_git()?;
+
+    if !git.supports_reference_transactions()? {
+        return Ok(());
+    }
+
+    git.init_repo()?;
+    git.detach_head()?;
+    git.commit_file("test1", 1)?;
+    git.commit_file("test2", 2)?;
+
+    {
+        let (stdout, _stderr) = git.branchless("obslog", &[])?;
+        insta::assert_snapshot!(stdout, @r###"
+        No rewrites recorded for commit: 96d1c37 create test2.txt
+        "###);
+    }
+
+    git.write_file_txt("test2", "updated contents")?;
+    git.branchless("amend", &[])?;
+    git.write_file_txt("test2", "updated contents again")?;
+    git.branchless("amend", &[])?;
+
+    {
+        let (stdout, _stderr) = git.branchless("obslog", &[])?;
+        insta::assert_snapshot!(stdout, @r###"
+        96d1c37 create test2.txt
+            rewritten as 7ac317b create test2.txt by amend
+        7ac317b create test2.txt
+            rewritten as dcf1d6e create test2.txt by amend
+        "###);
+    }
+
+    {
+        let (stdout, _stderr) = git.branchless("obslog", &["HEAD~"])?;
+        insta::assert_snapshot!(stdout, @r###"
+        No rewrites recorded for commit: 62fc20d create test1.txt
+        "###);
+    }
+
+    Ok(())
+}
+
+#[test]
+fn test_obslog_cherry_pick_and_revert() -> eyre::Result<()> {
+    let git = make_git()?;
+
+    git.init_repo()?;
+    git.detach_head()?;
+    let test1_oid = git.commit_file("test1", 1)?;
+    git.run(&["checkout", "master"])?;
+
+    git.run(&["cherry-pick", "-x", &test1_oid.to_string()])?;
+    {
+        let (stdout, _stderr) = git.branchless("obslog", &[])?;
+        assert!(
+            stdout.contains("copied from 62fc20d create test1.txt"),
+            "{stdout}"
+        );
+    }
+
+    git.run(&["revert", "--no-edit", "HEAD"])?;
+    {
+        let (stdout, _stderr) = git.branchless("obslog", &[])?;
+        assert!(stdout.contains("Revert \"create test1.txt\""), "{stdout}");
+        assert!(stdout.contains("    reverts "), "{stdout}");
+    }
+
+    Ok(())
+}
@@ -0,0 +1,145 @@
+use lib::testing::{
+    make_git, make_git_with_remote_repo, GitInitOptions, GitRunOptions, GitWrapperWithRemoteRepo,
+};
+
+#[test]
+fn test_phase() -> eyre::Result<()> {
+    let git = make_git()?;
+
+    git.init_repo()?;
+    git.detach_head()?;
+    git.commit_file("test1", 1)?;
+    git.commit_file("test2", 2)?;
+
+    {
+        let (stdout, _stderr) = git.branchless("phase", &["master", "draft()"])?;
+        insta::assert_snapshot!(stdout, @r###"
+        public: f777ecc create initial.txt
+        draft: 62fc20d create test1.txt
+        draft: 96d1c37 create test2.txt
+        "###);
+    }
+
+    {
+        let (stdout, _stderr) = git.branchless("phase", &["--secret", "HEAD~"])?;
+        insta::assert_snapshot!(stdout, @r###"
+        Recorded 1 commit as secret.
+        "###);
+    }
+    {
+        let (stdout, _stderr) = git.branchless("phase", &["draft()"])?;
+        insta::assert_snapshot!(stdout, @r###"
+        secret: 62fc20d create test1.txt
+        secret: 96d1c37 create test2.txt
+        "###);
+    }
+
+    {
+        let (stdout, _stderr) = git.branchless("phase", &["--public", "HEAD~"])?;
+        insta::assert_snapshot!(stdout, @r###"
+        Recorded 1 commit as public.
+        "###);
+    }
+    {
+        let (stdout, _stderr) = git.branchless("phase", &["HEAD~", "HEAD"])?;
+        insta::assert_snapshot!(stdout, @r###"
+        public: 62fc20d create test1.txt
+        draft: 96d1c37 create test2.txt
+        "###);
+    }
+
+    {
+        let (stdout, _stderr) = git.branchless("phase", &["--draft", "master"])?;
+        insta::assert_snapshot!(stdout, @r###"
+        Recorded 1 commit as draft.
+        (1 commit is still public, since it is reachable from the main branch.)
+        "###);
+    }
+
+    Ok(())
+}
+
+#[test]
+fn test_phase_hide_public_commit() -> eyre::Result<()> {
+    let git = make_git()?;
+
+    git.init_repo()?;
+    git.detach_head()?;
+    git.commit_file("test1", 1)?;
+    git.commit_file("test2", 2)?;
+    git.branchless("phase", &["--public", "HEAD"])?;
+
+    {
+        let (stdout, stderr) = git.branchless_with_options(
+            "hide",
+            &["HEAD~"],
+            &GitRunOptions {
+                expected_exit_code: 1,
+                ..Default::default()
+            },
+        )?;
+        insta::assert_snapshot!(stderr, @r###"
+        Cannot hide 1 commit recorded as public:
+        62fc20d create test1.txt
+        To hide them anyway, first run: git branchless phase --draft <commits>
+        "###);
+        insta::assert_snapshot!(stdout, @"");
+    }
+
+    {
+        let (stdout, _stderr) = git.branchless("phase", &["--draft", "HEAD~"])?;
+        insta::assert_snapshot!(stdout, @r###"
+        Recorded 1 commit as draft.
+        "###);
+    }
+    {
+        let (stdout, _stderr) = git.branchless("phase", &["HEAD~", "HEAD"])?;
+        insta::assert_snapshot!(stdout, @r###"
+        draft: 62fc20d create test1.txt
+        draft: 96d1c37 create test2.txt
+        "###);
+    }
+
+    Ok(())
+}
+
+#[test]
+fn test_phase_protected_remote() -> eyre::Result<()> {
+    let GitWrapperWithRemoteRepo {
+        temp_dir: _guard,
+        original_repo,
+        cloned_repo,
+    } = make_git_with_remote_repo()?;
+
+    if !original_repo.supports_reference_transactions()? {
+        return Ok(());
+    }
+
+    original_repo.init_repo()?;
+    original_repo.commit_file("test1", 1)?;
+    original_repo.clone_repo_into(&cloned_repo, &["--branch", "master"])?;
+    cloned_repo.init_repo_with_options(&GitInitOptions {
+        make_initial_commit: false,
+        ..Default::default()
+    })?;
+    cloned_repo.run(&["config", "branchless.core.protectedRemotes", "origin"])?;
+
+    cloned_repo.run(&["checkout", "-b", "feature"])?;
+    cloned_repo.commit_file("test2", 2)?;
+    {
+        let (stdout, _stderr) = cloned_repo.branchless("phase", &["feature"])?;
+        insta::assert_snapshot!(stdout, @r###"
+        draft: 96d1c37 create test2.txt
+        "###);
+    }
+
+    cloned_repo.run(&["push", "origin", "feature"])?;
+    {
+        let (stdout, _stderr) = cloned_repo.branchless("phase", &["feature"])?;
+        insta::assert_snapshot!(stdout, @r###"
+        public: 96d1c37 create test2.txt
+        "###);
+    }
+
+    Ok(())
+}
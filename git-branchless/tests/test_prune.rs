@@ -0,0 +1,74 @@
+use lib::testing::make_git;
+
+#[test]
+fn test_prune() -> eyre::Result<()> {
+    let git = make_git()?;
+
+    git.init_repo()?;
+    git.detach_head()?;
+    let test1_oid = git.commit_file("test1", 1)?;
+    git.run(&["checkout", "master"])?;
+    git.branchless("hide", &[&test1_oid.to_string()])?;
+    let pin_ref = format!("refs/branchless/{test1_oid}");
+
+    {
+        let (stdout, _stderr) = git.branchless("prune", &[])?;
+        insta::assert_snapshot!(stdout, @"No hidden commits to prune.
+");
+    }
+
+    {
+        // Shouldn't overflow when converting the number of days to seconds.
+        let (stdout, _stderr) = git.branchless(
+            "prune",
+            &["--older-than", &u64::MAX.to_string(), "--dry-run"],
+        )?;
+        insta::assert_snapshot!(stdout, @"No hidden commits to prune.
+");
+    }
+
+    {
+        let (stdout, _stderr) = git.branchless("prune", &["--older-than", "0", "--dry-run"])?;
+        insta::assert_snapshot!(stdout, @r###"
+        Would prune 1 hidden commit:
+        62fc20d create test1.txt
+        (This was a dry-run; run without --dry-run to apply changes.)
+        "###);
+        let (stdout, _stderr) = git.run(&["show-ref"])?;
+        assert!(stdout.contains(&pin_ref), "{stdout}");
+    }
+
+    {
+        let (stdout, _stderr) = git.branchless("prune", &["--older-than", "0"])?;
+        insta::assert_snapshot!(stdout, @r###"
+        Pruned 1 hidden commit:
+        62fc20d create test1.txt
+        To delete them from the repository, run: git gc
+        "###);
+        let (stdout, _stderr) = git.run(&["show-ref"])?;
+        assert!(!stdout.contains(&pin_ref), "{stdout}");
+    }
+
+    Ok(())
+}
+
+#[test]
+fn test_prune_keeps_rewritten_and_branch_commits() -> eyre::Result<()> {
+    let git = make_git()?;
+
+    git.init_repo()?;
+    git.detach_head()?;
+    git.commit_file("test1", 1)?;
+    git.run(&["commit", "--amend", "-m", "amended test1"])?;
+    git.commit_file("test2", 2)?;
+    git.run(&["branch", "foo"])?;
+    git.branchless("hide", &["--no-delete-branches", "foo"])?;
+
+    {
+        let (stdout, _stderr) = git.branchless("prune", &["--older-than", "0"])?;
+        insta::assert_snapshot!(stdout, @"No hidden commits to prune.
+");
+    }
+
+    Ok(())
+}
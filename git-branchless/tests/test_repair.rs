@@ -1,4 +1,7 @@
-use lib::git::{BranchType, ReferenceName};
+use std::time::SystemTime;
+
+use lib::core::eventlog::{Event, EventLogDb};
+use lib::git::{BranchType, MaybeZeroOid, ReferenceName};
 use lib::testing::make_git;
 
 #[test]
@@ -32,9 +35,10 @@ fn test_repair_broken_commit() -> eyre::Result<()> {
     }
 
     {
-        let (stdout, _stderr) = git.branchless("repair", &["--no-dry-run"])?;
+        let (stdout, stderr) = git.branchless("repair", &["--no-dry-run"])?;
         insta::assert_snapshot!(stdout, @"Found and repaired 1 broken commit: 70deb1e28791d8e7dd5a1f0c871a51b91282562f
 ");
+        assert!(stderr.contains("so they can't be undone"), "{stderr}");
     }
 
     {
@@ -88,3 +92,56 @@ fn test_repair_broken_branch() -> eyre::Result<()> {
 
     Ok(())
 }
+
+#[test]
+fn test_repair_rewrite_cycle() -> eyre::Result<()> {
+    let git = make_git()?;
+    git.init_repo()?;
+
+    git.detach_head()?;
+    let test1_oid = git.commit_file("test1", 1)?;
+    let test2_oid = git.commit_file("test2", 2)?;
+
+    {
+        let repo = git.get_repo()?;
+        let conn = repo.get_db_conn()?;
+        let event_log_db = EventLogDb::new(&conn)?;
+        let event_tx_id = event_log_db.make_transaction_id(SystemTime::now(), "test")?;
+        event_log_db.add_events(vec![
+            Event::RewriteEvent {
+                timestamp: 0.0,
+                event_tx_id,
+                old_commit_oid: MaybeZeroOid::NonZero(test1_oid),
+                new_commit_oid: MaybeZeroOid::NonZero(test2_oid),
+            },
+            Event::RewriteEvent {
+                timestamp: 0.0,
+                event_tx_id,
+                old_commit_oid: MaybeZeroOid::NonZero(test2_oid),
+                new_commit_oid: MaybeZeroOid::NonZero(test1_oid),
+            },
+        ])?;
+    }
+
+    {
+        let (stdout, _stderr) = git.branchless("repair", &[])?;
+        insta::assert_snapshot!(stdout, @r###"
+        Found and repaired a cycle of rewrites: 62fc20d2a290daea0d52bdc2ed2ad4be6491010e -> 96d1c37a3d4363611c49f7e52186e189a04c531f -> 62fc20d2a290daea0d52bdc2ed2ad4be6491010e
+        (This was a dry-run; run with --no-dry-run to apply changes.)
+        "###);
+    }
+
+    {
+        let (stdout, _stderr) = git.branchless("repair", &["--no-dry-run"])?;
+        insta::assert_snapshot!(stdout, @r###"
+        Found and repaired a cycle of rewrites: 62fc20d2a290daea0d52bdc2ed2ad4be6491010e -> 96d1c37a3d4363611c49f7e52186e189a04c531f -> 62fc20d2a290daea0d52bdc2ed2ad4be6491010e
+        "###);
+    }
+
+    {
+        let (stdout, _stderr) = git.branchless("repair", &["--no-dry-run"])?;
+        insta::assert_snapshot!(stdout, @"");
+    }
+
+    Ok(())
+}
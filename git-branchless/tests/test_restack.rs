@@ -1,3 +1,7 @@
+use std::time::SystemTime;
+
+use lib::core::eventlog::{Event, EventLogDb};
+use lib::git::MaybeZeroOid;
 use lib::testing::{make_git, remove_rebase_lines, GitInitOptions, GitRunOptions};
 
 #[test]
@@ -563,3 +567,130 @@ fn test_restack_non_observed_branch_commit() -> eyre::Result<()> {
 
     Ok(())
 }
+
+#[test]
+fn test_restack_discarded_branch_commit() -> eyre::Result<()> {
+    let git = make_git()?;
+
+    git.init_repo()?;
+    git.detach_head()?;
+    git.commit_file("test1", 1)?;
+    let test2_oid = git.commit_file("test2", 2)?;
+    git.run(&["branch", "foo"])?;
+    git.run(&["checkout", "master"])?;
+
+    {
+        let repo = git.get_repo()?;
+        let conn = repo.get_db_conn()?;
+        let event_log_db = EventLogDb::new(&conn)?;
+        let event_tx_id = event_log_db.make_transaction_id(SystemTime::now(), "discard")?;
+        event_log_db.add_events(vec![Event::RewriteEvent {
+            timestamp: 0.0,
+            event_tx_id,
+            old_commit_oid: MaybeZeroOid::NonZero(test2_oid),
+            new_commit_oid: MaybeZeroOid::Zero,
+        }])?;
+    }
+
+    {
+        let (stdout, _stderr) = git.run(&["restack"])?;
+        assert!(
+            stdout.contains(
+                "Moved branch foo to 62fc20d create test1.txt, since its commit was discarded."
+            ),
+            "{stdout}"
+        );
+    }
+
+    {
+        let stdout = git.smartlog()?;
+        insta::assert_snapshot!(stdout, @r###"
+        @ f777ecc (> master) create initial.txt
+        |
+        o 62fc20d (foo) create test1.txt
+        "###);
+    }
+
+    Ok(())
+}
+
+#[test]
+fn test_restack_discards_upstream_applied_commits() -> eyre::Result<()> {
+    let git = make_git()?;
+
+    git.init_repo()?;
+    git.detach_head()?;
+    let test1_oid = git.commit_file("test1", 1)?;
+    let test2_oid = git.commit_file("test2", 2)?;
+    git.run(&["checkout", "master"])?;
+    git.run(&["cherry-pick", &test1_oid.to_string()])?;
+    git.run(&["checkout", &test2_oid.to_string()])?;
+
+    {
+        let (stdout, _stderr) = git.branchless("restack", &[])?;
+        assert!(
+            stdout.contains("Discarded commit (was already applied upstream as "),
+            "{stdout}"
+        );
+        assert!(stdout.contains("): 62fc20d create test1.txt"), "{stdout}");
+    }
+
+    {
+        // The remaining commit is restacked on top of the landed change.
+        let (head_parent_oid, _stderr) = git.run(&["rev-parse", "HEAD^"])?;
+        let (master_oid, _stderr) = git.run(&["rev-parse", "master"])?;
+        assert_eq!(head_parent_oid, master_oid);
+        let (stdout, _stderr) = git.run(&["log", "--format=%s", "HEAD"])?;
+        insta::assert_snapshot!(stdout, @r###"
+        create test2.txt
+        create test1.txt
+        create initial.txt
+        "###);
+        let stdout = git.smartlog()?;
+        assert!(!stdout.contains("62fc20d"), "{stdout}");
+        assert!(!stdout.contains("96d1c37"), "{stdout}");
+    }
+
+    {
+        let (stdout, _stderr) = git.branchless("restack", &[])?;
+        assert!(!stdout.contains("Discarded commit"), "{stdout}");
+    }
+
+    Ok(())
+}
+
+#[test]
+fn test_restack_autosquash() -> eyre::Result<()> {
+    let git = make_git()?;
+
+    git.init_repo()?;
+    git.detach_head()?;
+    let test1_oid = git.commit_file("test1", 1)?;
+    git.commit_file("test2", 2)?;
+    git.write_file_txt("test1", "updated test1 contents\n")?;
+    git.run(&["commit", "-a", "--fixup", &test1_oid.to_string()])?;
+    git.commit_file("test3", 3)?;
+    git.write_file_txt("test3", "updated test3 contents\n")?;
+    git.run(&["commit", "-a", "--squash", "HEAD", "-m", "reword test3"])?;
+
+    {
+        let (stdout, _stderr) = git.branchless("restack", &["--autosquash"])?;
+        assert!(stdout.contains("Finished restacking commits."), "{stdout}");
+    }
+
+    {
+        // The `squash!` commit is left in place, since its message would
+        // otherwise be lost.
+        let (stdout, _stderr) = git.run(&["log", "--format=%s", "master..HEAD"])?;
+        insta::assert_snapshot!(stdout, @r###"
+        squash! create test3.txt
+        create test3.txt
+        create test2.txt
+        create test1.txt
+        "###);
+        let (stdout, _stderr) = git.run(&["show", "HEAD~3:test1.txt"])?;
+        assert_eq!(stdout, "updated test1 contents\n");
+    }
+
+    Ok(())
+}
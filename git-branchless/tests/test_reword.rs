@@ -660,3 +660,262 @@ fn test_reword_merge_commit() -> eyre::Result<()> {
 
     Ok(())
 }
+
+#[test]
+fn test_reword_author_and_date() -> eyre::Result<()> {
+    let git = make_git()?;
+
+    git.init_repo()?;
+    git.detach_head()?;
+    git.commit_file("test1", 1)?;
+    git.commit_file("test2", 2)?;
+
+    git.branchless(
+        "reword",
+        &[
+            "stack()",
+            "--author",
+            "Jane Doe <jane@example.com>",
+            "--date",
+            "@1672625045 +0100",
+        ],
+    )?;
+
+    {
+        let (stdout, _stderr) = git.run(&[
+            "log",
+            "--format=%s | %an <%ae> | %ad",
+            "--date=raw",
+            "master..HEAD",
+        ])?;
+        insta::assert_snapshot!(stdout, @r###"
+        create test2.txt | Jane Doe <jane@example.com> | 1672625045 +0100
+        create test1.txt | Jane Doe <jane@example.com> | 1672625045 +0100
+        "###);
+    }
+
+    {
+        let (_stdout, stderr) = git.branchless_with_options(
+            "reword",
+            &["--author", "Jane Doe"],
+            &GitRunOptions {
+                expected_exit_code: 1,
+                ..Default::default()
+            },
+        )?;
+        insta::assert_snapshot!(stderr, @r###"
+        Invalid author "Jane Doe": expected the form 'Name <email>'.
+        Aborting.
+        "###);
+    }
+
+    Ok(())
+}
+
+#[test]
+fn test_reword_trailers() -> eyre::Result<()> {
+    let git = make_git()?;
+
+    if !git.supports_committer_date_is_author_date()? {
+        return Ok(());
+    }
+    git.init_repo()?;
+    git.detach_head()?;
+    git.commit_file("test1", 1)?;
+    git.commit_file("test2", 2)?;
+
+    git.branchless(
+        "reword",
+        &[
+            "stack()",
+            "--add-trailer",
+            "Reviewed-by: Jane Doe <jane@example.com>",
+            "--trailers",
+            "gerrit",
+        ],
+    )?;
+    let (change_ids, _stderr) = git.run(&[
+        "log",
+        "--format=%(trailers:key=Change-Id,valueonly)",
+        "HEAD~2..HEAD",
+    ])?;
+    {
+        let (stdout, _stderr) = git.run(&["log", "--format=%B", "HEAD~2..HEAD"])?;
+        assert_eq!(
+            stdout
+                .matches("Reviewed-by: Jane Doe <jane@example.com>")
+                .count(),
+            2
+        );
+        assert_eq!(stdout.matches("Change-Id: I").count(), 2);
+    }
+
+    git.branchless(
+        "reword",
+        &[
+            "stack()",
+            "--remove-trailer",
+            "reviewed-by",
+            "--trailers",
+            "gerrit",
+        ],
+    )?;
+    {
+        let (stdout, _stderr) = git.run(&["log", "--format=%B", "HEAD~2..HEAD"])?;
+        assert!(!stdout.contains("Reviewed-by"), "{stdout}");
+        let (stdout, _stderr) = git.run(&[
+            "log",
+            "--format=%(trailers:key=Change-Id,valueonly)",
+            "HEAD~2..HEAD",
+        ])?;
+        assert_eq!(stdout, change_ids);
+    }
+
+    {
+        let (_stdout, stderr) = git.branchless_with_options(
+            "reword",
+            &["--trailers", "github"],
+            &GitRunOptions {
+                expected_exit_code: 1,
+                ..Default::default()
+            },
+        )?;
+        assert!(
+            stderr.contains("No trailers are configured for \"github\"."),
+            "{stderr}"
+        );
+    }
+
+    Ok(())
+}
+
+#[test]
+#[cfg(unix)]
+fn test_reword_runs_commit_message_hooks() -> eyre::Result<()> {
+    use std::{fs, os::unix::prelude::PermissionsExt};
+
+    let git = make_git()?;
+
+    if !git.supports_committer_date_is_author_date()? {
+        return Ok(());
+    }
+    git.init_repo()?;
+    git.detach_head()?;
+    git.commit_file("test1", 1)?;
+
+    let hooks_dir = git.repo_path.join(".git").join("hooks");
+    let write_hook = |name: &str, contents: &str| -> eyre::Result<()> {
+        let path = hooks_dir.join(name);
+        fs::write(&path, format!("#!/bin/sh\n{contents}\n"))?;
+        fs::set_permissions(&path, fs::Permissions::from_mode(0o755))?;
+        Ok(())
+    };
+    write_hook(
+        "prepare-commit-msg",
+        r#"test "$2" = message && printf '[ABC-123] %s' "$(cat "$1")" > "$1""#,
+    )?;
+    write_hook(
+        "commit-msg",
+        r#"grep -q 'forbidden' "$1" && exit 1; printf '\nHook-Ran: yes\n' >> "$1""#,
+    )?;
+
+    {
+        git.branchless("reword", &["--message", "foo"])?;
+        let (stdout, _stderr) = git.run(&["log", "-1", "--format=%B"])?;
+        assert_eq!(stdout, "[ABC-123] foo\n\nHook-Ran: yes\n\n");
+    }
+
+    {
+        let (_stdout, stderr) = git.branchless_with_options(
+            "reword",
+            &["--message", "forbidden"],
+            &GitRunOptions {
+                expected_exit_code: 1,
+                ..Default::default()
+            },
+        )?;
+        assert!(
+            stderr.contains(
+                "Aborting reword, since the commit-msg hook rejected the message of commit:"
+            ),
+            "{stderr}"
+        );
+        let (stdout, _stderr) = git.run(&["log", "-1", "--format=%s"])?;
+        assert_eq!(stdout, "[ABC-123] foo\n");
+    }
+
+    Ok(())
+}
+
+#[test]
+#[cfg(unix)]
+fn test_reword_runs_prepare_commit_msg_hook_before_editor() -> eyre::Result<()> {
+    use std::collections::HashMap;
+    use std::{fs, os::unix::prelude::PermissionsExt};
+
+    let git = make_git()?;
+
+    if !git.supports_committer_date_is_author_date()? {
+        return Ok(());
+    }
+    git.init_repo()?;
+    git.detach_head()?;
+    git.commit_file("test1", 1)?;
+
+    let git_dir = git.repo_path.join(".git");
+    let write_script = |path: &std::path::Path, contents: &str| -> eyre::Result<()> {
+        fs::write(path, format!("#!/bin/sh\n{contents}\n"))?;
+        fs::set_permissions(path, fs::Permissions::from_mode(0o755))?;
+        Ok(())
+    };
+    write_script(
+        &git_dir.join("hooks").join("prepare-commit-msg"),
+        r#"test "$2" = commit && printf '[ABC-123] %s' "$(cat "$1")" > "$1""#,
+    )?;
+    let editor_input_path = git_dir.join("editor-input.txt");
+    let editor_path = git_dir.join("editor.sh");
+    write_script(
+        &editor_path,
+        &format!(
+            r#"cp "$1" '{}' && printf 'reworded\n' > "$1""#,
+            editor_input_path.display()
+        ),
+    )?;
+
+    git.branchless_with_options(
+        "reword",
+        &[],
+        &GitRunOptions {
+            env: {
+                let mut env: HashMap<String, String> = HashMap::new();
+                env.insert(
+                    "GIT_EDITOR".to_string(),
+                    editor_path.to_string_lossy().into_owned(),
+                );
+                env
+            },
+            ..Default::default()
+        },
+    )?;
+
+    let editor_input = fs::read_to_string(&editor_input_path)?;
+    assert!(
+        editor_input.starts_with("[ABC-123] create test1.txt"),
+        "{editor_input}"
+    );
+    let (stdout, _stderr) = git.run(&["log", "-1", "--format=%B"])?;
+    assert_eq!(stdout, "reworded\n\n");
+
+    let leftover_message_files = fs::read_dir(&git_dir)?
+        .filter_map(|entry| entry.ok())
+        .filter(|entry| {
+            entry
+                .file_name()
+                .to_string_lossy()
+                .starts_with("BRANCHLESS_EDITMSG")
+        })
+        .count();
+    assert_eq!(leftover_message_files, 0);
+
+    Ok(())
+}
@@ -0,0 +1,119 @@
+use lib::testing::{make_git, GitRunOptions};
+
+#[test]
+fn test_rewrite_metadata_map_author() -> eyre::Result<()> {
+    let git = make_git()?;
+
+    git.init_repo()?;
+    git.detach_head()?;
+    git.commit_file("test1", 1)?;
+    git.run(&["config", "user.email", "old@example.com"])?;
+    git.commit_file("test2", 2)?;
+    git.commit_file("test3", 3)?;
+
+    {
+        let (stdout, _stderr) = git.branchless(
+            "rewrite-metadata",
+            &["--map-author", "OLD@example.com:new@example.com"],
+        )?;
+        assert!(
+            stdout.ends_with(
+                "Rewrote the metadata of 2 commits. If this was unintentional, run: git undo\n"
+            ),
+            "{stdout}"
+        );
+    }
+
+    {
+        let (stdout, _stderr) = git.run(&["log", "--format=%s | %ae | %ce", "master..HEAD"])?;
+        insta::assert_snapshot!(stdout, @r###"
+        create test3.txt | new@example.com | new@example.com
+        create test2.txt | new@example.com | new@example.com
+        create test1.txt | test@example.com | test@example.com
+        "###);
+    }
+
+    {
+        let (stdout, _stderr) = git.branchless(
+            "rewrite-metadata",
+            &["--map-author", "old@example.com:new@example.com"],
+        )?;
+        insta::assert_snapshot!(stdout, @"No commits matched the author mappings; nothing to do.
+");
+    }
+
+    {
+        let (_stdout, stderr) = git.branchless_with_options(
+            "rewrite-metadata",
+            &["--map-author", "old@example.com"],
+            &GitRunOptions {
+                expected_exit_code: 1,
+                ..Default::default()
+            },
+        )?;
+        insta::assert_snapshot!(stderr, @r###"
+        Invalid author mapping "old@example.com": expected the form 'old@example.com:new@example.com'.
+        "###);
+    }
+
+    Ok(())
+}
+
+#[test]
+fn test_rewrite_metadata_preserves_names_and_dates() -> eyre::Result<()> {
+    let git = make_git()?;
+
+    git.init_repo()?;
+    git.detach_head()?;
+    git.run(&["config", "user.email", "old@example.com"])?;
+    git.commit_file("test1", 1)?;
+
+    let (before, _stderr) = git.run(&["log", "-1", "--format=%an | %ad | %cn | %cd"])?;
+    git.branchless(
+        "rewrite-metadata",
+        &["--map-author", "old@example.com:new@example.com"],
+    )?;
+    let (after, _stderr) = git.run(&["log", "-1", "--format=%an | %ad | %cn | %cd"])?;
+    assert_eq!(before, after);
+
+    {
+        let (stdout, _stderr) = git.run(&["log", "-1", "--format=%ae | %ce"])?;
+        insta::assert_snapshot!(stdout, @r###"
+        new@example.com | new@example.com
+        "###);
+    }
+
+    Ok(())
+}
+
+#[test]
+fn test_rewrite_metadata_merge_descendant() -> eyre::Result<()> {
+    let git = make_git()?;
+
+    git.init_repo()?;
+    git.detach_head()?;
+    git.run(&["config", "user.email", "old@example.com"])?;
+    git.commit_file("test1", 1)?;
+    git.run(&["config", "user.email", "test@example.com"])?;
+    git.commit_file("test2", 2)?;
+    git.run(&["checkout", "HEAD^"])?;
+    git.commit_file("test3", 3)?;
+    git.run(&["merge", "--no-edit", "HEAD@{2}"])?;
+
+    {
+        let (_stdout, stderr) = git.branchless_with_options(
+            "rewrite-metadata",
+            &["--map-author", "old@example.com:new@example.com"],
+            &GitRunOptions {
+                expected_exit_code: 1,
+                ..Default::default()
+            },
+        )?;
+        assert!(
+            stderr.contains("merge commits can't be restacked in-memory"),
+            "{stderr}"
+        );
+    }
+
+    Ok(())
+}
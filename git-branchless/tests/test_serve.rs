@@ -0,0 +1,200 @@
+use std::io::{BufRead, BufReader, Read, Write};
+use std::net::TcpStream;
+use std::process::{Child, Command, Stdio};
+
+use lib::testing::{make_git, Git};
+use serde_json::{json, Value};
+
+/// Start `git branchless serve` on any available port, and return the port
+/// and the token to authenticate with.
+fn spawn_server(git: &Git) -> eyre::Result<(Child, u16, String)> {
+    let mut child = Command::new(&git.path_to_git)
+        .current_dir(&git.repo_path)
+        .args(["branchless", "serve"])
+        .env_clear()
+        .envs(git.get_base_env(0))
+        .stdout(Stdio::piped())
+        .spawn()?;
+    let mut stdout = BufReader::new(child.stdout.take().unwrap());
+    let mut line = String::new();
+    stdout.read_line(&mut line)?;
+    let port = match line.trim().strip_prefix("Listening on http://127.0.0.1:") {
+        Some(port) => port.parse()?,
+        None => eyre::bail!("Unexpected server output: {line:?}"),
+    };
+    line.clear();
+    stdout.read_line(&mut line)?;
+    let token = match line.trim().strip_prefix("Authorization: Bearer ") {
+        Some(token) => token.to_owned(),
+        None => eyre::bail!("Unexpected server output: {line:?}"),
+    };
+    // Keep reading the output of commands run by the server, so that it
+    // doesn't block on a full pipe.
+    std::thread::spawn(move || std::io::copy(&mut stdout, &mut std::io::sink()));
+    Ok((child, port, token))
+}
+
+fn send_request(port: u16, request: &str) -> eyre::Result<String> {
+    let mut stream = TcpStream::connect(("127.0.0.1", port))?;
+    stream.write_all(request.as_bytes())?;
+    let mut response = String::new();
+    stream.read_to_string(&mut response)?;
+    Ok(response)
+}
+
+fn call(port: u16, token: &str, method: &str, params: Value) -> eyre::Result<Value> {
+    let body = json!({
+        "jsonrpc": "2.0",
+        "id": 1,
+        "method": method,
+        "params": params,
+    })
+    .to_string();
+    let response = send_request(
+        port,
+        &format!(
+            "POST /rpc HTTP/1.1\r\nHost: 127.0.0.1:{port}\r\nAuthorization: Bearer {token}\r\nContent-Length: {}\r\n\r\n{body}",
+            body.len()
+        ),
+    )?;
+    let body = match response.split_once("\r\n\r\n") {
+        Some((_headers, body)) => body,
+        None => eyre::bail!("Malformed response: {response:?}"),
+    };
+    Ok(serde_json::from_str(body)?)
+}
+
+#[test]
+fn test_serve() -> eyre::Result<()> {
+    let git = make_git()?;
+
+    git.init_repo()?;
+    git.detach_head()?;
+    let test1_oid = git.commit_file("test1", 1)?;
+    let test2_oid = git.commit_file("test2", 2)?;
+
+    let (mut child, port, token) = spawn_server(&git)?;
+
+    {
+        let response = call(port, &token, "smartlog", Value::Null)?;
+        let result = &response["result"];
+        assert_eq!(result["head_oid"], json!(test2_oid.to_string()));
+        let summaries: Vec<&Value> = result["nodes"]
+            .as_array()
+            .unwrap()
+            .iter()
+            .map(|node| &node["summary"])
+            .collect();
+        assert_eq!(summaries.len(), 3);
+        assert!(summaries.contains(&&json!("create test1.txt")));
+        assert!(summaries.contains(&&json!("create test2.txt")));
+    }
+
+    let last_event_id = {
+        let response = call(port, &token, "events", json!({}))?;
+        let last_event_id = response["result"]["last_event_id"].as_u64().unwrap();
+        assert!(last_event_id > 0);
+        last_event_id
+    };
+
+    {
+        // An overly-long timeout is clamped rather than overflowing.
+        let response = call(port, &token, "events", json!({ "timeout": u64::MAX }))?;
+        assert_eq!(
+            response["result"]["last_event_id"].as_u64(),
+            Some(last_event_id)
+        );
+    }
+
+    {
+        let response = call(
+            port,
+            &token,
+            "hide",
+            json!({ "revsets": [test2_oid.to_string()] }),
+        )?;
+        assert_eq!(response["result"], Value::Null);
+
+        let response = call(
+            port,
+            &token,
+            "events",
+            json!({ "after": last_event_id, "timeout": 1000 }),
+        )?;
+        let events = response["result"]["events"].as_array().unwrap();
+        assert!(events.iter().any(|event| event["type"] == json!("obsolete")
+            && event["commit_oid"] == json!(test2_oid.to_string())));
+    }
+
+    {
+        let response = call(
+            port,
+            &token,
+            "checkout",
+            json!({ "target": test1_oid.to_string() }),
+        )?;
+        assert_eq!(response["result"], Value::Null);
+        assert_eq!(git.get_repo()?.get_head_info()?.oid, Some(test1_oid));
+    }
+
+    {
+        let response = call(port, &token, "frobnicate", Value::Null)?;
+        assert_eq!(response["error"]["code"], json!(-32601));
+    }
+
+    {
+        let response = send_request(
+            port,
+            &format!(
+                "POST /rpc HTTP/1.1\r\nHost: 127.0.0.1:{port}\r\nAuthorization: Bearer {token}\r\nOrigin: https://example.com\r\nContent-Length: 0\r\n\r\n"
+            ),
+        )?;
+        assert!(response.starts_with("HTTP/1.1 403 Forbidden"));
+    }
+
+    {
+        let response = send_request(
+            port,
+            &format!("POST /rpc HTTP/1.1\r\nHost: 127.0.0.1:{port}\r\nContent-Length: 0\r\n\r\n"),
+        )?;
+        assert!(response.starts_with("HTTP/1.1 401 Unauthorized"));
+
+        let response = send_request(
+            port,
+            &format!(
+                "POST /rpc HTTP/1.1\r\nHost: 127.0.0.1:{port}\r\nAuthorization: Bearer wrong\r\nContent-Length: 0\r\n\r\n"
+            ),
+        )?;
+        assert!(response.starts_with("HTTP/1.1 401 Unauthorized"));
+    }
+
+    {
+        let response = send_request(port, "garbage\r\n\r\n")?;
+        assert!(response.starts_with("HTTP/1.1 400 Bad Request"));
+    }
+
+    {
+        let mut stream = TcpStream::connect(("127.0.0.1", port))?;
+        write!(
+            stream,
+            "GET /events?after=0 HTTP/1.1\r\nHost: 127.0.0.1:{port}\r\nAuthorization: Bearer {token}\r\n\r\n"
+        )?;
+        let mut reader = BufReader::new(stream);
+        let mut line = String::new();
+        while !line.starts_with("id: ") {
+            line.clear();
+            if reader.read_line(&mut line)? == 0 {
+                eyre::bail!("Event stream ended unexpectedly");
+            }
+        }
+        assert_eq!(line, "id: 1\n");
+    }
+
+    {
+        let response = call(port, &token, "shutdown", Value::Null)?;
+        assert_eq!(response["result"], Value::Null);
+        assert!(child.wait()?.success());
+    }
+
+    Ok(())
+}
@@ -510,3 +510,80 @@ fn test_snapshot_restore_unborn_head() -> eyre::Result<()> {
 
     Ok(())
 }
+
+#[test]
+fn test_snapshot_list_and_show() -> eyre::Result<()> {
+    let git = make_git()?;
+
+    if !git.supports_reference_transactions()? {
+        return Ok(());
+    }
+    git.init_repo()?;
+
+    {
+        let (stdout, _stderr) = git.branchless("snapshot", &["list"])?;
+        assert_eq!(stdout, "No snapshots recorded.\n");
+    }
+
+    git.commit_file("test1", 1)?;
+    git.commit_file("test2", 2)?;
+    git.write_file_txt("test1", "test1 new contents\n")?;
+    git.write_file_txt("test2", "staged contents\n")?;
+    git.run(&["add", "test2.txt"])?;
+
+    let snapshot_oid = {
+        let (snapshot_oid, _stderr) = git.branchless("snapshot", &["create"])?;
+        NonZeroOid::from_str(snapshot_oid.trim())?
+    };
+
+    {
+        let (stdout, _stderr) = git.branchless("snapshot", &["list"])?;
+        let mut lines = stdout.lines();
+        let first_line = lines.next().unwrap_or_default();
+        assert!(
+            first_line.starts_with(&format!("{snapshot_oid} snapshot create")),
+            "{stdout}"
+        );
+        assert!(
+            lines
+                .next()
+                .unwrap_or_default()
+                .contains("create test2.txt"),
+            "{stdout}"
+        );
+    }
+
+    {
+        let (stdout, _stderr) = git.branchless("snapshot", &["show", &snapshot_oid.to_string()])?;
+        let staged_index = stdout.find("Staged changes:").unwrap();
+        let unstaged_index = stdout.find("Unstaged changes:").unwrap();
+        assert!(staged_index < unstaged_index, "{stdout}");
+        let (staged, unstaged) = stdout.split_at(unstaged_index);
+        assert!(staged.contains("+staged contents"), "{stdout}");
+        assert!(!staged.contains("test1.txt"), "{stdout}");
+        assert!(unstaged.contains("+test1 new contents"), "{stdout}");
+        // The staged changes aren't shown again as unstaged changes.
+        assert!(!unstaged.contains("test2.txt"), "{stdout}");
+    }
+
+    {
+        // The working copy was reset, so showing a snapshot doesn't restore it.
+        let (stdout, _stderr) = git.run(&["status", "--porcelain"])?;
+        assert_eq!(stdout, "");
+    }
+
+    {
+        let (head_oid, _stderr) = git.run(&["rev-parse", "HEAD"])?;
+        let (_stdout, stderr) = git.branchless_with_options(
+            "snapshot",
+            &["show", head_oid.trim()],
+            &GitRunOptions {
+                expected_exit_code: 1,
+                ..Default::default()
+            },
+        )?;
+        assert_eq!(stderr, format!("Not a snapshot commit: {}", head_oid));
+    }
+
+    Ok(())
+}
@@ -0,0 +1,175 @@
+use lib::testing::{make_git, GitRunOptions};
+
+#[test]
+fn test_stack_create_list_delete() -> eyre::Result<()> {
+    let git = make_git()?;
+
+    git.init_repo()?;
+    git.detach_head()?;
+    git.commit_file("test1", 1)?;
+    git.commit_file("test2", 2)?;
+
+    {
+        let (stdout, _stderr) = git.branchless("stack", &["create", "feature", "HEAD"])?;
+        insta::assert_snapshot!(stdout, @r###"
+        Named stack feature with tip 96d1c37 create test2.txt
+        "###);
+    }
+    {
+        let (stdout, _stderr) = git.branchless("stack", &["list"])?;
+        insta::assert_snapshot!(stdout, @r###"
+        feature: 96d1c37 create test2.txt
+        "###);
+    }
+    {
+        let (stdout, _stderr) = git.branchless("query", &["feature"])?;
+        insta::assert_snapshot!(stdout, @r###"
+        62fc20d create test1.txt
+        96d1c37 create test2.txt
+        "###);
+    }
+
+    {
+        let (stdout, _stderr) = git.branchless("stack", &["delete", "feature"])?;
+        insta::assert_snapshot!(stdout, @r###"
+        Deleted stack feature
+        "###);
+    }
+    {
+        let (stdout, stderr) = git.branchless_with_options(
+            "stack",
+            &["delete", "feature"],
+            &GitRunOptions {
+                expected_exit_code: 1,
+                ..Default::default()
+            },
+        )?;
+        insta::assert_snapshot!(stderr, @r###"
+        No stack named feature
+        "###);
+        insta::assert_snapshot!(stdout, @"");
+    }
+
+    Ok(())
+}
+
+#[test]
+fn test_stack_follows_rewrites() -> eyre::Result<()> {
+    let git = make_git()?;
+
+    git.init_repo()?;
+    git.detach_head()?;
+    git.commit_file("test1", 1)?;
+    git.commit_file("test2", 2)?;
+    git.branchless("stack", &["create", "feature"])?;
+
+    git.run(&["commit", "--amend", "-m", "amended test2"])?;
+    {
+        let (stdout, _stderr) = git.branchless("query", &["feature"])?;
+        assert!(stdout.contains("62fc20d create test1.txt"), "{stdout}");
+        assert!(stdout.contains("amended test2"), "{stdout}");
+        assert!(!stdout.contains("96d1c37"), "{stdout}");
+    }
+    {
+        let (stdout, _stderr) = git.branchless("stack", &["list"])?;
+        assert!(stdout.contains("feature: "), "{stdout}");
+        assert!(stdout.contains("amended test2"), "{stdout}");
+    }
+
+    Ok(())
+}
+
+#[test]
+fn test_stack_name_shadowed_by_branch() -> eyre::Result<()> {
+    let git = make_git()?;
+
+    git.init_repo()?;
+    git.detach_head()?;
+    git.commit_file("test1", 1)?;
+
+    {
+        let (stdout, stderr) = git.branchless_with_options(
+            "stack",
+            &["create", "master"],
+            &GitRunOptions {
+                expected_exit_code: 1,
+                ..Default::default()
+            },
+        )?;
+        insta::assert_snapshot!(stderr, @r###"
+        The name "master" already refers to commit f777ecc, so it can't be used as a stack name.
+        "###);
+        insta::assert_snapshot!(stdout, @"");
+    }
+
+    Ok(())
+}
+
+#[test]
+fn test_stack_switch_carry() -> eyre::Result<()> {
+    let git = make_git()?;
+
+    git.init_repo()?;
+    git.detach_head()?;
+    git.commit_file("test1", 1)?;
+    git.commit_file("test2", 2)?;
+    git.branchless("stack", &["create", "feature"])?;
+    git.run(&["checkout", "master"])?;
+
+    git.write_file_txt("initial", "local changes\n")?;
+    git.branchless("stack", &["switch", "feature", "--carry"])?;
+    {
+        let (stdout, _stderr) = git.run(&["rev-parse", "--short", "HEAD"])?;
+        insta::assert_snapshot!(stdout, @r###"
+        96d1c37
+        "###);
+    }
+    {
+        let (stdout, _stderr) = git.run(&["status", "--short"])?;
+        insta::assert_snapshot!(stdout, @r###"
+         M initial.txt
+        "###);
+    }
+    let contents = std::fs::read_to_string(git.repo_path.join("initial.txt"))?;
+    assert_eq!(contents, "local changes\n");
+
+    Ok(())
+}
+
+#[test]
+fn test_stack_switch_carry_conflict() -> eyre::Result<()> {
+    let git = make_git()?;
+
+    git.init_repo()?;
+    git.detach_head()?;
+    git.commit_file_with_contents("initial", 1, "feature contents\n")?;
+    git.branchless("stack", &["create", "feature"])?;
+    git.run(&["checkout", "master"])?;
+
+    git.write_file_txt("initial", "local changes\n")?;
+    {
+        let (stdout, _stderr) = git.branchless_with_options(
+            "stack",
+            &["switch", "feature", "--carry"],
+            &GitRunOptions {
+                expected_exit_code: 1,
+                ..Default::default()
+            },
+        )?;
+        insta::assert_snapshot!(stdout, @r###"
+        The uncommitted changes can't be carried over to stack feature, since they conflict with its tip in 1 file:
+        - initial.txt
+        The working copy was left unchanged.
+        "###);
+    }
+    {
+        let (stdout, _stderr) = git.run(&["rev-parse", "--abbrev-ref", "HEAD"])?;
+        insta::assert_snapshot!(stdout, @r###"
+        master
+        "###);
+    }
+    let contents = std::fs::read_to_string(git.repo_path.join("initial.txt"))?;
+    assert_eq!(contents, "local changes\n");
+
+    Ok(())
+}
@@ -0,0 +1,56 @@
+use lib::testing::make_git;
+
+#[test]
+fn test_stats() -> eyre::Result<()> {
+    let git = make_git()?;
+
+    git.init_repo()?;
+    git.detach_head()?;
+    git.commit_file("test1", 1)?;
+    git.commit_file("test2", 2)?;
+    git.write_file_txt("test2", "updated contents")?;
+    git.branchless("amend", &[])?;
+    git.branchless("hide", &["HEAD"])?;
+    git.branchless("unhide", &["HEAD"])?;
+
+    {
+        let (stdout, _stderr) = git.branchless("stats", &["--days", "1"])?;
+        insta::assert_snapshot!(stdout, @r###"
+        Over the last 1 day(s):
+          Commits amended: 1 (1.0 per day)
+          Restacks performed: 0
+          Commits hidden and later restored: 1
+        Stacks of draft commits: 1 (average depth: 2.0 commits)
+        "###);
+    }
+
+    Ok(())
+}
+
+#[test]
+fn test_stats_classify_rewrites() -> eyre::Result<()> {
+    let git = make_git()?;
+
+    git.init_repo()?;
+    git.detach_head()?;
+    git.commit_file("test1", 1)?;
+    git.commit_file("test2", 2)?;
+    git.run(&["checkout", "HEAD~"])?;
+    git.write_file_txt("test1", "updated contents")?;
+    git.branchless("amend", &[])?;
+    git.branchless("next", &[])?;
+    git.run(&["rebase", "--onto", "master", "HEAD~"])?;
+
+    {
+        let (stdout, _stderr) = git.branchless("stats", &["--days", "1"])?;
+        insta::assert_snapshot!(stdout, @r###"
+        Over the last 1 day(s):
+          Commits amended: 1 (1.0 per day)
+          Restacks performed: 1
+          Commits hidden and later restored: 0
+        Stacks of draft commits: 2 (average depth: 1.0 commits)
+        "###);
+    }
+
+    Ok(())
+}
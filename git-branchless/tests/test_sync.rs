@@ -2,6 +2,173 @@ use lib::testing::{
     make_git, make_git_with_remote_repo, remove_nondeterministic_lines, GitInitOptions,
     GitRunOptions, GitWrapperWithRemoteRepo,
 };
+use serde_json::json;
+
+#[test]
+fn test_sync_json_lines_output() -> eyre::Result<()> {
+    let git = make_git()?;
+
+    if !git.supports_reference_transactions()? {
+        return Ok(());
+    }
+    git.init_repo()?;
+
+    git.detach_head()?;
+    let test1_oid = git.commit_file("test1", 1)?.to_string();
+    let test2_oid = git.commit_file("test2", 2)?.to_string();
+
+    git.run(&["checkout", "master"])?;
+    git.commit_file("test3", 3)?;
+
+    let output_path = git.repo_path.join(".git").join("progress.jsonl");
+    git.branchless(
+        "sync",
+        &[
+            "--output",
+            "json-lines",
+            "--output-file",
+            output_path.to_str().unwrap(),
+        ],
+    )?;
+
+    let events = std::fs::read_to_string(&output_path)?
+        .lines()
+        .map(serde_json::from_str)
+        .collect::<Result<Vec<serde_json::Value>, _>>()?;
+    assert_eq!(
+        events,
+        vec![
+            json!({ "event": "planned", "operation": "sync", "steps": [test1_oid] }),
+            json!({ "event": "started", "operation": "sync", "step": test1_oid }),
+            json!({ "event": "planned", "operation": "rebase", "steps": [test1_oid, test2_oid] }),
+            json!({ "event": "started", "operation": "rebase", "step": test1_oid }),
+            json!({ "event": "finished", "operation": "rebase", "step": test1_oid, "status": "committed" }),
+            json!({ "event": "started", "operation": "rebase", "step": test2_oid }),
+            json!({ "event": "finished", "operation": "rebase", "step": test2_oid, "status": "committed" }),
+            json!({ "event": "finished", "operation": "sync", "step": test1_oid, "status": "synced" }),
+        ]
+    );
+
+    Ok(())
+}
+
+#[test]
+fn test_on_disk_rebase_json_lines_output() -> eyre::Result<()> {
+    let git = make_git()?;
+
+    if !git.supports_reference_transactions()? {
+        return Ok(());
+    }
+    git.init_repo()?;
+
+    git.detach_head()?;
+    let test1_oid = git.commit_file("test1", 1)?.to_string();
+    let test2_oid = git.commit_file("test2", 2)?.to_string();
+
+    git.run(&["checkout", "master"])?;
+    git.commit_file("test3", 3)?;
+
+    let output_path = git.repo_path.join(".git").join("progress.jsonl");
+    git.branchless(
+        "move",
+        &[
+            "--on-disk",
+            "-s",
+            &test1_oid,
+            "-d",
+            "master",
+            "--output",
+            "json-lines",
+            "--output-file",
+            output_path.to_str().unwrap(),
+        ],
+    )?;
+
+    let events = std::fs::read_to_string(&output_path)?
+        .lines()
+        .map(serde_json::from_str)
+        .collect::<Result<Vec<serde_json::Value>, _>>()?;
+    assert_eq!(
+        events,
+        vec![
+            json!({ "event": "planned", "operation": "rebase", "steps": [test1_oid, test2_oid] }),
+            json!({ "event": "finished", "operation": "rebase", "step": test1_oid, "status": "committed" }),
+            json!({ "event": "finished", "operation": "rebase", "step": test2_oid, "status": "committed" }),
+        ]
+    );
+
+    Ok(())
+}
+
+#[test]
+fn test_on_disk_rebase_json_lines_output_conflict() -> eyre::Result<()> {
+    let git = make_git()?;
+
+    if !git.supports_reference_transactions()? {
+        return Ok(());
+    }
+    git.init_repo()?;
+
+    git.detach_head()?;
+    let test1_oid = git.commit_file("test1", 1)?.to_string();
+
+    git.run(&["checkout", "master"])?;
+    git.commit_file_with_contents("test1", 2, "conflicting contents")?;
+
+    let output_path = git.repo_path.join(".git").join("progress.jsonl");
+    git.branchless_with_options(
+        "move",
+        &[
+            "--on-disk",
+            "-s",
+            &test1_oid,
+            "-d",
+            "master",
+            "--output",
+            "json-lines",
+            "--output-file",
+            output_path.to_str().unwrap(),
+        ],
+        &GitRunOptions {
+            expected_exit_code: 1,
+            ..Default::default()
+        },
+    )?;
+
+    let events = std::fs::read_to_string(&output_path)?
+        .lines()
+        .map(serde_json::from_str)
+        .collect::<Result<Vec<serde_json::Value>, _>>()?;
+    assert_eq!(
+        events,
+        vec![
+            json!({ "event": "planned", "operation": "rebase", "steps": [test1_oid] }),
+            json!({ "event": "conflicted", "operation": "rebase", "step": test1_oid, "paths": ["test1.txt"] }),
+        ]
+    );
+
+    Ok(())
+}
+
+#[test]
+fn test_json_lines_output_requires_output_file() -> eyre::Result<()> {
+    let git = make_git()?;
+    git.init_repo()?;
+
+    {
+        let (_stdout, stderr) = git.branchless_with_options(
+            "sync",
+            &["--output", "json-lines"],
+            &GitRunOptions {
+                expected_exit_code: 2,
+                ..Default::default()
+            },
+        )?;
+        assert!(stderr.contains("--output-file"), "{stderr}");
+    }
+
+    Ok(())
+}
 
 #[test]
 fn test_sync_basic() -> eyre::Result<()> {
@@ -192,6 +359,116 @@ fn test_sync_pull() -> eyre::Result<()> {
     Ok(())
 }
 
+#[test]
+fn test_sync_onto_new_main() -> eyre::Result<()> {
+    let GitWrapperWithRemoteRepo {
+        temp_dir: _guard,
+        original_repo,
+        cloned_repo,
+    } = make_git_with_remote_repo()?;
+    if !original_repo.supports_reference_transactions()? {
+        return Ok(());
+    }
+
+    original_repo.init_repo()?;
+    original_repo.commit_file("test1", 1)?;
+    let test2_oid = original_repo.commit_file("test2", 2)?;
+
+    original_repo.clone_repo_into(&cloned_repo, &["--branch", "master"])?;
+    cloned_repo.init_repo_with_options(&GitInitOptions {
+        make_initial_commit: false,
+        ..Default::default()
+    })?;
+    cloned_repo.detach_head()?;
+    cloned_repo.commit_file("test3", 3)?;
+
+    // Rewrite the upstream main branch, so that `test2` is replaced by an
+    // equivalent commit with a different parent.
+    original_repo.run(&["reset", "--hard", "HEAD^"])?;
+    original_repo.commit_file("test4", 4)?;
+    original_repo.run(&["cherry-pick", &test2_oid.to_string()])?;
+    let new_test2_oid = original_repo.get_repo()?.get_head_info()?.oid.unwrap();
+
+    {
+        let (stdout, stderr) = cloned_repo.run(&["fetch"])?;
+        assert!(
+            format!("{stdout}{stderr}").contains(
+                "branchless: the upstream main branch was rewritten; to move your commits onto it, run: git sync --onto-new-main"
+            ),
+            "{stdout}{stderr}"
+        );
+    }
+
+    {
+        let (stdout, _stderr) = cloned_repo.branchless("sync", &["--onto-new-main"])?;
+        assert!(stdout.contains("Resetting branch master to "), "{stdout}");
+        assert!(stdout.contains("Synced "), "{stdout}");
+    }
+
+    {
+        let repo = cloned_repo.get_repo()?;
+        assert_eq!(repo.get_main_branch_oid()?, new_test2_oid);
+        let head_oid = repo.get_head_info()?.oid.unwrap();
+        let head_commit = repo.find_commit_or_fail(head_oid)?;
+        assert_eq!(head_commit.get_summary()?, "create test3.txt");
+        assert_eq!(head_commit.get_parent_oids(), vec![new_test2_oid]);
+    }
+
+    {
+        let (stdout, _stderr) = cloned_repo.branchless("sync", &["--onto-new-main"])?;
+        insta::assert_snapshot!(stdout, @r###"
+        No stacks are based on the old main branch, so there is nothing to move.
+        "###);
+    }
+
+    Ok(())
+}
+
+#[test]
+fn test_sync_onto_new_main_tip() -> eyre::Result<()> {
+    let GitWrapperWithRemoteRepo {
+        temp_dir: _guard,
+        original_repo,
+        cloned_repo,
+    } = make_git_with_remote_repo()?;
+    if !original_repo.supports_reference_transactions()? {
+        return Ok(());
+    }
+
+    original_repo.init_repo()?;
+    original_repo.commit_file("test1", 1)?;
+    let test2_oid = original_repo.commit_file("test2", 2)?;
+
+    original_repo.clone_repo_into(&cloned_repo, &["--branch", "master"])?;
+    cloned_repo.init_repo_with_options(&GitInitOptions {
+        make_initial_commit: false,
+        ..Default::default()
+    })?;
+    cloned_repo.detach_head()?;
+    cloned_repo.commit_file("test3", 3)?;
+
+    // Rewrite the upstream main branch and then add another commit on top of
+    // the equivalent of `test2`.
+    original_repo.run(&["reset", "--hard", "HEAD^"])?;
+    original_repo.commit_file("test4", 4)?;
+    original_repo.run(&["cherry-pick", &test2_oid.to_string()])?;
+    let new_main_oid = original_repo.commit_file("test5", 5)?;
+
+    cloned_repo.run(&["fetch"])?;
+    cloned_repo.branchless("sync", &["--onto-new-main"])?;
+
+    {
+        let repo = cloned_repo.get_repo()?;
+        assert_eq!(repo.get_main_branch_oid()?, new_main_oid);
+        let head_oid = repo.get_head_info()?.oid.unwrap();
+        let head_commit = repo.find_commit_or_fail(head_oid)?;
+        assert_eq!(head_commit.get_summary()?, "create test3.txt");
+        assert_eq!(head_commit.get_parent_oids(), vec![new_main_oid]);
+    }
+
+    Ok(())
+}
+
 #[test]
 fn test_sync_stack_from_commit() -> eyre::Result<()> {
     let git = make_git()?;
@@ -532,3 +809,30 @@ fn test_sync_checked_out_main_with_dirty_working_copy() -> eyre::Result<()> {
 
     Ok(())
 }
+
+#[test]
+fn test_sync_pull_pinned_main_commit() -> eyre::Result<()> {
+    let git = make_git()?;
+    if !git.supports_reference_transactions()? {
+        return Ok(());
+    }
+
+    git.init_repo()?;
+    git.commit_file("test1", 1)?;
+    git.run(&["tag", "v1"])?;
+    git.detach_head()?;
+    git.commit_file("test2", 2)?;
+    git.run(&["config", "branchless.core.mainCommit", "v1"])?;
+
+    {
+        let (_stdout, stderr) = git.branchless("sync", &["--pull"])?;
+        assert!(
+            stderr.contains(
+                "Warning: the main branch is pinned to v1 with branchless.core.mainCommit, so it wasn't pulled."
+            ),
+            "{stderr}"
+        );
+    }
+
+    Ok(())
+}
@@ -0,0 +1,119 @@
+use lib::testing::{
+    make_git, make_git_with_remote_repo, GitInitOptions, GitRunOptions, GitWrapperWithRemoteRepo,
+};
+
+#[test]
+fn test_sync_state_push_pull() -> eyre::Result<()> {
+    let GitWrapperWithRemoteRepo {
+        temp_dir: _guard,
+        original_repo,
+        cloned_repo,
+    } = make_git_with_remote_repo()?;
+
+    original_repo.init_repo()?;
+    original_repo.commit_file("test1", 1)?;
+    original_repo.clone_repo_into(&cloned_repo, &["--branch", "master"])?;
+    cloned_repo.init_repo_with_options(&GitInitOptions {
+        make_initial_commit: false,
+        ..Default::default()
+    })?;
+    cloned_repo.detach_head()?;
+    cloned_repo.commit_file("test2", 2)?;
+    cloned_repo.commit_file("test3", 3)?;
+
+    {
+        let (stdout, _stderr) = cloned_repo.branchless("sync-state", &["push", "origin"])?;
+        assert!(
+            stdout.contains("Pushed state for 2 draft commits to origin."),
+            "{stdout}"
+        );
+    }
+
+    let cloned_path = cloned_repo.repo_path.to_str().unwrap();
+    original_repo.run(&["remote", "add", "other", cloned_path])?;
+    {
+        let stdout = original_repo.smartlog()?;
+        assert!(!stdout.contains("create test3.txt"), "{stdout}");
+    }
+    {
+        let (stdout, _stderr) = original_repo.branchless("sync-state", &["pull", "other"])?;
+        assert!(stdout.contains("Pulled "), "{stdout}");
+    }
+    {
+        let stdout = original_repo.smartlog()?;
+        assert!(stdout.contains("create test2.txt"), "{stdout}");
+        assert!(stdout.contains("create test3.txt"), "{stdout}");
+    }
+
+    cloned_repo.branchless("hide", &["HEAD"])?;
+    cloned_repo.branchless("sync-state", &["push", "origin"])?;
+    original_repo.branchless("sync-state", &["pull", "other"])?;
+    {
+        let stdout = original_repo.smartlog()?;
+        assert!(stdout.contains("create test2.txt"), "{stdout}");
+        assert!(!stdout.contains("create test3.txt"), "{stdout}");
+    }
+
+    Ok(())
+}
+
+#[test]
+fn test_sync_state_push_only_visible_commits() -> eyre::Result<()> {
+    let GitWrapperWithRemoteRepo {
+        temp_dir: _guard,
+        original_repo,
+        cloned_repo,
+    } = make_git_with_remote_repo()?;
+
+    original_repo.init_repo()?;
+    original_repo.commit_file("test1", 1)?;
+    original_repo.clone_repo_into(&cloned_repo, &["--branch", "master"])?;
+    cloned_repo.init_repo_with_options(&GitInitOptions {
+        make_initial_commit: false,
+        ..Default::default()
+    })?;
+    cloned_repo.detach_head()?;
+    let test2_oid = cloned_repo.commit_file("test2", 2)?;
+    let test3_oid = cloned_repo.commit_file("test3", 3)?;
+    cloned_repo.branchless("hide", &["HEAD"])?;
+    cloned_repo.run(&["checkout", &test2_oid.to_string()])?;
+
+    cloned_repo.branchless("sync-state", &["push", "origin"])?;
+    {
+        let (stdout, _stderr) = cloned_repo.run(&["show", "refs/branchless/state:events.json"])?;
+        assert!(stdout.contains(&test2_oid.to_string()), "{stdout}");
+        assert!(!stdout.contains(&test3_oid.to_string()), "{stdout}");
+    }
+
+    cloned_repo.run(&["config", "branchless.syncState.maxEvents", "1"])?;
+    cloned_repo.branchless("sync-state", &["push", "origin"])?;
+    {
+        let (stdout, _stderr) = cloned_repo.run(&["show", "refs/branchless/state:events.json"])?;
+        let events: Vec<serde_json::Value> = serde_json::from_str(&stdout)?;
+        assert_eq!(events.len(), 1, "{stdout}");
+    }
+
+    Ok(())
+}
+
+#[test]
+fn test_sync_state_no_remote() -> eyre::Result<()> {
+    let git = make_git()?;
+    git.init_repo()?;
+
+    {
+        let (_stdout, stderr) = git.branchless_with_options(
+            "sync-state",
+            &["push"],
+            &GitRunOptions {
+                expected_exit_code: 1,
+                ..Default::default()
+            },
+        )?;
+        insta::assert_snapshot!(stderr, @r###"
+        No remote was provided and `branchless.syncState.remote` is not set.
+        "###);
+    }
+
+    Ok(())
+}
@@ -2,7 +2,7 @@ use std::mem::swap;
 use std::rc::Rc;
 use std::sync::{Arc, Mutex};
 
-use git_branchless_undo::testing::{select_past_event, undo_events};
+use git_branchless_undo::testing::{select_past_event, undo_events, undo_selected_events};
 use git_branchless_undo::tui::testing::{
     screen_to_string, CursiveTestingBackend, CursiveTestingEvent,
 };
@@ -11,8 +11,11 @@ use lib::core::effects::Effects;
 use lib::core::eventlog::{EventCursor, EventLogDb, EventReplayer};
 use lib::core::formatting::Glyphs;
 use lib::core::repo_ext::RepoExt;
-use lib::git::{GitRunInfo, GitVersion, Repo};
-use lib::testing::{make_git, trim_lines, Git, GitInitOptions, GitRunOptions};
+use lib::git::{GitRunInfo, GitVersion, ReferenceName, Repo};
+use lib::testing::{
+    make_git, make_git_with_remote_repo, trim_lines, Git, GitInitOptions, GitRunOptions,
+    GitWrapperWithRemoteRepo,
+};
 
 use cursive_core::event::Key;
 use cursive_core::{Cursive, CursiveRunner};
@@ -43,13 +46,21 @@ fn run_select_past_event(
 }
 
 fn run_undo_events(git: &Git, event_cursor: EventCursor) -> eyre::Result<(isize, String)> {
+    run_undo_events_with_input(git, event_cursor, "y", false)
+}
+
+fn run_undo_events_with_input(
+    git: &Git,
+    event_cursor: EventCursor,
+    input: &str,
+    select_events: bool,
+) -> eyre::Result<(isize, String)> {
     let glyphs = Glyphs::text();
     let effects = Effects::new_suppress_for_test(glyphs.clone());
     let repo = git.get_repo()?;
     let conn = repo.get_db_conn()?;
     let mut event_log_db: EventLogDb = EventLogDb::new(&conn)?;
     let event_replayer = EventReplayer::from_event_log_db(&effects, &repo, &event_log_db)?;
-    let input = "y";
     let mut in_ = input.as_bytes();
     let stdout: Arc<Mutex<Vec<u8>>> = Default::default();
     let stderr: Arc<Mutex<Vec<u8>>> = Default::default();
@@ -60,15 +71,29 @@ fn run_undo_events(git: &Git, event_cursor: EventCursor) -> eyre::Result<(isize,
         env: git.get_base_env(0).into_iter().collect(),
     };
 
-    let exit_code = match undo_events(
-        &mut in_,
-        &Effects::new_from_buffer_for_test(glyphs, &stdout, &stderr),
-        &repo,
-        &git_run_info,
-        &mut event_log_db,
-        &event_replayer,
-        event_cursor,
-    )? {
+    let effects = Effects::new_from_buffer_for_test(glyphs, &stdout, &stderr);
+    let result = if select_events {
+        undo_selected_events(
+            &mut in_,
+            &effects,
+            &repo,
+            &git_run_info,
+            &mut event_log_db,
+            &event_replayer,
+            event_cursor,
+        )?
+    } else {
+        undo_events(
+            &mut in_,
+            &effects,
+            &repo,
+            &git_run_info,
+            &mut event_log_db,
+            &event_replayer,
+            event_cursor,
+        )?
+    };
+    let exit_code = match result {
         Ok(()) => 0,
         Err(ExitCode(exit_code)) => exit_code,
     };
@@ -372,6 +397,203 @@ fn test_undo_hide() -> eyre::Result<()> {
     Ok(())
 }
 
+#[test]
+fn test_undo_hide_select_events() -> eyre::Result<()> {
+    let git = make_git()?;
+
+    if !git.supports_reference_transactions()? {
+        return Ok(());
+    }
+
+    git.init_repo()?;
+    git.run(&["checkout", "-b", "test1"])?;
+    git.commit_file("test1", 1)?;
+    git.run(&["checkout", "HEAD^"])?;
+    git.commit_file("test2", 2)?;
+    git.branchless("hide", &["test1"])?;
+
+    let event_cursor = {
+        let repo = git.get_repo()?;
+        let conn = repo.get_db_conn()?;
+        let event_log_db = EventLogDb::new(&conn)?;
+        let effects = Effects::new_suppress_for_test(Glyphs::text());
+        let event_replayer = EventReplayer::from_event_log_db(&effects, &repo, &event_log_db)?;
+        event_replayer.advance_cursor_by_transaction(event_replayer.make_default_cursor(), -1)
+    };
+
+    {
+        let (exit_code, stdout) = run_undo_events_with_input(&git, event_cursor, "5", true)?;
+        insta::assert_snapshot!(stdout, @r###"
+        Will apply these actions:
+        1. Create branch test1 at 62fc20d create test1.txt
+
+        2. Unhide commit 62fc20d create test1.txt
+
+        Select actions to apply (e.g. 1 3-4), or press enter to apply all: Action 5 is out of range (expected 1-2)
+        Aborted.
+        "###);
+        assert_eq!(exit_code, 1);
+    }
+
+    {
+        let (exit_code, stdout) = run_undo_events_with_input(&git, event_cursor, "2\ny", true)?;
+        insta::assert_snapshot!(stdout, @r###"
+        Will apply these actions:
+        1. Create branch test1 at 62fc20d create test1.txt
+
+        2. Unhide commit 62fc20d create test1.txt
+
+        Select actions to apply (e.g. 1 3-4), or press enter to apply all: Will apply these actions:
+        1. Unhide commit 62fc20d create test1.txt
+
+        Confirm? [yN] Applied 1 inverse event.
+        "###);
+        assert_eq!(exit_code, 0);
+    }
+
+    {
+        let stdout = git.smartlog()?;
+        insta::assert_snapshot!(stdout, @r###"
+        O f777ecc (master) create initial.txt
+        |\
+        | o 62fc20d create test1.txt
+        |
+        @ fe65c1f create test2.txt
+        "###);
+    }
+
+    Ok(())
+}
+
+#[test]
+fn test_undo_to_operation() -> eyre::Result<()> {
+    let git = make_git()?;
+
+    if !git.supports_reference_transactions()? {
+        return Ok(());
+    }
+
+    git.init_repo()?;
+    git.run(&["checkout", "-b", "test1"])?;
+    git.commit_file("test1", 1)?;
+    git.run(&["checkout", "HEAD^"])?;
+    git.commit_file("test2", 2)?;
+    git.branchless("hide", &["test1"])?;
+    git.run(&["branch", "foo"])?;
+
+    {
+        let (stdout, stderr) = git.branchless_with_options(
+            "undo",
+            &["--to", "nonexistent", "-y"],
+            &GitRunOptions {
+                expected_exit_code: 1,
+                ..Default::default()
+            },
+        )?;
+        assert_eq!(stderr, "No operation found matching: nonexistent\n");
+        insta::assert_snapshot!(stdout, @"");
+    }
+
+    {
+        let (stdout, _stderr) = git.branchless("undo", &["--to", "hide 1 commit", "-y"])?;
+        let stdout = trim_lines(stdout);
+        insta::assert_snapshot!(stdout, @r###"
+        Will apply these actions:
+        1. Delete branch foo at fe65c1f create test2.txt
+
+        2. Create branch test1 at 62fc20d create test1.txt
+
+        3. Unhide commit 62fc20d create test1.txt
+
+        Applied 3 inverse events.
+        "###);
+    }
+
+    {
+        let stdout = git.smartlog()?;
+        insta::assert_snapshot!(stdout, @r###"
+        O f777ecc (master) create initial.txt
+        |\
+        | o 62fc20d (test1) create test1.txt
+        |
+        @ fe65c1f create test2.txt
+        "###);
+    }
+
+    Ok(())
+}
+
+#[test]
+fn test_undo_to_failed_operation() -> eyre::Result<()> {
+    let git = make_git()?;
+
+    if !git.supports_reference_transactions()? {
+        return Ok(());
+    }
+
+    git.init_repo()?;
+    git.detach_head()?;
+    let other_oid = git.commit_file_with_contents("conflict", 1, "conflict 1\n")?;
+    git.run(&["checkout", "master"])?;
+    git.commit_file_with_contents("conflict", 2, "conflict 2\n")?;
+    git.branchless_with_options(
+        "move",
+        &["--source", &other_oid.to_string(), "--on-disk"],
+        &GitRunOptions {
+            expected_exit_code: 1,
+            ..Default::default()
+        },
+    )?;
+
+    // The move didn't happen, so it shouldn't be offered as an operation to
+    // undo to.
+    {
+        let (stdout, stderr) = git.branchless_with_options(
+            "undo",
+            &["--to", "move 1 commit", "-y"],
+            &GitRunOptions {
+                expected_exit_code: 1,
+                ..Default::default()
+            },
+        )?;
+        assert_eq!(stderr, "No operation found matching: move 1 commit\n");
+        insta::assert_snapshot!(stdout, @"");
+    }
+
+    Ok(())
+}
+
+#[test]
+fn test_undo_to_time() -> eyre::Result<()> {
+    let git = make_git()?;
+
+    if !git.supports_reference_transactions()? {
+        return Ok(());
+    }
+
+    git.init_repo()?;
+    git.detach_head()?;
+    git.commit_file("test1", 1)?;
+    git.commit_file("test2", 2)?;
+
+    {
+        // Every event was recorded after the Unix epoch, so all of them are
+        // undone.
+        let (stdout, _stderr) = git.branchless("undo", &["--to", "@0", "-y"])?;
+        assert!(stdout.contains("Applied"), "{stdout}");
+    }
+
+    {
+        let (stdout, _stderr) = git.branchless("query", &["--raw", "draft()"])?;
+        assert_eq!(stdout, "");
+        let (stdout, _stderr) = git.run(&["rev-parse", "HEAD"])?;
+        let (master_oid, _stderr) = git.run(&["rev-parse", "master"])?;
+        assert_eq!(stdout, master_oid);
+    }
+
+    Ok(())
+}
+
 #[test]
 fn test_undo_move_refs() -> eyre::Result<()> {
     let git = make_git()?;
@@ -917,6 +1139,66 @@ fn test_undo_no_confirm() -> eyre::Result<()> {
     Ok(())
 }
 
+#[test]
+fn test_undo_uncommitted_changes() -> eyre::Result<()> {
+    let git = make_git()?;
+
+    if !git.supports_reference_transactions()? {
+        return Ok(());
+    }
+
+    git.init_repo()?;
+    git.commit_file("test1", 1)?;
+    git.commit_file("test2", 2)?;
+    git.run(&["checkout", "HEAD^"])?;
+    git.write_file_txt("test1", "uncommitted changes\n")?;
+
+    {
+        git.run(&["config", "branchless.undo.createSnapshots", "false"])?;
+        let (stdout, _stderr) = git.branchless_with_options(
+            "undo",
+            &["-y"],
+            &GitRunOptions {
+                expected_exit_code: 1,
+                ..Default::default()
+            },
+        )?;
+        let stdout = trim_lines(stdout);
+        insta::assert_snapshot!(stdout, @r###"
+        Will apply these actions:
+        1. Check out from 62fc20d create test1.txt
+                       to 96d1c37 create test2.txt
+        This operation would check out a different commit, but you have uncommitted
+        changes in your working copy which might be overwritten as a result.
+        Commit your changes and then try again, or re-run with --merge to carry them
+        over or --force to discard them.
+        "###);
+    }
+
+    {
+        let (stdout, _stderr) = git.run(&["status", "--porcelain"])?;
+        assert_eq!(stdout, " M test1.txt\n");
+    }
+
+    {
+        git.run(&["config", "branchless.undo.createSnapshots", "true"])?;
+        let (stdout, _stderr) = git.branchless("undo", &["-y"])?;
+        assert!(stdout.contains(
+            "Your uncommitted changes will be saved in a working copy snapshot; run `git undo` to restore them."
+        ));
+        let (stdout, _stderr) = git.run(&["status", "--porcelain"])?;
+        assert_eq!(stdout, "");
+    }
+
+    {
+        git.branchless("undo", &["-y"])?;
+        let (stdout, _stderr) = git.run(&["status", "--porcelain"])?;
+        assert_eq!(stdout, " M test1.txt\n");
+    }
+
+    Ok(())
+}
+
 #[test]
 fn test_undo_unseen_commit() -> eyre::Result<()> {
     // Disabled since we no longer support `origin/master` as a main branch, but this test might be
@@ -1001,3 +1283,52 @@ fn test_undo_unseen_commit() -> eyre::Result<()> {
 
     Ok(())
 }
+
+#[test]
+fn test_undo_fetch_prune() -> eyre::Result<()> {
+    let GitWrapperWithRemoteRepo {
+        temp_dir: _guard,
+        original_repo,
+        cloned_repo,
+    } = make_git_with_remote_repo()?;
+    if !original_repo.supports_reference_transactions()? {
+        return Ok(());
+    }
+
+    original_repo.init_repo()?;
+    original_repo.run(&["checkout", "-b", "feature"])?;
+    let feature_oid = original_repo.commit_file("test1", 1)?;
+    original_repo.run(&["checkout", "master"])?;
+
+    original_repo.clone_repo_into(&cloned_repo, &["--branch", "master"])?;
+    cloned_repo.init_repo_with_options(&GitInitOptions {
+        make_initial_commit: false,
+        ..Default::default()
+    })?;
+
+    let remote_ref_name = ReferenceName::from("refs/remotes/origin/feature");
+    original_repo.run(&["branch", "-D", "feature"])?;
+    cloned_repo.run(&["fetch", "--prune"])?;
+    {
+        let repo = cloned_repo.get_repo()?;
+        assert!(repo.find_reference(&remote_ref_name)?.is_none());
+        // The commit should have been kept alive so that it can be restored.
+        assert!(repo
+            .find_reference(&ReferenceName::from(format!(
+                "refs/branchless/{feature_oid}"
+            )))?
+            .is_some());
+    }
+
+    cloned_repo.branchless("undo", &["--yes"])?;
+    {
+        let repo = cloned_repo.get_repo()?;
+        let commit = match repo.find_reference(&remote_ref_name)? {
+            Some(reference) => reference.peel_to_commit()?,
+            None => None,
+        };
+        assert_eq!(commit.map(|commit| commit.get_oid()), Some(feature_oid));
+    }
+
+    Ok(())
+}
@@ -0,0 +1,126 @@
+use std::time::SystemTime;
+
+use lib::core::eventlog::{Event, EventLogDb};
+use lib::git::{MaybeZeroOid, ReferenceName};
+use lib::testing::{make_git, GitRunOptions};
+
+#[test]
+fn test_verify_missing_commit() -> eyre::Result<()> {
+    let git = make_git()?;
+    git.init_repo()?;
+
+    git.detach_head()?;
+    git.commit_file("test1", 1)?;
+    git.commit_file("test2", 2)?;
+    let test3_oid = git.commit_file("test3", 3)?;
+    git.run(&["checkout", "HEAD^"])?;
+
+    let repo = git.get_repo()?;
+    repo.find_reference(&ReferenceName::from(format!("refs/branchless/{test3_oid}")))?
+        .unwrap()
+        .delete()?;
+    git.run(&["gc", "--prune=now"])?;
+
+    {
+        let (stdout, stderr) = git.branchless_with_options(
+            "verify",
+            &[],
+            &GitRunOptions {
+                expected_exit_code: 1,
+                ..Default::default()
+            },
+        )?;
+        assert!(
+            stdout.starts_with(&format!("Found 1 broken commit: {test3_oid}\n")),
+            "{stdout}"
+        );
+        assert!(stderr.contains("so they can't be undone"), "{stderr}");
+        assert!(
+            stdout.ends_with(
+                "To repair these inconsistencies, run: git branchless repair --no-dry-run\n"
+            ),
+            "{stdout}"
+        );
+    }
+
+    // Verifying doesn't change anything.
+    {
+        let (stdout, _stderr) = git.branchless_with_options(
+            "verify",
+            &[],
+            &GitRunOptions {
+                expected_exit_code: 1,
+                ..Default::default()
+            },
+        )?;
+        assert!(
+            stdout.starts_with(&format!("Found 1 broken commit: {test3_oid}\n")),
+            "{stdout}"
+        );
+    }
+
+    git.branchless("repair", &["--no-dry-run"])?;
+    {
+        let (stdout, _stderr) = git.branchless("verify", &[])?;
+        insta::assert_snapshot!(stdout, @"The event log is consistent with the repository.
+");
+    }
+
+    Ok(())
+}
+
+#[test]
+fn test_verify_rewrite_cycle() -> eyre::Result<()> {
+    let git = make_git()?;
+    git.init_repo()?;
+
+    git.detach_head()?;
+    let test1_oid = git.commit_file("test1", 1)?;
+    let test2_oid = git.commit_file("test2", 2)?;
+
+    {
+        let repo = git.get_repo()?;
+        let conn = repo.get_db_conn()?;
+        let event_log_db = EventLogDb::new(&conn)?;
+        let event_tx_id = event_log_db.make_transaction_id(SystemTime::now(), "test")?;
+        event_log_db.add_events(vec![
+            Event::RewriteEvent {
+                timestamp: 0.0,
+                event_tx_id,
+                old_commit_oid: MaybeZeroOid::NonZero(test1_oid),
+                new_commit_oid: MaybeZeroOid::NonZero(test2_oid),
+            },
+            Event::RewriteEvent {
+                timestamp: 0.0,
+                event_tx_id,
+                old_commit_oid: MaybeZeroOid::NonZero(test2_oid),
+                new_commit_oid: MaybeZeroOid::NonZero(test1_oid),
+            },
+        ])?;
+    }
+
+    {
+        let (stdout, _stderr) = git.branchless_with_options(
+            "verify",
+            &[],
+            &GitRunOptions {
+                expected_exit_code: 1,
+                ..Default::default()
+            },
+        )?;
+        insta::assert_snapshot!(stdout, @r###"
+        Found a cycle of rewrites: 62fc20d2a290daea0d52bdc2ed2ad4be6491010e -> 96d1c37a3d4363611c49f7e52186e189a04c531f -> 62fc20d2a290daea0d52bdc2ed2ad4be6491010e
+        To repair these inconsistencies, run: git branchless repair --no-dry-run
+        "###);
+    }
+
+    git.branchless("repair", &["--no-dry-run"])?;
+
+    {
+        let (stdout, _stderr) = git.branchless("verify", &[])?;
+        insta::assert_snapshot!(stdout, @"The event log is consistent with the repository.
+");
+    }
+
+    Ok(())
+}
@@ -0,0 +1,65 @@
+use lib::testing::{make_git, GitRunOptions};
+
+#[test]
+fn test_worktree_add_and_remove() -> eyre::Result<()> {
+    let git = make_git()?;
+
+    if !git.supports_reference_transactions()? {
+        return Ok(());
+    }
+    git.init_repo()?;
+
+    git.detach_head()?;
+    let test1_oid = git.commit_file("test1", 1)?;
+    let test2_oid = git.commit_file("test2", 2)?;
+    git.run(&["checkout", "master"])?;
+
+    let worktree_path = {
+        let (stdout, _stderr) = git.branchless("worktree", &[&test1_oid.to_string()])?;
+        let worktree_path = stdout
+            .lines()
+            .find_map(|line| line.strip_prefix("Created worktree at "))
+            .and_then(|line| line.split(" for ").next())
+            .map(std::path::PathBuf::from);
+        assert!(stdout.contains("create test2.txt"), "{stdout}");
+        worktree_path.unwrap()
+    };
+
+    {
+        // The worktree is checked out at the tip of the stack.
+        let (stdout, _stderr) =
+            git.run(&["-C", worktree_path.to_str().unwrap(), "rev-parse", "HEAD"])?;
+        assert_eq!(stdout.trim(), test2_oid.to_string());
+        let (stdout, _stderr) = git.run(&["rev-parse", "HEAD"])?;
+        assert_ne!(stdout.trim(), test2_oid.to_string());
+    }
+
+    {
+        let (stdout, _stderr) = git.branchless("log-operations", &[])?;
+        assert!(stdout.contains(" worktree"), "{stdout}");
+    }
+
+    {
+        let (stdout, _stderr) =
+            git.branchless("worktree", &["--remove", &test1_oid.to_string()])?;
+        assert!(stdout.contains("Removed worktree at"), "{stdout}");
+        assert!(!worktree_path.exists());
+    }
+
+    {
+        let (stdout, _stderr) = git.branchless_with_options(
+            "worktree",
+            &["--remove", &test1_oid.to_string()],
+            &GitRunOptions {
+                expected_exit_code: 1,
+                ..Default::default()
+            },
+        )?;
+        assert!(
+            stdout.contains("There are no worktrees to remove for:"),
+            "{stdout}"
+        );
+    }
+
+    Ok(())
+}
@@ -2,6 +2,7 @@ use lib::core::effects::Effects;
 use lib::core::eventlog::testing::{get_event_replayer_events, redact_event_timestamp};
 use lib::core::eventlog::{Event, EventLogDb, EventReplayer};
 use lib::core::formatting::Glyphs;
+use lib::core::rewrite::rewrite_hooks::prompt_restack_abandoned;
 use lib::git::GitVersion;
 use lib::testing::{make_git, GitRunOptions};
 
@@ -374,3 +375,100 @@ fn test_wrap_exit_code() -> eyre::Result<()> {
 
     Ok(())
 }
+
+#[test]
+fn test_wrap_separator_and_label() -> eyre::Result<()> {
+    let git = make_git()?;
+
+    if !git.supports_reference_transactions()? {
+        return Ok(());
+    }
+
+    git.init_repo()?;
+    git.run(&["checkout", "-b", "foo"])?;
+    git.commit_file("test1", 1)?;
+    git.run(&["checkout", "master"])?;
+
+    git.branchless("wrap", &["--", "rebase", "foo"])?;
+
+    {
+        let (stdout, _stderr) = git.branchless("log-operations", &["-n", "1"])?;
+        assert!(stdout.contains("rebase: git rebase foo"), "{stdout}");
+    }
+
+    Ok(())
+}
+
+#[test]
+fn test_wrap_prompt_restack_abandoned() -> eyre::Result<()> {
+    let git = make_git()?;
+
+    git.init_repo()?;
+    git.commit_file("test1", 1)?;
+    git.commit_file("test2", 2)?;
+    git.run(&["checkout", "HEAD^"])?;
+
+    // Not run at a terminal, so there's no prompt.
+    git.branchless("wrap", &["commit", "--amend", "-m", "amended test1"])?;
+    {
+        let stdout = git.smartlog()?;
+        assert!(stdout.contains("rewritten as"), "{stdout}");
+    }
+
+    let effects = Effects::new_suppress_for_test(Glyphs::text());
+    let git_run_info = git.get_git_run_info();
+    let repo = git.get_repo()?;
+    let event_tx_id = {
+        let conn = repo.get_db_conn()?;
+        let event_log_db = EventLogDb::new(&conn)?;
+        event_log_db
+            .get_events()?
+            .into_iter()
+            .find_map(|event| match event {
+                Event::RewriteEvent { event_tx_id, .. } => Some(event_tx_id),
+                _ => None,
+            })
+            .unwrap()
+    };
+
+    prompt_restack_abandoned(
+        &effects,
+        &git_run_info,
+        &repo,
+        event_tx_id,
+        &mut "n\n".as_bytes(),
+    )?;
+    {
+        let stdout = git.smartlog()?;
+        assert!(stdout.contains("rewritten as"), "{stdout}");
+    }
+
+    git.run(&["config", "branchless.restack.promptAbandoned", "false"])?;
+    prompt_restack_abandoned(
+        &effects,
+        &git_run_info,
+        &repo,
+        event_tx_id,
+        &mut "y\n".as_bytes(),
+    )?;
+    {
+        let stdout = git.smartlog()?;
+        assert!(stdout.contains("rewritten as"), "{stdout}");
+    }
+
+    git.run(&["config", "branchless.restack.promptAbandoned", "true"])?;
+    prompt_restack_abandoned(
+        &effects,
+        &git_run_info,
+        &repo,
+        event_tx_id,
+        &mut "y\n".as_bytes(),
+    )?;
+    {
+        let stdout = git.smartlog()?;
+        assert!(!stdout.contains("rewritten as"), "{stdout}");
+        assert!(stdout.contains("create test2.txt"), "{stdout}");
+    }
+
+    Ok(())
+}
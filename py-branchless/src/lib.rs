@@ -0,0 +1,474 @@
+//! Python bindings for git-branchless.
+//!
+//! git-branchless was originally written in Python, and some users still have
+//! automation built against its Python API. This crate exposes the event log,
+//! the event replayer, and the smartlog graph to Python, using names similar to
+//! the original Python implementation.
+//!
+//! Build the extension module with `maturin build` from this directory; the
+//! resulting module is importable as `branchless`.
+
+#![warn(missing_docs)]
+#![warn(
+    clippy::all,
+    clippy::as_conversions,
+    clippy::clone_on_ref_ptr,
+    clippy::dbg_macro
+)]
+#![allow(clippy::too_many_arguments, clippy::blocks_in_if_conditions)]
+
+use std::cmp::Ordering;
+use std::collections::{HashMap, HashSet};
+use std::path::PathBuf;
+
+use pyo3::exceptions::{PyRuntimeError, PyValueError};
+use pyo3::prelude::*;
+
+use git_branchless_opts::{ResolveRevsetOptions, Revset};
+use git_branchless_revset::resolve_commits;
+use git_branchless_smartlog::make_smartlog_graph;
+use lib::core::config::get_smartlog_default_revset;
+use lib::core::dag::Dag;
+use lib::core::effects::Effects;
+use lib::core::eventlog::{
+    CommitActivityStatus, CopyType, Event, EventCursor, EventLogDb, EventReplayer,
+    EventTransactionId,
+};
+use lib::core::formatting::Glyphs;
+use lib::core::repo_ext::RepoExt;
+use lib::git::{NonZeroOid, Repo};
+
+fn to_py_err(err: impl Into<eyre::Error>) -> PyErr {
+    let err: eyre::Error = err.into();
+    PyRuntimeError::new_err(format!("{err:?}"))
+}
+
+fn parse_oid(oid: &str) -> PyResult<NonZeroOid> {
+    oid.parse()
+        .map_err(|err: eyre::Error| PyValueError::new_err(err.to_string()))
+}
+
+fn make_effects() -> Effects {
+    Effects::new(Glyphs::text())
+}
+
+fn open_repo(repo_path: Option<PathBuf>) -> PyResult<Repo> {
+    let repo = match repo_path {
+        Some(repo_path) => Repo::from_dir(&repo_path),
+        None => Repo::from_current_dir(),
+    };
+    repo.map_err(to_py_err)
+}
+
+/// An event from the event log.
+///
+/// The `event_type` attribute is one of `rewrite`, `ref-move`, `commit`, `hide`,
+/// `unhide`, `snapshot`, `cherry-pick`, or `revert`, matching the names stored
+/// in the event log. For `cherry-pick` and `revert` events, `old_oid` is the
+/// commit which was copied and `new_oid` is the resulting commit. Attributes
+/// which don't apply to the event's type are `None`.
+#[pyclass(name = "Event", module = "branchless", frozen, get_all)]
+#[derive(Clone, Debug)]
+pub struct PyEvent {
+    /// The type of the event.
+    event_type: &'static str,
+
+    /// The timestamp of the event, in seconds since the Unix epoch.
+    timestamp: f64,
+
+    /// The ID of the transaction containing this event.
+    event_tx_id: Option<isize>,
+
+    /// The OID of the affected commit, for `commit`, `hide`, `unhide`, and
+    /// `snapshot` events.
+    commit_oid: Option<String>,
+
+    /// The previous OID, for `rewrite` and `ref-move` events.
+    old_oid: Option<String>,
+
+    /// The new OID, for `rewrite` and `ref-move` events.
+    new_oid: Option<String>,
+
+    /// The full name of the affected reference, for `ref-move` and `snapshot`
+    /// events.
+    ref_name: Option<String>,
+
+    /// The message associated with the reference update, if any.
+    message: Option<String>,
+
+    /// The OID of `HEAD` at the time of a `snapshot` event.
+    head_oid: Option<String>,
+}
+
+#[pymethods]
+impl PyEvent {
+    fn __repr__(&self) -> String {
+        let Self {
+            event_type,
+            timestamp: _,
+            event_tx_id,
+            commit_oid,
+            old_oid,
+            new_oid,
+            ref_name,
+            message: _,
+            head_oid: _,
+        } = self;
+        format!(
+            "<Event event_type={event_type:?} event_tx_id={event_tx_id:?} commit_oid={commit_oid:?} old_oid={old_oid:?} new_oid={new_oid:?} ref_name={ref_name:?}>"
+        )
+    }
+}
+
+impl From<&Event> for PyEvent {
+    fn from(event: &Event) -> Self {
+        let timestamp = event
+            .get_timestamp()
+            .duration_since(std::time::SystemTime::UNIX_EPOCH)
+            .map(|duration| duration.as_secs_f64())
+            .unwrap_or_default();
+        let event_tx_id = match event.get_event_tx_id() {
+            EventTransactionId::Id(event_tx_id) => Some(event_tx_id),
+            EventTransactionId::Suppressed => None,
+        };
+        let empty = PyEvent {
+            event_type: "",
+            timestamp,
+            event_tx_id,
+            commit_oid: None,
+            old_oid: None,
+            new_oid: None,
+            ref_name: None,
+            message: None,
+            head_oid: None,
+        };
+        match event {
+            Event::RewriteEvent {
+                timestamp: _,
+                event_tx_id: _,
+                old_commit_oid,
+                new_commit_oid,
+            } => PyEvent {
+                event_type: "rewrite",
+                old_oid: Some(old_commit_oid.to_string()),
+                new_oid: Some(new_commit_oid.to_string()),
+                ..empty
+            },
+            Event::RefUpdateEvent {
+                timestamp: _,
+                event_tx_id: _,
+                ref_name,
+                old_oid,
+                new_oid,
+                message,
+            } => PyEvent {
+                event_type: "ref-move",
+                ref_name: Some(ref_name.as_str().to_owned()),
+                old_oid: Some(old_oid.to_string()),
+                new_oid: Some(new_oid.to_string()),
+                message: message.as_ref().map(|message| message.as_str().to_owned()),
+                ..empty
+            },
+            Event::CommitEvent {
+                timestamp: _,
+                event_tx_id: _,
+                commit_oid,
+            } => PyEvent {
+                event_type: "commit",
+                commit_oid: Some(commit_oid.to_string()),
+                ..empty
+            },
+            Event::ObsoleteEvent {
+                timestamp: _,
+                event_tx_id: _,
+                commit_oid,
+            } => PyEvent {
+                event_type: "hide",
+                commit_oid: Some(commit_oid.to_string()),
+                ..empty
+            },
+            Event::UnobsoleteEvent {
+                timestamp: _,
+                event_tx_id: _,
+                commit_oid,
+            } => PyEvent {
+                event_type: "unhide",
+                commit_oid: Some(commit_oid.to_string()),
+                ..empty
+            },
+            Event::WorkingCopySnapshot {
+                timestamp: _,
+                event_tx_id: _,
+                head_oid,
+                commit_oid,
+                ref_name,
+            } => PyEvent {
+                event_type: "snapshot",
+                head_oid: Some(head_oid.to_string()),
+                commit_oid: Some(commit_oid.to_string()),
+                ref_name: ref_name
+                    .as_ref()
+                    .map(|ref_name| ref_name.as_str().to_owned()),
+                ..empty
+            },
+            Event::CopyEvent {
+                timestamp: _,
+                event_tx_id: _,
+                copy_type,
+                source_commit_oid,
+                new_commit_oid,
+            } => PyEvent {
+                event_type: match copy_type {
+                    CopyType::CherryPick => "cherry-pick",
+                    CopyType::Revert => "revert",
+                },
+                old_oid: Some(source_commit_oid.to_string()),
+                new_oid: Some(new_commit_oid.to_string()),
+                ..empty
+            },
+        }
+    }
+}
+
+/// Read-only access to the event log for a repository.
+///
+/// If `repo_path` is not provided, the repository containing the current
+/// working directory is used.
+#[pyclass(name = "EventLogDb", module = "branchless", unsendable)]
+pub struct PyEventLogDb {
+    repo: Repo,
+    conn: rusqlite::Connection,
+}
+
+#[pymethods]
+impl PyEventLogDb {
+    #[new]
+    #[pyo3(signature = (repo_path=None))]
+    fn new(repo_path: Option<PathBuf>) -> PyResult<Self> {
+        let repo = open_repo(repo_path)?;
+        let conn = repo.get_db_conn().map_err(to_py_err)?;
+        // Create the tables if they don't exist yet.
+        EventLogDb::new(&conn).map_err(to_py_err)?;
+        Ok(Self { repo, conn })
+    }
+
+    /// Get all events in the event log, ordered from oldest to newest.
+    fn get_events(&self) -> PyResult<Vec<PyEvent>> {
+        let event_log_db = EventLogDb::new(&self.conn).map_err(to_py_err)?;
+        let events = event_log_db.get_events().map_err(to_py_err)?;
+        Ok(events.iter().map(PyEvent::from).collect())
+    }
+
+    /// Get the message associated with the given event transaction.
+    fn get_transaction_message(&self, event_tx_id: isize) -> PyResult<String> {
+        let event_log_db = EventLogDb::new(&self.conn).map_err(to_py_err)?;
+        event_log_db
+            .get_transaction_message(EventTransactionId::Id(event_tx_id))
+            .map_err(to_py_err)
+    }
+}
+
+/// Replays the event log to determine the state of the repository's commits.
+#[pyclass(name = "EventReplayer", module = "branchless", unsendable)]
+pub struct PyEventReplayer {
+    inner: EventReplayer,
+}
+
+impl PyEventReplayer {
+    /// Interpret `event_id` the same way as `git smartlog --event-id`: if not
+    /// provided, use the latest event, and if negative, treat it as an offset
+    /// from the latest event.
+    fn make_cursor(&self, event_id: Option<isize>) -> EventCursor {
+        let default_cursor = self.inner.make_default_cursor();
+        match event_id {
+            None => default_cursor,
+            Some(event_id) => match event_id.cmp(&0) {
+                Ordering::Less => self.inner.advance_cursor(default_cursor, event_id),
+                Ordering::Equal | Ordering::Greater => self.inner.make_cursor(event_id),
+            },
+        }
+    }
+}
+
+#[pymethods]
+impl PyEventReplayer {
+    /// Construct the replayer from all the events in the given event log.
+    #[staticmethod]
+    fn from_event_log_db(event_log_db: &PyEventLogDb) -> PyResult<Self> {
+        let effects = make_effects();
+        let db = EventLogDb::new(&event_log_db.conn).map_err(to_py_err)?;
+        let inner = EventReplayer::from_event_log_db(&effects, &event_log_db.repo, &db)
+            .map_err(to_py_err)?;
+        Ok(Self { inner })
+    }
+
+    /// Get the activity status of the given commit: one of `active`,
+    /// `inactive`, or `obsolete`.
+    #[pyo3(signature = (oid, event_id=None))]
+    fn get_commit_activity_status(&self, oid: &str, event_id: Option<isize>) -> PyResult<String> {
+        let oid = parse_oid(oid)?;
+        let cursor = self.make_cursor(event_id);
+        let status = match self.inner.get_cursor_commit_activity_status(cursor, oid) {
+            CommitActivityStatus::Active => "active",
+            CommitActivityStatus::Inactive => "inactive",
+            CommitActivityStatus::Obsolete => "obsolete",
+        };
+        Ok(status.to_owned())
+    }
+
+    /// Get the most recent event affecting the given commit, or `None` if the
+    /// commit was never observed.
+    #[pyo3(signature = (oid, event_id=None))]
+    fn get_commit_latest_event(
+        &self,
+        oid: &str,
+        event_id: Option<isize>,
+    ) -> PyResult<Option<PyEvent>> {
+        let oid = parse_oid(oid)?;
+        let cursor = self.make_cursor(event_id);
+        Ok(self
+            .inner
+            .get_cursor_commit_latest_event(cursor, oid)
+            .map(PyEvent::from))
+    }
+
+    /// Get the OIDs of all commits which have been observed by the event log.
+    #[pyo3(signature = (event_id=None))]
+    fn get_observed_oids(&self, event_id: Option<isize>) -> HashSet<String> {
+        let cursor = self.make_cursor(event_id);
+        self.inner
+            .get_cursor_oids(cursor)
+            .into_iter()
+            .map(|oid| oid.to_string())
+            .collect()
+    }
+}
+
+/// A node in the smartlog graph, as returned by `make_graph`.
+#[pyclass(name = "Node", module = "branchless", frozen, get_all)]
+#[derive(Clone, Debug)]
+pub struct PyNode {
+    /// The OID of the commit.
+    oid: String,
+
+    /// The OIDs of the parent nodes in the graph.
+    parents: Vec<String>,
+
+    /// The OIDs of the child nodes in the graph.
+    children: Vec<String>,
+
+    /// Whether this commit is part of the main branch.
+    is_main: bool,
+
+    /// Whether this commit has been rewritten or hidden.
+    is_obsolete: bool,
+
+    /// The number of visible descendants which aren't included in the graph.
+    num_omitted_descendants: usize,
+}
+
+#[pymethods]
+impl PyNode {
+    fn __repr__(&self) -> String {
+        format!(
+            "<Node oid={:?} is_main={} is_obsolete={}>",
+            self.oid, self.is_main, self.is_obsolete
+        )
+    }
+}
+
+/// Build the smartlog graph for the repository, as a mapping from commit OID
+/// to `Node`.
+///
+/// If `revset` is not provided, the default smartlog revset is used (see the
+/// `branchless.smartlog.defaultRevset` configuration variable).
+#[pyfunction]
+#[pyo3(signature = (repo_path=None, revset=None, show_hidden_commits=false))]
+fn make_graph(
+    repo_path: Option<PathBuf>,
+    revset: Option<String>,
+    show_hidden_commits: bool,
+) -> PyResult<HashMap<String, PyNode>> {
+    let effects = make_effects();
+    let repo = open_repo(repo_path)?;
+    let conn = repo.get_db_conn().map_err(to_py_err)?;
+    let event_log_db = EventLogDb::new(&conn).map_err(to_py_err)?;
+    let event_replayer =
+        EventReplayer::from_event_log_db(&effects, &repo, &event_log_db).map_err(to_py_err)?;
+    let event_cursor = event_replayer.make_default_cursor();
+    let references_snapshot = repo.get_references_snapshot().map_err(to_py_err)?;
+    let mut dag = Dag::open_and_sync(
+        &effects,
+        &repo,
+        &event_replayer,
+        event_cursor,
+        &references_snapshot,
+    )
+    .map_err(to_py_err)?;
+
+    let revset = match revset {
+        Some(revset) => Revset(revset),
+        None => Revset(get_smartlog_default_revset(&repo).map_err(to_py_err)?),
+    };
+    let commits = resolve_commits(
+        &effects,
+        &repo,
+        &mut dag,
+        &[revset],
+        &ResolveRevsetOptions {
+            show_hidden_commits,
+        },
+    )
+    .map_err(|err| PyValueError::new_err(err.to_string()))?;
+    let commits = match commits.as_slice() {
+        [commits] => commits,
+        other => {
+            return Err(PyRuntimeError::new_err(format!(
+                "Expected exactly 1 result from resolve commits, got: {}",
+                other.len()
+            )))
+        }
+    };
+
+    let graph = make_smartlog_graph(
+        &effects,
+        &repo,
+        &dag,
+        &event_replayer,
+        event_cursor,
+        commits,
+    )
+    .map_err(to_py_err)?;
+    let result = graph
+        .nodes
+        .iter()
+        .map(|(oid, node)| {
+            let node = PyNode {
+                oid: oid.to_string(),
+                parents: node.parents.iter().map(|oid| oid.to_string()).collect(),
+                children: node
+                    .children
+                    .iter()
+                    .map(|child| child.oid.to_string())
+                    .collect(),
+                is_main: node.is_main,
+                is_obsolete: node.is_obsolete,
+                num_omitted_descendants: node.num_omitted_descendants,
+            };
+            (oid.to_string(), node)
+        })
+        .collect();
+    Ok(result)
+}
+
+/// The `branchless` Python module.
+#[pymodule]
+#[pyo3(name = "branchless")]
+fn py_branchless(_py: Python, module: &PyModule) -> PyResult<()> {
+    module.add_class::<PyEvent>()?;
+    module.add_class::<PyEventLogDb>()?;
+    module.add_class::<PyEventReplayer>()?;
+    module.add_class::<PyNode>()?;
+    module.add_function(wrap_pyfunction!(make_graph, module)?)?;
+    Ok(())
+}